@@ -0,0 +1,319 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! A pool of reusable byte buffers shared by the connections of a node.
+//!
+//! The I/O stack of a connection, e.g. the framing of a security protocol
+//! and the frames of a stream multiplexer, needs buffers for reading and
+//! writing. Rather than allocating these afresh for every connection and
+//! growing them message by message, they are taken from a [`BufferPool`]
+//! and returned to it once they are dropped, keeping their capacity.
+//!
+//! Transports and multiplexers use the [`BufferPool::global`] pool, which
+//! can be tuned with [`BufferPool::configure`], e.g. through the swarm
+//! builder.
+
+use bytes::BytesMut;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{fmt, mem, ops::{Deref, DerefMut}, sync::{Arc, atomic::{AtomicU64, Ordering}}};
+
+lazy_static! {
+    static ref GLOBAL: BufferPool = BufferPool::new(BufferPoolConfig::default());
+}
+
+/// The configuration of a [`BufferPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferPoolConfig {
+    max_buffers: usize,
+    max_buffer_size: usize,
+}
+
+impl Default for BufferPoolConfig {
+    fn default() -> Self {
+        BufferPoolConfig {
+            max_buffers: 256,
+            max_buffer_size: 256 * 1024,
+        }
+    }
+}
+
+impl BufferPoolConfig {
+    /// Creates a new configuration with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of idle buffers retained by the pool.
+    ///
+    /// Buffers returned to a full pool are freed. A value of `0` disables
+    /// pooling altogether. Defaults to 256.
+    pub fn with_max_buffers(mut self, n: usize) -> Self {
+        self.max_buffers = n;
+        self
+    }
+
+    /// Sets the maximum capacity in bytes of a buffer retained by the pool.
+    ///
+    /// Buffers that grew beyond this size are freed instead of being
+    /// returned to the pool, bounding the memory held by idle buffers to
+    /// roughly `max_buffers * max_buffer_size`. Defaults to 256 KiB.
+    pub fn with_max_buffer_size(mut self, n: usize) -> Self {
+        self.max_buffer_size = n;
+        self
+    }
+
+    /// Returns the maximum number of idle buffers retained by the pool.
+    pub fn max_buffers(&self) -> usize {
+        self.max_buffers
+    }
+
+    /// Returns the maximum capacity in bytes of a buffer retained by the pool.
+    pub fn max_buffer_size(&self) -> usize {
+        self.max_buffer_size
+    }
+}
+
+/// A pool of reusable byte buffers, shared by all clones.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: Mutex<BufferPoolConfig>,
+    idle: Mutex<Vec<BytesMut>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Statistics of a [`BufferPool`], for tuning its configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BufferPoolStats {
+    /// The number of idle buffers currently retained by the pool.
+    pub idle: usize,
+    /// The number of buffers handed out that were taken from the pool.
+    pub hits: u64,
+    /// The number of buffers handed out that had to be newly created,
+    /// because the pool was empty.
+    pub misses: u64,
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("config", &*self.inner.config.lock())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl BufferPool {
+    /// Creates a new, empty pool with the given configuration.
+    pub fn new(config: BufferPoolConfig) -> Self {
+        BufferPool {
+            inner: Arc::new(Inner {
+                config: Mutex::new(config),
+                idle: Mutex::new(Vec::new()),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            })
+        }
+    }
+
+    /// Returns the process-wide pool used by the transports and stream
+    /// multiplexers of this crate family.
+    pub fn global() -> &'static BufferPool {
+        &GLOBAL
+    }
+
+    /// Changes the configuration of the pool.
+    ///
+    /// Idle buffers in excess of the new limits are freed immediately.
+    pub fn configure(&self, config: BufferPoolConfig) {
+        *self.inner.config.lock() = config;
+        let mut idle = self.inner.idle.lock();
+        idle.retain(|b| b.capacity() <= config.max_buffer_size);
+        idle.truncate(config.max_buffers);
+    }
+
+    /// Returns the current configuration of the pool.
+    pub fn config(&self) -> BufferPoolConfig {
+        *self.inner.config.lock()
+    }
+
+    /// Takes an empty buffer from the pool, creating a new one if no idle
+    /// buffer is available.
+    ///
+    /// The buffer is returned to the pool when the [`PooledBuffer`] is
+    /// dropped.
+    pub fn get(&self) -> PooledBuffer {
+        let buf = match self.inner.idle.lock().pop() {
+            Some(buf) => {
+                self.inner.hits.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.inner.misses.fetch_add(1, Ordering::Relaxed);
+                BytesMut::new()
+            }
+        };
+        PooledBuffer { buf, pool: Some(self.clone()) }
+    }
+
+    /// Returns statistics of the pool.
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            idle: self.inner.idle.lock().len(),
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn put(&self, mut buf: BytesMut) {
+        // Clearing retains the capacity after the current position of the
+        // buffer. Space split off into `Bytes` still in use elsewhere is
+        // reclaimed by a later `reserve`, once these are dropped.
+        buf.clear();
+        let config = self.config();
+        if buf.capacity() == 0 || buf.capacity() > config.max_buffer_size {
+            return
+        }
+        let mut idle = self.inner.idle.lock();
+        if idle.len() < config.max_buffers {
+            idle.push(buf)
+        }
+    }
+}
+
+/// A buffer taken from a [`BufferPool`], which is returned to the pool
+/// when dropped.
+///
+/// Dereferences to a [`BytesMut`], i.e. parts of the buffer can be split
+/// off and frozen into [`Bytes`](bytes::Bytes) without copying, sharing
+/// the allocation of the pooled buffer.
+pub struct PooledBuffer {
+    buf: BytesMut,
+    pool: Option<BufferPool>,
+}
+
+impl PooledBuffer {
+    /// Creates a buffer that is not associated with any pool.
+    pub fn detached() -> Self {
+        PooledBuffer { buf: BytesMut::new(), pool: None }
+    }
+
+    /// Detaches the buffer from its pool, returning the underlying `BytesMut`.
+    pub fn into_inner(mut self) -> BytesMut {
+        self.pool = None;
+        mem::take(&mut self.buf)
+    }
+}
+
+impl Default for PooledBuffer {
+    fn default() -> Self {
+        BufferPool::global().get()
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl fmt::Debug for PooledBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledBuffer")
+            .field("len", &self.buf.len())
+            .field("capacity", &self.buf.capacity())
+            .finish()
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.put(mem::take(&mut self.buf))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused() {
+        let pool = BufferPool::new(BufferPoolConfig::new());
+        let mut buf = pool.get();
+        buf.extend_from_slice(&[1; 1024]);
+        let ptr = buf.as_ptr();
+        drop(buf);
+        assert_eq!(pool.stats(), BufferPoolStats { idle: 1, hits: 0, misses: 1 });
+
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 1024);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(pool.stats(), BufferPoolStats { idle: 0, hits: 1, misses: 1 });
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let config = BufferPoolConfig::new().with_max_buffers(1).with_max_buffer_size(1024);
+        let pool = BufferPool::new(config);
+
+        let mut large = pool.get();
+        large.extend_from_slice(&[1; 2048]);
+        drop(large);
+        assert_eq!(pool.stats().idle, 0);
+
+        let mut a = pool.get();
+        let mut b = pool.get();
+        a.extend_from_slice(&[1; 16]);
+        b.extend_from_slice(&[1; 16]);
+        drop(a);
+        drop(b);
+        assert_eq!(pool.stats().idle, 1);
+
+        pool.configure(config.with_max_buffers(0));
+        assert_eq!(pool.stats().idle, 0);
+    }
+
+    #[test]
+    fn detached_buffers_are_not_returned() {
+        let pool = BufferPool::new(BufferPoolConfig::new());
+        let mut buf = pool.get();
+        buf.extend_from_slice(b"hello");
+        let inner = buf.into_inner();
+        assert_eq!(&inner[..], b"hello");
+        assert_eq!(pool.stats().idle, 0);
+    }
+}
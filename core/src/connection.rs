@@ -25,6 +25,7 @@ mod substream;
 
 pub(crate) mod manager;
 pub(crate) mod pool;
+pub(crate) mod stage;
 
 pub use error::{ConnectionError, PendingConnectionError};
 pub use handler::{ConnectionHandler, ConnectionHandlerEvent, IntoConnectionHandler};
@@ -32,7 +33,8 @@ pub use listeners::{ListenerId, ListenersStream, ListenersEvent};
 pub use manager::ConnectionId;
 pub use substream::{Substream, SubstreamEndpoint, Close};
 pub use pool::{EstablishedConnection, EstablishedConnectionIter, PendingConnection};
-pub use pool::{ConnectionLimits, ConnectionCounters};
+pub use pool::{ConnectionLimits, ConnectionCounters, PendingConnectionInfo};
+pub use stage::{ConnectionStage, StageProgress, StageTimeout, StageTimeouts};
 
 use crate::muxing::StreamMuxer;
 use crate::{Multiaddr, PeerId};
@@ -229,6 +231,14 @@ where
         self.handler.inject_event(event);
     }
 
+    /// Polls the connection handler for the completion of its pending
+    /// work ahead of a graceful close.
+    ///
+    /// See [`ConnectionHandler::poll_close`].
+    pub fn poll_close_handler(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.handler.poll_close(cx)
+    }
+
     /// Begins an orderly shutdown of the connection, returning a
     /// `Future` that resolves when connection shutdown is complete.
     pub fn close(self) -> Close<TMuxer> {
@@ -254,6 +264,9 @@ where
                     let endpoint = SubstreamEndpoint::Dialer(user_data);
                     self.handler.inject_substream(substream, endpoint)
                 }
+                Poll::Ready(Ok(SubstreamEvent::OutboundSubstreamFailed { user_data, error })) => {
+                    self.handler.inject_outbound_failure(user_data, error)
+                }
                 Poll::Ready(Ok(SubstreamEvent::AddressChange(address))) => {
                     self.handler.inject_address_change(&address);
                     return Poll::Ready(Ok(Event::AddressChange(address)));
@@ -19,7 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::Multiaddr;
-use std::{task::Context, task::Poll};
+use std::{io, task::Context, task::Poll};
 use super::{Connected, SubstreamEndpoint};
 
 /// The interface of a connection handler.
@@ -61,11 +61,29 @@ pub trait ConnectionHandler {
     /// Notifies the handler of a change in the address of the remote.
     fn inject_address_change(&mut self, new_address: &Multiaddr);
 
+    /// Notifies the handler that the outbound substream requested with the given
+    /// `OutboundOpenInfo` could not be opened, while the connection remains open.
+    ///
+    /// The error wraps an [`OutboundSubstreamError`](crate::muxing::OutboundSubstreamError).
+    /// The default implementation ignores the failure.
+    fn inject_outbound_failure(&mut self, _info: Self::OutboundOpenInfo, _error: io::Error) {}
+
     /// Polls the handler for events.
     ///
     /// Returning an error will close the connection to the remote.
     fn poll(&mut self, cx: &mut Context<'_>)
         -> Poll<Result<ConnectionHandlerEvent<Self::OutboundOpenInfo, Self::OutEvent>, Self::Error>>;
+
+    /// Polls the handler for the completion of its pending work before the
+    /// connection is gracefully closed.
+    ///
+    /// Once the connection is asked to drain, it keeps being polled as usual
+    /// and additionally calls this method until it returns `Ready`, after
+    /// which the connection is closed. The default implementation has no
+    /// pending work to wait for.
+    fn poll_close(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
 }
 
 /// Prototype for a `ConnectionHandler`.
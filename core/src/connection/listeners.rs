@@ -22,7 +22,7 @@
 
 use crate::{Multiaddr, Transport, transport::{TransportError, ListenerEvent}};
 use futures::{prelude::*, task::Context, task::Poll};
-use log::debug;
+use tracing::debug;
 use smallvec::SmallVec;
 use std::{collections::VecDeque, fmt, pin::Pin};
 
@@ -20,9 +20,10 @@
 
 use crate::{
     Executor,
-    muxing::StreamMuxer,
+    muxing::{MuxerStats, StreamMuxer},
 };
 use fnv::FnvHashMap;
+use parking_lot::Mutex;
 use futures::{
     prelude::*,
     channel::mpsc,
@@ -34,6 +35,7 @@ use std::{
     fmt,
     mem,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 use super::{
@@ -44,7 +46,8 @@ use super::{
     ConnectionHandler,
     IntoConnectionHandler,
     PendingConnectionError,
-    Substream
+    Substream,
+    stage::{SharedProgress, StageProgress, StageTimeouts},
 };
 use task::{Task, TaskId};
 
@@ -104,6 +107,9 @@ pub struct Manager<I, O, H, E, HE> {
     /// Size of the task command buffer (per task).
     task_command_buffer_size: usize,
 
+    /// Timeouts of the stages of pending connections.
+    stage_timeouts: StageTimeouts,
+
     /// The executor to use for running the background tasks. If `None`,
     /// the tasks are kept in `local_spawns` instead and polled on the
     /// current thread when the manager is polled for new events.
@@ -133,7 +139,8 @@ impl<I, O, H, E, HE> fmt::Debug for Manager<I, O, H, E, HE>
 /// Configuration options when creating a [`Manager`].
 ///
 /// The default configuration specifies no dedicated task executor, a
-/// task event buffer size of 32, and a task command buffer size of 7.
+/// task event buffer size of 32, a task command buffer size of 7 and
+/// no timeouts of the stages of pending connections.
 #[non_exhaustive]
 pub struct ManagerConfig {
     /// Executor to use to spawn tasks.
@@ -144,6 +151,9 @@ pub struct ManagerConfig {
 
     /// Size of the task event buffer (for all tasks).
     pub task_event_buffer_size: usize,
+
+    /// Timeouts of the stages of pending connections.
+    pub stage_timeouts: StageTimeouts,
 }
 
 impl Default for ManagerConfig {
@@ -152,6 +162,7 @@ impl Default for ManagerConfig {
             executor: None,
             task_event_buffer_size: 32,
             task_command_buffer_size: 7,
+            stage_timeouts: StageTimeouts::default(),
         }
     }
 }
@@ -166,13 +177,16 @@ struct TaskInfo<I> {
     sender: mpsc::Sender<task::Command<I>>,
     /// The state of the task as seen by the `Manager`.
     state: TaskState,
+    /// The statistics of the stream multiplexer, once established.
+    stats: Option<MuxerStats>,
 }
 
 /// Internal state of a running task as seen by the `Manager`.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 enum TaskState {
-    /// The connection is being established.
-    Pending,
+    /// The connection is being established, with the progress
+    /// through its stages reported by the task.
+    Pending(SharedProgress),
     /// The connection is established.
     Established(Connected),
 }
@@ -242,6 +256,7 @@ impl<I, O, H, TE, HE> Manager<I, O, H, TE, HE> {
             tasks: FnvHashMap::default(),
             next_task_id: TaskId(0),
             task_command_buffer_size: config.task_command_buffer_size,
+            stage_timeouts: config.stage_timeouts,
             executor: config.executor,
             local_spawns: FuturesUnordered::new(),
             events_tx: tx,
@@ -275,9 +290,18 @@ impl<I, O, H, TE, HE> Manager<I, O, H, TE, HE> {
         self.next_task_id.0 += 1;
 
         let (tx, rx) = mpsc::channel(self.task_command_buffer_size);
-        self.tasks.insert(task_id, TaskInfo { sender: tx, state: TaskState::Pending });
-
-        let task = Box::pin(Task::pending(task_id, self.events_tx.clone(), rx, future, handler));
+        let progress = Arc::new(Mutex::new(StageProgress::new()));
+        self.tasks.insert(task_id, TaskInfo { sender: tx, state: TaskState::Pending(progress.clone()), stats: None });
+
+        let task = Box::pin(Task::pending(
+            task_id,
+            self.events_tx.clone(),
+            rx,
+            future,
+            handler,
+            progress,
+            self.stage_timeouts.clone()
+        ));
         if let Some(executor) = &mut self.executor {
             executor.exec(task);
         } else {
@@ -308,13 +332,14 @@ impl<I, O, H, TE, HE> Manager<I, O, H, TE, HE> {
         let task_id = self.next_task_id;
         self.next_task_id.0 += 1;
 
+        let peer_id = info.peer_id;
         let (tx, rx) = mpsc::channel(self.task_command_buffer_size);
         self.tasks.insert(task_id, TaskInfo {
-            sender: tx, state: TaskState::Established(info)
+            sender: tx, state: TaskState::Established(info), stats: None
         });
 
         let task: Pin<Box<Task<Pin<Box<future::Pending<_>>>, _, _, _, _, _>>> =
-            Box::pin(Task::established(task_id, self.events_tx.clone(), rx, conn));
+            Box::pin(Task::established(task_id, self.events_tx.clone(), rx, conn, &peer_id));
 
         if let Some(executor) = &mut self.executor {
             executor.exec(task);
@@ -334,6 +359,15 @@ impl<I, O, H, TE, HE> Manager<I, O, H, TE, HE> {
         }
     }
 
+    /// Returns the progress of the pending connection with the given ID
+    /// through its stages, if the connection is pending.
+    pub fn pending_progress(&self, id: &ConnectionId) -> Option<StageProgress> {
+        match self.tasks.get(&id.0) {
+            Some(TaskInfo { state: TaskState::Pending(progress), .. }) => Some(progress.lock().clone()),
+            _ => None
+        }
+    }
+
     /// Checks whether an established connection with the given ID is currently managed.
     pub fn is_established(&self, id: &ConnectionId) -> bool {
         matches!(self.tasks.get(&id.0), Some(TaskInfo { state: TaskState::Established(..), .. }))
@@ -364,8 +398,9 @@ impl<I, O, H, TE, HE> Manager<I, O, H, TE, HE> {
                         entry: EstablishedEntry { task },
                         event
                     },
-                task::Event::Established { id: _, info } => { // (2)
+                task::Event::Established { id: _, info, stats } => { // (2)
                     task.get_mut().state = TaskState::Established(info); // (3)
+                    task.get_mut().stats = stats;
                     Event::ConnectionEstablished {
                         entry: EstablishedEntry { task },
                     }
@@ -398,7 +433,7 @@ impl<I, O, H, TE, HE> Manager<I, O, H, TE, HE> {
                     match task.state {
                         TaskState::Established(connected) =>
                             Event::ConnectionClosed { id, connected, error },
-                        TaskState::Pending => unreachable!(
+                        TaskState::Pending(_) => unreachable!(
                             "`Event::Closed` implies (2) occurred on that task and thus (3)."
                             ),
                     }
@@ -420,7 +455,7 @@ pub enum Entry<'a, I> {
 impl<'a, I> Entry<'a, I> {
     fn new(task: hash_map::OccupiedEntry<'a, TaskId, TaskInfo<I>>) -> Self {
         match &task.get().state {
-            TaskState::Pending => Entry::Pending(PendingEntry { task }),
+            TaskState::Pending(_) => Entry::Pending(PendingEntry { task }),
             TaskState::Established(_) => Entry::Established(EstablishedEntry { task })
         }
     }
@@ -482,11 +517,28 @@ impl<'a, I> EstablishedEntry<'a, I> {
         }
     }
 
+    /// Sends a drain command to the associated background task, thus
+    /// letting the connection handler complete its pending work before
+    /// initiating a graceful active close of the connection.
+    ///
+    /// Has no effect if the connection is already closing.
+    ///
+    /// When the connection is ultimately closed, [`Event::ConnectionClosed`]
+    /// is emitted by [`Manager::poll`].
+    pub fn start_drain(mut self) {
+        // Clone the sender so that we are guaranteed to have
+        // capacity for the drain command (every sender gets a slot).
+        match self.task.get_mut().sender.clone().try_send(task::Command::Drain) {
+            Ok(()) => {},
+            Err(e) => assert!(e.is_disconnected(), "No capacity for drain command.")
+        }
+    }
+
     /// Obtains information about the established connection.
     pub fn connected(&self) -> &Connected {
         match &self.task.get().state {
             TaskState::Established(c) => c,
-            TaskState::Pending => unreachable!("By Entry::new()")
+            TaskState::Pending(_) => unreachable!("By Entry::new()")
         }
     }
 
@@ -497,7 +549,7 @@ impl<'a, I> EstablishedEntry<'a, I> {
     pub fn remove(self) -> Connected {
         match self.task.remove().state {
             TaskState::Established(c) => c,
-            TaskState::Pending => unreachable!("By Entry::new()")
+            TaskState::Pending(_) => unreachable!("By Entry::new()")
         }
     }
 
@@ -505,6 +557,12 @@ impl<'a, I> EstablishedEntry<'a, I> {
     pub fn id(&self) -> ConnectionId {
         ConnectionId(*self.task.key())
     }
+
+    /// Returns the statistics of the stream multiplexer of the connection,
+    /// if collected by the multiplexer.
+    pub fn stats(&self) -> Option<&MuxerStats> {
+        self.task.get().stats.as_ref()
+    }
 }
 
 /// An entry for a managed connection that is currently being established
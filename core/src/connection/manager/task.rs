@@ -20,7 +20,8 @@
 
 use crate::{
     Multiaddr,
-    muxing::StreamMuxer,
+    PeerId,
+    muxing::{MuxerStats, StreamMuxer},
     connection::{
         self,
         Close,
@@ -31,9 +32,11 @@ use crate::{
         IntoConnectionHandler,
         PendingConnectionError,
         Substream,
+        stage::{self, ConnectionStage, SharedProgress, StageTimeout, StageTimeouts},
     },
 };
 use futures::{prelude::*, channel::mpsc, stream};
+use futures_timer::Delay;
 use std::{pin::Pin, task::Context, task::Poll};
 use super::ConnectResult;
 
@@ -49,13 +52,16 @@ pub enum Command<T> {
     /// Gracefully close the connection (active close) before
     /// terminating the task.
     Close,
+    /// Gracefully close the connection (active close) once the connection
+    /// handler has completed its pending work, before terminating the task.
+    Drain,
 }
 
 /// Events that a task can emit to its manager.
 #[derive(Debug)]
 pub enum Event<T, H, TE, HE> {
     /// A connection to a node has succeeded.
-    Established { id: TaskId, info: Connected },
+    Established { id: TaskId, info: Connected, stats: Option<MuxerStats> },
     /// A pending connection failed.
     Failed { id: TaskId, error: PendingConnectionError<TE>, handler: H },
     /// A node we are connected to has changed its address.
@@ -91,6 +97,9 @@ where
     /// The ID of this task.
     id: TaskId,
 
+    /// The span of the connection, in which the task is polled.
+    span: tracing::Span,
+
     /// Sender to emit events to the manager of this task.
     events: mpsc::Sender<Event<O, H, E, <H::Handler as ConnectionHandler>::Error>>,
 
@@ -113,15 +122,21 @@ where
         events: mpsc::Sender<Event<O, H, E, <H::Handler as ConnectionHandler>::Error>>,
         commands: mpsc::Receiver<Command<I>>,
         future: F,
-        handler: H
+        handler: H,
+        progress: SharedProgress,
+        timeouts: StageTimeouts
     ) -> Self {
         Task {
             id,
+            span: connection_span(id, None),
             events,
             commands: commands.fuse(),
             state: State::Pending {
                 future: Box::pin(future),
                 handler,
+                progress,
+                timeouts,
+                timer: None,
             },
         }
     }
@@ -131,17 +146,33 @@ where
         id: TaskId,
         events: mpsc::Sender<Event<O, H, E, <H::Handler as ConnectionHandler>::Error>>,
         commands: mpsc::Receiver<Command<I>>,
-        connection: Connection<M, H::Handler>
+        connection: Connection<M, H::Handler>,
+        peer_id: &PeerId
     ) -> Self {
         Task {
             id,
+            span: connection_span(id, Some(peer_id)),
             events,
             commands: commands.fuse(),
-            state: State::Established { connection, event: None },
+            state: State::Established { connection, event: None, draining: false },
         }
     }
 }
 
+/// Creates the span of the connection of the task with the given ID, with
+/// the ID of the remote peer once known.
+fn connection_span(id: TaskId, peer_id: Option<&PeerId>) -> tracing::Span {
+    let span = tracing::debug_span!(
+        "connection",
+        connection_id = id.0,
+        peer_id = tracing::field::Empty,
+    );
+    if let Some(peer_id) = peer_id {
+        span.record("peer_id", &tracing::field::display(peer_id));
+    }
+    span
+}
+
 /// The state associated with the `Task` of a connection.
 enum State<F, M, H, O, E>
 where
@@ -156,6 +187,12 @@ where
         future: Pin<Box<F>>,
         /// The intended handler for the established connection.
         handler: H,
+        /// The progress of the connection through its stages.
+        progress: SharedProgress,
+        /// The timeouts of the stages.
+        timeouts: StageTimeouts,
+        /// The timer of the current stage, if it has a timeout.
+        timer: Option<(ConnectionStage, Delay)>,
     },
 
     /// The connection is established.
@@ -165,7 +202,10 @@ where
         /// is polled for new events in this state, otherwise the event
         /// must be sent to the `Manager` before the connection can be
         /// polled again.
-        event: Option<Event<O, H, E, <H::Handler as ConnectionHandler>::Error>>
+        event: Option<Event<O, H, E, <H::Handler as ConnectionHandler>::Error>>,
+        /// Whether the connection is to be closed once the connection
+        /// handler has completed its pending work.
+        draining: bool,
     },
 
     /// The connection is closing (active close).
@@ -201,10 +241,12 @@ where
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
         let this = &mut *self;
         let id = this.id;
+        let span = this.span.clone();
+        let _entered = span.enter();
 
         'poll: loop {
             match std::mem::replace(&mut this.state, State::Done) {
-                State::Pending { mut future, handler } => {
+                State::Pending { mut future, handler, progress, timeouts, mut timer } => {
                     // Check whether the task is still registered with a `Manager`
                     // by polling the commands channel.
                     match this.commands.poll_next_unpin(cx) {
@@ -217,19 +259,48 @@ where
                             "Task received command while the connection is pending."
                         )
                     }
-                    // Check if the connection succeeded.
-                    match future.poll_unpin(cx) {
+                    // Check if the connection succeeded, letting the upgrades
+                    // of the connection report its progress.
+                    match stage::with_progress(&progress, || future.poll_unpin(cx)) {
                         Poll::Ready(Ok((info, muxer))) => {
+                            span.record("peer_id", &tracing::field::display(&info.peer_id));
+                            tracing::debug!("Connection established.");
+                            let stats = muxer.stats();
                             this.state = State::Established {
                                 connection: Connection::new(
                                     muxer,
                                     handler.into_handler(&info),
                                 ),
-                                event: Some(Event::Established { id, info })
+                                event: Some(Event::Established { id, info, stats }),
+                                draining: false,
                             }
                         }
                         Poll::Pending => {
-                            this.state = State::Pending { future, handler };
+                            // (Re)start the timer whenever the connection entered a new stage.
+                            let (current, elapsed) = {
+                                let progress = progress.lock();
+                                (progress.stage(), progress.current_stage_elapsed())
+                            };
+                            if timer.as_ref().map(|(s, _)| *s) != Some(current) {
+                                timer = timeouts.timeout(current).map(|t| {
+                                    (current, Delay::new(t.checked_sub(elapsed).unwrap_or_default()))
+                                });
+                            }
+                            // Check if the current stage timed out.
+                            if let Some((stage, delay)) = &mut timer {
+                                if delay.poll_unpin(cx).is_ready() {
+                                    let timeout = timeouts.timeout(*stage)
+                                        .expect("A timer is only started for a stage with a timeout.");
+                                    tracing::debug!("Pending connection {:?}: {} timed out.", id, stage);
+                                    this.commands.get_mut().close();
+                                    let error = PendingConnectionError::IO(
+                                        StageTimeout { stage: *stage, timeout }.into()
+                                    );
+                                    this.state = State::Terminating(Event::Failed { id, handler, error });
+                                    continue 'poll
+                                }
+                            }
+                            this.state = State::Pending { future, handler, progress, timeouts, timer };
                             return Poll::Pending
                         }
                         Poll::Ready(Err(error)) => {
@@ -242,7 +313,7 @@ where
                     }
                 }
 
-                State::Established { mut connection, event } => {
+                State::Established { mut connection, event, mut draining } => {
                     // Check for commands from the `Manager`.
                     loop {
                         match this.commands.poll_next_unpin(cx) {
@@ -256,6 +327,7 @@ where
                                 this.state = State::Closing(connection.close());
                                 continue 'poll
                             }
+                            Poll::Ready(Some(Command::Drain)) => draining = true,
                             Poll::Ready(None) => {
                                 // The manager has dropped the task or disappeared; abort.
                                 return Poll::Ready(())
@@ -267,13 +339,13 @@ where
                         // Send the event to the manager.
                         match this.events.poll_ready(cx) {
                             Poll::Pending => {
-                                this.state = State::Established { connection, event: Some(event) };
+                                this.state = State::Established { connection, event: Some(event), draining };
                                 return Poll::Pending
                             }
                             Poll::Ready(result) => {
                                 if result.is_ok() {
                                     if let Ok(()) = this.events.start_send(event) {
-                                        this.state = State::Established { connection, event: None };
+                                        this.state = State::Established { connection, event: None, draining };
                                         continue 'poll
                                     }
                                 }
@@ -285,19 +357,28 @@ where
                         // Poll the connection for new events.
                         match Connection::poll(Pin::new(&mut connection), cx) {
                             Poll::Pending => {
-                                this.state = State::Established { connection, event: None };
+                                if draining && connection.poll_close_handler(cx).is_ready() {
+                                    // Don't accept any further commands.
+                                    this.commands.get_mut().close();
+                                    // The handler is done, start a graceful close.
+                                    this.state = State::Closing(connection.close());
+                                    continue 'poll
+                                }
+                                this.state = State::Established { connection, event: None, draining };
                                 return Poll::Pending
                             }
                             Poll::Ready(Ok(connection::Event::Handler(event))) => {
                                 this.state = State::Established {
                                     connection,
-                                    event: Some(Event::Notify { id, event })
+                                    event: Some(Event::Notify { id, event }),
+                                    draining,
                                 };
                             }
                             Poll::Ready(Ok(connection::Event::AddressChange(new_address))) => {
                                 this.state = State::Established {
                                     connection,
-                                    event: Some(Event::AddressChange { id, new_address })
+                                    event: Some(Event::AddressChange { id, new_address }),
+                                    draining,
                                 };
                             }
                             Poll::Ready(Err(error)) => {
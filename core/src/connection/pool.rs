@@ -34,12 +34,13 @@ use crate::{
         OutgoingInfo,
         Substream,
         PendingConnectionError,
+        StageProgress,
         manager::{self, Manager, ManagerConfig},
     },
-    muxing::StreamMuxer,
+    muxing::{MuxerStats, StreamMuxer},
 };
 use either::Either;
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet};
 use futures::prelude::*;
 use smallvec::SmallVec;
 use std::{convert::TryFrom as _, error, fmt, num::NonZeroU32, task::Context, task::Poll};
@@ -69,6 +70,14 @@ pub struct Pool<TInEvent, TOutEvent, THandler, TTransErr, THandlerErr> {
     /// event for each. Every `ConnectionEstablished` event must be
     /// paired with (eventually) a `ConnectionClosed`.
     disconnected: Vec<Disconnected>,
+
+    /// The peers whose new connections evict established connections of
+    /// other peers when the limit of established connections is reached.
+    protected: FnvHashSet<PeerId>,
+
+    /// The established connections being closed to make room for
+    /// connections of protected peers.
+    evicting: FnvHashSet<ConnectionId>,
 }
 
 impl<TInEvent, TOutEvent, THandler, TTransErr, THandlerErr> fmt::Debug
@@ -213,9 +222,34 @@ impl<TInEvent, TOutEvent, THandler, TTransErr, THandlerErr>
             established: Default::default(),
             pending: Default::default(),
             disconnected: Vec::new(),
+            protected: Default::default(),
+            evicting: Default::default(),
         }
     }
 
+    /// Protects the connections of a peer, returning `false` if the peer
+    /// was already protected.
+    ///
+    /// When the limit of established incoming (outgoing) connections is
+    /// reached, a new connection of a protected peer is not rejected but
+    /// evicts an established incoming (outgoing) connection of an unprotected
+    /// peer instead, if there is one. Connections of peers with the most
+    /// connections are evicted first and among those the most recent ones.
+    pub fn add_protected_peer(&mut self, peer: PeerId) -> bool {
+        self.protected.insert(peer)
+    }
+
+    /// Removes the protection of the connections of a peer, returning
+    /// `false` if the peer was not protected.
+    pub fn remove_protected_peer(&mut self, peer: &PeerId) -> bool {
+        self.protected.remove(peer)
+    }
+
+    /// Checks whether the connections of a peer are protected.
+    pub fn is_protected(&self, peer: &PeerId) -> bool {
+        self.protected.contains(peer)
+    }
+
     /// Gets the dedicated connection counters.
     pub fn counters(&self) -> &ConnectionCounters {
         &self.counters
@@ -552,6 +586,16 @@ impl<TInEvent, TOutEvent, THandler, TTransErr, THandlerErr>
         self.pending.iter().map(|(id, (endpoint, info))| (id, endpoint, info))
     }
 
+    /// Returns an iterator over all pending connections together with
+    /// their progress through the stages of the connection setup.
+    pub fn iter_pending_progress(&self) -> impl Iterator<Item = PendingConnectionInfo<'_>> {
+        let manager = &self.manager;
+        self.pending.iter().filter_map(move |(id, (endpoint, peer_id))| {
+            let progress = manager.pending_progress(id)?;
+            Some(PendingConnectionInfo { id: *id, endpoint, peer_id: peer_id.as_ref(), progress })
+        })
+    }
+
     /// Returns an iterator over all connected peers, i.e. those that have
     /// at least one established connection in the pool.
     pub fn iter_connected<'a>(&'a self) -> impl Iterator<Item = &'a PeerId> + 'a {
@@ -575,6 +619,7 @@ impl<TInEvent, TOutEvent, THandler, TTransErr, THandlerErr>
         if let Some(Disconnected {
             id, connected, num_established
         }) = self.disconnected.pop() {
+            self.evicting.remove(&id);
             return Poll::Ready(PoolEvent::ConnectionClosed {
                 id,
                 connected,
@@ -606,6 +651,7 @@ impl<TInEvent, TOutEvent, THandler, TTransErr, THandlerErr>
                     }
                 },
                 manager::Event::ConnectionClosed { id, connected, error } => {
+                    self.evicting.remove(&id);
                     let num_established =
                         if let Some(conns) = self.established.get_mut(&connected.peer_id) {
                             if let Some(endpoint) = conns.remove(&id) {
@@ -627,17 +673,25 @@ impl<TInEvent, TOutEvent, THandler, TTransErr, THandlerErr>
                     if let Some((endpoint, peer)) = self.pending.remove(&id) {
                         self.counters.dec_pending(&endpoint);
 
-                        // Check general established connection limit.
+                        // Check general established connection limit, which
+                        // a protected peer may exceed by evicting a connection.
+                        let mut evict = None;
                         if let Err(e) = self.counters.check_max_established(&endpoint) {
-                            let connected = entry.remove();
-                            return Poll::Ready(PoolEvent::PendingConnectionError {
-                                id,
-                                endpoint: connected.endpoint,
-                                error: PendingConnectionError::ConnectionLimit(e),
-                                handler: None,
-                                peer,
-                                pool: self
-                            })
+                            if self.protected.contains(&entry.connected().peer_id) {
+                                evict = eviction_candidate(
+                                    &self.established, &self.protected, &self.evicting, &endpoint);
+                            }
+                            if evict.is_none() {
+                                let connected = entry.remove();
+                                return Poll::Ready(PoolEvent::PendingConnectionError {
+                                    id,
+                                    endpoint: connected.endpoint,
+                                    error: PendingConnectionError::ConnectionLimit(e),
+                                    handler: None,
+                                    peer,
+                                    pool: self
+                                })
+                            }
                         }
 
                         // Check per-peer established connection limit.
@@ -668,6 +722,14 @@ impl<TInEvent, TOutEvent, THandler, TTransErr, THandlerErr>
 
                         // Add the connection to the pool.
                         let peer = entry.connected().peer_id;
+                        if let Some(victim) = evict {
+                            tracing::debug!("Evicting connection {:?} for connection {:?} to protected peer {:?}.",
+                                victim, id, peer);
+                            self.evicting.insert(victim);
+                            if let Some(conn) = self.get_established(victim) {
+                                conn.start_close();
+                            }
+                        }
                         let conns = self.established.entry(peer).or_default();
                         let num_established = NonZeroU32::new(u32::try_from(conns.len() + 1).unwrap())
                             .expect("n + 1 is always non-zero; qed");
@@ -725,6 +787,20 @@ pub enum PoolConnection<'a, TInEvent> {
     Established(EstablishedConnection<'a, TInEvent>),
 }
 
+/// Information about a pending connection in a pool, including its
+/// progress through the stages of the connection setup.
+#[derive(Debug, Clone)]
+pub struct PendingConnectionInfo<'a> {
+    /// The ID of the connection.
+    pub id: ConnectionId,
+    /// The local endpoint of the connection.
+    pub endpoint: &'a ConnectedPoint,
+    /// The expected peer of the connection, if known.
+    pub peer_id: Option<&'a PeerId>,
+    /// The progress of the connection through its stages.
+    pub progress: StageProgress,
+}
+
 /// A pending connection in a pool.
 pub struct PendingConnection<'a, TInEvent> {
     entry: manager::PendingEntry<'a, TInEvent>,
@@ -795,6 +871,12 @@ impl<TInEvent> EstablishedConnection<'_, TInEvent> {
         self.entry.id()
     }
 
+    /// Returns the statistics of the stream multiplexer of the connection,
+    /// if collected by the multiplexer.
+    pub fn stats(&self) -> Option<&MuxerStats> {
+        self.entry.stats()
+    }
+
     /// (Asynchronously) sends an event to the connection handler.
     ///
     /// If the handler is not ready to receive the event, either because
@@ -825,6 +907,15 @@ impl<TInEvent> EstablishedConnection<'_, TInEvent> {
     pub fn start_close(self) {
         self.entry.start_close()
     }
+
+    /// Initiates a graceful close of the connection once the connection
+    /// handler has completed its pending work.
+    ///
+    /// See [`ConnectionHandler::poll_close`]. Has no effect if the
+    /// connection is already closing.
+    pub fn start_drain(self) {
+        self.entry.start_drain()
+    }
 }
 
 /// An iterator over established connections in a pool.
@@ -1021,6 +1112,22 @@ fn num_peer_established(
             .expect("Unexpectedly large number of connections for a peer."))
 }
 
+/// Selects an established connection in the same direction as the given
+/// endpoint to evict in favour of a connection of a protected peer.
+fn eviction_candidate(
+    established: &FnvHashMap<PeerId, FnvHashMap<ConnectionId, ConnectedPoint>>,
+    protected: &FnvHashSet<PeerId>,
+    evicting: &FnvHashSet<ConnectionId>,
+    endpoint: &ConnectedPoint,
+) -> Option<ConnectionId> {
+    established.iter()
+        .filter(|(peer, _)| !protected.contains(peer))
+        .flat_map(|(_, conns)| conns.iter().map(move |(id, e)| (conns.len(), *id, e)))
+        .filter(|(_, id, e)| e.is_listener() == endpoint.is_listener() && !evicting.contains(id))
+        .max_by_key(|(num_conns, id, _)| (*num_conns, *id))
+        .map(|(_, id, _)| id)
+}
+
 /// The configurable connection limits.
 ///
 /// By default no connection limits apply.
@@ -0,0 +1,260 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Stages of pending connections.
+//!
+//! A pending connection passes through up to three stages before it is
+//! established: the transport connection is set up (e.g. a TCP connect),
+//! a security protocol is negotiated and finally a stream multiplexer is
+//! negotiated. The stages are reported by the upgrades configured through
+//! [`transport::upgrade::Builder`](crate::transport::upgrade::Builder) while
+//! the pending connection is being polled by its background task.
+
+use parking_lot::Mutex;
+use std::{cell::RefCell, error, fmt, io, sync::Arc, time::Duration};
+use wasm_timer::Instant;
+
+/// A stage of a pending connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConnectionStage {
+    /// The transport connection is being established, e.g. a TCP connect.
+    Connecting,
+    /// The security protocol is being negotiated.
+    Securing,
+    /// The stream multiplexer is being negotiated.
+    Multiplexing,
+}
+
+impl ConnectionStage {
+    fn index(self) -> usize {
+        match self {
+            ConnectionStage::Connecting => 0,
+            ConnectionStage::Securing => 1,
+            ConnectionStage::Multiplexing => 2,
+        }
+    }
+}
+
+impl fmt::Display for ConnectionStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionStage::Connecting => f.write_str("connecting"),
+            ConnectionStage::Securing => f.write_str("security upgrade"),
+            ConnectionStage::Multiplexing => f.write_str("multiplexer upgrade"),
+        }
+    }
+}
+
+/// Timeouts of the individual stages of pending connections.
+///
+/// By default, no stage has a timeout.
+#[derive(Debug, Clone, Default)]
+pub struct StageTimeouts {
+    timeouts: [Option<Duration>; 3],
+}
+
+impl StageTimeouts {
+    /// Creates a configuration without any timeouts.
+    pub fn new() -> Self {
+        StageTimeouts::default()
+    }
+
+    /// Sets the timeout of the given stage.
+    pub fn with_timeout(mut self, stage: ConnectionStage, timeout: Duration) -> Self {
+        self.timeouts[stage.index()] = Some(timeout);
+        self
+    }
+
+    /// Sets the timeout of the [`ConnectionStage::Connecting`] stage.
+    pub fn with_connect_timeout(self, timeout: Duration) -> Self {
+        self.with_timeout(ConnectionStage::Connecting, timeout)
+    }
+
+    /// Sets the timeout of the [`ConnectionStage::Securing`] stage.
+    pub fn with_security_timeout(self, timeout: Duration) -> Self {
+        self.with_timeout(ConnectionStage::Securing, timeout)
+    }
+
+    /// Sets the timeout of the [`ConnectionStage::Multiplexing`] stage.
+    pub fn with_multiplex_timeout(self, timeout: Duration) -> Self {
+        self.with_timeout(ConnectionStage::Multiplexing, timeout)
+    }
+
+    /// Returns the timeout of the given stage, if any.
+    pub fn timeout(&self, stage: ConnectionStage) -> Option<Duration> {
+        self.timeouts[stage.index()]
+    }
+}
+
+/// The error of a pending connection that exceeded the timeout of a stage.
+///
+/// The error is reported as [`PendingConnectionError::IO`] with
+/// [`io::ErrorKind::TimedOut`] and can be obtained from the
+/// [`io::Error`] through [`io::Error::get_ref`].
+///
+/// [`PendingConnectionError::IO`]: crate::connection::PendingConnectionError::IO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageTimeout {
+    /// The stage that timed out.
+    pub stage: ConnectionStage,
+    /// The configured timeout of the stage.
+    pub timeout: Duration,
+}
+
+impl fmt::Display for StageTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.stage, self.timeout)
+    }
+}
+
+impl error::Error for StageTimeout {}
+
+impl From<StageTimeout> for io::Error {
+    fn from(timeout: StageTimeout) -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, timeout)
+    }
+}
+
+/// The progress of a pending connection through its stages.
+#[derive(Debug, Clone)]
+pub struct StageProgress {
+    /// The instants at which the stages were entered.
+    entered: [Option<Instant>; 3],
+}
+
+impl StageProgress {
+    pub(crate) fn new() -> Self {
+        StageProgress { entered: [Some(Instant::now()), None, None] }
+    }
+
+    /// Returns the current stage.
+    pub fn stage(&self) -> ConnectionStage {
+        if self.entered[ConnectionStage::Multiplexing.index()].is_some() {
+            ConnectionStage::Multiplexing
+        } else if self.entered[ConnectionStage::Securing.index()].is_some() {
+            ConnectionStage::Securing
+        } else {
+            ConnectionStage::Connecting
+        }
+    }
+
+    /// Returns the time elapsed since the connection attempt started.
+    pub fn elapsed(&self) -> Duration {
+        self.entered[0].map_or(Duration::from_secs(0), |i| i.elapsed())
+    }
+
+    /// Returns the time spent in the given stage.
+    ///
+    /// For the current stage, this is the time elapsed since the stage
+    /// was entered. Returns `None` if the stage has not been entered.
+    pub fn stage_elapsed(&self, stage: ConnectionStage) -> Option<Duration> {
+        let entered = self.entered[stage.index()]?;
+        let left = self.entered[stage.index() + 1 ..].iter().find_map(|i| *i);
+        Some(match left {
+            Some(left) => left.duration_since(entered),
+            None => entered.elapsed(),
+        })
+    }
+
+    /// Returns the time spent in the current stage.
+    pub fn current_stage_elapsed(&self) -> Duration {
+        self.stage_elapsed(self.stage()).unwrap_or_else(|| Duration::from_secs(0))
+    }
+
+    fn enter(&mut self, stage: ConnectionStage) {
+        let entered = &mut self.entered[stage.index()];
+        if entered.is_none() {
+            *entered = Some(Instant::now());
+        }
+    }
+}
+
+/// The progress of a pending connection, shared between its background
+/// task and the connection manager.
+pub(crate) type SharedProgress = Arc<Mutex<StageProgress>>;
+
+thread_local! {
+    /// The progress of the pending connection currently being polled, if any.
+    static CURRENT: RefCell<Option<SharedProgress>> = RefCell::new(None);
+}
+
+/// Runs `f` with `progress` as the progress of the pending connection
+/// being polled, to which [`enter`] reports.
+pub(crate) fn with_progress<R>(progress: &SharedProgress, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT.with(|c| c.replace(Some(progress.clone())));
+    let result = f();
+    CURRENT.with(|c| *c.borrow_mut() = previous);
+    result
+}
+
+/// Records that the pending connection currently being polled entered the
+/// given stage.
+///
+/// Has no effect if called outside of the background task of a pending
+/// connection.
+pub(crate) fn enter(stage: ConnectionStage) {
+    CURRENT.with(|c| {
+        if let Some(progress) = c.borrow().as_ref() {
+            progress.lock().enter(stage)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_through_stages() {
+        let progress = Arc::new(Mutex::new(StageProgress::new()));
+        assert_eq!(progress.lock().stage(), ConnectionStage::Connecting);
+
+        // Outside of `with_progress`, stages are not recorded.
+        enter(ConnectionStage::Securing);
+        assert_eq!(progress.lock().stage(), ConnectionStage::Connecting);
+
+        with_progress(&progress, || enter(ConnectionStage::Securing));
+        assert_eq!(progress.lock().stage(), ConnectionStage::Securing);
+        with_progress(&progress, || enter(ConnectionStage::Multiplexing));
+
+        let progress = progress.lock().clone();
+        assert_eq!(progress.stage(), ConnectionStage::Multiplexing);
+        assert!(progress.stage_elapsed(ConnectionStage::Connecting).is_some());
+        assert!(progress.stage_elapsed(ConnectionStage::Securing).unwrap() <= progress.elapsed());
+    }
+
+    #[test]
+    fn timeouts() {
+        let timeouts = StageTimeouts::new()
+            .with_connect_timeout(Duration::from_secs(5))
+            .with_multiplex_timeout(Duration::from_secs(10));
+        assert_eq!(timeouts.timeout(ConnectionStage::Connecting), Some(Duration::from_secs(5)));
+        assert_eq!(timeouts.timeout(ConnectionStage::Securing), None);
+        assert_eq!(timeouts.timeout(ConnectionStage::Multiplexing), Some(Duration::from_secs(10)));
+
+        let error = io::Error::from(StageTimeout {
+            stage: ConnectionStage::Securing,
+            timeout: Duration::from_secs(1),
+        });
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(error.get_ref().unwrap().downcast_ref::<StageTimeout>().is_some());
+    }
+}
@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::muxing::{StreamMuxer, StreamMuxerEvent, SubstreamRef, substream_from_ref};
+use crate::muxing::{OutboundSubstreamError, StreamMuxer, StreamMuxerEvent, SubstreamRef, substream_from_ref};
 use futures::prelude::*;
 use multiaddr::Multiaddr;
 use smallvec::SmallVec;
@@ -97,6 +97,16 @@ where
         substream: Substream<TMuxer>,
     },
 
+    /// An outbound substream could not be opened, without affecting the connection.
+    ///
+    /// See [`OutboundSubstreamError`].
+    OutboundSubstreamFailed {
+        /// User data that has been passed to the `open_substream` method.
+        user_data: TUserData,
+        /// The error that occurred.
+        error: IoError,
+    },
+
     /// Address to the remote has changed. The previous one is now obsolete.
     ///
     /// > **Note**: This can for example happen when using the QUIC protocol, where the two nodes
@@ -183,7 +193,14 @@ where
                 }
                 Poll::Ready(Err(err)) => {
                     self.inner.destroy_outbound(outbound);
-                    return Poll::Ready(Err(err.into()));
+                    let error: IoError = err.into();
+                    if OutboundSubstreamError::from_io_error(&error).is_some() {
+                        return Poll::Ready(Ok(SubstreamEvent::OutboundSubstreamFailed {
+                            user_data,
+                            error,
+                        }));
+                    }
+                    return Poll::Ready(Err(error));
                 }
             }
         }
@@ -262,6 +279,12 @@ where
                     .field("substream", substream)
                     .finish()
             },
+            SubstreamEvent::OutboundSubstreamFailed { user_data, error } => {
+                f.debug_struct("SubstreamEvent::OutboundSubstreamFailed")
+                    .field("user_data", user_data)
+                    .field("error", error)
+                    .finish()
+            },
             SubstreamEvent::AddressChange(address) => {
                 f.debug_struct("SubstreamEvent::AddressChange")
                     .field("address", address)
@@ -24,9 +24,10 @@ use crate::{
     transport::{Transport, ListenerEvent, TransportError},
     Multiaddr
 };
+use bytes::Bytes;
 use futures::{prelude::*, io::{IoSlice, IoSliceMut}};
 use pin_project::pin_project;
-use std::{fmt, io::{Error as IoError}, pin::Pin, task::Context, task::Poll};
+use std::{fmt, io::{Error as IoError}, pin::Pin, task::Context, task::Poll, time::Duration};
 
 #[derive(Debug, Copy, Clone)]
 pub enum EitherError<A, B> {
@@ -272,6 +273,30 @@ where
         }
     }
 
+    fn read_substream_bytes(&self, cx: &mut Context<'_>, sub: &mut Self::Substream, max: usize) -> Poll<Result<Bytes, Self::Error>> {
+        match (self, sub) {
+            (EitherOutput::First(ref inner), EitherOutput::First(ref mut sub)) => {
+                inner.read_substream_bytes(cx, sub, max).map_err(|e| e.into())
+            },
+            (EitherOutput::Second(ref inner), EitherOutput::Second(ref mut sub)) => {
+                inner.read_substream_bytes(cx, sub, max).map_err(|e| e.into())
+            },
+            _ => panic!("Wrong API usage")
+        }
+    }
+
+    fn write_substream_bytes(&self, cx: &mut Context<'_>, sub: &mut Self::Substream, buf: &Bytes) -> Poll<Result<usize, Self::Error>> {
+        match (self, sub) {
+            (EitherOutput::First(ref inner), EitherOutput::First(ref mut sub)) => {
+                inner.write_substream_bytes(cx, sub, buf).map_err(|e| e.into())
+            },
+            (EitherOutput::Second(ref inner), EitherOutput::Second(ref mut sub)) => {
+                inner.write_substream_bytes(cx, sub, buf).map_err(|e| e.into())
+            },
+            _ => panic!("Wrong API usage")
+        }
+    }
+
     fn flush_substream(&self, cx: &mut Context<'_>, sub: &mut Self::Substream) -> Poll<Result<(), Self::Error>> {
         match (self, sub) {
             (EitherOutput::First(ref inner), EitherOutput::First(ref mut sub)) => {
@@ -326,6 +351,13 @@ where
             EitherOutput::Second(inner) => inner.flush_all(cx).map_err(|e| e.into()),
         }
     }
+
+    fn rtt(&self) -> Option<Duration> {
+        match self {
+            EitherOutput::First(inner) => inner.rtt(),
+            EitherOutput::Second(inner) => inner.rtt(),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -202,7 +202,7 @@ impl PublicKey {
             }
             #[cfg(target_arch = "wasm32")]
             keys_proto::KeyType::Rsa => {
-                log::debug!("support for RSA was disabled at compile-time");
+                tracing::debug!("support for RSA was disabled at compile-time");
                 Err(DecodingError::new("Unsupported"))
             },
             #[cfg(feature = "secp256k1")]
@@ -211,7 +211,7 @@ impl PublicKey {
             }
             #[cfg(not(feature = "secp256k1"))]
             keys_proto::KeyType::Secp256k1 => {
-                log::debug!("support for secp256k1 was disabled at compile-time");
+                tracing::debug!("support for secp256k1 was disabled at compile-time");
                 Err("Unsupported".to_string().into())
             }
         }
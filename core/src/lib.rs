@@ -46,11 +46,15 @@ pub type Negotiated<T> = multistream_select::Negotiated<T>;
 mod peer_id;
 mod translation;
 
+pub mod buffer_pool;
 pub mod connection;
 pub mod either;
 pub mod identity;
 pub mod muxing;
 pub mod network;
+pub mod network_id;
+pub mod onion;
+pub mod simulation;
 pub mod transport;
 pub mod upgrade;
 pub mod simple_ser;
@@ -65,6 +69,7 @@ pub use translation::address_translation;
 pub use upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo, UpgradeError, ProtocolName};
 pub use connection::{Connected, Endpoint, ConnectedPoint};
 pub use network::Network;
+pub use network_id::{NetworkId, SignedNetworkId};
 pub use simple_ser::{SimplePopSerializer, SimplePushSerializer};
 
 use std::{future::Future, pin::Pin};
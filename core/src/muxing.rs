@@ -51,15 +51,18 @@
 //! The upgrade process will take ownership of the connection, which makes it possible for the
 //! implementation of `StreamMuxer` to control everything that happens on the wire.
 
+use bytes::{Bytes, BytesMut};
 use fnv::FnvHashMap;
-use futures::{future, prelude::*, task::Context, task::Poll};
+use futures::{future, prelude::*, ready, task::Context, task::Poll};
 use multiaddr::Multiaddr;
 use parking_lot::Mutex;
-use std::{io, ops::Deref, fmt, pin::Pin, sync::atomic::{AtomicUsize, Ordering}};
+use std::{io, ops::Deref, fmt, pin::Pin, sync::atomic::{AtomicUsize, Ordering}, time::Duration};
 
 pub use self::singleton::SingletonMuxer;
+pub use self::stats::{MuxerStats, ProtocolStats, SubstreamStats};
 
 mod singleton;
+mod stats;
 
 /// Implemented on objects that can open and manage substreams.
 ///
@@ -113,6 +116,10 @@ pub trait StreamMuxer {
     ///
     /// May panic or produce an undefined result if an earlier polling of the same substream
     /// returned `Ready` or `Err`.
+    ///
+    /// An error that converts into an [`io::Error`] wrapping an [`OutboundSubstreamError`]
+    /// only fails the outbound substream in question. Any other error is considered fatal
+    /// for the connection.
     fn poll_outbound(&self, cx: &mut Context<'_>, s: &mut Self::OutboundSubstream)
         -> Poll<Result<Self::Substream, Self::Error>>;
 
@@ -148,6 +155,36 @@ pub trait StreamMuxer {
     fn write_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &[u8])
         -> Poll<Result<usize, Self::Error>>;
 
+    /// Reads up to `max` bytes from a substream into a reference-counted buffer.
+    ///
+    /// The behaviour is the same as `read_substream`, except that an empty
+    /// buffer signals the end of the substream.
+    ///
+    /// Muxers receiving data in reference-counted buffers should override this
+    /// method to hand out (slices of) these buffers without copying. The default
+    /// implementation copies the data read with `read_substream`.
+    fn read_substream_bytes(&self, cx: &mut Context<'_>, s: &mut Self::Substream, max: usize)
+        -> Poll<Result<Bytes, Self::Error>>
+    {
+        let mut buf = BytesMut::new();
+        buf.resize(max, 0);
+        let n = ready!(self.read_substream(cx, s, &mut buf))?;
+        buf.truncate(n);
+        Poll::Ready(Ok(buf.freeze()))
+    }
+
+    /// Writes data from a reference-counted buffer to a substream, returning
+    /// the number of bytes written.
+    ///
+    /// The behaviour is the same as `write_substream`. Muxers sending data in
+    /// reference-counted buffers should override this method to take (a slice
+    /// of) `buf` without copying. The default implementation calls `write_substream`.
+    fn write_substream_bytes(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &Bytes)
+        -> Poll<Result<usize, Self::Error>>
+    {
+        self.write_substream(cx, s, buf)
+    }
+
     /// Flushes a substream. The behaviour is the same as `futures::AsyncWrite::poll_flush`.
     ///
     /// After this method has been called, data written earlier on the substream is guaranteed to
@@ -189,6 +226,19 @@ pub trait StreamMuxer {
         true
     }
 
+    /// Returns the statistics of the substreams of this muxer, if collected.
+    ///
+    /// Only [`StreamMuxerBox`] collects statistics by default.
+    fn stats(&self) -> Option<MuxerStats> {
+        None
+    }
+
+    /// Returns the most recent round-trip time measured by this muxer,
+    /// e.g. with keep-alive pings, if any.
+    fn rtt(&self) -> Option<Duration> {
+        None
+    }
+
     /// Closes this `StreamMuxer`.
     ///
     /// After this has returned `Poll::Ready(Ok(()))`, the muxer has become useless. All
@@ -224,6 +274,49 @@ pub enum StreamMuxerEvent<T> {
     AddressChange(Multiaddr),
 }
 
+/// Error failing the attempt to open a single outbound substream, without affecting the
+/// connection as a whole.
+///
+/// A [`StreamMuxer`] reports this error from [`StreamMuxer::poll_outbound`] by converting it
+/// into its error type via [`io::Error`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutboundSubstreamError {
+    /// Too many outbound substreams are already waiting to be opened.
+    TooManyPending,
+    /// The outbound substream could not be opened in time.
+    Timeout,
+}
+
+impl OutboundSubstreamError {
+    /// Returns the `OutboundSubstreamError` wrapped by the given [`io::Error`], if any.
+    pub fn from_io_error(error: &io::Error) -> Option<OutboundSubstreamError> {
+        error.get_ref().and_then(|e| e.downcast_ref()).copied()
+    }
+}
+
+impl fmt::Display for OutboundSubstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboundSubstreamError::TooManyPending =>
+                write!(f, "Too many pending outbound substreams"),
+            OutboundSubstreamError::Timeout =>
+                write!(f, "Timeout while opening an outbound substream"),
+        }
+    }
+}
+
+impl std::error::Error for OutboundSubstreamError {}
+
+impl From<OutboundSubstreamError> for io::Error {
+    fn from(error: OutboundSubstreamError) -> io::Error {
+        let kind = match error {
+            OutboundSubstreamError::TooManyPending => io::ErrorKind::Other,
+            OutboundSubstreamError::Timeout => io::ErrorKind::TimedOut,
+        };
+        io::Error::new(kind, error)
+    }
+}
+
 impl<T> StreamMuxerEvent<T> {
     /// If `self` is a [`StreamMuxerEvent::InboundSubstream`], returns the content. Otherwise
     /// returns `None`.
@@ -396,6 +489,47 @@ where
     }
 }
 
+impl<P> SubstreamRef<P>
+where
+    P: Deref<Target = StreamMuxerBox>,
+{
+    /// Returns a handle for attributing the traffic of this substream
+    /// to a protocol in the [`MuxerStats`] of the muxer.
+    pub fn stats(&self) -> Option<SubstreamStats> {
+        self.substream.map(|id| self.muxer.stats.substream(id))
+    }
+}
+
+impl<P> SubstreamRef<P>
+where
+    P: Deref,
+    P::Target: StreamMuxer,
+{
+    /// Reads up to `max` bytes into a reference-counted buffer, without copying
+    /// if supported by the muxer. An empty buffer signals the end of the substream.
+    ///
+    /// See [`StreamMuxer::read_substream_bytes`].
+    pub fn poll_read_bytes(mut self: Pin<&mut Self>, cx: &mut Context<'_>, max: usize)
+        -> Poll<Result<Bytes, io::Error>>
+    {
+        let this = &mut *self;
+        let s = this.substream.as_mut().expect("substream was empty");
+        this.muxer.read_substream_bytes(cx, s, max).map_err(|e| e.into())
+    }
+
+    /// Writes data from a reference-counted buffer, without copying if supported
+    /// by the muxer, returning the number of bytes written.
+    ///
+    /// See [`StreamMuxer::write_substream_bytes`].
+    pub fn poll_write_bytes(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &Bytes)
+        -> Poll<Result<usize, io::Error>>
+    {
+        let this = &mut *self;
+        let s = this.substream.as_mut().expect("substream was empty");
+        this.muxer.write_substream_bytes(cx, s, buf).map_err(|e| e.into())
+    }
+}
+
 impl<P> Unpin for SubstreamRef<P>
 where
     P: Deref,
@@ -484,6 +618,7 @@ where
 /// Abstract `StreamMuxer`.
 pub struct StreamMuxerBox {
     inner: Box<dyn StreamMuxer<Substream = usize, OutboundSubstream = usize, Error = io::Error> + Send + Sync>,
+    stats: MuxerStats,
 }
 
 impl StreamMuxerBox {
@@ -504,6 +639,7 @@ impl StreamMuxerBox {
 
         StreamMuxerBox {
             inner: Box::new(wrap),
+            stats: MuxerStats::new(),
         }
     }
 }
@@ -515,32 +651,75 @@ impl StreamMuxer for StreamMuxerBox {
 
     #[inline]
     fn poll_event(&self, cx: &mut Context<'_>) -> Poll<Result<StreamMuxerEvent<Self::Substream>, Self::Error>> {
-        self.inner.poll_event(cx)
+        let event = self.inner.poll_event(cx);
+        if let Poll::Ready(Ok(StreamMuxerEvent::InboundSubstream(_))) = &event {
+            self.stats.on_opened(true);
+        }
+        if let Some(rtt) = self.inner.rtt() {
+            self.stats.on_rtt(rtt);
+        }
+        event
     }
 
     #[inline]
     fn open_outbound(&self) -> Self::OutboundSubstream {
+        self.stats.on_outbound_requested();
         self.inner.open_outbound()
     }
 
     #[inline]
     fn poll_outbound(&self, cx: &mut Context<'_>, s: &mut Self::OutboundSubstream) -> Poll<Result<Self::Substream, Self::Error>> {
-        self.inner.poll_outbound(cx, s)
+        let result = self.inner.poll_outbound(cx, s);
+        if let Poll::Ready(Ok(_)) = &result {
+            self.stats.on_opened(false);
+        }
+        result
     }
 
     #[inline]
     fn destroy_outbound(&self, substream: Self::OutboundSubstream) {
+        self.stats.on_outbound_destroyed();
         self.inner.destroy_outbound(substream)
     }
 
     #[inline]
     fn read_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &mut [u8]) -> Poll<Result<usize, Self::Error>> {
-        self.inner.read_substream(cx, s, buf)
+        let result = self.inner.read_substream(cx, s, buf);
+        if let Poll::Ready(Ok(n)) = &result {
+            self.stats.on_read(*s, *n);
+        }
+        result
     }
 
     #[inline]
     fn write_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &[u8]) -> Poll<Result<usize, Self::Error>> {
-        self.inner.write_substream(cx, s, buf)
+        let result = self.inner.write_substream(cx, s, buf);
+        match &result {
+            Poll::Ready(Ok(n)) => self.stats.on_written(*s, *n),
+            Poll::Pending => self.stats.on_write_blocked(),
+            Poll::Ready(Err(_)) => {}
+        }
+        result
+    }
+
+    #[inline]
+    fn read_substream_bytes(&self, cx: &mut Context<'_>, s: &mut Self::Substream, max: usize) -> Poll<Result<Bytes, Self::Error>> {
+        let result = self.inner.read_substream_bytes(cx, s, max);
+        if let Poll::Ready(Ok(buf)) = &result {
+            self.stats.on_read(*s, buf.len());
+        }
+        result
+    }
+
+    #[inline]
+    fn write_substream_bytes(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &Bytes) -> Poll<Result<usize, Self::Error>> {
+        let result = self.inner.write_substream_bytes(cx, s, buf);
+        match &result {
+            Poll::Ready(Ok(n)) => self.stats.on_written(*s, *n),
+            Poll::Pending => self.stats.on_write_blocked(),
+            Poll::Ready(Err(_)) => {}
+        }
+        result
     }
 
     #[inline]
@@ -555,9 +734,19 @@ impl StreamMuxer for StreamMuxerBox {
 
     #[inline]
     fn destroy_substream(&self, s: Self::Substream) {
+        self.stats.on_closed(s);
         self.inner.destroy_substream(s)
     }
 
+    fn stats(&self) -> Option<MuxerStats> {
+        Some(self.stats.clone())
+    }
+
+    #[inline]
+    fn rtt(&self) -> Option<Duration> {
+        self.inner.rtt()
+    }
+
     #[inline]
     fn close(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.close(cx)
@@ -643,6 +832,18 @@ where
         self.inner.write_substream(cx, list.get_mut(s).unwrap(), buf).map_err(|e| e.into())
     }
 
+    #[inline]
+    fn read_substream_bytes(&self, cx: &mut Context<'_>, s: &mut Self::Substream, max: usize) -> Poll<Result<Bytes, Self::Error>> {
+        let mut list = self.substreams.lock();
+        self.inner.read_substream_bytes(cx, list.get_mut(s).unwrap(), max).map_err(|e| e.into())
+    }
+
+    #[inline]
+    fn write_substream_bytes(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &Bytes) -> Poll<Result<usize, Self::Error>> {
+        let mut list = self.substreams.lock();
+        self.inner.write_substream_bytes(cx, list.get_mut(s).unwrap(), buf).map_err(|e| e.into())
+    }
+
     #[inline]
     fn flush_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream) -> Poll<Result<(), Self::Error>> {
         let mut list = self.substreams.lock();
@@ -666,6 +867,11 @@ where
         self.inner.close(cx).map_err(|e| e.into())
     }
 
+    #[inline]
+    fn rtt(&self) -> Option<Duration> {
+        self.inner.rtt()
+    }
+
     #[inline]
     fn flush_all(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.inner.flush_all(cx).map_err(|e| e.into())
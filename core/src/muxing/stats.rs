@@ -0,0 +1,254 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Statistics of the substreams of a [`StreamMuxerBox`](super::StreamMuxerBox).
+
+use fnv::FnvHashMap;
+use parking_lot::Mutex;
+use std::{fmt, sync::{Arc, atomic::{AtomicU64, Ordering}}, time::Duration};
+
+/// Counters of a multiplexed connection, shared by all clones.
+///
+/// The traffic of a substream is attributed to a protocol once the protocol
+/// of the substream has been negotiated and set with
+/// [`SubstreamStats::set_protocol`]. Traffic before that, i.e. of the protocol
+/// negotiation itself, only counts towards the totals.
+#[derive(Clone, Default)]
+pub struct MuxerStats {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    inbound_opened: AtomicU64,
+    outbound_opened: AtomicU64,
+    closed: AtomicU64,
+    pending_outbound: AtomicU64,
+    write_blocked: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    /// The most recent round-trip time reported by the muxer.
+    rtt: Mutex<Option<Duration>>,
+    /// The protocol counters of the open substreams with a known protocol.
+    substreams: Mutex<FnvHashMap<usize, Arc<ProtocolCounters>>>,
+    /// The counters per protocol name.
+    protocols: Mutex<FnvHashMap<Vec<u8>, Arc<ProtocolCounters>>>,
+}
+
+#[derive(Default)]
+struct ProtocolCounters {
+    streams: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+/// Traffic of all substreams of a connection using the same protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProtocolStats {
+    /// The number of substreams that have used the protocol.
+    pub streams: u64,
+    /// The number of bytes read from these substreams.
+    pub bytes_read: u64,
+    /// The number of bytes written to these substreams.
+    pub bytes_written: u64,
+}
+
+impl MuxerStats {
+    /// Creates new statistics with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of substreams opened by the remote.
+    pub fn inbound_streams_opened(&self) -> u64 {
+        self.inner.inbound_opened.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of substreams opened by the local node.
+    pub fn outbound_streams_opened(&self) -> u64 {
+        self.inner.outbound_opened.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of substreams that have been closed.
+    pub fn streams_closed(&self) -> u64 {
+        self.inner.closed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of substreams that are currently open.
+    pub fn open_streams(&self) -> u64 {
+        (self.inbound_streams_opened() + self.outbound_streams_opened())
+            .saturating_sub(self.streams_closed())
+    }
+
+    /// Returns the number of outbound substreams which have been requested
+    /// and are not yet open, e.g. because of a limit of concurrent substreams.
+    pub fn pending_outbound_streams(&self) -> u64 {
+        self.inner.pending_outbound.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of times a write to a substream could not proceed
+    /// immediately, e.g. because the send window of the substream was
+    /// exhausted. A steadily increasing value indicates back-pressure from
+    /// the remote.
+    pub fn write_blocked(&self) -> u64 {
+        self.inner.write_blocked.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes read from all substreams.
+    pub fn bytes_read(&self) -> u64 {
+        self.inner.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes written to all substreams.
+    pub fn bytes_written(&self) -> u64 {
+        self.inner.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Returns the most recent round-trip time measured by the muxer, e.g.
+    /// with keep-alive pings (see [`StreamMuxer::rtt`](super::StreamMuxer::rtt)).
+    pub fn rtt(&self) -> Option<Duration> {
+        *self.inner.rtt.lock()
+    }
+
+    /// Returns the traffic per protocol, with protocol names that are not
+    /// valid UTF-8 converted lossily.
+    pub fn protocols(&self) -> Vec<(String, ProtocolStats)> {
+        self.inner.protocols.lock().iter()
+            .map(|(name, c)| (String::from_utf8_lossy(name).into_owned(), ProtocolStats {
+                streams: c.streams.load(Ordering::Relaxed),
+                bytes_read: c.bytes_read.load(Ordering::Relaxed),
+                bytes_written: c.bytes_written.load(Ordering::Relaxed),
+            }))
+            .collect()
+    }
+
+    /// Returns a handle for attributing the traffic of the given substream.
+    pub(crate) fn substream(&self, id: usize) -> SubstreamStats {
+        SubstreamStats { stats: self.clone(), id }
+    }
+
+    pub(crate) fn on_opened(&self, inbound: bool) {
+        if inbound {
+            self.inner.inbound_opened.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.inner.outbound_opened.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn on_closed(&self, id: usize) {
+        self.inner.closed.fetch_add(1, Ordering::Relaxed);
+        self.inner.substreams.lock().remove(&id);
+    }
+
+    pub(crate) fn on_outbound_requested(&self) {
+        self.inner.pending_outbound.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_outbound_destroyed(&self) {
+        let _ = self.inner.pending_outbound.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    pub(crate) fn on_rtt(&self, rtt: Duration) {
+        *self.inner.rtt.lock() = Some(rtt);
+    }
+
+    pub(crate) fn on_write_blocked(&self) {
+        self.inner.write_blocked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_read(&self, id: usize, n: usize) {
+        self.inner.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        if let Some(c) = self.inner.substreams.lock().get(&id) {
+            c.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn on_written(&self, id: usize, n: usize) {
+        self.inner.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+        if let Some(c) = self.inner.substreams.lock().get(&id) {
+            c.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+impl fmt::Debug for MuxerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MuxerStats")
+            .field("inbound_streams_opened", &self.inbound_streams_opened())
+            .field("outbound_streams_opened", &self.outbound_streams_opened())
+            .field("streams_closed", &self.streams_closed())
+            .field("pending_outbound_streams", &self.pending_outbound_streams())
+            .field("write_blocked", &self.write_blocked())
+            .field("bytes_read", &self.bytes_read())
+            .field("bytes_written", &self.bytes_written())
+            .field("rtt", &self.rtt())
+            .field("protocols", &self.protocols())
+            .finish()
+    }
+}
+
+/// A handle for attributing the traffic of a substream to its protocol,
+/// obtained from [`SubstreamRef::stats`](super::SubstreamRef::stats).
+#[derive(Debug, Clone)]
+pub struct SubstreamStats {
+    stats: MuxerStats,
+    id: usize,
+}
+
+impl SubstreamStats {
+    /// Attributes all further traffic of the substream to the given protocol.
+    pub fn set_protocol(&self, protocol: &[u8]) {
+        let counters = self.stats.inner.protocols.lock()
+            .entry(protocol.to_vec())
+            .or_default()
+            .clone();
+        counters.streams.fetch_add(1, Ordering::Relaxed);
+        self.stats.inner.substreams.lock().insert(self.id, counters);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_traffic_to_protocols() {
+        let stats = MuxerStats::new();
+        stats.on_opened(true);
+        stats.on_opened(false);
+        stats.on_read(0, 10);
+        stats.substream(0).set_protocol(b"/ping/1.0.0");
+        stats.substream(1).set_protocol(b"/ping/1.0.0");
+        stats.on_read(0, 32);
+        stats.on_written(1, 32);
+        stats.on_closed(0);
+        stats.on_read(0, 5);
+
+        assert_eq!(stats.open_streams(), 1);
+        assert_eq!(stats.bytes_read(), 47);
+        assert_eq!(stats.bytes_written(), 32);
+        assert_eq!(stats.protocols(), vec![("/ping/1.0.0".to_string(), ProtocolStats {
+            streams: 2,
+            bytes_read: 32,
+            bytes_written: 32,
+        })]);
+    }
+}
@@ -18,18 +18,22 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+mod bandwidth;
 mod event;
 pub mod peer;
 
 pub use crate::connection::{ConnectionLimits, ConnectionCounters};
+pub use bandwidth::ConnectionBandwidth;
 pub use event::{NetworkEvent, IncomingConnection};
 pub use peer::Peer;
 
 use crate::{
     ConnectedPoint,
+    Endpoint,
     Executor,
     Multiaddr,
     PeerId,
+    onion,
     connection::{
         ConnectionId,
         ConnectionLimit,
@@ -41,12 +45,14 @@ use crate::{
         ListenerId,
         ListenersStream,
         PendingConnectionError,
+        PendingConnectionInfo,
+        StageTimeouts,
         Substream,
         manager::ManagerConfig,
         pool::{Pool, PoolEvent},
     },
-    muxing::StreamMuxer,
-    transport::{Transport, TransportError},
+    muxing::{MuxerStats, ProtocolStats, StreamMuxer},
+    transport::{Transport, TransportError, DialCancelToken, cancel::Cancellable, role::AsListener},
 };
 use fnv::{FnvHashMap};
 use futures::{prelude::*, future};
@@ -56,6 +62,7 @@ use std::{
     convert::TryFrom as _,
     error,
     fmt,
+    io,
     num::NonZeroUsize,
     pin::Pin,
     task::{Context, Poll},
@@ -90,6 +97,9 @@ where
     /// > `Network` (see `dial_peer_impl` and `on_connection_failed`)
     /// > together with the implementation of `DialingAttempt::abort`.
     dialing: FnvHashMap<PeerId, SmallVec<[peer::DialingState; 10]>>,
+
+    /// The traffic of the connections per negotiated protocol.
+    bandwidth: bandwidth::BandwidthTracker,
 }
 
 impl<TTrans, TInEvent, TOutEvent, THandler> fmt::Debug for
@@ -149,6 +159,7 @@ where
             listeners: ListenersStream::new(transport),
             pool: Pool::new(local_peer_id, config.manager_config, config.limits),
             dialing: Default::default(),
+            bandwidth: Default::default(),
         }
     }
 
@@ -186,12 +197,25 @@ where
     /// other than the peer who reported the `observed_addr`.
     ///
     /// The translation is transport-specific. See [`Transport::address_translation`].
+    /// Observed onion addresses are returned unchanged if their key matches the
+    /// local peer and dropped otherwise, see [`onion::matches_peer`].
     pub fn address_translation<'a>(&'a self, observed_addr: &'a Multiaddr)
         -> impl Iterator<Item = Multiaddr> + 'a
     where
         TMuxer: 'a,
         THandler: 'a,
     {
+        // Onion addresses are not subject to any translation. An observed
+        // onion address is the one of our onion service, if it is ours at all.
+        if onion::is_onion(observed_addr) {
+            let addrs = if onion::matches_peer(observed_addr, &self.local_peer_id) {
+                vec![observed_addr.clone()]
+            } else {
+                Vec::new()
+            };
+            return addrs.into_iter()
+        }
+
         let transport = self.listeners.transport();
         let mut addrs: Vec<_> = self.listen_addrs()
             .filter_map(move |server| transport.address_translation(server, observed_addr))
@@ -224,12 +248,47 @@ where
         TMuxer::OutboundSubstream: Send,
         TInEvent: Send + 'static,
         TOutEvent: Send + 'static,
+    {
+        self.dial_with_params(address, handler, DialParams::default())
+    }
+
+    /// Like [`Network::dial`], but the connection attempt is aborted when
+    /// the given token is cancelled.
+    ///
+    /// A cancelled connection attempt fails with a [`PendingConnectionError::IO`]
+    /// of kind [`std::io::ErrorKind::Interrupted`].
+    pub fn dial_with_cancel(&mut self, address: &Multiaddr, handler: THandler, cancel: DialCancelToken)
+        -> Result<ConnectionId, ConnectionLimit>
+    where
+        TTrans: Transport<Output = (PeerId, TMuxer)>,
+        TTrans::Error: Send + 'static,
+        TTrans::Dial: Send + 'static,
+        TMuxer: Send + Sync + 'static,
+        TMuxer::OutboundSubstream: Send,
+        TInEvent: Send + 'static,
+        TOutEvent: Send + 'static,
+    {
+        self.dial_with_params(address, handler, DialParams::default().with_cancel(cancel))
+    }
+
+    /// Like [`Network::dial`], but with the given [`DialParams`] for the
+    /// connection attempt.
+    pub fn dial_with_params(&mut self, address: &Multiaddr, handler: THandler, params: DialParams)
+        -> Result<ConnectionId, ConnectionLimit>
+    where
+        TTrans: Transport<Output = (PeerId, TMuxer)>,
+        TTrans::Error: Send + 'static,
+        TTrans::Dial: Send + 'static,
+        TMuxer: Send + Sync + 'static,
+        TMuxer::OutboundSubstream: Send,
+        TInEvent: Send + 'static,
+        TOutEvent: Send + 'static,
     {
         let info = OutgoingInfo { address, peer_id: None };
         match self.transport().clone().dial(address.clone()) {
             Ok(f) => {
                 let f = f.map_err(|err| PendingConnectionError::Transport(TransportError::Other(err)));
-                self.pool.add_outgoing(f, handler, info)
+                self.pool.add_outgoing(with_params(f, &params), handler, info)
             }
             Err(err) => {
                 let f = future::err(PendingConnectionError::Transport(err));
@@ -242,17 +301,36 @@ where
     pub fn info(&self) -> NetworkInfo {
         let peers: Vec<PeerId> = self.pool.iter_connected().cloned().collect();
         let connection_counters = self.pool.counters().clone();
+        let connection_bandwidth = self.bandwidth.connections();
+        let protocol_bandwidth = self.bandwidth.protocols(&connection_bandwidth);
         NetworkInfo {
             peers,
             connection_counters,
+            connection_bandwidth,
+            protocol_bandwidth,
         }
     }
 
+    /// Returns the statistics of the stream multiplexer of an established
+    /// connection, if the connection exists and its multiplexer collects
+    /// statistics, as [`StreamMuxerBox`](crate::muxing::StreamMuxerBox) does.
+    ///
+    /// The returned statistics are updated as long as the connection is open.
+    pub fn connection_stats(&mut self, id: ConnectionId) -> Option<MuxerStats> {
+        self.pool.get_established(id).and_then(|c| c.stats().cloned())
+    }
+
     /// Returns an iterator for information on all pending incoming connections.
     pub fn incoming_info(&self) -> impl Iterator<Item = IncomingInfo<'_>> {
         self.pool.iter_pending_incoming()
     }
 
+    /// Returns an iterator over all pending connections, incoming and outgoing,
+    /// together with the time elapsed in each stage of the connection setup.
+    pub fn pending_connections(&self) -> impl Iterator<Item = PendingConnectionInfo<'_>> {
+        self.pool.iter_pending_progress()
+    }
+
     /// Returns the list of addresses we're currently dialing without knowing the `PeerId` of.
     pub fn unknown_dials(&self) -> impl Iterator<Item = &Multiaddr> {
         self.pool.iter_pending_outgoing()
@@ -287,6 +365,28 @@ where
         !self.is_connected(peer) && !self.is_dialing(peer)
     }
 
+    /// Protects the connections of a peer from being evicted, returning
+    /// `false` if the peer was already protected.
+    ///
+    /// When the limit of established incoming (outgoing) connections is
+    /// reached, a new connection of a protected peer is not rejected but
+    /// evicts an established incoming (outgoing) connection of an unprotected
+    /// peer instead, if there is one, see [`ConnectionLimits`].
+    pub fn add_protected_peer(&mut self, peer: PeerId) -> bool {
+        self.pool.add_protected_peer(peer)
+    }
+
+    /// Removes the protection of the connections of a peer, returning
+    /// `false` if the peer was not protected.
+    pub fn remove_protected_peer(&mut self, peer: &PeerId) -> bool {
+        self.pool.remove_protected_peer(peer)
+    }
+
+    /// Checks whether the connections of a peer are protected.
+    pub fn is_protected(&self, peer: &PeerId) -> bool {
+        self.pool.is_protected(peer)
+    }
+
     /// Returns a list of all the peers to whom a new outgoing connection
     /// is currently being established.
     pub fn dialing_peers(&self) -> impl Iterator<Item = &PeerId> {
@@ -386,6 +486,8 @@ where
                     }
                 }
 
+                self.bandwidth.on_established(connection.peer_id(), connection.id(), connection.stats());
+
                 NetworkEvent::ConnectionEstablished {
                     connection,
                     num_established,
@@ -397,12 +499,13 @@ where
                 if let Some(dial) = next {
                     let transport = self.listeners.transport().clone();
                     if let Err(e) = dial_peer_impl(transport, pool, dialing, dial) {
-                        log::warn!("Dialing aborted: {:?}", e);
+                        tracing::warn!("Dialing aborted: {:?}", e);
                     }
                 }
                 event
             }
             Poll::Ready(PoolEvent::ConnectionClosed { id, connected, error, num_established, .. }) => {
+                self.bandwidth.on_closed(&id);
                 NetworkEvent::ConnectionClosed {
                     id,
                     connected,
@@ -451,6 +554,82 @@ struct DialingOpts<PeerId, THandler> {
     handler: THandler,
     address: Multiaddr,
     remaining: Vec<Multiaddr>,
+    params: DialParams,
+}
+
+/// Parameters of a dialing attempt, see [`Network::dial_with_params`] and
+/// [`Peer::dial_with_params`].
+#[derive(Debug, Clone, Default)]
+pub struct DialParams {
+    cancel: Option<DialCancelToken>,
+    role_override: Option<Endpoint>,
+}
+
+impl DialParams {
+    /// Aborts the dialing attempt when the given token is cancelled.
+    ///
+    /// A cancelled connection attempt fails with a [`PendingConnectionError::IO`]
+    /// of kind [`std::io::ErrorKind::Interrupted`] and no further addresses
+    /// of the dialing attempt are tried.
+    pub fn with_cancel(mut self, cancel: DialCancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Overrides the role of the local node in the authentication and
+    /// multiplexing upgrades of the outgoing connections, which is
+    /// [`Endpoint::Dialer`] by default.
+    ///
+    /// Acting as the [`Endpoint::Listener`] is needed for hole punching,
+    /// where both peers dial each other simultaneously. The override applies
+    /// to the upgrades of an [`upgrade::Builder`](crate::transport::upgrade::Builder)
+    /// and the connection is still reported with a [`ConnectedPoint::Dialer`].
+    pub fn with_role_override(mut self, role: Endpoint) -> Self {
+        self.role_override = Some(role);
+        self
+    }
+
+    /// Returns the token for cancelling the dialing attempt, if any.
+    pub fn cancel(&self) -> Option<&DialCancelToken> {
+        self.cancel.as_ref()
+    }
+
+    /// Returns the role override of the dialing attempt, if any.
+    pub fn role_override(&self) -> Option<Endpoint> {
+        self.role_override
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().map_or(false, |c| c.is_cancelled())
+    }
+}
+
+/// Wraps the future of a connection attempt according to the given [`DialParams`].
+fn with_params<F, T, E>(future: F, params: &DialParams)
+    -> impl Future<Output = Result<T, PendingConnectionError<E>>>
+where
+    F: Future<Output = Result<T, PendingConnectionError<E>>>,
+{
+    let future = match &params.cancel {
+        Some(cancel) => future::Either::Left(cancellable(future, cancel.clone())),
+        None => future::Either::Right(future),
+    };
+    match params.role_override {
+        Some(Endpoint::Listener) => future::Either::Left(AsListener::new(future)),
+        _ => future::Either::Right(future),
+    }
+}
+
+/// Wraps the future of a connection attempt such that it fails when the
+/// given token is cancelled.
+fn cancellable<F, T, E>(future: F, cancel: DialCancelToken)
+    -> impl Future<Output = Result<T, PendingConnectionError<E>>>
+where
+    F: Future<Output = Result<T, PendingConnectionError<E>>>,
+{
+    Cancellable::new(future, cancel).map(|result| result.unwrap_or_else(|| {
+        Err(PendingConnectionError::IO(io::Error::new(io::ErrorKind::Interrupted, "dial cancelled")))
+    }))
 }
 
 /// Standalone implementation of `Network::dial_peer` for more granular borrowing.
@@ -482,7 +661,7 @@ where
         Ok(fut) => {
             let fut = fut.map_err(|e| PendingConnectionError::Transport(TransportError::Other(e)));
             let info = OutgoingInfo { address: &opts.address, peer_id: Some(&opts.peer) };
-            pool.add_outgoing(fut, opts.handler, info)
+            pool.add_outgoing(with_params(fut, &opts.params), opts.handler, info)
         },
         Err(err) => {
             let fut = future::err(PendingConnectionError::Transport(err));
@@ -496,6 +675,7 @@ where
             peer::DialingState {
                 current: (*id, opts.address),
                 remaining: opts.remaining,
+                params: opts.params,
             },
         );
     }
@@ -540,14 +720,19 @@ where
         let failed_addr = attempt.current.1.clone();
 
         let (opts, attempts_remaining) =
-            if num_remain > 0 {
+            if attempt.params.is_cancelled() {
+                // The dialing attempt has been cancelled, so the
+                // remaining addresses are not tried.
+                (None, 0)
+            } else if num_remain > 0 {
                 if let Some(handler) = handler {
                     let next_attempt = attempt.remaining.remove(0);
                     let opts = DialingOpts {
                         peer: peer_id,
                         handler,
                         address: next_attempt,
-                        remaining: attempt.remaining
+                        remaining: attempt.remaining,
+                        params: attempt.params,
                     };
                     (Some(opts), num_remain)
                 } else {
@@ -591,6 +776,10 @@ pub struct NetworkInfo {
     peers: Vec<PeerId>,
     /// Counters of ongoing network connections.
     connection_counters: ConnectionCounters,
+    /// The traffic of the established connections.
+    connection_bandwidth: Vec<ConnectionBandwidth>,
+    /// The traffic per protocol of all connections, established and closed.
+    protocol_bandwidth: Vec<(String, ProtocolStats)>,
 }
 
 impl NetworkInfo {
@@ -604,6 +793,22 @@ impl NetworkInfo {
     pub fn connection_counters(&self) -> &ConnectionCounters {
         &self.connection_counters
     }
+
+    /// Gets the traffic of each established connection per negotiated
+    /// protocol, for connections whose stream multiplexer collects
+    /// statistics (see [`MuxerStats`]).
+    pub fn connection_bandwidth(&self) -> &[ConnectionBandwidth] {
+        &self.connection_bandwidth
+    }
+
+    /// Gets the traffic per negotiated protocol, summed over all connections
+    /// since the creation of the `Network`, including closed connections.
+    ///
+    /// Since the protocol names are those negotiated on the substreams,
+    /// this attributes the traffic to the behaviours owning the protocols.
+    pub fn protocol_bandwidth(&self) -> &[(String, ProtocolStats)] {
+        &self.protocol_bandwidth
+    }
 }
 
 /// The (optional) configuration for a [`Network`].
@@ -666,6 +871,16 @@ impl NetworkConfig {
         self.limits = limits;
         self
     }
+
+    /// Sets the timeouts of the individual stages of pending connections.
+    ///
+    /// A pending connection exceeding the timeout of a stage fails with a
+    /// [`PendingConnectionError::IO`] error of kind [`std::io::ErrorKind::TimedOut`],
+    /// wrapping a [`StageTimeout`](crate::connection::StageTimeout).
+    pub fn with_stage_timeouts(mut self, timeouts: StageTimeouts) -> Self {
+        self.manager_config.stage_timeouts = timeouts;
+        self
+    }
 }
 
 #[cfg(test)]
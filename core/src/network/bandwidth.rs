@@ -0,0 +1,161 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Traffic accounting of established connections per negotiated protocol.
+
+use crate::{PeerId, connection::ConnectionId, muxing::{MuxerStats, ProtocolStats}};
+use fnv::FnvHashMap;
+
+/// A snapshot of the traffic of an established connection.
+#[derive(Clone, Debug)]
+pub struct ConnectionBandwidth {
+    peer_id: PeerId,
+    id: ConnectionId,
+    bytes_read: u64,
+    bytes_written: u64,
+    protocols: Vec<(String, ProtocolStats)>,
+}
+
+impl ConnectionBandwidth {
+    /// The peer of the connection.
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    /// The ID of the connection.
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /// The number of bytes read from all substreams of the connection.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// The number of bytes written to all substreams of the connection.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// The traffic of the connection per negotiated protocol, sorted by
+    /// protocol name.
+    pub fn protocols(&self) -> &[(String, ProtocolStats)] {
+        &self.protocols
+    }
+}
+
+/// Tracks the traffic of the established connections, retaining the
+/// traffic of closed connections per protocol.
+#[derive(Debug, Default)]
+pub(crate) struct BandwidthTracker {
+    /// The statistics of the established connections.
+    connections: FnvHashMap<ConnectionId, (PeerId, MuxerStats)>,
+    /// The traffic per protocol of all closed connections.
+    closed: FnvHashMap<String, ProtocolStats>,
+}
+
+impl BandwidthTracker {
+    /// Records a newly established connection, if its stream multiplexer
+    /// collects statistics.
+    pub(crate) fn on_established(&mut self, peer_id: PeerId, id: ConnectionId, stats: Option<&MuxerStats>) {
+        if let Some(stats) = stats {
+            self.connections.insert(id, (peer_id, stats.clone()));
+        }
+    }
+
+    /// Records a closed connection, retaining its traffic.
+    pub(crate) fn on_closed(&mut self, id: &ConnectionId) {
+        if let Some((_, stats)) = self.connections.remove(id) {
+            for (protocol, traffic) in stats.protocols() {
+                add(self.closed.entry(protocol).or_default(), &traffic);
+            }
+        }
+    }
+
+    /// Returns a snapshot of the traffic of the established connections.
+    pub(crate) fn connections(&self) -> Vec<ConnectionBandwidth> {
+        self.connections.iter()
+            .map(|(id, (peer_id, stats))| {
+                let mut protocols = stats.protocols();
+                protocols.sort_by(|a, b| a.0.cmp(&b.0));
+                ConnectionBandwidth {
+                    peer_id: *peer_id,
+                    id: *id,
+                    bytes_read: stats.bytes_read(),
+                    bytes_written: stats.bytes_written(),
+                    protocols,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the traffic per protocol of the given snapshot of the
+    /// established connections and of all closed connections, sorted by
+    /// protocol name.
+    pub(crate) fn protocols(&self, connections: &[ConnectionBandwidth]) -> Vec<(String, ProtocolStats)> {
+        let mut totals = self.closed.clone();
+        for connection in connections {
+            for (protocol, traffic) in &connection.protocols {
+                add(totals.entry(protocol.clone()).or_default(), traffic);
+            }
+        }
+        let mut totals = totals.into_iter().collect::<Vec<_>>();
+        totals.sort_by(|a, b| a.0.cmp(&b.0));
+        totals
+    }
+}
+
+fn add(total: &mut ProtocolStats, traffic: &ProtocolStats) {
+    total.streams += traffic.streams;
+    total.bytes_read += traffic.bytes_read;
+    total.bytes_written += traffic.bytes_written;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retains_traffic_of_closed_connections() {
+        let peer_id = PeerId::random();
+        let (stats1, stats2) = (MuxerStats::new(), MuxerStats::new());
+        stats1.substream(0).set_protocol(b"/ping/1.0.0");
+        stats1.on_written(0, 32);
+        stats2.substream(0).set_protocol(b"/ping/1.0.0");
+        stats2.substream(1).set_protocol(b"/ipfs/id/1.0.0");
+        stats2.on_read(0, 32);
+        stats2.on_read(1, 100);
+
+        let mut tracker = BandwidthTracker::default();
+        tracker.on_established(peer_id, ConnectionId::new(1), Some(&stats1));
+        tracker.on_established(peer_id, ConnectionId::new(2), Some(&stats2));
+        tracker.on_established(peer_id, ConnectionId::new(3), None);
+        tracker.on_closed(&ConnectionId::new(1));
+
+        let connections = tracker.connections();
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].id(), ConnectionId::new(2));
+        assert_eq!(connections[0].bytes_read(), 132);
+        assert_eq!(tracker.protocols(&connections), vec![
+            ("/ipfs/id/1.0.0".to_string(), ProtocolStats { streams: 1, bytes_read: 100, bytes_written: 0 }),
+            ("/ping/1.0.0".to_string(), ProtocolStats { streams: 2, bytes_read: 32, bytes_written: 32 }),
+        ]);
+    }
+}
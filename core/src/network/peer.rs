@@ -36,7 +36,8 @@ use crate::{
         Substream,
         pool::Pool,
     },
-    PeerId
+    PeerId,
+    transport::DialCancelToken,
 };
 use fnv::FnvHashMap;
 use smallvec::SmallVec;
@@ -45,7 +46,7 @@ use std::{
     error,
     fmt,
 };
-use super::{Network, DialingOpts};
+use super::{Network, DialingOpts, DialParams};
 
 /// The possible representations of a peer in a [`Network`], as
 /// seen by the local node.
@@ -214,6 +215,32 @@ where
         >
     where
         I: IntoIterator<Item = Multiaddr>,
+    {
+        self.dial_with_params(address, remaining, handler, DialParams::default())
+    }
+
+    /// Like [`Peer::dial`], but the dialing attempt, i.e. the current and all
+    /// remaining connection attempts, is aborted when the given token is cancelled.
+    pub fn dial_with_cancel<I>(self, address: Multiaddr, remaining: I, handler: THandler, cancel: DialCancelToken)
+        -> Result<
+            (ConnectionId, DialingPeer<'a, TTrans, TInEvent, TOutEvent, THandler>),
+            ConnectionLimit
+        >
+    where
+        I: IntoIterator<Item = Multiaddr>,
+    {
+        self.dial_with_params(address, remaining, handler, DialParams::default().with_cancel(cancel))
+    }
+
+    /// Like [`Peer::dial`], but with the given [`DialParams`] for all
+    /// connection attempts of the dialing attempt.
+    pub fn dial_with_params<I>(self, address: Multiaddr, remaining: I, handler: THandler, params: DialParams)
+        -> Result<
+            (ConnectionId, DialingPeer<'a, TTrans, TInEvent, TOutEvent, THandler>),
+            ConnectionLimit
+        >
+    where
+        I: IntoIterator<Item = Multiaddr>,
     {
         let (peer_id, network) = match self {
             Peer::Connected(p) => (p.peer_id, p.network),
@@ -227,6 +254,7 @@ where
             handler,
             address,
             remaining: remaining.into_iter().collect(),
+            params,
         })?;
 
         Ok((id, DialingPeer { network, peer_id }))
@@ -570,6 +598,8 @@ pub(super) struct DialingState {
     pub(super) current: (ConnectionId, Multiaddr),
     /// Multiaddresses to attempt if the current one fails.
     pub(super) remaining: Vec<Multiaddr>,
+    /// The parameters of the dialing attempt.
+    pub(super) params: DialParams,
 }
 
 /// A `DialingAttempt` is an ongoing outgoing connection attempt to
@@ -0,0 +1,175 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Optional tagging of a node identity with the network it belongs to.
+//!
+//! A [`NetworkId`] is a short identifier of a network, e.g. `mainnet` or
+//! `floonet`. A node claims membership of a network by means of a
+//! [`SignedNetworkId`], i.e. the network identifier signed with the node's
+//! identity keypair. Since the signature can be verified against the public
+//! key underlying the node's [`PeerId`], the claim can neither be forged nor
+//! transferred to another identity.
+//!
+//! Exchanging signed network identifiers (e.g. as part of the identify
+//! protocol) allows nodes to cheaply reject peers belonging to a different
+//! network before any application data is exchanged.
+
+use crate::identity::{Keypair, error::{DecodingError, SigningError}};
+use crate::{PeerId, PublicKey, SimplePopSerializer, SimplePushSerializer};
+use std::fmt;
+
+/// Domain separation prefix of the signed payload.
+const SIGNING_PREFIX: &[u8] = b"libp2p-network-id:";
+
+/// Version of the `SignedNetworkId` encoding.
+const ENCODING_VERSION: u16 = 1;
+
+/// The identifier of a network, e.g. `mainnet` or `floonet`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NetworkId(String);
+
+impl NetworkId {
+    /// The maximum length of a network identifier in bytes.
+    pub const MAX_LEN: usize = 32;
+
+    /// Creates a new network identifier.
+    ///
+    /// Returns `None` if the identifier is empty or longer than [`NetworkId::MAX_LEN`].
+    pub fn new(id: impl Into<String>) -> Option<Self> {
+        let id = id.into();
+        if id.is_empty() || id.len() > Self::MAX_LEN {
+            return None
+        }
+        Some(NetworkId(id))
+    }
+
+    /// Returns the network identifier as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the payload which is signed to claim membership of this network.
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(SIGNING_PREFIX.len() + self.0.len());
+        payload.extend_from_slice(SIGNING_PREFIX);
+        payload.extend_from_slice(self.0.as_bytes());
+        payload
+    }
+}
+
+impl fmt::Display for NetworkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A [`NetworkId`] signed with the identity keypair of a node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedNetworkId {
+    network_id: NetworkId,
+    signature: Vec<u8>,
+}
+
+impl SignedNetworkId {
+    /// Signs the given network identifier with the identity keypair of the local node.
+    pub fn new(keypair: &Keypair, network_id: NetworkId) -> Result<Self, SigningError> {
+        let signature = keypair.sign(&network_id.signing_payload())?;
+        Ok(SignedNetworkId { network_id, signature })
+    }
+
+    /// Returns the claimed network identifier.
+    ///
+    /// > **Note**: The claim is only valid if [`SignedNetworkId::verify`]
+    /// > succeeds for the public key of the remote.
+    pub fn network_id(&self) -> &NetworkId {
+        &self.network_id
+    }
+
+    /// Verifies that the network identifier has been signed by the given key.
+    pub fn verify(&self, public_key: &PublicKey) -> bool {
+        public_key.verify(&self.network_id.signing_payload(), &self.signature)
+    }
+
+    /// Verifies that the network identifier has been signed by the given key
+    /// and that this key is the key of the given peer.
+    pub fn verify_peer(&self, peer_id: &PeerId, public_key: &PublicKey) -> bool {
+        peer_id.is_public_key(public_key) == Some(true) && self.verify(public_key)
+    }
+
+    /// Encodes the signed network identifier for exchange with other nodes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ser = SimplePushSerializer::new(ENCODING_VERSION);
+        ser.push_vec(self.network_id.0.as_bytes());
+        ser.push_vec(&self.signature);
+        ser.to_vec()
+    }
+
+    /// Decodes a signed network identifier received from another node.
+    ///
+    /// > **Note**: Decoding does not verify the signature.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodingError> {
+        let mut ser = SimplePopSerializer::new(bytes);
+        if ser.version != ENCODING_VERSION {
+            return Err(DecodingError::new(format!("unknown network id version: {}", ser.version)))
+        }
+        let network_id = String::from_utf8(ser.pop_vec())
+            .map_err(|e| DecodingError::new("network id").source(e))?;
+        let network_id = NetworkId::new(network_id)
+            .ok_or_else(|| DecodingError::new("invalid network id length"))?;
+        let signature = ser.pop_vec();
+        if signature.is_empty() {
+            return Err(DecodingError::new("missing network id signature"))
+        }
+        Ok(SignedNetworkId { network_id, signature })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity;
+
+    #[test]
+    fn signed_network_id_roundtrip() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let network_id = NetworkId::new("floonet").unwrap();
+        let signed = SignedNetworkId::new(&keypair, network_id.clone()).unwrap();
+        let decoded = SignedNetworkId::from_bytes(&signed.to_bytes()).unwrap();
+        assert_eq!(decoded, signed);
+        assert_eq!(decoded.network_id(), &network_id);
+        assert!(decoded.verify_peer(&keypair.public().into_peer_id(), &keypair.public()));
+    }
+
+    #[test]
+    fn signed_network_id_rejects_other_key() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let other = identity::Keypair::generate_ed25519();
+        let signed = SignedNetworkId::new(&keypair, NetworkId::new("mainnet").unwrap()).unwrap();
+        assert!(!signed.verify(&other.public()));
+        assert!(!signed.verify_peer(&other.public().into_peer_id(), &keypair.public()));
+    }
+
+    #[test]
+    fn network_id_length() {
+        assert!(NetworkId::new("").is_none());
+        assert!(NetworkId::new("a".repeat(NetworkId::MAX_LEN)).is_some());
+        assert!(NetworkId::new("a".repeat(NetworkId::MAX_LEN + 1)).is_none());
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Helpers for handling onion addresses.
+//!
+//! The key of an Onion v3 address, i.e. a [`Protocol::Onion3`], is the ed25519
+//! public key of the onion service. If the service uses the same key as its
+//! libp2p identity, as it is the case for the nodes of this network, the key
+//! determines the [`PeerId`] that can be reached through the address.
+//!
+//! Onion v2 addresses, i.e. [`Protocol::Onion`], only contain a truncated hash
+//! of the key of the service and can't be checked against a [`PeerId`].
+
+use crate::{PeerId, PublicKey, identity::ed25519};
+use multiaddr::{Multiaddr, Onion3Addr, Protocol};
+use sha3::{Digest, Sha3_256};
+
+/// The version byte of Onion v3 addresses.
+const ONION3_VERSION: u8 = 0x03;
+
+/// Builds the Onion v3 address of the onion service with the given key.
+pub fn onion3_address(key: &ed25519::PublicKey, port: u16) -> Onion3Addr<'static> {
+    let key = key.encode();
+    let checksum = checksum(&key);
+    let mut hash = [0; 35];
+    hash[.. 32].copy_from_slice(&key);
+    hash[32 .. 34].copy_from_slice(&checksum);
+    hash[34] = ONION3_VERSION;
+    (hash, port).into()
+}
+
+/// Returns the key of the onion service of an Onion v3 address.
+///
+/// Returns `None` if the checksum or the version of the address is invalid.
+pub fn onion3_public_key(addr: &Onion3Addr<'_>) -> Option<ed25519::PublicKey> {
+    let hash = addr.hash();
+    if hash[34] != ONION3_VERSION || hash[32 .. 34] != checksum(&hash[.. 32]) {
+        return None
+    }
+    ed25519::PublicKey::decode(&hash[.. 32]).ok()
+}
+
+/// Returns the `PeerId` that can be reached through an Onion v3 address.
+pub fn onion3_peer_id(addr: &Onion3Addr<'_>) -> Option<PeerId> {
+    onion3_public_key(addr).map(|key| PublicKey::Ed25519(key).into_peer_id())
+}
+
+/// Returns the first onion component, i.e. [`Protocol::Onion`] or
+/// [`Protocol::Onion3`], of an address.
+pub fn onion_component(addr: &Multiaddr) -> Option<Protocol<'_>> {
+    addr.iter().find(|p| matches!(p, Protocol::Onion(..) | Protocol::Onion3(_)))
+}
+
+/// Whether the address contains an onion component.
+pub fn is_onion(addr: &Multiaddr) -> bool {
+    onion_component(addr).is_some()
+}
+
+/// Returns the address without its onion components.
+pub fn strip_onion(addr: &Multiaddr) -> Multiaddr {
+    addr.iter()
+        .filter(|p| !matches!(p, Protocol::Onion(..) | Protocol::Onion3(_)))
+        .collect()
+}
+
+/// Whether both addresses point to the same onion service, i.e. their
+/// first onion components have the same key. Ports are ignored.
+///
+/// Returns `false` if any of the addresses has no onion component.
+pub fn same_onion_service(a: &Multiaddr, b: &Multiaddr) -> bool {
+    match (onion_component(a), onion_component(b)) {
+        (Some(Protocol::Onion(a, _)), Some(Protocol::Onion(b, _))) => a == b,
+        (Some(Protocol::Onion3(a)), Some(Protocol::Onion3(b))) => a.hash() == b.hash(),
+        _ => false,
+    }
+}
+
+/// Whether the address may be used to reach the given peer, i.e. the keys of
+/// the Onion v3 components and the `/p2p` components of its final hop, if
+/// any, match the peer.
+///
+/// The final hop of a relayed address is the part after its last
+/// `/p2p-circuit`, the components before it lead to the relays.
+///
+/// Onion v3 components with an invalid checksum or version never match.
+pub fn matches_peer(addr: &Multiaddr, peer_id: &PeerId) -> bool {
+    let final_hop = addr.iter()
+        .enumerate()
+        .filter(|(_, p)| *p == Protocol::P2pCircuit)
+        .last()
+        .map_or(0, |(i, _)| i + 1);
+    addr.iter().skip(final_hop).all(|p| match p {
+        Protocol::Onion3(onion) => onion3_peer_id(&onion).as_ref() == Some(peer_id),
+        Protocol::P2p(hash) => PeerId::from_multihash(hash).ok().as_ref() == Some(peer_id),
+        _ => true,
+    })
+}
+
+/// Appends a `/p2p` component with the given peer to the address, unless it
+/// already ends with one.
+///
+/// Returns the address unchanged as an `Err` if it doesn't match the peer, see
+/// [`matches_peer`].
+pub fn with_p2p(addr: Multiaddr, peer_id: &PeerId) -> Result<Multiaddr, Multiaddr> {
+    if !matches_peer(&addr, peer_id) {
+        return Err(addr)
+    }
+    match addr.iter().last() {
+        Some(Protocol::P2p(_)) => Ok(addr),
+        _ => Ok(addr.with(Protocol::P2p((*peer_id).into()))),
+    }
+}
+
+/// The checksum of an Onion v3 address with the given key.
+fn checksum(key: &[u8]) -> [u8; 2] {
+    let mut hasher = Sha3_256::new();
+    hasher.input(b".onion checksum");
+    hasher.input(key);
+    hasher.input([ONION3_VERSION]);
+    let hash = hasher.result();
+    [hash[0], hash[1]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity;
+
+    fn onion3(key: &ed25519::PublicKey, port: u16) -> Multiaddr {
+        Multiaddr::empty().with(Protocol::Onion3(onion3_address(key, port)))
+    }
+
+    #[test]
+    fn onion3_address_roundtrip() {
+        let key = ed25519::Keypair::generate().public();
+        let peer_id = PublicKey::Ed25519(key.clone()).into_peer_id();
+        let addr = onion3_address(&key, 80);
+
+        assert_eq!(onion3_public_key(&addr).map(|k| k.encode()), Some(key.encode()));
+        assert_eq!(onion3_peer_id(&addr), Some(peer_id));
+
+        // The textual representation matches the one of the `PeerId`.
+        let text = Multiaddr::empty().with(Protocol::Onion3(addr)).to_string();
+        assert_eq!(text, format!("/onion3/{}:80", peer_id.as_onion_address().unwrap()));
+    }
+
+    #[test]
+    fn invalid_checksum_is_rejected() {
+        let key = ed25519::Keypair::generate().public();
+        let mut hash = *onion3_address(&key, 80).hash();
+        hash[33] ^= 0xff;
+        assert!(onion3_public_key(&(hash, 80).into()).is_none());
+    }
+
+    #[test]
+    fn matches_peer_checks_onion_and_p2p() {
+        let key = ed25519::Keypair::generate().public();
+        let peer_id = PublicKey::Ed25519(key.clone()).into_peer_id();
+        let other = identity::Keypair::generate_ed25519().public().into_peer_id();
+        let addr = onion3(&key, 80);
+
+        assert!(matches_peer(&addr, &peer_id));
+        assert!(!matches_peer(&addr, &other));
+        assert!(!matches_peer(&addr.clone().with(Protocol::P2p(other.into())), &peer_id));
+        assert!(matches_peer(&"/ip4/1.2.3.4/tcp/80".parse().unwrap(), &other));
+
+        assert_eq!(with_p2p(addr.clone(), &peer_id), Ok(addr.clone().with(Protocol::P2p(peer_id.into()))));
+        assert_eq!(with_p2p(addr.clone(), &other), Err(addr));
+    }
+
+    #[test]
+    fn matches_peer_checks_final_hop_of_relayed_addresses() {
+        let relay = identity::Keypair::generate_ed25519().public().into_peer_id();
+        let dst = identity::Keypair::generate_ed25519().public().into_peer_id();
+        let circuit = "/ip4/1.2.3.4/tcp/80".parse::<Multiaddr>().unwrap()
+            .with(Protocol::P2p(relay.into()))
+            .with(Protocol::P2pCircuit);
+
+        assert!(matches_peer(&circuit, &dst));
+        assert!(matches_peer(&circuit.clone().with(Protocol::P2p(dst.into())), &dst));
+        assert!(!matches_peer(&circuit.clone().with(Protocol::P2p(relay.into())), &dst));
+        assert_eq!(
+            with_p2p(circuit.clone(), &dst),
+            Ok(circuit.clone().with(Protocol::P2p(dst.into())))
+        );
+
+        // The onion service of the relay doesn't need to match the destination.
+        let key = ed25519::Keypair::generate().public();
+        let onion_relay = onion3(&key, 80)
+            .with(Protocol::P2p(PublicKey::Ed25519(key.clone()).into_peer_id().into()))
+            .with(Protocol::P2pCircuit);
+        assert!(matches_peer(&onion_relay, &dst));
+        assert!(!matches_peer(&onion_relay.clone().with(Protocol::Onion3(onion3_address(&key, 80))), &dst));
+    }
+
+    #[test]
+    fn strip_and_compare_onion_components() {
+        let key = ed25519::Keypair::generate().public();
+        let a = onion3(&key, 80).with(Protocol::Tcp(1));
+        let b = onion3(&key, 443);
+        let c = onion3(&ed25519::Keypair::generate().public(), 80);
+
+        assert!(is_onion(&a));
+        assert_eq!(strip_onion(&a), Multiaddr::empty().with(Protocol::Tcp(1)));
+        assert!(same_onion_service(&a, &b));
+        assert!(!same_onion_service(&a, &c));
+        assert!(!same_onion_service(&a, &strip_onion(&a)));
+    }
+}
@@ -0,0 +1,449 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Deterministic simulation of networks of nodes for tests.
+//!
+//! A [`Simulation`] runs tasks, e.g. swarms and the background tasks of
+//! their connections, on the current thread. Tasks that are woken up
+//! together are polled in an order determined by the seed of the
+//! simulation, so a failing test can be replayed with the same seed.
+//!
+//! Time is measured by a virtual clock, which only advances when all tasks
+//! are idle, straight to the deadline of the next [`Sleep`]. Tests waiting
+//! for timeouts or latencies therefore complete without waiting for the
+//! wall clock.
+//!
+//! Nodes are connected through the [`SimTransport`], a [`MemoryTransport`]
+//! whose outgoing connections are established after the latency of the
+//! simulated network has elapsed on the virtual clock.
+//!
+//! > **Note**: Timers based on the wall clock, e.g. `wasm_timer::Delay`,
+//! > are not driven by the virtual clock. The ports of the `/memory`
+//! > addresses of dialers are chosen randomly, independent of the seed.
+//!
+//! ```
+//! use libp2p_core::simulation::Simulation;
+//! use std::time::Duration;
+//!
+//! let mut simulation = Simulation::new(42);
+//! let handle = simulation.handle();
+//! let (tx, rx) = futures::channel::oneshot::channel();
+//! simulation.spawn(async move {
+//!     handle.sleep(Duration::from_secs(60)).await;
+//!     tx.send(()).unwrap();
+//! });
+//!
+//! assert_eq!(simulation.block_on(rx), Some(Ok(())));
+//! assert_eq!(simulation.now(), Duration::from_secs(60));
+//! ```
+
+use crate::{Executor, Multiaddr, Transport};
+use crate::transport::{
+    TransportError,
+    memory::{Channel, Listener, MemoryTransport, MemoryTransportError},
+};
+use futures::{future::{self, BoxFuture}, prelude::*, task::{self, ArcWake}};
+use parking_lot::Mutex;
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+use std::{
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// A task run by a [`Simulation`].
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// The ID of the future passed to [`Simulation::block_on`].
+const MAIN_TASK: u64 = u64::MAX;
+
+/// The state shared between a [`Simulation`], its handles and wakers.
+#[derive(Default)]
+struct Shared {
+    /// The time elapsed on the virtual clock since the start of the simulation.
+    now: Duration,
+    /// The wakers of the pending sleeps, by deadline and order of registration.
+    timers: BTreeMap<(Duration, u64), Waker>,
+    /// The number of sleeps registered so far.
+    timer_seq: u64,
+    /// The IDs of the tasks that have been woken up.
+    woken: BTreeSet<u64>,
+    /// The tasks spawned through a handle, not yet taken over by the simulation.
+    spawned: Vec<Task>,
+}
+
+/// Wakes up a task of a [`Simulation`].
+struct TaskWaker {
+    id: u64,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl ArcWake for TaskWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.shared.lock().woken.insert(arc_self.id);
+    }
+}
+
+/// A deterministic executor of tasks with a virtual clock.
+///
+/// See the [module documentation](self) for details.
+pub struct Simulation {
+    shared: Arc<Mutex<Shared>>,
+    /// The tasks that have not completed yet.
+    tasks: HashMap<u64, Task>,
+    /// The ID of the next task.
+    next_task: u64,
+    /// Determines the order in which woken tasks are polled.
+    rng: StdRng,
+}
+
+impl Simulation {
+    /// Creates a new `Simulation` without tasks, polling the tasks in an
+    /// order determined by the given seed.
+    pub fn new(seed: u64) -> Self {
+        Simulation {
+            shared: Arc::new(Mutex::new(Shared::default())),
+            tasks: HashMap::new(),
+            next_task: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Returns a handle to the simulation, for spawning tasks, sleeping on
+    /// the virtual clock and creating transports.
+    pub fn handle(&self) -> SimulationHandle {
+        SimulationHandle { shared: self.shared.clone() }
+    }
+
+    /// Returns the time elapsed on the virtual clock since the start of
+    /// the simulation.
+    pub fn now(&self) -> Duration {
+        self.shared.lock().now
+    }
+
+    /// Spawns a task, which is first polled by the next run of the simulation.
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + Send + 'static) {
+        self.handle().spawn(future)
+    }
+
+    /// Polls the tasks until all of them are idle, without advancing the
+    /// virtual clock.
+    pub fn run_until_stalled(&mut self) {
+        while self.poll_woken() {}
+    }
+
+    /// Runs the tasks while advancing the virtual clock by the given duration.
+    pub fn run_for(&mut self, duration: Duration) {
+        let end = self.now() + duration;
+        loop {
+            self.run_until_stalled();
+            match self.next_deadline() {
+                Some(deadline) if deadline <= end => { self.advance(); }
+                _ => break
+            }
+        }
+        let mut shared = self.shared.lock();
+        shared.now = cmp::max(shared.now, end);
+    }
+
+    /// Runs the tasks together with the given future until the future
+    /// completes, advancing the virtual clock whenever all are idle.
+    ///
+    /// Returns `None` if the future can not complete, i.e. if the future
+    /// and all tasks are idle and no [`Sleep`] is pending.
+    pub fn block_on<F: Future>(&mut self, future: F) -> Option<F::Output> {
+        futures::pin_mut!(future);
+        let waker = task::waker(Arc::new(TaskWaker { id: MAIN_TASK, shared: self.shared.clone() }));
+        self.shared.lock().woken.insert(MAIN_TASK);
+        loop {
+            if self.shared.lock().woken.remove(&MAIN_TASK) {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                    return Some(output)
+                }
+            }
+            if !self.poll_woken() && !self.shared.lock().woken.contains(&MAIN_TASK) && !self.advance() {
+                return None
+            }
+        }
+    }
+
+    /// Advances the virtual clock to the deadline of the next [`Sleep`],
+    /// waking up the tasks waiting for it.
+    ///
+    /// Returns `false` if no `Sleep` is pending.
+    pub fn advance(&mut self) -> bool {
+        let wakers = {
+            let mut shared = self.shared.lock();
+            let deadline = match shared.timers.keys().next() {
+                Some((deadline, _)) => *deadline,
+                None => return false,
+            };
+            shared.now = cmp::max(shared.now, deadline);
+            let later = shared.timers.split_off(&(deadline, u64::MAX));
+            mem::replace(&mut shared.timers, later)
+        };
+        // Wake up the tasks without holding the lock, which the wakers acquire.
+        for (_, waker) in wakers {
+            waker.wake()
+        }
+        true
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        self.shared.lock().timers.keys().next().map(|(deadline, _)| *deadline)
+    }
+
+    /// Polls the woken tasks once, in an order determined by the seed.
+    ///
+    /// Returns `false` if no task has been woken up.
+    fn poll_woken(&mut self) -> bool {
+        let woken = {
+            let mut shared = self.shared.lock();
+            for task in mem::take(&mut shared.spawned) {
+                self.tasks.insert(self.next_task, task);
+                shared.woken.insert(self.next_task);
+                self.next_task += 1;
+            }
+            let main = shared.woken.remove(&MAIN_TASK);
+            let woken = mem::take(&mut shared.woken);
+            if main {
+                shared.woken.insert(MAIN_TASK);
+            }
+            woken
+        };
+
+        if woken.is_empty() {
+            return false
+        }
+
+        let mut woken = woken.into_iter().collect::<Vec<_>>();
+        woken.shuffle(&mut self.rng);
+        for id in woken {
+            if let Some(mut task) = self.tasks.remove(&id) {
+                let waker = task::waker(Arc::new(TaskWaker { id, shared: self.shared.clone() }));
+                if task.as_mut().poll(&mut Context::from_waker(&waker)).is_pending() {
+                    self.tasks.insert(id, task);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// A handle to a [`Simulation`].
+///
+/// The handle is an [`Executor`], e.g. for the background tasks of the
+/// connections of a swarm.
+#[derive(Clone)]
+pub struct SimulationHandle {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl SimulationHandle {
+    /// Returns the time elapsed on the virtual clock since the start of
+    /// the simulation.
+    pub fn now(&self) -> Duration {
+        self.shared.lock().now
+    }
+
+    /// Returns a future completing once the given duration has elapsed
+    /// on the virtual clock.
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        Sleep {
+            shared: self.shared.clone(),
+            deadline: self.now() + duration,
+            key: None,
+        }
+    }
+
+    /// Spawns a task, which is first polled by the next run of the simulation.
+    pub fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        self.shared.lock().spawned.push(Box::pin(future))
+    }
+
+    /// Creates a transport for a node of the simulated network, establishing
+    /// outgoing connections after the given latency.
+    pub fn transport(&self, latency: Duration) -> SimTransport {
+        SimTransport { handle: self.clone(), latency }
+    }
+}
+
+impl Executor for SimulationHandle {
+    fn exec(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        self.shared.lock().spawned.push(future)
+    }
+}
+
+/// A future completing at a deadline of the virtual clock of a [`Simulation`].
+pub struct Sleep {
+    shared: Arc<Mutex<Shared>>,
+    deadline: Duration,
+    /// The key of the registered waker, if any.
+    key: Option<(Duration, u64)>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let mut shared = this.shared.lock();
+        if shared.now >= this.deadline {
+            if let Some(key) = this.key.take() {
+                shared.timers.remove(&key);
+            }
+            return Poll::Ready(())
+        }
+        let key = match this.key {
+            Some(key) => key,
+            None => {
+                shared.timer_seq += 1;
+                (this.deadline, shared.timer_seq)
+            }
+        };
+        shared.timers.insert(key, cx.waker().clone());
+        this.key = Some(key);
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.shared.lock().timers.remove(&key);
+        }
+    }
+}
+
+/// A [`MemoryTransport`] of a [`Simulation`], establishing outgoing
+/// connections after a latency measured by the virtual clock.
+///
+/// Created through [`SimulationHandle::transport`].
+#[derive(Clone)]
+pub struct SimTransport {
+    handle: SimulationHandle,
+    latency: Duration,
+}
+
+impl Transport for SimTransport {
+    type Output = Channel<Vec<u8>>;
+    type Error = MemoryTransportError;
+    type Listener = Listener;
+    type ListenerUpgrade = future::Ready<Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        MemoryTransport.listen_on(addr)
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let dial = MemoryTransport.dial(addr)?;
+        let latency = self.handle.sleep(self.latency);
+        Ok(async move {
+            latency.await;
+            dial.await
+        }.boxed())
+    }
+
+    fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{multiaddr::Protocol, transport::ListenerEvent};
+    use futures::channel::mpsc;
+
+    fn polling_order(seed: u64) -> Vec<u8> {
+        let mut simulation = Simulation::new(seed);
+        let (tx, rx) = mpsc::unbounded();
+        for i in 0 .. 16 {
+            let tx = tx.clone();
+            simulation.spawn(async move { tx.unbounded_send(i).unwrap() });
+        }
+        drop(tx);
+        simulation.block_on(rx.collect()).unwrap()
+    }
+
+    #[test]
+    fn polling_order_is_determined_by_seed() {
+        assert_eq!(polling_order(1), polling_order(1));
+        assert_ne!((0 .. 8).map(polling_order).collect::<BTreeSet<_>>().len(), 1);
+    }
+
+    #[test]
+    fn virtual_clock_advances_to_next_deadline() {
+        let mut simulation = Simulation::new(0);
+        let handle = simulation.handle();
+        let (tx, mut rx) = mpsc::unbounded();
+        for secs in &[30, 10, 20] {
+            let (handle, tx) = (handle.clone(), tx.clone());
+            simulation.spawn(async move {
+                handle.sleep(Duration::from_secs(*secs)).await;
+                tx.unbounded_send((*secs, handle.now())).unwrap();
+            });
+        }
+
+        simulation.run_for(Duration::from_secs(15));
+        assert_eq!(rx.try_next().unwrap(), Some((10, Duration::from_secs(10))));
+        assert!(rx.try_next().is_err());
+        assert_eq!(simulation.now(), Duration::from_secs(15));
+
+        drop(tx);
+        let rest = simulation.block_on(rx.collect::<Vec<_>>()).unwrap();
+        assert_eq!(rest, vec![(20, Duration::from_secs(20)), (30, Duration::from_secs(30))]);
+        assert_eq!(simulation.block_on(future::pending::<()>()), None);
+    }
+
+    #[test]
+    fn transport_connects_after_latency() {
+        let mut simulation = Simulation::new(0);
+        let transport = simulation.handle().transport(Duration::from_millis(50));
+        let addr: Multiaddr = Protocol::Memory(rand::random::<u64>().saturating_add(1)).into();
+
+        let mut listener = transport.clone().listen_on(addr.clone()).unwrap();
+        simulation.spawn(async move {
+            loop {
+                if let ListenerEvent::Upgrade { upgrade, .. } = listener.next().await.unwrap().unwrap() {
+                    let mut connection = upgrade.await.unwrap();
+                    connection.write_all(b"hello").await.unwrap();
+                    connection.flush().await.unwrap();
+                    return
+                }
+            }
+        });
+
+        let received = simulation.block_on(async move {
+            let mut connection = transport.dial(addr).unwrap().await.unwrap();
+            let mut buf = [0; 5];
+            connection.read_exact(&mut buf).await.unwrap();
+            buf
+        });
+        assert_eq!(received, Some(*b"hello"));
+        assert_eq!(simulation.now(), Duration::from_millis(50));
+    }
+}
@@ -34,7 +34,8 @@ use multiaddr::{Multiaddr, Protocol};
 /// This is a mixed-mode translation, i.e. an IPv4 / DNS4 address may be replaced by an IPv6 / DNS6
 /// address and vice versa.
 ///
-/// If the first [`Protocol`]s are not IP addresses, `None` is returned instead.
+/// If the first [`Protocol`]s are not IP addresses, `None` is returned instead. In particular,
+/// onion addresses are never translated.
 pub fn address_translation(original: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
     original.replace(0, move |proto| match proto {
         Protocol::Ip4(_)
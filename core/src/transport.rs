@@ -31,20 +31,26 @@ use multiaddr::Multiaddr;
 use std::{error::Error, fmt};
 
 pub mod and_then;
+pub mod cancel;
 pub mod choice;
 pub mod dummy;
 pub mod map;
 pub mod map_err;
 pub mod memory;
+pub mod ordered;
+pub mod retry;
 pub mod timeout;
 pub mod upgrade;
 
 mod boxed;
 mod optional;
+pub(crate) mod role;
 
 pub use self::boxed::Boxed;
+pub use self::cancel::DialCancelToken;
 pub use self::choice::OrTransport;
 pub use self::memory::MemoryTransport;
+pub use self::ordered::OrderedOrTransport;
 pub use self::optional::OptionalTransport;
 pub use self::upgrade::Upgrade;
 
@@ -351,6 +357,23 @@ impl<TErr> TransportError<TErr> {
             TransportError::Other(err) => TransportError::Other(map(err)),
         }
     }
+
+    /// Returns a numeric code identifying the variant of this error.
+    ///
+    /// Contrary to the error messages, the codes are stable across versions
+    /// and never reused, which makes them suitable for identifying errors
+    /// across language boundaries, e.g. in FFI bindings.
+    ///
+    /// | Variant                 | Code  |
+    /// |-------------------------|-------|
+    /// | `MultiaddrNotSupported` | `100` |
+    /// | `Other`                 | `101` |
+    pub fn code(&self) -> u32 {
+        match self {
+            TransportError::MultiaddrNotSupported(_) => 100,
+            TransportError::Other(_) => 101,
+        }
+    }
 }
 
 impl<TErr> fmt::Display for TransportError<TErr>
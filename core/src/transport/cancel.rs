@@ -0,0 +1,209 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Cancellation of dialing attempts.
+//!
+//! A [`DialCancelToken`] is handed to a dial, e.g. via
+//! [`Network::dial_with_cancel`](crate::Network::dial_with_cancel), and
+//! can be cancelled from anywhere, e.g. another thread. Cancelling the token
+//! drops the future returned by [`Transport::dial`](crate::Transport::dial)
+//! of all dials it has been handed to, aborting the connection attempts
+//! instead of letting them run to their timeout in the background.
+//!
+//! A `NetworkBehaviour` cancels its own dials by handing a token to the
+//! `DialOpts` of a `NetworkBehaviourAction::Dial` and keeping a clone to
+//! cancel later on.
+
+use futures::prelude::*;
+use parking_lot::Mutex;
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    collections::HashMap,
+    fmt,
+    pin::Pin,
+    sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}},
+    task::{Context, Poll, Waker},
+};
+
+/// A token for cancelling dialing attempts.
+///
+/// Clones of a token share the same state, i.e. cancelling one cancels all.
+#[derive(Clone, Default)]
+pub struct DialCancelToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    /// The tasks to wake up upon cancellation, by the slot of the
+    /// [`Cancellable`] that registered them.
+    wakers: Mutex<HashMap<u64, Waker>>,
+    /// The next free slot.
+    next_slot: AtomicU64,
+}
+
+impl DialCancelToken {
+    /// Creates a new token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all dialing attempts using this token.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        for (_, waker) in self.inner.wakers.lock().drain() {
+            waker.wake()
+        }
+    }
+
+    /// Returns `true` if the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Checks whether the token has been cancelled, registering the
+    /// current task in the given slot to be woken up upon cancellation
+    /// otherwise. A slot is allocated on first use.
+    fn poll_cancelled(&self, cx: &mut Context<'_>, slot: &mut Option<u64>) -> Poll<()> {
+        if self.is_cancelled() {
+            return Poll::Ready(())
+        }
+        {
+            let slot = *slot.get_or_insert_with(|| self.inner.next_slot.fetch_add(1, Ordering::Relaxed));
+            let mut wakers = self.inner.wakers.lock();
+            match wakers.get_mut(&slot) {
+                Some(w) if w.will_wake(cx.waker()) => {}
+                Some(w) => *w = cx.waker().clone(),
+                None => { wakers.insert(slot, cx.waker().clone()); }
+            }
+        }
+        // Check again in case the token was cancelled concurrently.
+        if self.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Releases a slot, such that its task is no longer woken up.
+    fn release(&self, slot: u64) {
+        self.inner.wakers.lock().remove(&slot);
+    }
+}
+
+impl fmt::Debug for DialCancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DialCancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// A future which resolves to `None` if the associated token is
+/// cancelled before the wrapped future completes.
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct Cancellable<F> {
+    #[pin]
+    inner: F,
+    token: DialCancelToken,
+    /// The slot of the waker registered with the token, if any.
+    slot: Option<u64>,
+}
+
+impl<F> Cancellable<F> {
+    /// Wraps the given future, e.g. as returned by [`Transport::dial`](crate::Transport::dial).
+    pub fn new(inner: F, token: DialCancelToken) -> Self {
+        Cancellable { inner, token, slot: None }
+    }
+}
+
+impl<F: Future> Future for Cancellable<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if this.token.poll_cancelled(cx, this.slot).is_ready() {
+            return Poll::Ready(None)
+        }
+        let output = futures::ready!(this.inner.poll(cx));
+        if let Some(slot) = this.slot.take() {
+            this.token.release(slot)
+        }
+        Poll::Ready(Some(output))
+    }
+}
+
+#[pinned_drop]
+impl<F> PinnedDrop for Cancellable<F> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        if let Some(slot) = this.slot.take() {
+            this.token.release(slot)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn cancel_aborts_pending_future() {
+        let token = DialCancelToken::new();
+        let mut fut = Cancellable::new(future::pending::<()>(), token.clone());
+        assert!(block_on(future::poll_fn(|cx| Poll::Ready(fut.poll_unpin(cx).is_pending()))));
+        token.cancel();
+        assert_eq!(block_on(fut), None);
+    }
+
+    #[test]
+    fn uncancelled_future_completes() {
+        let token = DialCancelToken::new();
+        assert_eq!(block_on(Cancellable::new(future::ready(1), token)), Some(1));
+    }
+
+    #[test]
+    fn finished_futures_release_their_wakers() {
+        let token = DialCancelToken::new();
+        let mut pending = Vec::new();
+        for _ in 0 .. 10 {
+            let mut fut = Cancellable::new(future::pending::<()>(), token.clone());
+            // Polling repeatedly keeps a single waker per future.
+            for _ in 0 .. 3 {
+                assert!(block_on(future::poll_fn(|cx| Poll::Ready(fut.poll_unpin(cx).is_pending()))));
+            }
+            pending.push(fut);
+        }
+        assert_eq!(token.inner.wakers.lock().len(), 10);
+        drop(pending);
+        assert!(token.inner.wakers.lock().is_empty());
+
+        let (tx, rx) = futures::channel::oneshot::channel();
+        let mut fut = Cancellable::new(rx, token.clone());
+        assert!(block_on(future::poll_fn(|cx| Poll::Ready(fut.poll_unpin(cx).is_pending()))));
+        tx.send(()).unwrap();
+        assert_eq!(block_on(&mut fut), Some(Ok(())));
+        assert!(token.inner.wakers.lock().is_empty());
+    }
+}
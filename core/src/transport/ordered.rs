@@ -0,0 +1,443 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Transports trying multiple underlying transports in order of preference.
+//!
+//! An [`OrderedOrTransport`] combines any number of (boxed) transports with
+//! the same output, e.g. fully upgraded QUIC, TCP and Tor transports. Contrary
+//! to [`OrTransport`](super::OrTransport), which only falls back to the second
+//! transport if the first one does not support an address, a dial falls back
+//! to the next transport in the configured order of preference whenever a
+//! transport fails to establish the connection or exceeds its dial timeout.
+//!
+//! The output of a connection records the name of the transport that
+//! established it, see [`OrderedOutput`].
+
+use crate::{Multiaddr, Transport, transport::{Boxed, ListenerEvent, TransportError}};
+use futures::{prelude::*, stream::BoxStream, future::BoxFuture};
+use futures_timer::Delay;
+use std::{collections::VecDeque, error, fmt, io, pin::Pin, task::Context, task::Poll, time::Duration};
+
+/// Decides whether a transport is considered for an address.
+pub type AddressFilter = fn(&Multiaddr) -> bool;
+
+/// A transport trying multiple underlying transports in order of preference.
+///
+/// See the [module documentation](self) for details.
+pub struct OrderedOrTransport<O> {
+    transports: Vec<Candidate<O>>,
+}
+
+/// A transport of an [`OrderedOrTransport`].
+struct Candidate<O> {
+    name: &'static str,
+    transport: Boxed<O>,
+    timeout: Option<Duration>,
+    filter: AddressFilter,
+}
+
+impl<O> Clone for Candidate<O> {
+    fn clone(&self) -> Self {
+        Candidate {
+            name: self.name,
+            transport: self.transport.clone(),
+            timeout: self.timeout,
+            filter: self.filter,
+        }
+    }
+}
+
+impl<O> Clone for OrderedOrTransport<O> {
+    fn clone(&self) -> Self {
+        OrderedOrTransport { transports: self.transports.clone() }
+    }
+}
+
+impl<O> fmt::Debug for OrderedOrTransport<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.transports.iter().map(|c| (c.name, c.timeout)))
+            .finish()
+    }
+}
+
+impl<O> Default for OrderedOrTransport<O> {
+    fn default() -> Self {
+        OrderedOrTransport { transports: Vec::new() }
+    }
+}
+
+impl<O> OrderedOrTransport<O> {
+    /// Creates an `OrderedOrTransport` without any transports.
+    pub fn new() -> Self {
+        OrderedOrTransport::default()
+    }
+
+    /// Adds a transport with a lower preference than all transports added before.
+    ///
+    /// The name identifies the transport in the configuration, in errors and
+    /// in the [`OrderedOutput`] of its connections.
+    pub fn with_transport<T>(mut self, name: &'static str, transport: T) -> Self
+    where
+        T: Transport<Output = O> + Clone + Send + Sync + 'static,
+        T::Error: Send + Sync,
+        T::Dial: Send + 'static,
+        T::Listener: Send + 'static,
+        T::ListenerUpgrade: Send + 'static,
+    {
+        self.transports.push(Candidate {
+            name,
+            transport: super::boxed::boxed(transport),
+            timeout: None,
+            filter: |_| true,
+        });
+        self
+    }
+
+    /// Sets the timeout for dials of the named transport, after which the
+    /// next transport in order of preference is tried.
+    ///
+    /// Has no effect if no transport with the given name has been added.
+    pub fn with_dial_timeout(mut self, name: &str, timeout: Duration) -> Self {
+        for c in self.transports.iter_mut().filter(|c| c.name == name) {
+            c.timeout = Some(timeout);
+        }
+        self
+    }
+
+    /// Restricts the named transport to addresses accepted by the given filter.
+    ///
+    /// By default, every transport is tried for every address, relying on
+    /// the transport to reject the addresses it does not support.
+    ///
+    /// Has no effect if no transport with the given name has been added.
+    pub fn with_address_filter(mut self, name: &str, filter: AddressFilter) -> Self {
+        for c in self.transports.iter_mut().filter(|c| c.name == name) {
+            c.filter = filter;
+        }
+        self
+    }
+
+    /// Sets the order of preference of the transports.
+    ///
+    /// The named transports are moved to the front in the given order,
+    /// followed by the remaining transports in their previous order.
+    pub fn with_preference(mut self, names: &[&str]) -> Self {
+        self.transports.sort_by_key(|c| {
+            names.iter().position(|n| *n == c.name).unwrap_or(names.len())
+        });
+        self
+    }
+
+    /// Returns the names of the transports in order of preference.
+    pub fn preference(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.transports.iter().map(|c| c.name)
+    }
+
+    /// Returns the rank of the most preferred transport considered for the
+    /// given address, where `0` is the most preferred transport overall.
+    ///
+    /// Returns `None` if no transport is considered for the address.
+    pub fn rank(&self, addr: &Multiaddr) -> Option<usize> {
+        self.transports.iter().position(|c| (c.filter)(addr))
+    }
+
+    /// Sorts the given addresses by the [`rank`](OrderedOrTransport::rank)
+    /// of their most preferred transport, so that dialing them in order
+    /// follows the preference of the transports.
+    ///
+    /// Addresses which no transport is considered for are moved to the end.
+    /// The sort is stable.
+    pub fn sort_addresses(&self, addrs: &mut [Multiaddr]) {
+        addrs.sort_by_key(|a| self.rank(a).unwrap_or(usize::MAX))
+    }
+
+    fn candidates(&self, addr: &Multiaddr) -> VecDeque<Candidate<O>> {
+        self.transports.iter().filter(|c| (c.filter)(addr)).cloned().collect()
+    }
+}
+
+impl<O> Transport for OrderedOrTransport<O>
+where
+    O: Send + 'static,
+{
+    type Output = OrderedOutput<O>;
+    type Error = OrderedError;
+    type Listener = BoxStream<'static, Result<ListenerEvent<Self::ListenerUpgrade, Self::Error>, Self::Error>>;
+    type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+    type Dial = OrderedDial<O>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let mut addr = addr;
+        for c in self.candidates(&addr) {
+            let name = c.name;
+            match c.transport.listen_on(addr) {
+                Ok(listener) => {
+                    let listener = listener
+                        .map_ok(move |event| {
+                            event
+                                .map(move |upgrade| {
+                                    upgrade
+                                        .map_ok(move |output| OrderedOutput { transport: name, output })
+                                        .map_err(move |e| OrderedError::new(name, e))
+                                        .boxed()
+                                })
+                                .map_err(move |e| OrderedError::new(name, e))
+                        })
+                        .map_err(move |e| OrderedError::new(name, e));
+                    return Ok(listener.boxed())
+                }
+                Err(TransportError::MultiaddrNotSupported(a)) => addr = a,
+                Err(TransportError::Other(e)) =>
+                    return Err(TransportError::Other(OrderedError::new(name, e))),
+            }
+        }
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let mut dial = OrderedDial {
+            remaining: self.candidates(&addr),
+            addr,
+            current: None,
+            attempts: Vec::new(),
+        };
+        dial.start_next();
+        if dial.current.is_some() {
+            Ok(dial)
+        } else if dial.attempts.is_empty() {
+            Err(TransportError::MultiaddrNotSupported(dial.addr))
+        } else {
+            Err(TransportError::Other(OrderedError { attempts: dial.attempts }))
+        }
+    }
+
+    fn address_translation(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.transports.iter().find_map(|c| c.transport.address_translation(server, observed))
+    }
+}
+
+/// The output of an [`OrderedOrTransport`], recording which transport
+/// established the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderedOutput<O> {
+    /// The name of the transport that established the connection.
+    pub transport: &'static str,
+    /// The output of that transport.
+    pub output: O,
+}
+
+impl<O> OrderedOutput<O> {
+    /// Returns the output of the transport, discarding its name.
+    pub fn into_inner(self) -> O {
+        self.output
+    }
+}
+
+/// The dial future of an [`OrderedOrTransport`].
+pub struct OrderedDial<O> {
+    addr: Multiaddr,
+    /// The transports yet to be tried, in order of preference.
+    remaining: VecDeque<Candidate<O>>,
+    /// The current dial attempt with its timeout, if any.
+    current: Option<(&'static str, BoxFuture<'static, io::Result<O>>, Option<Delay>)>,
+    /// The failed attempts so far.
+    attempts: Vec<(&'static str, io::Error)>,
+}
+
+impl<O> OrderedDial<O> {
+    /// Starts dialing with the next transport supporting the address, if any.
+    fn start_next(&mut self) {
+        while let Some(c) = self.remaining.pop_front() {
+            match c.transport.dial(self.addr.clone()) {
+                Ok(dial) => {
+                    tracing::debug!("Dialing {} via {}.", self.addr, c.name);
+                    self.current = Some((c.name, dial, c.timeout.map(Delay::new)));
+                    return
+                }
+                Err(TransportError::MultiaddrNotSupported(_)) => {}
+                Err(TransportError::Other(e)) => self.attempts.push((c.name, e)),
+            }
+        }
+    }
+}
+
+impl<O> Future for OrderedDial<O> {
+    type Output = Result<OrderedOutput<O>, OrderedError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            let (name, dial, timer) = match this.current.as_mut() {
+                Some((name, dial, timer)) => (*name, dial, timer),
+                None => return Poll::Ready(Err(OrderedError {
+                    attempts: std::mem::take(&mut this.attempts)
+                }))
+            };
+            let error = match dial.poll_unpin(cx) {
+                Poll::Ready(Ok(output)) => {
+                    return Poll::Ready(Ok(OrderedOutput { transport: name, output }))
+                }
+                Poll::Ready(Err(e)) => e,
+                Poll::Pending => match timer.as_mut().map(|t| t.poll_unpin(cx)) {
+                    Some(Poll::Ready(())) =>
+                        io::Error::new(io::ErrorKind::TimedOut, "dial timed out"),
+                    _ => return Poll::Pending
+                }
+            };
+            tracing::debug!("Dialing {} via {} failed: {}", this.addr, name, error);
+            this.attempts.push((name, error));
+            this.current = None;
+            this.start_next();
+        }
+    }
+}
+
+/// Error of an [`OrderedOrTransport`], with the errors of all transports tried.
+#[derive(Debug)]
+pub struct OrderedError {
+    attempts: Vec<(&'static str, io::Error)>,
+}
+
+impl OrderedError {
+    fn new(name: &'static str, error: io::Error) -> Self {
+        OrderedError { attempts: vec![(name, error)] }
+    }
+
+    /// Returns the names and errors of the transports tried, in order.
+    pub fn attempts(&self) -> &[(&'static str, io::Error)] {
+        &self.attempts
+    }
+}
+
+impl fmt::Display for OrderedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.attempts.is_empty() {
+            return f.write_str("No transport was tried")
+        }
+        for (i, (name, error)) in self.attempts.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: {}", name, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for OrderedError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.attempts.last().map(|(_, e)| e as &(dyn error::Error + 'static))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiaddr::Protocol;
+
+    /// A transport whose dials behave in a fixed way.
+    #[derive(Clone, Copy)]
+    enum Fixed {
+        Succeed,
+        Fail,
+        Stall,
+    }
+
+    impl Transport for Fixed {
+        type Output = ();
+        type Error = io::Error;
+        type Listener = stream::Pending<Result<ListenerEvent<Self::ListenerUpgrade, io::Error>, io::Error>>;
+        type ListenerUpgrade = future::Pending<Result<(), io::Error>>;
+        type Dial = BoxFuture<'static, Result<(), io::Error>>;
+
+        fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+            Err(TransportError::MultiaddrNotSupported(addr))
+        }
+
+        fn dial(self, _: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+            Ok(match self {
+                Fixed::Succeed => future::ok(()).boxed(),
+                Fixed::Fail => future::err(io::Error::from(io::ErrorKind::ConnectionRefused)).boxed(),
+                Fixed::Stall => future::pending().boxed(),
+            })
+        }
+
+        fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+            None
+        }
+    }
+
+    #[test]
+    fn falls_back_in_order_of_preference() {
+        let transport = OrderedOrTransport::new()
+            .with_transport("tcp", Fixed::Succeed)
+            .with_transport("quic", Fixed::Fail)
+            .with_transport("tor", Fixed::Stall)
+            .with_dial_timeout("tor", Duration::from_millis(10))
+            .with_preference(&["tor", "quic"]);
+        assert_eq!(transport.preference().collect::<Vec<_>>(), vec!["tor", "quic", "tcp"]);
+
+        let dial = transport.dial(Multiaddr::empty()).unwrap();
+        let output = futures::executor::block_on(dial).unwrap();
+        assert_eq!(output.transport, "tcp");
+    }
+
+    #[test]
+    fn reports_all_attempts() {
+        let transport = OrderedOrTransport::new()
+            .with_transport("a", Fixed::Fail)
+            .with_transport("b", Fixed::Stall)
+            .with_dial_timeout("b", Duration::from_millis(10));
+
+        let dial = transport.dial(Multiaddr::empty()).unwrap();
+        let error = futures::executor::block_on(dial).unwrap_err();
+        let attempts = error.attempts().iter()
+            .map(|(name, e)| (*name, e.kind()))
+            .collect::<Vec<_>>();
+        assert_eq!(attempts, vec![
+            ("a", io::ErrorKind::ConnectionRefused),
+            ("b", io::ErrorKind::TimedOut),
+        ]);
+    }
+
+    #[test]
+    fn sorts_addresses_by_preference() {
+        let transport = OrderedOrTransport::new()
+            .with_transport("quic", Fixed::Succeed)
+            .with_transport("tcp", Fixed::Succeed)
+            .with_address_filter("quic", |a| a.iter().any(|p| p == Protocol::Quic))
+            .with_address_filter("tcp", |a| a.iter().any(|p| matches!(p, Protocol::Tcp(_))));
+
+        let tcp: Multiaddr = "/ip4/127.0.0.1/tcp/1234".parse().unwrap();
+        let quic: Multiaddr = "/ip4/127.0.0.1/udp/1234/quic".parse().unwrap();
+        let memory: Multiaddr = "/memory/1234".parse().unwrap();
+
+        let mut addrs = vec![memory.clone(), tcp.clone(), quic.clone()];
+        transport.sort_addresses(&mut addrs);
+        assert_eq!(addrs, vec![quic, tcp, memory.clone()]);
+
+        assert!(matches!(
+            transport.dial(memory),
+            Err(TransportError::MultiaddrNotSupported(_))
+        ));
+    }
+}
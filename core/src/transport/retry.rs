@@ -0,0 +1,435 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Transports retrying failed dials with exponential backoff.
+//!
+//! A [`Retry`] wraps another `Transport` and re-dials an address if the
+//! connection setup fails with an error that is considered transient, e.g.
+//! a refused connection or a timeout. Errors which are not transient, e.g.
+//! an unsupported multiaddress, fail the dial immediately.
+//!
+//! When combining with [`TransportTimeout`](super::timeout::TransportTimeout),
+//! the timeout should be applied to the inner transport so that every attempt
+//! is subject to it, i.e. `Retry::new(TransportTimeout::new(inner, d), config)`.
+
+use crate::{Multiaddr, Transport, transport::{TransportError, map_err::{MapErr, MapErrListener, MapErrListenerUpgrade}, timeout::TransportTimeoutError}};
+use futures::{prelude::*, ready};
+use futures_timer::Delay;
+use std::{cmp, error, fmt, io, pin::Pin, task::Context, task::Poll, time::Duration};
+
+/// Decides whether a failed attempt should be retried.
+pub type Classifier = fn(&(dyn error::Error + 'static)) -> bool;
+
+/// Configuration of a [`Retry`] transport.
+#[derive(Clone, Copy)]
+pub struct RetryConfig {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    classify: Classifier,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            classify: is_transient,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Sets the maximum number of attempts per dial, including the first one.
+    ///
+    /// A value of `0` is treated as `1`, i.e. no retries.
+    pub fn with_max_attempts(mut self, n: u32) -> Self {
+        self.max_attempts = cmp::max(n, 1);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn with_initial_backoff(mut self, d: Duration) -> Self {
+        self.initial_backoff = d;
+        self
+    }
+
+    /// Sets the upper bound of the delay between two attempts.
+    pub fn with_max_backoff(mut self, d: Duration) -> Self {
+        self.max_backoff = d;
+        self
+    }
+
+    /// Sets the factor by which the delay grows after every retry.
+    ///
+    /// Negative values and NaN are treated as `1.0`, i.e. a constant delay.
+    /// The delay never exceeds the one set via [`RetryConfig::with_max_backoff`].
+    pub fn with_multiplier(mut self, m: f64) -> Self {
+        self.multiplier = if m >= 0.0 { m } else { 1.0 };
+        self
+    }
+
+    /// Sets the function deciding which errors are retried.
+    ///
+    /// Defaults to [`is_transient`].
+    pub fn with_classifier(mut self, classify: Classifier) -> Self {
+        self.classify = classify;
+        self
+    }
+}
+
+impl fmt::Debug for RetryConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryConfig")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("multiplier", &self.multiplier)
+            .finish()
+    }
+}
+
+/// The default error classification of [`RetryConfig`].
+///
+/// Inspects the chain of error sources for an `io::Error` and considers
+/// refused, reset or aborted connections as well as timeouts transient. A
+/// timeout of a [`TransportTimeout`](super::timeout::TransportTimeout)
+/// wrapping an I/O based transport is transient, too.
+pub fn is_transient(err: &(dyn error::Error + 'static)) -> bool {
+    let mut current = Some(err);
+    while let Some(e) = current {
+        if let Some(TransportTimeoutError::Timeout) = e.downcast_ref::<TransportTimeoutError<io::Error>>() {
+            return true
+        }
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return matches!(e.kind(),
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::Interrupted)
+        }
+        current = e.source();
+    }
+    false
+}
+
+/// A `Retry` is a `Transport` that wraps another `Transport` and retries
+/// failed dials with exponential backoff.
+///
+/// **Note**: Only dials are retried, listeners and the upgrades of accepted
+/// connections are passed through unchanged.
+#[derive(Debug, Copy, Clone)]
+pub struct Retry<T> {
+    inner: T,
+    config: RetryConfig,
+}
+
+impl<T> Retry<T> {
+    /// Wraps around a `Transport` to retry failed dials as per the given configuration.
+    pub fn new(inner: T, config: RetryConfig) -> Self {
+        Retry { inner, config }
+    }
+}
+
+impl<T> Transport for Retry<T>
+where
+    T: Transport + Clone,
+    T::Error: 'static,
+{
+    type Output = T::Output;
+    type Error = RetryError<T::Error>;
+    type Listener = MapErrListener<T, fn(T::Error) -> RetryError<T::Error>>;
+    type ListenerUpgrade = MapErrListenerUpgrade<T, fn(T::Error) -> RetryError<T::Error>>;
+    type Dial = RetryDial<T>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let map: fn(T::Error) -> RetryError<T::Error> = |error| RetryError::Failed { attempts: 1, error };
+        MapErr::new(self.inner, map).listen_on(addr)
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let mut backoff = self.config.initial_backoff;
+        let (dial, delay) = match self.inner.clone().dial(addr.clone()) {
+            Ok(dial) => (Some(dial), None),
+            Err(TransportError::MultiaddrNotSupported(addr)) =>
+                return Err(TransportError::MultiaddrNotSupported(addr)),
+            Err(TransportError::Other(error)) => {
+                let delay = schedule(&self.config, 1, &mut backoff, error)
+                    .map_err(TransportError::Other)?;
+                (None, Some(delay))
+            }
+        };
+        Ok(RetryDial {
+            transport: self.inner,
+            addr,
+            config: self.config,
+            attempts: 1,
+            backoff,
+            dial,
+            delay,
+        })
+    }
+
+    fn address_translation(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(server, observed)
+    }
+}
+
+/// Decides whether to retry after a failed attempt and if so, returns the
+/// delay to wait for before the next attempt.
+fn schedule<E>(config: &RetryConfig, attempts: u32, backoff: &mut Duration, error: E)
+    -> Result<Delay, RetryError<E>>
+where
+    E: error::Error + 'static
+{
+    if attempts >= config.max_attempts || !(config.classify)(&error) {
+        return Err(RetryError::Failed { attempts, error })
+    }
+    tracing::debug!("Dial attempt {} failed, retrying in {:?}: {}", attempts, backoff, error);
+    let delay = Delay::new(*backoff);
+    *backoff = next_backoff(config, *backoff);
+    Ok(delay)
+}
+
+/// Grows the delay by the multiplier, saturating at `max_backoff`.
+fn next_backoff(config: &RetryConfig, backoff: Duration) -> Duration {
+    let max = config.max_backoff;
+    let secs = backoff.as_secs_f64() * config.multiplier;
+    // Also rejects NaN, e.g. for a zero backoff and an infinite multiplier.
+    if secs < max.as_secs_f64() {
+        cmp::min(Duration::from_secs_f64(secs), max)
+    } else {
+        max
+    }
+}
+
+/// Dialing future for `Retry`.
+#[pin_project::pin_project]
+#[must_use = "futures do nothing unless polled"]
+pub struct RetryDial<T: Transport> {
+    transport: T,
+    addr: Multiaddr,
+    config: RetryConfig,
+    /// The number of attempts made so far.
+    attempts: u32,
+    /// The delay before the next retry.
+    backoff: Duration,
+    /// The current attempt, if any.
+    #[pin]
+    dial: Option<T::Dial>,
+    /// The delay before the next attempt, if any.
+    delay: Option<Delay>,
+}
+
+impl<T> Future for RetryDial<T>
+where
+    T: Transport + Clone,
+    T::Error: 'static,
+{
+    type Output = Result<T::Output, RetryError<T::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            if let Some(delay) = this.delay.as_mut() {
+                ready!(Pin::new(delay).poll(cx));
+                *this.delay = None;
+                *this.attempts += 1;
+                match this.transport.clone().dial(this.addr.clone()) {
+                    Ok(dial) => this.dial.set(Some(dial)),
+                    Err(TransportError::MultiaddrNotSupported(addr)) => {
+                        return Poll::Ready(Err(RetryError::MultiaddrNotSupported {
+                            attempts: *this.attempts,
+                            addr,
+                        }))
+                    }
+                    Err(TransportError::Other(error)) => {
+                        match schedule(this.config, *this.attempts, this.backoff, error) {
+                            Ok(delay) => *this.delay = Some(delay),
+                            Err(e) => return Poll::Ready(Err(e))
+                        }
+                        continue
+                    }
+                }
+            }
+
+            let dial = this.dial.as_mut().as_pin_mut().expect("RetryDial polled after completion");
+            let error = match ready!(dial.poll(cx)) {
+                Ok(output) => return Poll::Ready(Ok(output)),
+                Err(error) => error,
+            };
+            this.dial.set(None);
+            match schedule(this.config, *this.attempts, this.backoff, error) {
+                Ok(delay) => *this.delay = Some(delay),
+                Err(e) => return Poll::Ready(Err(e))
+            }
+        }
+    }
+}
+
+/// Error that can be produced by the `Retry` layer.
+#[derive(Debug)]
+pub enum RetryError<TErr> {
+    /// The last attempt failed with the given error.
+    Failed {
+        /// The number of attempts made.
+        attempts: u32,
+        /// The error of the last attempt.
+        error: TErr,
+    },
+    /// The inner transport did not support the address on a retry.
+    MultiaddrNotSupported {
+        /// The number of attempts made.
+        attempts: u32,
+        /// The address that was dialed.
+        addr: Multiaddr,
+    },
+}
+
+impl<TErr> RetryError<TErr> {
+    /// Returns the number of attempts made.
+    pub fn attempts(&self) -> u32 {
+        match self {
+            RetryError::Failed { attempts, .. } => *attempts,
+            RetryError::MultiaddrNotSupported { attempts, .. } => *attempts,
+        }
+    }
+}
+
+impl<TErr> fmt::Display for RetryError<TErr>
+where TErr: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetryError::Failed { attempts, error } =>
+                write!(f, "{} (after {} attempt(s))", error, attempts),
+            RetryError::MultiaddrNotSupported { attempts, addr } =>
+                write!(f, "Multiaddr not supported: {} (after {} attempt(s))", addr, attempts),
+        }
+    }
+}
+
+impl<TErr> error::Error for RetryError<TErr>
+where TErr: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RetryError::Failed { error, .. } => Some(error),
+            RetryError::MultiaddrNotSupported { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::ListenerEvent;
+    use std::sync::{Arc, atomic::{AtomicU32, Ordering}};
+
+    /// A transport whose dials fail with the given error a number of times.
+    #[derive(Clone)]
+    struct Flaky {
+        failures: u32,
+        kind: io::ErrorKind,
+        attempts: Arc<AtomicU32>,
+    }
+
+    impl Flaky {
+        fn new(failures: u32, kind: io::ErrorKind) -> Self {
+            Flaky { failures, kind, attempts: Arc::new(AtomicU32::new(0)) }
+        }
+    }
+
+    impl Transport for Flaky {
+        type Output = ();
+        type Error = io::Error;
+        type Listener = stream::Pending<Result<ListenerEvent<Self::ListenerUpgrade, io::Error>, io::Error>>;
+        type ListenerUpgrade = future::Pending<Result<(), io::Error>>;
+        type Dial = future::Ready<Result<(), io::Error>>;
+
+        fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+            Err(TransportError::MultiaddrNotSupported(addr))
+        }
+
+        fn dial(self, _: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Ok(future::err(self.kind.into()))
+            } else {
+                Ok(future::ok(()))
+            }
+        }
+
+        fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+            None
+        }
+    }
+
+    fn config() -> RetryConfig {
+        RetryConfig::default()
+            .with_max_attempts(3)
+            .with_initial_backoff(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let flaky = Flaky::new(2, io::ErrorKind::ConnectionRefused);
+        let dial = Retry::new(flaky.clone(), config()).dial(Multiaddr::empty()).unwrap();
+        assert!(futures::executor::block_on(dial).is_ok());
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn reports_attempts_when_exhausted() {
+        let flaky = Flaky::new(5, io::ErrorKind::TimedOut);
+        let dial = Retry::new(flaky.clone(), config()).dial(Multiaddr::empty()).unwrap();
+        let err = futures::executor::block_on(dial).unwrap_err();
+        assert_eq!(err.attempts(), 3);
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn backoff_saturates_at_max() {
+        let config = RetryConfig::default().with_max_backoff(Duration::from_secs(10));
+        let grow = |m, b| next_backoff(&config.with_multiplier(m), b);
+        assert_eq!(grow(2.0, Duration::from_secs(1)), Duration::from_secs(2));
+        assert_eq!(grow(2.0, Duration::from_secs(8)), Duration::from_secs(10));
+        assert_eq!(grow(f64::MAX, Duration::from_secs(1)), Duration::from_secs(10));
+        assert_eq!(grow(f64::INFINITY, Duration::from_secs(0)), Duration::from_secs(10));
+        assert_eq!(grow(-1.0, Duration::from_secs(1)), Duration::from_secs(1));
+        assert_eq!(grow(f64::NAN, Duration::from_secs(1)), Duration::from_secs(1));
+
+        let unbounded = RetryConfig::default().with_max_backoff(Duration::new(u64::MAX, 0));
+        assert_eq!(next_backoff(&unbounded.with_multiplier(1e300), Duration::from_secs(1)), unbounded.max_backoff);
+    }
+
+    #[test]
+    fn fails_fast_on_permanent_errors() {
+        let flaky = Flaky::new(5, io::ErrorKind::InvalidInput);
+        let dial = Retry::new(flaky.clone(), config()).dial(Multiaddr::empty()).unwrap();
+        let err = futures::executor::block_on(dial).unwrap_err();
+        assert_eq!(err.attempts(), 1);
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 1);
+    }
+}
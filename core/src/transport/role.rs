@@ -0,0 +1,87 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Overriding the role of the local node in the upgrades of outgoing connections.
+
+use crate::ConnectedPoint;
+use futures::prelude::*;
+use multiaddr::Multiaddr;
+use std::{cell::Cell, pin::Pin, task::{Context, Poll}};
+
+thread_local! {
+    /// Whether the outgoing connection currently being polled is upgraded
+    /// with the local node in the role of the listener.
+    static AS_LISTENER: Cell<bool> = Cell::new(false);
+}
+
+/// Wraps the future of an outgoing connection such that the local node acts
+/// as the listener in the authentication and multiplexing upgrades applied
+/// by an [`upgrade::Builder`](super::upgrade::Builder), e.g. for hole
+/// punching where both peers dial each other simultaneously.
+#[pin_project::pin_project]
+pub(crate) struct AsListener<F> {
+    #[pin]
+    inner: F,
+}
+
+impl<F> AsListener<F> {
+    pub(crate) fn new(inner: F) -> Self {
+        AsListener { inner }
+    }
+}
+
+impl<F: Future> Future for AsListener<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let previous = AS_LISTENER.with(|c| c.replace(true));
+        let result = this.inner.poll(cx);
+        AS_LISTENER.with(|c| c.set(previous));
+        result
+    }
+}
+
+/// Returns the endpoint according to which a connection is upgraded,
+/// i.e. the given endpoint unless the role of the local node is overridden
+/// by [`AsListener`].
+pub(crate) fn upgrade_endpoint(endpoint: ConnectedPoint) -> ConnectedPoint {
+    match endpoint {
+        ConnectedPoint::Dialer { address } if AS_LISTENER.with(|c| c.get()) =>
+            ConnectedPoint::Listener { local_addr: Multiaddr::empty(), send_back_addr: address },
+        endpoint => endpoint,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_dialer_role() {
+        let endpoint = ConnectedPoint::Dialer { address: Multiaddr::empty() };
+        assert!(upgrade_endpoint(endpoint.clone()).is_dialer());
+        let overridden = futures::executor::block_on(AsListener::new(async {
+            upgrade_endpoint(endpoint)
+        }));
+        assert!(overridden.is_listener());
+        assert!(!AS_LISTENER.with(|c| c.get()));
+    }
+}
@@ -25,12 +25,14 @@ pub use crate::upgrade::Version;
 use crate::{
     ConnectedPoint,
     Negotiated,
+    connection::stage::{self, ConnectionStage},
     transport::{
         Transport,
         TransportError,
         ListenerEvent,
         and_then::AndThen,
         boxed::boxed,
+        role,
         timeout::TransportTimeout,
     },
     muxing::{StreamMuxer, StreamMuxerBox},
@@ -76,6 +78,10 @@ use std::{
 ///      namely a tuple of a [`PeerId`] (from the authentication upgrade) and a
 ///      [`StreamMuxer`] (from the multiplexing upgrade).
 ///
+/// The stages of a connection upgraded in this way are reported to the
+/// [`Network`] and can be subjected to individual timeouts, see
+/// [`StageTimeouts`](crate::connection::StageTimeouts).
+///
 /// [`Network`]: crate::Network
 #[derive(Clone)]
 pub struct Builder<T> {
@@ -117,8 +123,9 @@ where
     {
         let version = self.version;
         Authenticated(Builder::new(self.inner.and_then(move |conn, endpoint| {
+            stage::enter(ConnectionStage::Securing);
             Authenticate {
-                inner: upgrade::apply(conn, upgrade, endpoint, version)
+                inner: upgrade::apply(conn, upgrade, role::upgrade_endpoint(endpoint), version)
             }
         }), version))
     }
@@ -241,7 +248,8 @@ where
     {
         let version = self.0.version;
         Multiplexed(self.0.inner.and_then(move |(i, c), endpoint| {
-            let upgrade = upgrade::apply(c, upgrade, endpoint, version);
+            stage::enter(ConnectionStage::Multiplexing);
+            let upgrade = upgrade::apply(c, upgrade, role::upgrade_endpoint(endpoint), version);
             Multiplex { peer_id: Some(i), upgrade }
         }))
     }
@@ -270,7 +278,9 @@ where
     {
         let version = self.0.version;
         Multiplexed(self.0.inner.and_then(move |(peer_id, c), endpoint| {
-            let upgrade = upgrade::apply(c, up(&peer_id, &endpoint), endpoint, version);
+            stage::enter(ConnectionStage::Multiplexing);
+            let upgrade = up(&peer_id, &endpoint);
+            let upgrade = upgrade::apply(c, upgrade, role::upgrade_endpoint(endpoint), version);
             Multiplex { peer_id: Some(peer_id), upgrade }
         }))
     }
@@ -64,6 +64,7 @@ mod error;
 mod from_fn;
 mod map;
 mod optional;
+mod peer_filter;
 mod select;
 mod transfer;
 
@@ -79,6 +80,7 @@ pub use self::{
     from_fn::{from_fn, FromFnUpgrade},
     map::{MapInboundUpgrade, MapOutboundUpgrade, MapInboundUpgradeErr, MapOutboundUpgradeErr},
     optional::OptionalUpgrade,
+    peer_filter::{PeerFilter, PeerFilterUpgrade, PeerFilterFuture, PeerFilterError},
     select::SelectUpgrade,
     transfer::{write_one, write_with_len_prefix, write_varint, read_one, ReadOneError, read_varint},
 };
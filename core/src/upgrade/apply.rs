@@ -21,7 +21,7 @@
 use crate::{ConnectedPoint, Negotiated};
 use crate::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeError, ProtocolName};
 use futures::{future::Either, prelude::*};
-use log::debug;
+use tracing::debug;
 use multistream_select::{self, DialerSelectFuture, ListenerSelectFuture};
 use std::{iter, mem, pin::Pin, task::Context, task::Poll};
 
@@ -86,7 +86,8 @@ where
         upgrade: U,
     },
     Upgrade {
-        future: Pin<Box<U::Future>>
+        future: Pin<Box<U::Future>>,
+        span: tracing::Span,
     },
     Undefined
 }
@@ -116,22 +117,24 @@ where
                             return Poll::Pending
                         }
                     };
+                    let span = upgrade_span("inbound", &info);
                     self.inner = InboundUpgradeApplyState::Upgrade {
-                        future: Box::pin(upgrade.upgrade_inbound(io, info.0))
+                        future: span.in_scope(|| Box::pin(upgrade.upgrade_inbound(io, info.0))),
+                        span,
                     };
                 }
-                InboundUpgradeApplyState::Upgrade { mut future } => {
-                    match Future::poll(Pin::new(&mut future), cx) {
+                InboundUpgradeApplyState::Upgrade { mut future, span } => {
+                    match span.in_scope(|| Future::poll(Pin::new(&mut future), cx)) {
                         Poll::Pending => {
-                            self.inner = InboundUpgradeApplyState::Upgrade { future };
+                            self.inner = InboundUpgradeApplyState::Upgrade { future, span };
                             return Poll::Pending
                         }
                         Poll::Ready(Ok(x)) => {
-                            debug!("Successfully applied negotiated protocol");
+                            debug!(parent: &span, "Successfully applied negotiated protocol");
                             return Poll::Ready(Ok(x))
                         }
                         Poll::Ready(Err(e)) => {
-                            debug!("Failed to apply negotiated protocol");
+                            debug!(parent: &span, "Failed to apply negotiated protocol");
                             return Poll::Ready(Err(UpgradeError::Apply(e)))
                         }
                     }
@@ -162,7 +165,8 @@ where
         upgrade: U
     },
     Upgrade {
-        future: Pin<Box<U::Future>>
+        future: Pin<Box<U::Future>>,
+        span: tracing::Span,
     },
     Undefined
 }
@@ -192,22 +196,24 @@ where
                             return Poll::Pending
                         }
                     };
+                    let span = upgrade_span("outbound", &info);
                     self.inner = OutboundUpgradeApplyState::Upgrade {
-                        future: Box::pin(upgrade.upgrade_outbound(connection, info.0))
+                        future: span.in_scope(|| Box::pin(upgrade.upgrade_outbound(connection, info.0))),
+                        span,
                     };
                 }
-                OutboundUpgradeApplyState::Upgrade { mut future } => {
-                    match Future::poll(Pin::new(&mut future), cx) {
+                OutboundUpgradeApplyState::Upgrade { mut future, span } => {
+                    match span.in_scope(|| Future::poll(Pin::new(&mut future), cx)) {
                         Poll::Pending => {
-                            self.inner = OutboundUpgradeApplyState::Upgrade { future };
+                            self.inner = OutboundUpgradeApplyState::Upgrade { future, span };
                             return Poll::Pending
                         }
                         Poll::Ready(Ok(x)) => {
-                            debug!("Successfully applied negotiated protocol");
+                            debug!(parent: &span, "Successfully applied negotiated protocol");
                             return Poll::Ready(Ok(x))
                         }
                         Poll::Ready(Err(e)) => {
-                            debug!("Failed to apply negotiated protocol");
+                            debug!(parent: &span, "Failed to apply negotiated protocol");
                             return Poll::Ready(Err(UpgradeError::Apply(e)));
                         }
                     }
@@ -219,6 +225,15 @@ where
     }
 }
 
+/// Creates the span of the application of an upgrade for the negotiated protocol.
+fn upgrade_span<N: ProtocolName>(direction: &'static str, protocol: &NameWrap<N>) -> tracing::Span {
+    tracing::debug_span!(
+        "upgrade",
+        direction,
+        protocol = %String::from_utf8_lossy(protocol.as_ref()),
+    )
+}
+
 type NameWrapIter<I> = iter::Map<I, fn(<I as Iterator>::Item) -> NameWrap<<I as Iterator>::Item>>;
 
 /// Wrapper type to expose an `AsRef<[u8]>` impl for all types implementing `ProtocolName`.
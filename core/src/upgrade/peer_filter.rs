@@ -0,0 +1,262 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::PeerId;
+use crate::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use futures::prelude::*;
+use parking_lot::RwLock;
+use std::{collections::HashSet, error, fmt, pin::Pin, sync::Arc, task::Context, task::Poll};
+
+/// A dynamic list of allowed and denied peers, shared by all clones.
+///
+/// A peer is permitted unless it is denied. If the filter is created with
+/// [`PeerFilter::allow_list`], a peer must additionally have been allowed.
+///
+/// The filter can be enforced at the security upgrade of a transport with
+/// [`PeerFilterUpgrade`], rejecting a peer right after the remote
+/// [`PeerId`] has been authenticated and before a multiplexer or any
+/// connection handler is created.
+#[derive(Debug, Clone, Default)]
+pub struct PeerFilter {
+    inner: Arc<RwLock<FilterState>>,
+}
+
+#[derive(Debug, Default)]
+struct FilterState {
+    /// Whether only allowed peers are permitted.
+    allow_only: bool,
+    allowed: HashSet<PeerId>,
+    denied: HashSet<PeerId>,
+}
+
+impl PeerFilter {
+    /// Creates a filter permitting all peers that are not denied.
+    pub fn deny_list() -> Self {
+        PeerFilter::default()
+    }
+
+    /// Creates a filter only permitting peers that are allowed and not denied.
+    pub fn allow_list() -> Self {
+        let filter = PeerFilter::default();
+        filter.inner.write().allow_only = true;
+        filter
+    }
+
+    /// Adds a peer to the allow list.
+    pub fn allow(&self, peer: PeerId) {
+        self.inner.write().allowed.insert(peer);
+    }
+
+    /// Removes a peer from the allow list.
+    pub fn disallow(&self, peer: &PeerId) {
+        self.inner.write().allowed.remove(peer);
+    }
+
+    /// Adds a peer to the deny list.
+    pub fn deny(&self, peer: PeerId) {
+        self.inner.write().denied.insert(peer);
+    }
+
+    /// Removes a peer from the deny list.
+    pub fn undeny(&self, peer: &PeerId) {
+        self.inner.write().denied.remove(peer);
+    }
+
+    /// Checks whether a connection to the given peer is permitted.
+    pub fn is_permitted(&self, peer: &PeerId) -> bool {
+        let state = self.inner.read();
+        !state.denied.contains(peer) && (!state.allow_only || state.allowed.contains(peer))
+    }
+}
+
+/// Wraps around a security upgrade yielding the authenticated [`PeerId`]
+/// of the remote and fails the upgrade if the remote is not permitted by
+/// a [`PeerFilter`].
+///
+/// The inner upgrade can be any authenticating upgrade, e.g. Noise or plaintext.
+#[derive(Debug, Clone)]
+pub struct PeerFilterUpgrade<U> {
+    upgrade: U,
+    filter: PeerFilter,
+}
+
+impl<U> PeerFilterUpgrade<U> {
+    pub fn new(upgrade: U, filter: PeerFilter) -> Self {
+        PeerFilterUpgrade { upgrade, filter }
+    }
+}
+
+impl<U> UpgradeInfo for PeerFilterUpgrade<U>
+where
+    U: UpgradeInfo
+{
+    type Info = U::Info;
+    type InfoIter = U::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.upgrade.protocol_info()
+    }
+}
+
+impl<C, U, T> InboundUpgrade<C> for PeerFilterUpgrade<U>
+where
+    U: InboundUpgrade<C, Output = (PeerId, T)>,
+{
+    type Output = (PeerId, T);
+    type Error = PeerFilterError<U::Error>;
+    type Future = PeerFilterFuture<U::Future>;
+
+    fn upgrade_inbound(self, sock: C, info: Self::Info) -> Self::Future {
+        PeerFilterFuture {
+            inner: self.upgrade.upgrade_inbound(sock, info),
+            filter: self.filter,
+        }
+    }
+}
+
+impl<C, U, T> OutboundUpgrade<C> for PeerFilterUpgrade<U>
+where
+    U: OutboundUpgrade<C, Output = (PeerId, T)>,
+{
+    type Output = (PeerId, T);
+    type Error = PeerFilterError<U::Error>;
+    type Future = PeerFilterFuture<U::Future>;
+
+    fn upgrade_outbound(self, sock: C, info: Self::Info) -> Self::Future {
+        PeerFilterFuture {
+            inner: self.upgrade.upgrade_outbound(sock, info),
+            filter: self.filter,
+        }
+    }
+}
+
+/// The future of a [`PeerFilterUpgrade`].
+#[pin_project::pin_project]
+pub struct PeerFilterFuture<F> {
+    #[pin]
+    inner: F,
+    filter: PeerFilter,
+}
+
+impl<F, T, E> Future for PeerFilterFuture<F>
+where
+    F: TryFuture<Ok = (PeerId, T), Error = E>,
+{
+    type Output = Result<(PeerId, T), PeerFilterError<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match TryFuture::try_poll(this.inner, cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Err(PeerFilterError::Upgrade(e))),
+            Poll::Ready(Ok((peer, output))) => {
+                if this.filter.is_permitted(&peer) {
+                    Poll::Ready(Ok((peer, output)))
+                } else {
+                    tracing::debug!("Rejecting connection to {} denied by peer filter.", peer);
+                    Poll::Ready(Err(PeerFilterError::Denied(peer)))
+                }
+            }
+        }
+    }
+}
+
+/// The error of a [`PeerFilterUpgrade`].
+#[derive(Debug)]
+pub enum PeerFilterError<E> {
+    /// The authenticated remote is not permitted by the filter.
+    Denied(PeerId),
+    /// The inner upgrade failed.
+    Upgrade(E),
+}
+
+impl<E> fmt::Display for PeerFilterError<E>
+where
+    E: fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerFilterError::Denied(peer) => write!(f, "peer {} denied by filter", peer),
+            PeerFilterError::Upgrade(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E> error::Error for PeerFilterError<E>
+where
+    E: error::Error + 'static
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PeerFilterError::Denied(_) => None,
+            PeerFilterError::Upgrade(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upgrade;
+
+    #[test]
+    fn deny_list() {
+        let filter = PeerFilter::deny_list();
+        let peer = PeerId::random();
+        assert!(filter.is_permitted(&peer));
+        filter.clone().deny(peer);
+        assert!(!filter.is_permitted(&peer));
+        filter.undeny(&peer);
+        assert!(filter.is_permitted(&peer));
+    }
+
+    #[test]
+    fn allow_list() {
+        let filter = PeerFilter::allow_list();
+        let peer = PeerId::random();
+        assert!(!filter.is_permitted(&peer));
+        filter.allow(peer);
+        assert!(filter.is_permitted(&peer));
+        filter.deny(peer);
+        assert!(!filter.is_permitted(&peer));
+    }
+
+    #[test]
+    fn upgrade_rejects_denied_peer() {
+        let peer = PeerId::random();
+        let filter = PeerFilter::deny_list();
+        let authenticate = move || upgrade::from_fn("/auth/1.0.0", move |sock: (), _| {
+            future::ready(Ok::<_, std::io::Error>((peer, sock)))
+        });
+
+        let up = PeerFilterUpgrade::new(authenticate(), filter.clone());
+        let info = up.protocol_info().next().unwrap();
+        assert!(futures::executor::block_on(up.upgrade_inbound((), info)).is_ok());
+
+        filter.deny(peer);
+        let up = PeerFilterUpgrade::new(authenticate(), filter);
+        let info = up.protocol_info().next().unwrap();
+        match futures::executor::block_on(up.upgrade_outbound((), info)) {
+            Err(PeerFilterError::Denied(p)) => assert_eq!(p, peer),
+            _ => panic!("Expected the peer to be denied")
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Sends a file to another node with a simple chunked transfer protocol built
+//! on top of request-response.
+//!
+//! In a first terminal window, start the receiver, which stores received files
+//! in the current directory:
+//!
+//! ```sh
+//! cargo run --example file-send
+//! ```
+//!
+//! It prints the addresses it is listening on, e.g.
+//! `Listening on "/ip4/127.0.0.1/tcp/24915"`. In a second terminal window,
+//! send a file to the receiver:
+//!
+//! ```sh
+//! cargo run --example file-send -- /ip4/127.0.0.1/tcp/24915 <file>
+//! ```
+//!
+//! The file is sent in chunks of at most 64 KiB, each of which is a request
+//! acknowledged by the receiver with the number of bytes received so far.
+
+mod transfer;
+
+use async_std::task;
+use libp2p::{PeerId, Swarm, identity};
+use std::{error::Error, path::Path};
+use transfer::{Outgoing, file_protocol, receive, send};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = PeerId::from_public_key(local_key.public());
+    let transport = libp2p::build_development_transport(local_key)?;
+    let mut swarm = Swarm::new(transport, file_protocol(), local_peer_id);
+
+    let mut args = std::env::args().skip(1);
+    match (args.next(), args.next()) {
+        (Some(addr), Some(path)) => {
+            let name = Path::new(&path).file_name()
+                .and_then(|n| n.to_str())
+                .ok_or("Invalid file name.")?
+                .to_string();
+            let data = std::fs::read(&path)?;
+            task::block_on(send(swarm, addr.parse()?, Outgoing::new(name, data)))
+        }
+        _ => {
+            Swarm::listen_on(&mut swarm, "/ip4/0.0.0.0/tcp/0".parse()?)?;
+            task::block_on(receive(swarm, std::env::current_dir()?))
+        }
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The chunked file transfer protocol of the `file-send` example, built on
+//! top of request-response.
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    Swarm,
+    core::{ProtocolName, upgrade::{read_one, write_one}},
+    request_response::{
+        ProtocolSupport,
+        RequestResponse,
+        RequestResponseCodec,
+        RequestResponseConfig,
+        RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    swarm::SwarmEvent,
+};
+use std::{collections::HashMap, convert::TryInto, error::Error, io, iter, path::PathBuf};
+
+/// The maximum number of file bytes carried by a chunk.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The maximum length of an encoded chunk, i.e. the data plus its header.
+const MAX_CHUNK_LEN: usize = CHUNK_SIZE + 1024;
+
+#[derive(Debug, Clone)]
+pub struct FileProtocol;
+
+impl ProtocolName for FileProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/file-send/1.0.0"
+    }
+}
+
+/// A chunk of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// The name of the file, without any directory components.
+    pub name: String,
+    /// The offset of the chunk data within the file.
+    pub offset: u64,
+    /// Whether this is the last chunk of the file.
+    pub last: bool,
+    pub data: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(11 + self.name.len() + self.data.len());
+        buf.extend_from_slice(&(self.name.len() as u16).to_be_bytes());
+        buf.extend_from_slice(self.name.as_bytes());
+        buf.extend_from_slice(&self.offset.to_be_bytes());
+        buf.push(self.last as u8);
+        buf.extend_from_slice(&self.data);
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if buf.len() < 2 {
+            return Err(invalid("chunk too short"))
+        }
+        let name_len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        let rest = &buf[2..];
+        if rest.len() < name_len + 9 {
+            return Err(invalid("chunk too short"))
+        }
+        let name = String::from_utf8(rest[.. name_len].to_vec())
+            .map_err(|_| invalid("file name is not UTF-8"))?;
+        if name.is_empty() || name == "." || name == ".." || name.contains(|c| c == '/' || c == '\\') {
+            return Err(invalid("invalid file name"))
+        }
+        let rest = &rest[name_len ..];
+        let offset = u64::from_be_bytes(rest[.. 8].try_into().expect("slice of length 8"));
+        let last = match rest[8] {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid("invalid last chunk flag"))
+        };
+        Ok(Chunk { name, offset, last, data: rest[9 ..].to_vec() })
+    }
+}
+
+/// Chunks are sent as length-prefixed requests and acknowledged with the
+/// number of bytes of the file received so far.
+#[derive(Clone)]
+pub struct FileCodec;
+
+#[async_trait]
+impl RequestResponseCodec for FileCodec {
+    type Protocol = FileProtocol;
+    type Request = Chunk;
+    type Response = u64;
+
+    async fn read_request<T>(&mut self, _: &FileProtocol, io: &mut T) -> io::Result<Chunk>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        let bytes = read_one(io, MAX_CHUNK_LEN).await
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Chunk::decode(&bytes)
+    }
+
+    async fn read_response<T>(&mut self, _: &FileProtocol, io: &mut T) -> io::Result<u64>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        let bytes = read_one(io, 8).await
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let bytes = bytes.as_slice().try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid acknowledgement"))?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    async fn write_request<T>(&mut self, _: &FileProtocol, io: &mut T, chunk: Chunk) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        write_one(io, chunk.encode()).await
+    }
+
+    async fn write_response<T>(&mut self, _: &FileProtocol, io: &mut T, received: u64) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        write_one(io, received.to_be_bytes()).await
+    }
+}
+
+pub fn file_protocol() -> RequestResponse<FileCodec> {
+    let protocols = iter::once((FileProtocol, ProtocolSupport::Full));
+    RequestResponse::new(FileCodec, protocols, RequestResponseConfig::default())
+}
+
+/// The sending side of a transfer, splitting a file into chunks.
+pub struct Outgoing {
+    name: String,
+    data: Vec<u8>,
+    offset: usize,
+    done: bool,
+}
+
+impl Outgoing {
+    pub fn new(name: String, data: Vec<u8>) -> Self {
+        Outgoing { name, data, offset: 0, done: false }
+    }
+
+    /// Returns the next chunk to send, or `None` once the last chunk
+    /// has been returned.
+    fn next_chunk(&mut self) -> Option<Chunk> {
+        if self.done {
+            return None
+        }
+        let end = usize::min(self.offset + CHUNK_SIZE, self.data.len());
+        let chunk = Chunk {
+            name: self.name.clone(),
+            offset: self.offset as u64,
+            last: end == self.data.len(),
+            data: self.data[self.offset .. end].to_vec(),
+        };
+        self.offset = end;
+        self.done = chunk.last;
+        Some(chunk)
+    }
+}
+
+/// The receiving side of transfers, reassembling files from chunks.
+#[derive(Default)]
+struct Incoming {
+    files: HashMap<(PeerId, String), Vec<u8>>,
+}
+
+impl Incoming {
+    /// Adds a chunk received from the given peer.
+    ///
+    /// Returns the number of bytes of the file received so far and, if the
+    /// chunk was the last one, the complete file.
+    fn on_chunk(&mut self, peer: PeerId, chunk: Chunk) -> io::Result<(u64, Option<(String, Vec<u8>)>)> {
+        let key = (peer, chunk.name);
+        let file = self.files.entry(key.clone()).or_default();
+        if file.len() as u64 != chunk.offset {
+            self.files.remove(&key);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected chunk offset"))
+        }
+        file.extend_from_slice(&chunk.data);
+        let received = file.len() as u64;
+        if chunk.last {
+            let data = self.files.remove(&key).unwrap_or_default();
+            return Ok((received, Some((key.1, data))))
+        }
+        Ok((received, None))
+    }
+}
+
+/// Sends a file to the node listening on the given address.
+pub async fn send(mut swarm: Swarm<RequestResponse<FileCodec>>, addr: Multiaddr, mut file: Outgoing)
+    -> Result<(), Box<dyn Error>>
+{
+    Swarm::dial_addr(&mut swarm, addr)?;
+    let mut receiver = None;
+    loop {
+        match swarm.next_event().await {
+            SwarmEvent::ConnectionEstablished { peer_id, .. } if receiver.is_none() => {
+                receiver = Some(peer_id);
+                let chunk = file.next_chunk().expect("A file has at least one chunk.");
+                swarm.send_request(&peer_id, chunk);
+            }
+            SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                peer, message: RequestResponseMessage::Response { response, .. }
+            }) => match file.next_chunk() {
+                Some(chunk) => {
+                    swarm.send_request(&peer, chunk);
+                }
+                None => {
+                    println!("Sent {} bytes to {}.", response, peer);
+                    return Ok(())
+                }
+            },
+            SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure { error, .. }) =>
+                return Err(format!("Transfer failed: {:?}", error).into()),
+            SwarmEvent::UnknownPeerUnreachableAddr { address, error, .. } =>
+                return Err(format!("Failed to reach {}: {}", address, error).into()),
+            _ => {}
+        }
+    }
+}
+
+/// Receives files from any node and stores them in the given directory.
+pub async fn receive(mut swarm: Swarm<RequestResponse<FileCodec>>, dir: PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut incoming = Incoming::default();
+    loop {
+        match swarm.next_event().await {
+            SwarmEvent::NewListenAddr(addr) => println!("Listening on {:?}", addr),
+            SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                peer, message: RequestResponseMessage::Request { request, channel, .. }
+            }) => match incoming.on_chunk(peer, request) {
+                Ok((received, file)) => {
+                    if let Some((name, data)) = file {
+                        std::fs::write(dir.join(&name), &data)?;
+                        println!("Received {} ({} bytes) from {}.", name, data.len(), peer);
+                    }
+                    let _ = swarm.send_response(channel, received);
+                }
+                // Dropping the response channel aborts the transfer on the sender's side.
+                Err(e) => println!("Rejected chunk from {}: {}", peer, e)
+            },
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The chat protocol of the `lan-chat` example, built on top of
+//! request-response.
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::{
+    core::{ProtocolName, upgrade::{read_one, write_one}},
+    request_response::{ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig},
+};
+use std::iter;
+
+/// The maximum length of a chat message in bytes.
+const MAX_MESSAGE_LEN: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct ChatProtocol;
+
+impl ProtocolName for ChatProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/lan-chat/1.0.0"
+    }
+}
+
+/// Chat messages are sent as length-prefixed UTF-8 strings and
+/// acknowledged with an empty response.
+#[derive(Clone)]
+pub struct ChatCodec;
+
+#[async_trait]
+impl RequestResponseCodec for ChatCodec {
+    type Protocol = ChatProtocol;
+    type Request = String;
+    type Response = ();
+
+    async fn read_request<T>(&mut self, _: &ChatProtocol, io: &mut T) -> std::io::Result<String>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        let bytes = read_one(io, MAX_MESSAGE_LEN).await
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        String::from_utf8(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    async fn read_response<T>(&mut self, _: &ChatProtocol, io: &mut T) -> std::io::Result<()>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        read_one(io, 0).await
+            .map(|_| ())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write_request<T>(&mut self, _: &ChatProtocol, io: &mut T, message: String) -> std::io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        write_one(io, message).await
+    }
+
+    async fn write_response<T>(&mut self, _: &ChatProtocol, io: &mut T, _: ()) -> std::io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        write_one(io, b"").await
+    }
+}
+
+pub fn chat_protocol() -> RequestResponse<ChatCodec> {
+    let protocols = iter::once((ChatProtocol, ProtocolSupport::Full));
+    RequestResponse::new(ChatCodec, protocols, RequestResponseConfig::default())
+}
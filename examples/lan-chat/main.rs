@@ -0,0 +1,149 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A chat application for the local network, demonstrating the composition
+//! of mDNS with the request-response protocol.
+//!
+//! Using two or more terminal windows on hosts of the same local network,
+//! start an instance in each:
+//!
+//! ```sh
+//! cargo run --example lan-chat
+//! ```
+//!
+//! The instances discover each other through mDNS. Type a message in any
+//! terminal and hit return: the message is sent as a request to every
+//! discovered peer, which prints it and acknowledges it with an empty
+//! response. Close with Ctrl-c.
+
+mod chat;
+
+use async_std::{io, task};
+use chat::{ChatCodec, chat_protocol};
+use futures::{future, prelude::*};
+use libp2p::{
+    NetworkBehaviour,
+    PeerId,
+    Swarm,
+    identity,
+    mdns::{Mdns, MdnsEvent},
+    request_response::{RequestResponse, RequestResponseEvent, RequestResponseMessage},
+    swarm::NetworkBehaviourEventProcess,
+};
+use std::{collections::HashSet, error::Error, task::{Context, Poll}};
+
+#[derive(NetworkBehaviour)]
+struct LanChat {
+    chat: RequestResponse<ChatCodec>,
+    mdns: Mdns,
+
+    /// The peers discovered through mDNS.
+    #[behaviour(ignore)]
+    peers: HashSet<PeerId>,
+}
+
+impl LanChat {
+    /// Sends a message to all discovered peers.
+    fn broadcast(&mut self, message: String) {
+        for peer in &self.peers {
+            self.chat.send_request(peer, message.clone());
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<RequestResponseEvent<String, ()>> for LanChat {
+    fn inject_event(&mut self, event: RequestResponseEvent<String, ()>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Request { request, channel, .. }
+            } => {
+                println!("{}: {}", peer, request);
+                let _ = self.chat.send_response(channel, ());
+            }
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                println!("Sending to {} failed: {:?}", peer, error);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<MdnsEvent> for LanChat {
+    fn inject_event(&mut self, event: MdnsEvent) {
+        match event {
+            MdnsEvent::Discovered(list) =>
+                for (peer, addr) in list {
+                    self.chat.add_address(&peer, addr);
+                    if self.peers.insert(peer) {
+                        println!("Discovered {}", peer);
+                    }
+                }
+            MdnsEvent::Expired(list) =>
+                for (peer, addr) in list {
+                    self.chat.remove_address(&peer, &addr);
+                    if !self.mdns.has_node(&peer) {
+                        self.peers.remove(&peer);
+                    }
+                }
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = PeerId::from_public_key(local_key.public());
+    println!("Local peer id: {:?}", local_peer_id);
+
+    let transport = libp2p::build_development_transport(local_key)?;
+
+    let mut swarm = {
+        let behaviour = LanChat {
+            chat: chat_protocol(),
+            mdns: task::block_on(Mdns::new())?,
+            peers: HashSet::new(),
+        };
+        Swarm::new(transport, behaviour, local_peer_id)
+    };
+
+    Swarm::listen_on(&mut swarm, "/ip4/0.0.0.0/tcp/0".parse()?)?;
+
+    let mut stdin = io::BufReader::new(io::stdin()).lines();
+
+    task::block_on(future::poll_fn(move |cx: &mut Context<'_>| {
+        loop {
+            match stdin.try_poll_next_unpin(cx)? {
+                Poll::Ready(Some(line)) => swarm.broadcast(line),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => break
+            }
+        }
+        loop {
+            match swarm.poll_next_unpin(cx) {
+                Poll::Ready(Some(_)) => {}
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => break
+            }
+        }
+        Poll::Pending
+    }))
+}
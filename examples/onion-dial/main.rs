@@ -0,0 +1,91 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Connects to a node through its Tor onion service, demonstrating onion
+//! addresses and the composition of a custom transport with TCP.
+//!
+//! The onion address of a node is derived from its ed25519 key, hence
+//! dialing an onion address also authenticates the remote. In a first
+//! terminal window, start a node listening on a local port:
+//!
+//! ```sh
+//! cargo run --example onion-dial -- listen 4001
+//! ```
+//!
+//! It prints its onion address, e.g. `/onion3/<address>:4001/p2p/<peer id>`.
+//! For the address to be reachable, a Tor daemon has to publish the onion
+//! service of the node's key, forwarding port 4001 to `127.0.0.1:4001`. Then,
+//! with a Tor daemon running locally, dial the node from a second terminal
+//! window:
+//!
+//! ```sh
+//! cargo run --example onion-dial -- dial /onion3/<address>:4001/p2p/<peer id>
+//! ```
+//!
+//! The connection is established through the SOCKS5 proxy of the Tor daemon
+//! at `127.0.0.1:9050`, unless another proxy address is given as the last
+//! argument. Both nodes then keep pinging each other.
+
+mod socks;
+
+use async_std::task;
+use libp2p::{PeerId, Swarm, identity, multiaddr::Protocol, ping::{Ping, PingConfig}};
+use std::error::Error;
+
+/// The default address of the SOCKS5 proxy of a Tor daemon.
+const DEFAULT_PROXY: &str = "127.0.0.1:9050";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let ed25519_key = identity::ed25519::Keypair::generate();
+    let onion_key = ed25519_key.public();
+    let local_key = identity::Keypair::Ed25519(ed25519_key);
+    let local_peer_id = PeerId::from_public_key(local_key.public());
+    println!("Local peer id: {:?}", local_peer_id);
+
+    let mut args = std::env::args().skip(1);
+    let (command, arg) = (args.next(), args.next());
+    let proxy = args.next().unwrap_or_else(|| DEFAULT_PROXY.to_string()).parse()?;
+
+    let transport = socks::build_transport(&local_key, proxy);
+    let behaviour = Ping::new(PingConfig::new().with_keep_alive(true));
+    let mut swarm = Swarm::new(transport, behaviour, local_peer_id);
+
+    match (command.as_deref(), arg) {
+        (Some("listen"), Some(port)) => {
+            let port = port.parse()?;
+            Swarm::listen_on(&mut swarm, format!("/ip4/127.0.0.1/tcp/{}", port).parse()?)?;
+            let addr = socks::onion_address(&onion_key, port).with(Protocol::P2p(local_peer_id.into()));
+            println!("Onion address: {}", addr);
+        }
+        (Some("dial"), Some(addr)) => {
+            Swarm::dial_addr(&mut swarm, addr.parse()?)?;
+            println!("Dialed {}", addr);
+        }
+        _ => return Err("Usage: onion-dial (listen <port> | dial <onion address> [<proxy>])".into()),
+    }
+
+    task::block_on(async move {
+        loop {
+            println!("{:?}", swarm.next().await);
+        }
+    })
+}
@@ -0,0 +1,165 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The transport of the `onion-dial` example: onion addresses are dialed
+//! through the SOCKS5 proxy of a Tor daemon, everything else over plain TCP.
+
+use async_std::net::TcpStream;
+use futures::{future::{self, BoxFuture}, prelude::*, stream};
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    Transport,
+    core::{
+        muxing::StreamMuxerBox,
+        onion,
+        transport::{self, ListenerEvent, TransportError},
+        upgrade,
+    },
+    identity,
+    mplex,
+    multiaddr::Protocol,
+    noise,
+    tcp::TcpConfig,
+};
+use std::{io, net::SocketAddr};
+
+/// A transport dialing `/onion3` addresses through a SOCKS5 proxy.
+///
+/// Listening is not supported: an onion service is published by the Tor
+/// daemon, which forwards incoming connections to a regular TCP listener.
+#[derive(Debug, Clone)]
+pub struct Socks5Transport {
+    proxy: SocketAddr,
+}
+
+impl Socks5Transport {
+    /// Creates a transport connecting through the proxy at the given address.
+    pub fn new(proxy: SocketAddr) -> Self {
+        Socks5Transport { proxy }
+    }
+}
+
+impl Transport for Socks5Transport {
+    type Output = TcpStream;
+    type Error = io::Error;
+    type Listener = stream::Pending<Result<ListenerEvent<Self::ListenerUpgrade, io::Error>, io::Error>>;
+    type ListenerUpgrade = future::Pending<Result<TcpStream, io::Error>>;
+    type Dial = BoxFuture<'static, Result<TcpStream, io::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<io::Error>> {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<io::Error>> {
+        let (host, port) = match onion_target(&addr) {
+            Some(target) => target,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+        Ok(async move {
+            let mut stream = TcpStream::connect(self.proxy).await?;
+            socks5_connect(&mut stream, &host, port).await?;
+            Ok(stream)
+        }.boxed())
+    }
+
+    fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+}
+
+/// Returns the host name and port to request from the proxy for an address
+/// of the form `/onion3/<address>:<port>`, optionally followed by `/p2p`.
+fn onion_target(addr: &Multiaddr) -> Option<(String, u16)> {
+    let mut iter = addr.iter();
+    let onion = match iter.next()? {
+        Protocol::Onion3(onion) => onion,
+        _ => return None,
+    };
+    match iter.next() {
+        None | Some(Protocol::P2p(_)) => {}
+        Some(_) => return None,
+    }
+    let peer_id = onion::onion3_peer_id(&onion)?;
+    let host = peer_id.as_onion_address().ok()?;
+    Some((format!("{}.onion", host), onion.port()))
+}
+
+/// Asks the SOCKS5 proxy at the other end of the stream to connect to the
+/// given host, without authentication.
+pub async fn socks5_connect<S>(stream: &mut S, host: &str, port: u16) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin
+{
+    // Version 5, offering the "no authentication" method only.
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [5, 0] {
+        return Err(io::Error::new(io::ErrorKind::Other, "proxy requires authentication"))
+    }
+
+    // A CONNECT request for a domain name.
+    let mut request = vec![5, 1, 0, 3, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        let msg = format!("proxy failed to connect with reply code {}", reply[1]);
+        return Err(io::Error::new(io::ErrorKind::ConnectionRefused, msg))
+    }
+    // Skip the address the proxy bound for the connection.
+    let addr_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid address type in proxy reply")),
+    };
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound).await
+}
+
+/// Returns the onion address under which a node with the given key is
+/// reachable, if the Tor daemon publishes its onion service on `port`.
+pub fn onion_address(key: &identity::ed25519::PublicKey, port: u16) -> Multiaddr {
+    Multiaddr::empty().with(Protocol::Onion3(onion::onion3_address(key, port)))
+}
+
+/// Builds the transport of a node, dialing onion addresses through the given
+/// proxy and other addresses, as well as listening, over TCP.
+pub fn build_transport(keypair: &identity::Keypair, proxy: SocketAddr) -> transport::Boxed<(PeerId, StreamMuxerBox)> {
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
+        .into_authentic(keypair)
+        .expect("Signing libp2p-noise static DH keypair failed.");
+
+    Socks5Transport::new(proxy)
+        .or_transport(TcpConfig::new().nodelay(true))
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(mplex::MplexConfig::new())
+        .boxed()
+}
@@ -0,0 +1,100 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Connects two nodes through a relay, demonstrating the circuit relay v2
+//! protocol.
+//!
+//! In a first terminal window, start the relay on a port of your choice:
+//!
+//! ```sh
+//! cargo run --example relay -- relay 4001
+//! ```
+//!
+//! It prints its peer ID. In a second terminal window, start a node asking
+//! the relay for a reservation:
+//!
+//! ```sh
+//! cargo run --example relay -- listen /ip4/127.0.0.1/tcp/4001/p2p/<relay id>
+//! ```
+//!
+//! Once the reservation is accepted, the node prints its relayed address,
+//! e.g. `/ip4/127.0.0.1/tcp/4001/p2p/<relay id>/p2p-circuit/p2p/<peer id>`.
+//! In a third terminal window, dial the node through the relay:
+//!
+//! ```sh
+//! cargo run --example relay -- dial <relayed address>
+//! ```
+//!
+//! The two nodes then keep pinging each other over the relayed connection.
+
+mod node;
+
+use async_std::task;
+use libp2p::{Multiaddr, PeerId, Swarm, identity, multiaddr::Protocol, swarm::SwarmEvent};
+use node::Event;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = PeerId::from_public_key(local_key.public());
+    println!("Local peer id: {:?}", local_peer_id);
+
+    let mut args = std::env::args().skip(1);
+    match (args.next().as_deref(), args.next()) {
+        (Some("relay"), Some(port)) => {
+            let mut swarm = node::relay_server(local_key);
+            Swarm::listen_on(&mut swarm, format!("/ip4/0.0.0.0/tcp/{}", port).parse()?)?;
+            task::block_on(async move {
+                loop { print_event(swarm.next_event().await, local_peer_id) }
+            })
+        }
+        (Some("listen"), Some(relay_addr)) => {
+            let mut swarm = node::relay_client(local_key);
+            let relay_addr: Multiaddr = relay_addr.parse()?;
+            Swarm::listen_on(&mut swarm, relay_addr.with(Protocol::P2pCircuit))?;
+            task::block_on(async move {
+                loop { print_event(swarm.next_event().await, local_peer_id) }
+            })
+        }
+        (Some("dial"), Some(addr)) => {
+            let mut swarm = node::relay_client(local_key);
+            Swarm::dial_addr(&mut swarm, addr.parse()?)?;
+            task::block_on(async move {
+                loop { print_event(swarm.next_event().await, local_peer_id) }
+            })
+        }
+        _ => Err("Usage: relay (relay <port> | listen <relay address> | dial <relayed address>)".into()),
+    }
+}
+
+/// Prints the events of the behaviour and the listen addresses of the
+/// local node, in a form other nodes can dial.
+fn print_event<E>(event: SwarmEvent<Event, E>, local_peer_id: PeerId) {
+    match event {
+        SwarmEvent::NewListenAddr(addr) =>
+            println!("Listening on {}", addr.with(Protocol::P2p(local_peer_id.into()))),
+        SwarmEvent::Behaviour(Event::Relay(event)) => println!("{:?}", event),
+        SwarmEvent::Behaviour(Event::Client(event)) => println!("{:?}", event),
+        SwarmEvent::Behaviour(Event::Ping(event)) => println!("{:?}", event),
+        _ => {}
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The nodes of the `relay` example: a relay server and the nodes using
+//! it, all of which answer pings.
+
+use libp2p::{
+    NetworkBehaviour,
+    PeerId,
+    Swarm,
+    Transport,
+    core::{muxing::StreamMuxerBox, transport, upgrade},
+    identity,
+    noise,
+    ping::{Ping, PingConfig, PingEvent},
+    relay::{Client, ClientEvent, Relay, RelayConfig, RelayEvent},
+    tcp::TcpConfig,
+    yamux,
+};
+
+/// The behaviour of the relay server.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "Event", event_process = false)]
+pub struct RelayServer {
+    relay: Relay,
+    ping: Ping,
+}
+
+/// The behaviour of the nodes using the relay.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "Event", event_process = false)]
+pub struct RelayClient {
+    relay_client: Client,
+    ping: Ping,
+}
+
+/// The events of both [`RelayServer`] and [`RelayClient`].
+#[derive(Debug)]
+pub enum Event {
+    Relay(RelayEvent),
+    Client(ClientEvent),
+    Ping(PingEvent),
+}
+
+impl From<RelayEvent> for Event {
+    fn from(event: RelayEvent) -> Self {
+        Event::Relay(event)
+    }
+}
+
+impl From<ClientEvent> for Event {
+    fn from(event: ClientEvent) -> Self {
+        Event::Client(event)
+    }
+}
+
+impl From<PingEvent> for Event {
+    fn from(event: PingEvent) -> Self {
+        Event::Ping(event)
+    }
+}
+
+/// Creates a relay server communicating over TCP.
+pub fn relay_server(keypair: identity::Keypair) -> Swarm<RelayServer> {
+    let peer_id = PeerId::from_public_key(keypair.public());
+    let transport = upgrade_transport(TcpConfig::new(), &keypair);
+    let behaviour = RelayServer {
+        relay: Relay::new(RelayConfig::default()),
+        ping: Ping::new(PingConfig::new().with_keep_alive(true)),
+    };
+    Swarm::new(transport, behaviour, peer_id)
+}
+
+/// Creates a node communicating over TCP, either directly or through relays.
+pub fn relay_client(keypair: identity::Keypair) -> Swarm<RelayClient> {
+    let peer_id = PeerId::from_public_key(keypair.public());
+    let (relay_transport, relay_client) = Client::new_transport_and_behaviour();
+    let transport = upgrade_transport(relay_transport.or_transport(TcpConfig::new()), &keypair);
+    let behaviour = RelayClient {
+        relay_client,
+        ping: Ping::new(PingConfig::new().with_keep_alive(true)),
+    };
+    Swarm::new(transport, behaviour, peer_id)
+}
+
+fn upgrade_transport<T>(transport: T, keypair: &identity::Keypair)
+    -> transport::Boxed<(PeerId, StreamMuxerBox)>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: futures::AsyncRead + futures::AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+{
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
+        .into_authentic(keypair)
+        .expect("Signing libp2p-noise static DH keypair failed.");
+
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(yamux::YamuxConfig::default())
+        .boxed()
+}
@@ -0,0 +1,96 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! The request-response codec for opaque request and response payloads.
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::core::upgrade::{read_one, write_one, ProtocolName};
+use libp2p::request_response::RequestResponseCodec;
+use std::io;
+
+/// The name of the request-response protocol, as configured by the embedder.
+#[derive(Debug, Clone)]
+pub struct BytesProtocol(pub Vec<u8>);
+
+impl ProtocolName for BytesProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A codec for requests and responses consisting of opaque bytes, each
+/// sent as a single length-prefixed message.
+#[derive(Debug, Clone)]
+pub struct BytesCodec {
+    /// The maximum size of a request or response in bytes.
+    max_size: usize,
+}
+
+impl BytesCodec {
+    /// Creates a new codec accepting messages of up to `max_size` bytes.
+    pub fn new(max_size: usize) -> Self {
+        BytesCodec { max_size }
+    }
+
+    async fn read<T>(&self, io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        read_one(io, self.max_size).await
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[async_trait]
+impl RequestResponseCodec for BytesCodec {
+    type Protocol = BytesProtocol;
+    type Request = Vec<u8>;
+    type Response = Vec<u8>;
+
+    async fn read_request<T>(&mut self, _: &BytesProtocol, io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        self.read(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &BytesProtocol, io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        self.read(io).await
+    }
+
+    async fn write_request<T>(&mut self, _: &BytesProtocol, io: &mut T, data: Vec<u8>) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        write_one(io, data).await
+    }
+
+    async fn write_response<T>(&mut self, _: &BytesProtocol, io: &mut T, data: Vec<u8>) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        write_one(io, data).await
+    }
+}
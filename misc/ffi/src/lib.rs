@@ -0,0 +1,475 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! C ABI for embedding libp2p in the MWC wallets.
+//!
+//! A node is created from an [`MwcP2pConfig`] with [`mwc_p2p_node_new`] and
+//! runs on a background thread until it is freed with [`mwc_p2p_node_free`].
+//! Peers exchange opaque requests and responses over a single, configurable
+//! request-response protocol. Requests are sent with [`mwc_p2p_send_request`]
+//! and inbound requests answered with [`mwc_p2p_send_response`].
+//!
+//! Everything happening on the network is reported as an [`MwcP2pEvent`],
+//! retrieved with [`mwc_p2p_poll_event`]. To avoid polling in vain, an
+//! optional callback is invoked every time an event has been queued.
+//!
+//! # Ownership
+//!
+//! - A node handle returned by [`mwc_p2p_node_new`] is owned by the caller
+//!   and must be freed exactly once with [`mwc_p2p_node_free`]. A handle
+//!   may be used from any thread, but must not be used after it is freed.
+//! - Strings and byte arrays passed to the library are only borrowed for
+//!   the duration of the call. Strings are NUL-terminated UTF-8.
+//! - An [`MwcP2pBuffer`] written by the library is owned by the caller and
+//!   must be freed with [`mwc_p2p_buffer_free`]. Likewise, an event written
+//!   by [`mwc_p2p_poll_event`] must be freed with [`mwc_p2p_event_free`],
+//!   which frees all buffers of the event. Buffers are not NUL-terminated.
+//! - The callback passed to [`mwc_p2p_node_new`] is invoked on the background
+//!   thread of the node. It may call any function of this library except
+//!   [`mwc_p2p_node_free`] for the same node. The `user_data` pointer is
+//!   passed to the callback as-is and must remain valid until the node is freed.
+//!
+//! # Errors
+//!
+//! All functions returning an `i32` return [`MWC_P2P_OK`] on success and a
+//! negative `MWC_P2P_ERR_*` constant on failure. The error codes of failed
+//! requests reported in events are those of
+//! [`OutboundFailure::code`](libp2p::request_response::OutboundFailure::code)
+//! and [`InboundFailure::code`](libp2p::request_response::InboundFailure::code).
+
+mod codec;
+pub mod node;
+
+pub use node::{Closed, Config, Event, Node};
+
+use libp2p::{identity, Multiaddr, PeerId};
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_void},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    slice,
+    time::Duration,
+};
+
+/// The operation succeeded.
+pub const MWC_P2P_OK: i32 = 0;
+/// [`mwc_p2p_poll_event`] found no queued event.
+pub const MWC_P2P_EMPTY: i32 = 1;
+/// A required pointer argument is null.
+pub const MWC_P2P_ERR_NULL: i32 = -1;
+/// An argument is invalid, e.g. a malformed address or peer ID.
+pub const MWC_P2P_ERR_INVALID: i32 = -2;
+/// The background thread of the node has terminated.
+pub const MWC_P2P_ERR_CLOSED: i32 = -3;
+/// The node could not be created, e.g. because listening failed.
+pub const MWC_P2P_ERR_IO: i32 = -4;
+/// An unexpected internal error occurred.
+pub const MWC_P2P_ERR_PANIC: i32 = -5;
+
+/// The node listens on a new address, given by `address`.
+pub const MWC_P2P_EVENT_NEW_LISTEN_ADDR: u32 = 1;
+/// A connection to `peer` has been established.
+pub const MWC_P2P_EVENT_CONNECTED: u32 = 2;
+/// The last connection to `peer` has been closed.
+pub const MWC_P2P_EVENT_DISCONNECTED: u32 = 3;
+/// Dialing `address` failed.
+pub const MWC_P2P_EVENT_DIAL_FAILURE: u32 = 4;
+/// `peer` sent the request `data` with ID `request_id`, to be answered
+/// with [`mwc_p2p_send_response`].
+pub const MWC_P2P_EVENT_INBOUND_REQUEST: u32 = 5;
+/// `peer` answered the request with ID `request_id` with `data`.
+pub const MWC_P2P_EVENT_RESPONSE: u32 = 6;
+/// The request to `peer` with ID `request_id` failed with `error_code`.
+pub const MWC_P2P_EVENT_OUTBOUND_FAILURE: u32 = 7;
+/// The request of `peer` with ID `request_id` could not be answered,
+/// failing with `error_code`.
+pub const MWC_P2P_EVENT_INBOUND_FAILURE: u32 = 8;
+
+/// The configuration of a node.
+#[repr(C)]
+pub struct MwcP2pConfig {
+    /// A 32 byte Ed25519 secret key, or null to generate a new identity.
+    pub secret_key: *const u8,
+    /// The address to listen on, or null to not listen.
+    pub listen_addr: *const c_char,
+    /// The name of the request-response protocol, e.g. `/mwc/wallet/1.0.0`.
+    pub protocol: *const c_char,
+    /// The timeout of outbound requests in seconds, or 0 for the default.
+    pub request_timeout_secs: u64,
+    /// The maximum size of a request or response in bytes, or 0 for the default.
+    pub max_message_size: usize,
+}
+
+/// A byte buffer owned by the caller, to be freed with [`mwc_p2p_buffer_free`].
+///
+/// An empty buffer has a null `data` pointer.
+#[repr(C)]
+pub struct MwcP2pBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl MwcP2pBuffer {
+    fn empty() -> Self {
+        MwcP2pBuffer { data: ptr::null_mut(), len: 0 }
+    }
+
+    fn from_vec(v: Vec<u8>) -> Self {
+        if v.is_empty() {
+            return MwcP2pBuffer::empty()
+        }
+        let len = v.len();
+        let data = Box::into_raw(v.into_boxed_slice()) as *mut u8;
+        MwcP2pBuffer { data, len }
+    }
+
+    unsafe fn free(&mut self) {
+        if !self.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(self.data, self.len)));
+        }
+        *self = MwcP2pBuffer::empty();
+    }
+}
+
+/// An event reported by a node, to be freed with [`mwc_p2p_event_free`].
+///
+/// The meaning of the fields depends on the `kind` of event, one of the
+/// `MWC_P2P_EVENT_*` constants. Unused fields are zero or empty.
+#[repr(C)]
+pub struct MwcP2pEvent {
+    pub kind: u32,
+    pub error_code: u32,
+    pub request_id: u64,
+    /// The base58 encoded peer ID.
+    pub peer: MwcP2pBuffer,
+    /// The textual representation of the address.
+    pub address: MwcP2pBuffer,
+    pub data: MwcP2pBuffer,
+}
+
+impl From<Event> for MwcP2pEvent {
+    fn from(event: Event) -> Self {
+        let mut e = MwcP2pEvent {
+            kind: 0,
+            error_code: 0,
+            request_id: 0,
+            peer: MwcP2pBuffer::empty(),
+            address: MwcP2pBuffer::empty(),
+            data: MwcP2pBuffer::empty(),
+        };
+        let peer = |p: PeerId| MwcP2pBuffer::from_vec(p.to_base58().into_bytes());
+        let address = |a: Multiaddr| MwcP2pBuffer::from_vec(a.to_string().into_bytes());
+        match event {
+            Event::NewListenAddr(addr) => {
+                e.kind = MWC_P2P_EVENT_NEW_LISTEN_ADDR;
+                e.address = address(addr);
+            }
+            Event::Connected(p) => {
+                e.kind = MWC_P2P_EVENT_CONNECTED;
+                e.peer = peer(p);
+            }
+            Event::Disconnected(p) => {
+                e.kind = MWC_P2P_EVENT_DISCONNECTED;
+                e.peer = peer(p);
+            }
+            Event::DialFailure { address: addr } => {
+                e.kind = MWC_P2P_EVENT_DIAL_FAILURE;
+                e.address = address(addr);
+            }
+            Event::InboundRequest { peer: p, request_id, data } => {
+                e.kind = MWC_P2P_EVENT_INBOUND_REQUEST;
+                e.peer = peer(p);
+                e.request_id = request_id;
+                e.data = MwcP2pBuffer::from_vec(data);
+            }
+            Event::Response { peer: p, request_id, data } => {
+                e.kind = MWC_P2P_EVENT_RESPONSE;
+                e.peer = peer(p);
+                e.request_id = request_id;
+                e.data = MwcP2pBuffer::from_vec(data);
+            }
+            Event::OutboundFailure { peer: p, request_id, error } => {
+                e.kind = MWC_P2P_EVENT_OUTBOUND_FAILURE;
+                e.peer = peer(p);
+                e.request_id = request_id;
+                e.error_code = error.code();
+            }
+            Event::InboundFailure { peer: p, request_id, error } => {
+                e.kind = MWC_P2P_EVENT_INBOUND_FAILURE;
+                e.peer = peer(p);
+                e.request_id = request_id;
+                e.error_code = error.code();
+            }
+        }
+        e
+    }
+}
+
+/// The callback invoked on the background thread of a node whenever an
+/// event has been queued.
+pub type MwcP2pNotify = Option<unsafe extern "C" fn(user_data: *mut c_void)>;
+
+/// The callback of a node together with its user data.
+struct Notify {
+    callback: unsafe extern "C" fn(*mut c_void),
+    user_data: *mut c_void,
+}
+
+// The embedder guarantees that `user_data` may be used from the background thread.
+unsafe impl Send for Notify {}
+
+/// Creates a new node and starts its background thread.
+///
+/// Returns null on failure, e.g. if the configuration is invalid or
+/// listening on the configured address failed.
+///
+/// # Safety
+///
+/// `config` must point to a valid [`MwcP2pConfig`] and `user_data` must remain
+/// valid until the node is freed. See also the [ownership rules](crate#ownership).
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_node_new(
+    config: *const MwcP2pConfig,
+    notify: MwcP2pNotify,
+    user_data: *mut c_void,
+) -> *mut Node {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let config = config_from_c(config.as_ref()?)?;
+        let notify = notify.map(|callback| Notify { callback, user_data });
+        let notify = Box::new(move || {
+            if let Some(n) = &notify {
+                (n.callback)(n.user_data)
+            }
+        });
+        match Node::new(config, notify) {
+            Ok(node) => Some(Box::into_raw(Box::new(node))),
+            Err(e) => {
+                log::error!("Failed to create node: {}", e);
+                None
+            }
+        }
+    }));
+    result.ok().flatten().unwrap_or(ptr::null_mut())
+}
+
+/// Shuts down a node, waiting for its background thread to terminate,
+/// and frees it. Does nothing if `node` is null.
+///
+/// # Safety
+///
+/// `node` must have been returned by [`mwc_p2p_node_new`] and not yet been freed.
+/// It must not be called from the callback of the same node.
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_node_free(node: *mut Node) {
+    if !node.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(node))));
+    }
+}
+
+/// Writes the base58 encoded peer ID of the node into `out`.
+///
+/// # Safety
+///
+/// `node` must be a valid node handle and `out` must point to writable memory
+/// for an [`MwcP2pBuffer`].
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_node_peer_id(node: *const Node, out: *mut MwcP2pBuffer) -> i32 {
+    guard(|| {
+        let node = node.as_ref().ok_or(MWC_P2P_ERR_NULL)?;
+        let out = out.as_mut().ok_or(MWC_P2P_ERR_NULL)?;
+        *out = MwcP2pBuffer::from_vec(node.local_peer_id().to_base58().into_bytes());
+        Ok(MWC_P2P_OK)
+    })
+}
+
+/// Dials the given address. Failure to connect is reported with an
+/// [`MWC_P2P_EVENT_DIAL_FAILURE`] event.
+///
+/// # Safety
+///
+/// `node` must be a valid node handle and `addr` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_dial(node: *const Node, addr: *const c_char) -> i32 {
+    guard(|| {
+        let node = node.as_ref().ok_or(MWC_P2P_ERR_NULL)?;
+        let addr = c_str(addr)?.parse::<Multiaddr>().map_err(|_| MWC_P2P_ERR_INVALID)?;
+        node.dial(addr).map_err(|Closed| MWC_P2P_ERR_CLOSED)?;
+        Ok(MWC_P2P_OK)
+    })
+}
+
+/// Sends a request to the peer with the given base58 encoded peer ID,
+/// writing the ID of the request into `request_id`.
+///
+/// The outcome is reported with either an [`MWC_P2P_EVENT_RESPONSE`] or an
+/// [`MWC_P2P_EVENT_OUTBOUND_FAILURE`] event carrying the same request ID.
+///
+/// # Safety
+///
+/// `node` must be a valid node handle, `peer` a valid NUL-terminated string,
+/// `data` must point to `len` readable bytes (or be null if `len` is 0) and
+/// `request_id` must point to writable memory for a `u64`.
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_send_request(
+    node: *const Node,
+    peer: *const c_char,
+    data: *const u8,
+    len: usize,
+    request_id: *mut u64,
+) -> i32 {
+    guard(|| {
+        let node = node.as_ref().ok_or(MWC_P2P_ERR_NULL)?;
+        let request_id = request_id.as_mut().ok_or(MWC_P2P_ERR_NULL)?;
+        let peer = c_str(peer)?.parse::<PeerId>().map_err(|_| MWC_P2P_ERR_INVALID)?;
+        let data = bytes(data, len)?.to_vec();
+        *request_id = node.send_request(peer, data).map_err(|Closed| MWC_P2P_ERR_CLOSED)?;
+        Ok(MWC_P2P_OK)
+    })
+}
+
+/// Answers the inbound request with the given ID, as reported by an
+/// [`MWC_P2P_EVENT_INBOUND_REQUEST`] event.
+///
+/// # Safety
+///
+/// `node` must be a valid node handle and `data` must point to `len`
+/// readable bytes (or be null if `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_send_response(
+    node: *const Node,
+    request_id: u64,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    guard(|| {
+        let node = node.as_ref().ok_or(MWC_P2P_ERR_NULL)?;
+        let data = bytes(data, len)?.to_vec();
+        node.send_response(request_id, data).map_err(|Closed| MWC_P2P_ERR_CLOSED)?;
+        Ok(MWC_P2P_OK)
+    })
+}
+
+/// Writes the next queued event into `out`.
+///
+/// Returns [`MWC_P2P_OK`] if an event has been written, which must then be
+/// freed with [`mwc_p2p_event_free`], and [`MWC_P2P_EMPTY`] if no event is queued.
+///
+/// # Safety
+///
+/// `node` must be a valid node handle and `out` must point to writable memory
+/// for an [`MwcP2pEvent`].
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_poll_event(node: *const Node, out: *mut MwcP2pEvent) -> i32 {
+    guard(|| {
+        let node = node.as_ref().ok_or(MWC_P2P_ERR_NULL)?;
+        let out = out.as_mut().ok_or(MWC_P2P_ERR_NULL)?;
+        match node.poll_event() {
+            Some(event) => {
+                ptr::write(out, MwcP2pEvent::from(event));
+                Ok(MWC_P2P_OK)
+            }
+            None => Ok(MWC_P2P_EMPTY)
+        }
+    })
+}
+
+/// Frees the buffers of an event written by [`mwc_p2p_poll_event`],
+/// leaving them empty. Does nothing if `event` is null.
+///
+/// # Safety
+///
+/// `event` must have been written by [`mwc_p2p_poll_event`] and its buffers
+/// not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_event_free(event: *mut MwcP2pEvent) {
+    if let Some(event) = event.as_mut() {
+        event.peer.free();
+        event.address.free();
+        event.data.free();
+    }
+}
+
+/// Frees a buffer written by the library, leaving it empty.
+/// Does nothing if `buffer` is null.
+///
+/// # Safety
+///
+/// `buffer` must have been written by the library and not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mwc_p2p_buffer_free(buffer: *mut MwcP2pBuffer) {
+    if let Some(buffer) = buffer.as_mut() {
+        buffer.free();
+    }
+}
+
+/// Runs `f`, turning a panic into [`MWC_P2P_ERR_PANIC`] so
+/// that it does not unwind across the FFI boundary.
+fn guard(f: impl FnOnce() -> Result<i32, i32>) -> i32 {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(code)) | Ok(Err(code)) => code,
+        Err(_) => MWC_P2P_ERR_PANIC,
+    }
+}
+
+/// Converts a NUL-terminated C string into a `&str`.
+unsafe fn c_str<'a>(s: *const c_char) -> Result<&'a str, i32> {
+    if s.is_null() {
+        return Err(MWC_P2P_ERR_NULL)
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| MWC_P2P_ERR_INVALID)
+}
+
+/// Converts a pointer and length into a byte slice.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], i32> {
+    if len == 0 {
+        return Ok(&[])
+    }
+    if data.is_null() {
+        return Err(MWC_P2P_ERR_NULL)
+    }
+    Ok(slice::from_raw_parts(data, len))
+}
+
+/// Converts an [`MwcP2pConfig`] into a [`Config`], returning
+/// `None` if the configuration is invalid.
+unsafe fn config_from_c(config: &MwcP2pConfig) -> Option<Config> {
+    let keypair = if config.secret_key.is_null() {
+        identity::Keypair::generate_ed25519()
+    } else {
+        let mut secret = [0u8; 32];
+        secret.copy_from_slice(slice::from_raw_parts(config.secret_key, 32));
+        let secret = identity::ed25519::SecretKey::from_bytes(&mut secret).ok()?;
+        identity::Keypair::Ed25519(secret.into())
+    };
+    let protocol = c_str(config.protocol).ok()?.as_bytes().to_vec();
+    let mut cfg = Config::new(keypair, protocol);
+    if !config.listen_addr.is_null() {
+        cfg.listen_addr = Some(c_str(config.listen_addr).ok()?.parse().ok()?);
+    }
+    if config.request_timeout_secs > 0 {
+        cfg.request_timeout = Duration::from_secs(config.request_timeout_secs);
+    }
+    if config.max_message_size > 0 {
+        cfg.max_message_size = config.max_message_size;
+    }
+    Some(cfg)
+}
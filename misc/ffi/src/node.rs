@@ -0,0 +1,375 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! A libp2p node running on a background thread.
+//!
+//! The [`Node`] is the safe Rust API underlying the C ABI. It owns a
+//! [`Swarm`] with a [`RequestResponse`] behaviour exchanging opaque
+//! payloads, which is driven on a dedicated thread. Commands are passed
+//! to that thread over a channel and events are queued until they are
+//! retrieved with [`Node::poll_event`].
+
+use crate::codec::{BytesCodec, BytesProtocol};
+use futures::{channel::mpsc, executor, future::{self, Either}, prelude::*};
+use libp2p::{
+    core::{muxing::StreamMuxerBox, transport::Boxed, upgrade},
+    dns::DnsConfig,
+    identity,
+    mplex::MplexConfig,
+    noise::{self, NoiseConfig, X25519Spec},
+    request_response::{
+        InboundFailure,
+        OutboundFailure,
+        ProtocolSupport,
+        RequestId,
+        RequestResponse,
+        RequestResponseConfig,
+        RequestResponseEvent,
+        RequestResponseMessage,
+        ResponseChannel,
+    },
+    swarm::SwarmEvent,
+    tcp::TcpConfig,
+    yamux::YamuxConfig,
+    Multiaddr,
+    PeerId,
+    Swarm,
+    Transport,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    io,
+    iter,
+    sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}},
+    thread,
+    time::Duration,
+};
+
+/// The configuration of a [`Node`].
+#[derive(Clone)]
+pub struct Config {
+    /// The identity of the node.
+    pub keypair: identity::Keypair,
+    /// The address to listen on, if any.
+    pub listen_addr: Option<Multiaddr>,
+    /// The name of the request-response protocol.
+    pub protocol: Vec<u8>,
+    /// The timeout of outbound requests.
+    pub request_timeout: Duration,
+    /// The maximum size of a request or response in bytes.
+    pub max_message_size: usize,
+}
+
+impl Config {
+    /// Creates a new configuration for the given identity and protocol name
+    /// with default values for all other settings.
+    pub fn new(keypair: identity::Keypair, protocol: Vec<u8>) -> Self {
+        Config {
+            keypair,
+            listen_addr: None,
+            protocol,
+            request_timeout: Duration::from_secs(30),
+            max_message_size: 1024 * 1024,
+        }
+    }
+}
+
+/// An event reported by a [`Node`].
+#[derive(Debug)]
+pub enum Event {
+    /// The node listens on a new address.
+    NewListenAddr(Multiaddr),
+    /// A connection to a peer has been established.
+    Connected(PeerId),
+    /// The last connection to a peer has been closed.
+    Disconnected(PeerId),
+    /// Dialing an address failed.
+    DialFailure {
+        /// The address that could not be dialed.
+        address: Multiaddr,
+    },
+    /// A peer sent a request, to be answered with [`Node::send_response`].
+    InboundRequest {
+        peer: PeerId,
+        request_id: u64,
+        data: Vec<u8>,
+    },
+    /// A peer answered a request sent with [`Node::send_request`].
+    Response {
+        peer: PeerId,
+        request_id: u64,
+        data: Vec<u8>,
+    },
+    /// A request sent with [`Node::send_request`] failed.
+    OutboundFailure {
+        peer: PeerId,
+        request_id: u64,
+        error: OutboundFailure,
+    },
+    /// An inbound request could not be answered.
+    InboundFailure {
+        peer: PeerId,
+        request_id: u64,
+        error: InboundFailure,
+    },
+}
+
+/// The error returned when issuing a command to a [`Node`]
+/// whose background thread has terminated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("node has been shut down")
+    }
+}
+
+impl std::error::Error for Closed {}
+
+/// A command for the background thread of a [`Node`].
+enum Command {
+    Dial(Multiaddr),
+    SendRequest { request_id: u64, peer: PeerId, data: Vec<u8> },
+    SendResponse { request_id: u64, data: Vec<u8> },
+    Shutdown,
+}
+
+/// A libp2p node running on a background thread.
+///
+/// Dropping the `Node` shuts down the background thread and waits for it
+/// to terminate.
+pub struct Node {
+    local_peer_id: PeerId,
+    commands: mpsc::UnboundedSender<Command>,
+    events: Mutex<mpsc::UnboundedReceiver<Event>>,
+    next_request_id: Arc<AtomicU64>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Node {
+    /// Creates a new node and starts its background thread.
+    ///
+    /// The `notify` function is called on the background thread every time
+    /// an event has been queued for retrieval with [`Node::poll_event`].
+    pub fn new(config: Config, notify: Box<dyn Fn() + Send>) -> io::Result<Node> {
+        let local_peer_id = config.keypair.public().into_peer_id();
+        let transport = build_transport(&config.keypair)?;
+
+        let mut cfg = RequestResponseConfig::default();
+        cfg.set_request_timeout(config.request_timeout);
+        let behaviour = RequestResponse::new(
+            BytesCodec::new(config.max_message_size),
+            iter::once((BytesProtocol(config.protocol), ProtocolSupport::Full)),
+            cfg);
+
+        let mut swarm = Swarm::new(transport, behaviour, local_peer_id);
+        if let Some(addr) = config.listen_addr {
+            Swarm::listen_on(&mut swarm, addr)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        }
+
+        let (commands, commands_rx) = mpsc::unbounded();
+        let (events_tx, events) = mpsc::unbounded();
+        let next_request_id = Arc::new(AtomicU64::new(1));
+
+        let ids = next_request_id.clone();
+        let thread = thread::Builder::new()
+            .name("mwc-libp2p".into())
+            .spawn(move || executor::block_on(run(swarm, commands_rx, events_tx, ids, notify)))?;
+
+        Ok(Node {
+            local_peer_id,
+            commands,
+            events: Mutex::new(events),
+            next_request_id,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the `PeerId` of the node.
+    pub fn local_peer_id(&self) -> &PeerId {
+        &self.local_peer_id
+    }
+
+    /// Dials the given address.
+    ///
+    /// Failure to connect is reported with [`Event::DialFailure`].
+    pub fn dial(&self, addr: Multiaddr) -> Result<(), Closed> {
+        self.send(Command::Dial(addr))
+    }
+
+    /// Sends a request to a peer, returning the ID of the request.
+    ///
+    /// The outcome is reported with either [`Event::Response`] or
+    /// [`Event::OutboundFailure`] carrying the same request ID.
+    pub fn send_request(&self, peer: PeerId, data: Vec<u8>) -> Result<u64, Closed> {
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        self.send(Command::SendRequest { request_id, peer, data })?;
+        Ok(request_id)
+    }
+
+    /// Answers a request received with [`Event::InboundRequest`].
+    ///
+    /// Responses to unknown or expired requests are ignored.
+    pub fn send_response(&self, request_id: u64, data: Vec<u8>) -> Result<(), Closed> {
+        self.send(Command::SendResponse { request_id, data })
+    }
+
+    /// Returns the next queued event, if any.
+    pub fn poll_event(&self) -> Option<Event> {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        events.try_next().ok().flatten()
+    }
+
+    fn send(&self, command: Command) -> Result<(), Closed> {
+        self.commands.unbounded_send(command).map_err(|_| Closed)
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = self.commands.unbounded_send(Command::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Background thread of the node panicked.");
+            }
+        }
+    }
+}
+
+/// Builds a TCP transport with DNS resolution, noise encryption and
+/// yamux or mplex multiplexing.
+fn build_transport(keypair: &identity::Keypair)
+    -> io::Result<Boxed<(PeerId, StreamMuxerBox)>>
+{
+    let noise_keys = noise::Keypair::<X25519Spec>::new()
+        .into_authentic(keypair)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+    Ok(DnsConfig::new(TcpConfig::new().nodelay(true))?
+        .upgrade(upgrade::Version::V1)
+        .authenticate(NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(upgrade::SelectUpgrade::new(YamuxConfig::default(), MplexConfig::default()))
+        .timeout(Duration::from_secs(20))
+        .boxed())
+}
+
+/// Drives the swarm, executing commands and queueing events,
+/// until a [`Command::Shutdown`] is received.
+async fn run(
+    mut swarm: Swarm<RequestResponse<BytesCodec>>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    events: mpsc::UnboundedSender<Event>,
+    next_request_id: Arc<AtomicU64>,
+    notify: Box<dyn Fn() + Send>,
+) {
+    // Maps the IDs of outbound requests to the IDs handed out by `Node::send_request`.
+    let mut outbound: HashMap<RequestId, u64> = HashMap::new();
+    // Maps the IDs of inbound requests to the IDs reported with `Event::InboundRequest`.
+    let mut inbound: HashMap<RequestId, u64> = HashMap::new();
+    // The channels of inbound requests awaiting a response.
+    let mut channels: HashMap<u64, ResponseChannel<Vec<u8>>> = HashMap::new();
+
+    let emit = |event: Event| {
+        if events.unbounded_send(event).is_ok() {
+            notify()
+        }
+    };
+
+    loop {
+        let next = {
+            let event = swarm.next_event();
+            futures::pin_mut!(event);
+            match future::select(commands.next(), event).await {
+                Either::Left((command, _)) => Either::Left(command),
+                Either::Right((event, _)) => Either::Right(event),
+            }
+        };
+
+        match next {
+            Either::Left(None) | Either::Left(Some(Command::Shutdown)) => break,
+            Either::Left(Some(Command::Dial(address))) => {
+                if let Err(e) = Swarm::dial_addr(&mut swarm, address.clone()) {
+                    log::debug!("Failed to dial {}: {}", address, e);
+                    emit(Event::DialFailure { address })
+                }
+            }
+            Either::Left(Some(Command::SendRequest { request_id, peer, data })) => {
+                let id = swarm.send_request(&peer, data);
+                outbound.insert(id, request_id);
+            }
+            Either::Left(Some(Command::SendResponse { request_id, data })) => {
+                if let Some(channel) = channels.remove(&request_id) {
+                    // A failure to send the response is reported
+                    // with a `RequestResponseEvent::InboundFailure`.
+                    let _ = swarm.send_response(channel, data);
+                }
+            }
+            Either::Right(SwarmEvent::NewListenAddr(addr)) => emit(Event::NewListenAddr(addr)),
+            Either::Right(SwarmEvent::ConnectionEstablished { peer_id, num_established, .. }) => {
+                if num_established.get() == 1 {
+                    emit(Event::Connected(peer_id))
+                }
+            }
+            Either::Right(SwarmEvent::ConnectionClosed { peer_id, num_established, .. }) => {
+                if num_established == 0 {
+                    emit(Event::Disconnected(peer_id))
+                }
+            }
+            Either::Right(SwarmEvent::UnreachableAddr { address, .. })
+            | Either::Right(SwarmEvent::UnknownPeerUnreachableAddr { address, .. }) => {
+                emit(Event::DialFailure { address })
+            }
+            Either::Right(SwarmEvent::Behaviour(event)) => match event {
+                RequestResponseEvent::Message { peer, message } => match message {
+                    RequestResponseMessage::Request { request_id, request, channel } => {
+                        let id = next_request_id.fetch_add(1, Ordering::Relaxed);
+                        inbound.insert(request_id, id);
+                        channels.insert(id, channel);
+                        emit(Event::InboundRequest { peer, request_id: id, data: request })
+                    }
+                    RequestResponseMessage::Response { request_id, response } => {
+                        if let Some(id) = outbound.remove(&request_id) {
+                            emit(Event::Response { peer, request_id: id, data: response })
+                        }
+                    }
+                },
+                RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                    if let Some(id) = outbound.remove(&request_id) {
+                        emit(Event::OutboundFailure { peer, request_id: id, error })
+                    }
+                }
+                RequestResponseEvent::InboundFailure { peer, request_id, error } => {
+                    if let Some(id) = inbound.remove(&request_id) {
+                        channels.remove(&id);
+                        emit(Event::InboundFailure { peer, request_id: id, error })
+                    }
+                }
+                RequestResponseEvent::ResponseSent { request_id, .. } => {
+                    inbound.remove(&request_id);
+                }
+            },
+            Either::Right(_) => {}
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use mwc_libp2p_ffi::*;
+use std::{ffi::CString, ptr, slice, thread, time::{Duration, Instant}};
+
+fn new_node(protocol: &CString, listen_addr: Option<&CString>) -> *mut Node {
+    let config = MwcP2pConfig {
+        secret_key: ptr::null(),
+        listen_addr: listen_addr.map_or(ptr::null(), |a| a.as_ptr()),
+        protocol: protocol.as_ptr(),
+        request_timeout_secs: 0,
+        max_message_size: 0,
+    };
+    let node = unsafe { mwc_p2p_node_new(&config, None, ptr::null_mut()) };
+    assert!(!node.is_null());
+    node
+}
+
+/// The fields of an event, copied out of the buffers of the library.
+struct Received {
+    request_id: u64,
+    peer: Vec<u8>,
+    address: Vec<u8>,
+    data: Vec<u8>,
+}
+
+/// Polls the node until an event of the given kind arrives.
+fn wait_for(node: *mut Node, kind: u32) -> Received {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        let mut event = unsafe { std::mem::zeroed::<MwcP2pEvent>() };
+        if unsafe { mwc_p2p_poll_event(node, &mut event) } == MWC_P2P_OK {
+            let result = Received {
+                request_id: event.request_id,
+                peer: to_vec(&event.peer),
+                address: to_vec(&event.address),
+                data: to_vec(&event.data),
+            };
+            let found = event.kind == kind;
+            unsafe { mwc_p2p_event_free(&mut event) };
+            if found {
+                return result
+            }
+        } else {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    panic!("timed out waiting for event {}", kind)
+}
+
+fn to_vec(buffer: &MwcP2pBuffer) -> Vec<u8> {
+    if buffer.data.is_null() {
+        return Vec::new()
+    }
+    unsafe { slice::from_raw_parts(buffer.data, buffer.len).to_vec() }
+}
+
+#[test]
+fn request_response_roundtrip() {
+    let protocol = CString::new("/mwc/test/1.0.0").unwrap();
+    let listen_addr = CString::new("/ip4/127.0.0.1/tcp/0").unwrap();
+    let server = new_node(&protocol, Some(&listen_addr));
+    let client = new_node(&protocol, None);
+
+    let addr = CString::new(wait_for(server, MWC_P2P_EVENT_NEW_LISTEN_ADDR).address).unwrap();
+    assert_eq!(unsafe { mwc_p2p_dial(client, addr.as_ptr()) }, MWC_P2P_OK);
+
+    let mut peer = MwcP2pBuffer { data: ptr::null_mut(), len: 0 };
+    assert_eq!(unsafe { mwc_p2p_node_peer_id(server, &mut peer) }, MWC_P2P_OK);
+    let server_id = CString::new(to_vec(&peer)).unwrap();
+    unsafe { mwc_p2p_buffer_free(&mut peer) };
+    assert!(peer.data.is_null());
+    assert_eq!(wait_for(client, MWC_P2P_EVENT_CONNECTED).peer, server_id.as_bytes());
+
+    let mut request_id = 0;
+    let request = b"ping";
+    assert_eq!(unsafe {
+        mwc_p2p_send_request(client, server_id.as_ptr(), request.as_ptr(), request.len(), &mut request_id)
+    }, MWC_P2P_OK);
+
+    let inbound = wait_for(server, MWC_P2P_EVENT_INBOUND_REQUEST);
+    assert_eq!(inbound.data, request);
+    let response = b"pong";
+    assert_eq!(unsafe {
+        mwc_p2p_send_response(server, inbound.request_id, response.as_ptr(), response.len())
+    }, MWC_P2P_OK);
+
+    let received = wait_for(client, MWC_P2P_EVENT_RESPONSE);
+    assert_eq!(received.request_id, request_id);
+    assert_eq!(received.peer, server_id.as_bytes());
+    assert_eq!(received.data, response);
+
+    unsafe {
+        mwc_p2p_node_free(client);
+        mwc_p2p_node_free(server);
+    }
+}
+
+#[test]
+fn invalid_arguments() {
+    let protocol = CString::new("/mwc/test/1.0.0").unwrap();
+    let node = new_node(&protocol, None);
+    let addr = CString::new("not an address").unwrap();
+    assert_eq!(unsafe { mwc_p2p_dial(node, addr.as_ptr()) }, MWC_P2P_ERR_INVALID);
+    assert_eq!(unsafe { mwc_p2p_dial(node, ptr::null()) }, MWC_P2P_ERR_NULL);
+    assert_eq!(unsafe { mwc_p2p_dial(ptr::null(), addr.as_ptr()) }, MWC_P2P_ERR_NULL);
+    let mut event = unsafe { std::mem::zeroed::<MwcP2pEvent>() };
+    assert_eq!(unsafe { mwc_p2p_poll_event(node, &mut event) }, MWC_P2P_EMPTY);
+    unsafe { mwc_p2p_node_free(node) };
+}
@@ -0,0 +1,98 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use libp2p_identify::IdentifyEvent;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::registry::Registry;
+
+pub(crate) struct Metrics {
+    received: Counter,
+    sent: Counter,
+    pushed: Counter,
+    network_mismatch: Counter,
+    error: Counter,
+}
+
+impl Metrics {
+    pub(crate) fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("identify");
+
+        let received = Counter::default();
+        sub_registry.register(
+            "received",
+            "Number of identification infos received from remotes",
+            Box::new(received.clone()),
+        );
+
+        let sent = Counter::default();
+        sub_registry.register(
+            "sent",
+            "Number of identification infos sent to remotes on request",
+            Box::new(sent.clone()),
+        );
+
+        let pushed = Counter::default();
+        sub_registry.register(
+            "pushed",
+            "Number of identification infos pushed to remotes",
+            Box::new(pushed.clone()),
+        );
+
+        let network_mismatch = Counter::default();
+        sub_registry.register(
+            "network_mismatch",
+            "Number of remotes identified as belonging to another network",
+            Box::new(network_mismatch.clone()),
+        );
+
+        let error = Counter::default();
+        sub_registry.register(
+            "error",
+            "Number of failed identification exchanges",
+            Box::new(error.clone()),
+        );
+
+        Metrics { received, sent, pushed, network_mismatch, error }
+    }
+}
+
+impl super::Recorder<IdentifyEvent> for super::Metrics {
+    fn record(&self, event: &IdentifyEvent) {
+        let metrics = &self.identify;
+        match event {
+            IdentifyEvent::Received { .. } => {
+                metrics.received.inc();
+            }
+            IdentifyEvent::Sent { .. } => {
+                metrics.sent.inc();
+            }
+            IdentifyEvent::Pushed { .. } => {
+                metrics.pushed.inc();
+            }
+            IdentifyEvent::NetworkMismatch { .. } => {
+                metrics.network_mismatch.inc();
+            }
+            IdentifyEvent::Error { .. } => {
+                metrics.error.inc();
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use libp2p_kad::{KademliaEvent, QueryResult, QueryStats};
+use prometheus_client::encoding::text::Encode;
+use prometheus_client::metrics::{
+    counter::Counter,
+    family::Family,
+    histogram::{exponential_buckets, Histogram},
+};
+use prometheus_client::registry::{Registry, Unit};
+
+pub(crate) struct Metrics {
+    query_result: Family<QueryOutcomeLabels, Counter>,
+    query_result_num_requests: Family<QueryLabels, Histogram>,
+    query_result_num_success: Family<QueryLabels, Histogram>,
+    query_result_num_failure: Family<QueryLabels, Histogram>,
+    query_result_duration: Family<QueryLabels, Histogram>,
+    routing_updated: Counter,
+    peer_evicted: Counter,
+    unroutable_peer: Counter,
+    routable_peer: Counter,
+    pending_routable_peer: Counter,
+    bucket_emptied: Counter,
+}
+
+impl Metrics {
+    pub(crate) fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("kad");
+
+        let query_result = Family::default();
+        sub_registry.register(
+            "query_result",
+            "Number of finished queries by type and outcome",
+            Box::new(query_result.clone()),
+        );
+
+        let query_result_num_requests: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(1.0, 2.0, 10)));
+        sub_registry.register(
+            "query_result_num_requests",
+            "Number of requests sent by a query",
+            Box::new(query_result_num_requests.clone()),
+        );
+
+        let query_result_num_success: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(1.0, 2.0, 10)));
+        sub_registry.register(
+            "query_result_num_success",
+            "Number of successful requests of a query",
+            Box::new(query_result_num_success.clone()),
+        );
+
+        let query_result_num_failure: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(1.0, 2.0, 10)));
+        sub_registry.register(
+            "query_result_num_failure",
+            "Number of failed requests of a query",
+            Box::new(query_result_num_failure.clone()),
+        );
+
+        let query_result_duration: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.1, 2.0, 10)));
+        sub_registry.register_with_unit(
+            "query_result_duration",
+            "Duration of a query",
+            Unit::Seconds,
+            Box::new(query_result_duration.clone()),
+        );
+
+        let routing_updated = Counter::default();
+        sub_registry.register(
+            "routing_updated",
+            "Number of peers added to or updated in the routing table",
+            Box::new(routing_updated.clone()),
+        );
+
+        let peer_evicted = Counter::default();
+        sub_registry.register(
+            "peer_evicted",
+            "Number of peers evicted from the routing table",
+            Box::new(peer_evicted.clone()),
+        );
+
+        let unroutable_peer = Counter::default();
+        sub_registry.register(
+            "unroutable_peer",
+            "Number of connected peers without a known listen address",
+            Box::new(unroutable_peer.clone()),
+        );
+
+        let routable_peer = Counter::default();
+        sub_registry.register(
+            "routable_peer",
+            "Number of connected peers with a known listen address not added to the routing table",
+            Box::new(routable_peer.clone()),
+        );
+
+        let pending_routable_peer = Counter::default();
+        sub_registry.register(
+            "pending_routable_peer",
+            "Number of connected peers pending insertion into a full bucket of the routing table",
+            Box::new(pending_routable_peer.clone()),
+        );
+
+        let bucket_emptied = Counter::default();
+        sub_registry.register(
+            "bucket_emptied",
+            "Number of buckets of the routing table emptied through removals",
+            Box::new(bucket_emptied.clone()),
+        );
+
+        Metrics {
+            query_result,
+            query_result_num_requests,
+            query_result_num_success,
+            query_result_num_failure,
+            query_result_duration,
+            routing_updated,
+            peer_evicted,
+            unroutable_peer,
+            routable_peer,
+            pending_routable_peer,
+            bucket_emptied,
+        }
+    }
+
+    fn record_query(&self, result: &QueryResult, stats: &QueryStats) {
+        let (query, ok) = match result {
+            QueryResult::Bootstrap(r) => (QueryType::Bootstrap, r.is_ok()),
+            QueryResult::GetClosestPeers(r) => (QueryType::GetClosestPeers, r.is_ok()),
+            QueryResult::GetProviders(r) => (QueryType::GetProviders, r.is_ok()),
+            QueryResult::StartProviding(r) => (QueryType::StartProviding, r.is_ok()),
+            QueryResult::RepublishProvider(r) => (QueryType::RepublishProvider, r.is_ok()),
+            QueryResult::GetRecord(r) => (QueryType::GetRecord, r.is_ok()),
+            QueryResult::PutRecord(r) => (QueryType::PutRecord, r.is_ok()),
+            QueryResult::RepublishRecord(r) => (QueryType::RepublishRecord, r.is_ok()),
+        };
+        let outcome = if ok { Outcome::Ok } else { Outcome::Error };
+
+        self.query_result
+            .get_or_create(&QueryOutcomeLabels { query: query.clone(), outcome })
+            .inc();
+
+        let labels = QueryLabels { query };
+        self.query_result_num_requests
+            .get_or_create(&labels)
+            .observe(stats.num_requests().into());
+        self.query_result_num_success
+            .get_or_create(&labels)
+            .observe(stats.num_successes().into());
+        self.query_result_num_failure
+            .get_or_create(&labels)
+            .observe(stats.num_failures().into());
+        if let Some(duration) = stats.duration() {
+            self.query_result_duration
+                .get_or_create(&labels)
+                .observe(duration.as_secs_f64());
+        }
+    }
+}
+
+impl super::Recorder<KademliaEvent> for super::Metrics {
+    fn record(&self, event: &KademliaEvent) {
+        let metrics = &self.kad;
+        match event {
+            KademliaEvent::QueryResult { result, stats, .. } => {
+                metrics.record_query(result, stats);
+            }
+            KademliaEvent::RoutingUpdated { .. } => {
+                metrics.routing_updated.inc();
+            }
+            KademliaEvent::PeerEvicted { .. } => {
+                metrics.peer_evicted.inc();
+            }
+            KademliaEvent::UnroutablePeer { .. } => {
+                metrics.unroutable_peer.inc();
+            }
+            KademliaEvent::RoutablePeer { .. } => {
+                metrics.routable_peer.inc();
+            }
+            KademliaEvent::PendingRoutablePeer { .. } => {
+                metrics.pending_routable_peer.inc();
+            }
+            KademliaEvent::BucketEmptied { .. } => {
+                metrics.bucket_emptied.inc();
+            }
+            KademliaEvent::ProvidingStarted { .. } => {}
+        }
+    }
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+struct QueryLabels {
+    query: QueryType,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+struct QueryOutcomeLabels {
+    query: QueryType,
+    outcome: Outcome,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+enum QueryType {
+    Bootstrap,
+    GetClosestPeers,
+    GetProviders,
+    StartProviding,
+    RepublishProvider,
+    GetRecord,
+    PutRecord,
+    RepublishRecord,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+enum Outcome {
+    Ok,
+    Error,
+}
@@ -0,0 +1,112 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Metrics for the [`Swarm`] and the libp2p protocols, recorded into a
+//! [`prometheus_client`] [`Registry`].
+//!
+//! A single [`Metrics::new`] registers the metrics of the swarm and of
+//! every protocol enabled through the feature of the same name. Events are
+//! recorded with [`Recorder::record`], which [`Metrics`] implements for the
+//! [`SwarmEvent`]s and the events of each enabled protocol:
+//!
+//! ```ignore
+//! let mut registry = Registry::default();
+//! let metrics = Metrics::new(&mut registry);
+//!
+//! loop {
+//!     match swarm.next_event().await {
+//!         SwarmEvent::Behaviour(MyEvent::Ping(event)) => metrics.record(&event),
+//!         event => metrics.record(&event),
+//!     }
+//! }
+//! ```
+//!
+//! # Conventions
+//!
+//! All metrics are prefixed with `libp2p_` followed by the name of the
+//! protocol, e.g. `libp2p_kad_query_result_duration_seconds`. Labels are
+//! only ever derived from the kind of an event, e.g. the role of a
+//! connection or the type of a query, never from peer IDs, addresses or
+//! topics, so that the number of time series stays bounded.
+//!
+//! [`Swarm`]: libp2p_swarm::Swarm
+//! [`SwarmEvent`]: libp2p_swarm::SwarmEvent
+
+#[cfg(feature = "gossipsub")]
+mod gossipsub;
+#[cfg(feature = "identify")]
+mod identify;
+#[cfg(feature = "kad")]
+mod kad;
+#[cfg(feature = "ping")]
+mod ping;
+#[cfg(feature = "relay")]
+mod relay;
+#[cfg(feature = "request-response")]
+mod request_response;
+mod swarm;
+
+use prometheus_client::registry::Registry;
+
+/// The metrics of the swarm and of the enabled protocols.
+pub struct Metrics {
+    #[cfg(feature = "gossipsub")]
+    gossipsub: gossipsub::Metrics,
+    #[cfg(feature = "identify")]
+    identify: identify::Metrics,
+    #[cfg(feature = "kad")]
+    kad: kad::Metrics,
+    #[cfg(feature = "ping")]
+    ping: ping::Metrics,
+    #[cfg(feature = "relay")]
+    relay: relay::Metrics,
+    #[cfg(feature = "request-response")]
+    request_response: request_response::Metrics,
+    swarm: swarm::Metrics,
+}
+
+impl Metrics {
+    /// Creates the metrics and registers them with the given registry.
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("libp2p");
+        Metrics {
+            #[cfg(feature = "gossipsub")]
+            gossipsub: gossipsub::Metrics::new(sub_registry),
+            #[cfg(feature = "identify")]
+            identify: identify::Metrics::new(sub_registry),
+            #[cfg(feature = "kad")]
+            kad: kad::Metrics::new(sub_registry),
+            #[cfg(feature = "ping")]
+            ping: ping::Metrics::new(sub_registry),
+            #[cfg(feature = "relay")]
+            relay: relay::Metrics::new(sub_registry),
+            #[cfg(feature = "request-response")]
+            request_response: request_response::Metrics::new(sub_registry),
+            swarm: swarm::Metrics::new(sub_registry),
+        }
+    }
+}
+
+/// Recorder of the events of type `Event` into metrics.
+pub trait Recorder<Event> {
+    /// Records the given event.
+    fn record(&self, event: &Event);
+}
@@ -0,0 +1,97 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use libp2p_ping::{PingEvent, PingFailure, PingSuccess};
+use prometheus_client::encoding::text::Encode;
+use prometheus_client::metrics::{
+    counter::Counter,
+    family::Family,
+    histogram::{exponential_buckets, Histogram},
+};
+use prometheus_client::registry::{Registry, Unit};
+
+pub(crate) struct Metrics {
+    rtt: Histogram,
+    failure: Family<FailureLabels, Counter>,
+    pong_sent: Counter,
+}
+
+impl Metrics {
+    pub(crate) fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("ping");
+
+        let rtt = Histogram::new(exponential_buckets(0.001, 2.0, 12));
+        sub_registry.register_with_unit(
+            "rtt",
+            "Round-trip time of outbound pings",
+            Unit::Seconds,
+            Box::new(rtt.clone()),
+        );
+
+        let failure = Family::default();
+        sub_registry.register(
+            "failure",
+            "Number of failed outbound pings",
+            Box::new(failure.clone()),
+        );
+
+        let pong_sent = Counter::default();
+        sub_registry.register(
+            "pong_sent",
+            "Number of inbound pings answered",
+            Box::new(pong_sent.clone()),
+        );
+
+        Metrics { rtt, failure, pong_sent }
+    }
+}
+
+impl super::Recorder<PingEvent> for super::Metrics {
+    fn record(&self, event: &PingEvent) {
+        let metrics = &self.ping;
+        match &event.result {
+            Ok(PingSuccess::Pong) => {
+                metrics.pong_sent.inc();
+            }
+            Ok(PingSuccess::Ping { rtt }) => {
+                metrics.rtt.observe(rtt.as_secs_f64());
+            }
+            Err(failure) => {
+                let reason = match failure {
+                    PingFailure::Timeout => FailureReason::Timeout,
+                    PingFailure::Other { .. } => FailureReason::Other,
+                };
+                metrics.failure.get_or_create(&FailureLabels { reason }).inc();
+            }
+        }
+    }
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+struct FailureLabels {
+    reason: FailureReason,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+enum FailureReason {
+    Timeout,
+    Other,
+}
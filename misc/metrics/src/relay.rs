@@ -0,0 +1,114 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use libp2p_relay::{ClientEvent, RelayEvent};
+use prometheus_client::encoding::text::Encode;
+use prometheus_client::metrics::{counter::Counter, family::Family};
+use prometheus_client::registry::Registry;
+
+pub(crate) struct Metrics {
+    events: Family<EventLabels, Counter>,
+    client_events: Family<ClientEventLabels, Counter>,
+}
+
+impl Metrics {
+    pub(crate) fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("relay");
+
+        let events = Family::default();
+        sub_registry.register(
+            "events",
+            "Events emitted by the relay behaviour",
+            Box::new(events.clone()),
+        );
+
+        let client_events = Family::default();
+        sub_registry.register(
+            "client_events",
+            "Events emitted by the relay client behaviour",
+            Box::new(client_events.clone()),
+        );
+
+        Metrics { events, client_events }
+    }
+}
+
+impl super::Recorder<RelayEvent> for super::Metrics {
+    fn record(&self, event: &RelayEvent) {
+        let event = match event {
+            RelayEvent::ReservationReqAccepted { .. } => EventType::ReservationReqAccepted,
+            RelayEvent::ReservationReqAcceptFailed { .. } => EventType::ReservationReqAcceptFailed,
+            RelayEvent::ReservationReqDenied { .. } => EventType::ReservationReqDenied,
+            RelayEvent::ReservationTimedOut { .. } => EventType::ReservationTimedOut,
+            RelayEvent::CircuitReqDenied { .. } => EventType::CircuitReqDenied,
+            RelayEvent::CircuitReqAccepted { .. } => EventType::CircuitReqAccepted,
+            RelayEvent::CircuitReqAcceptFailed { .. } => EventType::CircuitReqAcceptFailed,
+            RelayEvent::CircuitClosed { .. } => EventType::CircuitClosed,
+        };
+        self.relay.events.get_or_create(&EventLabels { event }).inc();
+    }
+}
+
+impl super::Recorder<ClientEvent> for super::Metrics {
+    fn record(&self, event: &ClientEvent) {
+        let event = match event {
+            ClientEvent::ReservationReqAccepted { .. } => ClientEventType::ReservationReqAccepted,
+            ClientEvent::ReservationReqFailed { .. } => ClientEventType::ReservationReqFailed,
+            ClientEvent::OutboundCircuitEstablished { .. } => ClientEventType::OutboundCircuitEstablished,
+            ClientEvent::OutboundCircuitReqFailed { .. } => ClientEventType::OutboundCircuitReqFailed,
+            ClientEvent::InboundCircuitEstablished { .. } => ClientEventType::InboundCircuitEstablished,
+            ClientEvent::InboundCircuitReqDenied { .. } => ClientEventType::InboundCircuitReqDenied,
+        };
+        self.relay.client_events.get_or_create(&ClientEventLabels { event }).inc();
+    }
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+struct EventLabels {
+    event: EventType,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+enum EventType {
+    ReservationReqAccepted,
+    ReservationReqAcceptFailed,
+    ReservationReqDenied,
+    ReservationTimedOut,
+    CircuitReqDenied,
+    CircuitReqAccepted,
+    CircuitReqAcceptFailed,
+    CircuitClosed,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+struct ClientEventLabels {
+    event: ClientEventType,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+enum ClientEventType {
+    ReservationReqAccepted,
+    ReservationReqFailed,
+    OutboundCircuitEstablished,
+    OutboundCircuitReqFailed,
+    InboundCircuitEstablished,
+    InboundCircuitReqDenied,
+}
@@ -0,0 +1,136 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use libp2p_request_response::{
+    InboundFailure,
+    OutboundFailure,
+    RequestResponseEvent,
+    RequestResponseMessage,
+};
+use prometheus_client::encoding::text::Encode;
+use prometheus_client::metrics::{counter::Counter, family::Family};
+use prometheus_client::registry::Registry;
+
+pub(crate) struct Metrics {
+    messages: Family<MessageLabels, Counter>,
+    outbound_failure: Family<FailureLabels, Counter>,
+    inbound_failure: Family<FailureLabels, Counter>,
+    response_sent: Counter,
+}
+
+impl Metrics {
+    pub(crate) fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("request_response");
+
+        let messages = Family::default();
+        sub_registry.register(
+            "messages",
+            "Number of requests and responses received",
+            Box::new(messages.clone()),
+        );
+
+        let outbound_failure = Family::default();
+        sub_registry.register(
+            "outbound_failure",
+            "Number of failed outbound requests",
+            Box::new(outbound_failure.clone()),
+        );
+
+        let inbound_failure = Family::default();
+        sub_registry.register(
+            "inbound_failure",
+            "Number of failed inbound requests",
+            Box::new(inbound_failure.clone()),
+        );
+
+        let response_sent = Counter::default();
+        sub_registry.register(
+            "response_sent",
+            "Number of responses sent",
+            Box::new(response_sent.clone()),
+        );
+
+        Metrics { messages, outbound_failure, inbound_failure, response_sent }
+    }
+}
+
+impl<TRequest, TResponse, TChannelResponse>
+    super::Recorder<RequestResponseEvent<TRequest, TResponse, TChannelResponse>> for super::Metrics
+{
+    fn record(&self, event: &RequestResponseEvent<TRequest, TResponse, TChannelResponse>) {
+        let metrics = &self.request_response;
+        match event {
+            RequestResponseEvent::Message { message, .. } => {
+                let kind = match message {
+                    RequestResponseMessage::Request { .. } => MessageKind::Request,
+                    RequestResponseMessage::Response { .. } => MessageKind::Response,
+                };
+                metrics.messages.get_or_create(&MessageLabels { kind }).inc();
+            }
+            RequestResponseEvent::OutboundFailure { error, .. } => {
+                let reason = match error {
+                    OutboundFailure::DialFailure => FailureReason::DialFailure,
+                    OutboundFailure::Timeout => FailureReason::Timeout,
+                    OutboundFailure::ConnectionClosed => FailureReason::ConnectionClosed,
+                    OutboundFailure::UnsupportedProtocols => FailureReason::UnsupportedProtocols,
+                };
+                metrics.outbound_failure.get_or_create(&FailureLabels { reason }).inc();
+            }
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                let reason = match error {
+                    InboundFailure::Timeout => FailureReason::Timeout,
+                    InboundFailure::ConnectionClosed => FailureReason::ConnectionClosed,
+                    InboundFailure::UnsupportedProtocols => FailureReason::UnsupportedProtocols,
+                    InboundFailure::ResponseOmission => FailureReason::ResponseOmission,
+                };
+                metrics.inbound_failure.get_or_create(&FailureLabels { reason }).inc();
+            }
+            RequestResponseEvent::ResponseSent { .. } => {
+                metrics.response_sent.inc();
+            }
+        }
+    }
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+struct MessageLabels {
+    kind: MessageKind,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+enum MessageKind {
+    Request,
+    Response,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+struct FailureLabels {
+    reason: FailureReason,
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+enum FailureReason {
+    DialFailure,
+    Timeout,
+    ConnectionClosed,
+    UnsupportedProtocols,
+    ResponseOmission,
+}
@@ -0,0 +1,247 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use libp2p_core::ConnectedPoint;
+use libp2p_swarm::SwarmEvent;
+use prometheus_client::encoding::text::Encode;
+use prometheus_client::metrics::{counter::Counter, family::Family};
+use prometheus_client::registry::Registry;
+
+pub(crate) struct Metrics {
+    connections_established: Family<ConnectionLabels, Counter>,
+    connections_closed: Family<ConnectionLabels, Counter>,
+    connections_incoming: Counter,
+    connections_incoming_error: Counter,
+    dial_attempt: Counter,
+    unreachable_addr: Counter,
+    banned_peer: Counter,
+    new_listen_addr: Counter,
+    expired_listen_addr: Counter,
+    listener_closed: Counter,
+    listener_error: Counter,
+    external_addr_confirmed: Counter,
+    external_addr_expired: Counter,
+}
+
+impl Metrics {
+    pub(crate) fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("swarm");
+
+        let connections_established = Family::default();
+        sub_registry.register(
+            "connections_established",
+            "Number of connections established",
+            Box::new(connections_established.clone()),
+        );
+
+        let connections_closed = Family::default();
+        sub_registry.register(
+            "connections_closed",
+            "Number of connections closed",
+            Box::new(connections_closed.clone()),
+        );
+
+        let connections_incoming = Counter::default();
+        sub_registry.register(
+            "connections_incoming",
+            "Number of incoming connections being upgraded",
+            Box::new(connections_incoming.clone()),
+        );
+
+        let connections_incoming_error = Counter::default();
+        sub_registry.register(
+            "connections_incoming_error",
+            "Number of incoming connections failing to be upgraded",
+            Box::new(connections_incoming_error.clone()),
+        );
+
+        let dial_attempt = Counter::default();
+        sub_registry.register(
+            "dial_attempt",
+            "Number of attempts to dial a peer",
+            Box::new(dial_attempt.clone()),
+        );
+
+        let unreachable_addr = Counter::default();
+        sub_registry.register(
+            "unreachable_addr",
+            "Number of failed attempts to reach an address",
+            Box::new(unreachable_addr.clone()),
+        );
+
+        let banned_peer = Counter::default();
+        sub_registry.register(
+            "banned_peer",
+            "Number of connections to banned peers that were closed",
+            Box::new(banned_peer.clone()),
+        );
+
+        let new_listen_addr = Counter::default();
+        sub_registry.register(
+            "new_listen_addr",
+            "Number of new listen addresses",
+            Box::new(new_listen_addr.clone()),
+        );
+
+        let expired_listen_addr = Counter::default();
+        sub_registry.register(
+            "expired_listen_addr",
+            "Number of expired listen addresses",
+            Box::new(expired_listen_addr.clone()),
+        );
+
+        let listener_closed = Counter::default();
+        sub_registry.register(
+            "listener_closed",
+            "Number of listeners closed",
+            Box::new(listener_closed.clone()),
+        );
+
+        let listener_error = Counter::default();
+        sub_registry.register(
+            "listener_error",
+            "Number of non-fatal listener errors",
+            Box::new(listener_error.clone()),
+        );
+
+        let external_addr_confirmed = Counter::default();
+        sub_registry.register(
+            "external_addr_confirmed",
+            "Number of external addresses confirmed",
+            Box::new(external_addr_confirmed.clone()),
+        );
+
+        let external_addr_expired = Counter::default();
+        sub_registry.register(
+            "external_addr_expired",
+            "Number of confirmed external addresses expired",
+            Box::new(external_addr_expired.clone()),
+        );
+
+        Metrics {
+            connections_established,
+            connections_closed,
+            connections_incoming,
+            connections_incoming_error,
+            dial_attempt,
+            unreachable_addr,
+            banned_peer,
+            new_listen_addr,
+            expired_listen_addr,
+            listener_closed,
+            listener_error,
+            external_addr_confirmed,
+            external_addr_expired,
+        }
+    }
+}
+
+impl<TBvEv, THandleErr> super::Recorder<SwarmEvent<TBvEv, THandleErr>> for super::Metrics {
+    fn record(&self, event: &SwarmEvent<TBvEv, THandleErr>) {
+        let metrics = &self.swarm;
+        match event {
+            SwarmEvent::Behaviour(_) => {}
+            SwarmEvent::ConnectionEstablished { endpoint, .. } => {
+                metrics.connections_established.get_or_create(&endpoint.into()).inc();
+            }
+            SwarmEvent::ConnectionClosed { endpoint, .. } => {
+                metrics.connections_closed.get_or_create(&endpoint.into()).inc();
+            }
+            SwarmEvent::IncomingConnection { .. } => {
+                metrics.connections_incoming.inc();
+            }
+            SwarmEvent::IncomingConnectionError { .. } => {
+                metrics.connections_incoming_error.inc();
+            }
+            SwarmEvent::BannedPeer { .. } => {
+                metrics.banned_peer.inc();
+            }
+            SwarmEvent::UnreachableAddr { .. } | SwarmEvent::UnknownPeerUnreachableAddr { .. } => {
+                metrics.unreachable_addr.inc();
+            }
+            SwarmEvent::NewListenAddr(_) => {
+                metrics.new_listen_addr.inc();
+            }
+            SwarmEvent::ExpiredListenAddr(_) => {
+                metrics.expired_listen_addr.inc();
+            }
+            SwarmEvent::ListenerClosed { .. } => {
+                metrics.listener_closed.inc();
+            }
+            SwarmEvent::ListenerError { .. } => {
+                metrics.listener_error.inc();
+            }
+            SwarmEvent::Dialing(_) => {
+                metrics.dial_attempt.inc();
+            }
+            SwarmEvent::ExternalAddrConfirmed(_) => {
+                metrics.external_addr_confirmed.inc();
+            }
+            SwarmEvent::ExternalAddrExpired(_) => {
+                metrics.external_addr_expired.inc();
+            }
+        }
+    }
+}
+
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+struct ConnectionLabels {
+    role: Role,
+}
+
+/// The role of the local node on a connection.
+#[derive(Encode, Hash, Clone, Eq, PartialEq)]
+enum Role {
+    Dialer,
+    Listener,
+}
+
+impl From<&ConnectedPoint> for ConnectionLabels {
+    fn from(endpoint: &ConnectedPoint) -> Self {
+        let role = match endpoint {
+            ConnectedPoint::Dialer { .. } => Role::Dialer,
+            ConnectedPoint::Listener { .. } => Role::Listener,
+        };
+        ConnectionLabels { role }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Metrics, Recorder};
+    use libp2p_core::PeerId;
+    use libp2p_swarm::SwarmEvent;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    #[test]
+    fn records_swarm_events() {
+        let mut registry = Registry::default();
+        let metrics = Metrics::new(&mut registry);
+
+        metrics.record(&SwarmEvent::<(), std::io::Error>::Dialing(PeerId::random()));
+
+        let mut buf = Vec::new();
+        encode(&mut buf, &registry).unwrap();
+        let encoded = String::from_utf8(buf).unwrap();
+        assert!(encoded.contains("libp2p_swarm_dial_attempt_total 1"), "{}", encoded);
+    }
+}
@@ -2,7 +2,7 @@
 use arrayref::array_ref;
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use crate::{Result, Error};
-use data_encoding::BASE32;
+use data_encoding::{BASE32, BASE64URL_NOPAD};
 use multihash::Multihash;
 use std::{
     borrow::Cow,
@@ -15,6 +15,7 @@ use std::{
 use unsigned_varint::{encode, decode};
 use crate::onion_addr::Onion3Addr;
 
+const CERTHASH: u32 = 466;
 const DCCP: u32 = 33;
 const DNS: u32 = 53;
 const DNS4: u32 = 54;
@@ -39,6 +40,7 @@ const UDP: u32 = 273;
 const UDT: u32 = 301;
 const UNIX: u32 = 400;
 const UTP: u32 = 302;
+const WEBRTC_DIRECT: u32 = 280;
 const WS: u32 = 477;
 const WS_WITH_PATH: u32 = 4770;         // Note: not standard
 const WSS: u32 = 478;
@@ -65,6 +67,9 @@ const PATH_SEGMENT_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::
 /// happen separately.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Protocol<'a> {
+    /// The multihash of a TLS or DTLS certificate, e.g. the certificate
+    /// used by the remote of a `WebRtcDirect` connection.
+    Certhash(Multihash),
     Dccp(u16),
     Dns(Cow<'a, str>),
     Dns4(Cow<'a, str>),
@@ -90,6 +95,7 @@ pub enum Protocol<'a> {
     Udt,
     Unix(Cow<'a, str>),
     Utp,
+    WebRtcDirect,
     Ws(Cow<'a, str>),
     Wss(Cow<'a, str>),
 }
@@ -118,6 +124,10 @@ impl<'a> Protocol<'a> {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Udp(s.parse()?))
             }
+            "certhash" => {
+                let s = iter.next().ok_or(Error::InvalidProtocolString)?;
+                Ok(Protocol::Certhash(read_multibase_multihash(s)?))
+            }
             "dccp" => {
                 let s = iter.next().ok_or(Error::InvalidProtocolString)?;
                 Ok(Protocol::Dccp(s.parse()?))
@@ -170,6 +180,7 @@ impl<'a> Protocol<'a> {
                     .and_then(|s| read_onion3(&s.to_uppercase()))
                     .map(|(a, p)| Protocol::Onion3((a, p).into())),
             "quic" => Ok(Protocol::Quic),
+            "webrtc-direct" => Ok(Protocol::WebRtcDirect),
             "ws" => Ok(Protocol::Ws(Cow::Borrowed("/"))),
             "wss" => Ok(Protocol::Wss(Cow::Borrowed("/"))),
             "x-parity-ws" => {
@@ -205,6 +216,11 @@ impl<'a> Protocol<'a> {
         }
         let (id, input) = decode::u32(input)?;
         match id {
+            CERTHASH => {
+                let (n, input) = decode::usize(input)?;
+                let (data, rest) = split_at(n, input)?;
+                Ok((Protocol::Certhash(Multihash::from_bytes(data)?), rest))
+            }
             DCCP => {
                 let (data, rest) = split_at(2, input)?;
                 let mut rdr = Cursor::new(data);
@@ -308,6 +324,7 @@ impl<'a> Protocol<'a> {
                 Ok((Protocol::Unix(Cow::Borrowed(str::from_utf8(data)?)), rest))
             }
             UTP => Ok((Protocol::Utp, input)),
+            WEBRTC_DIRECT => Ok((Protocol::WebRtcDirect, input)),
             WS => Ok((Protocol::Ws(Cow::Borrowed("/")), input)),
             WS_WITH_PATH => {
                 let (n, input) = decode::usize(input)?;
@@ -391,6 +408,12 @@ impl<'a> Protocol<'a> {
                 w.write_all(encode::usize(bytes.len(), &mut encode::usize_buffer()))?;
                 w.write_all(&bytes)?
             }
+            Protocol::Certhash(multihash) => {
+                w.write_all(encode::u32(CERTHASH, &mut buf))?;
+                let bytes = multihash.to_bytes();
+                w.write_all(encode::usize(bytes.len(), &mut encode::usize_buffer()))?;
+                w.write_all(&bytes)?
+            }
             Protocol::Onion(addr, port) => {
                 w.write_all(encode::u32(ONION, &mut buf))?;
                 w.write_all(addr.as_ref())?;
@@ -404,6 +427,7 @@ impl<'a> Protocol<'a> {
             Protocol::Quic => w.write_all(encode::u32(QUIC, &mut buf))?,
             Protocol::Utp => w.write_all(encode::u32(UTP, &mut buf))?,
             Protocol::Udt => w.write_all(encode::u32(UDT, &mut buf))?,
+            Protocol::WebRtcDirect => w.write_all(encode::u32(WEBRTC_DIRECT, &mut buf))?,
             Protocol::Http => w.write_all(encode::u32(HTTP, &mut buf))?,
             Protocol::Https => w.write_all(encode::u32(HTTPS, &mut buf))?,
             Protocol::Ws(ref s) if s == "/" => w.write_all(encode::u32(WS, &mut buf))?,
@@ -436,6 +460,7 @@ impl<'a> Protocol<'a> {
     pub fn acquire<'b>(self) -> Protocol<'b> {
         use self::Protocol::*;
         match self {
+            Certhash(a) => Certhash(a),
             Dccp(a) => Dccp(a),
             Dns(cow) => Dns(Cow::Owned(cow.into_owned())),
             Dns4(cow) => Dns4(Cow::Owned(cow.into_owned())),
@@ -460,6 +485,7 @@ impl<'a> Protocol<'a> {
             Udt => Udt,
             Unix(cow) => Unix(Cow::Owned(cow.into_owned())),
             Utp => Utp,
+            WebRtcDirect => WebRtcDirect,
             Ws(cow) => Ws(Cow::Owned(cow.into_owned())),
             Wss(cow) => Wss(Cow::Owned(cow.into_owned())),
         }
@@ -470,6 +496,7 @@ impl<'a> fmt::Display for Protocol<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use self::Protocol::*;
         match self {
+            Certhash(c) => write!(f, "/certhash/u{}", BASE64URL_NOPAD.encode(&c.to_bytes())),
             Dccp(port) => write!(f, "/dccp/{}", port),
             Dns(s) => write!(f, "/dns/{}", s),
             Dns4(s) => write!(f, "/dns4/{}", s),
@@ -500,6 +527,7 @@ impl<'a> fmt::Display for Protocol<'a> {
             Udt => f.write_str("/udt"),
             Unix(s) => write!(f, "/unix/{}", s),
             Utp => f.write_str("/utp"),
+            WebRtcDirect => f.write_str("/webrtc-direct"),
             Ws(ref s) if s == "/" => f.write_str("/ws"),
             Ws(s) => {
                 let encoded = percent_encoding::percent_encode(s.as_bytes(), PATH_SEGMENT_ENCODE_SET);
@@ -584,3 +612,17 @@ read_onion_impl!(read_onion, 10, 16);
 //
 // Format: <base-32 address> ":" <port number>
 read_onion_impl!(read_onion3, 35, 56);
+
+// Parse a multibase encoded multihash.
+//
+// Only the base64url (`u`) and base58btc (`z`) encodings are supported.
+fn read_multibase_multihash(s: &str) -> Result<Multihash> {
+    let mut chars = s.chars();
+    let decoded = match chars.next() {
+        Some('u') => BASE64URL_NOPAD.decode(chars.as_str().as_bytes())
+            .map_err(|_| Error::InvalidMultiaddr)?,
+        Some('z') => bs58::decode(chars.as_str()).into_vec()?,
+        _ => return Err(Error::InvalidMultiaddr)
+    };
+    Ok(Multihash::from_bytes(&decoded)?)
+}
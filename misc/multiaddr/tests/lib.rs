@@ -76,7 +76,7 @@ struct Proto(Protocol<'static>);
 impl Arbitrary for Proto {
     fn arbitrary<G: Gen>(g: &mut G) -> Self {
         use Protocol::*;
-        match g.gen_range(0, 25) { // TODO: Add Protocol::Quic
+        match g.gen_range(0, 27) { // TODO: Add Protocol::Quic
              0 => Proto(Dccp(g.gen())),
              1 => Proto(Dns(Cow::Owned(SubString::arbitrary(g).0))),
              2 => Proto(Dns4(Cow::Owned(SubString::arbitrary(g).0))),
@@ -111,6 +111,8 @@ impl Arbitrary for Proto {
                 g.fill_bytes(&mut a);
                 Proto(Onion3((a, g.gen_range(1, std::u16::MAX)).into()))
             },
+            25 => Proto(WebRtcDirect),
+            26 => Proto(Certhash(multihash("QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"))),
              _ => panic!("outside range")
         }
     }
@@ -235,6 +237,11 @@ fn construct_success() {
         "3819736A632D312E626F6F7473747261702E6C69627032702E696F0604D2A50322122006B3608AA000274049EB28AD8E793A26FF6FAB281A7D3BD77CD18EB745DFAABB",
         vec![Dnsaddr(Cow::Borrowed("sjc-1.bootstrap.libp2p.io")), Tcp(1234), P2p(multihash("QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN"))]
     );
+    ma_valid(
+        "/ip4/127.0.0.1/udp/1234/webrtc-direct/certhash/uEiCDdrDFbM9JTSRxXg4eNjqdPT1SYHpGQrnrXjQmrBHrPQ",
+        "047F000001910204D29802D2032212208376B0C56CCF494D24715E0E1E363A9D3D3D52607A4642B9EB5E3426AC11EB3D",
+        vec![Ip4(local.clone()), Udp(1234), WebRtcDirect, Certhash(Multihash::from_bytes(&HEXUPPER.decode(b"12208376B0C56CCF494D24715E0E1E363A9D3D3D52607A4642B9EB5E3426AC11EB3D").unwrap()).unwrap())]
+    );
 }
 
 #[test]
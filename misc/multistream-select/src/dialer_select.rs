@@ -206,7 +206,7 @@ where
                     if let Err(err) = Pin::new(&mut io).start_send(Message::Protocol(p.clone())) {
                         return Poll::Ready(Err(From::from(err)));
                     }
-                    log::debug!("Dialer: Proposed protocol: {}", p);
+                    tracing::debug!("Dialer: Proposed protocol: {}", p);
 
                     if this.protocols.peek().is_some() {
                         *this.state = SeqState::FlushProtocol { io, protocol }
@@ -218,7 +218,7 @@ where
                             // the dialer supports for this negotiation. Notably,
                             // the dialer expects a regular `V1` response.
                             Version::V1Lazy => {
-                                log::debug!("Dialer: Expecting proposed protocol: {}", p);
+                                tracing::debug!("Dialer: Expecting proposed protocol: {}", p);
                                 let hl = HeaderLine::from(Version::V1Lazy);
                                 let io = Negotiated::expecting(io.into_reader(), p, Some(hl));
                                 return Poll::Ready(Ok((protocol, io)))
@@ -255,12 +255,12 @@ where
                             *this.state = SeqState::AwaitProtocol { io, protocol };
                         }
                         Message::Protocol(ref p) if p.as_ref() == protocol.as_ref() => {
-                            log::debug!("Dialer: Received confirmation for protocol: {}", p);
+                            tracing::debug!("Dialer: Received confirmation for protocol: {}", p);
                             let io = Negotiated::completed(io.into_inner());
                             return Poll::Ready(Ok((protocol, io)));
                         }
                         Message::NotAvailable => {
-                            log::debug!("Dialer: Received rejection of protocol: {}",
+                            tracing::debug!("Dialer: Received rejection of protocol: {}",
                                 String::from_utf8_lossy(protocol.as_ref()));
                             let protocol = this.protocols.next().ok_or(NegotiationError::Failed)?;
                             *this.state = SeqState::SendProtocol { io, protocol }
@@ -348,7 +348,7 @@ where
                         return Poll::Ready(Err(From::from(err)));
                     }
 
-                    log::debug!("Dialer: Requested supported protocols.");
+                    tracing::debug!("Dialer: Requested supported protocols.");
                     *this.state = ParState::Flush { io }
                 }
 
@@ -384,7 +384,7 @@ where
                                 .find(|p| supported.iter().any(|s|
                                     s.as_ref() == p.as_ref()))
                                 .ok_or(NegotiationError::Failed)?;
-                            log::debug!("Dialer: Found supported protocol: {}",
+                            tracing::debug!("Dialer: Found supported protocol: {}",
                                 String::from_utf8_lossy(protocol.as_ref()));
                             *this.state = ParState::SendProtocol { io, protocol };
                         }
@@ -406,7 +406,7 @@ where
                         return Poll::Ready(Err(From::from(err)));
                     }
 
-                    log::debug!("Dialer: Expecting proposed protocol: {}", p);
+                    tracing::debug!("Dialer: Expecting proposed protocol: {}", p);
                     let io = Negotiated::expecting(io.into_reader(), p, None);
 
                     return Poll::Ready(Ok((protocol, io)))
@@ -48,7 +48,7 @@ where
         match Protocol::try_from(n.as_ref()) {
             Ok(p) => Some((n, p)),
             Err(e) => {
-                log::warn!("Listener: Ignoring invalid protocol: {} due to {}",
+                tracing::warn!("Listener: Ignoring invalid protocol: {} due to {}",
                       String::from_utf8_lossy(n.as_ref()), e);
                 None
             }
@@ -183,13 +183,13 @@ where
                                 // the dialer also raises `NegotiationError::Failed` when finally
                                 // reading the `N/A` response.
                                 if let ProtocolError::InvalidMessage = &err {
-                                    log::trace!("Listener: Negotiation failed with invalid \
+                                    tracing::trace!("Listener: Negotiation failed with invalid \
                                         message after protocol rejection.");
                                     return Poll::Ready(Err(NegotiationError::Failed))
                                 }
                                 if let ProtocolError::IoError(e) = &err {
                                     if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                                        log::trace!("Listener: Negotiation failed with EOF \
+                                        tracing::trace!("Listener: Negotiation failed with EOF \
                                             after protocol rejection.");
                                         return Poll::Ready(Err(NegotiationError::Failed))
                                     }
@@ -216,10 +216,10 @@ where
                             });
 
                             let message = if protocol.is_some() {
-                                log::debug!("Listener: confirming protocol: {}", p);
+                                tracing::debug!("Listener: confirming protocol: {}", p);
                                 Message::Protocol(p.clone())
                             } else {
-                                log::debug!("Listener: rejecting protocol: {}",
+                                tracing::debug!("Listener: rejecting protocol: {}",
                                     String::from_utf8_lossy(p.as_ref()));
                                 Message::NotAvailable
                             };
@@ -264,7 +264,7 @@ where
                             // Otherwise expect to receive another message.
                             match protocol {
                                 Some(protocol) => {
-                                    log::debug!("Listener: sent confirmed protocol: {}",
+                                    tracing::debug!("Listener: sent confirmed protocol: {}",
                                         String::from_utf8_lossy(protocol.as_ref()));
                                     let io = Negotiated::completed(io.into_inner());
                                     return Poll::Ready(Ok((protocol, io)))
@@ -364,7 +364,7 @@ where
         return Poll::Ready(None)
     };
 
-    log::trace!("Received message: {:?}", msg);
+    tracing::trace!("Received message: {:?}", msg);
 
     Poll::Ready(Some(Ok(msg)))
 }
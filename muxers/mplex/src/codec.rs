@@ -237,11 +237,13 @@ impl Decoder for Codec {
     }
 }
 
-impl Encoder for Codec {
-    type Item = Frame<LocalStreamId>;
-    type Error = io::Error;
-
-    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+impl Codec {
+    /// Encodes the header of a frame, i.e. the header and the length of
+    /// the frame data, into `dst`, returning the frame data.
+    ///
+    /// The frame data is thus not copied and can be written separately,
+    /// e.g. with a vectored write.
+    pub(crate) fn encode_header(item: Frame<LocalStreamId>, dst: &mut BytesMut) -> io::Result<Bytes> {
         let (header, data) = match item {
             Frame::Open { stream_id } => {
                 (u64::from(stream_id.num) << 3, Bytes::new())
@@ -277,9 +279,20 @@ impl Encoder for Codec {
             return Err(io::Error::new(io::ErrorKind::InvalidData, "data size exceed maximum"));
         }
 
-        dst.reserve(header_bytes.len() + data_len_bytes.len() + data_len);
+        dst.reserve(header_bytes.len() + data_len_bytes.len());
         dst.put(header_bytes);
         dst.put(data_len_bytes);
+        Ok(data)
+    }
+}
+
+impl Encoder for Codec {
+    type Item = Frame<LocalStreamId>;
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let data = Codec::encode_header(item, dst)?;
+        dst.reserve(data.len());
         dst.put(data);
         Ok(())
     }
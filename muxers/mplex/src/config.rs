@@ -19,7 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::codec::MAX_FRAME_SIZE;
-use std::cmp;
+use std::{cmp, time::Duration};
 
 /// Configuration for the multiplexer.
 #[derive(Debug, Clone)]
@@ -33,6 +33,16 @@ pub struct MplexConfig {
     /// When sending data, split it into frames whose maximum size is this value
     /// (max 1MByte, as per the Mplex spec).
     pub(crate) split_send_size: usize,
+    /// Maximum number of inbound substreams kept pending when the
+    /// substream limit is reached.
+    pub(crate) max_pending_inbound: usize,
+    /// Maximum duration an inbound substream is kept pending.
+    pub(crate) pending_inbound_timeout: Duration,
+    /// Maximum number of outbound substreams waiting to be opened
+    /// when the substream limit is reached.
+    pub(crate) max_pending_outbound: usize,
+    /// Maximum duration an outbound substream waits to be opened.
+    pub(crate) pending_outbound_timeout: Duration,
 }
 
 impl MplexConfig {
@@ -49,17 +59,75 @@ impl MplexConfig {
     /// can be read before the `StreamMuxer` API signals EOF).
     ///
     /// When the limit is reached, opening of outbound substreams
-    /// is delayed until another substream is dropped, whereas new
-    /// inbound substreams are immediately answered with a `Reset`.
-    /// If the number of inbound substreams that need to be reset
-    /// accumulates too quickly (judged by internal bounds), the
-    /// connection is closed with an error due to the misbehaved
-    /// remote.
+    /// is delayed until another substream is dropped, up to the limits
+    /// set by [`MplexConfig::set_max_pending_outbound`] and
+    /// [`MplexConfig::set_pending_outbound_timeout`], whereas new
+    /// inbound substreams are kept pending up to the limit set by
+    /// [`MplexConfig::set_max_pending_inbound`] and are otherwise
+    /// immediately answered with a `Reset`. If the number of inbound
+    /// substreams that need to be reset accumulates too quickly (judged
+    /// by internal bounds), the connection is closed with an error due
+    /// to the misbehaved remote.
     pub fn set_max_num_streams(&mut self, max: usize) -> &mut Self {
         self.max_substreams = max;
         self
     }
 
+    /// Sets the maximum number of inbound substreams which are kept
+    /// pending, rather than being reset, while the limit set by
+    /// [`MplexConfig::set_max_num_streams`] is reached.
+    ///
+    /// Pending inbound substreams are handed out in the order in which
+    /// they were opened by the remote as soon as other substreams are
+    /// dropped, thereby applying back-pressure to a remote opening
+    /// substreams in bursts. Data received on a pending substream is
+    /// buffered up to [`MplexConfig::set_max_buffer_size`], beyond which
+    /// the substream is reset.
+    ///
+    /// The default is `0`, i.e. inbound substreams are reset as soon as
+    /// the limit is reached.
+    pub fn set_max_pending_inbound(&mut self, max: usize) -> &mut Self {
+        self.max_pending_inbound = max;
+        self
+    }
+
+    /// Sets the maximum duration an inbound substream is kept pending
+    /// (see [`MplexConfig::set_max_pending_inbound`]).
+    ///
+    /// Pending inbound substreams exceeding this duration are reset
+    /// instead of being handed out. The default is 10 seconds.
+    pub fn set_pending_inbound_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.pending_inbound_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of outbound substreams which wait to be
+    /// opened while the limit set by [`MplexConfig::set_max_num_streams`]
+    /// is reached.
+    ///
+    /// Opening any further outbound substream fails immediately with
+    /// [`OutboundSubstreamError::TooManyPending`], which only fails the
+    /// substream in question and not the connection. The default is `128`.
+    ///
+    /// [`OutboundSubstreamError::TooManyPending`]: libp2p_core::muxing::OutboundSubstreamError::TooManyPending
+    pub fn set_max_pending_outbound(&mut self, max: usize) -> &mut Self {
+        self.max_pending_outbound = max;
+        self
+    }
+
+    /// Sets the maximum duration an outbound substream waits to be opened
+    /// (see [`MplexConfig::set_max_pending_outbound`]).
+    ///
+    /// Outbound substreams exceeding this duration fail with
+    /// [`OutboundSubstreamError::Timeout`], which only fails the substream
+    /// in question and not the connection. The default is 10 seconds.
+    ///
+    /// [`OutboundSubstreamError::Timeout`]: libp2p_core::muxing::OutboundSubstreamError::Timeout
+    pub fn set_pending_outbound_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.pending_outbound_timeout = timeout;
+        self
+    }
+
     /// Sets the maximum number of frames buffered per substream.
     ///
     /// A limit is necessary in order to avoid DoS attacks.
@@ -120,6 +188,10 @@ impl Default for MplexConfig {
             max_buffer_len: 32,
             max_buffer_behaviour: MaxBufferBehaviour::Block,
             split_send_size: 8 * 1024,
+            max_pending_inbound: 0,
+            pending_inbound_timeout: Duration::from_secs(10),
+            max_pending_outbound: 128,
+            pending_outbound_timeout: Duration::from_secs(10),
         }
     }
 }
@@ -20,19 +20,23 @@
 
 use bytes::Bytes;
 use crate::{MplexConfig, MaxBufferBehaviour};
-use crate::codec::{Codec, Frame, LocalStreamId, RemoteStreamId};
+use crate::codec::{Frame, LocalStreamId, RemoteStreamId};
+use framed::Framed;
+use libp2p_core::muxing::OutboundSubstreamError;
 use log::{debug, trace};
 use futures::{prelude::*, ready, stream::Fuse};
 use futures::task::{AtomicWaker, ArcWake, waker_ref, WakerRef};
-use asynchronous_codec::Framed;
 use nohash_hasher::{IntMap, IntSet};
 use parking_lot::Mutex;
 use smallvec::SmallVec;
 use std::collections::VecDeque;
 use std::{cmp, fmt, io, mem, sync::Arc, task::{Context, Poll, Waker}};
+use wasm_timer::{Delay, Instant};
 
 pub use std::io::{Result, Error, ErrorKind};
 
+mod framed;
+
 /// A connection identifier.
 ///
 /// Randomly generated and mainly intended to improve log output
@@ -58,13 +62,25 @@ pub struct Multiplexed<C> {
     /// The current operating status of the multiplex stream.
     status: Status,
     /// The underlying multiplexed I/O stream.
-    io: Fuse<Framed<C, Codec>>,
+    io: Fuse<Framed<C>>,
     /// The configuration.
     config: MplexConfig,
     /// The buffer of new inbound substreams that have not yet
     /// been drained by `poll_next_stream`. This buffer is
     /// effectively bounded by `max_substreams - substreams.len()`.
     open_buffer: VecDeque<LocalStreamId>,
+    /// Inbound substreams received while the substream limit is reached,
+    /// together with the time they were received, in order of arrival.
+    /// Pending inbound substreams are contained in `substreams` (in order
+    /// to buffer their data) but do not count towards the limit. This
+    /// buffer is bounded by `max_pending_inbound`.
+    pending_inbound: VecDeque<(LocalStreamId, Instant)>,
+    /// The timer for the expiry of the oldest pending inbound substream,
+    /// armed by `poll_next_stream`.
+    pending_inbound_timer: Option<(LocalStreamId, Delay)>,
+    /// The number of outbound substreams waiting to be opened while the
+    /// substream limit is reached. Bounded by `max_pending_outbound`.
+    num_pending_outbound: usize,
     /// Whether a flush is pending due to one or more new outbound
     /// `Open` frames, before reading frames can proceed.
     pending_flush_open: IntSet<LocalStreamId>,
@@ -96,6 +112,14 @@ pub struct Multiplexed<C> {
     notifier_open: NotifierOpen,
 }
 
+/// An attempt to open an outbound substream via [`Multiplexed::poll_open_stream`].
+#[derive(Default)]
+pub struct PendingOpen {
+    /// The timeout for waiting on the substream limit, present while
+    /// the attempt counts towards the `max_pending_outbound` limit.
+    timeout: Option<Delay>,
+}
+
 /// The operation status of a `Multiplexed` I/O stream.
 #[derive(Debug)]
 enum Status {
@@ -119,8 +143,11 @@ where
             id,
             config,
             status: Status::Open,
-            io: Framed::new(io, Codec::new()).fuse(),
+            io: Framed::new(io).fuse(),
             open_buffer: Default::default(),
+            pending_inbound: Default::default(),
+            pending_inbound_timer: None,
+            num_pending_outbound: 0,
             substreams: Default::default(),
             pending_flush_open: Default::default(),
             pending_frames: Default::default(),
@@ -185,6 +212,8 @@ where
                 // We do not support read-after-close on the underlying
                 // I/O stream, hence clearing the buffer and substreams.
                 self.open_buffer = Default::default();
+                self.pending_inbound = Default::default();
+                self.pending_inbound_timer = None;
                 self.substreams = Default::default();
                 self.status = Status::Closed;
                 Poll::Ready(Ok(()))
@@ -196,8 +225,11 @@ where
     ///
     /// If the number of already used substreams (i.e. substreams that have not
     /// yet been dropped via `drop_substream`) reaches the configured
-    /// `max_substreams`, any further inbound substreams are immediately reset
-    /// until existing substreams are dropped.
+    /// `max_substreams`, further inbound substreams are kept pending up to
+    /// `max_pending_inbound` and are otherwise immediately reset until
+    /// existing substreams are dropped. Pending inbound substreams exceeding
+    /// the `pending_inbound_timeout` are reset, for which the current task
+    /// is woken in time.
     ///
     /// Data frames read for existing substreams in the context of this
     /// method call are buffered and tasks interested in reading from
@@ -207,6 +239,7 @@ where
     /// buffer is full.
     pub fn poll_next_stream(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<LocalStreamId>> {
         self.guard_open()?;
+        self.poll_expire_pending_inbound(cx)?;
 
         // Try to read from the buffer first.
         if let Some(stream_id) = self.open_buffer.pop_back() {
@@ -248,17 +281,45 @@ where
     }
 
     /// Creates a new (outbound) substream, returning the allocated stream ID.
-    pub fn poll_open_stream(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<LocalStreamId>> {
+    ///
+    /// If the number of used substreams reaches the configured
+    /// `max_substreams`, opening the substream waits for existing
+    /// substreams to be dropped for at most `pending_outbound_timeout`,
+    /// while up to `max_pending_outbound` substreams may be waiting.
+    /// Otherwise the attempt fails with an [`OutboundSubstreamError`],
+    /// which leaves the multiplexed stream intact.
+    ///
+    /// > **Note**: Every attempt, i.e. `PendingOpen`, must eventually be
+    /// > passed to `cancel_open` if it does not complete.
+    pub fn poll_open_stream(&mut self, cx: &mut Context<'_>, pending: &mut PendingOpen)
+        -> Poll<io::Result<LocalStreamId>>
+    {
         self.guard_open()?;
 
         // Check the stream limits.
-        if self.substreams.len() >= self.config.max_substreams {
-            debug!("{}: Maximum number of substreams reached ({})",
-                self.id, self.config.max_substreams);
+        if self.num_used() >= self.config.max_substreams {
+            if pending.timeout.is_none() {
+                if self.num_pending_outbound >= self.config.max_pending_outbound {
+                    debug!("{}: Maximum number of pending outbound substreams reached ({})",
+                        self.id, self.config.max_pending_outbound);
+                    return Poll::Ready(Err(OutboundSubstreamError::TooManyPending.into()))
+                }
+                debug!("{}: Maximum number of substreams reached ({})",
+                    self.id, self.config.max_substreams);
+                self.num_pending_outbound += 1;
+                pending.timeout = Some(Delay::new(self.config.pending_outbound_timeout));
+            }
+            if pending.timeout.as_mut().map_or(false, |t| t.poll_unpin(cx).is_ready()) {
+                debug!("{}: Pending outbound substream timed out.", self.id);
+                self.cancel_open(pending);
+                return Poll::Ready(Err(OutboundSubstreamError::Timeout.into()))
+            }
             self.notifier_open.register(cx.waker());
             return Poll::Pending
         }
 
+        self.cancel_open(pending);
+
         // Send the `Open` frame.
         let waker = NotifierWrite::register(&self.notifier_write, cx.waker());
         match ready!(self.io.poll_ready_unpin(&mut Context::from_waker(&waker))) {
@@ -284,6 +345,14 @@ where
         }
     }
 
+    /// Ends an attempt to open an outbound substream via `poll_open_stream`,
+    /// such that it no longer counts towards the `max_pending_outbound` limit.
+    pub fn cancel_open(&mut self, pending: &mut PendingOpen) {
+        if pending.timeout.take().is_some() {
+            self.num_pending_outbound -= 1;
+        }
+    }
+
     /// Immediately drops a substream.
     ///
     /// All locally allocated resources for the dropped substream
@@ -323,10 +392,12 @@ where
         match self.substreams.remove(&id) {
             None => {},
             Some(state) => {
-                // If we fell below the substream limit, notify tasks that had
-                // interest in opening an outbound substream earlier.
-                let below_limit = self.substreams.len() == self.config.max_substreams - 1;
-                if below_limit {
+                // If we fell below the substream limit, hand out the next
+                // pending inbound substream, if any, and otherwise notify
+                // tasks that had interest in opening an outbound substream
+                // earlier.
+                let below_limit = self.num_used() == self.config.max_substreams - 1;
+                if below_limit && !self.promote_pending_inbound() {
                     self.notifier_open.wake_all();
                 }
                 // Schedule any pending final frames to send, if necessary.
@@ -356,6 +427,24 @@ where
     /// Writes data to a substream.
     pub fn poll_write_stream(&mut self, cx: &mut Context<'_>, id: LocalStreamId, buf: &[u8])
         -> Poll<io::Result<usize>>
+    {
+        self.poll_write_stream_with(cx, id, buf.len(), |io, n| io.copy_data(&buf[.. n]))
+    }
+
+    /// Writes data from a reference-counted buffer to a substream,
+    /// sending (a slice of) the buffer without copying.
+    pub fn poll_write_stream_bytes(&mut self, cx: &mut Context<'_>, id: LocalStreamId, buf: &Bytes)
+        -> Poll<io::Result<usize>>
+    {
+        self.poll_write_stream_with(cx, id, buf.len(), |_, n| buf.slice(.. n))
+    }
+
+    /// Writes at most `len` bytes to a substream, obtaining the data
+    /// of the frame to send via `data`.
+    fn poll_write_stream_with<F>(&mut self, cx: &mut Context<'_>, id: LocalStreamId, len: usize, data: F)
+        -> Poll<io::Result<usize>>
+    where
+        F: FnOnce(&mut Framed<C>, usize) -> Bytes
     {
         self.guard_open()?;
 
@@ -371,12 +460,11 @@ where
         }
 
         // Determine the size of the frame to send.
-        let frame_len = cmp::min(buf.len(), self.config.split_send_size);
+        let frame_len = cmp::min(len, self.config.split_send_size);
 
         // Send the data frame.
-        ready!(self.poll_send_frame(cx, || {
-            let data = Bytes::copy_from_slice(&buf[.. frame_len]);
-            Frame::Data { stream_id: id, data }
+        ready!(self.poll_send_frame(cx, |io| {
+            Frame::Data { stream_id: id, data: data(io, frame_len) }
         }))?;
 
         Poll::Ready(Ok(frame_len))
@@ -395,7 +483,8 @@ where
     /// this method call are buffered up to the configured `max_substreams`
     /// and under consideration of the number of already used substreams,
     /// thereby waking the task that last called `poll_next_stream`, if any.
-    /// Inbound substreams received in excess of that limit are immediately reset.
+    /// Inbound substreams received in excess of that limit are kept pending
+    /// up to `max_pending_inbound` and are otherwise immediately reset.
     pub fn poll_read_stream(&mut self, cx: &mut Context<'_>, id: LocalStreamId)
         -> Poll<io::Result<Option<Bytes>>>
     {
@@ -515,7 +604,7 @@ where
                 Poll::Ready(Ok(()))
             }
             Some(SubstreamState::Open { buf }) => {
-                if self.poll_send_frame(cx, || Frame::Close { stream_id: id })?.is_pending() {
+                if self.poll_send_frame(cx, |_| Frame::Close { stream_id: id })?.is_pending() {
                     self.substreams.insert(id, SubstreamState::Open { buf });
                     Poll::Pending
                 } else {
@@ -525,7 +614,7 @@ where
                 }
             }
             Some(SubstreamState::RecvClosed { buf }) => {
-                if self.poll_send_frame(cx, || Frame::Close { stream_id: id })?.is_pending() {
+                if self.poll_send_frame(cx, |_| Frame::Close { stream_id: id })?.is_pending() {
                     self.substreams.insert(id, SubstreamState::RecvClosed { buf });
                     Poll::Pending
                 } else {
@@ -540,16 +629,17 @@ where
     /// Sends a (lazily constructed) mplex frame on the underlying I/O stream.
     ///
     /// The frame is only constructed if the underlying sink is ready to
-    /// send another frame.
+    /// send another frame, e.g. copying the frame data into the write
+    /// buffer of the underlying [`Framed`].
     fn poll_send_frame<F>(&mut self, cx: &mut Context<'_>, frame: F)
         -> Poll<io::Result<()>>
     where
-        F: FnOnce() -> Frame<LocalStreamId>
+        F: FnOnce(&mut Framed<C>) -> Frame<LocalStreamId>
     {
         let waker = NotifierWrite::register(&self.notifier_write, cx.waker());
         match ready!(self.io.poll_ready_unpin(&mut Context::from_waker(&waker))) {
             Ok(()) => {
-                let frame = frame();
+                let frame = frame(self.io.get_mut());
                 trace!("{}: Sending {:?}", self.id, frame);
                 match self.io.start_send_unpin(frame) {
                     Ok(()) => Poll::Ready(Ok(())),
@@ -635,7 +725,18 @@ where
                 "Protocol error: Received `Open` frame for open substream."))
         }
 
-        if self.substreams.len() >= self.config.max_substreams {
+        if self.num_used() >= self.config.max_substreams {
+            self.expire_pending_inbound()?;
+            if self.pending_inbound.len() < self.config.max_pending_inbound {
+                self.substreams.insert(id, SubstreamState::Open {
+                    buf: Default::default()
+                });
+                self.pending_inbound.push_back((id, Instant::now()));
+                debug!("{}: Maximum number of substreams reached ({}), new inbound \
+                    substream {} pending (total pending {})", self.id,
+                    self.config.max_substreams, id, self.pending_inbound.len());
+                return Ok(None)
+            }
             debug!("{}: Maximum number of substreams exceeded: {}",
                 self.id, self.config.max_substreams);
             self.check_max_pending_frames()?;
@@ -719,6 +820,96 @@ where
         Ok(())
     }
 
+    /// Hands out the oldest pending inbound substream which has neither
+    /// been reset by the remote nor exceeded the `pending_inbound_timeout`,
+    /// waking the task that last called `poll_next_stream`, if any.
+    ///
+    /// Returns `false` if there is no such substream.
+    fn promote_pending_inbound(&mut self) -> bool {
+        if self.expire_pending_inbound().is_err() {
+            return false
+        }
+        while let Some((id, _)) = self.pending_inbound.pop_front() {
+            match self.substreams.get(&id) {
+                None => {}
+                Some(SubstreamState::Reset { .. }) => {
+                    trace!("{}: Discarding pending inbound substream {} reset by remote.",
+                        self.id, id);
+                    self.substreams.remove(&id);
+                }
+                Some(_) => {
+                    debug!("{}: New inbound substream: {} (total {})",
+                        self.id, id, self.num_used());
+                    self.open_buffer.push_front(id);
+                    self.notifier_read.wake_next_stream();
+                    return true
+                }
+            }
+        }
+        false
+    }
+
+    /// Resets all pending inbound substreams which exceeded the
+    /// `pending_inbound_timeout` and arms the timer for the next one
+    /// to expire, registering the current task with it.
+    fn poll_expire_pending_inbound(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        loop {
+            self.expire_pending_inbound()?;
+            let (id, since) = match self.pending_inbound.front() {
+                Some(front) => *front,
+                None => {
+                    self.pending_inbound_timer = None;
+                    return Ok(())
+                }
+            };
+            if self.pending_inbound_timer.as_ref().map_or(true, |(timer_id, _)| *timer_id != id) {
+                let remaining = self.config.pending_inbound_timeout
+                    .checked_sub(Instant::now().duration_since(since))
+                    .unwrap_or_default();
+                self.pending_inbound_timer = Some((id, Delay::new(remaining)));
+            }
+            match &mut self.pending_inbound_timer {
+                Some((_, timer)) if timer.poll_unpin(cx).is_ready() => {}
+                _ => return Ok(())
+            }
+        }
+    }
+
+    /// Resets all pending inbound substreams which exceeded the
+    /// `pending_inbound_timeout`.
+    fn expire_pending_inbound(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        while let Some((id, since)) = self.pending_inbound.front().copied() {
+            if now.duration_since(since) < self.config.pending_inbound_timeout {
+                break
+            }
+            debug!("{}: Pending inbound substream {} timed out.", self.id, id);
+            self.pending_inbound.pop_front();
+            self.reset_pending_inbound(id)?;
+        }
+        Ok(())
+    }
+
+    /// Removes a substream that is no longer pending, resetting it unless
+    /// it has already been reset by the remote.
+    fn reset_pending_inbound(&mut self, id: LocalStreamId) -> io::Result<()> {
+        match self.substreams.remove(&id) {
+            None | Some(SubstreamState::Reset { .. }) => {}
+            Some(_) => {
+                self.check_max_pending_frames()?;
+                debug!("{}: Pending reset for pending inbound stream {}", self.id, id);
+                self.pending_frames.push_front(Frame::Reset { stream_id: id });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of used substreams, i.e. the substreams counting
+    /// towards the `max_substreams` limit.
+    fn num_used(&self) -> usize {
+        self.substreams.len() - self.pending_inbound.len()
+    }
+
     /// Generates the next outbound stream ID.
     fn next_outbound_stream_id(&mut self) -> LocalStreamId {
         let id = self.next_outbound_stream_id;
@@ -737,7 +928,7 @@ where
     /// Sends pending frames, without flushing.
     fn send_pending_frames(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         while let Some(frame) = self.pending_frames.pop_back() {
-            if self.poll_send_frame(cx, || {
+            if self.poll_send_frame(cx, |_| {
                 frame.clone()
             })?.is_pending() {
                 self.pending_frames.push_back(frame);
@@ -755,6 +946,8 @@ where
         self.pending_frames =  Default::default();
         self.substreams = Default::default();
         self.open_buffer = Default::default();
+        self.pending_inbound = Default::default();
+        self.pending_inbound_timer = None;
         Err(e)
     }
 
@@ -808,6 +1001,12 @@ where
         self.notifier_read.wake_read_stream(id);
         if buf.len() > self.config.max_buffer_len {
             debug!("{}: Frame buffer of stream {} is full.", self.id, id);
+            if let Some(pos) = self.pending_inbound.iter().position(|(p, _)| *p == id) {
+                // No task reads from a pending substream, so it is reset
+                // rather than blocking reading from all substreams.
+                self.pending_inbound.remove(pos);
+                return self.reset_pending_inbound(id)
+            }
             match self.config.max_buffer_behaviour {
                 MaxBufferBehaviour::ResetStream => {
                     let buf = buf.clone();
@@ -1005,12 +1204,14 @@ mod tests {
     use bytes::BytesMut;
     use futures::prelude::*;
     use asynchronous_codec::{Decoder, Encoder};
+    use crate::codec::Codec;
     use quickcheck::*;
     use rand::prelude::*;
     use std::collections::HashSet;
     use std::num::NonZeroU8;
     use std::ops::DerefMut;
     use std::pin::Pin;
+    use std::time::Duration;
     use super::*;
 
     impl Arbitrary for MaxBufferBehaviour {
@@ -1026,6 +1227,10 @@ mod tests {
                 max_buffer_len: g.gen_range(1, 1000),
                 max_buffer_behaviour: MaxBufferBehaviour::arbitrary(g),
                 split_send_size: g.gen_range(1, 10000),
+                max_pending_inbound: g.gen_range(0, 10),
+                pending_inbound_timeout: Duration::from_secs(10),
+                max_pending_outbound: g.gen_range(0, 10),
+                pending_outbound_timeout: Duration::from_secs(10),
             }
         }
     }
@@ -1208,6 +1413,147 @@ mod tests {
         quickcheck(prop as fn(_,_))
     }
 
+    #[test]
+    fn pending_inbound() {
+        let _ = env_logger::try_init();
+
+        let mut cfg = MplexConfig::new();
+        cfg.set_max_num_streams(1);
+        cfg.set_max_pending_inbound(1);
+
+        // Open one inbound stream more than the limit and
+        // the number of pending streams permit.
+        let mut r_buf = BytesMut::new();
+        let mut codec = Codec::new();
+        for i in 0 .. 3 {
+            let stream_id = LocalStreamId::dialer(i);
+            codec.encode(Frame::Open { stream_id }, &mut r_buf).unwrap();
+        }
+
+        let conn = Connection { r_buf, w_buf: BytesMut::new(), eof: false };
+        let mut m = Multiplexed::new(conn, cfg);
+
+        task::block_on(future::poll_fn(move |cx| {
+            let first = match m.poll_next_stream(cx) {
+                Poll::Ready(Ok(id)) => id,
+                poll => panic!("Unexpected: {:?}", poll)
+            };
+            assert_eq!(first, LocalStreamId::listener(0));
+
+            // The second stream is kept pending, the third one is reset.
+            assert!(m.poll_next_stream(cx).is_pending());
+            assert_eq!(m.pending_inbound.len(), 1);
+            let mut open = PendingOpen::default();
+            assert!(m.poll_open_stream(cx, &mut open).is_pending());
+            m.cancel_open(&mut open);
+
+            // Dropping the first stream hands out the pending one.
+            m.drop_stream(first);
+            match m.poll_next_stream(cx) {
+                Poll::Ready(Ok(id)) => assert_eq!(id, LocalStreamId::listener(1)),
+                poll => panic!("Unexpected: {:?}", poll)
+            }
+            assert!(m.pending_inbound.is_empty());
+
+            let _ = m.poll_flush(cx);
+            let w_buf = &mut m.io.get_mut().deref_mut().w_buf;
+            let stream_id = LocalStreamId::dialer(2).into_remote();
+            assert_eq!(codec.decode(w_buf).unwrap(), Some(Frame::Reset { stream_id }));
+
+            Poll::Ready(())
+        }));
+    }
+
+    #[test]
+    fn pending_inbound_timeout() {
+        let _ = env_logger::try_init();
+
+        let mut cfg = MplexConfig::new();
+        cfg.set_max_num_streams(1);
+        cfg.set_max_pending_inbound(1);
+        cfg.set_pending_inbound_timeout(Duration::from_millis(100));
+
+        let mut r_buf = BytesMut::new();
+        let mut codec = Codec::new();
+        for i in 0 .. 2 {
+            let stream_id = LocalStreamId::dialer(i);
+            codec.encode(Frame::Open { stream_id }, &mut r_buf).unwrap();
+        }
+
+        let conn = Connection { r_buf, w_buf: BytesMut::new(), eof: false };
+        let mut m = Multiplexed::new(conn, cfg);
+
+        task::block_on(future::poll_fn(|cx| {
+            match m.poll_next_stream(cx) {
+                Poll::Ready(Ok(id)) => assert_eq!(id, LocalStreamId::listener(0)),
+                poll => panic!("Unexpected: {:?}", poll)
+            }
+            assert!(m.poll_next_stream(cx).is_pending());
+            assert_eq!(m.pending_inbound.len(), 1);
+            Poll::Ready(())
+        }));
+
+        // Without any further frames from the remote, the task
+        // is woken to reset the pending stream once it expires.
+        task::block_on(future::poll_fn(|cx| {
+            assert!(m.poll_next_stream(cx).is_pending());
+            if m.pending_inbound.is_empty() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }));
+
+        task::block_on(future::poll_fn(|cx| m.poll_flush(cx))).unwrap();
+        let w_buf = &mut m.io.get_mut().deref_mut().w_buf;
+        let stream_id = LocalStreamId::dialer(1).into_remote();
+        assert_eq!(codec.decode(w_buf).unwrap(), Some(Frame::Reset { stream_id }));
+    }
+
+    #[test]
+    fn pending_outbound() {
+        let _ = env_logger::try_init();
+
+        let mut cfg = MplexConfig::new();
+        cfg.set_max_num_streams(1);
+        cfg.set_max_pending_outbound(1);
+        cfg.set_pending_outbound_timeout(Duration::from_millis(100));
+
+        let conn = Connection { r_buf: BytesMut::new(), w_buf: BytesMut::new(), eof: false };
+        let mut m = Multiplexed::new(conn, cfg);
+
+        let mut pending = PendingOpen::default();
+        let first = task::block_on(future::poll_fn(|cx| {
+            let first = match m.poll_open_stream(cx, &mut PendingOpen::default()) {
+                Poll::Ready(Ok(id)) => id,
+                poll => panic!("Unexpected: {:?}", poll)
+            };
+
+            // The second stream waits for the substream limit,
+            // the third one exceeds the pending limit.
+            assert!(m.poll_open_stream(cx, &mut pending).is_pending());
+            match m.poll_open_stream(cx, &mut PendingOpen::default()) {
+                Poll::Ready(Err(e)) => assert_eq!(
+                    OutboundSubstreamError::from_io_error(&e),
+                    Some(OutboundSubstreamError::TooManyPending)),
+                poll => panic!("Unexpected: {:?}", poll)
+            }
+
+            Poll::Ready(first)
+        }));
+
+        // The waiting stream times out.
+        let error = task::block_on(future::poll_fn(|cx| m.poll_open_stream(cx, &mut pending)))
+            .unwrap_err();
+        assert_eq!(OutboundSubstreamError::from_io_error(&error), Some(OutboundSubstreamError::Timeout));
+        assert_eq!(m.num_pending_outbound, 0);
+
+        // The connection is unaffected.
+        m.drop_stream(first);
+        let mut open = PendingOpen::default();
+        task::block_on(future::poll_fn(|cx| m.poll_open_stream(cx, &mut open))).unwrap();
+    }
+
     #[test]
     fn close_on_error() {
         let _ = env_logger::try_init();
@@ -1228,7 +1574,7 @@ mod tests {
             task::block_on(future::poll_fn(move |cx| {
                 // Open a number of streams.
                 for _ in 0 .. num_streams {
-                    let id = ready!(m.poll_open_stream(cx)).unwrap();
+                    let id = ready!(m.poll_open_stream(cx, &mut PendingOpen::default())).unwrap();
                     assert!(opened.insert(id));
                     assert!(m.poll_read_stream(cx, id).is_pending());
                 }
@@ -0,0 +1,303 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! This module provides a `Sink` and `Stream` for mplex frames in form
+//! of [`Framed`], writing frames with vectored writes.
+
+use bytes::{Buf, Bytes};
+use crate::codec::{Codec, Frame, LocalStreamId, RemoteStreamId};
+use asynchronous_codec::Decoder;
+use futures::{prelude::*, ready};
+use libp2p_core::buffer_pool::{BufferPool, PooledBuffer};
+use smallvec::SmallVec;
+use std::{cmp, collections::VecDeque, io::{self, IoSlice}, ops::{Deref, DerefMut}, pin::Pin, task::{Context, Poll}};
+
+/// The number of bytes read from the underlying I/O stream at once.
+const READ_SIZE: usize = 8 * 1024;
+/// The number of buffered bytes from which on `poll_ready` first writes
+/// the buffered frames to the underlying I/O stream.
+const SEND_HIGH_WATER: usize = 128 * 1024;
+/// The minimum capacity allocated for the write buffer, which holds frame
+/// headers and copied frame data for many frames.
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+/// The maximum number of slices passed to a single vectored write.
+const MAX_WRITE_SLICES: usize = 64;
+
+/// A `Framed` is a `Sink` and `Stream` for mplex frames.
+///
+/// Unlike a generic codec, the frame data is not copied into a contiguous
+/// write buffer. Instead, the encoded frame headers and the frame data are
+/// queued as [`Bytes`] and written with vectored writes. The read and write
+/// buffers are taken from the [`BufferPool::global`] pool.
+pub(crate) struct Framed<C> {
+    io: C,
+    codec: Codec,
+    read_buffer: PooledBuffer,
+    /// Holds the encoded frame headers and copied frame data, which are
+    /// split off as `Bytes`. The space is reclaimed once these have been
+    /// written and dropped.
+    write_buffer: PooledBuffer,
+    /// The headers and data of the frames not yet (completely) written.
+    write_queue: VecDeque<Bytes>,
+    /// The number of bytes in `write_queue`.
+    write_len: usize,
+    eof: bool,
+}
+
+impl<C> Framed<C> {
+    /// Creates a new `Framed` on top of the given I/O stream.
+    pub(crate) fn new(io: C) -> Self {
+        let pool = BufferPool::global();
+        Framed {
+            io,
+            codec: Codec::new(),
+            read_buffer: pool.get(),
+            write_buffer: pool.get(),
+            write_queue: VecDeque::new(),
+            write_len: 0,
+            eof: false,
+        }
+    }
+
+    /// Copies data into the write buffer, returning it as `Bytes` to send
+    /// with a frame without allocating for every frame.
+    pub(crate) fn copy_data(&mut self, data: &[u8]) -> Bytes {
+        self.reserve(data.len());
+        self.write_buffer.extend_from_slice(data);
+        self.write_buffer.split().freeze()
+    }
+
+    fn reserve(&mut self, n: usize) {
+        if self.write_buffer.capacity() < n {
+            self.write_buffer.reserve(cmp::max(n, WRITE_BUFFER_SIZE));
+        }
+    }
+
+    fn enqueue(&mut self, bytes: Bytes) {
+        if !bytes.is_empty() {
+            self.write_len += bytes.len();
+            self.write_queue.push_back(bytes);
+        }
+    }
+
+    /// Removes `n` written bytes from the front of the write queue.
+    fn advance(&mut self, mut n: usize) {
+        self.write_len -= n;
+        while n > 0 {
+            let front = self.write_queue.front_mut().expect("n <= write_len");
+            if n < front.len() {
+                front.advance(n);
+                return
+            }
+            n -= front.len();
+            self.write_queue.pop_front();
+        }
+    }
+}
+
+impl<C> Deref for Framed<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.io
+    }
+}
+
+impl<C> DerefMut for Framed<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.io
+    }
+}
+
+impl<C: AsyncWrite + Unpin> Framed<C> {
+    /// Writes all queued frames to the underlying I/O stream.
+    fn poll_write_queue(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_queue.is_empty() {
+            let n = {
+                let bufs = self.write_queue.iter()
+                    .take(MAX_WRITE_SLICES)
+                    .map(|b| IoSlice::new(b))
+                    .collect::<SmallVec<[_; MAX_WRITE_SLICES]>>();
+                ready!(Pin::new(&mut self.io).poll_write_vectored(cx, &bufs))?
+            };
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()))
+            }
+            self.advance(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<C: AsyncRead + Unpin> Stream for Framed<C> {
+    type Item = io::Result<Frame<RemoteStreamId>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = Pin::into_inner(self);
+        loop {
+            match this.codec.decode(&mut this.read_buffer) {
+                Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+
+            if this.eof {
+                if this.read_buffer.is_empty() {
+                    return Poll::Ready(None)
+                }
+                let msg = "bytes remaining on stream";
+                return Poll::Ready(Some(Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg))))
+            }
+
+            let len = this.read_buffer.len();
+            this.read_buffer.resize(len + READ_SIZE, 0);
+            let n = match Pin::new(&mut this.io).poll_read(cx, &mut this.read_buffer[len ..]) {
+                Poll::Ready(Ok(n)) => n,
+                Poll::Ready(Err(e)) => {
+                    this.read_buffer.truncate(len);
+                    return Poll::Ready(Some(Err(e)))
+                }
+                Poll::Pending => {
+                    this.read_buffer.truncate(len);
+                    return Poll::Pending
+                }
+            };
+            this.read_buffer.truncate(len + n);
+            this.eof = n == 0;
+        }
+    }
+}
+
+impl<C: AsyncWrite + Unpin> Sink<Frame<LocalStreamId>> for Framed<C> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = Pin::into_inner(self);
+        if this.write_len >= SEND_HIGH_WATER {
+            ready!(this.poll_write_queue(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, frame: Frame<LocalStreamId>) -> Result<(), Self::Error> {
+        let this = Pin::into_inner(self);
+        // A varint-encoded `u64` header and `usize` length take at most 20 bytes.
+        this.reserve(20);
+        let data = Codec::encode_header(frame, &mut this.write_buffer)?;
+        let header = this.write_buffer.split().freeze();
+        this.enqueue(header);
+        this.enqueue(data);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = Pin::into_inner(self);
+        ready!(this.poll_write_queue(cx))?;
+        Pin::new(&mut this.io).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.io).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asynchronous_codec::Encoder;
+    use bytes::BytesMut;
+    use futures::executor::block_on;
+
+    /// An I/O stream that writes at most 3 bytes of only the first slice
+    /// of a vectored write, recording the number of writes.
+    struct Chunked {
+        buf: Vec<u8>,
+        writes: usize,
+    }
+
+    impl AsyncWrite for Chunked {
+        fn poll_write(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            let n = cmp::min(3, buf.len());
+            self.buf.extend_from_slice(&buf[.. n]);
+            self.writes += 1;
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncRead for Chunked {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            let n = cmp::min(buf.len(), self.buf.len());
+            buf[.. n].copy_from_slice(&self.buf[.. n]);
+            self.buf.drain(.. n);
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    #[test]
+    fn partial_writes_roundtrip() {
+        let mut framed = Framed::new(Chunked { buf: Vec::new(), writes: 0 });
+        let stream_id = LocalStreamId::dialer(7);
+        let frames = vec![
+            Frame::Open { stream_id },
+            Frame::Data { stream_id, data: Bytes::from_static(b"hello world") },
+            Frame::Data { stream_id, data: framed.copy_data(b"copied") },
+            Frame::Close { stream_id },
+        ];
+
+        let mut expected = BytesMut::new();
+        let mut codec = Codec::new();
+        for frame in frames.clone() {
+            codec.encode(frame, &mut expected).unwrap();
+        }
+
+        block_on(async {
+            for frame in frames.clone() {
+                framed.feed(frame).await.unwrap();
+            }
+            framed.flush().await.unwrap();
+        });
+        assert_eq!(framed.write_len, 0);
+        assert!(framed.io.writes > frames.len());
+        assert_eq!(&framed.io.buf[..], &expected[..]);
+
+        let received = block_on(framed.take(frames.len()).collect::<Vec<_>>());
+        assert_eq!(received.len(), frames.len());
+        for (frame, sent) in received.into_iter().zip(frames) {
+            let frame = frame.unwrap();
+            assert_eq!(frame.remote_id().into_local(), LocalStreamId::listener(7));
+            match (frame, sent) {
+                (Frame::Open { .. }, Frame::Open { .. }) => {}
+                (Frame::Close { .. }, Frame::Close { .. }) => {}
+                (Frame::Data { data, .. }, Frame::Data { data: sent, .. }) => assert_eq!(data, sent),
+                (frame, sent) => panic!("Unexpected frame {:?}, expected {:?}", frame, sent),
+            }
+        }
+    }
+}
@@ -99,18 +99,18 @@ where
     }
 
     fn open_outbound(&self) -> Self::OutboundSubstream {
-        OutboundSubstream {}
+        OutboundSubstream { pending: Default::default() }
     }
 
-    fn poll_outbound(&self, cx: &mut Context<'_>, _: &mut Self::OutboundSubstream)
+    fn poll_outbound(&self, cx: &mut Context<'_>, substream: &mut Self::OutboundSubstream)
         -> Poll<Result<Self::Substream, io::Error>>
     {
-        let stream_id = ready!(self.io.lock().poll_open_stream(cx))?;
+        let stream_id = ready!(self.io.lock().poll_open_stream(cx, &mut substream.pending))?;
         Poll::Ready(Ok(Substream::new(stream_id)))
     }
 
-    fn destroy_outbound(&self, _substream: Self::OutboundSubstream) {
-        // Nothing to do, since `open_outbound` creates no new local state.
+    fn destroy_outbound(&self, mut substream: Self::OutboundSubstream) {
+        self.io.lock().cancel_open(&mut substream.pending);
     }
 
     fn read_substream(&self, cx: &mut Context<'_>, substream: &mut Self::Substream, buf: &mut [u8])
@@ -138,6 +138,30 @@ where
         self.io.lock().poll_write_stream(cx, substream.id, buf)
     }
 
+    fn read_substream_bytes(&self, cx: &mut Context<'_>, substream: &mut Self::Substream, max: usize)
+        -> Poll<Result<Bytes, io::Error>>
+    {
+        loop {
+            // Hand out (a slice of) the current frame without copying.
+            if !substream.current_data.is_empty() || max == 0 {
+                let len = cmp::min(substream.current_data.len(), max);
+                return Poll::Ready(Ok(substream.current_data.split_to(len)));
+            }
+
+            // Read the next data frame from the multiplexed stream.
+            match ready!(self.io.lock().poll_read_stream(cx, substream.id))? {
+                Some(data) => { substream.current_data = data; }
+                None => { return Poll::Ready(Ok(Bytes::new())) }
+            }
+        }
+    }
+
+    fn write_substream_bytes(&self, cx: &mut Context<'_>, substream: &mut Self::Substream, buf: &Bytes)
+        -> Poll<Result<usize, io::Error>>
+    {
+        self.io.lock().poll_write_stream_bytes(cx, substream.id, buf)
+    }
+
     fn flush_substream(&self, cx: &mut Context<'_>, substream: &mut Self::Substream)
         -> Poll<Result<(), io::Error>>
     {
@@ -164,7 +188,10 @@ where
 }
 
 /// Active attempt to open an outbound substream.
-pub struct OutboundSubstream {}
+pub struct OutboundSubstream {
+    /// The state of the attempt while waiting for the substream limit.
+    pending: io::PendingOpen,
+}
 
 /// Active substream to the remote.
 pub struct Substream {
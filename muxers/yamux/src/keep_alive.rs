@@ -0,0 +1,312 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Keep-alive pings at the yamux layer.
+//!
+//! The `yamux` crate answers ping frames sent by the remote but never sends
+//! any itself. [`KeepAlive`] wraps the I/O resource underlying a yamux
+//! connection and periodically injects a ping frame into the outgoing byte
+//! stream, in between the frames written by the connection. The matching
+//! acknowledgement, which the connection ignores, yields the round-trip time.
+//! If no acknowledgement arrives within the configured timeout, reading from
+//! the I/O resource fails, thereby failing the connection.
+
+use futures::{prelude::*, ready};
+use parking_lot::Mutex;
+use std::{cmp, io, pin::Pin, sync::Arc, task::{Context, Poll}, time::Duration};
+use wasm_timer::{Delay, Instant};
+
+/// The length of a yamux frame header.
+const HEADER_LEN: usize = 12;
+/// The frame type of data frames, the only frames with a body.
+const TYPE_DATA: u8 = 0;
+/// The frame type of ping frames.
+const TYPE_PING: u8 = 2;
+/// The flag marking a ping request.
+const FLAG_SYN: u16 = 1;
+/// The flag marking a ping acknowledgement.
+const FLAG_ACK: u16 = 2;
+
+/// The configuration of yamux keep-alive pings.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct KeepAliveConfig {
+    /// The interval between the acknowledgement of a ping and the next ping.
+    pub(crate) interval: Duration,
+    /// The time to wait for a ping to be sent and acknowledged.
+    pub(crate) timeout: Duration,
+}
+
+/// The most recent round-trip time measured by a [`KeepAlive`].
+pub(crate) type Rtt = Arc<Mutex<Option<Duration>>>;
+
+/// An I/O resource sending keep-alive pings on the yamux connection on top of it.
+pub(crate) struct KeepAlive<C> {
+    io: C,
+    config: KeepAliveConfig,
+    /// The frames written by the yamux connection.
+    outbound: FrameTracker,
+    /// The frames received from the remote.
+    inbound: FrameTracker,
+    /// Whether a ping is to be sent at the next frame boundary.
+    ping_due: bool,
+    /// The ping frame being written and the number of bytes already written.
+    sending: Option<([u8; HEADER_LEN], usize)>,
+    /// Whether the underlying I/O resource must be flushed after writing a ping.
+    flush: bool,
+    /// The nonce and send time of the ping awaiting an acknowledgement.
+    outstanding: Option<(u32, Instant)>,
+    /// The nonce of the last ping.
+    nonce: u32,
+    /// Fires when the next ping is due or the outstanding ping timed out.
+    timer: Delay,
+    rtt: Rtt,
+}
+
+impl<C> KeepAlive<C> {
+    /// Wraps the given I/O resource, sending the first ping after `config.interval`.
+    pub(crate) fn new(io: C, config: KeepAliveConfig) -> Self {
+        KeepAlive {
+            io,
+            config,
+            outbound: FrameTracker::default(),
+            inbound: FrameTracker::default(),
+            ping_due: false,
+            sending: None,
+            flush: false,
+            outstanding: None,
+            nonce: 0,
+            timer: Delay::new(config.interval),
+            rtt: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns a handle to the most recent round-trip time.
+    pub(crate) fn rtt(&self) -> Rtt {
+        self.rtt.clone()
+    }
+}
+
+impl<C: AsyncWrite + Unpin> KeepAlive<C> {
+    /// Drives the keep-alive timer and writes due pings.
+    ///
+    /// Called from [`AsyncRead::poll_read`], since on an idle connection
+    /// the yamux connection does not write anything.
+    fn poll_keep_alive(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        while let Poll::Ready(result) = self.timer.poll_unpin(cx) {
+            result?;
+            if self.outstanding.is_some() || self.ping_due {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "yamux keep-alive ping timed out"))
+            }
+            // A ping that can not be sent within the timeout, e.g. because
+            // the yamux connection is stuck in the middle of a frame, fails
+            // the connection just like a ping that is not acknowledged.
+            self.ping_due = true;
+            self.timer.reset(self.config.timeout);
+        }
+
+        if self.ping_due && self.sending.is_none() && self.outbound.at_boundary() {
+            self.ping_due = false;
+            self.nonce = self.nonce.wrapping_add(1);
+            self.sending = Some((ping_frame(self.nonce), 0));
+            self.outstanding = Some((self.nonce, Instant::now()));
+        }
+
+        if let Poll::Ready(result) = self.poll_send_ping(cx) {
+            result?
+        }
+        if self.flush {
+            if let Poll::Ready(result) = Pin::new(&mut self.io).poll_flush(cx) {
+                self.flush = false;
+                result?
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the remainder of the ping being sent, if any.
+    fn poll_send_ping(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some((frame, written)) = self.sending {
+            let n = ready!(Pin::new(&mut self.io).poll_write(cx, &frame[written ..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()))
+            }
+            if written + n == HEADER_LEN {
+                self.sending = None;
+                self.flush = true;
+            } else {
+                self.sending = Some((frame, written + n))
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<C: AsyncRead + AsyncWrite + Unpin> AsyncRead for KeepAlive<C> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        this.poll_keep_alive(cx)?;
+        let n = ready!(Pin::new(&mut this.io).poll_read(cx, buf))?;
+
+        let nonce = this.outstanding.map(|(nonce, _)| nonce);
+        let mut acknowledged = false;
+        this.inbound.advance(&buf[.. n], |header| {
+            if header[1] == TYPE_PING
+                && u16::from_be_bytes([header[2], header[3]]) & FLAG_ACK != 0
+                && Some(frame_length(header)) == nonce
+            {
+                acknowledged = true
+            }
+        });
+
+        if acknowledged {
+            if let Some((_, sent)) = this.outstanding.take() {
+                *this.rtt.lock() = Some(sent.elapsed());
+            }
+            this.timer.reset(this.config.interval);
+            // Register the timer with the current task.
+            this.poll_keep_alive(cx)?;
+        }
+
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<C: AsyncWrite + Unpin> AsyncWrite for KeepAlive<C> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_send_ping(cx))?;
+        let n = ready!(Pin::new(&mut this.io).poll_write(cx, buf))?;
+        this.outbound.advance(&buf[.. n], |_| ());
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_send_ping(cx))?;
+        ready!(Pin::new(&mut this.io).poll_flush(cx))?;
+        this.flush = false;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(this.poll_send_ping(cx))?;
+        Pin::new(&mut this.io).poll_close(cx)
+    }
+}
+
+/// Tracks the frame boundaries in a stream of yamux frames.
+#[derive(Debug, Default)]
+struct FrameTracker {
+    /// The frame header read so far.
+    header: [u8; HEADER_LEN],
+    /// The number of bytes of `header` read so far.
+    header_len: usize,
+    /// The number of bytes of the current frame body yet to be read.
+    body_remaining: usize,
+}
+
+impl FrameTracker {
+    /// Whether the stream is in between two frames.
+    fn at_boundary(&self) -> bool {
+        self.header_len == 0 && self.body_remaining == 0
+    }
+
+    /// Advances over the given bytes, calling `on_header` for every
+    /// complete frame header.
+    fn advance(&mut self, mut buf: &[u8], mut on_header: impl FnMut(&[u8; HEADER_LEN])) {
+        while !buf.is_empty() {
+            if self.body_remaining > 0 {
+                let n = cmp::min(self.body_remaining, buf.len());
+                self.body_remaining -= n;
+                buf = &buf[n ..];
+                continue
+            }
+            let n = cmp::min(HEADER_LEN - self.header_len, buf.len());
+            self.header[self.header_len .. self.header_len + n].copy_from_slice(&buf[.. n]);
+            self.header_len += n;
+            buf = &buf[n ..];
+            if self.header_len == HEADER_LEN {
+                self.header_len = 0;
+                on_header(&self.header);
+                if self.header[1] == TYPE_DATA {
+                    self.body_remaining = frame_length(&self.header) as usize
+                }
+            }
+        }
+    }
+}
+
+/// Returns the length field of a frame header, i.e. the body length of
+/// data frames and the nonce of ping frames.
+fn frame_length(header: &[u8; HEADER_LEN]) -> u32 {
+    u32::from_be_bytes([header[8], header[9], header[10], header[11]])
+}
+
+/// Creates a ping request frame on the session stream (id 0).
+fn ping_frame(nonce: u32) -> [u8; HEADER_LEN] {
+    let mut frame = [0; HEADER_LEN];
+    frame[1] = TYPE_PING;
+    frame[2 .. 4].copy_from_slice(&FLAG_SYN.to_be_bytes());
+    frame[8 ..].copy_from_slice(&nonce.to_be_bytes());
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_frame(body: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; HEADER_LEN];
+        frame[4 .. 8].copy_from_slice(&1u32.to_be_bytes());
+        frame[8 .. 12].copy_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[test]
+    fn frame_boundaries() {
+        let mut bytes = data_frame(b"hello");
+        bytes.extend_from_slice(&ping_frame(7));
+        bytes.extend_from_slice(&data_frame(b""));
+
+        // Feed the frames byte by byte, recording the boundaries.
+        let mut tracker = FrameTracker::default();
+        let mut headers = Vec::new();
+        let mut boundaries = Vec::new();
+        for (i, b) in bytes.iter().enumerate() {
+            tracker.advance(&[*b], |h| headers.push(*h));
+            if tracker.at_boundary() {
+                boundaries.push(i + 1)
+            }
+        }
+        assert_eq!(boundaries, vec![HEADER_LEN + 5, 2 * HEADER_LEN + 5, 3 * HEADER_LEN + 5]);
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers[1][1], TYPE_PING);
+        assert_eq!(frame_length(&headers[1]), 7);
+
+        // Feeding all frames at once yields the same headers.
+        let mut tracker = FrameTracker::default();
+        let mut count = 0;
+        tracker.advance(&bytes, |_| count += 1);
+        assert_eq!(count, 3);
+        assert!(tracker.at_boundary());
+    }
+}
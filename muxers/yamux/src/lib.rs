@@ -21,11 +21,14 @@
 //! Implements the Yamux multiplexing protocol for libp2p, see also the
 //! [specification](https://github.com/hashicorp/yamux/blob/master/spec.md).
 
+mod keep_alive;
+
 use futures::{future, prelude::*, ready, stream::{BoxStream, LocalBoxStream}};
 use libp2p_core::muxing::{StreamMuxer, StreamMuxerEvent};
 use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
 use parking_lot::Mutex;
-use std::{fmt, io, iter, pin::Pin, task::{Context, Poll}};
+use keep_alive::{KeepAlive, KeepAliveConfig, Rtt};
+use std::{fmt, io, iter, pin::Pin, task::{Context, Poll}, time::Duration};
 use thiserror::Error;
 
 /// A Yamux connection.
@@ -42,6 +45,8 @@ struct Inner<S> {
     incoming: S,
     /// Handle to control the connection.
     control: yamux::Control,
+    /// The round-trip time measured by keep-alive pings, if enabled.
+    rtt: Option<Rtt>,
 }
 
 /// A token to poll for an outbound substream.
@@ -53,15 +58,25 @@ where
     C: AsyncRead + AsyncWrite + Send + Unpin + 'static
 {
     /// Create a new Yamux connection.
-    fn new(io: C, cfg: yamux::Config, mode: yamux::Mode) -> Self {
-        let conn = yamux::Connection::new(io, cfg, mode);
-        let ctrl = conn.control();
+    fn new(io: C, cfg: yamux::Config, mode: yamux::Mode, keep_alive: Option<KeepAliveConfig>) -> Self {
+        let (stream, ctrl, rtt) = if let Some(keep_alive) = keep_alive {
+            let io = KeepAlive::new(io, keep_alive);
+            let rtt = io.rtt();
+            let conn = yamux::Connection::new(io, cfg, mode);
+            let ctrl = conn.control();
+            (yamux::into_stream(conn).err_into().boxed(), ctrl, Some(rtt))
+        } else {
+            let conn = yamux::Connection::new(io, cfg, mode);
+            let ctrl = conn.control();
+            (yamux::into_stream(conn).err_into().boxed(), ctrl, None)
+        };
         let inner = Inner {
             incoming: Incoming {
-                stream: yamux::into_stream(conn).err_into().boxed(),
+                stream,
                 _marker: std::marker::PhantomData
             },
             control: ctrl,
+            rtt,
         };
         Yamux(Mutex::new(inner))
     }
@@ -72,15 +87,25 @@ where
     C: AsyncRead + AsyncWrite + Unpin + 'static
 {
     /// Create a new Yamux connection (which is ![`Send`]).
-    fn local(io: C, cfg: yamux::Config, mode: yamux::Mode) -> Self {
-        let conn = yamux::Connection::new(io, cfg, mode);
-        let ctrl = conn.control();
+    fn local(io: C, cfg: yamux::Config, mode: yamux::Mode, keep_alive: Option<KeepAliveConfig>) -> Self {
+        let (stream, ctrl, rtt) = if let Some(keep_alive) = keep_alive {
+            let io = KeepAlive::new(io, keep_alive);
+            let rtt = io.rtt();
+            let conn = yamux::Connection::new(io, cfg, mode);
+            let ctrl = conn.control();
+            (yamux::into_stream(conn).err_into().boxed_local(), ctrl, Some(rtt))
+        } else {
+            let conn = yamux::Connection::new(io, cfg, mode);
+            let ctrl = conn.control();
+            (yamux::into_stream(conn).err_into().boxed_local(), ctrl, None)
+        };
         let inner = Inner {
             incoming: LocalIncoming {
-                stream: yamux::into_stream(conn).err_into().boxed_local(),
+                stream,
                 _marker: std::marker::PhantomData
             },
             control: ctrl,
+            rtt,
         };
         Yamux(Mutex::new(inner))
     }
@@ -167,17 +192,31 @@ where
     fn flush_all(&self, _: &mut Context<'_>) -> Poll<YamuxResult<()>> {
         Poll::Ready(Ok(()))
     }
+
+    fn rtt(&self) -> Option<Duration> {
+        self.0.lock().rtt.as_ref().and_then(|rtt| *rtt.lock())
+    }
 }
 
 /// The yamux configuration.
+///
+/// The defaults are those of the `yamux` crate, except that the window update
+/// mode is [`WindowUpdateMode::on_read`], so that applications slow to read
+/// from a substream exert back-pressure on the remote. On links with a high
+/// bandwidth-delay product, the receive window and buffer sizes should be
+/// increased for the desired throughput.
+///
+/// Keep-alive pings are disabled by default, see [`YamuxConfig::set_keep_alive`].
 #[derive(Clone)]
 pub struct YamuxConfig {
     inner: yamux::Config,
-    mode: Option<yamux::Mode>
+    mode: Option<yamux::Mode>,
+    keep_alive: Option<KeepAliveConfig>
 }
 
 /// The window update mode determines when window updates are
 /// sent to the remote, giving it new credit to send more data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct WindowUpdateMode(yamux::WindowUpdateMode);
 
 impl WindowUpdateMode {
@@ -238,6 +277,10 @@ impl YamuxConfig {
     }
 
     /// Sets the size (in bytes) of the receive window per substream.
+    ///
+    /// The remote can send at most this many bytes on a substream before
+    /// waiting for a window update, i.e. the throughput of a substream is
+    /// bounded by the window size divided by the round-trip time.
     pub fn set_receive_window_size(&mut self, num_bytes: u32) -> &mut Self {
         self.inner.set_receive_window(num_bytes);
         self
@@ -257,11 +300,29 @@ impl YamuxConfig {
 
     /// Sets the window update mode that determines when the remote
     /// is given new credit for sending more data.
+    ///
+    /// Defaults to [`WindowUpdateMode::on_read`].
     pub fn set_window_update_mode(&mut self, mode: WindowUpdateMode) -> &mut Self {
         self.inner.set_window_update_mode(mode.0);
         self
     }
 
+    /// Enables keep-alive pings at the yamux layer.
+    ///
+    /// A yamux ping frame is sent `interval` after the previous ping has been
+    /// acknowledged. If a ping is not acknowledged within `timeout`, the
+    /// connection fails with an [`io::ErrorKind::TimedOut`] error. The
+    /// measured round-trip time is reported by [`StreamMuxer::rtt`].
+    ///
+    /// Unlike the ping protocol, these pings do not require a substream or
+    /// any behaviour and keep idle connections, e.g. NAT bindings of relayed
+    /// connections, open. They do not keep a connection alive from the point
+    /// of view of the connection handlers, however.
+    pub fn set_keep_alive(&mut self, interval: Duration, timeout: Duration) -> &mut Self {
+        self.keep_alive = Some(KeepAliveConfig { interval, timeout });
+        self
+    }
+
     /// Converts the config into a [`YamuxLocalConfig`] for use with upgrades
     /// of I/O streams that are ![`Send`].
     pub fn into_local(self) -> YamuxLocalConfig {
@@ -275,7 +336,10 @@ impl Default for YamuxConfig {
         // For conformity with mplex, read-after-close on a multiplexed
         // connection is never permitted and not configurable.
         inner.set_read_after_close(false);
-        YamuxConfig { inner, mode: None }
+        // Let slow readers of a substream exert back-pressure on the remote,
+        // rather than buffering up to the maximum buffer size.
+        inner.set_window_update_mode(yamux::WindowUpdateMode::OnRead);
+        YamuxConfig { inner, mode: None, keep_alive: None }
     }
 }
 
@@ -307,7 +371,7 @@ where
 
     fn upgrade_inbound(self, io: C, _: Self::Info) -> Self::Future {
         let mode = self.mode.unwrap_or(yamux::Mode::Server);
-        future::ready(Ok(Yamux::new(io, self.inner, mode)))
+        future::ready(Ok(Yamux::new(io, self.inner, mode, self.keep_alive)))
     }
 }
 
@@ -322,7 +386,7 @@ where
     fn upgrade_inbound(self, io: C, _: Self::Info) -> Self::Future {
         let cfg = self.0;
         let mode = cfg.mode.unwrap_or(yamux::Mode::Server);
-        future::ready(Ok(Yamux::local(io, cfg.inner, mode)))
+        future::ready(Ok(Yamux::local(io, cfg.inner, mode, cfg.keep_alive)))
     }
 }
 
@@ -336,7 +400,7 @@ where
 
     fn upgrade_outbound(self, io: C, _: Self::Info) -> Self::Future {
         let mode = self.mode.unwrap_or(yamux::Mode::Client);
-        future::ready(Ok(Yamux::new(io, self.inner, mode)))
+        future::ready(Ok(Yamux::new(io, self.inner, mode, self.keep_alive)))
     }
 }
 
@@ -351,7 +415,7 @@ where
     fn upgrade_outbound(self, io: C, _: Self::Info) -> Self::Future {
         let cfg = self.0;
         let mode = cfg.mode.unwrap_or(yamux::Mode::Client);
-        future::ready(Ok(Yamux::local(io, cfg.inner, mode)))
+        future::ready(Ok(Yamux::local(io, cfg.inner, mode, cfg.keep_alive)))
     }
 }
 
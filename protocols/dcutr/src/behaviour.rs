@@ -0,0 +1,318 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::handler;
+use crate::protocol::{inbound, is_relayed, ProtocolError};
+use libp2p_core::{connection::ConnectionId, ConnectedPoint, Endpoint, Multiaddr, PeerId};
+use libp2p_swarm::{
+    DialOpts,
+    DialPeerCondition,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+    ProtocolsHandlerUpgrErr,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error,
+    fmt,
+    task::{Context, Poll},
+};
+
+/// The number of hole punches attempted before giving up on a peer.
+const MAX_NUMBER_OF_UPGRADE_ATTEMPTS: u8 = 3;
+
+/// Event that can be produced by the [`Dcutr`] behaviour.
+#[derive(Debug)]
+pub enum DcutrEvent {
+    /// The local node initiated a hole punch with a peer connected through
+    /// a relay.
+    InitiatedDirectConnectionUpgrade { remote_peer_id: PeerId },
+    /// A peer connected through a relay initiated a hole punch.
+    RemoteInitiatedDirectConnectionUpgrade { remote_peer_id: PeerId },
+    /// A direct connection to a peer has been established.
+    DirectConnectionUpgradeSucceeded { remote_peer_id: PeerId },
+    /// The hole punch with a peer failed for good.
+    DirectConnectionUpgradeFailed { remote_peer_id: PeerId, error: UpgradeError },
+}
+
+/// The reason a hole punch failed.
+#[derive(Debug)]
+pub enum UpgradeError {
+    /// Dialing the remote directly failed.
+    Dial,
+    /// Answering the hole punch request of the remote failed.
+    Inbound(ProtocolError),
+    /// The hole punch request of the local node failed.
+    Outbound(ProtocolsHandlerUpgrErr<ProtocolError>),
+}
+
+impl fmt::Display for UpgradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpgradeError::Dial => f.write_str("Failed to dial remote directly"),
+            UpgradeError::Inbound(e) => write!(f, "Inbound hole punch failed: {}", e),
+            UpgradeError::Outbound(e) => write!(f, "Outbound hole punch failed: {}", e),
+        }
+    }
+}
+
+impl error::Error for UpgradeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            UpgradeError::Dial => None,
+            UpgradeError::Inbound(e) => Some(e),
+            UpgradeError::Outbound(e) => Some(e),
+        }
+    }
+}
+
+/// A hole punch in progress.
+#[derive(Debug)]
+struct Attempt {
+    /// The relayed connection the exchange takes place on.
+    relayed_connection: ConnectionId,
+    /// Whether the local node initiated the hole punch, in which case it
+    /// retries on failure.
+    initiator: bool,
+    attempt: u8,
+}
+
+/// An action of the [`Dcutr`] behaviour waiting to be returned from `poll`.
+enum Action {
+    Done(NetworkBehaviourAction<handler::In, DcutrEvent>),
+    /// Initiate a hole punch, with the addresses of the local node known
+    /// only in `poll`.
+    Connect {
+        peer_id: PeerId,
+        connection: ConnectionId,
+    },
+    /// Answer a hole punch request, with the addresses of the local node
+    /// known only in `poll`.
+    AcceptInboundConnect {
+        peer_id: PeerId,
+        connection: ConnectionId,
+        inbound_connect: inbound::PendingConnect,
+    },
+}
+
+impl From<NetworkBehaviourAction<handler::In, DcutrEvent>> for Action {
+    fn from(action: NetworkBehaviourAction<handler::In, DcutrEvent>) -> Self {
+        Action::Done(action)
+    }
+}
+
+/// A [`NetworkBehaviour`] upgrading relayed connections to direct ones.
+///
+/// Whenever a peer connects to the local node through a relay, the local
+/// node initiates a hole punch: both peers exchange their external
+/// addresses over the relayed connection, measure the round trip time and
+/// dial each other simultaneously. See the [crate documentation](crate)
+/// for the requirements on the transport.
+pub struct Dcutr {
+    /// Hole punches in progress, by remote peer.
+    attempts: HashMap<PeerId, Attempt>,
+    /// The direct connections to each peer.
+    direct_connections: HashMap<PeerId, HashSet<ConnectionId>>,
+    queued_actions: VecDeque<Action>,
+}
+
+impl Dcutr {
+    /// Creates a new `Dcutr` behaviour.
+    pub fn new() -> Self {
+        Dcutr {
+            attempts: HashMap::new(),
+            direct_connections: HashMap::new(),
+            queued_actions: VecDeque::new(),
+        }
+    }
+
+    fn fail(&mut self, remote_peer_id: PeerId, error: UpgradeError) {
+        self.attempts.remove(&remote_peer_id);
+        self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+            DcutrEvent::DirectConnectionUpgradeFailed { remote_peer_id, error }
+        ).into());
+    }
+
+    fn dial(&mut self, peer_id: PeerId, remote_addrs: Vec<Multiaddr>, role: Endpoint) {
+        let opts = DialOpts::peer_id(peer_id)
+            .addresses(remote_addrs)
+            .condition(DialPeerCondition::Always);
+        let opts = match role {
+            Endpoint::Dialer => opts,
+            Endpoint::Listener => opts.override_role(Endpoint::Listener),
+        };
+        self.queued_actions.push_back(NetworkBehaviourAction::Dial { opts }.into());
+    }
+}
+
+impl Default for Dcutr {
+    fn default() -> Self {
+        Dcutr::new()
+    }
+}
+
+/// Whether the connection is relayed.
+fn is_relayed_connection(endpoint: &ConnectedPoint) -> bool {
+    match endpoint {
+        ConnectedPoint::Dialer { address } => is_relayed(address),
+        ConnectedPoint::Listener { local_addr, .. } => is_relayed(local_addr),
+    }
+}
+
+impl NetworkBehaviour for Dcutr {
+    type ProtocolsHandler = handler::Handler;
+    type OutEvent = DcutrEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        handler::Handler::new()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_connection_established(&mut self, peer_id: &PeerId, connection: &ConnectionId, endpoint: &ConnectedPoint) {
+        if !is_relayed_connection(endpoint) {
+            self.direct_connections.entry(*peer_id).or_default().insert(*connection);
+            if self.attempts.remove(peer_id).is_some() {
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    DcutrEvent::DirectConnectionUpgradeSucceeded { remote_peer_id: *peer_id }
+                ).into());
+            }
+            return
+        }
+
+        // The peer that accepted the relayed connection initiates the hole punch.
+        if let ConnectedPoint::Listener { .. } = endpoint {
+            if self.direct_connections.contains_key(peer_id) || self.attempts.contains_key(peer_id) {
+                return
+            }
+            self.attempts.insert(*peer_id, Attempt {
+                relayed_connection: *connection,
+                initiator: true,
+                attempt: 1,
+            });
+            self.queued_actions.push_back(Action::Connect { peer_id: *peer_id, connection: *connection });
+            self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                DcutrEvent::InitiatedDirectConnectionUpgrade { remote_peer_id: *peer_id }
+            ).into());
+        }
+    }
+
+    fn inject_connection_closed(&mut self, peer_id: &PeerId, connection: &ConnectionId, _: &ConnectedPoint) {
+        if let Some(connections) = self.direct_connections.get_mut(peer_id) {
+            connections.remove(connection);
+            if connections.is_empty() {
+                self.direct_connections.remove(peer_id);
+            }
+        }
+
+        if self.attempts.get(peer_id).map_or(false, |a| a.relayed_connection == *connection) {
+            self.attempts.remove(peer_id);
+        }
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        let retry = match self.attempts.get_mut(peer_id) {
+            None => return,
+            Some(attempt) if attempt.initiator && attempt.attempt < MAX_NUMBER_OF_UPGRADE_ATTEMPTS => {
+                attempt.attempt += 1;
+                Some(attempt.relayed_connection)
+            }
+            Some(_) => None,
+        };
+
+        match retry {
+            Some(connection) => {
+                log::debug!("Direct connection to {} failed, retrying hole punch.", peer_id);
+                self.queued_actions.push_back(Action::Connect { peer_id: *peer_id, connection });
+            }
+            None => self.fail(*peer_id, UpgradeError::Dial),
+        }
+    }
+
+    fn inject_event(&mut self, peer_id: PeerId, connection: ConnectionId, event: handler::Event) {
+        match event {
+            handler::Event::InboundConnectReq { inbound_connect } => {
+                self.attempts.entry(peer_id).or_insert(Attempt {
+                    relayed_connection: connection,
+                    initiator: false,
+                    attempt: 1,
+                });
+                self.queued_actions.push_back(Action::AcceptInboundConnect { peer_id, connection, inbound_connect });
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    DcutrEvent::RemoteInitiatedDirectConnectionUpgrade { remote_peer_id: peer_id }
+                ).into());
+            }
+            handler::Event::InboundConnectNegotiated { remote_addrs } => {
+                // The remote dials in about half a round trip from now, the
+                // local node dials right away.
+                self.dial(peer_id, remote_addrs, Endpoint::Dialer);
+            }
+            handler::Event::InboundConnectFailed { error } => {
+                self.fail(peer_id, UpgradeError::Inbound(error));
+            }
+            handler::Event::OutboundConnectNegotiated { remote_addrs } => {
+                // In case of a TCP simultaneous open, the initiator of the
+                // hole punch acts as the listener, see the specification.
+                self.dial(peer_id, remote_addrs, Endpoint::Listener);
+            }
+            handler::Event::OutboundConnectFailed { error } => {
+                self.fail(peer_id, UpgradeError::Outbound(error));
+            }
+        }
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<handler::In, DcutrEvent>>
+    {
+        let action = match self.queued_actions.pop_front() {
+            Some(action) => action,
+            None => return Poll::Pending,
+        };
+
+        let obs_addrs = || params.external_addresses()
+            .map(|r| r.addr)
+            .filter(|a| !is_relayed(a))
+            .collect::<Vec<_>>();
+
+        Poll::Ready(match action {
+            Action::Done(action) => action,
+            Action::Connect { peer_id, connection } => NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::One(connection),
+                event: handler::In::Connect { obs_addrs: obs_addrs() },
+            },
+            Action::AcceptInboundConnect { peer_id, connection, inbound_connect } =>
+                NetworkBehaviourAction::NotifyHandler {
+                    peer_id,
+                    handler: NotifyHandler::One(connection),
+                    event: handler::In::AcceptInboundConnect { inbound_connect, obs_addrs: obs_addrs() },
+                },
+        })
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::protocol::{inbound, outbound, ProtocolError};
+use futures::{future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::Multiaddr;
+use libp2p_swarm::{
+    KeepAlive,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use std::{
+    collections::VecDeque,
+    task::{Context, Poll},
+};
+use void::Void;
+
+/// Instruction of the [`Dcutr`](crate::Dcutr) behaviour to a [`Handler`].
+#[derive(Debug)]
+pub enum In {
+    /// Initiate a hole punch, announcing the given addresses of the local node.
+    Connect { obs_addrs: Vec<Multiaddr> },
+    /// Answer a hole punch request of the remote.
+    AcceptInboundConnect {
+        inbound_connect: inbound::PendingConnect,
+        obs_addrs: Vec<Multiaddr>,
+    },
+}
+
+/// Event produced by a [`Handler`] for the [`Dcutr`](crate::Dcutr) behaviour.
+#[derive(Debug)]
+pub enum Event {
+    /// The remote requested a hole punch.
+    InboundConnectReq { inbound_connect: inbound::PendingConnect },
+    /// The remote signalled the start of the hole punch it requested.
+    InboundConnectNegotiated { remote_addrs: Vec<Multiaddr> },
+    /// The hole punch requested by the remote failed.
+    InboundConnectFailed { error: ProtocolError },
+    /// The remote agreed to the hole punch requested by the local node.
+    OutboundConnectNegotiated { remote_addrs: Vec<Multiaddr> },
+    /// The hole punch requested by the local node failed.
+    OutboundConnectFailed { error: ProtocolsHandlerUpgrErr<ProtocolError> },
+}
+
+/// Protocols handler of the hole punch exchange on a single connection.
+pub struct Handler {
+    queued_events: VecDeque<ProtocolsHandlerEvent<outbound::Upgrade, (), Event, Void>>,
+    /// Futures answering hole punch requests of the remote.
+    inbound_connects: FuturesUnordered<BoxFuture<'static, Result<Vec<Multiaddr>, ProtocolError>>>,
+    /// Number of pending outbound hole punch requests.
+    pending_outbound: usize,
+}
+
+impl Handler {
+    pub(crate) fn new() -> Self {
+        Handler {
+            queued_events: VecDeque::new(),
+            inbound_connects: FuturesUnordered::new(),
+            pending_outbound: 0,
+        }
+    }
+}
+
+impl ProtocolsHandler for Handler {
+    type InEvent = In;
+    type OutEvent = Event;
+    type Error = Void;
+    type InboundProtocol = inbound::Upgrade;
+    type OutboundProtocol = outbound::Upgrade;
+    type OutboundOpenInfo = ();
+    type InboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(inbound::Upgrade, ())
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, inbound_connect: inbound::PendingConnect, (): ()) {
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+            Event::InboundConnectReq { inbound_connect }
+        ));
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, remote_addrs: Vec<Multiaddr>, (): ()) {
+        self.pending_outbound -= 1;
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+            Event::OutboundConnectNegotiated { remote_addrs }
+        ));
+    }
+
+    fn inject_event(&mut self, event: In) {
+        match event {
+            In::Connect { obs_addrs } => {
+                self.pending_outbound += 1;
+                self.queued_events.push_back(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(outbound::Upgrade::new(obs_addrs), ()),
+                });
+            }
+            In::AcceptInboundConnect { inbound_connect, obs_addrs } => {
+                self.inbound_connects.push(inbound_connect.accept(obs_addrs).boxed());
+            }
+        }
+    }
+
+    fn inject_dial_upgrade_error(&mut self, (): (), error: ProtocolsHandlerUpgrErr<ProtocolError>) {
+        self.pending_outbound -= 1;
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+            Event::OutboundConnectFailed { error }
+        ));
+    }
+
+    fn inject_listen_upgrade_error(&mut self, (): (), error: ProtocolsHandlerUpgrErr<ProtocolError>) {
+        log::debug!("Inbound hole punch request failed: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        // The exchange keeps the relayed connection alive, which is
+        // otherwise left to the other protocols.
+        if self.pending_outbound > 0 || !self.inbound_connects.is_empty() || !self.queued_events.is_empty() {
+            KeepAlive::Yes
+        } else {
+            KeepAlive::No
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event)
+        }
+
+        if let Poll::Ready(Some(result)) = self.inbound_connects.poll_next_unpin(cx) {
+            let event = match result {
+                Ok(remote_addrs) => Event::InboundConnectNegotiated { remote_addrs },
+                Err(error) => Event::InboundConnectFailed { error },
+            };
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(event))
+        }
+
+        Poll::Pending
+    }
+}
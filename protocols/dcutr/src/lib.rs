@@ -0,0 +1,63 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Implementation of the [Direct Connection Upgrade through Relay] protocol.
+//!
+//! Two nodes that are both behind a NAT can connect to each other through a
+//! relay, see `libp2p-relay`. The [`Dcutr`] behaviour then attempts to
+//! upgrade such a relayed connection to a direct one by *hole punching*:
+//! both nodes exchange their external addresses over the relayed
+//! connection, measure the round trip time between them and dial each other
+//! at the same moment, so that each NAT sees an outgoing connection before
+//! the incoming one arrives.
+//!
+//! The node that accepted the relayed connection initiates the exchange and
+//! retries a few times on failure. The outcome is reported through
+//! [`DcutrEvent`]s.
+//!
+//! # Requirements
+//!
+//! - The addresses announced to the remote are the external addresses of
+//!   the local node, e.g. as reported by the `libp2p-identify` protocol and
+//!   added with `Swarm::add_external_address`. Relayed addresses are never
+//!   announced.
+//!
+//! - For TCP, the dials have to originate from the port the node listens
+//!   on, which requires enabling port reuse on the transport with
+//!   `TcpConfig::port_reuse(true)`.
+//!
+//! - A TCP simultaneous open leaves both nodes believing to be the dialer of
+//!   the resulting connection. To still agree on the roles of the connection
+//!   upgrade, the node initiating the exchange dials with the role of a
+//!   listener, as mandated by the specification, see `DialOpts::override_role`.
+//!
+//! [Direct Connection Upgrade through Relay]: https://github.com/libp2p/specs/blob/master/relay/DCUtR.md
+
+mod behaviour;
+mod handler;
+mod protocol;
+
+mod message_proto {
+    include!(concat!(env!("OUT_DIR"), "/message_proto.rs"));
+}
+
+pub use behaviour::{Dcutr, DcutrEvent, UpgradeError};
+pub use protocol::{ProtocolError, PROTOCOL_NAME};
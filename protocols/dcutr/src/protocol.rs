@@ -0,0 +1,160 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::message_proto;
+use futures::prelude::*;
+use libp2p_core::{multiaddr::Protocol, upgrade, Multiaddr};
+use prost::Message;
+use std::{convert::TryFrom, error, fmt, io};
+
+pub mod inbound;
+pub mod outbound;
+
+/// Protocol name of the direct connection upgrade through relay protocol.
+pub const PROTOCOL_NAME: &[u8] = b"/libp2p/dcutr";
+
+/// The maximum size of a hole punch message.
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Error of the exchange on a relayed connection.
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// I/O error on the substream.
+    Io(io::Error),
+    /// The remote sent a message that could not be decoded or was of an
+    /// unexpected type.
+    Malformed(String),
+    /// The remote did not announce any address to dial it on.
+    NoAddresses,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(f, "I/O error: {}", e),
+            ProtocolError::Malformed(e) => write!(f, "Malformed message: {}", e),
+            ProtocolError::NoAddresses => f.write_str("Remote announced no addresses"),
+        }
+    }
+}
+
+impl error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ProtocolError::Io(e) => Some(e),
+            ProtocolError::Malformed(_) => None,
+            ProtocolError::NoAddresses => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        ProtocolError::Io(e)
+    }
+}
+
+impl From<upgrade::ReadOneError> for ProtocolError {
+    fn from(e: upgrade::ReadOneError) -> Self {
+        match e {
+            upgrade::ReadOneError::Io(e) => ProtocolError::Io(e),
+            e => ProtocolError::Malformed(e.to_string()),
+        }
+    }
+}
+
+/// Reads a hole punch message of the given type from the substream.
+async fn read_message<S>(substream: &mut S, expected: message_proto::hole_punch::Type)
+    -> Result<message_proto::HolePunch, ProtocolError>
+where
+    S: AsyncRead + Unpin,
+{
+    let bytes = upgrade::read_one(substream, MAX_MESSAGE_SIZE).await?;
+    let msg = message_proto::HolePunch::decode(&bytes[..])
+        .map_err(|e| ProtocolError::Malformed(e.to_string()))?;
+
+    if message_proto::hole_punch::Type::from_i32(msg.r#type) != Some(expected) {
+        return Err(ProtocolError::Malformed("Unexpected message type".into()))
+    }
+
+    Ok(msg)
+}
+
+/// Writes a hole punch message of the given type to the substream.
+async fn write_message<S>(
+    substream: &mut S,
+    r#type: message_proto::hole_punch::Type,
+    obs_addrs: &[Multiaddr],
+) -> Result<(), io::Error>
+where
+    S: AsyncWrite + Unpin,
+{
+    let msg = message_proto::HolePunch {
+        r#type: r#type.into(),
+        obs_addrs: obs_addrs.iter().map(|a| a.to_vec()).collect(),
+    };
+    let mut bytes = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+    upgrade::write_with_len_prefix(substream, bytes).await
+}
+
+/// Parses the addresses announced by the remote, skipping invalid and
+/// relayed ones.
+fn parse_obs_addrs(obs_addrs: Vec<Vec<u8>>) -> Result<Vec<Multiaddr>, ProtocolError> {
+    let addrs = obs_addrs
+        .into_iter()
+        .filter_map(|a| Multiaddr::try_from(a).ok())
+        .filter(|a| !is_relayed(a))
+        .collect::<Vec<_>>();
+
+    if addrs.is_empty() {
+        return Err(ProtocolError::NoAddresses)
+    }
+
+    Ok(addrs)
+}
+
+/// Whether the address is a relayed, i.e. `/p2p-circuit`, address.
+pub(crate) fn is_relayed(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| p == Protocol::P2pCircuit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_obs_addrs_skips_invalid_and_relayed() {
+        let direct: Multiaddr = "/ip4/1.2.3.4/tcp/4001".parse().unwrap();
+        let relayed: Multiaddr = "/ip4/5.6.7.8/tcp/4001/p2p-circuit".parse().unwrap();
+        let addrs = parse_obs_addrs(vec![direct.to_vec(), relayed.to_vec(), vec![0xff, 0xff]]).unwrap();
+        assert_eq!(addrs, vec![direct]);
+    }
+
+    #[test]
+    fn parse_obs_addrs_requires_direct_address() {
+        let relayed: Multiaddr = "/ip4/5.6.7.8/tcp/4001/p2p-circuit".parse().unwrap();
+        match parse_obs_addrs(vec![relayed.to_vec()]) {
+            Err(ProtocolError::NoAddresses) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::message_proto::hole_punch::Type;
+use crate::protocol::{parse_obs_addrs, read_message, write_message, ProtocolError, PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, Multiaddr};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{fmt, iter};
+
+/// Upgrade for inbound hole punch requests, used by the peer that opened
+/// the relayed connection.
+#[derive(Debug, Clone)]
+pub struct Upgrade;
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::InboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = PendingConnect;
+    type Error = ProtocolError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            let msg = read_message(&mut substream, Type::Connect).await?;
+            let remote_obs_addrs = parse_obs_addrs(msg.obs_addrs)?;
+            Ok(PendingConnect { substream, remote_obs_addrs })
+        }.boxed()
+    }
+}
+
+/// A hole punch request of the remote, to be answered with the addresses
+/// of the local node.
+pub struct PendingConnect {
+    substream: NegotiatedSubstream,
+    remote_obs_addrs: Vec<Multiaddr>,
+}
+
+impl fmt::Debug for PendingConnect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingConnect")
+            .field("remote_obs_addrs", &self.remote_obs_addrs)
+            .finish()
+    }
+}
+
+impl PendingConnect {
+    /// Answers the request with the given addresses of the local node and
+    /// waits for the remote to signal the start of the hole punch.
+    ///
+    /// Returns the addresses of the remote to dial right away.
+    pub async fn accept(mut self, local_obs_addrs: Vec<Multiaddr>) -> Result<Vec<Multiaddr>, ProtocolError> {
+        write_message(&mut self.substream, Type::Connect, &local_obs_addrs).await?;
+        read_message(&mut self.substream, Type::Sync).await?;
+        Ok(self.remote_obs_addrs)
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::message_proto::hole_punch::Type;
+use crate::protocol::{parse_obs_addrs, read_message, write_message, ProtocolError, PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, Multiaddr};
+use libp2p_swarm::NegotiatedSubstream;
+use std::iter;
+use wasm_timer::{Delay, Instant};
+
+/// Upgrade for outbound hole punch requests, used by the peer that accepted
+/// the relayed connection.
+///
+/// The upgrade measures the round trip time of the exchange and resolves
+/// half of it after sending the final `SYNC`, i.e. at about the time the
+/// remote receives it and dials, so that both dials happen simultaneously.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    obs_addrs: Vec<Multiaddr>,
+}
+
+impl Upgrade {
+    /// Creates an upgrade announcing the given addresses of the local node.
+    pub fn new(obs_addrs: Vec<Multiaddr>) -> Self {
+        Upgrade { obs_addrs }
+    }
+}
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::OutboundUpgrade<NegotiatedSubstream> for Upgrade {
+    /// The addresses of the remote to dial.
+    type Output = Vec<Multiaddr>;
+    type Error = ProtocolError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            write_message(&mut substream, Type::Connect, &self.obs_addrs).await?;
+            let sent = Instant::now();
+            let msg = read_message(&mut substream, Type::Connect).await?;
+            let rtt = sent.elapsed();
+            let remote_obs_addrs = parse_obs_addrs(msg.obs_addrs)?;
+
+            write_message(&mut substream, Type::Sync, &[]).await?;
+            substream.close().await?;
+
+            Delay::new(rtt / 2).await?;
+
+            Ok(remote_obs_addrs)
+        }.boxed()
+    }
+}
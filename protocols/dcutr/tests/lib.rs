@@ -0,0 +1,219 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Integration tests for the hole punch of the `Dcutr` behaviour.
+
+use futures::{
+    executor::block_on,
+    future::{self, BoxFuture, Either},
+    prelude::*,
+    stream::BoxStream,
+};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{
+        self,
+        memory::{Channel, MemoryTransportError},
+        ListenerEvent,
+        MemoryTransport,
+        Transport,
+        TransportError,
+    },
+    upgrade,
+    ConnectedPoint,
+    PeerId,
+};
+use libp2p_dcutr::{Dcutr, DcutrEvent};
+use libp2p_noise as noise;
+use libp2p_swarm::{AddressScore, Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+#[test]
+fn simultaneous_open_negotiates_roles() {
+    let rendezvous = Rendezvous::default();
+    let (a_peer_id, mut a) = build_swarm(rendezvous.clone());
+    let (b_peer_id, mut b) = build_swarm(rendezvous);
+
+    // `b` accepts the relayed connection and hence initiates the hole punch.
+    let relayed_addr = Multiaddr::from(Protocol::Memory(rand::random::<u64>()))
+        .with(Protocol::P2pCircuit);
+    Swarm::listen_on(&mut b, relayed_addr.clone()).unwrap();
+    Swarm::dial_addr(&mut a, relayed_addr).unwrap();
+
+    let mut a_direct = None;
+    let mut b_direct = None;
+    let mut b_initiated = false;
+    block_on(async {
+        while a_direct.is_none() || b_direct.is_none() {
+            match future::select(Box::pin(a.next_event()), Box::pin(b.next_event())).await {
+                Either::Left((event, _)) => match event {
+                    SwarmEvent::Behaviour(DcutrEvent::InitiatedDirectConnectionUpgrade { .. }) =>
+                        panic!("Hole punch initiated by the dialer of the relayed connection"),
+                    SwarmEvent::Behaviour(DcutrEvent::DirectConnectionUpgradeFailed { error, .. }) =>
+                        panic!("Hole punch failed: {}", error),
+                    SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } if !is_relayed(&endpoint) => {
+                        assert_eq!(peer_id, b_peer_id);
+                        a_direct = Some(endpoint);
+                    }
+                    _ => {}
+                },
+                Either::Right((event, _)) => match event {
+                    SwarmEvent::Behaviour(DcutrEvent::InitiatedDirectConnectionUpgrade { remote_peer_id }) => {
+                        assert_eq!(remote_peer_id, a_peer_id);
+                        b_initiated = true;
+                    }
+                    SwarmEvent::Behaviour(DcutrEvent::DirectConnectionUpgradeFailed { error, .. }) =>
+                        panic!("Hole punch failed: {}", error),
+                    SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } if !is_relayed(&endpoint) => {
+                        assert_eq!(peer_id, a_peer_id);
+                        b_direct = Some(endpoint);
+                    }
+                    _ => {}
+                },
+            }
+        }
+    });
+
+    assert!(b_initiated);
+    // Both nodes dialed the single direct connection, yet completed the
+    // Noise handshake on it, which requires exactly one of them to act as
+    // the responder.
+    assert!(a_direct.unwrap().is_dialer());
+    assert!(b_direct.unwrap().is_dialer());
+}
+
+fn is_relayed(endpoint: &ConnectedPoint) -> bool {
+    let addr = match endpoint {
+        ConnectedPoint::Dialer { address } => address,
+        ConnectedPoint::Listener { local_addr, .. } => local_addr,
+    };
+    addr.iter().any(|p| p == Protocol::P2pCircuit)
+}
+
+/// Pending simultaneous opens, by the pair of dialed addresses, with the
+/// address on which the first dialer awaits the connection.
+type Rendezvous = Arc<Mutex<HashMap<(Multiaddr, Multiaddr), Multiaddr>>>;
+
+/// A transport relaying connections to addresses ending in `/p2p-circuit`
+/// over memory and simulating a TCP simultaneous open for all other
+/// addresses: two nodes dialing each other end up with a single connection,
+/// which both consider outbound.
+#[derive(Clone)]
+struct HolePunchTransport {
+    /// The direct address of the local node.
+    local_addr: Multiaddr,
+    rendezvous: Rendezvous,
+}
+
+fn strip_circuit(addr: &Multiaddr) -> Option<Multiaddr> {
+    let mut addr = addr.clone();
+    match addr.pop() {
+        Some(Protocol::P2pCircuit) => Some(addr),
+        _ => None,
+    }
+}
+
+impl Transport for HolePunchTransport {
+    type Output = Channel<Vec<u8>>;
+    type Error = MemoryTransportError;
+    type Listener = BoxStream<'static, Result<ListenerEvent<Self::ListenerUpgrade, Self::Error>, Self::Error>>;
+    type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let memory_addr = match strip_circuit(&addr) {
+            Some(a) => a,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+        let listener = MemoryTransport::default().listen_on(memory_addr)?;
+        Ok(listener.map_ok(|event| match event {
+            ListenerEvent::NewAddress(a) => ListenerEvent::NewAddress(a.with(Protocol::P2pCircuit)),
+            ListenerEvent::Upgrade { upgrade, local_addr, remote_addr } => ListenerEvent::Upgrade {
+                upgrade: upgrade.boxed(),
+                local_addr: local_addr.with(Protocol::P2pCircuit),
+                remote_addr,
+            },
+            ListenerEvent::AddressExpired(a) => ListenerEvent::AddressExpired(a.with(Protocol::P2pCircuit)),
+            ListenerEvent::Error(e) => ListenerEvent::Error(e),
+        }).boxed())
+    }
+
+    fn dial(self, mut addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        if let Some(memory_addr) = strip_circuit(&addr) {
+            return Ok(MemoryTransport::default().dial(memory_addr)?.boxed())
+        }
+        if let Some(Protocol::P2p(_)) = addr.iter().last() {
+            addr.pop();
+        }
+
+        let key = if self.local_addr < addr {
+            (self.local_addr, addr)
+        } else {
+            (addr, self.local_addr)
+        };
+        let mut rendezvous = self.rendezvous.lock().unwrap();
+        // The remote dialed first and awaits the connection.
+        if let Some(remote) = rendezvous.remove(&key) {
+            return Ok(MemoryTransport::default().dial(remote)?.boxed())
+        }
+        let local = Multiaddr::from(Protocol::Memory(rand::random::<u64>()));
+        let mut listener = MemoryTransport::default().listen_on(local.clone())?;
+        rendezvous.insert(key, local);
+        Ok(async move {
+            while let Some(event) = listener.try_next().await? {
+                if let ListenerEvent::Upgrade { upgrade, .. } = event {
+                    return upgrade.await
+                }
+            }
+            Err(MemoryTransportError::Unreachable)
+        }.boxed())
+    }
+
+    fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+}
+
+fn build_swarm(rendezvous: Rendezvous) -> (PeerId, Swarm<Dcutr>) {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = id_keys.public().into_peer_id();
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(&id_keys).unwrap();
+
+    let local_addr = Multiaddr::from(Protocol::Memory(rand::random::<u64>()));
+    let transport: transport::Boxed<(PeerId, StreamMuxerBox)> = HolePunchTransport {
+        local_addr: local_addr.clone(),
+        rendezvous,
+    }
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(yamux::YamuxConfig::default())
+        .boxed();
+
+    let mut swarm = Swarm::new(transport, Dcutr::new(), peer_id);
+    Swarm::add_external_address(&mut swarm, local_addr, AddressScore::Infinite);
+    (peer_id, swarm)
+}
@@ -0,0 +1,86 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::topic::Topic;
+use regex::Regex;
+use std::collections::HashSet;
+
+/// Filter deciding which topics the local node accepts subscriptions to,
+/// both from remotes and from itself.
+///
+/// Subscriptions of remotes to rejected topics are ignored and messages
+/// exclusively published to rejected topics are neither reported nor
+/// propagated.
+#[derive(Debug, Clone)]
+pub enum TopicFilter {
+    /// All topics are accepted.
+    AllowAll,
+    /// Only the topics with one of the given ids are accepted.
+    AllowList(HashSet<String>),
+    /// Only the topics whose id matches the regular expression are accepted.
+    Regex(Regex),
+}
+
+impl TopicFilter {
+    /// Creates a filter accepting only the given topics.
+    pub fn allow_list<I, T>(topics: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Topic>,
+    {
+        TopicFilter::AllowList(topics.into_iter().map(|t| String::from(Into::<Topic>::into(t))).collect())
+    }
+
+    /// Whether the given topic is accepted by the filter.
+    pub fn allows(&self, topic: &Topic) -> bool {
+        match self {
+            TopicFilter::AllowAll => true,
+            TopicFilter::AllowList(topics) => topics.contains(topic.id()),
+            TopicFilter::Regex(regex) => regex.is_match(topic.id()),
+        }
+    }
+}
+
+impl Default for TopicFilter {
+    fn default() -> Self {
+        TopicFilter::AllowAll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allow_list() {
+        let filter = TopicFilter::allow_list(vec![Topic::new("chat")]);
+        assert!(filter.allows(&Topic::new("chat")));
+        assert!(!filter.allows(&Topic::new("junk")));
+    }
+
+    #[test]
+    fn regex() {
+        let filter = TopicFilter::Regex(Regex::new("^blocks/[0-9]+$").unwrap());
+        assert!(filter.allows(&Topic::new("blocks/42")));
+        assert!(!filter.allows(&Topic::new("blocks/42/junk")));
+        assert!(!filter.allows(&Topic::new("chat")));
+    }
+}
@@ -30,10 +30,12 @@ use libp2p_swarm::{
     PollParameters,
     ProtocolsHandler,
     OneShotHandler,
+    OneShotHandlerConfig,
     NotifyHandler,
     DialPeerCondition,
+    SubstreamProtocol,
 };
-use log::warn;
+use log::{debug, warn};
 use smallvec::SmallVec;
 use std::{collections::VecDeque, iter};
 use std::collections::hash_map::{DefaultHasher, HashMap};
@@ -116,12 +118,17 @@ impl Floodsub {
 
     /// Subscribes to a topic.
     ///
-    /// Returns true if the subscription worked. Returns false if we were already subscribed.
+    /// Returns true if the subscription worked. Returns false if we were already subscribed
+    /// or the topic is rejected by the configured [`TopicFilter`](crate::TopicFilter).
     pub fn subscribe(&mut self, topic: Topic) -> bool {
         if self.subscribed_topics.iter().any(|t| t.id() == topic.id()) {
             return false;
         }
 
+        if !self.config.topic_filter.allows(&topic) {
+            return false;
+        }
+
         for peer in self.connected_peers.keys() {
             self.events.push_back(NetworkBehaviourAction::NotifyHandler {
                 peer_id: *peer,
@@ -246,7 +253,9 @@ impl NetworkBehaviour for Floodsub {
     type OutEvent = FloodsubEvent;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        Default::default()
+        let protocol = FloodsubProtocol::new()
+            .with_max_message_size(self.config.max_message_size);
+        OneShotHandler::new(SubstreamProtocol::new(protocol, ()), OneShotHandlerConfig::default())
     }
 
     fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
@@ -307,7 +316,17 @@ impl NetworkBehaviour for Floodsub {
                 .expect("connected_peers is kept in sync with the peers we are connected to; we are guaranteed to only receive events from connected peers; QED");
             match subscription.action {
                 FloodsubSubscriptionAction::Subscribe => {
+                    if !self.config.topic_filter.allows(&subscription.topic) {
+                        debug!("Ignoring subscription of {} to filtered topic {:?}",
+                            propagation_source, subscription.topic);
+                        continue;
+                    }
                     if !remote_peer_topics.contains(&subscription.topic) {
+                        if remote_peer_topics.len() >= self.config.max_topics_per_peer {
+                            warn!("Ignoring subscription of {} to {:?}: subscribed to too many topics",
+                                propagation_source, subscription.topic);
+                            continue;
+                        }
                         remote_peer_topics.push(subscription.topic.clone());
                     }
                     self.events.push_back(NetworkBehaviourAction::GenerateEvent(FloodsubEvent::Subscribed {
@@ -331,6 +350,13 @@ impl NetworkBehaviour for Floodsub {
         let mut rpcs_to_dispatch: Vec<(PeerId, FloodsubRpc)> = Vec::new();
 
         for message in event.messages {
+            // Skip the messages that aren't published to any topic we accept.
+            if !message.topics.iter().any(|t| self.config.topic_filter.allows(t)) {
+                debug!("Ignoring message from {} to filtered topics {:?}",
+                    propagation_source, message.topics);
+                continue;
+            }
+
             // Use `self.received` to skip the messages that we have already received in the past.
             // Note that this can result in false positives.
             match self.received.test_and_add(&message) {
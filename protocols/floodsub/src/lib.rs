@@ -25,6 +25,7 @@ use libp2p_core::PeerId;
 
 pub mod protocol;
 
+mod filter;
 mod layer;
 mod topic;
 
@@ -32,6 +33,7 @@ mod rpc_proto {
     include!(concat!(env!("OUT_DIR"), "/floodsub.pb.rs"));
 }
 
+pub use self::filter::TopicFilter;
 pub use self::layer::{Floodsub, FloodsubEvent};
 pub use self::protocol::{FloodsubMessage, FloodsubRpc};
 pub use self::topic::Topic;
@@ -44,13 +46,29 @@ pub struct FloodsubConfig {
     /// `true` if messages published by local node should be propagated as messages received from
     /// the network, `false` by default.
     pub subscribe_local_messages: bool,
+
+    /// The maximum size in bytes of an RPC received from a remote, 2048 by default.
+    ///
+    /// Larger RPCs are discarded without closing the connection.
+    pub max_message_size: usize,
+
+    /// The maximum number of topics a remote can be subscribed to, 1024 by default.
+    ///
+    /// Subscriptions of a remote beyond this number are ignored.
+    pub max_topics_per_peer: usize,
+
+    /// Filter for the topics we accept subscriptions to, accepting all topics by default.
+    pub topic_filter: TopicFilter,
 }
 
 impl FloodsubConfig {
     pub fn new(local_peer_id: PeerId) -> Self {
         Self {
             local_peer_id,
-            subscribe_local_messages: false
+            subscribe_local_messages: false,
+            max_message_size: 2048,
+            max_topics_per_peer: 1024,
+            topic_filter: TopicFilter::AllowAll,
         }
     }
 }
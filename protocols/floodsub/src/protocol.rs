@@ -26,13 +26,28 @@ use std::{error, fmt, io, iter, pin::Pin};
 use futures::{Future, io::{AsyncRead, AsyncWrite}};
 
 /// Implementation of `ConnectionUpgrade` for the floodsub protocol.
-#[derive(Debug, Clone, Default)]
-pub struct FloodsubProtocol {}
+#[derive(Debug, Clone)]
+pub struct FloodsubProtocol {
+    /// The maximum size in bytes of an inbound RPC.
+    max_message_size: usize,
+}
 
 impl FloodsubProtocol {
     /// Builds a new `FloodsubProtocol`.
     pub fn new() -> FloodsubProtocol {
-        FloodsubProtocol {}
+        FloodsubProtocol { max_message_size: 2048 }
+    }
+
+    /// Sets the maximum size in bytes of an inbound RPC.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+}
+
+impl Default for FloodsubProtocol {
+    fn default() -> Self {
+        FloodsubProtocol::new()
     }
 }
 
@@ -55,7 +70,7 @@ where
 
     fn upgrade_inbound(self, mut socket: TSocket, _: Self::Info) -> Self::Future {
         Box::pin(async move {
-            let packet = upgrade::read_one(&mut socket, 2048).await?;
+            let packet = upgrade::read_one(&mut socket, self.max_message_size).await?;
             let rpc = rpc_proto::Rpc::decode(&packet[..])?;
 
             let mut messages = Vec::with_capacity(rpc.publish.len());
@@ -31,7 +31,7 @@ use std::{
     time::Duration,
 };
 
-use futures::StreamExt;
+use futures::{channel::mpsc, StreamExt};
 use log::{debug, error, info, trace, warn};
 use prost::Message;
 use rand::{seq::SliceRandom, seq::IteratorRandom, thread_rng, RngCore};
@@ -52,6 +52,7 @@ use crate::error::{PublishError, SubscriptionError, ValidationError};
 use crate::gossip_promises::GossipPromises;
 use crate::handler::{GossipsubHandler, HandlerEvent};
 use crate::mcache::MessageCache;
+use crate::metrics::{CacheStats, GossipsubMetrics, TopicStats};
 use crate::peer_score::{PeerScore, PeerScoreParams, PeerScoreThresholds, RejectReason};
 use crate::protocol::SIGNING_PREFIX;
 use crate::subscription_filter::{AllowAllSubscriptionFilter, TopicSubscriptionFilter};
@@ -61,6 +62,7 @@ use crate::transform::{DataTransform, IdentityTransform};
 use crate::types::{
     FastMessageId, GossipsubControlAction, GossipsubMessage, GossipsubSubscription,
     GossipsubSubscriptionAction, MessageAcceptance, MessageId, PeerInfo, RawGossipsubMessage,
+    ValidationHandle, ValidationResult,
 };
 use crate::types::{GossipsubRpc, PeerKind};
 use crate::{rpc_proto, TopicScoreParams};
@@ -228,6 +230,17 @@ pub struct Gossipsub<
     /// Information used for publishing messages.
     publish_config: PublishConfig,
 
+    /// The sending side of the channel of validation results reported through
+    /// [`ValidationHandle`]s.
+    validation_sender: mpsc::UnboundedSender<ValidationResult>,
+
+    /// Validation results reported through [`ValidationHandle`]s, applied when polled.
+    validation_receiver: mpsc::UnboundedReceiver<ValidationResult>,
+
+    /// Information used for publishing messages on topics that override the
+    /// [`MessageAuthenticity`] of the behaviour.
+    topic_publish_config: HashMap<TopicHash, PublishConfig>,
+
     /// An LRU Time cache for storing seen messages (based on their ID). This cache prevents
     /// duplicates from being propagated to the application and on the network.
     duplicate_cache: DuplicateCache<MessageId>,
@@ -294,6 +307,9 @@ pub struct Gossipsub<
     /// Counts the number of `IWANT` that we sent the each peer since the last heartbeat.
     count_sent_iwant: HashMap<PeerId, usize>,
 
+    /// Cumulative counters of the exchanged gossip.
+    metrics: GossipsubMetrics,
+
     /// Short term cache for published messsage ids. This is used for penalizing peers sending
     /// our own messages back if the messages are anonymous or use a random author.
     published_message_ids: DuplicateCache<MessageId>,
@@ -393,10 +409,15 @@ where
 
         // Set up message publishing parameters.
 
+        let (validation_sender, validation_receiver) = mpsc::unbounded();
+
         Ok(Gossipsub {
             events: VecDeque::new(),
             control_pool: HashMap::new(),
             publish_config: privacy.into(),
+            validation_sender,
+            validation_receiver,
+            topic_publish_config: HashMap::new(),
             duplicate_cache: DuplicateCache::new(config.duplicate_cache_time()),
             fast_messsage_id_cache: TimeCache::new(config.duplicate_cache_time()),
             topic_peers: HashMap::new(),
@@ -422,6 +443,7 @@ where
             peer_score: None,
             count_received_ihave: HashMap::new(),
             count_sent_iwant: HashMap::new(),
+            metrics: GossipsubMetrics::default(),
             peer_protocols: HashMap::new(),
             published_message_ids: DuplicateCache::new(config.published_message_ids_cache_time()),
             config,
@@ -471,6 +493,35 @@ where
         self.peer_protocols.iter()
     }
 
+    /// Returns the cumulative counters of the gossip exchanged with other peers.
+    pub fn metrics(&self) -> &GossipsubMetrics {
+        &self.metrics
+    }
+
+    /// Returns the current occupancy of the message cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            messages: self.mcache.num_messages(),
+            gossip_messages: self.mcache.num_gossip_messages(),
+            history_windows: self.mcache.num_windows(),
+        }
+    }
+
+    /// Returns a snapshot of the mesh, fanout and subscribed peers of a topic.
+    pub fn topic_stats(&self, topic_hash: &TopicHash) -> TopicStats {
+        let peers = |map: &HashMap<TopicHash, BTreeSet<PeerId>>| {
+            map.get(topic_hash)
+                .map(|peers| peers.iter().cloned().collect())
+                .unwrap_or_default()
+        };
+        TopicStats {
+            topic: topic_hash.clone(),
+            mesh_peers: peers(&self.mesh),
+            fanout_peers: peers(&self.fanout),
+            subscribed_peers: self.topic_peers.get(topic_hash).map_or(0, |p| p.len()),
+        }
+    }
+
     /// Returns the gossipsub score for a given peer, if one exists.
     pub fn peer_score(&self, peer_id: &PeerId) -> Option<f64> {
         self.peer_score
@@ -712,7 +763,10 @@ where
 
         // If the message is anonymous or has a random author add it to the published message ids
         // cache.
-        if let PublishConfig::RandomAuthor | PublishConfig::Anonymous = self.publish_config {
+        if matches!(
+            self.publish_config(&topic_hash),
+            PublishConfig::RandomAuthor | PublishConfig::Anonymous
+        ) {
             if !self.config.allow_self_origin() {
                 self.published_message_ids.insert(msg_id.clone());
             }
@@ -792,6 +846,37 @@ where
         }
     }
 
+    /// Returns a [`ValidationHandle`] to report the validation result of a received message
+    /// when [`GossipsubConfig::validate_messages()`] is `true`.
+    ///
+    /// The handle can be moved to wherever the message is validated, e.g. another task, and
+    /// the result it reports is applied as by [`Gossipsub::report_message_validation_result`]
+    /// the next time the behaviour is polled. Forwarding of the message is deferred until then.
+    pub fn validation_handle(
+        &self,
+        msg_id: &MessageId,
+        propagation_source: &PeerId,
+    ) -> ValidationHandle {
+        ValidationHandle::new(
+            msg_id.clone(),
+            *propagation_source,
+            self.validation_sender.clone(),
+        )
+    }
+
+    /// Applies the validation results reported through [`ValidationHandle`]s.
+    fn apply_validation_results(&mut self, cx: &mut Context<'_>) {
+        while let Poll::Ready(Some((msg_id, propagation_source, acceptance))) =
+            self.validation_receiver.poll_next_unpin(cx)
+        {
+            if let Err(e) =
+                self.report_message_validation_result(&msg_id, &propagation_source, acceptance)
+            {
+                warn!("Failed to forward validated message {}: {:?}", msg_id, e);
+            }
+        }
+    }
+
     /// Adds a new peer to the list of explicitly connected peers.
     pub fn add_explicit_peer(&mut self, peer_id: &PeerId) {
         debug!("Adding explicit peer {}", peer_id);
@@ -1085,6 +1170,10 @@ where
     /// Handles an IHAVE control message. Checks our cache of messages. If the message is unknown,
     /// requests it with an IWANT control message.
     fn handle_ihave(&mut self, peer_id: &PeerId, ihave_msgs: Vec<(TopicHash, Vec<MessageId>)>) {
+        self.metrics.ihave_received += ihave_msgs.len() as u64;
+        self.metrics.ihave_message_ids_received +=
+            ihave_msgs.iter().map(|(_, ids)| ids.len() as u64).sum::<u64>();
+
         // We ignore IHAVE gossip from any peer whose score is below the gossip threshold
         if let (true, score) = self.score_below_threshold(peer_id, |pst| pst.gossip_threshold) {
             debug!(
@@ -1178,6 +1267,9 @@ where
                 peer_id, message_ids
             );
 
+            self.metrics.iwant_sent += 1;
+            self.metrics.iwant_message_ids_sent += message_ids.len() as u64;
+
             Self::control_pool_add(
                 &mut self.control_pool,
                 *peer_id,
@@ -1190,6 +1282,9 @@ where
     /// Handles an IWANT control message. Checks our cache of messages. If the message exists it is
     /// forwarded to the requesting peer.
     fn handle_iwant(&mut self, peer_id: &PeerId, iwant_msgs: Vec<MessageId>) {
+        self.metrics.iwant_received += 1;
+        self.metrics.iwant_message_ids_received += iwant_msgs.len() as u64;
+
         // We ignore IWANT gossip from any peer whose score is below the gossip threshold
         if let (true, score) = self.score_below_threshold(peer_id, |pst| pst.gossip_threshold) {
             debug!(
@@ -1222,10 +1317,11 @@ where
         if !cached_messages.is_empty() {
             debug!("IWANT: Sending cached messages to peer: {:?}", peer_id);
             // Send the messages to the peer
-            let message_list = cached_messages
+            let message_list: Vec<_> = cached_messages
                 .into_iter()
                 .map(|entry| entry.1)
                 .collect();
+            self.metrics.iwant_messages_served += message_list.len() as u64;
             if self
                 .send_message(
                     *peer_id,
@@ -1548,7 +1644,7 @@ where
 
         // reject messages claiming to be from ourselves but not locally published
         let self_published = !self.config.allow_self_origin()
-            && if let Some(own_id) = self.publish_config.get_own_id() {
+            && if let Some(own_id) = self.publish_config(&raw_message.topic).get_own_id() {
                 own_id != propagation_source
                     && raw_message.source.as_ref().map_or(false, |s| s == own_id)
             } else {
@@ -2353,6 +2449,8 @@ where
                 }
 
                 // send an IHAVE message
+                self.metrics.ihave_sent += 1;
+                self.metrics.ihave_message_ids_sent += peer_message_ids.len() as u64;
                 Self::control_pool_add(
                     &mut self.control_pool,
                     peer,
@@ -2513,13 +2611,43 @@ where
         }
     }
 
+    /// Sets the [`MessageAuthenticity`] of messages published on the given topic, e.g. to
+    /// publish anonymously on privacy-sensitive topics while signing all other messages.
+    ///
+    /// As for [`Gossipsub::new`], an error is returned if published messages would be
+    /// rejected by the configured [`ValidationMode`]. Publishing signed and unsigned
+    /// messages therefore requires [`ValidationMode::Permissive`] or [`ValidationMode::None`].
+    pub fn set_topic_authenticity<H: Hasher>(
+        &mut self,
+        topic: &Topic<H>,
+        authenticity: MessageAuthenticity,
+    ) -> Result<(), &'static str> {
+        validate_config(&authenticity, &self.config.validation_mode())?;
+        self.topic_publish_config
+            .insert(topic.hash(), authenticity.into());
+        Ok(())
+    }
+
+    /// Removes the [`MessageAuthenticity`] set for the given topic, if any, such that
+    /// messages on the topic are published with the [`MessageAuthenticity`] of the behaviour.
+    pub fn remove_topic_authenticity<H: Hasher>(&mut self, topic: &Topic<H>) {
+        self.topic_publish_config.remove(&topic.hash());
+    }
+
+    /// Returns the [`PublishConfig`] of messages published on the given topic.
+    fn publish_config(&self, topic: &TopicHash) -> &PublishConfig {
+        self.topic_publish_config
+            .get(topic)
+            .unwrap_or(&self.publish_config)
+    }
+
     /// Constructs a [`RawGossipsubMessage`] performing message signing if required.
     pub(crate) fn build_raw_message(
         &self,
         topic: TopicHash,
         data: Vec<u8>,
     ) -> Result<RawGossipsubMessage, PublishError> {
-        match &self.publish_config {
+        match self.publish_config(&topic) {
             PublishConfig::Signing {
                 ref keypair,
                 author,
@@ -3203,6 +3331,8 @@ where
             Self::OutEvent,
         >,
     > {
+        self.apply_validation_results(cx);
+
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(match event {
                 NetworkBehaviourAction::NotifyHandler {
@@ -3227,9 +3357,15 @@ where
                 NetworkBehaviourAction::DialPeer { peer_id, condition } => {
                     NetworkBehaviourAction::DialPeer { peer_id, condition }
                 }
+                NetworkBehaviourAction::Dial { opts } => {
+                    NetworkBehaviourAction::Dial { opts }
+                }
                 NetworkBehaviourAction::ReportObservedAddr { address, score } => {
                     NetworkBehaviourAction::ReportObservedAddr { address, score }
                 }
+                NetworkBehaviourAction::ReportExternalAddr { address, source } => {
+                    NetworkBehaviourAction::ReportExternalAddr { address, source }
+                }
                 NetworkBehaviourAction::DisconnectPeer { peer_id } => {
                     NetworkBehaviourAction::DisconnectPeer { peer_id }
                 }
@@ -3343,6 +3479,7 @@ impl<C: DataTransform, F: TopicSubscriptionFilter> fmt::Debug for Gossipsub<C, F
             .field("events", &self.events)
             .field("control_pool", &self.control_pool)
             .field("publish_config", &self.publish_config)
+            .field("topic_publish_config", &self.topic_publish_config)
             .field("topic_peers", &self.topic_peers)
             .field("peer_topics", &self.peer_topics)
             .field("explicit_peers", &self.explicit_peers)
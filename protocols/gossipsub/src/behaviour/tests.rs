@@ -858,6 +858,49 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Test that messages on topics with their own `MessageAuthenticity` are published
+    /// accordingly.
+    fn test_topic_authenticity() {
+        let gs_config = GossipsubConfigBuilder::default()
+            .validation_mode(ValidationMode::Permissive)
+            .build()
+            .unwrap();
+        let keypair = libp2p_core::identity::Keypair::generate_secp256k1();
+        let own_id = keypair.public().into_peer_id();
+        let mut gs: Gossipsub = Gossipsub::new(MessageAuthenticity::Signed(keypair), gs_config)
+            .unwrap();
+
+        let signed = Topic::new("signed");
+        let anonymous = Topic::new("anonymous");
+        gs.set_topic_authenticity(&anonymous, MessageAuthenticity::Anonymous)
+            .unwrap();
+
+        let message = gs.build_raw_message(anonymous.hash(), vec![1]).unwrap();
+        assert_eq!(message.source, None);
+        assert_eq!(message.sequence_number, None);
+        assert!(message.signature.is_none());
+
+        let message = gs.build_raw_message(signed.hash(), vec![1]).unwrap();
+        assert_eq!(message.source, Some(own_id));
+        assert!(message.signature.is_some());
+
+        gs.remove_topic_authenticity(&anonymous);
+        let message = gs.build_raw_message(anonymous.hash(), vec![1]).unwrap();
+        assert_eq!(message.source, Some(own_id));
+
+        // Unsigned messages would be rejected in strict validation mode.
+        let keypair = libp2p_core::identity::Keypair::generate_secp256k1();
+        let mut gs: Gossipsub = Gossipsub::new(
+            MessageAuthenticity::Signed(keypair),
+            GossipsubConfig::default(),
+        )
+        .unwrap();
+        assert!(gs
+            .set_topic_authenticity(&anonymous, MessageAuthenticity::RandomAuthor)
+            .is_err());
+    }
+
     #[test]
     /// Test Gossipsub.get_random_peers() function
     fn test_get_random_peers() {
@@ -979,6 +1022,57 @@ mod tests {
         );
     }
 
+    /// Tests that the exchanged gossip and the state of the cache and mesh can be inspected.
+    #[test]
+    fn test_introspection() {
+        let (mut gs, peers, topic_hashes) = inject_nodes1()
+            .peer_no(3)
+            .topics(vec![String::from("topic1")])
+            .to_subscribe(true)
+            .create_network();
+
+        let raw_message = RawGossipsubMessage {
+            source: Some(peers[1].clone()),
+            data: vec![1, 2, 3, 4],
+            sequence_number: Some(1u64),
+            topic: topic_hashes[0].clone(),
+            signature: None,
+            key: None,
+            validated: true,
+        };
+        let message = &gs
+            .data_transform
+            .inbound_transform(raw_message.clone())
+            .unwrap();
+        let msg_id = gs.config.message_id(&message);
+        gs.mcache.put(&msg_id, raw_message);
+
+        let stats = gs.cache_stats();
+        assert_eq!(stats.messages, 1);
+        assert_eq!(stats.gossip_messages, 1);
+        assert_eq!(stats.history_windows, gs.config.history_length());
+
+        gs.handle_iwant(&peers[0], vec![msg_id.clone(), MessageId::new(b"unknown")]);
+        gs.handle_ihave(
+            &peers[1],
+            vec![(topic_hashes[0].clone(), vec![MessageId::new(b"unknown")])],
+        );
+
+        let metrics = gs.metrics();
+        assert_eq!(metrics.iwant_received, 1);
+        assert_eq!(metrics.iwant_message_ids_received, 2);
+        assert_eq!(metrics.iwant_messages_served, 1);
+        assert_eq!(metrics.ihave_received, 1);
+        assert_eq!(metrics.ihave_message_ids_received, 1);
+        assert_eq!(metrics.iwant_sent, 1);
+        assert_eq!(metrics.iwant_message_ids_sent, 1);
+
+        let stats = gs.topic_stats(&topic_hashes[0]);
+        assert_eq!(stats.mesh_peers.len(), 3);
+        assert!(stats.fanout_peers.is_empty());
+        assert_eq!(stats.subscribed_peers, 3);
+    }
+
     /// Tests that messages are sent correctly depending on the shifting of the message cache.
     #[test]
     fn test_handle_iwant_msg_cached_shifted() {
@@ -3502,6 +3596,64 @@ mod tests {
         assert_eq!(gs.peer_score.as_ref().unwrap().0.score(&peers[0]), 0.0);
     }
 
+    #[test]
+    fn test_validation_handle() {
+        let config = GossipsubConfigBuilder::default()
+            .validate_messages()
+            .build()
+            .unwrap();
+
+        let (mut gs, peers, topics) = inject_nodes1()
+            .peer_no(2)
+            .topics(vec!["test".into()])
+            .to_subscribe(true)
+            .gs_config(config.clone())
+            .create_network();
+        flush_events(&mut gs);
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut seq = 0;
+
+        let count_forwarded = |gs: &Gossipsub| {
+            gs.events
+                .iter()
+                .filter(|e| match e {
+                    NetworkBehaviourAction::NotifyHandler { peer_id, event, .. } => {
+                        peer_id == &peers[1] && !proto_to_message(event).messages.is_empty()
+                    }
+                    _ => false,
+                })
+                .count()
+        };
+
+        // An accepted message is forwarded once the result is applied.
+        let m1 = random_message(&mut seq, &topics);
+        gs.handle_received_message(m1.clone(), &peers[0]);
+        let message1 = &gs.data_transform.inbound_transform(m1).unwrap();
+        let msg_id1 = config.message_id(message1);
+        let handle = gs.validation_handle(&msg_id1, &peers[0]);
+        assert_eq!(count_forwarded(&gs), 0, "Message should not be forwarded before validation");
+
+        std::thread::spawn(move || handle.report(MessageAcceptance::Accept))
+            .join()
+            .unwrap();
+        gs.apply_validation_results(&mut cx);
+        assert_eq!(count_forwarded(&gs), 1, "Accepted message should be forwarded");
+        flush_events(&mut gs);
+
+        // Dropping a handle ignores the message.
+        let m2 = random_message(&mut seq, &topics);
+        gs.handle_received_message(m2.clone(), &peers[0]);
+        let message2 = &gs.data_transform.inbound_transform(m2).unwrap();
+        let msg_id2 = config.message_id(message2);
+        drop(gs.validation_handle(&msg_id2, &peers[0]));
+        gs.apply_validation_results(&mut cx);
+
+        assert_eq!(count_forwarded(&gs), 0, "Ignored message should not be forwarded");
+        assert!(gs.mcache.get(&msg_id2).is_none());
+    }
+
     #[test]
     fn test_scoring_p4_invalid_signature() {
         let config = GossipsubConfigBuilder::default()
@@ -40,6 +40,8 @@ pub enum ValidationMode {
     Permissive,
     /// This setting requires the author, sequence number and signature fields of a message to be
     /// empty. Any message that contains these fields is considered invalid.
+    ///
+    /// This corresponds to the `StrictNoSign` signature policy of other implementations.
     Anonymous,
     /// This setting does not check the author, sequence number or signature fields of incoming
     /// messages. If these fields contain data, they are simply ignored.
@@ -197,7 +199,8 @@ impl GossipsubConfig {
     /// When set to `true`, prevents automatic forwarding of all received messages. This setting
     /// allows a user to validate the messages before propagating them to their peers. If set to
     /// true, the user must manually call [`crate::Gossipsub::report_message_validation_result()`]
-    /// on the behaviour to forward message once validated (default is `false`).
+    /// on the behaviour, or report the result through a [`crate::ValidationHandle`], to forward
+    /// message once validated (default is `false`).
     /// The default is `false`.
     pub fn validate_messages(&self) -> bool {
         self.validate_messages
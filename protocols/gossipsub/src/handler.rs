@@ -522,4 +522,18 @@ impl ProtocolsHandler for GossipsubHandler {
 
         Poll::Pending
     }
+
+    fn poll_close(&mut self, _: &mut Context<'_>) -> Poll<()> {
+        // Messages are sent and flushed by `poll`, so the handler is done once
+        // the send queue is empty and the outbound substream is idle.
+        let idle = match self.outbound_substream {
+            None | Some(OutboundSubstreamState::WaitingOutput(_)) => true,
+            Some(_) => false,
+        };
+        if self.protocol_unsupported || (self.send_queue.is_empty() && idle) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
 }
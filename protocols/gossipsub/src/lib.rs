@@ -131,6 +131,7 @@ mod config;
 mod gossip_promises;
 mod handler;
 mod mcache;
+mod metrics;
 mod peer_score;
 pub mod subscription_filter;
 pub mod time_cache;
@@ -148,6 +149,7 @@ pub use self::behaviour::{Gossipsub, GossipsubEvent, MessageAuthenticity, PEER_T
 pub use self::transform::{DataTransform, IdentityTransform};
 
 pub use self::config::{GossipsubConfig, GossipsubConfigBuilder, ValidationMode};
+pub use self::metrics::{CacheStats, GossipsubMetrics, TopicStats};
 pub use self::peer_score::{
     score_parameter_decay, score_parameter_decay_with_base, PeerScoreParams, PeerScoreThresholds,
     TopicScoreParams,
@@ -155,7 +157,7 @@ pub use self::peer_score::{
 pub use self::topic::{Hasher, Topic, TopicHash};
 pub use self::types::{
     FastMessageId, GossipsubMessage, GossipsubRpc, MessageAcceptance, MessageId,
-    RawGossipsubMessage,
+    RawGossipsubMessage, ValidationHandle,
 };
 pub type IdentTopic = Topic<self::topic::IdentityHash>;
 pub type Sha256Topic = Topic<self::topic::Sha256Hash>;
@@ -88,6 +88,21 @@ impl MessageCache {
         seen_message
     }
 
+    /// Gets the number of cached messages.
+    pub fn num_messages(&self) -> usize {
+        self.msgs.len()
+    }
+
+    /// Gets the number of cached messages that are still gossipped.
+    pub fn num_gossip_messages(&self) -> usize {
+        self.history[..self.gossip].iter().map(Vec::len).sum()
+    }
+
+    /// Gets the number of history windows for which messages are cached.
+    pub fn num_windows(&self) -> usize {
+        self.history.len()
+    }
+
     /// Get a message with `message_id`
     #[cfg(test)]
     pub fn get(&self, message_id: &MessageId) -> Option<&RawGossipsubMessage> {
@@ -0,0 +1,75 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Counters and snapshots for the introspection of a [`crate::Gossipsub`] behaviour.
+
+use crate::topic::TopicHash;
+use libp2p_core::PeerId;
+
+/// Cumulative counters of the gossip exchanged by a [`crate::Gossipsub`] behaviour,
+/// see [`crate::Gossipsub::metrics`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GossipsubMetrics {
+    /// The number of IHAVE control messages received.
+    pub ihave_received: u64,
+    /// The number of message IDs advertised by received IHAVE control messages.
+    pub ihave_message_ids_received: u64,
+    /// The number of IHAVE control messages sent.
+    pub ihave_sent: u64,
+    /// The number of message IDs advertised by sent IHAVE control messages.
+    pub ihave_message_ids_sent: u64,
+    /// The number of IWANT control messages received.
+    pub iwant_received: u64,
+    /// The number of message IDs requested by received IWANT control messages.
+    pub iwant_message_ids_received: u64,
+    /// The number of cached messages sent in response to IWANT control messages.
+    pub iwant_messages_served: u64,
+    /// The number of IWANT control messages sent.
+    pub iwant_sent: u64,
+    /// The number of message IDs requested by sent IWANT control messages.
+    pub iwant_message_ids_sent: u64,
+}
+
+/// The occupancy of the message cache of a [`crate::Gossipsub`] behaviour,
+/// see [`crate::Gossipsub::cache_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of cached messages.
+    pub messages: usize,
+    /// The number of cached messages that are still advertised in IHAVE control messages.
+    pub gossip_messages: usize,
+    /// The number of history windows, i.e. heartbeats, for which messages are cached.
+    pub history_windows: usize,
+}
+
+/// A snapshot of the peers of a topic, see [`crate::Gossipsub::topic_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicStats {
+    /// The topic.
+    pub topic: TopicHash,
+    /// The peers in the mesh of the topic, if the local node is subscribed to the topic.
+    pub mesh_peers: Vec<PeerId>,
+    /// The fanout peers of the topic, if the local node publishes to the topic without
+    /// being subscribed.
+    pub fanout_peers: Vec<PeerId>,
+    /// The number of known peers subscribed to the topic.
+    pub subscribed_peers: usize,
+}
@@ -21,6 +21,7 @@
 //! A collection of types using the Gossipsub system.
 use crate::rpc_proto;
 use crate::TopicHash;
+use futures::channel::mpsc;
 use libp2p_core::PeerId;
 use std::fmt;
 use std::fmt::Debug;
@@ -37,6 +38,70 @@ pub enum MessageAcceptance {
     Ignore,
 }
 
+/// The validation result of a received message, reported through a [`ValidationHandle`].
+pub(crate) type ValidationResult = (MessageId, PeerId, MessageAcceptance);
+
+/// A handle to report the validation result of a received message, obtained from
+/// [`crate::Gossipsub::validation_handle`].
+///
+/// In contrast to [`crate::Gossipsub::report_message_validation_result`], the handle does not
+/// borrow the behaviour and can be moved to wherever the message is validated, e.g. another
+/// task. The result is applied the next time the behaviour is polled.
+///
+/// Dropping the handle without reporting a result reports [`MessageAcceptance::Ignore`].
+#[derive(Debug)]
+pub struct ValidationHandle {
+    message_id: MessageId,
+    propagation_source: PeerId,
+    sender: Option<mpsc::UnboundedSender<ValidationResult>>,
+}
+
+impl ValidationHandle {
+    pub(crate) fn new(
+        message_id: MessageId,
+        propagation_source: PeerId,
+        sender: mpsc::UnboundedSender<ValidationResult>,
+    ) -> Self {
+        ValidationHandle {
+            message_id,
+            propagation_source,
+            sender: Some(sender),
+        }
+    }
+
+    /// The [`MessageId`] of the message to validate.
+    pub fn message_id(&self) -> &MessageId {
+        &self.message_id
+    }
+
+    /// The peer that forwarded the message to validate.
+    pub fn propagation_source(&self) -> &PeerId {
+        &self.propagation_source
+    }
+
+    /// Reports the validation result of the message.
+    pub fn report(mut self, acceptance: MessageAcceptance) {
+        self.send(acceptance)
+    }
+
+    fn send(&mut self, acceptance: MessageAcceptance) {
+        if let Some(sender) = self.sender.take() {
+            // The behaviour may have been dropped in the meantime.
+            let _ = sender.unbounded_send((
+                self.message_id.clone(),
+                self.propagation_source,
+                acceptance,
+            ));
+        }
+    }
+}
+
+impl Drop for ValidationHandle {
+    fn drop(&mut self) {
+        self.send(MessageAcceptance::Ignore)
+    }
+}
+
 /// Macro for declaring message id types
 macro_rules! declare_message_id_type {
     ($name: ident, $name_string: expr) => {
@@ -18,12 +18,22 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::protocol::{RemoteInfo, IdentifyProtocolConfig, ReplySubstream};
+use crate::protocol::{
+    RemoteInfo,
+    IdentifyProtocolConfig,
+    IdentifyPush,
+    IdentifyPushProtocolConfig,
+    ReplySubstream
+};
 use futures::prelude::*;
+use libp2p_core::either::{EitherError, EitherOutput};
 use libp2p_core::upgrade::{
+    EitherUpgrade,
     InboundUpgrade,
     OutboundUpgrade,
-    ReadOneError
+    ReadOneError,
+    SelectUpgrade,
+    UpgradeError
 };
 use libp2p_swarm::{
     NegotiatedSubstream,
@@ -34,7 +44,7 @@ use libp2p_swarm::{
     ProtocolsHandlerUpgrErr
 };
 use smallvec::SmallVec;
-use std::{pin::Pin, task::Context, task::Poll, time::Duration};
+use std::{collections::VecDeque, pin::Pin, task::Context, task::Poll, time::Duration};
 use wasm_timer::Delay;
 
 /// Delay between the moment we connect and the first time we identify.
@@ -49,6 +59,10 @@ const TRY_AGAIN_ON_ERR: Duration = Duration::from_secs(60 * 60);
 /// Outbound requests are sent periodically. The handler performs expects
 /// at least one identification request to be answered by the remote before
 /// permitting the underlying connection to be closed.
+///
+/// Information pushed by the remote is reported like the answer to a
+/// request. Information of the local node is pushed to the remote on
+/// request of the behaviour.
 pub struct IdentifyHandler {
     /// Configuration for the protocol.
     config: IdentifyProtocolConfig,
@@ -56,6 +70,9 @@ pub struct IdentifyHandler {
     /// Pending events to yield.
     events: SmallVec<[IdentifyHandlerEvent; 4]>,
 
+    /// Pending information to push to the remote.
+    pending_pushes: VecDeque<IdentifyPush>,
+
     /// Future that fires when we need to identify the node again.
     next_id: Delay,
 
@@ -70,16 +87,29 @@ pub enum IdentifyHandlerEvent {
     Identified(RemoteInfo),
     /// We received a request for identification.
     Identify(ReplySubstream<NegotiatedSubstream>),
-    /// Failed to identify the remote.
+    /// We pushed our identification information to the remote.
+    IdentificationPushed,
+    /// Failed to identify the remote or to push our identification
+    /// information to it.
     IdentificationError(ProtocolsHandlerUpgrErr<ReadOneError>),
 }
 
+/// The kind of an outbound substream of the `IdentifyHandler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboundKind {
+    /// Requesting the information of the remote.
+    Identify,
+    /// Pushing the information of the local node.
+    Push,
+}
+
 impl IdentifyHandler {
     /// Creates a new `IdentifyHandler`.
     pub fn new() -> Self {
         IdentifyHandler {
             config: IdentifyProtocolConfig,
             events: SmallVec::new(),
+            pending_pushes: VecDeque::new(),
             next_id: Delay::new(DELAY_TO_FIRST_ID),
             keep_alive: KeepAlive::Yes,
         }
@@ -87,16 +117,16 @@ impl IdentifyHandler {
 }
 
 impl ProtocolsHandler for IdentifyHandler {
-    type InEvent = ();
+    type InEvent = IdentifyPush;
     type OutEvent = IdentifyHandlerEvent;
     type Error = ReadOneError;
-    type InboundProtocol = IdentifyProtocolConfig;
-    type OutboundProtocol = IdentifyProtocolConfig;
-    type OutboundOpenInfo = ();
+    type InboundProtocol = SelectUpgrade<IdentifyProtocolConfig, IdentifyPushProtocolConfig>;
+    type OutboundProtocol = EitherUpgrade<IdentifyProtocolConfig, IdentifyPush>;
+    type OutboundOpenInfo = OutboundKind;
     type InboundOpenInfo = ();
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
-        SubstreamProtocol::new(self.config.clone(), ())
+        SubstreamProtocol::new(SelectUpgrade::new(self.config.clone(), IdentifyPushProtocolConfig), ())
     }
 
     fn inject_fully_negotiated_inbound(
@@ -104,7 +134,10 @@ impl ProtocolsHandler for IdentifyHandler {
         protocol: <Self::InboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Output,
         _info: Self::InboundOpenInfo
     ) {
-        self.events.push(IdentifyHandlerEvent::Identify(protocol))
+        match protocol {
+            EitherOutput::First(substream) => self.events.push(IdentifyHandlerEvent::Identify(substream)),
+            EitherOutput::Second(pushed) => self.events.push(IdentifyHandlerEvent::Identified(pushed)),
+        }
     }
 
     fn inject_fully_negotiated_outbound(
@@ -112,22 +145,45 @@ impl ProtocolsHandler for IdentifyHandler {
         protocol: <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
         _info: Self::OutboundOpenInfo,
     ) {
-        self.events.push(IdentifyHandlerEvent::Identified(protocol));
-        self.keep_alive = KeepAlive::No;
+        match protocol {
+            EitherOutput::First(remote_info) => {
+                self.events.push(IdentifyHandlerEvent::Identified(remote_info));
+                self.keep_alive = KeepAlive::No;
+            }
+            EitherOutput::Second(()) => self.events.push(IdentifyHandlerEvent::IdentificationPushed),
+        }
     }
 
-    fn inject_event(&mut self, _: Self::InEvent) {}
+    fn inject_event(&mut self, push: Self::InEvent) {
+        self.pending_pushes.push_back(push);
+    }
 
     fn inject_dial_upgrade_error(
         &mut self,
-        _info: Self::OutboundOpenInfo,
+        info: Self::OutboundOpenInfo,
         err: ProtocolsHandlerUpgrErr<
             <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Error
         >
     ) {
-        self.events.push(IdentifyHandlerEvent::IdentificationError(err));
-        self.keep_alive = KeepAlive::No;
-        self.next_id.reset(TRY_AGAIN_ON_ERR);
+        let err = err.map_upgrade_err(|e| e.map_err(|e| match e {
+            EitherError::A(e) => e,
+            EitherError::B(e) => e,
+        }));
+        match info {
+            OutboundKind::Identify => {
+                self.events.push(IdentifyHandlerEvent::IdentificationError(err));
+                self.keep_alive = KeepAlive::No;
+                self.next_id.reset(TRY_AGAIN_ON_ERR);
+            }
+            OutboundKind::Push => {
+                // Not all remotes support pushes, which is no reason to report an error.
+                if let ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(_)) = err {
+                    log::debug!("Remote does not support identify pushes.");
+                } else {
+                    self.events.push(IdentifyHandlerEvent::IdentificationError(err));
+                }
+            }
+        }
     }
 
     fn connection_keep_alive(&self) -> KeepAlive {
@@ -148,13 +204,19 @@ impl ProtocolsHandler for IdentifyHandler {
             ));
         }
 
+        if let Some(push) = self.pending_pushes.pop_front() {
+            return Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(EitherUpgrade::B(push), OutboundKind::Push)
+            });
+        }
+
         // Poll the future that fires when we need to identify the node again.
         match Future::poll(Pin::new(&mut self.next_id), cx) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(Ok(())) => {
                 self.next_id.reset(DELAY_TO_NEXT_ID);
                 let ev = ProtocolsHandlerEvent::OutboundSubstreamRequest {
-                    protocol: SubstreamProtocol::new(self.config.clone(), ())
+                    protocol: SubstreamProtocol::new(EitherUpgrade::A(self.config.clone()), OutboundKind::Identify)
                 };
                 Poll::Ready(ev)
             }
@@ -19,22 +19,27 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::handler::{IdentifyHandler, IdentifyHandlerEvent};
-use crate::protocol::{IdentifyInfo, ReplySubstream};
+use crate::protocol::{IdentifyInfo, IdentifyPush, ReplySubstream};
 use futures::prelude::*;
 use libp2p_core::{
     ConnectedPoint,
     Multiaddr,
+    NetworkId,
     PeerId,
     PublicKey,
+    SignedNetworkId,
     connection::ConnectionId,
+    onion,
     upgrade::{ReadOneError, UpgradeError}
 };
 use libp2p_swarm::{
-    AddressScore,
+    ExternalAddrSource,
     NegotiatedSubstream,
     NetworkBehaviour,
     NetworkBehaviourAction,
+    NotifyHandler,
     PollParameters,
+    ProtocolRegistry,
     ProtocolsHandler,
     ProtocolsHandlerUpgrErr
 };
@@ -50,21 +55,49 @@ use std::{
 /// about them, and answers identify queries from other nodes.
 ///
 /// All external addresses of the local node supposedly observed by remotes
-/// are reported via [`NetworkBehaviourAction::ReportObservedAddr`] with a
-/// [score](AddressScore) of `1`.
+/// are reported via [`NetworkBehaviourAction::ReportExternalAddr`], i.e. an
+/// address becomes an external address once enough remotes observed it.
+///
+/// Optionally, the local node can advertise the network it belongs to (see
+/// [`Identify::set_network_id`]) and require remotes to belong to a given
+/// network (see [`Identify::set_expected_network_id`]). Remotes failing to
+/// prove their membership of the expected network are disconnected and
+/// reported via [`IdentifyEvent::NetworkMismatch`].
+///
+/// The protocols advertised to remotes are the ones supported by the
+/// [`NetworkBehaviour`], complemented by the ones registered with a
+/// [`ProtocolRegistry`] (see [`Identify::set_protocol_registry`]) and
+/// the ones set with [`Identify::set_additional_protocols`].
+///
+/// The information of the local node can be pushed to connected peers with
+/// [`Identify::push`]. With [`Identify::set_push_updates`], it is pushed
+/// automatically whenever the listen addresses, the external addresses or
+/// the information set on the behaviour change.
 pub struct Identify {
     /// Protocol version to send back to remotes.
     protocol_version: String,
     /// Agent version to send back to remotes.
     agent_version: String,
+    /// Protocols advertised in addition to the supported and registered ones.
+    additional_protocols: Vec<String>,
     /// The public key of the local node. To report on the wire.
     local_public_key: PublicKey,
+    /// The signed network identifier of the local node. To report on the wire.
+    local_network_id: Option<SignedNetworkId>,
+    /// The network identifier remotes are required to prove.
+    expected_network_id: Option<NetworkId>,
+    /// The registry of the protocols advertised to remotes.
+    protocol_registry: Option<ProtocolRegistry>,
     /// For each peer we're connected to, the observed address to send back to it.
     observed_addresses: HashMap<PeerId, HashMap<ConnectionId, Multiaddr>>,
     /// Pending replies to send.
     pending_replies: VecDeque<Reply>,
+    /// Whether to push changes of the local information to connected peers.
+    push_updates: bool,
+    /// Peers to push the local information to.
+    pending_pushes: VecDeque<PeerId>,
     /// Pending events to be emitted when polled.
-    events: VecDeque<NetworkBehaviourAction<(), IdentifyEvent>>,
+    events: VecDeque<NetworkBehaviourAction<IdentifyPush, IdentifyEvent>>,
 }
 
 /// A pending reply to an inbound identification request.
@@ -88,12 +121,141 @@ impl Identify {
         Identify {
             protocol_version,
             agent_version,
+            additional_protocols: Vec::new(),
             local_public_key,
+            local_network_id: None,
+            expected_network_id: None,
+            protocol_registry: None,
             observed_addresses: HashMap::new(),
             pending_replies: VecDeque::new(),
+            push_updates: false,
+            pending_pushes: VecDeque::new(),
             events: VecDeque::new(),
         }
     }
+
+    /// Sets the agent version sent to remotes.
+    pub fn set_agent_version(&mut self, agent_version: String) {
+        self.agent_version = agent_version;
+        self.push_update();
+    }
+
+    /// Sets the protocols advertised to remotes in addition to the ones
+    /// supported by the [`NetworkBehaviour`] and the registered ones.
+    pub fn set_additional_protocols(&mut self, protocols: Vec<String>) {
+        self.additional_protocols = protocols;
+        self.push_update();
+    }
+
+    /// Sets whether changes of the information of the local node are pushed
+    /// to all connected peers, `false` by default.
+    ///
+    /// The information is pushed whenever the listen addresses or the
+    /// external addresses of the local node change, and whenever it is
+    /// changed with one of the setters of `Identify`.
+    pub fn set_push_updates(&mut self, enabled: bool) {
+        self.push_updates = enabled;
+    }
+
+    /// Pushes the information of the local node to the given peers.
+    ///
+    /// Peers that are not connected are ignored. Every successful push is
+    /// reported via [`IdentifyEvent::Pushed`].
+    pub fn push<I>(&mut self, peers: I)
+    where
+        I: IntoIterator<Item = PeerId>
+    {
+        for peer in peers {
+            if self.observed_addresses.contains_key(&peer) && !self.pending_pushes.contains(&peer) {
+                self.pending_pushes.push_back(peer);
+            }
+        }
+    }
+
+    /// Pushes the information of the local node to all connected peers, if
+    /// enabled with [`Identify::set_push_updates`].
+    fn push_update(&mut self) {
+        if self.push_updates {
+            let peers = self.observed_addresses.keys().copied().collect::<Vec<_>>();
+            self.push(peers);
+        }
+    }
+
+    /// Sets the signed network identifier sent to remotes.
+    ///
+    /// The network identifier must be signed with the keypair corresponding
+    /// to the local public key for remotes to accept it.
+    pub fn set_network_id(&mut self, network_id: Option<SignedNetworkId>) {
+        self.local_network_id = network_id;
+        self.push_update();
+    }
+
+    /// Sets the network identifier remotes are required to prove.
+    ///
+    /// If set, remotes which do not send a valid signed network identifier
+    /// equal to `network_id` are disconnected.
+    pub fn set_expected_network_id(&mut self, network_id: Option<NetworkId>) {
+        self.expected_network_id = network_id;
+    }
+
+    /// Sets the registry of protocols to advertise to remotes in addition to
+    /// the protocols supported by the [`NetworkBehaviour`].
+    pub fn set_protocol_registry(&mut self, registry: Option<ProtocolRegistry>) {
+        self.protocol_registry = registry;
+    }
+
+    /// Registers the protocol of `Identify` with the given registry.
+    pub fn register_protocols(&self, registry: &ProtocolRegistry) {
+        registry.register("identify", b"/ipfs/id/1.0.0");
+        registry.register("identify", b"/ipfs/id/push/1.0.0");
+    }
+
+    /// Returns the information of the local node to send to remotes.
+    fn local_info(&self, params: &impl PollParameters) -> IdentifyInfo {
+        // The protocol names can be bytes, but the identify protocol except UTF-8 strings.
+        // There's not much we can do to solve this conflict except strip non-UTF-8 characters.
+        let mut protocols: Vec<_> = params
+            .supported_protocols()
+            .map(|p| String::from_utf8_lossy(&p).to_string())
+            .collect();
+        let registered = self.protocol_registry.iter().flat_map(|r| r.protocols());
+        for p in registered.chain(self.additional_protocols.iter().cloned()) {
+            if !protocols.contains(&p) {
+                protocols.push(p)
+            }
+        }
+
+        let mut listen_addrs: Vec<_> = params.external_addresses().map(|r| r.addr).collect();
+        listen_addrs.extend(params.listened_addresses());
+
+        IdentifyInfo {
+            public_key: self.local_public_key.clone(),
+            protocol_version: self.protocol_version.clone(),
+            agent_version: self.agent_version.clone(),
+            listen_addrs,
+            protocols,
+            network_id: self.local_network_id.clone(),
+        }
+    }
+
+    /// Checks the network identifier received from a remote against the
+    /// expected one.
+    ///
+    /// Returns the verified network identifier of the remote (if any) in
+    /// case of a mismatch.
+    fn check_network_id(&self, peer_id: &PeerId, info: &IdentifyInfo) -> Result<(), Option<NetworkId>> {
+        let expected = match &self.expected_network_id {
+            Some(expected) => expected,
+            None => return Ok(())
+        };
+        let received = info.network_id.as_ref()
+            .filter(|n| n.verify_peer(peer_id, &info.public_key))
+            .map(|n| n.network_id());
+        match received {
+            Some(n) if n == expected => Ok(()),
+            received => Err(received.cloned())
+        }
+    }
 }
 
 impl NetworkBehaviour for Identify {
@@ -128,6 +290,23 @@ impl NetworkBehaviour for Identify {
 
     fn inject_disconnected(&mut self, peer_id: &PeerId) {
         self.observed_addresses.remove(peer_id);
+        self.pending_pushes.retain(|p| p != peer_id);
+    }
+
+    fn inject_new_listen_addr(&mut self, _: &Multiaddr) {
+        self.push_update();
+    }
+
+    fn inject_expired_listen_addr(&mut self, _: &Multiaddr) {
+        self.push_update();
+    }
+
+    fn inject_new_external_addr(&mut self, _: &Multiaddr) {
+        self.push_update();
+    }
+
+    fn inject_expired_external_addr(&mut self, _: &Multiaddr) {
+        self.push_update();
     }
 
     fn inject_event(
@@ -137,7 +316,18 @@ impl NetworkBehaviour for Identify {
         event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
     ) {
         match event {
-            IdentifyHandlerEvent::Identified(remote) => {
+            IdentifyHandlerEvent::Identified(mut remote) => {
+                // The remote can't be reached through onion services of
+                // other keys, which it may announce by mistake or maliciously.
+                remote.info.listen_addrs.retain(|a| onion::matches_peer(a, &peer_id));
+                if let Err(network_id) = self.check_network_id(&peer_id, &remote.info) {
+                    log::debug!("Peer {} is not on the expected network: {:?}", peer_id, network_id);
+                    self.events.push_back(
+                        NetworkBehaviourAction::GenerateEvent(
+                            IdentifyEvent::NetworkMismatch { peer_id, network_id }));
+                    self.events.push_back(NetworkBehaviourAction::DisconnectPeer { peer_id });
+                    return
+                }
                 self.events.push_back(
                     NetworkBehaviourAction::GenerateEvent(
                         IdentifyEvent::Received {
@@ -146,9 +336,9 @@ impl NetworkBehaviour for Identify {
                             observed_addr: remote.observed_addr.clone(),
                         }));
                 self.events.push_back(
-                    NetworkBehaviourAction::ReportObservedAddr {
+                    NetworkBehaviourAction::ReportExternalAddr {
                         address: remote.observed_addr,
-                        score: AddressScore::Finite(1),
+                        source: ExternalAddrSource::Observed(peer_id),
                     });
             }
             IdentifyHandlerEvent::Identify(sender) => {
@@ -163,6 +353,11 @@ impl NetworkBehaviour for Identify {
                         observed: observed.clone()
                     });
             }
+            IdentifyHandlerEvent::IdentificationPushed => {
+                self.events.push_back(
+                    NetworkBehaviourAction::GenerateEvent(
+                        IdentifyEvent::Pushed { peer_id }));
+            }
             IdentifyHandlerEvent::IdentificationError(error) => {
                 self.events.push_back(
                     NetworkBehaviourAction::GenerateEvent(
@@ -185,16 +380,28 @@ impl NetworkBehaviour for Identify {
             return Poll::Ready(event);
         }
 
-        if let Some(r) = self.pending_replies.pop_front() {
-            // The protocol names can be bytes, but the identify protocol except UTF-8 strings.
-            // There's not much we can do to solve this conflict except strip non-UTF-8 characters.
-            let protocols: Vec<_> = params
-                .supported_protocols()
-                .map(|p| String::from_utf8_lossy(&p).to_string())
-                .collect();
+        if !self.pending_pushes.is_empty() {
+            let info = self.local_info(params);
+            while let Some(peer_id) = self.pending_pushes.pop_front() {
+                // The push goes to an arbitrary connection, together with the
+                // address observed on that connection.
+                let connection = self.observed_addresses.get(&peer_id)
+                    .and_then(|addrs| addrs.iter().next());
+                if let Some((connection, observed_addr)) = connection {
+                    self.events.push_back(NetworkBehaviourAction::NotifyHandler {
+                        peer_id,
+                        handler: NotifyHandler::One(*connection),
+                        event: IdentifyPush { info: info.clone(), observed_addr: observed_addr.clone() },
+                    });
+                }
+            }
+            if let Some(event) = self.events.pop_front() {
+                return Poll::Ready(event);
+            }
+        }
 
-            let mut listen_addrs: Vec<_> = params.external_addresses().map(|r| r.addr).collect();
-            listen_addrs.extend(params.listened_addresses());
+        if let Some(r) = self.pending_replies.pop_front() {
+            let info = self.local_info(params);
 
             let mut sending = 0;
             let to_send = self.pending_replies.len() + 1;
@@ -202,14 +409,7 @@ impl NetworkBehaviour for Identify {
             loop {
                 match reply {
                     Some(Reply::Queued { peer, io, observed }) => {
-                        let info = IdentifyInfo {
-                            public_key: self.local_public_key.clone(),
-                            protocol_version: self.protocol_version.clone(),
-                            agent_version: self.agent_version.clone(),
-                            listen_addrs: listen_addrs.clone(),
-                            protocols: protocols.clone(),
-                        };
-                        let io = Box::pin(io.send(info, &observed));
+                        let io = Box::pin(io.send(info.clone(), &observed));
                         reply = Some(Reply::Sending { peer, io });
                     }
                     Some(Reply::Sending { peer, mut io }) => {
@@ -249,7 +449,8 @@ impl NetworkBehaviour for Identify {
 /// Event emitted  by the `Identify` behaviour.
 #[derive(Debug)]
 pub enum IdentifyEvent {
-    /// Identifying information has been received from a peer.
+    /// Identifying information has been received from a peer, either in
+    /// answer to a request or pushed by the peer.
     Received {
         /// The peer that has been identified.
         peer_id: PeerId,
@@ -263,6 +464,23 @@ pub enum IdentifyEvent {
         /// The peer that the information has been sent to.
         peer_id: PeerId,
     },
+    /// Identifying information of the local node has been pushed to a peer.
+    ///
+    /// See [`Identify::push`].
+    Pushed {
+        /// The peer that the information has been pushed to.
+        peer_id: PeerId,
+    },
+    /// The remote failed to prove that it belongs to the expected network
+    /// and is being disconnected.
+    ///
+    /// See [`Identify::set_expected_network_id`].
+    NetworkMismatch {
+        /// The peer that has been identified.
+        peer_id: PeerId,
+        /// The verified network the peer belongs to, if it sent any.
+        network_id: Option<NetworkId>,
+    },
     /// Error while attempting to identify the remote.
     Error {
         /// The peer with whom the error originated.
@@ -278,7 +496,9 @@ mod tests {
     use futures::{prelude::*, pin_mut};
     use libp2p_core::{
         identity,
+        NetworkId,
         PeerId,
+        SignedNetworkId,
         muxing::StreamMuxerBox,
         transport,
         Transport,
@@ -290,16 +510,20 @@ mod tests {
     use libp2p_mplex::MplexConfig;
 
     fn transport() -> (identity::PublicKey, transport::Boxed<(PeerId, StreamMuxerBox)>) {
+        let (id_keys, transport) = transport_with_keys();
+        (id_keys.public(), transport)
+    }
+
+    fn transport_with_keys() -> (identity::Keypair, transport::Boxed<(PeerId, StreamMuxerBox)>) {
         let id_keys = identity::Keypair::generate_ed25519();
         let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(&id_keys).unwrap();
-        let pubkey = id_keys.public();
         let transport = TcpConfig::new()
             .nodelay(true)
             .upgrade(upgrade::Version::V1)
             .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
             .multiplex(MplexConfig::new())
             .boxed();
-        (pubkey, transport)
+        (id_keys, transport)
     }
 
     #[test]
@@ -365,4 +589,59 @@ mod tests {
             }
         })
     }
+
+    #[test]
+    fn network_mismatch_is_reported() {
+        let mut swarm1 = {
+            let (pubkey, transport) = transport();
+            let mut protocol = Identify::new("a".to_string(), "b".to_string(), pubkey.clone());
+            protocol.set_expected_network_id(NetworkId::new("mainnet"));
+            Swarm::new(transport, protocol, pubkey.into_peer_id())
+        };
+
+        let (mut swarm2, peer_id2) = {
+            let (keys, transport) = transport_with_keys();
+            let pubkey = keys.public();
+            let mut protocol = Identify::new("c".to_string(), "d".to_string(), pubkey.clone());
+            let network_id = NetworkId::new("floonet").unwrap();
+            protocol.set_network_id(Some(SignedNetworkId::new(&keys, network_id).unwrap()));
+            let peer_id = pubkey.into_peer_id();
+            (Swarm::new(transport, protocol, peer_id), peer_id)
+        };
+
+        Swarm::listen_on(&mut swarm1, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+        let listen_addr = async_std::task::block_on(async {
+            loop {
+                let swarm1_fut = swarm1.next_event();
+                pin_mut!(swarm1_fut);
+                match swarm1_fut.await {
+                    SwarmEvent::NewListenAddr(addr) => return addr,
+                    _ => {}
+                }
+            }
+        });
+        Swarm::dial_addr(&mut swarm2, listen_addr).unwrap();
+
+        async_std::task::block_on(async move {
+            loop {
+                let swarm1_fut = swarm1.next();
+                pin_mut!(swarm1_fut);
+                let swarm2_fut = swarm2.next();
+                pin_mut!(swarm2_fut);
+
+                match future::select(swarm1_fut, swarm2_fut).await.factor_second().0 {
+                    future::Either::Left(IdentifyEvent::NetworkMismatch { peer_id, network_id }) => {
+                        assert_eq!(peer_id, peer_id2);
+                        assert_eq!(network_id, NetworkId::new("floonet"));
+                        return;
+                    }
+                    future::Either::Left(IdentifyEvent::Received { .. }) => {
+                        panic!("Peer on the wrong network has been accepted.")
+                    }
+                    _ => {}
+                }
+            }
+        })
+    }
 }
@@ -23,6 +23,7 @@ use futures::prelude::*;
 use libp2p_core::{
     Multiaddr,
     PublicKey,
+    SignedNetworkId,
     upgrade::{self, InboundUpgrade, OutboundUpgrade, UpgradeInfo}
 };
 use log::{debug, trace};
@@ -68,30 +69,29 @@ where
         debug!("Sending identify info to client");
         trace!("Sending: {:?}", info);
 
-        let listen_addrs = info.listen_addrs
-            .into_iter()
-            .map(|addr| addr.to_vec())
-            .collect();
-
-        let pubkey_bytes = info.public_key.into_protobuf_encoding();
-
-        let message = structs_proto::Identify {
-            agent_version: Some(info.agent_version),
-            protocol_version: Some(info.protocol_version),
-            public_key: Some(pubkey_bytes),
-            listen_addrs,
-            observed_addr: Some(observed_addr.to_vec()),
-            protocols: info.protocols
-        };
+        let bytes = encode_proto_msg(info, observed_addr);
 
         async move {
-            let mut bytes = Vec::with_capacity(message.encoded_len());
-            message.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
             upgrade::write_one(&mut self.inner, &bytes).await
         }
     }
 }
 
+/// Configuration for an upgrade to the `Identify` push protocol, receiving
+/// the information pushed by the remote.
+#[derive(Debug, Clone)]
+pub struct IdentifyPushProtocolConfig;
+
+/// An upgrade to the `Identify` push protocol, pushing the information of
+/// the local node to the remote.
+#[derive(Debug, Clone)]
+pub struct IdentifyPush {
+    /// The information of the local node.
+    pub info: IdentifyInfo,
+    /// Address the local node sees for the remote.
+    pub observed_addr: Multiaddr,
+}
+
 /// Information of a peer sent in `Identify` protocol responses.
 #[derive(Debug, Clone)]
 pub struct IdentifyInfo {
@@ -107,6 +107,8 @@ pub struct IdentifyInfo {
     pub listen_addrs: Vec<Multiaddr>,
     /// The list of protocols supported by the peer, e.g. `/ipfs/ping/1.0.0`.
     pub protocols: Vec<String>,
+    /// The network the peer claims to belong to, if any.
+    pub network_id: Option<SignedNetworkId>,
 }
 
 impl UpgradeInfo for IdentifyProtocolConfig {
@@ -118,6 +120,24 @@ impl UpgradeInfo for IdentifyProtocolConfig {
     }
 }
 
+impl UpgradeInfo for IdentifyPushProtocolConfig {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(b"/ipfs/id/push/1.0.0")
+    }
+}
+
+impl UpgradeInfo for IdentifyPush {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(b"/ipfs/id/push/1.0.0")
+    }
+}
+
 impl<C> InboundUpgrade<C> for IdentifyProtocolConfig
 where
     C: AsyncRead + AsyncWrite + Unpin,
@@ -163,6 +183,67 @@ where
     }
 }
 
+impl<C> InboundUpgrade<C> for IdentifyPushProtocolConfig
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = RemoteInfo;
+    type Error = upgrade::ReadOneError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
+
+    fn upgrade_inbound(self, mut socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(async move {
+            let msg = upgrade::read_one(&mut socket, 4096).await?;
+            let (info, observed_addr) = parse_proto_msg(msg)?;
+            trace!("Information pushed: {:?}", info);
+            Ok(RemoteInfo { info, observed_addr })
+        })
+    }
+}
+
+impl<C> OutboundUpgrade<C> for IdentifyPush
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = ();
+    type Error = upgrade::ReadOneError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
+
+    fn upgrade_outbound(self, mut socket: C, _: Self::Info) -> Self::Future {
+        trace!("Pushing: {:?}", self.info);
+        let bytes = encode_proto_msg(self.info, &self.observed_addr);
+        Box::pin(async move {
+            upgrade::write_one(&mut socket, &bytes).await?;
+            Ok(())
+        })
+    }
+}
+
+// Turns an `IdentifyInfo` and the address observed for the remote into an encoded protobuf
+// message.
+fn encode_proto_msg(info: IdentifyInfo, observed_addr: &Multiaddr) -> Vec<u8> {
+    let listen_addrs = info.listen_addrs
+        .into_iter()
+        .map(|addr| addr.to_vec())
+        .collect();
+
+    let pubkey_bytes = info.public_key.into_protobuf_encoding();
+
+    let message = structs_proto::Identify {
+        agent_version: Some(info.agent_version),
+        protocol_version: Some(info.protocol_version),
+        public_key: Some(pubkey_bytes),
+        listen_addrs,
+        observed_addr: Some(observed_addr.to_vec()),
+        protocols: info.protocols,
+        signed_network_id: info.network_id.map(|n| n.to_bytes()),
+    };
+
+    let mut bytes = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+    bytes
+}
+
 // Turns a protobuf message into an `IdentifyInfo` and an observed address. If something bad
 // happens, turn it into an `io::Error`.
 fn parse_proto_msg(msg: impl AsRef<[u8]>) -> Result<(IdentifyInfo, Multiaddr), io::Error> {
@@ -186,13 +267,20 @@ fn parse_proto_msg(msg: impl AsRef<[u8]>) -> Result<(IdentifyInfo, Multiaddr), i
             let public_key = PublicKey::from_protobuf_encoding(&msg.public_key.unwrap_or_default())
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
+            let network_id = match msg.signed_network_id {
+                Some(bytes) => Some(SignedNetworkId::from_bytes(&bytes)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?),
+                None => None
+            };
+
             let observed_addr = bytes_to_multiaddr(msg.observed_addr.unwrap_or_default())?;
             let info = IdentifyInfo {
                 public_key,
                 protocol_version: msg.protocol_version.unwrap_or_default(),
                 agent_version: msg.agent_version.unwrap_or_default(),
                 listen_addrs,
-                protocols: msg.protocols
+                protocols: msg.protocols,
+                network_id,
             };
 
             Ok((info, observed_addr))
@@ -204,11 +292,13 @@ fn parse_proto_msg(msg: impl AsRef<[u8]>) -> Result<(IdentifyInfo, Multiaddr), i
 
 #[cfg(test)]
 mod tests {
-    use crate::protocol::{IdentifyInfo, RemoteInfo, IdentifyProtocolConfig};
+    use crate::protocol::{IdentifyInfo, IdentifyPush, IdentifyPushProtocolConfig, RemoteInfo, IdentifyProtocolConfig};
     use libp2p_tcp::TcpConfig;
     use futures::{prelude::*, channel::oneshot};
     use libp2p_core::{
         identity,
+        NetworkId,
+        SignedNetworkId,
         Transport,
         upgrade::{self, apply_outbound, apply_inbound}
     };
@@ -217,8 +307,11 @@ mod tests {
     fn correct_transfer() {
         // We open a server and a client, send info from the server to the client, and check that
         // they were successfully received.
-        let send_pubkey = identity::Keypair::generate_ed25519().public();
+        let send_keypair = identity::Keypair::generate_ed25519();
+        let send_pubkey = send_keypair.public();
         let recv_pubkey = send_pubkey.clone();
+        let network_id = NetworkId::new("floonet").unwrap();
+        let send_network_id = SignedNetworkId::new(&send_keypair, network_id.clone()).unwrap();
 
         let (tx, rx) = oneshot::channel();
 
@@ -248,6 +341,7 @@ mod tests {
                         "/ip6/::1/udp/1000".parse().unwrap(),
                     ],
                     protocols: vec!["proto1".to_string(), "proto2".to_string()],
+                    network_id: Some(send_network_id),
                 },
                 &"/ip4/100.101.102.103/tcp/5000".parse().unwrap(),
             ).await.unwrap();
@@ -267,8 +361,63 @@ mod tests {
                 &["/ip4/80.81.82.83/tcp/500".parse().unwrap(),
                 "/ip6/::1/udp/1000".parse().unwrap()]);
             assert_eq!(info.protocols, &["proto1".to_string(), "proto2".to_string()]);
+            let recv_network_id = info.network_id.expect("signed network id");
+            assert_eq!(recv_network_id.network_id(), &network_id);
+            assert!(recv_network_id.verify(&recv_pubkey));
 
             bg_task.await;
         });
     }
+
+    #[test]
+    fn correct_push() {
+        let send_pubkey = identity::Keypair::generate_ed25519().public();
+        let recv_pubkey = send_pubkey.clone();
+
+        let (tx, rx) = oneshot::channel();
+
+        let bg_task = async_std::task::spawn(async move {
+            let transport = TcpConfig::new();
+
+            let mut listener = transport
+                .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .unwrap();
+
+            let addr = listener.next().await
+                .expect("some event")
+                .expect("no error")
+                .into_new_address()
+                .expect("listen address");
+            tx.send(addr).unwrap();
+
+            let socket = listener.next().await.unwrap().unwrap().into_upgrade().unwrap().0.await.unwrap();
+            apply_inbound(socket, IdentifyPushProtocolConfig).await.unwrap()
+        });
+
+        async_std::task::block_on(async move {
+            let transport = TcpConfig::new();
+
+            let socket = transport.dial(rx.await.unwrap()).unwrap().await.unwrap();
+            let push = IdentifyPush {
+                info: IdentifyInfo {
+                    public_key: send_pubkey,
+                    protocol_version: "proto_version".to_owned(),
+                    agent_version: "agent_version".to_owned(),
+                    listen_addrs: vec!["/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234".parse().unwrap()],
+                    protocols: vec!["proto1".to_string()],
+                    network_id: None,
+                },
+                observed_addr: "/ip4/100.101.102.103/tcp/5000".parse().unwrap(),
+            };
+            apply_outbound(socket, push, upgrade::Version::V1).await.unwrap();
+
+            let RemoteInfo { info, observed_addr, .. } = bg_task.await;
+            assert_eq!(observed_addr, "/ip4/100.101.102.103/tcp/5000".parse().unwrap());
+            assert_eq!(info.public_key, recv_pubkey);
+            assert_eq!(info.agent_version, "agent_version");
+            assert_eq!(info.listen_addrs.len(), 1);
+            assert_eq!(info.protocols, &["proto1".to_string()]);
+            assert!(info.network_id.is_none());
+        });
+    }
 }
@@ -35,7 +35,7 @@ use crate::jobs::*;
 use crate::kbucket::{self, KBucketsTable, NodeStatus};
 use crate::protocol::{KademliaProtocolConfig, KadConnectionType, KadPeer};
 use crate::query::{Query, QueryId, QueryPool, QueryConfig, QueryPoolState};
-use crate::record::{self, store::{self, RecordStore}, Record, ProviderRecord};
+use crate::record::{self, store::{self, RecordStore}, validator::{RecordValidator, Validators}, Record, ProviderRecord};
 use fnv::{FnvHashMap, FnvHashSet};
 use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, connection::ConnectionId};
 use libp2p_swarm::{
@@ -77,6 +77,13 @@ pub struct Kademlia<TStore> {
     /// This is a superset of the connected peers currently in the routing table.
     connected_peers: FnvHashSet<PeerId>,
 
+    /// The peers pinned into the routing table, which are never evicted.
+    pinned_peers: FnvHashSet<PeerId>,
+
+    /// The last time the status of the peers in the routing table changed,
+    /// i.e. a peer connected or disconnected.
+    last_seen: FnvHashMap<PeerId, Instant>,
+
     /// Periodic job for re-publication of provider records for keys
     /// provided by the local node.
     add_provider_job: Option<AddProviderJob>,
@@ -85,12 +92,24 @@ pub struct Kademlia<TStore> {
     /// regular (value-)records.
     put_record_job: Option<PutRecordJob>,
 
+    /// Whether the records found in the store on creation, e.g. those
+    /// loaded by a persistent store from disk, are still to be republished
+    /// once the first peer is connected.
+    republish_stored: bool,
+
     /// The TTL of regular (value-)records.
     record_ttl: Option<Duration>,
 
     /// The TTL of provider records.
     provider_record_ttl: Option<Duration>,
 
+    /// The announcements of provider records by [`Kademlia::start_providing`]
+    /// waiting for a running announcement to complete.
+    provider_publications: VecDeque<(QueryId, record::Key)>,
+
+    /// The maximum number of provider announcements in progress at a time.
+    max_provider_publications: NonZeroUsize,
+
     /// How long to keep connections alive when they're idle.
     connection_idle_timeout: Duration,
 
@@ -102,6 +121,9 @@ pub struct Kademlia<TStore> {
 
     /// The record storage.
     store: TStore,
+
+    /// The validators of records received from remotes.
+    validators: Validators,
 }
 
 /// The configurable strategies for the insertion of peers
@@ -141,8 +163,10 @@ pub struct KademliaConfig {
     record_publication_interval: Option<Duration>,
     provider_record_ttl: Option<Duration>,
     provider_publication_interval: Option<Duration>,
+    max_provider_publications: NonZeroUsize,
     connection_idle_timeout: Duration,
     kbucket_inserts: KademliaBucketInserts,
+    validators: Validators,
 }
 
 impl Default for KademliaConfig {
@@ -156,8 +180,10 @@ impl Default for KademliaConfig {
             record_publication_interval: Some(Duration::from_secs(24 * 60 * 60)),
             provider_publication_interval: Some(Duration::from_secs(12 * 60 * 60)),
             provider_record_ttl: Some(Duration::from_secs(24 * 60 * 60)),
+            max_provider_publications: NonZeroUsize::new(32).expect("32 > 0"),
             connection_idle_timeout: Duration::from_secs(10),
             kbucket_inserts: KademliaBucketInserts::OnConnected,
+            validators: Validators::default(),
         }
     }
 }
@@ -217,12 +243,30 @@ impl KademliaConfig {
     /// parallelism.
     ///
     /// See the S/Kademlia paper for more information on the high level design
-    /// as well as its security improvements.
+    /// as well as its security improvements. To also require the results of
+    /// lookups to be reported on multiple paths, see
+    /// [`KademliaConfig::set_disjoint_path_corroboration`].
     pub fn disjoint_query_paths(&mut self, enabled: bool) -> &mut Self {
         self.query_config.disjoint_query_paths = enabled;
         self
     }
 
+    /// Sets the number of disjoint paths on which a record or provider must
+    /// be received for it to be part of the result of a [`Kademlia::get_record`]
+    /// or [`Kademlia::get_providers`] query.
+    ///
+    /// Results that are only reported by peers on fewer paths, e.g. those
+    /// injected by a small set of adversarial nodes, are discarded. A record
+    /// only counts towards the [`Quorum`] once it is corroborated.
+    ///
+    /// Only applies with [`KademliaConfig::disjoint_query_paths`] enabled and
+    /// should not exceed the configured parallelism, i.e. the number of paths.
+    /// The default is 1, i.e. results are not required to be corroborated.
+    pub fn set_disjoint_path_corroboration(&mut self, paths: NonZeroUsize) -> &mut Self {
+        self.query_config.disjoint_path_corroboration = paths;
+        self
+    }
+
     /// Sets the TTL for stored records.
     ///
     /// The TTL should be significantly longer than the (re-)publication
@@ -293,12 +337,28 @@ impl KademliaConfig {
     /// `None` means that stored provider records are never automatically
     /// re-published.
     ///
-    /// Must be significantly less than the provider record TTL.
+    /// Must be significantly less than the provider record TTL. An interval
+    /// that is not less than the TTL is replaced by half the TTL, so that
+    /// provider records are always re-published before they expire.
     pub fn set_provider_publication_interval(&mut self, interval: Option<Duration>) -> &mut Self {
         self.provider_publication_interval = interval;
         self
     }
 
+    /// Sets the maximum number of provider announcements, i.e. queries
+    /// started by [`Kademlia::start_providing`] or the periodic
+    /// re-publication of provider records, that are in progress at a time.
+    ///
+    /// Further announcements by [`Kademlia::start_providing`] are queued
+    /// and started in batches as running announcements complete, whereby
+    /// [`KademliaEvent::ProvidingStarted`] is emitted for every key.
+    ///
+    /// The default is 32.
+    pub fn set_max_provider_publications(&mut self, max: NonZeroUsize) -> &mut Self {
+        self.max_provider_publications = max;
+        self
+    }
+
     /// Sets the amount of time to keep connections alive when they're idle.
     pub fn set_connection_idle_timeout(&mut self, duration: Duration) -> &mut Self {
         self.connection_idle_timeout = duration;
@@ -319,6 +379,23 @@ impl KademliaConfig {
         self.kbucket_inserts = inserts;
         self
     }
+
+    /// Adds a validator for the records with keys of the form
+    /// `/<namespace>/...`, e.g. [`PublicKeyRecord`](record::validator::PublicKeyRecord)
+    /// for the `pk` namespace.
+    ///
+    /// The validators are run on records received from remotes, both on
+    /// records to be stored and on the results of [`Kademlia::get_record`].
+    /// Invalid records are rejected, i.e. neither stored nor reported. A
+    /// record must pass all validators of its namespace. Records of other
+    /// namespaces are not validated.
+    pub fn add_record_validator<V>(&mut self, namespace: &str, validator: V) -> &mut Self
+    where
+        V: RecordValidator
+    {
+        self.validators.add(namespace, validator);
+        self
+    }
 }
 
 impl<TStore> Kademlia<TStore>
@@ -351,8 +428,17 @@ where
 
         let add_provider_job = config
             .provider_publication_interval
+            .map(|interval| match config.provider_record_ttl {
+                // Re-publish provider records before they expire.
+                Some(ttl) if interval >= ttl => ttl / 2,
+                _ => interval
+            })
             .map(AddProviderJob::new);
 
+        // A persistent store may come with the records of a previous run.
+        let republish_stored = store.provided().next().is_some()
+            || store.records().next().is_some();
+
         Kademlia {
             store,
             kbuckets: KBucketsTable::new(local_key, config.kbucket_pending_timeout),
@@ -361,12 +447,18 @@ where
             queued_events: VecDeque::with_capacity(config.query_config.replication_factor.get()),
             queries: QueryPool::new(config.query_config),
             connected_peers: Default::default(),
+            pinned_peers: Default::default(),
+            last_seen: Default::default(),
             add_provider_job,
             put_record_job,
+            republish_stored,
             record_ttl: config.record_ttl,
             provider_record_ttl: config.provider_record_ttl,
+            provider_publications: VecDeque::new(),
+            max_provider_publications: config.max_provider_publications,
             connection_idle_timeout: config.connection_idle_timeout,
-            local_addrs: HashSet::new()
+            local_addrs: HashSet::new(),
+            validators: config.validators,
         }
     }
 
@@ -496,44 +588,188 @@ where
         -> Option<kbucket::EntryView<kbucket::Key<PeerId>, Addresses>>
     {
         let key = kbucket::Key::from(*peer);
-        match self.kbuckets.entry(&key) {
+        let (removed, present) = match self.kbuckets.entry(&key) {
             kbucket::Entry::Present(mut entry, _) => {
                 if entry.value().remove(address).is_err() {
-                    Some(entry.remove()) // it is the last address, thus remove the peer.
+                    (Some(entry.remove()), true) // it is the last address, thus remove the peer.
                 } else {
-                    None
+                    (None, true)
                 }
             }
             kbucket::Entry::Pending(mut entry, _) => {
                 if entry.value().remove(address).is_err() {
-                    Some(entry.remove()) // it is the last address, thus remove the peer.
+                    (Some(entry.remove()), false) // it is the last address, thus remove the peer.
                 } else {
-                    None
+                    (None, false)
                 }
             }
             kbucket::Entry::Absent(..) | kbucket::Entry::SelfEntry => {
-                None
+                (None, false)
             }
+        };
+        if removed.is_some() {
+            self.routing_entry_removed(&key, present);
         }
+        removed
     }
 
     /// Removes a peer from the routing table.
     ///
     /// Returns `None` if the peer was not in the routing table,
     /// not even pending insertion.
+    ///
+    /// A pinned peer is unpinned when removed, see [`Kademlia::pin_peer`].
     pub fn remove_peer(&mut self, peer: &PeerId)
         -> Option<kbucket::EntryView<kbucket::Key<PeerId>, Addresses>>
     {
         let key = kbucket::Key::from(*peer);
-        match self.kbuckets.entry(&key) {
+        let (removed, present) = match self.kbuckets.entry(&key) {
             kbucket::Entry::Present(entry, _) => {
-                Some(entry.remove())
+                (Some(entry.remove()), true)
             }
             kbucket::Entry::Pending(entry, _) => {
-                Some(entry.remove())
+                (Some(entry.remove()), false)
             }
             kbucket::Entry::Absent(..) | kbucket::Entry::SelfEntry => {
-                None
+                (None, false)
+            }
+        };
+        if removed.is_some() {
+            self.routing_entry_removed(&key, present);
+        }
+        removed
+    }
+
+    /// Pins a peer into the routing table, adding it with the given address.
+    ///
+    /// A pinned peer is never evicted from its bucket to make room for
+    /// another peer, even while disconnected. It is only removed through
+    /// [`Kademlia::remove_peer`] or [`Kademlia::remove_address`], which
+    /// also unpin it.
+    ///
+    /// If the bucket of the peer is full, the peer is only pinned once it
+    /// is inserted, as indicated by the returned [`RoutingUpdate`].
+    pub fn pin_peer(&mut self, peer: &PeerId, address: Multiaddr) -> RoutingUpdate {
+        let update = self.add_address(peer, address);
+        match update {
+            RoutingUpdate::Success | RoutingUpdate::Pending => {
+                self.pinned_peers.insert(*peer);
+            }
+            RoutingUpdate::Failed => {}
+        }
+        update
+    }
+
+    /// Unpins a peer, making it subject to eviction from the routing table
+    /// again. Returns `false` if the peer was not pinned.
+    pub fn unpin_peer(&mut self, peer: &PeerId) -> bool {
+        self.pinned_peers.remove(peer)
+    }
+
+    /// Checks whether a peer is pinned into the routing table.
+    pub fn is_pinned(&self, peer: &PeerId) -> bool {
+        self.pinned_peers.contains(peer)
+    }
+
+    /// Returns a snapshot of all non-empty buckets in the routing table,
+    /// including the status, the time last seen and whether it is pinned of
+    /// every peer.
+    pub fn routing_table(&mut self) -> Vec<RoutingTableBucket> {
+        let pinned_peers = &self.pinned_peers;
+        let last_seen = &self.last_seen;
+        self.kbuckets.iter()
+            .filter(|b| !b.is_empty())
+            .map(|b| RoutingTableBucket {
+                range: b.range(),
+                has_pending: b.has_pending(),
+                entries: b.iter()
+                    .map(|e| {
+                        let peer = *e.node.key.preimage();
+                        RoutingTableEntry {
+                            peer,
+                            addresses: e.node.value.clone(),
+                            status: e.status,
+                            last_seen: last_seen.get(&peer).copied(),
+                            pinned: pinned_peers.contains(&peer),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Drains the applied pending entries of the routing table, returning
+    /// the event for the first entry that is kept.
+    fn apply_pending_entries(&mut self) -> Option<KademliaEvent> {
+        while let Some(entry) = self.kbuckets.take_applied_pending() {
+            let kbucket::Node { key, value } = entry.inserted;
+            let old_peer = match entry.evicted {
+                Some(evicted) if self.pinned_peers.contains(evicted.key.preimage()) => {
+                    self.restore_pinned(key, evicted);
+                    continue
+                }
+                Some(evicted) => {
+                    let peer = evicted.key.into_preimage();
+                    self.last_seen.remove(&peer);
+                    self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
+                        KademliaEvent::PeerEvicted {
+                            peer,
+                            addresses: evicted.value,
+                            replaced_by: *key.preimage(),
+                        }
+                    ));
+                    Some(peer)
+                }
+                None => None,
+            };
+            return Some(KademliaEvent::RoutingUpdated {
+                peer: key.into_preimage(),
+                addresses: value,
+                old_peer,
+            })
+        }
+        None
+    }
+
+    /// Reverts the eviction of a pinned peer in favour of a pending peer,
+    /// dropping the latter from the routing table again.
+    fn restore_pinned(
+        &mut self,
+        inserted: kbucket::Key<PeerId>,
+        evicted: kbucket::Node<kbucket::Key<PeerId>, Addresses>,
+    ) {
+        debug!("Keeping pinned peer {} in the routing table instead of {}.",
+            evicted.key.preimage(), inserted.preimage());
+        if let kbucket::Entry::Present(entry, _) = self.kbuckets.entry(&inserted) {
+            entry.remove();
+        }
+        self.last_seen.remove(inserted.preimage());
+        let status = if self.connected_peers.contains(evicted.key.preimage()) {
+            NodeStatus::Connected
+        } else {
+            NodeStatus::Disconnected
+        };
+        if let kbucket::Entry::Absent(entry) = self.kbuckets.entry(&evicted.key) {
+            match entry.insert(evicted.value, status) {
+                kbucket::InsertResult::Inserted => {}
+                _ => unreachable!("The bucket has room for the evicted peer.")
+            }
+        }
+    }
+
+    /// Forgets the state associated with a peer removed from the routing
+    /// table, reporting its bucket if it became empty.
+    fn routing_entry_removed(&mut self, key: &kbucket::Key<PeerId>, present: bool) {
+        self.pinned_peers.remove(key.preimage());
+        self.last_seen.remove(key.preimage());
+        // Pending entries do not count towards the entries of a bucket.
+        if present {
+            if let Some(bucket) = self.kbuckets.bucket(key) {
+                if bucket.is_empty() {
+                    self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
+                        KademliaEvent::BucketEmptied { range: bucket.range() }
+                    ));
+                }
             }
         }
     }
@@ -561,6 +797,21 @@ where
     /// The result of the query is delivered in a
     /// [`KademliaEvent::QueryResult{QueryResult::GetClosestPeers}`].
     pub fn get_closest_peers<K>(&mut self, key: K) -> QueryId
+    where
+        K: Into<kbucket::Key<K>> + Into<Vec<u8>> + Clone
+    {
+        self.get_closest_peers_with(key, QueryOptions::default())
+    }
+
+    /// Initiates an iterative query for the closest peers to the given key,
+    /// overriding the configured timeout and replication factor with those
+    /// of the given [`QueryOptions`], if set.
+    ///
+    /// The quorum of the options is ignored.
+    ///
+    /// The result of the query is delivered in a
+    /// [`KademliaEvent::QueryResult{QueryResult::GetClosestPeers}`].
+    pub fn get_closest_peers_with<K>(&mut self, key: K, options: QueryOptions) -> QueryId
     where
         K: Into<kbucket::Key<K>> + Into<Vec<u8>> + Clone
     {
@@ -568,7 +819,8 @@ where
         let target: kbucket::Key<K> = key.into();
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
-        self.queries.add_iter_closest(target.clone(), peers, inner)
+        let (timeout, replication_factor) = self.query_overrides(&options);
+        self.queries.add_iter_closest_with(target.clone(), peers, inner, timeout, replication_factor)
     }
 
     /// Performs a lookup for a record in the DHT.
@@ -576,7 +828,19 @@ where
     /// The result of this operation is delivered in a
     /// [`KademliaEvent::QueryResult{QueryResult::GetRecord}`].
     pub fn get_record(&mut self, key: &record::Key, quorum: Quorum) -> QueryId {
-        let quorum = quorum.eval(self.queries.config().replication_factor);
+        self.get_record_with(key, QueryOptions { quorum: Some(quorum), .. QueryOptions::default() })
+    }
+
+    /// Performs a lookup for a record in the DHT, overriding the configured
+    /// timeout and replication factor with those of the given [`QueryOptions`],
+    /// if set. The quorum defaults to [`Quorum::One`] and is evaluated w.r.t.
+    /// the replication factor of the query.
+    ///
+    /// The result of this operation is delivered in a
+    /// [`KademliaEvent::QueryResult{QueryResult::GetRecord}`].
+    pub fn get_record_with(&mut self, key: &record::Key, options: QueryOptions) -> QueryId {
+        let (timeout, replication_factor) = self.query_overrides(&options);
+        let quorum = options.quorum.unwrap_or(Quorum::One).eval(replication_factor);
         let mut records = Vec::with_capacity(quorum.get());
 
         if let Some(record) = self.store.get(key) {
@@ -592,7 +856,8 @@ where
         let info = QueryInfo::GetRecord { key: key.clone(), records, quorum, cache_at: None };
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
-        let id = self.queries.add_iter_closest(target.clone(), peers, inner); // (*)
+        let id = self.queries.add_iter_closest_with(
+            target.clone(), peers, inner, timeout, replication_factor); // (*)
 
         // Instantly finish the query if we already have enough records.
         if done {
@@ -716,6 +981,11 @@ where
     ///
     /// The results of the (repeated) provider announcements sent by this node are
     /// reported via [`KademliaEvent::QueryResult{QueryResult::StartProviding}`].
+    ///
+    /// If the maximum number of provider announcements in progress is reached,
+    /// see [`KademliaConfig::set_max_provider_publications`], the announcement
+    /// is queued. The start of every announcement is reported via
+    /// [`KademliaEvent::ProvidingStarted`].
     pub fn start_providing(&mut self, key: record::Key) -> Result<QueryId, store::Error> {
         // Note: We store our own provider records locally without local addresses
         // to avoid redundant storage and outdated addresses. Instead these are
@@ -726,16 +996,9 @@ where
             *self.kbuckets.local_key().preimage(),
             local_addrs);
         self.store.add_provider(record)?;
-        let target = kbucket::Key::new(key.clone());
-        let peers = self.kbuckets.closest_keys(&target);
-        let context = AddProviderContext::Publish;
-        let info = QueryInfo::AddProvider {
-            context,
-            key,
-            phase: AddProviderPhase::GetClosestPeers
-        };
-        let inner = QueryInner::new(info);
-        let id = self.queries.add_iter_closest(target.clone(), peers, inner);
+        let id = self.queries.next_query_id();
+        self.provider_publications.push_back((id, key));
+        self.start_provider_publications(1);
         Ok(id)
     }
 
@@ -743,8 +1006,38 @@ where
     ///
     /// This is a local operation. The local node will still be considered as a
     /// provider for the key by other nodes until these provider records expire.
+    /// Queued announcements for the key are discarded.
     pub fn stop_providing(&mut self, key: &record::Key) {
         self.store.remove_provider(key, self.kbuckets.local_key().preimage());
+        self.provider_publications.retain(|(_, k)| k != key);
+    }
+
+    /// Gets the number of provider announcements in progress.
+    fn num_provider_publications(&self) -> usize {
+        self.queries.iter()
+            .filter(|q| matches!(q.inner.info, QueryInfo::AddProvider { .. }))
+            .count()
+    }
+
+    /// Starts up to `max` queued provider announcements, as long as the
+    /// maximum number of announcements in progress is not reached.
+    fn start_provider_publications(&mut self, max: usize) {
+        let capacity = self.max_provider_publications.get()
+            .saturating_sub(self.num_provider_publications());
+        for _ in 0 .. usize::min(max, capacity) {
+            if let Some((id, key)) = self.provider_publications.pop_front() {
+                self.start_add_provider(id, key.clone(), AddProviderContext::Publish);
+                self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    KademliaEvent::ProvidingStarted {
+                        id,
+                        key,
+                        queued: self.provider_publications.len(),
+                    }
+                ));
+            } else {
+                break
+            }
+        }
     }
 
     /// Performs a lookup for providers of a value to the given key.
@@ -752,14 +1045,41 @@ where
     /// The result of this operation is delivered in a
     /// reported via [`KademliaEvent::QueryResult{QueryResult::GetProviders}`].
     pub fn get_providers(&mut self, key: record::Key) -> QueryId {
+        self.get_providers_with(key, QueryOptions::default())
+    }
+
+    /// Performs a lookup for providers of a value to the given key, overriding
+    /// the configured timeout and replication factor with those of the given
+    /// [`QueryOptions`], if set.
+    ///
+    /// If the options specify a quorum, evaluated w.r.t. the replication factor
+    /// of the query, the query finishes as soon as that many providers are found.
+    /// Otherwise the query continues until the closest peers to the key have
+    /// been contacted.
+    ///
+    /// The result of this operation is delivered in a
+    /// reported via [`KademliaEvent::QueryResult{QueryResult::GetProviders}`].
+    pub fn get_providers_with(&mut self, key: record::Key, options: QueryOptions) -> QueryId {
+        let (timeout, replication_factor) = self.query_overrides(&options);
         let info = QueryInfo::GetProviders {
             key: key.clone(),
             providers: HashSet::new(),
+            quorum: options.quorum.map(|q| q.eval(replication_factor)),
         };
         let target = kbucket::Key::new(key);
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
-        self.queries.add_iter_closest(target.clone(), peers, inner)
+        self.queries.add_iter_closest_with(target.clone(), peers, inner, timeout, replication_factor)
+    }
+
+    /// Resolves the timeout and replication factor of a query with the given
+    /// options, falling back to the configured values.
+    fn query_overrides(&self, options: &QueryOptions) -> (Duration, NonZeroUsize) {
+        let config = self.queries.config();
+        (
+            options.timeout.unwrap_or(config.timeout),
+            options.replication_factor.unwrap_or(config.replication_factor),
+        )
     }
 
     /// Processes discovered peers from a successful request in an iterative `Query`.
@@ -844,7 +1164,7 @@ where
     }
 
     /// Starts an iterative `ADD_PROVIDER` query for the given key.
-    fn start_add_provider(&mut self, key: record::Key, context: AddProviderContext) {
+    fn start_add_provider(&mut self, id: QueryId, key: record::Key, context: AddProviderContext) {
         let info = QueryInfo::AddProvider {
             context,
             key: key.clone(),
@@ -853,7 +1173,7 @@ where
         let target = kbucket::Key::new(key);
         let peers = self.kbuckets.closest_keys(&target);
         let inner = QueryInner::new(info);
-        self.queries.add_iter_closest(target.clone(), peers, inner);
+        self.queries.continue_iter_closest(id, target.clone(), peers, inner);
     }
 
     /// Starts an iterative `PUT_VALUE` query for the given record.
@@ -873,6 +1193,7 @@ where
         let key = kbucket::Key::from(peer);
         match self.kbuckets.entry(&key) {
             kbucket::Entry::Present(mut entry, old_status) => {
+                self.last_seen.insert(peer, Instant::now());
                 if let Some(address) = address {
                     if entry.value().insert(address) {
                         self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
@@ -890,6 +1211,7 @@ where
             },
 
             kbucket::Entry::Pending(mut entry, old_status) => {
+                self.last_seen.insert(peer, Instant::now());
                 if let Some(address) = address {
                     entry.value().insert(address);
                 }
@@ -918,6 +1240,7 @@ where
                         let addresses = Addresses::new(a);
                         match entry.insert(addresses.clone(), new_status) {
                             kbucket::InsertResult::Inserted => {
+                                self.last_seen.insert(peer, Instant::now());
                                 let event = KademliaEvent::RoutingUpdated {
                                     peer,
                                     addresses,
@@ -935,6 +1258,7 @@ where
                             },
                             kbucket::InsertResult::Pending { disconnected } => {
                                 debug_assert!(!self.connected_peers.contains(disconnected.preimage()));
+                                self.last_seen.insert(peer, Instant::now());
                                 let address = addresses.first().clone();
                                 self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
                                     KademliaEvent::PendingRoutablePeer { peer, address }
@@ -958,7 +1282,11 @@ where
     {
         let query_id = q.id();
         log::trace!("Query {:?} finished.", query_id);
+        let timeout = q.timeout();
+        let replication_factor = q.replication_factor();
         let result = q.into_result();
+        let required = self.disjoint_path_corroboration();
+        let corroboration = result.inner.corroboration;
         match result.inner.info {
             QueryInfo::Bootstrap { peer, remaining } => {
                 let local_key = self.kbuckets.local_key().clone();
@@ -1017,16 +1345,32 @@ where
             }
 
             QueryInfo::GetClosestPeers { key, .. } => {
+                let peers = result.peers.collect::<Vec<_>>();
+                let params = QueryParams {
+                    quorum: None,
+                    quorum_reached: true,
+                    replication_factor,
+                    replication_reached: peers.len() >= replication_factor.get(),
+                    timeout,
+                };
                 Some(KademliaEvent::QueryResult {
                     id: query_id,
                     stats: result.stats,
                     result: QueryResult::GetClosestPeers(Ok(
-                        GetClosestPeersOk { key, peers: result.peers.collect() }
+                        GetClosestPeersOk { key, peers, params }
                     ))
                 })
             }
 
-            QueryInfo::GetProviders { key, providers } => {
+            QueryInfo::GetProviders { key, providers, quorum } => {
+                let closest_peers = result.peers.collect::<Vec<_>>();
+                let params = QueryParams {
+                    quorum,
+                    quorum_reached: quorum.map_or(true, |q| providers.len() >= q.get()),
+                    replication_factor,
+                    replication_reached: closest_peers.len() >= replication_factor.get(),
+                    timeout,
+                };
                 Some(KademliaEvent::QueryResult {
                     id: query_id,
                     stats: result.stats,
@@ -1034,7 +1378,8 @@ where
                         GetProvidersOk {
                             key,
                             providers,
-                            closest_peers: result.peers.collect()
+                            closest_peers,
+                            params,
                         }
                     ))
                 })
@@ -1083,7 +1428,14 @@ where
                 }
             }
 
-            QueryInfo::GetRecord { key, records, quorum, cache_at } => {
+            QueryInfo::GetRecord { key, mut records, quorum, cache_at } => {
+                let closest_peers = result.peers.collect::<Vec<_>>();
+                let received = records.len();
+                records.retain(|r| corroboration.is_record_corroborated(r, required));
+                if records.len() < received {
+                    debug!("GetRecord query ({:?}) discarded {} uncorroborated records.",
+                        query_id, received - records.len());
+                }
                 let results = if records.len() >= quorum.get() { // [not empty]
                     if let Some(cache_key) = cache_at {
                         // Cache the record at the closest node to the key that
@@ -1103,12 +1455,16 @@ where
                         let inner = QueryInner::new(info);
                         self.queries.add_fixed(iter::once(cache_key.into_preimage()), inner);
                     }
-                    Ok(GetRecordOk { records })
+                    let params = QueryParams {
+                        quorum: Some(quorum),
+                        quorum_reached: true,
+                        replication_factor,
+                        replication_reached: closest_peers.len() >= replication_factor.get(),
+                        timeout,
+                    };
+                    Ok(GetRecordOk { records, params })
                 } else if records.is_empty() {
-                    Err(GetRecordError::NotFound {
-                        key,
-                        closest_peers: result.peers.collect()
-                    })
+                    Err(GetRecordError::NotFound { key, closest_peers })
                 } else {
                     Err(GetRecordError::QuorumFailed { key, records, quorum })
                 };
@@ -1183,6 +1539,8 @@ where
         let query_id = query.id();
         log::trace!("Query {:?} timed out.", query_id);
         let result = query.into_result();
+        let required = self.disjoint_path_corroboration();
+        let corroboration = result.inner.corroboration;
         match result.inner.info {
             QueryInfo::Bootstrap { peer, mut remaining } => {
                 let num_remaining = remaining.as_ref().map(|r| r.len().saturating_sub(1) as u32);
@@ -1289,16 +1647,18 @@ where
                 }
             }
 
-            QueryInfo::GetRecord { key, records, quorum, .. } =>
+            QueryInfo::GetRecord { key, mut records, quorum, .. } => {
+                records.retain(|r| corroboration.is_record_corroborated(r, required));
                 Some(KademliaEvent::QueryResult {
                     id: query_id,
                     stats: result.stats,
                     result: QueryResult::GetRecord(Err(
                         GetRecordError::Timeout { key, records, quorum },
                     ))
-                }),
+                })
+            }
 
-            QueryInfo::GetProviders { key, providers } =>
+            QueryInfo::GetProviders { key, providers, .. } =>
                 Some(KademliaEvent::QueryResult {
                     id: query_id,
                     stats: result.stats,
@@ -1313,6 +1673,17 @@ where
             }
     }
 
+    /// Returns the number of disjoint paths on which a result of a query
+    /// must be received to be accepted.
+    fn disjoint_path_corroboration(&self) -> usize {
+        let config = self.queries.config();
+        if config.disjoint_query_paths {
+            config.disjoint_path_corroboration.get()
+        } else {
+            1
+        }
+    }
+
     /// Processes a record received from a peer.
     fn record_received(
         &mut self,
@@ -1339,6 +1710,16 @@ where
 
         let now = Instant::now();
 
+        if let Err(e) = self.validators.validate(&record, now) {
+            info!("Record rejected: {:?}: {}", record.key, e);
+            self.queued_events.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: source,
+                handler: NotifyHandler::One(connection),
+                event: KademliaHandlerIn::Reset(request_id)
+            });
+            return
+        }
+
         // Calculate the expiration exponentially inversely proportional to the
         // number of nodes between the local node and the closest node to the key
         // (beyond the replication factor). This ensures avoiding over-caching
@@ -1658,13 +2039,28 @@ where
             } => {
                 let peers = closer_peers.iter().chain(provider_peers.iter());
                 self.discovered(&user_data, &source, peers);
+                let required = self.disjoint_path_corroboration();
                 if let Some(query) = self.queries.get_mut(&user_data) {
+                    let path = query.path_of(&source);
+                    let mut done = false;
                     if let QueryInfo::GetProviders {
-                        providers, ..
+                        providers, quorum, ..
                     } = &mut query.inner.info {
+                        let corroboration = &mut query.inner.corroboration;
                         for peer in provider_peers {
-                            providers.insert(peer.node_id);
+                            let id = peer.node_id.to_bytes();
+                            corroboration.insert(&id, path);
+                            if corroboration.is_corroborated(&id, required) {
+                                providers.insert(peer.node_id);
+                            }
                         }
+                        done = quorum.map_or(false, |q| providers.len() >= q.get());
+                    }
+                    // Desired number of providers found. The query may finish.
+                    // See [`Query::try_finish`] for details.
+                    if done && !query.try_finish(iter::once(&source)) {
+                        debug!("GetProviders query ({:?}) reached quorum with response \
+                                from peer {} but could not yet finish.", user_data, source);
                     }
                 }
             }
@@ -1720,18 +2116,35 @@ where
                 closer_peers,
                 user_data,
             } => {
+                let required = self.disjoint_path_corroboration();
+                // Invalid records are treated as if the remote did not have the record.
+                let record = record.filter(|r| match self.validators.validate(r, Instant::now()) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        debug!("Discarding invalid record {:?} from {}: {}", r.key, source, e);
+                        false
+                    }
+                });
                 if let Some(query) = self.queries.get_mut(&user_data) {
+                    let path = query.path_of(&source);
                     if let QueryInfo::GetRecord {
                         key, records, quorum, cache_at
                     } = &mut query.inner.info {
                         if let Some(record) = record {
+                            let corroboration = &mut query.inner.corroboration;
+                            corroboration.insert(&record.value, path);
                             records.push(PeerRecord{ peer: Some(source), record });
 
+                            // Only corroborated records count towards the quorum.
+                            let accepted = records.iter()
+                                .filter(|r| corroboration.is_record_corroborated(r, required))
+                                .collect::<Vec<_>>();
+
                             let quorum = quorum.get();
-                            if records.len() >= quorum {
+                            if accepted.len() >= quorum {
                                 // Desired quorum reached. The query may finish. See
                                 // [`Query::try_finish`] for details.
-                                let peers = records.iter()
+                                let peers = accepted.iter()
                                     .filter_map(|PeerRecord{ peer, .. }| peer.as_ref())
                                     .cloned()
                                     .collect::<Vec<_>>();
@@ -1814,6 +2227,10 @@ where
         }
     }
 
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.local_addrs.remove(addr);
+    }
+
     fn poll(&mut self, cx: &mut Context<'_>, parameters: &mut impl PollParameters) -> Poll<
         NetworkBehaviourAction<
             KademliaHandlerIn<QueryId>,
@@ -1822,15 +2239,39 @@ where
     > {
         let now = Instant::now();
 
+        // Flush pending writes of the record store, if any.
+        if let Poll::Ready(Err(e)) = self.store.poll_flush(cx) {
+            warn!("Failed to flush the record store: {}", e);
+        }
+
+        // Republish the records of a previous run as soon as there is
+        // a peer to start the queries with, instead of waiting a full
+        // interval of the background jobs.
+        if self.republish_stored && !self.connected_peers.is_empty() {
+            self.republish_stored = false;
+            if let Some(job) = self.add_provider_job.as_mut() {
+                job.asap();
+            }
+            if let Some(job) = self.put_record_job.as_mut() {
+                job.asap(true);
+            }
+        }
+
+        // Start the next batch of queued provider announcements.
+        self.start_provider_publications(JOBS_MAX_NEW_QUERIES);
+
         // Calculate the available capacity for queries triggered by background jobs.
         let mut jobs_query_capacity = JOBS_MAX_QUERIES.saturating_sub(self.queries.size());
 
         // Run the periodic provider announcement job.
         if let Some(mut job) = self.add_provider_job.take() {
             let num = usize::min(JOBS_MAX_NEW_QUERIES, jobs_query_capacity);
-            for _ in 0 .. num {
+            let publication_capacity = self.max_provider_publications.get()
+                .saturating_sub(self.num_provider_publications());
+            for _ in 0 .. usize::min(num, publication_capacity) {
                 if let Poll::Ready(r) = job.poll(cx, &mut self.store, now) {
-                    self.start_add_provider(r.key, AddProviderContext::Republish)
+                    let id = self.queries.next_query_id();
+                    self.start_add_provider(id, r.key, AddProviderContext::Republish)
                 } else {
                     break
                 }
@@ -1864,13 +2305,7 @@ where
             }
 
             // Drain applied pending entries from the routing table.
-            if let Some(entry) = self.kbuckets.take_applied_pending() {
-                let kbucket::Node { key, value } = entry.inserted;
-                let event = KademliaEvent::RoutingUpdated {
-                    peer: key.into_preimage(),
-                    addresses: value,
-                    old_peer: entry.evicted.map(|n| n.key.into_preimage())
-                };
+            if let Some(event) = self.apply_pending_entries() {
                 return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event))
             }
 
@@ -1948,6 +2383,45 @@ impl Quorum {
     }
 }
 
+/// Per-query overrides of the [`KademliaConfig`], used by
+/// [`Kademlia::get_record_with`], [`Kademlia::get_providers_with`] and
+/// [`Kademlia::get_closest_peers_with`].
+///
+/// Unset options fall back to the configured values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryOptions {
+    /// The quorum of the query, evaluated w.r.t. its replication factor.
+    pub quorum: Option<Quorum>,
+    /// The timeout of the query.
+    ///
+    /// See [`KademliaConfig::set_query_timeout`].
+    pub timeout: Option<Duration>,
+    /// The number of closest peers to the key the query aims to reach.
+    ///
+    /// See [`KademliaConfig::set_replication_factor`].
+    pub replication_factor: Option<NonZeroUsize>,
+}
+
+/// The parameters a query was run with and whether they were satisfied
+/// by the time the query finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryParams {
+    /// The number of results the query required, if any.
+    pub quorum: Option<NonZeroUsize>,
+    /// Whether the quorum was reached. Always `true` if the query
+    /// required no quorum.
+    pub quorum_reached: bool,
+    /// The number of closest peers to the key the query aimed to reach.
+    pub replication_factor: NonZeroUsize,
+    /// Whether `replication_factor` peers were successfully contacted.
+    ///
+    /// > **Note**: A query that finishes early, e.g. because its quorum
+    /// > was reached, usually contacts fewer peers.
+    pub replication_reached: bool,
+    /// The timeout of the query.
+    pub timeout: Duration,
+}
+
 /// A record either received by the given peer or retrieved from the local
 /// record store.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -2026,7 +2500,39 @@ pub enum KademliaEvent {
     PendingRoutablePeer {
         peer: PeerId,
         address: Multiaddr,
-    }
+    },
+
+    /// A peer has been evicted from the routing table to make room for
+    /// another peer, having been the least-recently connected peer of its
+    /// bucket and unresponsive. Also reported by [`KademliaEvent::RoutingUpdated`].
+    PeerEvicted {
+        /// The ID of the evicted peer.
+        peer: PeerId,
+        /// The addresses of the evicted peer.
+        addresses: Addresses,
+        /// The ID of the peer that took its place.
+        replaced_by: PeerId,
+    },
+
+    /// The last peer of a bucket of the routing table has been removed
+    /// through [`Kademlia::remove_peer`] or [`Kademlia::remove_address`].
+    BucketEmptied {
+        /// The minimum and maximum inclusive distance of the peers of the
+        /// bucket to the local key.
+        range: (kbucket::Distance, kbucket::Distance),
+    },
+
+    /// The announcement of a provider record requested with
+    /// [`Kademlia::start_providing`] has started. Its result is reported
+    /// via [`KademliaEvent::QueryResult{QueryResult::StartProviding}`].
+    ProvidingStarted {
+        /// The ID of the query announcing the provider record.
+        id: QueryId,
+        /// The key of the provider record.
+        key: record::Key,
+        /// The number of announcements still queued.
+        queued: usize,
+    },
 }
 
 /// The results of Kademlia queries.
@@ -2063,7 +2569,9 @@ pub type GetRecordResult = Result<GetRecordOk, GetRecordError>;
 /// The successful result of [`Kademlia::get_record`].
 #[derive(Debug, Clone)]
 pub struct GetRecordOk {
-    pub records: Vec<PeerRecord>
+    pub records: Vec<PeerRecord>,
+    /// The parameters the query was run with and whether they were satisfied.
+    pub params: QueryParams,
 }
 
 /// The error result of [`Kademlia::get_record`].
@@ -2177,7 +2685,9 @@ pub type GetClosestPeersResult = Result<GetClosestPeersOk, GetClosestPeersError>
 #[derive(Debug, Clone)]
 pub struct GetClosestPeersOk {
     pub key: Vec<u8>,
-    pub peers: Vec<PeerId>
+    pub peers: Vec<PeerId>,
+    /// The parameters the query was run with and whether they were satisfied.
+    pub params: QueryParams,
 }
 
 /// The error result of [`Kademlia::get_closest_peers`].
@@ -2214,7 +2724,9 @@ pub type GetProvidersResult = Result<GetProvidersOk, GetProvidersError>;
 pub struct GetProvidersOk {
     pub key: record::Key,
     pub providers: HashSet<PeerId>,
-    pub closest_peers: Vec<PeerId>
+    pub closest_peers: Vec<PeerId>,
+    /// The parameters the query was run with and whether they were satisfied.
+    pub params: QueryParams,
 }
 
 /// The error result of [`Kademlia::get_providers`].
@@ -2303,7 +2815,9 @@ struct QueryInner {
     ///
     /// A request is pending if the targeted peer is not currently connected
     /// and these requests are sent as soon as a connection to the peer is established.
-    pending_rpcs: SmallVec<[(PeerId, KademliaHandlerIn<QueryId>); K_VALUE.get()]>
+    pending_rpcs: SmallVec<[(PeerId, KademliaHandlerIn<QueryId>); K_VALUE.get()]>,
+    /// The disjoint paths on which the results of the query have been received.
+    corroboration: Corroboration,
 }
 
 impl QueryInner {
@@ -2311,11 +2825,42 @@ impl QueryInner {
         QueryInner {
             info,
             addresses: Default::default(),
-            pending_rpcs: SmallVec::default()
+            pending_rpcs: SmallVec::default(),
+            corroboration: Default::default(),
         }
     }
 }
 
+/// The disjoint paths on which the results of a query, identified by
+/// their encoding, have been received.
+///
+/// See [`KademliaConfig::set_disjoint_path_corroboration`].
+#[derive(Default)]
+struct Corroboration {
+    paths: FnvHashMap<Vec<u8>, FnvHashSet<usize>>,
+}
+
+impl Corroboration {
+    /// Records that `result` has been received on the given path, if any.
+    fn insert(&mut self, result: &[u8], path: Option<usize>) {
+        if let Some(path) = path {
+            self.paths.entry(result.to_vec()).or_default().insert(path);
+        }
+    }
+
+    /// Checks whether `result` has been received on at least `required` paths.
+    fn is_corroborated(&self, result: &[u8], required: usize) -> bool {
+        required <= 1 || self.paths.get(result).map_or(0, |p| p.len()) >= required
+    }
+
+    /// Checks whether a record received by a `GetRecord` query is corroborated.
+    ///
+    /// A record found in the local store is always accepted.
+    fn is_record_corroborated(&self, record: &PeerRecord, required: usize) -> bool {
+        record.peer.is_none() || self.is_corroborated(&record.record.value, required)
+    }
+}
+
 /// The context of a [`QueryInfo::AddProvider`] query.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AddProviderContext {
@@ -2357,6 +2902,8 @@ pub enum QueryInfo {
         key: record::Key,
         /// The found providers.
         providers: HashSet<PeerId>,
+        /// The number of providers after which the query finishes, if any.
+        quorum: Option<NonZeroUsize>,
     },
 
     /// A (repeated) query initiated by [`Kademlia::start_providing`].
@@ -2547,6 +3094,34 @@ impl fmt::Display for NoKnownPeers {
 
 impl std::error::Error for NoKnownPeers {}
 
+/// A snapshot of a bucket of the routing table, see [`Kademlia::routing_table`].
+#[derive(Debug, Clone)]
+pub struct RoutingTableBucket {
+    /// The minimum and maximum inclusive distance of the peers of the
+    /// bucket to the local key.
+    pub range: (kbucket::Distance, kbucket::Distance),
+    /// The peers in the bucket, from least-recently to most-recently connected.
+    pub entries: Vec<RoutingTableEntry>,
+    /// Whether a peer is pending insertion into the full bucket.
+    pub has_pending: bool,
+}
+
+/// A snapshot of a peer in the routing table, see [`Kademlia::routing_table`].
+#[derive(Debug, Clone)]
+pub struct RoutingTableEntry {
+    /// The ID of the peer.
+    pub peer: PeerId,
+    /// The known addresses of the peer.
+    pub addresses: Addresses,
+    /// Whether the peer is considered connected.
+    pub status: NodeStatus,
+    /// The last time the peer connected or disconnected, if either happened
+    /// since it was added to the routing table.
+    pub last_seen: Option<Instant>,
+    /// Whether the peer is pinned, see [`Kademlia::pin_peer`].
+    pub pinned: bool,
+}
+
 /// The possible outcomes of [`Kademlia::add_address`].
 pub enum RoutingUpdate {
     /// The given peer and address has been added to the routing
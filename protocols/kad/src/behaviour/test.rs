@@ -24,7 +24,7 @@ use super::*;
 
 use crate::K_VALUE;
 use crate::kbucket::Distance;
-use crate::record::{Key, store::MemoryStore};
+use crate::record::{Key, store::MemoryStore, validator};
 use futures::{
     prelude::*,
     executor::block_on,
@@ -55,6 +55,13 @@ fn build_node() -> (Multiaddr, TestSwarm) {
 }
 
 fn build_node_with_config(cfg: KademliaConfig) -> (Multiaddr, TestSwarm) {
+    build_node_with_store(cfg, MemoryStore::new)
+}
+
+fn build_node_with_store<F>(cfg: KademliaConfig, store: F) -> (Multiaddr, TestSwarm)
+where
+    F: FnOnce(PeerId) -> MemoryStore
+{
     let local_key = identity::Keypair::generate_ed25519();
     let local_public_key = local_key.public();
     let noise_keys = noise::Keypair::<noise::X25519>::new().into_authentic(&local_key).unwrap();
@@ -65,7 +72,7 @@ fn build_node_with_config(cfg: KademliaConfig) -> (Multiaddr, TestSwarm) {
         .boxed();
 
     let local_id = local_public_key.clone().into_peer_id();
-    let store = MemoryStore::new(local_id.clone());
+    let store = store(local_id.clone());
     let behaviour = Kademlia::with_config(local_id.clone(), store, cfg.clone());
 
     let mut swarm = Swarm::new(transport, behaviour, local_id);
@@ -653,7 +660,7 @@ fn get_record() {
                     match swarm.poll_next_unpin(ctx) {
                         Poll::Ready(Some(KademliaEvent::QueryResult {
                             id,
-                            result: QueryResult::GetRecord(Ok(GetRecordOk { records })),
+                            result: QueryResult::GetRecord(Ok(GetRecordOk { records, .. })),
                             ..
                         })) => {
                             assert_eq!(id, qid);
@@ -699,7 +706,7 @@ fn get_record_many() {
                     match swarm.poll_next_unpin(ctx) {
                         Poll::Ready(Some(KademliaEvent::QueryResult {
                             id,
-                            result: QueryResult::GetRecord(Ok(GetRecordOk { records })),
+                            result: QueryResult::GetRecord(Ok(GetRecordOk { records, .. })),
                             ..
                         })) => {
                             assert_eq!(id, qid);
@@ -912,6 +919,89 @@ fn exceed_jobs_max_queries() {
     )
 }
 
+/// Provider announcements beyond the configured maximum are queued and
+/// started in order as running announcements complete.
+#[test]
+fn provider_publications_are_queued() {
+    let mut config = KademliaConfig::default();
+    config.set_max_provider_publications(NonZeroUsize::new(1).unwrap());
+    let (_addr, mut swarm) = build_node_with_config(config);
+
+    let keys = (0 .. 3).map(|_| Key::from(random_multihash())).collect::<Vec<_>>();
+    let qids = keys.iter()
+        .map(|k| swarm.start_providing(k.clone()).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(swarm.queries.size(), 1);
+    assert_eq!(swarm.provider_publications.len(), 2);
+
+    let mut started = Vec::new();
+    let mut finished = Vec::new();
+    block_on(
+        poll_fn(move |ctx| {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(KademliaEvent::ProvidingStarted { id, key, queued })) => {
+                        // Only a single announcement is ever in progress.
+                        assert_eq!(started.len(), finished.len());
+                        started.push((id, key, queued));
+                    }
+                    Poll::Ready(Some(KademliaEvent::QueryResult {
+                        id, result: QueryResult::StartProviding(Ok(_)), ..
+                    })) => {
+                        finished.push(id);
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+
+            if finished.len() < keys.len() {
+                return Poll::Pending
+            }
+
+            assert_eq!(finished, qids);
+            assert_eq!(started, vec![
+                (qids[0], keys[0].clone(), 0),
+                (qids[1], keys[1].clone(), 1),
+                (qids[2], keys[2].clone(), 0),
+            ]);
+            Poll::Ready(())
+        })
+    )
+}
+
+/// The provider records found in the store on creation, as if loaded from
+/// disk, are republished as soon as the first peer is connected.
+#[test]
+fn republish_stored_records_on_first_connection() {
+    let key = Key::from(random_multihash());
+    let (_, mut stored) = build_node_with_store(Default::default(), |local_id| {
+        let mut store = MemoryStore::new(local_id);
+        store.add_provider(ProviderRecord::new(key.clone(), local_id, Vec::new())).unwrap();
+        store
+    });
+    let (addr, mut other) = build_node();
+    let other_id = *Swarm::local_peer_id(&other);
+
+    stored.add_address(&other_id, addr);
+    Swarm::dial(&mut stored, &other_id).unwrap();
+
+    block_on(
+        poll_fn(move |ctx| {
+            for swarm in [&mut stored, &mut other].iter_mut() {
+                while let Poll::Ready(Some(_)) = swarm.poll_next_unpin(ctx) {}
+            }
+            if other.store.providers(&key).is_empty() {
+                return Poll::Pending
+            }
+            Poll::Ready(())
+        })
+    )
+}
+
 #[test]
 fn exp_decr_expiration_overflow() {
     fn prop_no_panic(ttl: Duration, factor: u32) {
@@ -1058,6 +1148,217 @@ fn disjoint_query_does_not_finish_before_all_paths_did() {
 
 /// Tests that peers are not automatically inserted into
 /// the routing table with `KademliaBucketInserts::Manual`.
+/// Has a node with two disjoint paths, requiring results to be corroborated
+/// by both, look up a record held by one node per given record.
+fn get_record_with_corroboration(records: Vec<Record>) -> GetRecordResult {
+    let mut config = KademliaConfig::default();
+    config.disjoint_query_paths(true);
+    config.set_parallelism(NonZeroUsize::new(2).unwrap());
+    config.set_disjoint_path_corroboration(NonZeroUsize::new(2).unwrap());
+
+    let key = records[0].key.clone();
+    let (_, mut alice) = build_node_with_config(config);
+    let mut swarms = records.into_iter()
+        .map(|record| {
+            let (addr, mut swarm) = build_node();
+            swarm.store.put(record).unwrap();
+            alice.add_address(Swarm::local_peer_id(&swarm), addr);
+            swarm
+        })
+        .collect::<Vec<_>>();
+
+    alice.get_record(&key, Quorum::One);
+
+    block_on(
+        poll_fn(|ctx| {
+            loop {
+                match alice.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(KademliaEvent::QueryResult {
+                        result: QueryResult::GetRecord(result),
+                        ..
+                    })) => return Poll::Ready(result),
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    Poll::Ready(None) => panic!("Expected Kademlia behaviour not to finish."),
+                    Poll::Pending => break,
+                }
+            }
+            for swarm in &mut swarms {
+                while let Poll::Ready(Some(_)) = swarm.poll_next_unpin(ctx) {}
+            }
+            Poll::Pending
+        })
+    )
+}
+
+#[test]
+fn disjoint_query_accepts_corroborated_records() {
+    let key = Key::from(random_multihash());
+    let record = Record::new(key, b"bob".to_vec());
+
+    match get_record_with_corroboration(vec![record.clone(), record.clone()]) {
+        Ok(ok) => {
+            assert_eq!(2, ok.records.len());
+            assert!(ok.records.iter().all(|r| r.record == record));
+        }
+        Err(e) => panic!("Expected corroborated record to be found: {:?}", e),
+    }
+}
+
+#[test]
+fn disjoint_query_discards_uncorroborated_records() {
+    let key = Key::from(random_multihash());
+    let record_bob = Record::new(key.clone(), b"bob".to_vec());
+    let record_trudy = Record::new(key, b"trudy".to_vec());
+
+    // Each version of the record is only reported on one of the two paths.
+    match get_record_with_corroboration(vec![record_bob, record_trudy]) {
+        Err(GetRecordError::NotFound { .. }) => {}
+        r => panic!("Expected uncorroborated records to be discarded: {:?}", r),
+    }
+}
+
+#[test]
+fn get_closest_peers_with_replication_factor() {
+    let mut swarms = build_fully_connected_nodes_with_config(5, Default::default())
+        .into_iter()
+        .map(|(_addr, swarm)| swarm)
+        .collect::<Vec<_>>();
+
+    let replication_factor = NonZeroUsize::new(2).unwrap();
+    let timeout = Duration::from_secs(30);
+    let options = QueryOptions {
+        replication_factor: Some(replication_factor),
+        timeout: Some(timeout),
+        .. QueryOptions::default()
+    };
+    let qid = swarms[0].get_closest_peers_with(PeerId::random(), options);
+
+    block_on(
+        poll_fn(move |ctx| {
+            for swarm in &mut swarms {
+                loop {
+                    match swarm.poll_next_unpin(ctx) {
+                        Poll::Ready(Some(KademliaEvent::QueryResult {
+                            id,
+                            result: QueryResult::GetClosestPeers(Ok(ok)),
+                            ..
+                        })) => {
+                            assert_eq!(id, qid);
+                            assert_eq!(ok.peers.len(), replication_factor.get());
+                            assert_eq!(ok.params, QueryParams {
+                                quorum: None,
+                                quorum_reached: true,
+                                replication_factor,
+                                replication_reached: true,
+                                timeout,
+                            });
+                            return Poll::Ready(());
+                        }
+                        // Ignore any other event.
+                        Poll::Ready(Some(_)) => (),
+                        e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                        Poll::Pending => break,
+                    }
+                }
+            }
+
+            Poll::Pending
+        })
+    )
+}
+
+#[test]
+fn get_providers_with_quorum() {
+    let mut swarms = build_fully_connected_nodes_with_config(4, Default::default())
+        .into_iter()
+        .map(|(_addr, swarm)| swarm)
+        .collect::<Vec<_>>();
+
+    let key = Key::from(random_multihash());
+    for swarm in swarms.iter_mut().skip(1) {
+        let provider = *Swarm::local_peer_id(swarm);
+        swarm.store.add_provider(ProviderRecord::new(key.clone(), provider, Vec::new())).unwrap();
+    }
+
+    let options = QueryOptions { quorum: Some(Quorum::One), .. QueryOptions::default() };
+    let qid = swarms[0].get_providers_with(key, options);
+
+    block_on(
+        poll_fn(move |ctx| {
+            for swarm in &mut swarms {
+                loop {
+                    match swarm.poll_next_unpin(ctx) {
+                        Poll::Ready(Some(KademliaEvent::QueryResult {
+                            id,
+                            result: QueryResult::GetProviders(Ok(ok)),
+                            ..
+                        })) => {
+                            assert_eq!(id, qid);
+                            assert!(!ok.providers.is_empty());
+                            assert_eq!(ok.params.quorum, NonZeroUsize::new(1));
+                            assert!(ok.params.quorum_reached);
+                            return Poll::Ready(());
+                        }
+                        // Ignore any other event.
+                        Poll::Ready(Some(_)) => (),
+                        e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                        Poll::Pending => break,
+                    }
+                }
+            }
+
+            Poll::Pending
+        })
+    )
+}
+
+#[test]
+fn put_record_rejected_by_validator() {
+    let mut config = KademliaConfig::default();
+    config.add_record_validator("test", validator::MaxValueSize(1));
+
+    let (_, mut alice) = build_node();
+    let (bob_addr, mut bob) = build_node_with_config(config);
+    alice.add_address(Swarm::local_peer_id(&bob), bob_addr);
+
+    let valid = Record::new(Key::with_namespace("test", b"valid"), vec![0]);
+    let invalid = Record::new(Key::with_namespace("test", b"invalid"), vec![0; 2]);
+    let mut expected = vec![
+        (alice.put_record(valid.clone(), Quorum::One).unwrap(), true),
+        (alice.put_record(invalid.clone(), Quorum::One).unwrap(), false),
+    ];
+
+    block_on(
+        poll_fn(|ctx| {
+            loop {
+                match alice.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(KademliaEvent::QueryResult {
+                        id, result: QueryResult::PutRecord(result), ..
+                    })) => {
+                        let i = expected.iter().position(|(q, _)| *q == id).unwrap();
+                        let (_, success) = expected.remove(i);
+                        assert_eq!(success, result.is_ok(), "{:?}", result);
+                    }
+                    // Ignore any other event.
+                    Poll::Ready(Some(_)) => (),
+                    Poll::Ready(None) => panic!("Expected Kademlia behaviour not to finish."),
+                    Poll::Pending => break,
+                }
+            }
+            while let Poll::Ready(Some(_)) = bob.poll_next_unpin(ctx) {}
+            if expected.is_empty() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+    );
+
+    assert!(bob.store.get(&valid.key).is_some());
+    assert!(bob.store.get(&invalid.key).is_none());
+}
+
 #[test]
 fn manual_bucket_inserts() {
     let mut cfg = KademliaConfig::default();
@@ -1154,3 +1455,87 @@ fn network_behaviour_inject_address_change() {
         kademlia.addresses_of_peer(&remote_peer_id),
     );
 }
+
+#[test]
+fn routing_table_introspection() {
+    let local_id = PeerId::random();
+    let mut kademlia = Kademlia::new(local_id, MemoryStore::new(local_id));
+    let peer = PeerId::random();
+    let address: Multiaddr = Protocol::Memory(1).into();
+
+    assert!(matches!(kademlia.add_address(&peer, address.clone()), RoutingUpdate::Success));
+    kademlia.connection_updated(peer, None, NodeStatus::Connected);
+
+    let table = kademlia.routing_table();
+    assert_eq!(table.len(), 1);
+    let entry = &table[0].entries[0];
+    assert_eq!(entry.peer, peer);
+    assert_eq!(entry.addresses.iter().collect::<Vec<_>>(), vec![&address]);
+    assert_eq!(entry.status, NodeStatus::Connected);
+    assert!(entry.last_seen.is_some());
+    assert!(!entry.pinned);
+
+    kademlia.queued_events.clear();
+    kademlia.remove_peer(&peer);
+    assert!(kademlia.routing_table().is_empty());
+    assert!(kademlia.queued_events.iter().any(|e| matches!(e,
+        NetworkBehaviourAction::GenerateEvent(KademliaEvent::BucketEmptied { range })
+            if *range == table[0].range
+    )));
+}
+
+#[test]
+fn pinned_peer_is_not_evicted() {
+    let local_id = PeerId::random();
+    let mut config = KademliaConfig::default();
+    config.kbucket_pending_timeout = Duration::from_millis(1);
+    let mut kademlia = Kademlia::with_config(local_id, MemoryStore::new(local_id), config);
+    let address: Multiaddr = Protocol::Memory(1).into();
+
+    // Peers of the farthest bucket, which holds half of all keys.
+    let local_key = kbucket::Key::from(local_id);
+    let mut peers = std::iter::repeat_with(PeerId::random)
+        .filter(|p| local_key.distance(&kbucket::Key::from(*p)).ilog2() == Some(255))
+        .take(K_VALUE.get() + 1)
+        .collect::<Vec<_>>();
+    let newcomer = peers.pop().unwrap();
+
+    // Fill the bucket with disconnected peers, the pinned one being the
+    // least-recently connected.
+    assert!(matches!(kademlia.pin_peer(&peers[0], address.clone()), RoutingUpdate::Success));
+    for peer in &peers[1..] {
+        assert!(matches!(kademlia.add_address(peer, address.clone()), RoutingUpdate::Success));
+    }
+
+    // A connected peer is pending insertion in place of the pinned peer.
+    kademlia.connected_peers.insert(newcomer);
+    assert!(matches!(kademlia.add_address(&newcomer, address.clone()), RoutingUpdate::Pending));
+    std::thread::sleep(Duration::from_millis(10));
+    // Accessing the routing table applies the pending entry.
+    kademlia.routing_table();
+    assert!(kademlia.apply_pending_entries().is_none());
+    let table = kademlia.routing_table()
+        .into_iter()
+        .flat_map(|b| b.entries.into_iter().map(|e| (e.peer, e.pinned)))
+        .collect::<Vec<_>>();
+    assert!(table.contains(&(peers[0], true)));
+    assert!(!table.iter().any(|(p, _)| *p == newcomer));
+
+    // Once unpinned, the next least-recently connected peer is evicted.
+    kademlia.queued_events.clear();
+    assert!(kademlia.unpin_peer(&peers[0]));
+    assert!(matches!(kademlia.add_address(&newcomer, address), RoutingUpdate::Pending));
+    std::thread::sleep(Duration::from_millis(10));
+    kademlia.routing_table();
+    match kademlia.apply_pending_entries() {
+        Some(KademliaEvent::RoutingUpdated { peer, old_peer, .. }) => {
+            assert_eq!(peer, newcomer);
+            assert_eq!(old_peer, Some(peers[1]));
+        }
+        e => panic!("Unexpected event: {:?}", e),
+    }
+    assert!(kademlia.queued_events.iter().any(|e| matches!(e,
+        NetworkBehaviourAction::GenerateEvent(KademliaEvent::PeerEvicted { peer, replaced_by, .. })
+            if *peer == peers[1] && *replaced_by == newcomer
+    )));
+}
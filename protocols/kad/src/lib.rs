@@ -40,6 +40,7 @@ mod dht_proto {
 
 pub use addresses::Addresses;
 pub use behaviour::{Kademlia, KademliaBucketInserts, KademliaConfig, KademliaEvent, Quorum};
+pub use behaviour::{RoutingTableBucket, RoutingTableEntry};
 pub use behaviour::{
     QueryRef,
     QueryMut,
@@ -47,6 +48,8 @@ pub use behaviour::{
     QueryResult,
     QueryInfo,
     QueryStats,
+    QueryOptions,
+    QueryParams,
 
     PeerRecord,
 
@@ -80,7 +83,7 @@ pub use behaviour::{
 };
 pub use query::QueryId;
 pub use protocol::KadConnectionType;
-pub use record::{store, Record, ProviderRecord};
+pub use record::{store, validator, Record, ProviderRecord};
 
 use std::num::NonZeroUsize;
 
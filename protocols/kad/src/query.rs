@@ -106,7 +106,7 @@ impl<TInner> QueryPool<TInner> {
         assert!(!self.queries.contains_key(&id));
         let parallelism = self.config.replication_factor;
         let peer_iter = QueryPeerIter::Fixed(FixedPeersIter::new(peers, parallelism));
-        let query = Query::new(id, peer_iter, inner);
+        let query = Query::new(id, peer_iter, inner, self.config.timeout, parallelism);
         self.queries.insert(id, query);
     }
 
@@ -121,14 +121,52 @@ impl<TInner> QueryPool<TInner> {
         id
     }
 
+    /// Adds a query to the pool that iterates towards the closest peers to the target,
+    /// using the given timeout and replication factor instead of those of the
+    /// `QueryConfig` of the pool.
+    pub fn add_iter_closest_with<T, I>(
+        &mut self,
+        target: T,
+        peers: I,
+        inner: TInner,
+        timeout: Duration,
+        replication_factor: NonZeroUsize,
+    ) -> QueryId
+    where
+        T: Into<KeyBytes> + Clone,
+        I: IntoIterator<Item = Key<PeerId>>
+    {
+        let id = self.next_query_id();
+        self.insert_iter_closest(id, target, peers, inner, timeout, replication_factor);
+        id
+    }
+
     /// Adds a query to the pool that iterates towards the closest peers to the target.
     pub fn continue_iter_closest<T, I>(&mut self, id: QueryId, target: T, peers: I, inner: TInner)
+    where
+        T: Into<KeyBytes> + Clone,
+        I: IntoIterator<Item = Key<PeerId>>
+    {
+        let timeout = self.config.timeout;
+        let replication_factor = self.config.replication_factor;
+        self.insert_iter_closest(id, target, peers, inner, timeout, replication_factor)
+    }
+
+    fn insert_iter_closest<T, I>(
+        &mut self,
+        id: QueryId,
+        target: T,
+        peers: I,
+        inner: TInner,
+        timeout: Duration,
+        replication_factor: NonZeroUsize,
+    )
     where
         T: Into<KeyBytes> + Clone,
         I: IntoIterator<Item = Key<PeerId>>
     {
         let cfg = ClosestPeersIterConfig {
-            num_results: self.config.replication_factor,
+            num_results: replication_factor,
             parallelism: self.config.parallelism,
             .. ClosestPeersIterConfig::default()
         };
@@ -141,11 +179,13 @@ impl<TInner> QueryPool<TInner> {
             QueryPeerIter::Closest(ClosestPeersIter::with_config(cfg, target, peers))
         };
 
-        let query = Query::new(id, peer_iter, inner);
+        let query = Query::new(id, peer_iter, inner, timeout, replication_factor);
         self.queries.insert(id, query);
     }
 
-    fn next_query_id(&mut self) -> QueryId {
+    /// Allocates the ID of a query that is added to the pool later,
+    /// with one of the `continue_*` methods.
+    pub fn next_query_id(&mut self) -> QueryId {
         let id = QueryId(self.next_id);
         self.next_id = self.next_id.wrapping_add(1);
         id
@@ -181,7 +221,7 @@ impl<TInner> QueryPool<TInner> {
                 }
                 PeersIterState::Waiting(None) | PeersIterState::WaitingAtCapacity => {
                     let elapsed = now - query.stats.start.unwrap_or(now);
-                    if elapsed >= self.config.timeout {
+                    if elapsed >= query.timeout {
                         timeout = Some(query_id);
                         break
                     }
@@ -240,6 +280,11 @@ pub struct QueryConfig {
     ///
     /// See [`crate::behaviour::KademliaConfig::disjoint_query_paths`] for details.
     pub disjoint_query_paths: bool,
+
+    /// The number of disjoint paths on which a result must be received to be accepted.
+    ///
+    /// See [`crate::behaviour::KademliaConfig::set_disjoint_path_corroboration`] for details.
+    pub disjoint_path_corroboration: NonZeroUsize,
 }
 
 impl Default for QueryConfig {
@@ -249,6 +294,7 @@ impl Default for QueryConfig {
             replication_factor: NonZeroUsize::new(K_VALUE.get()).expect("K_VALUE > 0"),
             parallelism: ALPHA_VALUE,
             disjoint_query_paths: false,
+            disjoint_path_corroboration: NonZeroUsize::new(1).expect("1 > 0"),
         }
     }
 }
@@ -261,6 +307,10 @@ pub struct Query<TInner> {
     peer_iter: QueryPeerIter,
     /// Execution statistics of the query.
     stats: QueryStats,
+    /// The timeout of the query.
+    timeout: Duration,
+    /// The number of closest peers the query aims to reach.
+    replication_factor: NonZeroUsize,
     /// The opaque inner query state.
     pub inner: TInner,
 }
@@ -274,8 +324,14 @@ enum QueryPeerIter {
 
 impl<TInner> Query<TInner> {
     /// Creates a new query without starting it.
-    fn new(id: QueryId, peer_iter: QueryPeerIter, inner: TInner) -> Self {
-        Query { id, inner, peer_iter, stats: QueryStats::empty() }
+    fn new(
+        id: QueryId,
+        peer_iter: QueryPeerIter,
+        inner: TInner,
+        timeout: Duration,
+        replication_factor: NonZeroUsize,
+    ) -> Self {
+        Query { id, inner, peer_iter, stats: QueryStats::empty(), timeout, replication_factor }
     }
 
     /// Gets the unique ID of the query.
@@ -288,6 +344,16 @@ impl<TInner> Query<TInner> {
         &self.stats
     }
 
+    /// Gets the timeout of the query.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Gets the number of closest peers the query aims to reach.
+    pub fn replication_factor(&self) -> NonZeroUsize {
+        self.replication_factor
+    }
+
     /// Informs the query that the attempt to contact `peer` failed.
     pub fn on_failure(&mut self, peer: &PeerId) {
         let updated = match &mut self.peer_iter {
@@ -326,6 +392,15 @@ impl<TInner> Query<TInner> {
         }
     }
 
+    /// Returns the index of the disjoint path on which `peer` was contacted,
+    /// if the query uses disjoint paths and contacted `peer`.
+    pub fn path_of(&self, peer: &PeerId) -> Option<usize> {
+        match &self.peer_iter {
+            QueryPeerIter::ClosestDisjoint(iter) => iter.path_of(peer),
+            QueryPeerIter::Closest(_) | QueryPeerIter::Fixed(_) => None
+        }
+    }
+
     /// Advances the state of the underlying peer iterator.
     fn next(&mut self, now: Instant) -> PeersIterState<'_> {
         let state = match &mut self.peer_iter {
@@ -177,6 +177,12 @@ impl ClosestDisjointPeersIter {
         updated
     }
 
+    /// Returns the index of the disjoint path on which `peer` was contacted,
+    /// if it has been contacted by the iterator.
+    pub fn path_of(&self, peer: &PeerId) -> Option<usize> {
+        self.contacted_peers.get(peer).map(|s| s.initiated_by.0)
+    }
+
     pub fn is_waiting(&self, peer: &PeerId) -> bool {
         self.iters.iter().any(|i| i.is_waiting(peer))
     }
@@ -969,4 +975,35 @@ mod tests {
             response: ResponseState::Succeeded,
         }));
     }
+
+    #[test]
+    fn path_of_contacted_peers() {
+        let now = Instant::now();
+        let peers = (0..3).map(|_| Key::from(PeerId::random())).collect::<Vec<_>>();
+        let config = ClosestPeersIterConfig {
+            parallelism: NonZeroUsize::new(3).unwrap(),
+            ..ClosestPeersIterConfig::default()
+        };
+        let mut iter = ClosestDisjointPeersIter::with_config(
+            config,
+            Key::from(PeerId::random()),
+            peers.clone(),
+        );
+
+        assert_eq!(iter.path_of(peers[0].preimage()), None);
+
+        let mut paths = Vec::new();
+        for _ in 0..3 {
+            if let PeersIterState::Waiting(Some(peer)) = iter.next(now) {
+                let peer = peer.into_owned();
+                paths.push(iter.path_of(&peer).expect("Contacted peer to have a path."));
+            } else {
+                panic!("Expected iterator to return peer to query.");
+            }
+        }
+
+        // Each path contacted one of the peers.
+        paths.sort_unstable();
+        assert_eq!(paths, vec![0, 1, 2]);
+    }
 }
@@ -21,6 +21,7 @@
 //! Records and record storage abstraction of the libp2p Kademlia DHT.
 
 pub mod store;
+pub mod validator;
 
 use bytes::Bytes;
 use libp2p_core::{PeerId, Multiaddr, multihash::Multihash};
@@ -38,10 +39,31 @@ impl Key {
         Key(Bytes::copy_from_slice(key.as_ref()))
     }
 
+    /// Creates a new key of the form `/<namespace>/<rest>`.
+    pub fn with_namespace(namespace: &str, rest: &[u8]) -> Self {
+        let mut bytes = Vec::with_capacity(namespace.len() + rest.len() + 2);
+        bytes.push(b'/');
+        bytes.extend_from_slice(namespace.as_bytes());
+        bytes.push(b'/');
+        bytes.extend_from_slice(rest);
+        Key(Bytes::from(bytes))
+    }
+
     /// Copies the bytes of the key into a new vector.
     pub fn to_vec(&self) -> Vec<u8> {
         Vec::from(&self.0[..])
     }
+
+    /// Splits a key of the form `/<namespace>/<rest>` into the namespace
+    /// and the rest, returning `None` for keys without a namespace.
+    pub fn split_namespace(&self) -> Option<(&[u8], &[u8])> {
+        if self.0.first() != Some(&b'/') {
+            return None
+        }
+        let key = &self.0[1..];
+        let end = key.iter().position(|b| *b == b'/')?;
+        Some((&key[.. end], &key[end + 1 ..]))
+    }
 }
 
 impl Borrow<[u8]> for Key {
@@ -198,4 +220,13 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn split_namespace() {
+        let key = Key::with_namespace("pk", b"peer/id");
+        assert_eq!(key.as_ref(), b"/pk/peer/id");
+        assert_eq!(key.split_namespace(), Some((&b"pk"[..], &b"peer/id"[..])));
+        assert_eq!(Key::new(&b"/pk").split_namespace(), None);
+        assert_eq!(Key::new(&b"pk/peer").split_namespace(), None);
+    }
 }
@@ -19,12 +19,16 @@
 // DEALINGS IN THE SOFTWARE.
 
 mod memory;
+#[cfg(feature = "sled-store")]
+mod sled_store;
 
 pub use memory::{MemoryStore, MemoryStoreConfig};
+#[cfg(feature = "sled-store")]
+pub use sled_store::SledStore;
 
 use crate::K_VALUE;
 use super::*;
-use std::borrow::Cow;
+use std::{borrow::Cow, io, task::{Context, Poll}};
 
 /// The result of an operation on a `RecordStore`.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -59,6 +63,12 @@ pub enum Error {
 ///      content. Just like a regular record, a provider record is distributed
 ///      to the closest nodes to the key.
 ///
+/// A store may persist its records, e.g. on disk, in which case the records
+/// found in the store when `Kademlia` is created are republished once the
+/// first peer is connected. Since the methods of the trait are called
+/// synchronously from within `Kademlia::poll`, such a store should only
+/// update its working set in these methods and batch the writes to the
+/// persistent storage, which `Kademlia` drives via [`RecordStore::poll_flush`].
 pub trait RecordStore<'a> {
     type RecordsIter: Iterator<Item = Cow<'a, Record>>;
     type ProvidedIter: Iterator<Item = Cow<'a, ProviderRecord>>;
@@ -91,5 +101,15 @@ pub trait RecordStore<'a> {
 
     /// Removes a provider record from the store.
     fn remove_provider(&'a mut self, k: &Key, p: &PeerId);
+
+    /// Makes progress on writing the pending changes to the persistent
+    /// storage of the store, if any.
+    ///
+    /// Called on every poll of `Kademlia`. Errors are logged and the
+    /// method is polled again later. The default implementation, suitable
+    /// for stores without persistent storage, has nothing to write.
+    fn poll_flush(&'a mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
 }
 
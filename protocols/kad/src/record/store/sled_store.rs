@@ -0,0 +1,401 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use super::*;
+
+use futures::{future::BoxFuture, prelude::*, ready};
+use libp2p_core::{Multiaddr, PeerId};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{io, mem};
+use std::task::{Context, Poll};
+use wasm_timer::Instant;
+
+/// A `RecordStore` persisting its records in a [sled] database.
+///
+/// All records are kept in memory in a [`MemoryStore`], which is loaded from
+/// the database on creation and answers all reads. Modifications are
+/// collected in batches which are written to the database and flushed to
+/// disk by [`RecordStore::poll_flush`], i.e. in the background of
+/// `Kademlia::poll`. Changes that have not been flushed yet are lost if the
+/// process exits, as are the changes of a batch whose write fails.
+///
+/// Expiration times are persisted as wall-clock times, so that records
+/// expiring while the node is down are discarded on the next start.
+///
+/// [sled]: https://docs.rs/sled
+pub struct SledStore {
+    /// The working set of records.
+    memory: MemoryStore,
+    /// The database.
+    db: ::sled::Db,
+    /// The tree of (value-)records, by key.
+    records: ::sled::Tree,
+    /// The tree of the provider records, by key.
+    providers: ::sled::Tree,
+    /// The pending modifications of `records`.
+    records_batch: ::sled::Batch,
+    /// The pending modifications of `providers`.
+    providers_batch: ::sled::Batch,
+    /// Whether there are pending modifications in the batches.
+    dirty: bool,
+    /// The flush of the database in progress, if any.
+    flushing: Option<BoxFuture<'static, ::sled::Result<usize>>>,
+}
+
+impl SledStore {
+    /// Opens or creates the database at the given path with a default
+    /// configuration for the working set.
+    pub fn open<P: AsRef<Path>>(local_id: PeerId, path: P) -> io::Result<Self> {
+        let db = ::sled::open(path)?;
+        Self::with_config(local_id, db, Default::default())
+    }
+
+    /// Creates a new `SledStore` on the given database, loading the records
+    /// stored previously.
+    ///
+    /// The limits of the given configuration apply to the loaded records as
+    /// well. Expired and undecodable records are discarded.
+    pub fn with_config(local_id: PeerId, db: ::sled::Db, config: MemoryStoreConfig)
+        -> io::Result<Self>
+    {
+        let records = db.open_tree("records")?;
+        let providers = db.open_tree("providers")?;
+        let mut store = SledStore {
+            memory: MemoryStore::with_config(local_id, config),
+            db,
+            records,
+            providers,
+            records_batch: Default::default(),
+            providers_batch: Default::default(),
+            dirty: false,
+            flushing: None,
+        };
+        store.load()?;
+        Ok(store)
+    }
+
+    /// Loads the records of the database into the working set.
+    fn load(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+
+        for entry in self.records.iter() {
+            let (k, v) = entry?;
+            let key = Key::new(&k);
+            match decode_record(key.clone(), &v) {
+                Ok(record) if !record.is_expired(now) => {
+                    if let Err(e) = self.memory.put(record) {
+                        log::debug!("Dropping stored record {:?}: {:?}", key, e);
+                    }
+                }
+                Ok(_) => self.records_batch.remove(k),
+                Err(e) => {
+                    log::warn!("Discarding undecodable stored record {:?}: {}", key, e);
+                    self.records_batch.remove(k)
+                }
+            }
+        }
+
+        for entry in self.providers.iter() {
+            let (k, v) = entry?;
+            let key = Key::new(&k);
+            match decode_providers(key.clone(), &v) {
+                Ok(records) => {
+                    for record in records.into_iter().filter(|r| !r.is_expired(now)) {
+                        if let Err(e) = self.memory.add_provider(record) {
+                            log::debug!("Dropping stored provider record {:?}: {:?}", key, e);
+                        }
+                    }
+                    // Rewrite the entry to reflect the expired and dropped records.
+                    self.write_providers(&key);
+                }
+                Err(e) => {
+                    log::warn!("Discarding undecodable stored providers {:?}: {}", key, e);
+                    self.providers_batch.remove(k)
+                }
+            }
+        }
+
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Records the current provider records for the given key in the batch.
+    fn write_providers(&mut self, key: &Key) {
+        let providers = self.memory.providers(key);
+        if providers.is_empty() {
+            self.providers_batch.remove(key.to_vec());
+        } else {
+            self.providers_batch.insert(key.to_vec(), encode_providers(&providers));
+        }
+        self.dirty = true;
+    }
+}
+
+impl<'a> RecordStore<'a> for SledStore {
+    type RecordsIter = <MemoryStore as RecordStore<'a>>::RecordsIter;
+    type ProvidedIter = <MemoryStore as RecordStore<'a>>::ProvidedIter;
+
+    fn get(&'a self, k: &Key) -> Option<Cow<'_, Record>> {
+        self.memory.get(k)
+    }
+
+    fn put(&'a mut self, r: Record) -> Result<()> {
+        let value = encode_record(&r);
+        let key = r.key.to_vec();
+        self.memory.put(r)?;
+        self.records_batch.insert(key, value);
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn remove(&'a mut self, k: &Key) {
+        self.memory.remove(k);
+        self.records_batch.remove(k.to_vec());
+        self.dirty = true;
+    }
+
+    fn records(&'a self) -> Self::RecordsIter {
+        self.memory.records()
+    }
+
+    fn add_provider(&'a mut self, record: ProviderRecord) -> Result<()> {
+        let key = record.key.clone();
+        self.memory.add_provider(record)?;
+        self.write_providers(&key);
+        Ok(())
+    }
+
+    fn providers(&'a self, key: &Key) -> Vec<ProviderRecord> {
+        self.memory.providers(key)
+    }
+
+    fn provided(&'a self) -> Self::ProvidedIter {
+        self.memory.provided()
+    }
+
+    fn remove_provider(&'a mut self, k: &Key, p: &PeerId) {
+        self.memory.remove_provider(k, p);
+        self.write_providers(k);
+    }
+
+    fn poll_flush(&'a mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(flushing) = self.flushing.as_mut() {
+                let result = ready!(flushing.as_mut().poll(cx));
+                self.flushing = None;
+                result?;
+            }
+
+            if !self.dirty {
+                return Poll::Ready(Ok(()))
+            }
+
+            self.dirty = false;
+            self.records.apply_batch(mem::take(&mut self.records_batch))?;
+            self.providers.apply_batch(mem::take(&mut self.providers_batch))?;
+            let db = self.db.clone();
+            self.flushing = Some(async move { db.flush_async().await }.boxed());
+        }
+    }
+}
+
+// Encoding of the stored values, in terms of unsigned varints (`uvi`) and
+// length-prefixed byte strings (`bytes`):
+//
+//   record    = bytes(value) option(bytes(publisher)) option(uvi(expires))
+//   providers = uvi(count) *(bytes(provider) option(uvi(expires)) uvi(count) *bytes(address))
+//   option(x) = uvi(0) / uvi(1) x
+//
+// Keys are not part of the values, being the keys of the database entries.
+// Expiration times are in seconds since the UNIX epoch.
+
+fn encode_record(record: &Record) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(record.value.len() + 64);
+    put_bytes(&mut buf, &record.value);
+    match &record.publisher {
+        Some(p) => { put_uvi(&mut buf, 1); put_bytes(&mut buf, &p.to_bytes()) }
+        None => put_uvi(&mut buf, 0),
+    }
+    put_expires(&mut buf, record.expires);
+    buf
+}
+
+fn decode_record(key: Key, mut buf: &[u8]) -> io::Result<Record> {
+    let value = take_bytes(&mut buf)?.to_vec();
+    let publisher = match take_uvi(&mut buf)? {
+        0 => None,
+        _ => Some(take_peer_id(&mut buf)?),
+    };
+    let expires = take_expires(&mut buf)?;
+    Ok(Record { key, value, publisher, expires })
+}
+
+fn encode_providers(records: &[ProviderRecord]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_uvi(&mut buf, records.len() as u64);
+    for record in records {
+        put_bytes(&mut buf, &record.provider.to_bytes());
+        put_expires(&mut buf, record.expires);
+        put_uvi(&mut buf, record.addresses.len() as u64);
+        for addr in &record.addresses {
+            put_bytes(&mut buf, addr.as_ref());
+        }
+    }
+    buf
+}
+
+fn decode_providers(key: Key, mut buf: &[u8]) -> io::Result<Vec<ProviderRecord>> {
+    let count = take_uvi(&mut buf)?;
+    let mut records = Vec::new();
+    for _ in 0 .. count {
+        let provider = take_peer_id(&mut buf)?;
+        let expires = take_expires(&mut buf)?;
+        let mut addresses = Vec::new();
+        for _ in 0 .. take_uvi(&mut buf)? {
+            let addr = Multiaddr::try_from(take_bytes(&mut buf)?.to_vec())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            addresses.push(addr);
+        }
+        records.push(ProviderRecord { key: key.clone(), provider, expires, addresses });
+    }
+    Ok(records)
+}
+
+fn put_uvi(buf: &mut Vec<u8>, n: u64) {
+    buf.extend_from_slice(unsigned_varint::encode::u64(n, &mut unsigned_varint::encode::u64_buffer()))
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_uvi(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes)
+}
+
+fn put_expires(buf: &mut Vec<u8>, expires: Option<Instant>) {
+    match expires {
+        Some(t) => {
+            let now = Instant::now();
+            let at = if t >= now {
+                SystemTime::now() + (t - now)
+            } else {
+                SystemTime::now() - (now - t)
+            };
+            put_uvi(buf, 1);
+            put_uvi(buf, at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+        }
+        None => put_uvi(buf, 0),
+    }
+}
+
+fn take_uvi(buf: &mut &[u8]) -> io::Result<u64> {
+    let (n, rest) = unsigned_varint::decode::u64(buf)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid varint"))?;
+    *buf = rest;
+    Ok(n)
+}
+
+fn take_bytes<'b>(buf: &mut &'b [u8]) -> io::Result<&'b [u8]> {
+    let len = take_uvi(buf)? as usize;
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into())
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+fn take_peer_id(buf: &mut &[u8]) -> io::Result<PeerId> {
+    PeerId::from_bytes(take_bytes(buf)?)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid peer ID"))
+}
+
+fn take_expires(buf: &mut &[u8]) -> io::Result<Option<Instant>> {
+    if take_uvi(buf)? == 0 {
+        return Ok(None)
+    }
+    let at = UNIX_EPOCH + Duration::from_secs(take_uvi(buf)?);
+    let now = Instant::now();
+    Ok(Some(match at.duration_since(SystemTime::now()) {
+        Ok(d) => now + d,
+        Err(e) => now.checked_sub(e.duration()).unwrap_or(now),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::future::poll_fn;
+
+    fn flush(store: &mut SledStore) {
+        block_on(poll_fn(|cx| store.poll_flush(cx))).unwrap()
+    }
+
+    #[test]
+    fn records_survive_reopening() {
+        let db = ::sled::Config::new().temporary(true).open().unwrap();
+        let local_id = PeerId::random();
+        let key = Key::new(&b"key");
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+
+        let mut record = Record::new(key.clone(), b"value".to_vec());
+        record.publisher = Some(local_id);
+        record.expires = Some(Instant::now() + Duration::from_secs(3600));
+        let expired = Record {
+            key: Key::new(&b"expired"),
+            value: Vec::new(),
+            publisher: None,
+            expires: Some(Instant::now() + Duration::from_secs(1)),
+        };
+        let provider = ProviderRecord::new(key.clone(), local_id, vec![addr.clone()]);
+
+        {
+            let mut store = SledStore::with_config(local_id, db.clone(), Default::default()).unwrap();
+            store.put(record.clone()).unwrap();
+            store.put(expired.clone()).unwrap();
+            store.add_provider(provider.clone()).unwrap();
+            flush(&mut store);
+        }
+
+        std::thread::sleep(Duration::from_secs(2));
+
+        let mut store = SledStore::with_config(local_id, db.clone(), Default::default()).unwrap();
+        let loaded = store.get(&key).unwrap().into_owned();
+        assert_eq!(loaded.value, record.value);
+        assert_eq!(loaded.publisher, record.publisher);
+        assert!(loaded.expires.is_some());
+        assert!(store.get(&expired.key).is_none());
+        assert_eq!(store.provided().map(|r| r.into_owned()).collect::<Vec<_>>(), vec![provider]);
+        assert_eq!(store.providers(&key)[0].addresses, vec![addr]);
+
+        // Removals are persisted as well.
+        store.remove(&key);
+        store.remove_provider(&key, &local_id);
+        flush(&mut store);
+        drop(store);
+
+        let store = SledStore::with_config(local_id, db, Default::default()).unwrap();
+        assert_eq!(store.records().count(), 0);
+        assert_eq!(store.provided().count(), 0);
+    }
+}
@@ -0,0 +1,324 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Validation of records by the namespace of their keys.
+//!
+//! Keys of the form `/<namespace>/<rest>` are namespaced. The validators
+//! registered for a namespace via [`KademliaConfig::add_record_validator`]
+//! are run on every record with a key in that namespace which is received
+//! from a remote, i.e. on records to be stored as well as on the results of
+//! [`Kademlia::get_record`]. Invalid records are neither stored nor reported.
+//!
+//! [`KademliaConfig::add_record_validator`]: crate::KademliaConfig::add_record_validator
+//! [`Kademlia::get_record`]: crate::Kademlia::get_record
+
+use super::*;
+use libp2p_core::identity::{self, PublicKey};
+use std::{error, fmt, sync::Arc, time::Duration};
+
+/// A validator of records.
+///
+/// Implemented for closures taking the record to validate and the current time.
+pub trait RecordValidator: Send + Sync + 'static {
+    /// Checks whether the given record, received from a remote, is valid.
+    fn validate(&self, record: &Record, now: Instant) -> Result<(), ValidationError>;
+}
+
+impl<F> RecordValidator for F
+where
+    F: Fn(&Record, Instant) -> Result<(), ValidationError> + Send + Sync + 'static
+{
+    fn validate(&self, record: &Record, now: Instant) -> Result<(), ValidationError> {
+        self(record, now)
+    }
+}
+
+/// Rejects records whose value exceeds the given number of bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxValueSize(pub usize);
+
+impl RecordValidator for MaxValueSize {
+    fn validate(&self, record: &Record, _: Instant) -> Result<(), ValidationError> {
+        if record.value.len() > self.0 {
+            return Err(ValidationError::ValueTooLarge { size: record.value.len(), max: self.0 })
+        }
+        Ok(())
+    }
+}
+
+/// Rejects records which are expired or which do not expire within the
+/// given duration, ensuring that only fresh records are stored.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxTtl(pub Duration);
+
+impl RecordValidator for MaxTtl {
+    fn validate(&self, record: &Record, now: Instant) -> Result<(), ValidationError> {
+        match record.expires {
+            Some(t) if t <= now => Err(ValidationError::Expired),
+            Some(t) if t - now <= self.0 => Ok(()),
+            _ => Err(ValidationError::TtlTooLong),
+        }
+    }
+}
+
+/// Validates records of the `/pk/` namespace, whose keys are
+/// `/pk/<peer ID>` and whose values are the protobuf encoding of the
+/// public key of that peer.
+#[derive(Debug, Clone, Copy)]
+pub struct PublicKeyRecord;
+
+impl RecordValidator for PublicKeyRecord {
+    fn validate(&self, record: &Record, _: Instant) -> Result<(), ValidationError> {
+        let peer = record.key.split_namespace()
+            .and_then(|(_, rest)| PeerId::from_bytes(rest).ok())
+            .ok_or(ValidationError::InvalidKey)?;
+        let public_key = PublicKey::from_protobuf_encoding(&record.value)
+            .map_err(|_| ValidationError::InvalidValue)?;
+        if peer.is_public_key(&public_key) != Some(true) {
+            return Err(ValidationError::InvalidKey)
+        }
+        Ok(())
+    }
+}
+
+/// Validates records whose value is signed by their publisher, as created
+/// by [`sign_value`].
+///
+/// The public key of the publisher is obtained from its peer ID, hence only
+/// publishers with identity-hashed peer IDs, e.g. ed25519 keys, are supported.
+#[derive(Debug, Clone, Copy)]
+pub struct PublisherSignature;
+
+impl RecordValidator for PublisherSignature {
+    fn validate(&self, record: &Record, _: Instant) -> Result<(), ValidationError> {
+        let publisher = record.publisher.as_ref().ok_or(ValidationError::InvalidSignature)?;
+        let public_key = PublicKey::from_protobuf_encoding(publisher.as_ref().digest())
+            .map_err(|_| ValidationError::InvalidSignature)?;
+        if publisher.is_public_key(&public_key) != Some(true) {
+            return Err(ValidationError::InvalidSignature)
+        }
+        let (signature, payload) = split_signed_value(&record.value)
+            .ok_or(ValidationError::InvalidValue)?;
+        if !public_key.verify(&signed_message(&record.key, payload), signature) {
+            return Err(ValidationError::InvalidSignature)
+        }
+        Ok(())
+    }
+}
+
+/// Creates the value of a record with the given key, signed with the given
+/// keypair of the publisher, to be validated by [`PublisherSignature`].
+///
+/// The value is the length-prefixed signature of the key and the payload,
+/// followed by the payload, which is obtained with [`signed_payload`].
+pub fn sign_value(keypair: &identity::Keypair, key: &Key, payload: &[u8])
+    -> Result<Vec<u8>, identity::error::SigningError>
+{
+    let signature = keypair.sign(&signed_message(key, payload))?;
+    let mut len = unsigned_varint::encode::usize_buffer();
+    let len = unsigned_varint::encode::usize(signature.len(), &mut len);
+    let mut value = Vec::with_capacity(len.len() + signature.len() + payload.len());
+    value.extend_from_slice(len);
+    value.extend_from_slice(&signature);
+    value.extend_from_slice(payload);
+    Ok(value)
+}
+
+/// Returns the payload of a value created by [`sign_value`].
+pub fn signed_payload(value: &[u8]) -> Option<&[u8]> {
+    split_signed_value(value).map(|(_, payload)| payload)
+}
+
+fn split_signed_value(value: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = unsigned_varint::decode::usize(value).ok()?;
+    if rest.len() < len {
+        return None
+    }
+    Some(rest.split_at(len))
+}
+
+fn signed_message(key: &Key, payload: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(key.as_ref().len() + payload.len());
+    msg.extend_from_slice(key.as_ref());
+    msg.extend_from_slice(payload);
+    msg
+}
+
+/// The validators of the records of each namespace.
+#[derive(Clone, Default)]
+pub struct Validators {
+    validators: Vec<(Vec<u8>, Arc<dyn RecordValidator>)>,
+}
+
+impl Validators {
+    /// Adds a validator for the records with keys in the given namespace.
+    ///
+    /// A record must pass all validators of its namespace.
+    pub fn add<V: RecordValidator>(&mut self, namespace: &str, validator: V) {
+        self.validators.push((namespace.as_bytes().to_vec(), Arc::new(validator)))
+    }
+
+    /// Validates a record against the validators of the namespace of its key.
+    ///
+    /// Records without a namespace, or of a namespace without validators, are valid.
+    pub fn validate(&self, record: &Record, now: Instant) -> Result<(), ValidationError> {
+        let namespace = match record.key.split_namespace() {
+            Some((namespace, _)) => namespace,
+            None => return Ok(()),
+        };
+        self.validators.iter()
+            .filter(|(ns, _)| ns.as_slice() == namespace)
+            .try_for_each(|(_, v)| v.validate(record, now))
+    }
+}
+
+impl fmt::Debug for Validators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.validators.iter().map(|(ns, _)| String::from_utf8_lossy(ns)))
+            .finish()
+    }
+}
+
+/// The reasons for a record to be invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The value of the record is too large.
+    ValueTooLarge { size: usize, max: usize },
+    /// The record is expired.
+    Expired,
+    /// The record does not expire soon enough.
+    TtlTooLong,
+    /// The key of the record is malformed or does not match the value.
+    InvalidKey,
+    /// The value of the record is malformed.
+    InvalidValue,
+    /// The signature of the record is missing or invalid.
+    InvalidSignature,
+    /// The record is invalid for an application-specific reason.
+    Other(String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::ValueTooLarge { size, max } =>
+                write!(f, "value of {} bytes exceeds the maximum of {} bytes", size, max),
+            ValidationError::Expired => write!(f, "record is expired"),
+            ValidationError::TtlTooLong => write!(f, "record expires too late"),
+            ValidationError::InvalidKey => write!(f, "invalid key"),
+            ValidationError::InvalidValue => write!(f, "invalid value"),
+            ValidationError::InvalidSignature => write!(f, "invalid signature"),
+            ValidationError::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl error::Error for ValidationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: Key, value: Vec<u8>) -> Record {
+        Record::new(key, value)
+    }
+
+    #[test]
+    fn validators_apply_by_namespace() {
+        let mut validators = Validators::default();
+        validators.add("small", MaxValueSize(1));
+        validators.add("small", |r: &Record, _: Instant| {
+            if r.value == b"x" { Err(ValidationError::Other("x".into())) } else { Ok(()) }
+        });
+
+        let now = Instant::now();
+        let large = vec![0; 2];
+        assert!(validators.validate(&record(Key::new(&b"small"), large.clone()), now).is_ok());
+        assert!(validators.validate(&record(Key::with_namespace("other", b"k"), large.clone()), now).is_ok());
+        assert_eq!(
+            validators.validate(&record(Key::with_namespace("small", b"k"), large), now),
+            Err(ValidationError::ValueTooLarge { size: 2, max: 1 }),
+        );
+        assert_eq!(
+            validators.validate(&record(Key::with_namespace("small", b"k"), b"x".to_vec()), now),
+            Err(ValidationError::Other("x".into())),
+        );
+        assert!(validators.validate(&record(Key::with_namespace("small", b"k"), b"y".to_vec()), now).is_ok());
+    }
+
+    #[test]
+    fn max_ttl() {
+        let now = Instant::now();
+        let validator = MaxTtl(Duration::from_secs(60));
+        let mut r = record(Key::new(&b"k"), Vec::new());
+        assert_eq!(validator.validate(&r, now), Err(ValidationError::TtlTooLong));
+        r.expires = Some(now + Duration::from_secs(61));
+        assert_eq!(validator.validate(&r, now), Err(ValidationError::TtlTooLong));
+        r.expires = Some(now);
+        assert_eq!(validator.validate(&r, now), Err(ValidationError::Expired));
+        r.expires = Some(now + Duration::from_secs(60));
+        assert!(validator.validate(&r, now).is_ok());
+    }
+
+    #[test]
+    fn public_key_record() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let peer = keypair.public().into_peer_id();
+        let key = Key::with_namespace("pk", &peer.to_bytes());
+        let value = keypair.public().into_protobuf_encoding();
+        let now = Instant::now();
+
+        assert!(PublicKeyRecord.validate(&record(key.clone(), value.clone()), now).is_ok());
+
+        let other = identity::Keypair::generate_ed25519().public().into_protobuf_encoding();
+        assert_eq!(
+            PublicKeyRecord.validate(&record(key, other), now),
+            Err(ValidationError::InvalidKey),
+        );
+        assert_eq!(
+            PublicKeyRecord.validate(&record(Key::with_namespace("pk", b"?"), value), now),
+            Err(ValidationError::InvalidKey),
+        );
+    }
+
+    #[test]
+    fn publisher_signature() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let key = Key::with_namespace("mwc-slatepack", b"slate");
+        let value = sign_value(&keypair, &key, b"payload").unwrap();
+        assert_eq!(signed_payload(&value), Some(&b"payload"[..]));
+
+        let now = Instant::now();
+        let mut r = record(key, value);
+        assert_eq!(PublisherSignature.validate(&r, now), Err(ValidationError::InvalidSignature));
+
+        r.publisher = Some(keypair.public().into_peer_id());
+        assert!(PublisherSignature.validate(&r, now).is_ok());
+
+        // The signature does not cover another key.
+        r.key = Key::with_namespace("mwc-slatepack", b"other");
+        assert_eq!(PublisherSignature.validate(&r, now), Err(ValidationError::InvalidSignature));
+
+        r.publisher = Some(identity::Keypair::generate_ed25519().public().into_peer_id());
+        assert_eq!(PublisherSignature.validate(&r, now), Err(ValidationError::InvalidSignature));
+    }
+}
@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::service::{MdnsPacket, MdnsService, build_query_response, build_service_discovery_response};
+use crate::service::{InterfaceInfo, MdnsPacket, MdnsService, build_query_response, build_service_discovery_response};
 use async_io::Timer;
 use futures::prelude::*;
 use libp2p_core::{
@@ -29,17 +29,66 @@ use libp2p_core::{
     multiaddr::Protocol
 };
 use libp2p_swarm::{
+    DialPeerCondition,
     NetworkBehaviour,
     NetworkBehaviourAction,
     PollParameters,
     ProtocolsHandler,
     protocols_handler::DummyProtocolsHandler
 };
-use smallvec::SmallVec;
-use std::{cmp, fmt, io, iter, mem, pin::Pin, time::{Duration, Instant}, task::Context, task::Poll};
+use smallvec::{SmallVec, smallvec};
+use std::{
+    cmp,
+    collections::VecDeque,
+    fmt,
+    io,
+    iter,
+    mem,
+    pin::Pin,
+    time::{Duration, Instant},
+    task::Context,
+    task::Poll
+};
 
 const MDNS_RESPONSE_TTL: std::time::Duration = Duration::from_secs(5 * 60);
 
+/// Configuration for the [`Mdns`] behaviour.
+#[derive(Debug, Clone, Default)]
+pub struct MdnsConfig {
+    /// The maximum number of newly discovered peers to report per window, if paced.
+    pacing: Option<(usize, Duration)>,
+    /// Whether to dial newly discovered peers.
+    dial_discovered: bool,
+}
+
+impl MdnsConfig {
+    /// Creates a new configuration which reports discovered peers as soon
+    /// as they are discovered and does not dial them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paces the reporting of newly discovered peers, and dialing them if
+    /// enabled, to at most `max_peers` peers per `window`.
+    ///
+    /// When many peers are discovered at once, e.g. when joining a LAN with
+    /// many nodes, this spreads the follow-up actions of the application
+    /// over time rather than triggering a storm of simultaneous dials that
+    /// trips connection limits and NAT state tables. Addresses of peers
+    /// that have already been reported are never delayed.
+    pub fn with_pacing(mut self, max_peers: usize, window: Duration) -> Self {
+        self.pacing = Some((cmp::max(max_peers, 1), window));
+        self
+    }
+
+    /// Sets whether newly discovered peers are dialed, subject to the
+    /// pacing configured with [`MdnsConfig::with_pacing`].
+    pub fn with_dial_discovered(mut self, dial: bool) -> Self {
+        self.dial_discovered = dial;
+        self
+    }
+}
+
 /// A `NetworkBehaviour` for mDNS. Automatically discovers peers on the local network and adds
 /// them to the topology.
 pub struct Mdns {
@@ -56,6 +105,80 @@ pub struct Mdns {
     ///
     /// `None` if `discovered_nodes` is empty.
     closest_expiration: Option<Timer>,
+
+    /// Whether to dial newly discovered peers.
+    dial_discovered: bool,
+
+    /// Newly discovered peers waiting to be reported, if pacing is enabled.
+    pacer: Option<Pacer>,
+
+    /// Future that fires when the next peers may be released by the `pacer`,
+    /// together with that instant.
+    pacing_timer: Option<(Instant, Timer)>,
+
+    /// Actions to return from `poll` before anything else.
+    pending_actions: VecDeque<NetworkBehaviourAction<void::Void, MdnsEvent>>,
+}
+
+/// Paces the release of newly discovered peers to at most `max_peers`
+/// per `window`.
+#[derive(Debug)]
+struct Pacer {
+    max_peers: usize,
+    window: Duration,
+    /// The start of the current window, if any peers have been released.
+    window_start: Option<Instant>,
+    /// The number of peers released in the current window.
+    released: usize,
+    /// The peers waiting to be released, with their addresses and
+    /// the interfaces on which the addresses have been discovered.
+    queue: VecDeque<(PeerId, SmallVec<[(Multiaddr, Option<InterfaceInfo>); 4]>)>,
+}
+
+impl Pacer {
+    fn new(max_peers: usize, window: Duration) -> Self {
+        Pacer { max_peers, window, window_start: None, released: 0, queue: VecDeque::new() }
+    }
+
+    /// Returns true if the given peer is waiting to be released.
+    fn contains(&self, peer_id: &PeerId) -> bool {
+        self.queue.iter().any(|(p, _)| p == peer_id)
+    }
+
+    /// Queues an address of a peer for release.
+    fn push(&mut self, peer_id: PeerId, addr: Multiaddr, interface: Option<InterfaceInfo>) {
+        if let Some((_, addrs)) = self.queue.iter_mut().find(|(p, _)| *p == peer_id) {
+            if !addrs.iter().any(|(a, _)| *a == addr) {
+                addrs.push((addr, interface))
+            }
+        } else {
+            self.queue.push_back((peer_id, smallvec![(addr, interface)]))
+        }
+    }
+
+    /// Releases the peers that may be released at `now`, in the order
+    /// in which they have been queued.
+    fn release(&mut self, now: Instant) -> Vec<(PeerId, SmallVec<[(Multiaddr, Option<InterfaceInfo>); 4]>)> {
+        if self.queue.is_empty() {
+            return Vec::new()
+        }
+        if self.window_start.map_or(true, |start| now >= start + self.window) {
+            self.window_start = Some(now);
+            self.released = 0;
+        }
+        let n = cmp::min(self.max_peers - self.released, self.queue.len());
+        self.released += n;
+        self.queue.drain(.. n).collect()
+    }
+
+    /// Returns the instant at which the next queued peers may be released,
+    /// if any are queued.
+    fn next_release(&self) -> Option<Instant> {
+        if self.queue.is_empty() {
+            return None
+        }
+        self.window_start.map(|start| start + self.window)
+    }
 }
 
 /// `MdnsService::next` takes ownership of `self`, returning a future that resolves with both itself
@@ -91,10 +214,19 @@ impl fmt::Debug for MdnsBusyWrapper {
 impl Mdns {
     /// Builds a new `Mdns` behaviour.
     pub async fn new() -> io::Result<Self> {
+        Self::with_config(MdnsConfig::default()).await
+    }
+
+    /// Builds a new `Mdns` behaviour with the given configuration.
+    pub async fn with_config(config: MdnsConfig) -> io::Result<Self> {
         Ok(Self {
             service: MdnsBusyWrapper::Free(MdnsService::new().await?),
             discovered_nodes: SmallVec::new(),
             closest_expiration: None,
+            dial_discovered: config.dial_discovered,
+            pacer: config.pacing.map(|(max_peers, window)| Pacer::new(max_peers, window)),
+            pacing_timer: None,
+            pending_actions: VecDeque::new(),
         })
     }
 
@@ -149,6 +281,10 @@ impl NetworkBehaviour for Mdns {
             Self::OutEvent,
         >,
     > {
+        if let Some(action) = self.pending_actions.pop_front() {
+            return Poll::Ready(action);
+        }
+
         // Remove expired peers.
         if let Some(ref mut closest_expiration) = self.closest_expiration {
             match Pin::new(closest_expiration).poll(cx) {
@@ -171,6 +307,39 @@ impl NetworkBehaviour for Mdns {
             }
         }
 
+        // Release newly discovered peers whose report has been delayed.
+        if let Some(pacer) = &mut self.pacer {
+            let released = pacer.release(Instant::now());
+            if !released.is_empty() {
+                let mut discovered: SmallVec<[_; 4]> = SmallVec::new();
+                for (peer_id, addrs) in released {
+                    if self.dial_discovered {
+                        self.pending_actions.push_back(NetworkBehaviourAction::DialPeer {
+                            peer_id,
+                            condition: DialPeerCondition::Disconnected,
+                        });
+                    }
+                    discovered.extend(addrs.into_iter().map(|(address, interface)| {
+                        DiscoveredAddr { peer_id, address, interface }
+                    }));
+                }
+                return Poll::Ready(NetworkBehaviourAction::GenerateEvent(MdnsEvent::Discovered(DiscoveredAddrsIter {
+                    inner: discovered.into_iter(),
+                })));
+            }
+
+            match (pacer.next_release(), &mut self.pacing_timer) {
+                (Some(at), Some((current, _))) if at == *current => {}
+                (at, timer) => *timer = at.map(|at| (at, Timer::at(at))),
+            }
+            if let Some((_, timer)) = &mut self.pacing_timer {
+                if Pin::new(timer).poll(cx).is_ready() {
+                    self.pacing_timer = None;
+                    cx.waker().wake_by_ref();
+                }
+            }
+        }
+
         // Polling the mDNS service, and obtain the list of nodes discovered this round.
         let discovered = loop {
             let service = mem::replace(&mut self.service, MdnsBusyWrapper::Poisoned);
@@ -218,6 +387,7 @@ impl NetworkBehaviour for Mdns {
                         .chain(iter::once(obs_port))
                         .collect();
 
+                    let interface = response.interface();
                     let mut discovered: SmallVec<[_; 4]> = SmallVec::new();
                     for peer in response.discovered_peers() {
                         if peer.id() == params.local_peer_id() {
@@ -225,6 +395,7 @@ impl NetworkBehaviour for Mdns {
                         }
 
                         let new_expiration = Instant::now() + peer.ttl();
+                        let is_new_peer = !self.discovered_nodes.iter().any(|(p, _, _)| p == peer.id());
 
                         let mut addrs: Vec<Multiaddr> = Vec::new();
                         for addr in peer.addresses() {
@@ -235,15 +406,32 @@ impl NetworkBehaviour for Mdns {
                         }
 
                         for addr in addrs {
-                            if let Some((_, _, cur_expires)) = self.discovered_nodes.iter_mut()
+                            let is_new_addr = if let Some((_, _, cur_expires)) = self.discovered_nodes.iter_mut()
                                 .find(|(p, a, _)| p == peer.id() && *a == addr)
                             {
                                 *cur_expires = cmp::max(*cur_expires, new_expiration);
+                                false
                             } else {
                                 self.discovered_nodes.push((*peer.id(), addr.clone(), new_expiration));
+                                true
+                            };
+
+                            match &mut self.pacer {
+                                Some(pacer) if is_new_addr || pacer.contains(peer.id()) =>
+                                    pacer.push(*peer.id(), addr, interface.cloned()),
+                                _ => discovered.push(DiscoveredAddr {
+                                    peer_id: *peer.id(),
+                                    address: addr,
+                                    interface: interface.cloned(),
+                                }),
                             }
+                        }
 
-                            discovered.push((*peer.id(), addr));
+                        if is_new_peer && self.dial_discovered && self.pacer.is_none() {
+                            self.pending_actions.push_back(NetworkBehaviourAction::DialPeer {
+                                peer_id: *peer.id(),
+                                condition: DialPeerCondition::Disconnected,
+                            });
                         }
                     }
 
@@ -270,6 +458,12 @@ impl NetworkBehaviour for Mdns {
             })
             .map(Timer::at);
 
+        if discovered.is_empty() {
+            // All newly discovered peers are paced, or none were discovered.
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
         Poll::Ready(NetworkBehaviourAction::GenerateEvent(MdnsEvent::Discovered(DiscoveredAddrsIter {
             inner: discovered.into_iter(),
         })))
@@ -297,9 +491,66 @@ pub enum MdnsEvent {
     Expired(ExpiredAddrsIter),
 }
 
+/// An address of a peer discovered through mDNS.
+#[derive(Debug, Clone)]
+pub struct DiscoveredAddr {
+    peer_id: PeerId,
+    address: Multiaddr,
+    interface: Option<InterfaceInfo>,
+}
+
+impl DiscoveredAddr {
+    /// Returns the discovered peer.
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    /// Returns the discovered address of the peer.
+    pub fn address(&self) -> &Multiaddr {
+        &self.address
+    }
+
+    /// Returns the local interface on which the address has been
+    /// discovered, if known.
+    ///
+    /// Follow-up dials of multi-homed nodes should use this interface.
+    pub fn interface(&self) -> Option<&InterfaceInfo> {
+        self.interface.as_ref()
+    }
+
+    /// Returns `true` if the address is an IPv4 (`169.254.0.0/16`) or
+    /// IPv6 (`fe80::/10`) link-local address.
+    pub fn is_link_local(&self) -> bool {
+        match self.address.iter().next() {
+            Some(Protocol::Ip4(ip)) => ip.is_link_local(),
+            Some(Protocol::Ip6(ip)) => ip.segments()[0] & 0xffc0 == 0xfe80,
+            _ => false,
+        }
+    }
+
+    /// Returns the zone index with which to scope the address, i.e. the
+    /// index of the interface on which it has been discovered, if the
+    /// address is an IPv6 link-local address.
+    pub fn scope_id(&self) -> Option<u32> {
+        match self.address.iter().next() {
+            Some(Protocol::Ip6(_)) if self.is_link_local() =>
+                self.interface.as_ref().and_then(|i| i.index()),
+            _ => None,
+        }
+    }
+}
+
 /// Iterator that produces the list of addresses that have been discovered.
 pub struct DiscoveredAddrsIter {
-    inner: smallvec::IntoIter<[(PeerId, Multiaddr); 4]>
+    inner: smallvec::IntoIter<[DiscoveredAddr; 4]>
+}
+
+impl DiscoveredAddrsIter {
+    /// Converts the iterator into an iterator over the discovered addresses
+    /// together with the interfaces on which they have been discovered.
+    pub fn with_interfaces(self) -> impl ExactSizeIterator<Item = DiscoveredAddr> {
+        self.inner
+    }
 }
 
 impl Iterator for DiscoveredAddrsIter {
@@ -307,7 +558,7 @@ impl Iterator for DiscoveredAddrsIter {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next()
+        self.inner.next().map(|d| (d.peer_id, d.address))
     }
 
     #[inline]
@@ -354,3 +605,32 @@ impl fmt::Debug for ExpiredAddrsIter {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pacer_releases_at_most_max_peers_per_window() {
+        let window = Duration::from_secs(1);
+        let mut pacer = Pacer::new(2, window);
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        let peers = (0 .. 5).map(|_| PeerId::random()).collect::<Vec<_>>();
+        for peer in &peers {
+            pacer.push(*peer, addr.clone(), None);
+        }
+        // Additional addresses of a queued peer do not count towards the limit.
+        pacer.push(peers[0], "/ip4/127.0.0.1/tcp/4002".parse().unwrap(), None);
+
+        let start = Instant::now();
+        let released = pacer.release(start);
+        assert_eq!(released.iter().map(|(p, _)| *p).collect::<Vec<_>>(), peers[.. 2]);
+        assert_eq!(released[0].1.len(), 2);
+        assert!(pacer.release(start + window / 2).is_empty());
+        assert_eq!(pacer.next_release(), Some(start + window));
+
+        assert_eq!(pacer.release(start + window).len(), 2);
+        assert_eq!(pacer.release(start + window * 2).len(), 1);
+        assert_eq!(pacer.next_release(), None);
+    }
+}
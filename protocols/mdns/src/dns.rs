@@ -46,6 +46,12 @@ const MAX_PACKET_SIZE: usize = 9000 - 68;
 /// header data to be added by [`query_response_packet()`].
 const MAX_RECORDS_PER_PACKET: usize = (MAX_PACKET_SIZE - 100) / MAX_TXT_RECORD_SIZE;
 
+/// The record type of a `PTR` record.
+const TYPE_PTR: u16 = 0x000c;
+
+/// The record type of an `NSEC` record.
+const TYPE_NSEC: u16 = 0x002f;
+
 /// An encoded MDNS packet.
 pub type MdnsPacket = Vec<u8>;
 
@@ -202,6 +208,74 @@ pub fn build_service_discovery_response(id: u16, ttl: Duration) -> MdnsPacket {
     out
 }
 
+/// Builds a negative response to a query for `name`, as described in
+/// [RFC 6762 §6.1](https://tools.ietf.org/html/rfc6762#section-6.1).
+///
+/// The response consists of a single NSEC record asserting that `PTR` is the
+/// only record type that exists for `name`, so that queriers asking for other
+/// record types (e.g. `AAAA` or `SRV`) do not keep re-asking.
+pub fn build_nsec_response(id: u16, name: &[u8], ttl: Duration) -> MdnsPacket {
+    // Convert the TTL into seconds.
+    let ttl = duration_to_secs(ttl);
+
+    let mut qname = Vec::with_capacity(name.len() + 2);
+    append_qname(&mut qname, name);
+
+    let bitmap = nsec_type_bitmap(&[TYPE_PTR]);
+
+    let mut out = Vec::with_capacity(12 + 2 * qname.len() + 10 + bitmap.len());
+
+    append_u16(&mut out, id);
+    // 0x84 flag for an answer.
+    append_u16(&mut out, 0x8400);
+    // Number of questions, answers, authorities, additionals.
+    append_u16(&mut out, 0x0);
+    append_u16(&mut out, 0x1);
+    append_u16(&mut out, 0x0);
+    append_u16(&mut out, 0x0);
+
+    // Our single answer.
+    // The name.
+    out.extend_from_slice(&qname);
+
+    // Flags.
+    append_u16(&mut out, TYPE_NSEC);
+    append_u16(&mut out, 0x8001);
+
+    // TTL for the answer
+    append_u32(&mut out, ttl);
+
+    // The "Next Domain Name" is the name itself, followed by the type bitmap.
+    append_u16(&mut out, (qname.len() + bitmap.len()) as u16);
+    out.extend_from_slice(&qname);
+    out.extend_from_slice(&bitmap);
+
+    debug_assert_eq!(out.capacity(), out.len());
+    out
+}
+
+/// Encodes the NSEC type bitmap (as defined by RFC4034) for the given record types.
+///
+/// Only types of the first window block (i.e. below 256) are supported, which
+/// is all that RFC6762 permits in mDNS negative responses.
+fn nsec_type_bitmap(types: &[u16]) -> Vec<u8> {
+    let mut bitmap = [0u8; 32];
+    let mut len = 0;
+    for &ty in types {
+        debug_assert!(ty < 256);
+        let byte = usize::from(ty / 8);
+        bitmap[byte] |= 0x80 >> (ty % 8);
+        len = cmp::max(len, byte + 1);
+    }
+
+    let mut out = Vec::with_capacity(2 + len);
+    // Window block number.
+    out.push(0);
+    out.push(len as u8);
+    out.extend_from_slice(&bitmap[..len]);
+    out
+}
+
 /// Constructs an MDNS query response packet for an address lookup.
 fn query_response_packet(id: u16, peer_id: &[u8], records: &[Vec<u8>], ttl: u32) -> MdnsPacket {
     let mut out = Vec::with_capacity(records.len() * MAX_TXT_RECORD_SIZE);
@@ -434,6 +508,26 @@ mod tests {
         assert!(Packet::parse(&query).is_ok());
     }
 
+    #[test]
+    fn build_nsec_response_correct() {
+        let response = build_nsec_response(0x1234, SERVICE_NAME, Duration::from_secs(120));
+        let packet = Packet::parse(&response).unwrap();
+        assert_eq!(packet.answers.len(), 1);
+        let answer = &packet.answers[0];
+        assert_eq!(answer.name.to_string().as_bytes(), SERVICE_NAME);
+        assert_eq!(answer.ttl, 120);
+        assert!(answer.multicast_unique);
+        // The type bitmap announces `PTR` as the only existing record type.
+        assert!(response.ends_with(&[0x00, 0x02, 0x00, 0x08]));
+    }
+
+    #[test]
+    fn nsec_type_bitmap_correct() {
+        assert_eq!(nsec_type_bitmap(&[TYPE_PTR]), vec![0x00, 0x02, 0x00, 0x08]);
+        // `A` (1) and `TXT` (16).
+        assert_eq!(nsec_type_bitmap(&[0x01, 0x10]), vec![0x00, 0x03, 0x40, 0x00, 0x80]);
+    }
+
     #[test]
     fn test_segment_peer_id() {
         let str_32 = String::from_utf8(vec![b'x'; 32]).unwrap();
@@ -36,8 +36,8 @@ const SERVICE_NAME: &[u8] = b"_p2p._udp.local";
 const META_QUERY_SERVICE: &[u8] = b"_services._dns-sd._udp.local";
 
 pub use crate::{
-    behaviour::{Mdns, MdnsEvent},
-    service::MdnsService,
+    behaviour::{DiscoveredAddr, Mdns, MdnsConfig, MdnsEvent},
+    service::{InterfaceInfo, MdnsService},
 };
 
 mod behaviour;
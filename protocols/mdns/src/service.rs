@@ -20,17 +20,23 @@
 
 use crate::{SERVICE_NAME, META_QUERY_SERVICE, dns};
 use async_io::{Async, Timer};
-use dns_parser::{Packet, RData};
-use futures::{prelude::*, select};
+use dns_parser::{Packet, QueryType, Question, RData};
+use futures::{future::BoxFuture, prelude::*, select};
 use if_watch::{IfEvent, IfWatcher};
 use lazy_static::lazy_static;
 use libp2p_core::{multiaddr::{Multiaddr, Protocol}, PeerId};
 use log::warn;
 use socket2::{Socket, Domain, Type};
-use std::{fmt, io, net::{IpAddr, Ipv4Addr, UdpSocket, SocketAddr}, str, time::{Duration, Instant}};
+use std::{fmt, io, net::{IpAddr, Ipv4Addr, UdpSocket, SocketAddr}, pin::Pin, str, time::{Duration, Instant}};
 
 pub use dns::{build_query_response, build_service_discovery_response};
 
+/// The TTL of negative responses, matching the TTL of the records they refer to.
+const NSEC_RESPONSE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The interval between the queries sent on the network.
+const QUERY_INTERVAL: Duration = Duration::from_secs(20);
+
 lazy_static! {
     static ref IPV4_MDNS_MULTICAST_ADDRESS: SocketAddr = SocketAddr::from((
         Ipv4Addr::new(224, 0, 0, 251),
@@ -106,14 +112,11 @@ lazy_static! {
 /// # };
 /// # }
 pub struct MdnsService {
-    /// Main socket for listening.
-    socket: Async<UdpSocket>,
-
-    /// Socket for sending queries on the network.
-    query_socket: Async<UdpSocket>,
+    /// The sockets for listening and for sending queries.
+    sockets: Sockets,
 
     /// Interval for sending queries.
-    query_interval: Timer,
+    query_interval: Pin<Box<dyn Stream<Item = ()> + Send>>,
     /// Whether we send queries on the network at all.
     /// Note that we still need to have an interval for querying, as we need to wake up the socket
     /// regularly to recover from errors. Otherwise we could simply use an `Option<Timer>`.
@@ -129,8 +132,9 @@ pub struct MdnsService {
     send_buffers: Vec<Vec<u8>>,
     /// Buffers pending to send on the query socket.
     query_send_buffers: Vec<Vec<u8>>,
-    /// Iface watch.
-    if_watch: IfWatcher,
+    /// The IPv4 interfaces with their netmasks, for determining the
+    /// interface on which a packet has been received.
+    interfaces: Vec<(InterfaceInfo, Ipv4Addr)>,
 }
 
 impl MdnsService {
@@ -168,16 +172,55 @@ impl MdnsService {
 
         let if_watch = if_watch::IfWatcher::new().await?;
 
-        Ok(Self {
-            socket,
-            query_socket,
-            query_interval: Timer::interval_at(Instant::now(), Duration::from_secs(20)),
+        let mut service = Self {
+            sockets: Sockets::Udp { socket, query_socket, if_watch },
+            query_interval: Box::pin(Timer::interval_at(Instant::now(), QUERY_INTERVAL).map(drop)),
             silent,
             recv_buffer: [0; 4096],
             send_buffers: Vec::new(),
             query_send_buffers: Vec::new(),
-            if_watch,
-        })
+            interfaces: Vec::new(),
+        };
+        service.refresh_interfaces();
+        Ok(service)
+    }
+
+    /// Refreshes the list of IPv4 interfaces.
+    fn refresh_interfaces(&mut self) {
+        match if_addrs::get_if_addrs() {
+            Ok(interfaces) => {
+                self.interfaces = interfaces.into_iter()
+                    .filter_map(|iface| match iface.addr {
+                        if_addrs::IfAddr::V4(addr) => {
+                            let info = InterfaceInfo {
+                                index: interface_index(&iface.name),
+                                name: iface.name,
+                                addr: IpAddr::V4(addr.ip),
+                            };
+                            Some((info, addr.netmask))
+                        }
+                        if_addrs::IfAddr::V6(_) => None,
+                    })
+                    .collect();
+            }
+            Err(err) => log::debug!("listing interfaces failed: {}", err),
+        }
+    }
+
+    /// Returns the interface whose network contains the given address,
+    /// preferring the most specific network.
+    fn interface_of(&self, ip: IpAddr) -> Option<InterfaceInfo> {
+        let ip = match ip {
+            IpAddr::V4(ip) => u32::from(ip),
+            IpAddr::V6(_) => return None,
+        };
+        self.interfaces.iter()
+            .filter(|(info, mask)| match info.addr {
+                IpAddr::V4(addr) => u32::from(addr) & u32::from(*mask) == ip & u32::from(*mask),
+                IpAddr::V6(_) => false,
+            })
+            .max_by_key(|(_, mask)| u32::from(*mask))
+            .map(|(info, _)| info.clone())
     }
 
     pub fn enqueue_response(&mut self, rsp: Vec<u8>) {
@@ -212,7 +255,7 @@ impl MdnsService {
             while !self.send_buffers.is_empty() {
                 let to_send = self.send_buffers.remove(0);
 
-                match self.socket.send_to(&to_send, *IPV4_MDNS_MULTICAST_ADDRESS).await {
+                match self.sockets.send(&to_send, false).await {
                     Ok(bytes_written) => {
                         debug_assert_eq!(bytes_written, to_send.len());
                     }
@@ -229,7 +272,7 @@ impl MdnsService {
             while !self.query_send_buffers.is_empty() {
                 let to_send = self.query_send_buffers.remove(0);
 
-                match self.query_socket.send_to(&to_send, *IPV4_MDNS_MULTICAST_ADDRESS).await {
+                match self.sockets.send(&to_send, true).await {
                     Ok(bytes_written) => {
                         debug_assert_eq!(bytes_written, to_send.len());
                     }
@@ -242,11 +285,29 @@ impl MdnsService {
                 }
             }
 
-            select! {
-                res = self.socket.recv_from(&mut self.recv_buffer).fuse() => match res {
+            let event = match &mut self.sockets {
+                Sockets::Udp { socket, if_watch, .. } => select! {
+                    res = socket.recv_from(&mut self.recv_buffer).fuse() => SocketEvent::Received(res),
+                    _ = self.query_interval.next().fuse() => SocketEvent::QueryInterval,
+                    event = if_watch.next().fuse() => SocketEvent::Interface(event),
+                },
+                #[cfg(test)]
+                Sockets::Simulated(socket) => select! {
+                    res = socket.recv_from(&mut self.recv_buffer).fuse() => SocketEvent::Received(res),
+                    _ = self.query_interval.next().fuse() => SocketEvent::QueryInterval,
+                },
+            };
+
+            match event {
+                SocketEvent::Received(res) => match res {
                     Ok((len, from)) => {
-                        match MdnsPacket::new_from_bytes(&self.recv_buffer[..len], from) {
-                            Some(packet) => return (self, packet),
+                        match MdnsPacket::new_from_bytes(&self.recv_buffer[..len], from, &mut self.send_buffers) {
+                            Some(mut packet) => {
+                                if let MdnsPacket::Response(response) = &mut packet {
+                                    response.interface = self.interface_of(from.ip());
+                                }
+                                return (self, packet)
+                            },
                             None => {},
                         }
                     },
@@ -255,7 +316,7 @@ impl MdnsService {
                         // The query interval will wake up the task at some point so that we can try again.
                     },
                 },
-                _ = self.query_interval.next().fuse() => {
+                SocketEvent::QueryInterval => {
                     // Ensure underlying task is woken up on the next interval tick.
                     while let Some(_) = self.query_interval.next().now_or_never() {};
 
@@ -264,9 +325,13 @@ impl MdnsService {
                         self.query_send_buffers.push(query.to_vec());
                     }
                 },
-                event = self.if_watch.next().fuse() => {
+                SocketEvent::Interface(event) => {
                     let multicast = From::from([224, 0, 0, 251]);
-                    let socket = self.socket.get_ref();
+                    let socket = match &self.sockets {
+                        Sockets::Udp { socket, .. } => socket.get_ref(),
+                        #[cfg(test)]
+                        Sockets::Simulated(_) => unreachable!("simulated sockets have no interfaces"),
+                    };
                     match event {
                         Ok(IfEvent::Up(inet)) => {
                             if inet.addr().is_loopback() {
@@ -292,6 +357,7 @@ impl MdnsService {
                         }
                         Err(err) => log::error!("if watch returned an error: {}", err),
                     }
+                    self.refresh_interfaces();
                 }
             };
         }
@@ -306,6 +372,48 @@ impl fmt::Debug for MdnsService {
     }
 }
 
+/// The sockets of a [`MdnsService`].
+enum Sockets {
+    /// UDP sockets, joining the mDNS multicast group on the interfaces
+    /// reported by the `IfWatcher`.
+    Udp {
+        /// Main socket for listening.
+        socket: Async<UdpSocket>,
+        /// Socket for sending queries on the network.
+        query_socket: Async<UdpSocket>,
+        /// Iface watch.
+        if_watch: IfWatcher,
+    },
+    /// A socket of a simulated network, for tests.
+    #[cfg(test)]
+    Simulated(tests::SimSocket),
+}
+
+impl Sockets {
+    /// Sends a packet to the mDNS multicast address, from the socket for
+    /// queries if `query` is `true`.
+    fn send<'a>(&'a self, packet: &'a [u8], query: bool) -> BoxFuture<'a, io::Result<usize>> {
+        match self {
+            Sockets::Udp { socket, query_socket, .. } => {
+                let socket = if query { query_socket } else { socket };
+                socket.send_to(packet, *IPV4_MDNS_MULTICAST_ADDRESS).boxed()
+            }
+            #[cfg(test)]
+            Sockets::Simulated(socket) => future::ready(socket.send(packet, query)).boxed(),
+        }
+    }
+}
+
+/// An event awaited by [`MdnsService::next`].
+enum SocketEvent {
+    /// A packet has been received on the main socket.
+    Received(io::Result<(usize, SocketAddr)>),
+    /// It is time to send a query.
+    QueryInterval,
+    /// An interface went up or down.
+    Interface(io::Result<IfEvent>),
+}
+
 /// A valid mDNS packet received by the service.
 #[derive(Debug)]
 pub enum MdnsPacket {
@@ -318,14 +426,37 @@ pub enum MdnsPacket {
 }
 
 impl MdnsPacket {
-    fn new_from_bytes(buf: &[u8], from: SocketAddr) -> Option<MdnsPacket> {
+    /// Parses a received packet.
+    ///
+    /// Queries for our names asking for record types we do not publish are
+    /// answered right away with a negative response, which is pushed onto
+    /// `negative_responses`.
+    fn new_from_bytes(
+        buf: &[u8],
+        from: SocketAddr,
+        negative_responses: &mut Vec<Vec<u8>>,
+    ) -> Option<MdnsPacket> {
         match Packet::parse(buf) {
             Ok(packet) => {
                 if packet.header.query {
+                    for name in &[SERVICE_NAME, META_QUERY_SERVICE] {
+                        if packet
+                            .questions
+                            .iter()
+                            .any(|q| q.qname.to_string().as_bytes() == *name && !is_ptr_query(q))
+                        {
+                            negative_responses.push(dns::build_nsec_response(
+                                packet.header.id,
+                                name,
+                                NSEC_RESPONSE_TTL,
+                            ));
+                        }
+                    }
+
                     if packet
                         .questions
                         .iter()
-                        .any(|q| q.qname.to_string().as_bytes() == SERVICE_NAME)
+                        .any(|q| q.qname.to_string().as_bytes() == SERVICE_NAME && is_ptr_query(q))
                     {
                         let query = MdnsPacket::Query(MdnsQuery {
                             from,
@@ -335,7 +466,7 @@ impl MdnsPacket {
                     } else if packet
                         .questions
                         .iter()
-                        .any(|q| q.qname.to_string().as_bytes() == META_QUERY_SERVICE)
+                        .any(|q| q.qname.to_string().as_bytes() == META_QUERY_SERVICE && is_ptr_query(q))
                     {
                         // TODO: what if multiple questions, one with SERVICE_NAME and one with META_QUERY_SERVICE?
                         let discovery = MdnsPacket::ServiceDiscovery(
@@ -364,6 +495,14 @@ impl MdnsPacket {
     }
 }
 
+/// Returns `true` if the question can be answered with our `PTR` records.
+fn is_ptr_query(question: &Question<'_>) -> bool {
+    match question.qtype {
+        QueryType::PTR | QueryType::All => true,
+        _ => false,
+    }
+}
+
 /// A received mDNS query.
 pub struct MdnsQuery {
     /// Sender of the address.
@@ -422,10 +561,56 @@ impl fmt::Debug for MdnsServiceDiscovery {
     }
 }
 
+/// A network interface of the local node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceInfo {
+    name: String,
+    index: Option<u32>,
+    addr: IpAddr,
+}
+
+impl InterfaceInfo {
+    /// Returns the name of the interface, e.g. `eth0`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the index of the interface, if known.
+    ///
+    /// The index is the zone index for scoping IPv6 link-local addresses
+    /// reachable via this interface. It is only determined on Unix.
+    pub fn index(&self) -> Option<u32> {
+        self.index
+    }
+
+    /// Returns the local address of the interface.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+}
+
+/// Returns the index of the interface with the given name.
+#[cfg(unix)]
+fn interface_index(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: `name` is a valid NUL-terminated string.
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        index => Some(index),
+    }
+}
+
+/// Returns the index of the interface with the given name.
+#[cfg(not(unix))]
+fn interface_index(_: &str) -> Option<u32> {
+    None
+}
+
 /// A received mDNS response.
 pub struct MdnsResponse {
     peers: Vec<MdnsPeer>,
     from: SocketAddr,
+    interface: Option<InterfaceInfo>,
 }
 
 impl MdnsResponse {
@@ -468,6 +653,7 @@ impl MdnsResponse {
         MdnsResponse {
             peers,
             from,
+            interface: None,
         }
     }
 
@@ -483,12 +669,19 @@ impl MdnsResponse {
     pub fn remote_addr(&self) -> &SocketAddr {
         &self.from
     }
+
+    /// Returns the local interface on which the packet has been received,
+    /// if it could be determined from the source address of the packet.
+    pub fn interface(&self) -> Option<&InterfaceInfo> {
+        self.interface.as_ref()
+    }
 }
 
 impl fmt::Debug for MdnsResponse {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("MdnsResponse")
             .field("from", self.remote_addr())
+            .field("interface", &self.interface)
             .finish()
     }
 }
@@ -589,124 +782,141 @@ impl fmt::Debug for MdnsPeer {
 
 #[cfg(test)]
 mod tests {
-    macro_rules! testgen {
-        ($runtime_name:ident, $service_name:ty, $block_on_fn:tt) => {
-    mod $runtime_name {
-        use libp2p_core::{PeerId, multihash::{Code, MultihashDigest}};
-        use std::time::Duration;
-        use crate::service::MdnsPacket;
-
-        fn discover(peer_id: PeerId) {
-            let fut = async {
-                let mut service = <$service_name>::new().await.unwrap();
-
-                loop {
-                    let next = service.next().await;
-                    service = next.0;
-
-                    match next.1 {
-                        MdnsPacket::Query(query) => {
-                            let resp = crate::dns::build_query_response(
-                                query.query_id(),
-                                peer_id.clone(),
-                                vec![].into_iter(),
-                                Duration::from_secs(120),
-                            );
-                            for r in resp {
-                                service.enqueue_response(r);
-                            }
-                        }
-                        MdnsPacket::Response(response) => {
-                            for peer in response.discovered_peers() {
-                                if peer.id() == &peer_id {
-                                    return;
-                                }
-                            }
-                        }
-                        MdnsPacket::ServiceDiscovery(_) => panic!(
-                            "did not expect a service discovery packet",
-                        )
-                    }
-                }
-            };
+    use super::*;
+    use futures::channel::mpsc;
+    use libp2p_core::{
+        multihash::{Code, MultihashDigest},
+        simulation::{Simulation, SimulationHandle},
+    };
+    use std::sync::{Arc, Mutex};
+
+    /// A simulated local network, delivering the packets sent by each of its
+    /// sockets to all of them, including the sender.
+    #[derive(Clone, Default)]
+    struct SimNetwork(Arc<Mutex<Vec<mpsc::UnboundedSender<(Vec<u8>, SocketAddr)>>>>);
+
+    /// A socket of a [`SimNetwork`].
+    pub(super) struct SimSocket {
+        network: SimNetwork,
+        ip: Ipv4Addr,
+        packets: mpsc::UnboundedReceiver<(Vec<u8>, SocketAddr)>,
+    }
 
-            $block_on_fn(Box::pin(fut));
+    impl SimSocket {
+        pub(super) fn send(&self, packet: &[u8], query: bool) -> io::Result<usize> {
+            // Queries are sent from an ephemeral port, like the query socket does.
+            let from = SocketAddr::from((self.ip, if query { 49152 } else { 5353 }));
+            self.network.0.lock().unwrap()
+                .retain(|tx| tx.unbounded_send((packet.to_vec(), from)).is_ok());
+            Ok(packet.len())
         }
 
-        // As of today the underlying UDP socket is not stubbed out. Thus tests run in parallel to
-        // this unit tests inter fear with it. Test needs to be run in sequence to ensure test
-        // properties.
-        #[ignore]
-        #[test]
-        fn respect_query_interval() {
-            let own_ips: Vec<std::net::IpAddr> = if_addrs::get_if_addrs().unwrap()
-                .into_iter()
-                .map(|i| i.addr.ip())
-                .collect();
-
-            let fut = async {
-                let mut service = <$service_name>::new().await.unwrap();
-
-                let mut sent_queries = vec![];
-
-                loop {
-                    let next = service.next().await;
-                    service = next.0;
-
-                    match next.1 {
-                        MdnsPacket::Query(query) => {
-                            // Ignore queries from other nodes.
-                            let source_ip = query.remote_addr().ip();
-                            if !own_ips.contains(&source_ip) {
-                                continue;
-                            }
+        pub(super) async fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+            let (packet, from) = self.packets.next().await
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+            let len = packet.len().min(buf.len());
+            buf[.. len].copy_from_slice(&packet[.. len]);
+            Ok((len, from))
+        }
+    }
 
-                            sent_queries.push(query);
+    impl MdnsService {
+        /// Creates a service on the given simulated network, querying at the
+        /// interval measured by the virtual clock of the simulation.
+        fn simulated(network: &SimNetwork, handle: SimulationHandle, ip: Ipv4Addr) -> Self {
+            let (tx, packets) = mpsc::unbounded();
+            network.0.lock().unwrap().push(tx);
+            let query_interval = stream::once(future::ready(()))
+                .chain(stream::unfold(handle, |handle| async move {
+                    handle.sleep(QUERY_INTERVAL).await;
+                    Some(((), handle))
+                }));
+            MdnsService {
+                sockets: Sockets::Simulated(SimSocket { network: network.clone(), ip, packets }),
+                query_interval: Box::pin(query_interval),
+                silent: false,
+                recv_buffer: [0; 4096],
+                send_buffers: Vec::new(),
+                query_send_buffers: Vec::new(),
+                interfaces: Vec::new(),
+            }
+        }
+    }
 
-                            if sent_queries.len() > 1 {
-                                return;
-                            }
-                        }
-                        // Ignore response packets. We don't stub out the UDP socket, thus this is
-                        // either random noise from the network, or noise from other unit tests
-                        // running in parallel.
-                        MdnsPacket::Response(_) => {},
-                        MdnsPacket::ServiceDiscovery(_) => {
-                            panic!("Did not expect a service discovery packet.");
-                        },
-                    }
-                }
-            };
+    const IP: Ipv4Addr = Ipv4Addr::new(192, 168, 0, 1);
 
-            $block_on_fn(Box::pin(fut));
-        }
+    fn discover(peer_id: PeerId) {
+        let mut simulation = Simulation::new(0);
+        let mut service = MdnsService::simulated(&SimNetwork::default(), simulation.handle(), IP);
 
-        #[ignore]
-        #[test]
-        fn discover_normal_peer_id() {
-            discover(PeerId::random())
-        }
+        let discovered = simulation.block_on(async move {
+            loop {
+                let next = service.next().await;
+                service = next.0;
 
-        #[ignore]
-        #[test]
-        fn discover_long_peer_id() {
-            let max_value = String::from_utf8(vec![b'f'; 42]).unwrap();
-            let hash = Code::Identity.digest(max_value.as_ref());
-            discover(PeerId::from_multihash(hash).unwrap())
-        }
-    }
+                match next.1 {
+                    MdnsPacket::Query(query) => {
+                        let resp = build_query_response(
+                            query.query_id(),
+                            peer_id,
+                            vec![].into_iter(),
+                            Duration::from_secs(120),
+                        );
+                        for r in resp {
+                            service.enqueue_response(r);
+                        }
+                    }
+                    MdnsPacket::Response(response) => {
+                        if response.discovered_peers().any(|peer| peer.id() == &peer_id) {
+                            return
+                        }
+                    }
+                    MdnsPacket::ServiceDiscovery(_) => panic!(
+                        "did not expect a service discovery packet",
+                    )
+                }
+            }
+        });
+        assert_eq!(discovered, Some(()));
     }
+
+    #[test]
+    fn respect_query_interval() {
+        let mut simulation = Simulation::new(0);
+        let handle = simulation.handle();
+        let mut service = MdnsService::simulated(&SimNetwork::default(), handle.clone(), IP);
+
+        let sent_queries = simulation.block_on(async move {
+            let mut sent_queries = vec![];
+            while sent_queries.len() < 3 {
+                let next = service.next().await;
+                service = next.0;
+
+                match next.1 {
+                    MdnsPacket::Query(query) => {
+                        assert_eq!(query.remote_addr().ip(), IpAddr::V4(IP));
+                        sent_queries.push(handle.now());
+                    }
+                    MdnsPacket::Response(_) => {},
+                    MdnsPacket::ServiceDiscovery(_) => {
+                        panic!("Did not expect a service discovery packet.");
+                    },
+                }
+            }
+            sent_queries
+        });
+        assert_eq!(sent_queries, Some(vec![Duration::from_secs(0), QUERY_INTERVAL, 2 * QUERY_INTERVAL]));
     }
 
-    testgen!(
-        async_std,
-        crate::service::MdnsService,
-        (|fut| async_std::task::block_on::<_, ()>(fut))
-    );
+    #[test]
+    fn discover_normal_peer_id() {
+        discover(PeerId::random())
+    }
 
-    testgen!(
-        tokio,
-        crate::service::MdnsService,
-        (|fut| tokio::runtime::Runtime::new().unwrap().block_on::<futures::future::BoxFuture<()>>(fut))
-    );
+    #[test]
+    fn discover_long_peer_id() {
+        let max_value = String::from_utf8(vec![b'f'; 42]).unwrap();
+        let hash = Code::Identity.digest(max_value.as_ref());
+        discover(PeerId::from_multihash(hash).unwrap())
+    }
 }
@@ -53,6 +53,8 @@ pub struct PingConfig {
     /// connection is deemed unhealthy, indicating to the `Swarm` that it
     /// should be closed.
     max_failures: NonZeroU32,
+    /// What happens once `max_failures` is reached.
+    failure_policy: PingFailurePolicy,
     /// Whether the connection should generally be kept alive unless
     /// `max_failures` occur.
     keep_alive: bool,
@@ -64,6 +66,7 @@ impl PingConfig {
     ///   * [`PingConfig::with_interval`] 15s
     ///   * [`PingConfig::with_timeout`] 20s
     ///   * [`PingConfig::with_max_failures`] 1
+    ///   * [`PingConfig::with_failure_policy`] [`PingFailurePolicy::Close`]
     ///   * [`PingConfig::with_keep_alive`] false
     ///
     /// These settings have the following effect:
@@ -81,6 +84,7 @@ impl PingConfig {
             timeout: Duration::from_secs(20),
             interval: Duration::from_secs(15),
             max_failures: NonZeroU32::new(1).expect("1 != 0"),
+            failure_policy: PingFailurePolicy::Close,
             keep_alive: false
         }
     }
@@ -104,6 +108,13 @@ impl PingConfig {
         self
     }
 
+    /// Sets what happens once the maximum number of consecutive ping
+    /// failures is reached.
+    pub fn with_failure_policy(mut self, policy: PingFailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+
     /// Sets whether the ping protocol itself should keep the connection alive,
     /// apart from the maximum allowed failures.
     ///
@@ -111,15 +122,28 @@ impl PingConfig {
     /// at any time, i.e. in the absence of ping failures the connection lifetime
     /// is determined by other protocol handlers.
     ///
-    /// If the maximum number of allowed ping failures is reached, the
-    /// connection is always terminated as a result of [`ProtocolsHandler::poll`]
-    /// returning an error, regardless of the keep-alive setting.
+    /// If the maximum number of allowed ping failures is reached and the
+    /// failure policy is [`PingFailurePolicy::Close`], the connection is
+    /// always terminated as a result of [`ProtocolsHandler::poll`] returning
+    /// an error, regardless of the keep-alive setting.
     pub fn with_keep_alive(mut self, b: bool) -> Self {
         self.keep_alive = b;
         self
     }
 }
 
+/// What happens once the maximum number of consecutive ping failures is
+/// reached, see [`PingConfig::with_max_failures`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PingFailurePolicy {
+    /// The connection is closed.
+    Close,
+    /// The failures are merely reported and the connection is left open,
+    /// e.g. for connections over high-latency transports like Tor, whose
+    /// lifetime is determined by other means.
+    Report,
+}
+
 /// The result of an inbound or outbound ping.
 pub type PingResult = Result<PingSuccess, PingFailure>;
 
@@ -274,7 +298,9 @@ impl ProtocolsHandler for PingHandler {
                 // resets `failures` to `0`, while at the same time emitting
                 // events only for `max_failures - 1` failures, as before.
                 if self.failures > 1 || self.config.max_failures.get() > 1 {
-                    if self.failures >= self.config.max_failures.get() {
+                    if self.failures >= self.config.max_failures.get()
+                        && self.config.failure_policy == PingFailurePolicy::Close
+                    {
                         log::debug!("Too many failures ({}). Closing connection.", self.failures);
                         return Poll::Ready(ProtocolsHandlerEvent::Close(error))
                     }
@@ -29,10 +29,14 @@
 //! The [`Ping`] struct implements the [`NetworkBehaviour`] trait. When used with a [`Swarm`],
 //! it will respond to inbound ping requests and as necessary periodically send outbound
 //! ping requests on every established connection. If a configurable number of consecutive
-//! pings fail, the connection will be closed.
+//! pings fail, the connection will be closed, or the failures merely reported, depending on
+//! the configured [`PingFailurePolicy`].
 //!
 //! The `Ping` network behaviour produces [`PingEvent`]s, which may be consumed from the `Swarm`
-//! by an application, e.g. to collect statistics.
+//! by an application, e.g. to collect statistics. Other components interested only in the
+//! measured round-trip times, e.g. for scoring addresses or peers, can obtain them as
+//! [`RttMeasurement`]s through [`Ping::subscribe_rtt`] or query the latest one of a peer
+//! via [`Ping::rtt`].
 //!
 //! > **Note**: The ping protocol does not keep otherwise idle connections alive
 //! > by default, see [`PingConfig::with_keep_alive`] for changing this behaviour.
@@ -43,12 +47,13 @@
 pub mod protocol;
 pub mod handler;
 
-pub use handler::{PingConfig, PingResult, PingSuccess, PingFailure};
+pub use handler::{PingConfig, PingFailurePolicy, PingResult, PingSuccess, PingFailure};
 use handler::PingHandler;
 
+use futures::channel::mpsc;
 use libp2p_core::{Multiaddr, PeerId, connection::ConnectionId};
-use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters};
-use std::{collections::VecDeque, task::Context, task::Poll};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolRegistry};
+use std::{collections::{HashMap, VecDeque}, task::Context, task::Poll, time::Duration};
 use void::Void;
 
 /// `Ping` is a [`NetworkBehaviour`] that responds to inbound pings and
@@ -60,6 +65,10 @@ pub struct Ping {
     config: PingConfig,
     /// Queue of events to yield to the swarm.
     events: VecDeque<PingEvent>,
+    /// The most recently measured round-trip time of every connected peer.
+    last_rtt: HashMap<PeerId, Duration>,
+    /// Subscribers to round-trip time measurements.
+    rtt_subscribers: Vec<mpsc::Sender<RttMeasurement>>,
 }
 
 /// Event generated by the `Ping` network behaviour.
@@ -71,14 +80,50 @@ pub struct PingEvent {
     pub result: PingResult,
 }
 
+/// A round-trip time measured by a successful outbound ping.
+#[derive(Debug, Clone)]
+pub struct RttMeasurement {
+    /// The peer ID of the remote.
+    pub peer: PeerId,
+    /// The connection on which the ping was sent.
+    pub connection: ConnectionId,
+    /// The measured round-trip time.
+    pub rtt: Duration,
+}
+
 impl Ping {
     /// Creates a new `Ping` network behaviour with the given configuration.
     pub fn new(config: PingConfig) -> Self {
         Ping {
             config,
             events: VecDeque::new(),
+            last_rtt: HashMap::new(),
+            rtt_subscribers: Vec::new(),
         }
     }
+
+    /// Returns the most recently measured round-trip time to the given
+    /// peer, if it is connected and any outbound ping succeeded.
+    pub fn rtt(&self, peer: &PeerId) -> Option<Duration> {
+        self.last_rtt.get(peer).copied()
+    }
+
+    /// Subscribes to the round-trip times measured by outbound pings on all
+    /// connections.
+    ///
+    /// Up to `capacity` measurements are buffered for the returned receiver.
+    /// Measurements are dropped for a subscriber that does not keep up,
+    /// so that a slow consumer never holds up the `Ping` behaviour.
+    pub fn subscribe_rtt(&mut self, capacity: usize) -> mpsc::Receiver<RttMeasurement> {
+        let (tx, rx) = mpsc::channel(capacity);
+        self.rtt_subscribers.push(tx);
+        rx
+    }
+
+    /// Registers the protocol of `Ping` with the given registry.
+    pub fn register_protocols(&self, registry: &ProtocolRegistry) {
+        registry.register("ping", b"/ipfs/ping/1.0.0");
+    }
 }
 
 impl Default for Ping {
@@ -101,9 +146,22 @@ impl NetworkBehaviour for Ping {
 
     fn inject_connected(&mut self, _: &PeerId) {}
 
-    fn inject_disconnected(&mut self, _: &PeerId) {}
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.last_rtt.remove(peer);
+    }
 
-    fn inject_event(&mut self, peer: PeerId, _: ConnectionId, result: PingResult) {
+    fn inject_event(&mut self, peer: PeerId, connection: ConnectionId, result: PingResult) {
+        if let Ok(PingSuccess::Ping { rtt }) = &result {
+            self.last_rtt.insert(peer, *rtt);
+            let measurement = RttMeasurement { peer, connection, rtt: *rtt };
+            self.rtt_subscribers = self.rtt_subscribers
+                .drain(..)
+                .filter_map(|mut tx| match tx.try_send(measurement.clone()) {
+                    Err(e) if e.is_disconnected() => None,
+                    _ => Some(tx),
+                })
+                .collect();
+        }
         self.events.push_front(PingEvent { peer, result })
     }
 
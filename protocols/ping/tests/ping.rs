@@ -37,7 +37,7 @@ use libp2p_yamux as yamux;
 use futures::{prelude::*, channel::mpsc};
 use quickcheck::*;
 use rand::prelude::*;
-use std::{num::NonZeroU8, time::Duration};
+use std::{num::{NonZeroU8, NonZeroU32}, time::Duration};
 
 #[test]
 fn ping_pong() {
@@ -183,6 +183,104 @@ fn max_failures() {
     QuickCheck::new().tests(10).quickcheck(prop as fn(_,_))
 }
 
+/// Tests that the connection is kept open beyond the maximum number of
+/// consecutive ping failures with [`PingFailurePolicy::Report`].
+#[test]
+fn report_failures() {
+    let cfg = PingConfig::new()
+        .with_keep_alive(true)
+        .with_interval(Duration::from_millis(10))
+        .with_timeout(Duration::from_millis(0))
+        .with_max_failures(NonZeroU32::new(1).unwrap())
+        .with_failure_policy(PingFailurePolicy::Report);
+
+    let (peer1_id, trans) = mk_transport(MuxerChoice::Yamux);
+    let mut swarm1 = Swarm::new(trans, Ping::new(cfg.clone()), peer1_id);
+
+    let (peer2_id, trans) = mk_transport(MuxerChoice::Yamux);
+    let mut swarm2 = Swarm::new(trans, Ping::new(cfg), peer2_id);
+
+    let (mut tx, mut rx) = mpsc::channel::<Multiaddr>(1);
+
+    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    Swarm::listen_on(&mut swarm1, addr).unwrap();
+
+    let peer1 = async move {
+        loop {
+            match swarm1.next_event().await {
+                SwarmEvent::NewListenAddr(listener) => tx.send(listener).await.unwrap(),
+                SwarmEvent::ConnectionClosed { .. } => panic!("Unexpected connection close"),
+                _ => {}
+            }
+        }
+    };
+
+    let peer2 = async move {
+        Swarm::dial_addr(&mut swarm2, rx.next().await.unwrap()).unwrap();
+
+        let mut failures: u8 = 0;
+
+        loop {
+            match swarm2.next_event().await {
+                SwarmEvent::Behaviour(PingEvent { result: Err(_), .. }) => {
+                    failures += 1;
+                    if failures == 5 {
+                        return
+                    }
+                }
+                SwarmEvent::ConnectionClosed { .. } => panic!("Unexpected connection close"),
+                _ => {}
+            }
+        }
+    };
+
+    async_std::task::block_on(future::select(Box::pin(peer1), Box::pin(peer2)));
+}
+
+/// Tests that round-trip times of successful pings are reported to
+/// subscribers and recorded per peer.
+#[test]
+fn rtt_measurements() {
+    let cfg = PingConfig::new()
+        .with_keep_alive(true)
+        .with_interval(Duration::from_millis(10));
+
+    let (peer1_id, trans) = mk_transport(MuxerChoice::Yamux);
+    let mut swarm1 = Swarm::new(trans, Ping::new(cfg.clone()), peer1_id);
+
+    let (peer2_id, trans) = mk_transport(MuxerChoice::Yamux);
+    let mut swarm2 = Swarm::new(trans, Ping::new(cfg), peer2_id);
+    let mut measurements = swarm2.subscribe_rtt(1);
+
+    let (mut tx, mut rx) = mpsc::channel::<Multiaddr>(1);
+
+    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    Swarm::listen_on(&mut swarm1, addr).unwrap();
+
+    let peer1 = async move {
+        loop {
+            if let SwarmEvent::NewListenAddr(listener) = swarm1.next_event().await {
+                tx.send(listener).await.unwrap()
+            }
+        }
+    };
+
+    let peer2 = async move {
+        Swarm::dial_addr(&mut swarm2, rx.next().await.unwrap()).unwrap();
+
+        loop {
+            if let PingEvent { result: Ok(PingSuccess::Ping { rtt }), .. } = swarm2.next().await {
+                let measurement = measurements.next().await.unwrap();
+                assert_eq!(measurement.peer, peer1_id);
+                assert_eq!(measurement.rtt, rtt);
+                assert_eq!(swarm2.rtt(&peer1_id), Some(rtt));
+                return
+            }
+        }
+    };
+
+    async_std::task::block_on(future::select(Box::pin(peer1), Box::pin(peer2)));
+}
 
 fn mk_transport(muxer: MuxerChoice) -> (
     PeerId,
@@ -0,0 +1,210 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! The client side of the protocol.
+
+mod handler;
+pub(crate) mod transport;
+
+use crate::protocol::Limit;
+use futures::{channel::mpsc, prelude::*};
+use libp2p_core::{connection::ConnectionId, ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::{
+    DialPeerCondition,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    task::{Context, Poll},
+};
+use transport::{ClientTransport, ToListenerMsg, TransportToBehaviourMsg};
+
+/// Event that can be produced by the [`Client`] behaviour.
+#[derive(Debug)]
+pub enum ClientEvent {
+    /// A relay granted or renewed the reservation.
+    ReservationReqAccepted {
+        relay_peer_id: PeerId,
+        renewal: bool,
+        /// The limits of circuits relayed on behalf of the reservation.
+        limit: Option<Limit>,
+    },
+    /// A relay did not grant or renew the reservation.
+    ReservationReqFailed { relay_peer_id: PeerId, renewal: bool },
+    /// A circuit to a destination has been established through a relay.
+    OutboundCircuitEstablished { relay_peer_id: PeerId, limit: Option<Limit> },
+    /// A circuit to a destination could not be established through a relay.
+    OutboundCircuitReqFailed { relay_peer_id: PeerId },
+    /// A relay established a circuit from a remote peer to the local node.
+    InboundCircuitEstablished { src_peer_id: PeerId, limit: Option<Limit> },
+    /// A circuit from a remote peer has been denied for lack of a listener.
+    InboundCircuitReqDenied { src_peer_id: PeerId },
+}
+
+/// A [`NetworkBehaviour`] carrying out the requests of a [`ClientTransport`]
+/// with relays.
+pub struct Client {
+    from_transport: mpsc::UnboundedReceiver<TransportToBehaviourMsg>,
+    /// The connections to each peer.
+    connections: HashMap<PeerId, Vec<ConnectionId>>,
+    /// The addresses of relays to dial.
+    relay_addrs: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Requests waiting for a connection to the relay.
+    pending_requests: HashMap<PeerId, Vec<handler::In>>,
+    queued_actions: VecDeque<NetworkBehaviourAction<handler::In, ClientEvent>>,
+}
+
+impl Client {
+    /// Creates a new `Client` behaviour together with the [`ClientTransport`]
+    /// handing its requests to it.
+    pub fn new_transport_and_behaviour() -> (ClientTransport, Client) {
+        let (transport, from_transport) = ClientTransport::new();
+        let behaviour = Client {
+            from_transport,
+            connections: HashMap::new(),
+            relay_addrs: HashMap::new(),
+            pending_requests: HashMap::new(),
+            queued_actions: VecDeque::new(),
+        };
+        (transport, behaviour)
+    }
+
+    /// Sends the request to a connection to the relay, dialing the relay
+    /// first if needed.
+    fn request(&mut self, relay_peer_id: PeerId, relay_addr: Multiaddr, event: handler::In) {
+        if let Some(connection) = self.connections.get(&relay_peer_id).and_then(|c| c.first()) {
+            self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: relay_peer_id,
+                handler: NotifyHandler::One(*connection),
+                event,
+            });
+            return
+        }
+
+        if !relay_addr.is_empty() {
+            let addrs = self.relay_addrs.entry(relay_peer_id).or_default();
+            if !addrs.contains(&relay_addr) {
+                addrs.push(relay_addr);
+            }
+        }
+        self.pending_requests.entry(relay_peer_id).or_default().push(event);
+        self.queued_actions.push_back(NetworkBehaviourAction::DialPeer {
+            peer_id: relay_peer_id,
+            condition: DialPeerCondition::Disconnected,
+        });
+    }
+}
+
+impl NetworkBehaviour for Client {
+    type ProtocolsHandler = handler::Handler;
+    type OutEvent = ClientEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        handler::Handler::new()
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.relay_addrs.get(peer_id).cloned().unwrap_or_default()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_connection_established(&mut self, peer_id: &PeerId, connection: &ConnectionId, _: &ConnectedPoint) {
+        self.connections.entry(*peer_id).or_default().push(*connection);
+
+        for event in self.pending_requests.remove(peer_id).unwrap_or_default() {
+            self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: *peer_id,
+                handler: NotifyHandler::One(*connection),
+                event,
+            });
+        }
+    }
+
+    fn inject_connection_closed(&mut self, peer_id: &PeerId, connection: &ConnectionId, _: &ConnectedPoint) {
+        if let Some(connections) = self.connections.get_mut(peer_id) {
+            connections.retain(|c| c != connection);
+            if connections.is_empty() {
+                self.connections.remove(peer_id);
+            }
+        }
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        for event in self.pending_requests.remove(peer_id).unwrap_or_default() {
+            match event {
+                handler::In::Reserve { to_listener } => {
+                    let _ = to_listener.unbounded_send(ToListenerMsg::Reservation(Err(())));
+                    self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                        ClientEvent::ReservationReqFailed { relay_peer_id: *peer_id, renewal: false }
+                    ));
+                }
+                handler::In::EstablishCircuit { send_back, .. } => {
+                    let _ = send_back.send(Err(()));
+                    self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                        ClientEvent::OutboundCircuitReqFailed { relay_peer_id: *peer_id }
+                    ));
+                }
+            }
+        }
+    }
+
+    fn inject_event(&mut self, peer_id: PeerId, _: ConnectionId, event: handler::Event) {
+        let event = match event {
+            handler::Event::ReservationReqAccepted { renewal, limit } =>
+                ClientEvent::ReservationReqAccepted { relay_peer_id: peer_id, renewal, limit },
+            handler::Event::ReservationReqFailed { renewal } =>
+                ClientEvent::ReservationReqFailed { relay_peer_id: peer_id, renewal },
+            handler::Event::OutboundCircuitEstablished { limit } =>
+                ClientEvent::OutboundCircuitEstablished { relay_peer_id: peer_id, limit },
+            handler::Event::OutboundCircuitReqFailed =>
+                ClientEvent::OutboundCircuitReqFailed { relay_peer_id: peer_id },
+            handler::Event::InboundCircuitEstablished { src_peer_id, limit } =>
+                ClientEvent::InboundCircuitEstablished { src_peer_id, limit },
+            handler::Event::InboundCircuitReqDenied { src_peer_id } =>
+                ClientEvent::InboundCircuitReqDenied { src_peer_id },
+        };
+        self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(event));
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<handler::In, ClientEvent>>
+    {
+        loop {
+            if let Some(action) = self.queued_actions.pop_front() {
+                return Poll::Ready(action)
+            }
+
+            match self.from_transport.poll_next_unpin(cx) {
+                Poll::Ready(Some(TransportToBehaviourMsg::ListenReq { relay_peer_id, relay_addr, to_listener })) =>
+                    self.request(relay_peer_id, relay_addr, handler::In::Reserve { to_listener }),
+                Poll::Ready(Some(TransportToBehaviourMsg::DialReq { relay_peer_id, relay_addr, dst_peer_id, send_back })) =>
+                    self.request(relay_peer_id, relay_addr, handler::In::EstablishCircuit { dst_peer_id, send_back }),
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::client::transport::{RelayedConnection, ToListenerMsg};
+use crate::protocol::{inbound_stop, outbound_hop, Limit, Status, UpgradeError};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::BoxFuture,
+    prelude::*,
+    stream::FuturesUnordered,
+};
+use libp2p_core::PeerId;
+use libp2p_swarm::{
+    KeepAlive,
+    NegotiatedSubstream,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+use void::Void;
+use wasm_timer::{Delay, Instant};
+
+/// The duration for which a connection without reservation or circuit is
+/// kept alive.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Instruction of the [`Client`](crate::Client) behaviour to a [`Handler`].
+pub enum In {
+    /// Obtain a reservation with the relay.
+    Reserve {
+        to_listener: mpsc::UnboundedSender<ToListenerMsg>,
+    },
+    /// Establish a circuit to the destination through the relay.
+    EstablishCircuit {
+        dst_peer_id: PeerId,
+        send_back: oneshot::Sender<Result<RelayedConnection, ()>>,
+    },
+}
+
+impl fmt::Debug for In {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            In::Reserve { .. } => f.debug_struct("In::Reserve").finish(),
+            In::EstablishCircuit { dst_peer_id, .. } => f.debug_struct("In::EstablishCircuit")
+                .field("dst_peer_id", dst_peer_id)
+                .finish(),
+        }
+    }
+}
+
+/// Event produced by a [`Handler`] for the [`Client`](crate::Client) behaviour.
+#[derive(Debug)]
+pub enum Event {
+    ReservationReqAccepted { renewal: bool, limit: Option<Limit> },
+    ReservationReqFailed { renewal: bool },
+    OutboundCircuitEstablished { limit: Option<Limit> },
+    OutboundCircuitReqFailed,
+    InboundCircuitEstablished { src_peer_id: PeerId, limit: Option<Limit> },
+    InboundCircuitReqDenied { src_peer_id: PeerId },
+}
+
+/// Information attached to an outbound hop request.
+pub enum OutboundOpenInfo {
+    Reserve {
+        to_listener: mpsc::UnboundedSender<ToListenerMsg>,
+        renewal: bool,
+    },
+    Connect {
+        send_back: oneshot::Sender<Result<RelayedConnection, ()>>,
+    },
+}
+
+/// A reservation held with the relay.
+struct Reservation {
+    /// Fires when the reservation is to be renewed. `None` while a renewal
+    /// is in progress.
+    renewal_timeout: Option<Delay>,
+    to_listener: mpsc::UnboundedSender<ToListenerMsg>,
+}
+
+/// Protocols handler of a client for a single connection to a relay.
+pub struct Handler {
+    queued_events: VecDeque<ProtocolsHandlerEvent<outbound_hop::Upgrade, OutboundOpenInfo, Event, Void>>,
+    reservation: Option<Reservation>,
+    /// Futures accepting inbound circuits.
+    circuit_accept_futures: FuturesUnordered<
+        BoxFuture<'static, (PeerId, Option<Limit>, Result<NegotiatedSubstream, io::Error>)>
+    >,
+    /// Futures denying inbound circuits.
+    circuit_deny_futures: FuturesUnordered<BoxFuture<'static, PeerId>>,
+    /// Substreams handed out as [`RelayedConnection`]s, resolving once the
+    /// connection is dropped.
+    lent_out_substreams: FuturesUnordered<oneshot::Receiver<Void>>,
+    /// Number of pending outbound hop requests.
+    pending_outbound: usize,
+    /// Until when the connection is kept alive while idle.
+    idle_until: Instant,
+}
+
+impl Handler {
+    pub(crate) fn new() -> Self {
+        Handler {
+            queued_events: VecDeque::new(),
+            reservation: None,
+            circuit_accept_futures: FuturesUnordered::new(),
+            circuit_deny_futures: FuturesUnordered::new(),
+            lent_out_substreams: FuturesUnordered::new(),
+            pending_outbound: 0,
+            idle_until: Instant::now() + IDLE_TIMEOUT,
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.reservation.is_none()
+            && self.circuit_accept_futures.is_empty()
+            && self.circuit_deny_futures.is_empty()
+            && self.lent_out_substreams.is_empty()
+            && self.pending_outbound == 0
+    }
+
+    fn request_reservation(&mut self, to_listener: mpsc::UnboundedSender<ToListenerMsg>, renewal: bool) {
+        self.pending_outbound += 1;
+        self.queued_events.push_back(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+            protocol: SubstreamProtocol::new(
+                outbound_hop::Upgrade::Reserve,
+                OutboundOpenInfo::Reserve { to_listener, renewal },
+            ),
+        });
+    }
+
+    fn lend_out(&mut self, substream: NegotiatedSubstream) -> RelayedConnection {
+        let (drop_notifier, dropped) = oneshot::channel();
+        self.lent_out_substreams.push(dropped);
+        RelayedConnection::new(substream, drop_notifier)
+    }
+}
+
+impl ProtocolsHandler for Handler {
+    type InEvent = In;
+    type OutEvent = Event;
+    type Error = Void;
+    type InboundProtocol = inbound_stop::Upgrade;
+    type OutboundProtocol = outbound_hop::Upgrade;
+    type OutboundOpenInfo = OutboundOpenInfo;
+    type InboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(inbound_stop::Upgrade, ())
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, circuit: inbound_stop::Circuit, (): ()) {
+        let src_peer_id = circuit.src_peer_id();
+
+        let has_listener = self.reservation.as_ref()
+            .map_or(false, |r| !r.to_listener.is_closed());
+
+        if has_listener {
+            let limit = circuit.limit();
+            self.circuit_accept_futures.push(async move {
+                (src_peer_id, limit, circuit.accept().await)
+            }.boxed());
+        } else {
+            self.circuit_deny_futures.push(async move {
+                if let Err(e) = circuit.deny(Status::NoReservation).await {
+                    log::debug!("Failed to deny inbound circuit: {:?}", e);
+                }
+                src_peer_id
+            }.boxed());
+        }
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, output: outbound_hop::Output, info: OutboundOpenInfo) {
+        self.pending_outbound -= 1;
+
+        let event = match (output, info) {
+            (
+                outbound_hop::Output::Reservation { expires_in, limit, .. },
+                OutboundOpenInfo::Reserve { to_listener, renewal },
+            ) => {
+                let _ = to_listener.unbounded_send(ToListenerMsg::Reservation(Ok(())));
+                // Renew after half of the granted lifetime, like a lease.
+                self.reservation = Some(Reservation {
+                    renewal_timeout: Some(Delay::new(expires_in / 2)),
+                    to_listener,
+                });
+                Event::ReservationReqAccepted { renewal, limit }
+            }
+            (
+                outbound_hop::Output::Circuit { substream, limit },
+                OutboundOpenInfo::Connect { send_back },
+            ) => {
+                let connection = self.lend_out(substream);
+                let _ = send_back.send(Ok(connection));
+                Event::OutboundCircuitEstablished { limit }
+            }
+            _ => unreachable!("The outbound hop upgrade yields the output requested by the open info."),
+        };
+
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(event));
+    }
+
+    fn inject_event(&mut self, event: In) {
+        match event {
+            In::Reserve { to_listener } => self.request_reservation(to_listener, false),
+            In::EstablishCircuit { dst_peer_id, send_back } => {
+                self.pending_outbound += 1;
+                self.queued_events.push_back(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(
+                        outbound_hop::Upgrade::Connect { dst_peer_id },
+                        OutboundOpenInfo::Connect { send_back },
+                    ),
+                });
+            }
+        }
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        info: OutboundOpenInfo,
+        error: ProtocolsHandlerUpgrErr<UpgradeError>,
+    ) {
+        self.pending_outbound -= 1;
+
+        let event = match info {
+            OutboundOpenInfo::Reserve { to_listener, renewal } => {
+                log::debug!("Reservation request failed: {:?}", error);
+                let _ = to_listener.unbounded_send(ToListenerMsg::Reservation(Err(())));
+                if renewal {
+                    self.reservation = None;
+                }
+                Event::ReservationReqFailed { renewal }
+            }
+            OutboundOpenInfo::Connect { send_back } => {
+                log::debug!("Circuit request failed: {:?}", error);
+                let _ = send_back.send(Err(()));
+                Event::OutboundCircuitReqFailed
+            }
+        };
+
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(event));
+    }
+
+    fn inject_listen_upgrade_error(&mut self, _: (), error: ProtocolsHandlerUpgrErr<UpgradeError>) {
+        log::debug!("Inbound stop request failed: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        if self.is_idle() {
+            KeepAlive::Until(self.idle_until)
+        } else {
+            KeepAlive::Yes
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event)
+        }
+
+        // Renew the reservation for as long as the listener is alive.
+        if let Some(reservation) = self.reservation.as_mut() {
+            if reservation.to_listener.is_closed() {
+                self.reservation = None;
+            } else if let Some(timeout) = reservation.renewal_timeout.as_mut() {
+                if timeout.poll_unpin(cx).is_ready() {
+                    reservation.renewal_timeout = None;
+                    let to_listener = reservation.to_listener.clone();
+                    self.request_reservation(to_listener, true);
+                    if let Some(event) = self.queued_events.pop_front() {
+                        return Poll::Ready(event)
+                    }
+                }
+            }
+        }
+
+        if let Poll::Ready(Some((src_peer_id, limit, result))) = self.circuit_accept_futures.poll_next_unpin(cx) {
+            match result {
+                Ok(substream) => {
+                    let stream = self.lend_out(substream);
+                    if let Some(reservation) = self.reservation.as_ref() {
+                        let _ = reservation.to_listener.unbounded_send(
+                            ToListenerMsg::IncomingRelayedConnection { stream, src_peer_id }
+                        );
+                    }
+                    return Poll::Ready(ProtocolsHandlerEvent::Custom(
+                        Event::InboundCircuitEstablished { src_peer_id, limit }
+                    ))
+                }
+                Err(e) => log::debug!("Failed to accept inbound circuit from {}: {:?}", src_peer_id, e),
+            }
+        }
+
+        if let Poll::Ready(Some(src_peer_id)) = self.circuit_deny_futures.poll_next_unpin(cx) {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(Event::InboundCircuitReqDenied { src_peer_id }))
+        }
+
+        while let Poll::Ready(Some(_)) = self.lent_out_substreams.poll_next_unpin(cx) {}
+
+        if !self.is_idle() {
+            self.idle_until = Instant::now() + IDLE_TIMEOUT;
+        }
+
+        Poll::Pending
+    }
+}
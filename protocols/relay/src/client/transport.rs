@@ -0,0 +1,362 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture},
+    prelude::*,
+};
+use libp2p_core::{
+    multiaddr::{Multiaddr, Protocol},
+    transport::{ListenerEvent, TransportError},
+    PeerId,
+    Transport,
+};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{
+    error,
+    fmt,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use void::Void;
+
+/// A [`Transport`] listening and dialing through relays.
+///
+/// The transport only handles addresses containing `/p2p-circuit` and is
+/// expected to be combined with a transport for direct connections, e.g. via
+/// [`Transport::or_transport`]. It does not do any work by itself but hands
+/// the requests over to the [`Client`](crate::Client) behaviour it was
+/// created with.
+#[derive(Debug, Clone)]
+pub struct ClientTransport {
+    to_behaviour: mpsc::UnboundedSender<TransportToBehaviourMsg>,
+}
+
+impl ClientTransport {
+    pub(crate) fn new() -> (Self, mpsc::UnboundedReceiver<TransportToBehaviourMsg>) {
+        let (to_behaviour, from_transport) = mpsc::unbounded();
+        (ClientTransport { to_behaviour }, from_transport)
+    }
+}
+
+impl Transport for ClientTransport {
+    type Output = RelayedConnection;
+    type Error = RelayError;
+    type Listener = RelayListener;
+    type ListenerUpgrade = future::Ready<Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let RelayedMultiaddr { relay_addr, relay_peer_id, dst_peer_id } = match parse(&addr) {
+            Some(Ok(parsed)) => parsed,
+            Some(Err(e)) => return Err(TransportError::Other(e)),
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+        if dst_peer_id.is_some() {
+            return Err(TransportError::MultiaddrNotSupported(addr))
+        }
+
+        let (to_listener, from_behaviour) = mpsc::unbounded();
+        self.to_behaviour
+            .unbounded_send(TransportToBehaviourMsg::ListenReq {
+                relay_peer_id,
+                relay_addr: relay_addr.clone(),
+                to_listener,
+            })
+            .map_err(|_| TransportError::Other(RelayError::BehaviourDropped))?;
+
+        Ok(RelayListener {
+            listen_addr: relay_addr
+                .with(Protocol::P2p(relay_peer_id.into()))
+                .with(Protocol::P2pCircuit),
+            from_behaviour,
+            reported: false,
+            state: ListenerState::Active,
+        })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let (relay_addr, relay_peer_id, dst_peer_id) = match parse(&addr) {
+            Some(Ok(RelayedMultiaddr { relay_addr, relay_peer_id, dst_peer_id: Some(dst) })) =>
+                (relay_addr, relay_peer_id, dst),
+            Some(Ok(_)) => return Err(TransportError::Other(RelayError::MissingDstPeerId)),
+            Some(Err(e)) => return Err(TransportError::Other(e)),
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        let (send_back, response) = oneshot::channel();
+        self.to_behaviour
+            .unbounded_send(TransportToBehaviourMsg::DialReq {
+                relay_peer_id,
+                relay_addr,
+                dst_peer_id,
+                send_back,
+            })
+            .map_err(|_| TransportError::Other(RelayError::BehaviourDropped))?;
+
+        Ok(async move {
+            response.await
+                .map_err(|_| RelayError::BehaviourDropped)?
+                .map_err(|()| RelayError::Connect)
+        }.boxed())
+    }
+
+    fn address_translation(&self, _: &Multiaddr, _: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+}
+
+/// The components of a `/p2p-circuit` address.
+struct RelayedMultiaddr {
+    relay_addr: Multiaddr,
+    relay_peer_id: PeerId,
+    dst_peer_id: Option<PeerId>,
+}
+
+/// Parses an address of the form `/<relay-addr>/p2p/<relay-id>/p2p-circuit[/p2p/<dst-id>]`.
+///
+/// Returns `None` if the address does not contain `/p2p-circuit`.
+fn parse(addr: &Multiaddr) -> Option<Result<RelayedMultiaddr, RelayError>> {
+    let mut relay_addr = Multiaddr::empty();
+    let mut iter = addr.iter();
+
+    loop {
+        match iter.next()? {
+            Protocol::P2pCircuit => break,
+            p => relay_addr.push(p),
+        }
+    }
+
+    let relay_peer_id = match relay_addr.pop() {
+        Some(Protocol::P2p(hash)) => match PeerId::from_multihash(hash) {
+            Ok(peer_id) => peer_id,
+            Err(_) => return Some(Err(RelayError::InvalidAddress)),
+        },
+        _ => return Some(Err(RelayError::MissingRelayPeerId)),
+    };
+
+    let dst_peer_id = match iter.next() {
+        None => None,
+        Some(Protocol::P2p(hash)) => match PeerId::from_multihash(hash) {
+            Ok(peer_id) => Some(peer_id),
+            Err(_) => return Some(Err(RelayError::InvalidAddress)),
+        },
+        Some(_) => return Some(Err(RelayError::InvalidAddress)),
+    };
+
+    if iter.next().is_some() {
+        return Some(Err(RelayError::InvalidAddress))
+    }
+
+    Some(Ok(RelayedMultiaddr { relay_addr, relay_peer_id, dst_peer_id }))
+}
+
+/// Message from the [`ClientTransport`] to the [`Client`](crate::Client) behaviour.
+pub enum TransportToBehaviourMsg {
+    /// Obtain a reservation with the relay and listen through it.
+    ListenReq {
+        relay_peer_id: PeerId,
+        relay_addr: Multiaddr,
+        to_listener: mpsc::UnboundedSender<ToListenerMsg>,
+    },
+    /// Establish a circuit to the destination through the relay.
+    DialReq {
+        relay_peer_id: PeerId,
+        relay_addr: Multiaddr,
+        dst_peer_id: PeerId,
+        send_back: oneshot::Sender<Result<RelayedConnection, ()>>,
+    },
+}
+
+impl fmt::Debug for TransportToBehaviourMsg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportToBehaviourMsg::ListenReq { relay_peer_id, relay_addr, .. } =>
+                f.debug_struct("ListenReq")
+                    .field("relay_peer_id", relay_peer_id)
+                    .field("relay_addr", relay_addr)
+                    .finish(),
+            TransportToBehaviourMsg::DialReq { relay_peer_id, relay_addr, dst_peer_id, .. } =>
+                f.debug_struct("DialReq")
+                    .field("relay_peer_id", relay_peer_id)
+                    .field("relay_addr", relay_addr)
+                    .field("dst_peer_id", dst_peer_id)
+                    .finish(),
+        }
+    }
+}
+
+/// Message to a [`RelayListener`].
+pub enum ToListenerMsg {
+    /// The reservation with the relay has been obtained or renewed, or
+    /// failed to be.
+    Reservation(Result<(), ()>),
+    /// A circuit has been established to the local node.
+    IncomingRelayedConnection {
+        stream: RelayedConnection,
+        src_peer_id: PeerId,
+    },
+}
+
+enum ListenerState {
+    Active,
+    /// The reservation failed; the error is yet to be reported.
+    Failed,
+    Closed,
+}
+
+/// The listener of a [`ClientTransport`], accepting connections relayed
+/// on behalf of a reservation.
+pub struct RelayListener {
+    listen_addr: Multiaddr,
+    from_behaviour: mpsc::UnboundedReceiver<ToListenerMsg>,
+    /// Whether `listen_addr` has been reported as a new address.
+    reported: bool,
+    state: ListenerState,
+}
+
+impl Stream for RelayListener {
+    type Item = Result<
+        ListenerEvent<future::Ready<Result<RelayedConnection, RelayError>>, RelayError>,
+        RelayError,
+    >;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.state {
+            ListenerState::Active => {}
+            ListenerState::Failed => {
+                self.state = ListenerState::Closed;
+                return Poll::Ready(Some(Err(RelayError::Reservation)))
+            }
+            ListenerState::Closed => return Poll::Ready(None),
+        }
+
+        loop {
+            match futures::ready!(self.from_behaviour.poll_next_unpin(cx)) {
+                Some(ToListenerMsg::Reservation(Ok(()))) => {
+                    if !self.reported {
+                        self.reported = true;
+                        return Poll::Ready(Some(Ok(ListenerEvent::NewAddress(self.listen_addr.clone()))))
+                    }
+                }
+                Some(ToListenerMsg::Reservation(Err(()))) => {
+                    if self.reported {
+                        self.reported = false;
+                        self.state = ListenerState::Failed;
+                        return Poll::Ready(Some(Ok(ListenerEvent::AddressExpired(self.listen_addr.clone()))))
+                    }
+                    self.state = ListenerState::Closed;
+                    return Poll::Ready(Some(Err(RelayError::Reservation)))
+                }
+                Some(ToListenerMsg::IncomingRelayedConnection { stream, src_peer_id }) => {
+                    return Poll::Ready(Some(Ok(ListenerEvent::Upgrade {
+                        upgrade: future::ok(stream),
+                        local_addr: self.listen_addr.clone(),
+                        remote_addr: Multiaddr::empty().with(Protocol::P2p(src_peer_id.into())),
+                    })))
+                }
+                None => {
+                    self.state = ListenerState::Closed;
+                    return Poll::Ready(None)
+                }
+            }
+        }
+    }
+}
+
+/// A connection relayed through a relay, i.e. a substream of the
+/// connection to the relay.
+pub struct RelayedConnection {
+    stream: NegotiatedSubstream,
+    /// Dropped together with the connection, notifying the handler of the
+    /// connection to the relay that the substream is no longer in use.
+    _drop_notifier: oneshot::Sender<Void>,
+}
+
+impl RelayedConnection {
+    pub(crate) fn new(stream: NegotiatedSubstream, drop_notifier: oneshot::Sender<Void>) -> Self {
+        RelayedConnection { stream, _drop_notifier: drop_notifier }
+    }
+}
+
+impl fmt::Debug for RelayedConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RelayedConnection").finish()
+    }
+}
+
+impl AsyncRead for RelayedConnection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for RelayedConnection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
+
+/// Error of the [`ClientTransport`].
+#[derive(Debug)]
+pub enum RelayError {
+    /// The address lacks the `/p2p/<relay-id>` before `/p2p-circuit`.
+    MissingRelayPeerId,
+    /// The dialed address lacks the `/p2p/<dst-id>` after `/p2p-circuit`.
+    MissingDstPeerId,
+    /// The address is not a valid `/p2p-circuit` address.
+    InvalidAddress,
+    /// The [`Client`](crate::Client) behaviour has been dropped.
+    BehaviourDropped,
+    /// The relay did not grant or renew the reservation.
+    Reservation,
+    /// The circuit to the destination could not be established.
+    Connect,
+}
+
+impl fmt::Display for RelayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelayError::MissingRelayPeerId => f.write_str("Missing relay peer id"),
+            RelayError::MissingDstPeerId => f.write_str("Missing destination peer id"),
+            RelayError::InvalidAddress => f.write_str("Invalid relayed address"),
+            RelayError::BehaviourDropped => f.write_str("Relay client behaviour dropped"),
+            RelayError::Reservation => f.write_str("Failed to obtain reservation with relay"),
+            RelayError::Connect => f.write_str("Failed to connect through relay"),
+        }
+    }
+}
+
+impl error::Error for RelayError {}
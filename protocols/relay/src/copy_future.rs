@@ -0,0 +1,138 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Relaying of data between the two substreams of a circuit.
+
+use futures::{future::Either, io, prelude::*};
+use std::time::Duration;
+use wasm_timer::Delay;
+
+/// Copies data between the two given streams in both directions until both
+/// reach EOF, the circuit has been open for `max_duration`, or `max_bytes`
+/// have been relayed in one direction.
+///
+/// Reaching the data limit ends the affected direction as if the sender
+/// had closed it, whereas reaching the duration limit is reported as an
+/// error of kind [`io::ErrorKind::TimedOut`].
+pub(crate) async fn copy_with_limits<A, B>(
+    a: A,
+    b: B,
+    max_duration: Duration,
+    max_bytes: u64,
+) -> io::Result<()>
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let (a_read, a_write) = a.split();
+    let (b_read, b_write) = b.split();
+
+    let copy = future::try_join(
+        forward(a_read.take(max_bytes), b_write),
+        forward(b_read.take(max_bytes), a_write),
+    );
+    futures::pin_mut!(copy);
+
+    match future::select(copy, Delay::new(max_duration)).await {
+        Either::Left((result, _)) => result.map(|_| ()),
+        Either::Right(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "Max circuit duration reached")),
+    }
+}
+
+/// Copies data from `reader` to `writer` until EOF and closes `writer`.
+async fn forward<R, W>(reader: R, mut writer: W) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    io::copy(reader, &mut writer).await?;
+    writer.close().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::{pin::Pin, task::{Context, Poll}};
+
+    #[test]
+    fn copies_in_both_directions() {
+        let mut a = Duplex { read: io::Cursor::new(b"ping".to_vec()), write: Vec::new() };
+        let mut b = Duplex { read: io::Cursor::new(b"pong".to_vec()), write: Vec::new() };
+
+        block_on(copy_with_limits(&mut a, &mut b, Duration::from_secs(10), 1024)).unwrap();
+
+        assert_eq!(a.write, b"pong");
+        assert_eq!(b.write, b"ping");
+    }
+
+    #[test]
+    fn stops_at_data_limit() {
+        let mut a = Duplex { read: io::Cursor::new(vec![1; 100]), write: Vec::new() };
+        let mut b = Duplex { read: io::Cursor::new(Vec::new()), write: Vec::new() };
+
+        block_on(copy_with_limits(&mut a, &mut b, Duration::from_secs(10), 10)).unwrap();
+
+        assert_eq!(b.write.len(), 10);
+    }
+
+    #[test]
+    fn stops_at_duration_limit() {
+        let (_tx, rx) = futures::channel::mpsc::unbounded::<io::Result<Vec<u8>>>();
+        let mut a = Duplex { read: rx.into_async_read(), write: Vec::new() };
+        let mut b = Duplex { read: io::Cursor::new(Vec::new()), write: Vec::new() };
+
+        let err = block_on(copy_with_limits(&mut a, &mut b, Duration::from_millis(10), 10))
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    /// A stream reading from and writing to two independent buffers.
+    struct Duplex<R> {
+        read: R,
+        write: Vec<u8>,
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncRead for Duplex<R> {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8])
+            -> Poll<io::Result<usize>>
+        {
+            Pin::new(&mut self.read).poll_read(cx, buf)
+        }
+    }
+
+    impl<R: Unpin> AsyncWrite for Duplex<R> {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8])
+            -> Poll<io::Result<usize>>
+        {
+            Pin::new(&mut self.write).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.write).poll_flush(cx)
+        }
+
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.write).poll_close(cx)
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Implementation of the [circuit relay v2] protocol.
+//!
+//! A node that is not directly reachable, e.g. because it is behind a NAT,
+//! can make itself reachable through a relay. It asks the relay for a
+//! *reservation*, which the relay grants for a limited time, and in return
+//! obtains a `/p2p-circuit` address on which other nodes can dial it through
+//! the relay. Every circuit relayed on behalf of a reservation is in turn
+//! limited in duration and in the number of bytes transferred.
+//!
+//! # Usage
+//!
+//! The [`Relay`] behaviour implements the relay side. It accepts reservations
+//! and circuits within the limits of its [`RelayConfig`], both globally and
+//! per peer.
+//!
+//! The [`Client`] behaviour, together with the [`ClientTransport`] obtained
+//! from [`Client::new_transport_and_behaviour`], implements the side of the
+//! nodes using a relay. Listening with the [`ClientTransport`] on an address
+//! of the form `/<relay-addr>/p2p/<relay-id>/p2p-circuit` requests a
+//! reservation from the relay and keeps it renewed for as long as the
+//! listener is alive. Dialing `/<relay-addr>/p2p/<relay-id>/p2p-circuit/p2p/<dst-id>`
+//! establishes a circuit to the destination through the relay.
+//!
+//! > **Note**: Reservation vouchers, which are optional in the specification,
+//! > are neither issued nor verified.
+//!
+//! [circuit relay v2]: https://github.com/libp2p/specs/blob/master/relay/circuit-v2.md
+
+mod client;
+mod copy_future;
+mod protocol;
+mod relay;
+
+mod message_proto {
+    include!(concat!(env!("OUT_DIR"), "/message_proto.rs"));
+}
+
+pub use client::{
+    Client,
+    ClientEvent,
+    transport::{ClientTransport, RelayError, RelayListener, RelayedConnection},
+};
+pub use protocol::{Limit, Status, UpgradeError, HOP_PROTOCOL_NAME, STOP_PROTOCOL_NAME};
+pub use relay::{Relay, RelayConfig, RelayEvent};
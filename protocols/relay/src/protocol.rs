@@ -0,0 +1,206 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::message_proto;
+use futures::prelude::*;
+use libp2p_core::upgrade;
+use prost::Message;
+use std::{error, fmt, io, time::Duration};
+
+pub mod inbound_hop;
+pub mod inbound_stop;
+pub mod outbound_hop;
+pub mod outbound_stop;
+
+/// Protocol name of the hop protocol, spoken between the relay and its clients.
+pub const HOP_PROTOCOL_NAME: &[u8] = b"/libp2p/circuit/relay/0.2.0/hop";
+/// Protocol name of the stop protocol, spoken by the relay to the destination
+/// of a circuit.
+pub const STOP_PROTOCOL_NAME: &[u8] = b"/libp2p/circuit/relay/0.2.0/stop";
+
+/// The maximum size of a hop or stop message.
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// The limits the relay applies to a relayed connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    duration: Option<Duration>,
+    data_in_bytes: Option<u64>,
+}
+
+impl Limit {
+    /// The maximum duration of a relayed connection, if limited.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// The maximum number of bytes relayed in each direction, if limited.
+    pub fn data_in_bytes(&self) -> Option<u64> {
+        self.data_in_bytes
+    }
+}
+
+impl From<message_proto::Limit> for Limit {
+    fn from(limit: message_proto::Limit) -> Self {
+        Limit {
+            duration: limit.duration.map(|d| Duration::from_secs(d.into())),
+            data_in_bytes: limit.data,
+        }
+    }
+}
+
+/// Encodes the limit of a circuit for the wire.
+fn encode_limit(max_circuit_duration: Duration, max_circuit_bytes: u64) -> message_proto::Limit {
+    message_proto::Limit {
+        duration: Some(max_circuit_duration.as_secs().min(u32::MAX.into()) as u32),
+        data: Some(max_circuit_bytes),
+    }
+}
+
+/// The reason for which a peer refused a hop or stop request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The relay refused the reservation.
+    ReservationRefused,
+    /// A resource limit of the relay, e.g. the number of reservations or
+    /// circuits, has been reached.
+    ResourceLimitExceeded,
+    /// The request is not permitted.
+    PermissionDenied,
+    /// The relay could not connect to the destination, or the destination
+    /// did not accept the circuit.
+    ConnectionFailed,
+    /// The destination has no reservation with the relay.
+    NoReservation,
+    /// The request was malformed.
+    MalformedMessage,
+    /// The request was not expected.
+    UnexpectedMessage,
+}
+
+impl Status {
+    fn from_proto(status: i32) -> Option<Result<(), Status>> {
+        Some(match message_proto::Status::from_i32(status)? {
+            message_proto::Status::Ok => Ok(()),
+            message_proto::Status::ReservationRefused => Err(Status::ReservationRefused),
+            message_proto::Status::ResourceLimitExceeded => Err(Status::ResourceLimitExceeded),
+            message_proto::Status::PermissionDenied => Err(Status::PermissionDenied),
+            message_proto::Status::ConnectionFailed => Err(Status::ConnectionFailed),
+            message_proto::Status::NoReservation => Err(Status::NoReservation),
+            message_proto::Status::MalformedMessage => Err(Status::MalformedMessage),
+            message_proto::Status::UnexpectedMessage => Err(Status::UnexpectedMessage),
+        })
+    }
+
+    fn to_proto(self) -> message_proto::Status {
+        match self {
+            Status::ReservationRefused => message_proto::Status::ReservationRefused,
+            Status::ResourceLimitExceeded => message_proto::Status::ResourceLimitExceeded,
+            Status::PermissionDenied => message_proto::Status::PermissionDenied,
+            Status::ConnectionFailed => message_proto::Status::ConnectionFailed,
+            Status::NoReservation => message_proto::Status::NoReservation,
+            Status::MalformedMessage => message_proto::Status::MalformedMessage,
+            Status::UnexpectedMessage => message_proto::Status::UnexpectedMessage,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::ReservationRefused => f.write_str("reservation refused"),
+            Status::ResourceLimitExceeded => f.write_str("resource limit exceeded"),
+            Status::PermissionDenied => f.write_str("permission denied"),
+            Status::ConnectionFailed => f.write_str("connection failed"),
+            Status::NoReservation => f.write_str("no reservation"),
+            Status::MalformedMessage => f.write_str("malformed message"),
+            Status::UnexpectedMessage => f.write_str("unexpected message"),
+        }
+    }
+}
+
+/// Error while upgrading a substream to the hop or stop protocol.
+#[derive(Debug)]
+pub enum UpgradeError {
+    /// I/O error on the substream.
+    Io(io::Error),
+    /// The remote sent a message that could not be decoded, lacked a
+    /// required field or was of an unexpected type.
+    Malformed(String),
+    /// The remote refused the request.
+    Refused(Status),
+}
+
+impl fmt::Display for UpgradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpgradeError::Io(e) => write!(f, "I/O error: {}", e),
+            UpgradeError::Malformed(e) => write!(f, "Malformed message: {}", e),
+            UpgradeError::Refused(s) => write!(f, "Request refused: {}", s),
+        }
+    }
+}
+
+impl error::Error for UpgradeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            UpgradeError::Io(e) => Some(e),
+            UpgradeError::Malformed(_) => None,
+            UpgradeError::Refused(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for UpgradeError {
+    fn from(e: io::Error) -> Self {
+        UpgradeError::Io(e)
+    }
+}
+
+impl From<upgrade::ReadOneError> for UpgradeError {
+    fn from(e: upgrade::ReadOneError) -> Self {
+        match e {
+            upgrade::ReadOneError::Io(e) => UpgradeError::Io(e),
+            e => UpgradeError::Malformed(e.to_string()),
+        }
+    }
+}
+
+/// Reads a single length-prefixed message from the substream.
+async fn read_message<M, S>(substream: &mut S) -> Result<M, UpgradeError>
+where
+    M: Message + Default,
+    S: AsyncRead + Unpin,
+{
+    let bytes = upgrade::read_one(substream, MAX_MESSAGE_SIZE).await?;
+    M::decode(&bytes[..]).map_err(|e| UpgradeError::Malformed(e.to_string()))
+}
+
+/// Writes a single length-prefixed message to the substream and flushes it.
+async fn write_message<M, S>(substream: &mut S, message: &M) -> Result<(), io::Error>
+where
+    M: Message,
+    S: AsyncWrite + Unpin,
+{
+    let mut bytes = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+    upgrade::write_with_len_prefix(substream, bytes).await
+}
@@ -0,0 +1,188 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::message_proto::{self, hop_message, HopMessage, Reservation};
+use crate::protocol::{encode_limit, read_message, write_message, Status, UpgradeError, HOP_PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, Multiaddr, PeerId};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{fmt, io, iter, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+/// Upgrade for inbound hop requests, used by the relay.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    pub reservation_duration: Duration,
+    pub max_circuit_duration: Duration,
+    pub max_circuit_bytes: u64,
+}
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(HOP_PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::InboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = Req;
+    type Error = UpgradeError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            let msg: HopMessage = read_message(&mut substream).await?;
+
+            match hop_message::Type::from_i32(msg.r#type) {
+                Some(hop_message::Type::Reserve) => Ok(Req::Reserve(ReservationReq {
+                    substream,
+                    reservation_duration: self.reservation_duration,
+                    max_circuit_duration: self.max_circuit_duration,
+                    max_circuit_bytes: self.max_circuit_bytes,
+                })),
+                Some(hop_message::Type::Connect) => {
+                    let peer = msg.peer
+                        .ok_or_else(|| UpgradeError::Malformed("Missing peer".into()))?;
+                    let dst = PeerId::from_bytes(&peer.id)
+                        .map_err(|_| UpgradeError::Malformed("Invalid peer id".into()))?;
+                    Ok(Req::Connect(CircuitReq {
+                        dst,
+                        substream,
+                        max_circuit_duration: self.max_circuit_duration,
+                        max_circuit_bytes: self.max_circuit_bytes,
+                    }))
+                }
+                _ => Err(UpgradeError::Malformed("Unexpected message type".into())),
+            }
+        }.boxed()
+    }
+}
+
+/// An inbound hop request.
+#[derive(Debug)]
+pub enum Req {
+    Reserve(ReservationReq),
+    Connect(CircuitReq),
+}
+
+/// A reservation request of a client, to be accepted or denied.
+pub struct ReservationReq {
+    substream: NegotiatedSubstream,
+    reservation_duration: Duration,
+    max_circuit_duration: Duration,
+    max_circuit_bytes: u64,
+}
+
+impl fmt::Debug for ReservationReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReservationReq")
+            .field("reservation_duration", &self.reservation_duration)
+            .finish()
+    }
+}
+
+impl ReservationReq {
+    /// Accepts the reservation, announcing the given relay addresses to the client.
+    pub async fn accept(self, addrs: Vec<Multiaddr>) -> Result<(), io::Error> {
+        let expire = (SystemTime::now() + self.reservation_duration)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let msg = HopMessage {
+            r#type: hop_message::Type::Status.into(),
+            peer: None,
+            reservation: Some(Reservation {
+                expire,
+                addrs: addrs.into_iter().map(|a| a.to_vec()).collect(),
+                voucher: None,
+            }),
+            limit: Some(encode_limit(self.max_circuit_duration, self.max_circuit_bytes)),
+            status: Some(message_proto::Status::Ok.into()),
+        };
+
+        send(self.substream, msg).await
+    }
+
+    /// Denies the reservation with the given status.
+    pub async fn deny(self, status: Status) -> Result<(), io::Error> {
+        send(self.substream, status_message(status)).await
+    }
+}
+
+/// A request of a client to connect to a destination through the relay.
+pub struct CircuitReq {
+    dst: PeerId,
+    substream: NegotiatedSubstream,
+    max_circuit_duration: Duration,
+    max_circuit_bytes: u64,
+}
+
+impl fmt::Debug for CircuitReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitReq")
+            .field("dst", &self.dst)
+            .finish()
+    }
+}
+
+impl CircuitReq {
+    /// The destination of the requested circuit.
+    pub fn dst(&self) -> PeerId {
+        self.dst
+    }
+
+    /// Accepts the circuit request, returning the substream to relay data on.
+    pub async fn accept(mut self) -> Result<NegotiatedSubstream, io::Error> {
+        let msg = HopMessage {
+            r#type: hop_message::Type::Status.into(),
+            peer: None,
+            reservation: None,
+            limit: Some(encode_limit(self.max_circuit_duration, self.max_circuit_bytes)),
+            status: Some(message_proto::Status::Ok.into()),
+        };
+
+        write_message(&mut self.substream, &msg).await?;
+        Ok(self.substream)
+    }
+
+    /// Denies the circuit request with the given status.
+    pub async fn deny(self, status: Status) -> Result<(), io::Error> {
+        send(self.substream, status_message(status)).await
+    }
+}
+
+fn status_message(status: Status) -> HopMessage {
+    HopMessage {
+        r#type: hop_message::Type::Status.into(),
+        peer: None,
+        reservation: None,
+        limit: None,
+        status: Some(status.to_proto().into()),
+    }
+}
+
+/// Sends the final message of an exchange and closes the substream.
+async fn send(mut substream: NegotiatedSubstream, msg: HopMessage) -> Result<(), io::Error> {
+    write_message(&mut substream, &msg).await?;
+    substream.close().await
+}
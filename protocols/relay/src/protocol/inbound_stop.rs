@@ -0,0 +1,122 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::message_proto::{self, stop_message, StopMessage};
+use crate::protocol::{read_message, write_message, Limit, Status, UpgradeError, STOP_PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, PeerId};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{fmt, io, iter};
+
+/// Upgrade for inbound stop requests, used by clients to accept circuits
+/// relayed to them.
+#[derive(Debug, Clone)]
+pub struct Upgrade;
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(STOP_PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::InboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = Circuit;
+    type Error = UpgradeError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            let msg: StopMessage = read_message(&mut substream).await?;
+
+            if stop_message::Type::from_i32(msg.r#type) != Some(stop_message::Type::Connect) {
+                return Err(UpgradeError::Malformed("Unexpected message type".into()))
+            }
+
+            let peer = msg.peer
+                .ok_or_else(|| UpgradeError::Malformed("Missing peer".into()))?;
+            let src_peer_id = PeerId::from_bytes(&peer.id)
+                .map_err(|_| UpgradeError::Malformed("Invalid peer id".into()))?;
+
+            Ok(Circuit {
+                substream,
+                src_peer_id,
+                limit: msg.limit.map(Into::into),
+            })
+        }.boxed()
+    }
+}
+
+/// A circuit the relay opened on behalf of a remote peer, to be accepted or denied.
+pub struct Circuit {
+    substream: NegotiatedSubstream,
+    src_peer_id: PeerId,
+    limit: Option<Limit>,
+}
+
+impl fmt::Debug for Circuit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Circuit")
+            .field("src_peer_id", &self.src_peer_id)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+impl Circuit {
+    /// The peer on whose behalf the circuit was opened.
+    pub fn src_peer_id(&self) -> PeerId {
+        self.src_peer_id
+    }
+
+    /// The limits the relay applies to the circuit.
+    pub fn limit(&self) -> Option<Limit> {
+        self.limit
+    }
+
+    /// Accepts the circuit, returning the substream to the source peer.
+    pub async fn accept(mut self) -> Result<NegotiatedSubstream, io::Error> {
+        let msg = StopMessage {
+            r#type: stop_message::Type::Status.into(),
+            peer: None,
+            limit: None,
+            status: Some(message_proto::Status::Ok.into()),
+        };
+
+        write_message(&mut self.substream, &msg).await?;
+        Ok(self.substream)
+    }
+
+    /// Denies the circuit with the given status.
+    pub async fn deny(mut self, status: Status) -> Result<(), io::Error> {
+        let msg = StopMessage {
+            r#type: stop_message::Type::Status.into(),
+            peer: None,
+            limit: None,
+            status: Some(status.to_proto().into()),
+        };
+
+        write_message(&mut self.substream, &msg).await?;
+        self.substream.close().await
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::message_proto::{hop_message, HopMessage, Peer};
+use crate::protocol::{read_message, write_message, Limit, Status, UpgradeError, HOP_PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, Multiaddr, PeerId};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{convert::TryFrom, fmt, iter, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+/// Upgrade for outbound hop requests, used by clients.
+#[derive(Debug, Clone)]
+pub enum Upgrade {
+    /// Requests a reservation with the relay.
+    Reserve,
+    /// Requests a circuit to the given destination.
+    Connect { dst_peer_id: PeerId },
+}
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(HOP_PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::OutboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = Output;
+    type Error = UpgradeError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        let msg = match self {
+            Upgrade::Reserve => HopMessage {
+                r#type: hop_message::Type::Reserve.into(),
+                peer: None,
+                reservation: None,
+                limit: None,
+                status: None,
+            },
+            Upgrade::Connect { dst_peer_id } => HopMessage {
+                r#type: hop_message::Type::Connect.into(),
+                peer: Some(Peer {
+                    id: dst_peer_id.to_bytes(),
+                    addrs: Vec::new(),
+                }),
+                reservation: None,
+                limit: None,
+                status: None,
+            },
+        };
+
+        async move {
+            write_message(&mut substream, &msg).await?;
+            let msg: HopMessage = read_message(&mut substream).await?;
+
+            if hop_message::Type::from_i32(msg.r#type) != Some(hop_message::Type::Status) {
+                return Err(UpgradeError::Malformed("Unexpected message type".into()))
+            }
+
+            match msg.status.and_then(Status::from_proto) {
+                Some(Ok(())) => {}
+                Some(Err(status)) => return Err(UpgradeError::Refused(status)),
+                None => return Err(UpgradeError::Malformed("Missing or invalid status".into())),
+            }
+
+            let limit = msg.limit.map(Limit::from);
+
+            match self {
+                Upgrade::Reserve => {
+                    let reservation = msg.reservation
+                        .ok_or_else(|| UpgradeError::Malformed("Missing reservation".into()))?;
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    let addrs = reservation.addrs
+                        .into_iter()
+                        .filter_map(|a| Multiaddr::try_from(a).ok())
+                        .collect();
+
+                    substream.close().await?;
+
+                    Ok(Output::Reservation {
+                        expires_in: Duration::from_secs(reservation.expire.saturating_sub(now)),
+                        addrs,
+                        limit,
+                    })
+                }
+                Upgrade::Connect { .. } => Ok(Output::Circuit { substream, limit }),
+            }
+        }.boxed()
+    }
+}
+
+/// The outcome of a successful outbound hop request.
+pub enum Output {
+    /// The relay granted a reservation.
+    Reservation {
+        /// The remaining lifetime of the reservation.
+        expires_in: Duration,
+        /// The addresses of the relay announced for the reservation.
+        addrs: Vec<Multiaddr>,
+        /// The limits of circuits relayed on behalf of the reservation.
+        limit: Option<Limit>,
+    },
+    /// The relay established a circuit to the destination.
+    Circuit {
+        substream: NegotiatedSubstream,
+        limit: Option<Limit>,
+    },
+}
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Reservation { expires_in, addrs, limit } => f.debug_struct("Reservation")
+                .field("expires_in", expires_in)
+                .field("addrs", addrs)
+                .field("limit", limit)
+                .finish(),
+            Output::Circuit { limit, .. } => f.debug_struct("Circuit")
+                .field("limit", limit)
+                .finish(),
+        }
+    }
+}
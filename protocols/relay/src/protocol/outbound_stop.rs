@@ -0,0 +1,78 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::message_proto::{stop_message, Peer, StopMessage};
+use crate::protocol::{encode_limit, read_message, write_message, Status, UpgradeError, STOP_PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, PeerId};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{iter, time::Duration};
+
+/// Upgrade for outbound stop requests, used by the relay to open a circuit
+/// to its destination.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    pub src_peer_id: PeerId,
+    pub max_circuit_duration: Duration,
+    pub max_circuit_bytes: u64,
+}
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(STOP_PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::OutboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = NegotiatedSubstream;
+    type Error = UpgradeError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        let msg = StopMessage {
+            r#type: stop_message::Type::Connect.into(),
+            peer: Some(Peer {
+                id: self.src_peer_id.to_bytes(),
+                addrs: Vec::new(),
+            }),
+            limit: Some(encode_limit(self.max_circuit_duration, self.max_circuit_bytes)),
+            status: None,
+        };
+
+        async move {
+            write_message(&mut substream, &msg).await?;
+            let msg: StopMessage = read_message(&mut substream).await?;
+
+            if stop_message::Type::from_i32(msg.r#type) != Some(stop_message::Type::Status) {
+                return Err(UpgradeError::Malformed("Unexpected message type".into()))
+            }
+
+            match msg.status.and_then(Status::from_proto) {
+                Some(Ok(())) => Ok(substream),
+                Some(Err(status)) => Err(UpgradeError::Refused(status)),
+                None => Err(UpgradeError::Malformed("Missing or invalid status".into())),
+            }
+        }.boxed()
+    }
+}
@@ -0,0 +1,455 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! The relay side of the protocol.
+
+mod handler;
+
+use crate::protocol::{inbound_hop, Status};
+use libp2p_core::{connection::ConnectionId, multiaddr::Protocol, ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, NotifyHandler, PollParameters};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Configuration for the [`Relay`] behaviour.
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    max_reservations: usize,
+    max_reservations_per_peer: usize,
+    reservation_duration: Duration,
+    max_circuits: usize,
+    max_circuits_per_peer: usize,
+    max_circuit_duration: Duration,
+    max_circuit_bytes: u64,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        RelayConfig {
+            max_reservations: 128,
+            max_reservations_per_peer: 4,
+            reservation_duration: Duration::from_secs(60 * 60),
+            max_circuits: 16,
+            max_circuits_per_peer: 4,
+            max_circuit_duration: Duration::from_secs(2 * 60),
+            max_circuit_bytes: 1 << 17,
+        }
+    }
+}
+
+impl RelayConfig {
+    /// Creates a new configuration with default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of reservations held by all peers together.
+    pub fn set_max_reservations(&mut self, n: usize) -> &mut Self {
+        self.max_reservations = n;
+        self
+    }
+
+    /// Sets the maximum number of reservations held by a single peer,
+    /// i.e. over its connections to the relay.
+    pub fn set_max_reservations_per_peer(&mut self, n: usize) -> &mut Self {
+        self.max_reservations_per_peer = n;
+        self
+    }
+
+    /// Sets the duration for which a reservation is granted.
+    ///
+    /// Clients are expected to renew their reservation before it expires.
+    pub fn set_reservation_duration(&mut self, d: Duration) -> &mut Self {
+        self.reservation_duration = d;
+        self
+    }
+
+    /// Sets the maximum number of circuits relayed at the same time.
+    pub fn set_max_circuits(&mut self, n: usize) -> &mut Self {
+        self.max_circuits = n;
+        self
+    }
+
+    /// Sets the maximum number of circuits relayed at the same time on
+    /// behalf of a single source peer.
+    pub fn set_max_circuits_per_peer(&mut self, n: usize) -> &mut Self {
+        self.max_circuits_per_peer = n;
+        self
+    }
+
+    /// Sets the duration after which a circuit is closed.
+    pub fn set_max_circuit_duration(&mut self, d: Duration) -> &mut Self {
+        self.max_circuit_duration = d;
+        self
+    }
+
+    /// Sets the number of bytes relayed in each direction of a circuit
+    /// after which the circuit is closed.
+    pub fn set_max_circuit_bytes(&mut self, n: u64) -> &mut Self {
+        self.max_circuit_bytes = n;
+        self
+    }
+}
+
+/// Event that can be produced by the [`Relay`] behaviour.
+#[derive(Debug)]
+pub enum RelayEvent {
+    /// A reservation has been accepted, or an existing one renewed.
+    ReservationReqAccepted { src_peer_id: PeerId, renewed: bool },
+    /// Accepting a reservation failed.
+    ReservationReqAcceptFailed { src_peer_id: PeerId, error: io::Error },
+    /// A reservation has been denied because a limit was reached.
+    ReservationReqDenied { src_peer_id: PeerId },
+    /// A reservation expired without being renewed.
+    ReservationTimedOut { src_peer_id: PeerId },
+    /// A circuit request has been denied.
+    CircuitReqDenied { src_peer_id: PeerId, dst_peer_id: PeerId, status: Status },
+    /// A circuit has been established.
+    CircuitReqAccepted { src_peer_id: PeerId, dst_peer_id: PeerId },
+    /// Accepting a circuit request failed after the destination accepted it.
+    CircuitReqAcceptFailed { src_peer_id: PeerId, dst_peer_id: PeerId, error: io::Error },
+    /// A circuit has been closed.
+    ///
+    /// The error is set if the circuit failed or reached its duration limit.
+    CircuitClosed { src_peer_id: PeerId, dst_peer_id: PeerId, error: Option<io::Error> },
+}
+
+/// Identifier of a circuit relayed by the [`Relay`] behaviour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CircuitId(u64);
+
+/// A circuit relayed, or about to be relayed, by the [`Relay`] behaviour.
+#[derive(Debug)]
+struct Circuit {
+    src_peer_id: PeerId,
+    src_connection_id: ConnectionId,
+    dst_peer_id: PeerId,
+    dst_connection_id: ConnectionId,
+}
+
+/// An action of the [`Relay`] behaviour waiting to be returned from `poll`.
+enum Action {
+    Done(NetworkBehaviourAction<handler::In, RelayEvent>),
+    /// A reservation to accept, with the addresses of the relay known only
+    /// in `poll`.
+    AcceptReservation {
+        peer_id: PeerId,
+        connection: ConnectionId,
+        inbound_reservation_req: inbound_hop::ReservationReq,
+    },
+}
+
+impl From<NetworkBehaviourAction<handler::In, RelayEvent>> for Action {
+    fn from(action: NetworkBehaviourAction<handler::In, RelayEvent>) -> Self {
+        Action::Done(action)
+    }
+}
+
+/// A [`NetworkBehaviour`] relaying circuits between peers.
+///
+/// Peers obtain a reservation with the relay, after which other peers can
+/// open circuits to them through the relay. Both reservations and circuits
+/// are bounded by the limits of the [`RelayConfig`].
+pub struct Relay {
+    config: RelayConfig,
+    /// The connections over which each peer holds a reservation.
+    reservations: HashMap<PeerId, HashSet<ConnectionId>>,
+    /// The circuits being established or relayed.
+    circuits: HashMap<CircuitId, Circuit>,
+    next_circuit_id: u64,
+    queued_actions: VecDeque<Action>,
+}
+
+impl Relay {
+    /// Creates a new `Relay` behaviour with the given configuration.
+    pub fn new(config: RelayConfig) -> Self {
+        Relay {
+            config,
+            reservations: HashMap::new(),
+            circuits: HashMap::new(),
+            next_circuit_id: 0,
+            queued_actions: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of reservations currently held.
+    pub fn num_reservations(&self) -> usize {
+        self.reservations.values().map(HashSet::len).sum()
+    }
+
+    /// Returns the number of circuits currently being established or relayed.
+    pub fn num_circuits(&self) -> usize {
+        self.circuits.len()
+    }
+
+    fn handle_reservation_req(
+        &mut self,
+        peer_id: PeerId,
+        connection: ConnectionId,
+        inbound_reservation_req: inbound_hop::ReservationReq,
+    ) {
+        let peer_reservations = self.reservations.get(&peer_id);
+        let renewal = peer_reservations.map_or(false, |c| c.contains(&connection));
+        let within_limits = self.num_reservations() < self.config.max_reservations
+            && peer_reservations.map_or(0, HashSet::len) < self.config.max_reservations_per_peer;
+
+        if renewal || within_limits {
+            self.reservations.entry(peer_id).or_default().insert(connection);
+            self.queued_actions.push_back(Action::AcceptReservation {
+                peer_id,
+                connection,
+                inbound_reservation_req,
+            });
+        } else {
+            self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::One(connection),
+                event: handler::In::DenyReservationReq {
+                    inbound_reservation_req,
+                    status: Status::ResourceLimitExceeded,
+                },
+            }.into());
+        }
+    }
+
+    fn handle_circuit_req(
+        &mut self,
+        src_peer_id: PeerId,
+        src_connection_id: ConnectionId,
+        inbound_circuit_req: inbound_hop::CircuitReq,
+    ) {
+        let dst_peer_id = inbound_circuit_req.dst();
+        let src_circuits = self.circuits.values()
+            .filter(|c| c.src_peer_id == src_peer_id)
+            .count();
+        let dst_connection_id = self.reservations.get(&dst_peer_id)
+            .and_then(|c| c.iter().next().copied());
+
+        let action = match dst_connection_id {
+            _ if self.circuits.len() >= self.config.max_circuits
+                || src_circuits >= self.config.max_circuits_per_peer =>
+                deny_circuit_req(src_peer_id, src_connection_id, inbound_circuit_req, Status::ResourceLimitExceeded),
+            None =>
+                deny_circuit_req(src_peer_id, src_connection_id, inbound_circuit_req, Status::NoReservation),
+            Some(dst_connection_id) => {
+                let circuit_id = CircuitId(self.next_circuit_id);
+                self.next_circuit_id += 1;
+                self.circuits.insert(circuit_id, Circuit {
+                    src_peer_id,
+                    src_connection_id,
+                    dst_peer_id,
+                    dst_connection_id,
+                });
+                NetworkBehaviourAction::NotifyHandler {
+                    peer_id: dst_peer_id,
+                    handler: NotifyHandler::One(dst_connection_id),
+                    event: handler::In::NegotiateOutboundConnect {
+                        circuit_id,
+                        inbound_circuit_req,
+                        src_peer_id,
+                        src_connection_id,
+                    },
+                }
+            }
+        };
+        self.queued_actions.push_back(action.into());
+    }
+
+    fn remove_reservation(&mut self, peer_id: &PeerId, connection: &ConnectionId) {
+        if let Some(connections) = self.reservations.get_mut(peer_id) {
+            connections.remove(connection);
+            if connections.is_empty() {
+                self.reservations.remove(peer_id);
+            }
+        }
+    }
+}
+
+/// Creates the action denying a circuit request of the given peer.
+fn deny_circuit_req(
+    peer_id: PeerId,
+    connection: ConnectionId,
+    inbound_circuit_req: inbound_hop::CircuitReq,
+    status: Status,
+) -> NetworkBehaviourAction<handler::In, RelayEvent> {
+    NetworkBehaviourAction::NotifyHandler {
+        peer_id,
+        handler: NotifyHandler::One(connection),
+        event: handler::In::DenyCircuitReq {
+            circuit_id: None,
+            inbound_circuit_req,
+            status,
+        },
+    }
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        Relay::new(RelayConfig::default())
+    }
+}
+
+impl NetworkBehaviour for Relay {
+    type ProtocolsHandler = handler::Handler;
+    type OutEvent = RelayEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        handler::Handler::new(handler::Config {
+            reservation_duration: self.config.reservation_duration,
+            max_circuit_duration: self.config.max_circuit_duration,
+            max_circuit_bytes: self.config.max_circuit_bytes,
+        })
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_connection_closed(&mut self, peer_id: &PeerId, connection: &ConnectionId, _: &ConnectedPoint) {
+        self.remove_reservation(peer_id, connection);
+
+        self.circuits.retain(|_, c| {
+            !(c.src_peer_id == *peer_id && c.src_connection_id == *connection)
+                && !(c.dst_peer_id == *peer_id && c.dst_connection_id == *connection)
+        });
+    }
+
+    fn inject_event(&mut self, peer_id: PeerId, connection: ConnectionId, event: handler::Event) {
+        match event {
+            handler::Event::ReservationReqReceived { inbound_reservation_req } => {
+                self.handle_reservation_req(peer_id, connection, inbound_reservation_req);
+            }
+            handler::Event::ReservationReqAccepted { renewed } => {
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationReqAccepted { src_peer_id: peer_id, renewed }
+                ).into());
+            }
+            handler::Event::ReservationReqAcceptFailed { error } => {
+                self.remove_reservation(&peer_id, &connection);
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationReqAcceptFailed { src_peer_id: peer_id, error }
+                ).into());
+            }
+            handler::Event::ReservationReqDenied => {
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationReqDenied { src_peer_id: peer_id }
+                ).into());
+            }
+            handler::Event::ReservationTimedOut => {
+                self.remove_reservation(&peer_id, &connection);
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationTimedOut { src_peer_id: peer_id }
+                ).into());
+            }
+            handler::Event::CircuitReqReceived { inbound_circuit_req } => {
+                self.handle_circuit_req(peer_id, connection, inbound_circuit_req);
+            }
+            handler::Event::CircuitReqDenied { circuit_id, dst_peer_id, status } => {
+                if let Some(circuit_id) = circuit_id {
+                    self.circuits.remove(&circuit_id);
+                }
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::CircuitReqDenied { src_peer_id: peer_id, dst_peer_id, status }
+                ).into());
+            }
+            handler::Event::OutboundConnectNegotiated {
+                circuit_id,
+                src_peer_id,
+                src_connection_id,
+                inbound_circuit_req,
+                dst_stream,
+            } => {
+                self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: src_peer_id,
+                    handler: NotifyHandler::One(src_connection_id),
+                    event: handler::In::AcceptAndDriveCircuit {
+                        circuit_id,
+                        dst_peer_id: peer_id,
+                        inbound_circuit_req,
+                        dst_stream,
+                    },
+                }.into());
+            }
+            handler::Event::OutboundConnectNegotiationFailed {
+                circuit_id,
+                src_peer_id,
+                src_connection_id,
+                inbound_circuit_req,
+                status,
+            } => {
+                self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: src_peer_id,
+                    handler: NotifyHandler::One(src_connection_id),
+                    event: handler::In::DenyCircuitReq {
+                        circuit_id: Some(circuit_id),
+                        inbound_circuit_req,
+                        status,
+                    },
+                }.into());
+            }
+            handler::Event::CircuitReqAccepted { dst_peer_id, .. } => {
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::CircuitReqAccepted { src_peer_id: peer_id, dst_peer_id }
+                ).into());
+            }
+            handler::Event::CircuitReqAcceptFailed { circuit_id, dst_peer_id, error } => {
+                self.circuits.remove(&circuit_id);
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::CircuitReqAcceptFailed { src_peer_id: peer_id, dst_peer_id, error }
+                ).into());
+            }
+            handler::Event::CircuitClosed { circuit_id, dst_peer_id, error } => {
+                self.circuits.remove(&circuit_id);
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::CircuitClosed { src_peer_id: peer_id, dst_peer_id, error }
+                ).into());
+            }
+        }
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<handler::In, RelayEvent>>
+    {
+        match self.queued_actions.pop_front() {
+            Some(Action::Done(action)) => Poll::Ready(action),
+            Some(Action::AcceptReservation { peer_id, connection, inbound_reservation_req }) => {
+                let local_peer_id = *params.local_peer_id();
+                let addrs = params.external_addresses()
+                    .map(|r| r.addr.with(Protocol::P2p(local_peer_id.into())))
+                    .collect();
+                Poll::Ready(NetworkBehaviourAction::NotifyHandler {
+                    peer_id,
+                    handler: NotifyHandler::One(connection),
+                    event: handler::In::AcceptReservationReq { inbound_reservation_req, addrs },
+                })
+            }
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,418 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::copy_future::copy_with_limits;
+use crate::protocol::{inbound_hop, outbound_stop, Status, UpgradeError};
+use crate::relay::CircuitId;
+use futures::{future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::{connection::ConnectionId, Multiaddr, PeerId};
+use libp2p_swarm::{
+    KeepAlive,
+    NegotiatedSubstream,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+use void::Void;
+use wasm_timer::{Delay, Instant};
+
+/// The duration for which a connection without reservation or circuit is
+/// kept alive.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The limits of the relay applied by a [`Handler`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub reservation_duration: Duration,
+    pub max_circuit_duration: Duration,
+    pub max_circuit_bytes: u64,
+}
+
+/// Instruction of the [`Relay`](crate::Relay) behaviour to a [`Handler`].
+pub enum In {
+    AcceptReservationReq {
+        inbound_reservation_req: inbound_hop::ReservationReq,
+        addrs: Vec<Multiaddr>,
+    },
+    DenyReservationReq {
+        inbound_reservation_req: inbound_hop::ReservationReq,
+        status: Status,
+    },
+    DenyCircuitReq {
+        circuit_id: Option<CircuitId>,
+        inbound_circuit_req: inbound_hop::CircuitReq,
+        status: Status,
+    },
+    NegotiateOutboundConnect {
+        circuit_id: CircuitId,
+        inbound_circuit_req: inbound_hop::CircuitReq,
+        src_peer_id: PeerId,
+        src_connection_id: ConnectionId,
+    },
+    AcceptAndDriveCircuit {
+        circuit_id: CircuitId,
+        dst_peer_id: PeerId,
+        inbound_circuit_req: inbound_hop::CircuitReq,
+        dst_stream: NegotiatedSubstream,
+    },
+}
+
+impl fmt::Debug for In {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            In::AcceptReservationReq { addrs, .. } => f.debug_struct("In::AcceptReservationReq")
+                .field("addrs", addrs)
+                .finish(),
+            In::DenyReservationReq { status, .. } => f.debug_struct("In::DenyReservationReq")
+                .field("status", status)
+                .finish(),
+            In::DenyCircuitReq { circuit_id, status, .. } => f.debug_struct("In::DenyCircuitReq")
+                .field("circuit_id", circuit_id)
+                .field("status", status)
+                .finish(),
+            In::NegotiateOutboundConnect { circuit_id, src_peer_id, .. } =>
+                f.debug_struct("In::NegotiateOutboundConnect")
+                    .field("circuit_id", circuit_id)
+                    .field("src_peer_id", src_peer_id)
+                    .finish(),
+            In::AcceptAndDriveCircuit { circuit_id, dst_peer_id, .. } =>
+                f.debug_struct("In::AcceptAndDriveCircuit")
+                    .field("circuit_id", circuit_id)
+                    .field("dst_peer_id", dst_peer_id)
+                    .finish(),
+        }
+    }
+}
+
+/// Event produced by a [`Handler`] for the [`Relay`](crate::Relay) behaviour.
+pub enum Event {
+    /// The remote requested a reservation.
+    ReservationReqReceived {
+        inbound_reservation_req: inbound_hop::ReservationReq,
+    },
+    /// A reservation has been accepted.
+    ReservationReqAccepted { renewed: bool },
+    /// Accepting a reservation failed.
+    ReservationReqAcceptFailed { error: io::Error },
+    /// A reservation has been denied.
+    ReservationReqDenied,
+    /// The reservation of the remote has expired.
+    ReservationTimedOut,
+    /// The remote requested a circuit.
+    CircuitReqReceived {
+        inbound_circuit_req: inbound_hop::CircuitReq,
+    },
+    /// A circuit request of the remote has been denied.
+    CircuitReqDenied {
+        circuit_id: Option<CircuitId>,
+        dst_peer_id: PeerId,
+        status: Status,
+    },
+    /// A circuit request of the remote has been accepted.
+    CircuitReqAccepted {
+        circuit_id: CircuitId,
+        dst_peer_id: PeerId,
+    },
+    /// Accepting a circuit request of the remote failed.
+    CircuitReqAcceptFailed {
+        circuit_id: CircuitId,
+        dst_peer_id: PeerId,
+        error: io::Error,
+    },
+    /// The remote, being the destination of a circuit, accepted it.
+    OutboundConnectNegotiated {
+        circuit_id: CircuitId,
+        src_peer_id: PeerId,
+        src_connection_id: ConnectionId,
+        inbound_circuit_req: inbound_hop::CircuitReq,
+        dst_stream: NegotiatedSubstream,
+    },
+    /// The remote, being the destination of a circuit, did not accept it.
+    OutboundConnectNegotiationFailed {
+        circuit_id: CircuitId,
+        src_peer_id: PeerId,
+        src_connection_id: ConnectionId,
+        inbound_circuit_req: inbound_hop::CircuitReq,
+        status: Status,
+    },
+    /// A circuit of the remote has been closed.
+    CircuitClosed {
+        circuit_id: CircuitId,
+        dst_peer_id: PeerId,
+        error: Option<io::Error>,
+    },
+}
+
+/// Information attached to an outbound stop request.
+pub struct OutboundOpenInfo {
+    circuit_id: CircuitId,
+    inbound_circuit_req: inbound_hop::CircuitReq,
+    src_peer_id: PeerId,
+    src_connection_id: ConnectionId,
+}
+
+/// Protocols handler of the relay for a single connection.
+pub struct Handler {
+    config: Config,
+    /// Events to return from `poll`.
+    queued_events: VecDeque<ProtocolsHandlerEvent<outbound_stop::Upgrade, OutboundOpenInfo, Event, Void>>,
+    /// Expiry of the reservation of the remote, if any.
+    active_reservation: Option<Delay>,
+    /// Futures accepting or denying reservation requests.
+    reservation_futures: FuturesUnordered<BoxFuture<'static, Event>>,
+    /// Futures denying circuit requests.
+    circuit_deny_futures: FuturesUnordered<BoxFuture<'static, Event>>,
+    /// Futures accepting circuit requests, resolving to both ends of the circuit.
+    circuit_accept_futures: FuturesUnordered<
+        BoxFuture<'static, (CircuitId, PeerId, Result<(NegotiatedSubstream, NegotiatedSubstream), io::Error>)>
+    >,
+    /// Circuits relaying data.
+    circuits: FuturesUnordered<BoxFuture<'static, (CircuitId, PeerId, io::Result<()>)>>,
+    /// Number of pending outbound stop requests.
+    pending_outbound: usize,
+    /// Until when the connection is kept alive while idle.
+    idle_until: Instant,
+}
+
+impl Handler {
+    pub(crate) fn new(config: Config) -> Self {
+        Handler {
+            config,
+            queued_events: VecDeque::new(),
+            active_reservation: None,
+            reservation_futures: FuturesUnordered::new(),
+            circuit_deny_futures: FuturesUnordered::new(),
+            circuit_accept_futures: FuturesUnordered::new(),
+            circuits: FuturesUnordered::new(),
+            pending_outbound: 0,
+            idle_until: Instant::now() + IDLE_TIMEOUT,
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.active_reservation.is_none()
+            && self.reservation_futures.is_empty()
+            && self.circuit_deny_futures.is_empty()
+            && self.circuit_accept_futures.is_empty()
+            && self.circuits.is_empty()
+            && self.pending_outbound == 0
+    }
+}
+
+impl ProtocolsHandler for Handler {
+    type InEvent = In;
+    type OutEvent = Event;
+    type Error = Void;
+    type InboundProtocol = inbound_hop::Upgrade;
+    type OutboundProtocol = outbound_stop::Upgrade;
+    type OutboundOpenInfo = OutboundOpenInfo;
+    type InboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(inbound_hop::Upgrade {
+            reservation_duration: self.config.reservation_duration,
+            max_circuit_duration: self.config.max_circuit_duration,
+            max_circuit_bytes: self.config.max_circuit_bytes,
+        }, ())
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, req: inbound_hop::Req, (): ()) {
+        let event = match req {
+            inbound_hop::Req::Reserve(inbound_reservation_req) =>
+                Event::ReservationReqReceived { inbound_reservation_req },
+            inbound_hop::Req::Connect(inbound_circuit_req) =>
+                Event::CircuitReqReceived { inbound_circuit_req },
+        };
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(event));
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, dst_stream: NegotiatedSubstream, info: OutboundOpenInfo) {
+        self.pending_outbound -= 1;
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(Event::OutboundConnectNegotiated {
+            circuit_id: info.circuit_id,
+            src_peer_id: info.src_peer_id,
+            src_connection_id: info.src_connection_id,
+            inbound_circuit_req: info.inbound_circuit_req,
+            dst_stream,
+        }));
+    }
+
+    fn inject_event(&mut self, event: In) {
+        match event {
+            In::AcceptReservationReq { inbound_reservation_req, addrs } => {
+                let renewed = self.active_reservation.is_some();
+                // The reservation is considered active right away, so that
+                // the connection is kept alive. It is dropped again should
+                // accepting fail.
+                self.active_reservation = Some(Delay::new(self.config.reservation_duration));
+                self.reservation_futures.push(async move {
+                    match inbound_reservation_req.accept(addrs).await {
+                        Ok(()) => Event::ReservationReqAccepted { renewed },
+                        Err(error) => Event::ReservationReqAcceptFailed { error },
+                    }
+                }.boxed());
+            }
+            In::DenyReservationReq { inbound_reservation_req, status } => {
+                self.reservation_futures.push(async move {
+                    if let Err(e) = inbound_reservation_req.deny(status).await {
+                        log::debug!("Failed to deny reservation request: {:?}", e);
+                    }
+                    Event::ReservationReqDenied
+                }.boxed());
+            }
+            In::DenyCircuitReq { circuit_id, inbound_circuit_req, status } => {
+                let dst_peer_id = inbound_circuit_req.dst();
+                self.circuit_deny_futures.push(async move {
+                    if let Err(e) = inbound_circuit_req.deny(status).await {
+                        log::debug!("Failed to deny circuit request: {:?}", e);
+                    }
+                    Event::CircuitReqDenied { circuit_id, dst_peer_id, status }
+                }.boxed());
+            }
+            In::NegotiateOutboundConnect { circuit_id, inbound_circuit_req, src_peer_id, src_connection_id } => {
+                self.pending_outbound += 1;
+                self.queued_events.push_back(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(outbound_stop::Upgrade {
+                        src_peer_id,
+                        max_circuit_duration: self.config.max_circuit_duration,
+                        max_circuit_bytes: self.config.max_circuit_bytes,
+                    }, OutboundOpenInfo {
+                        circuit_id,
+                        inbound_circuit_req,
+                        src_peer_id,
+                        src_connection_id,
+                    }),
+                });
+            }
+            In::AcceptAndDriveCircuit { circuit_id, dst_peer_id, inbound_circuit_req, dst_stream } => {
+                self.circuit_accept_futures.push(async move {
+                    let result = inbound_circuit_req.accept().await
+                        .map(|src_stream| (src_stream, dst_stream));
+                    (circuit_id, dst_peer_id, result)
+                }.boxed());
+            }
+        }
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        info: OutboundOpenInfo,
+        error: ProtocolsHandlerUpgrErr<UpgradeError>,
+    ) {
+        self.pending_outbound -= 1;
+        let status = match error {
+            ProtocolsHandlerUpgrErr::Upgrade(libp2p_core::upgrade::UpgradeError::Apply(
+                UpgradeError::Refused(Status::ResourceLimitExceeded)
+            )) => Status::ResourceLimitExceeded,
+            e => {
+                log::debug!("Failed to open circuit to destination: {:?}", e);
+                Status::ConnectionFailed
+            }
+        };
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(Event::OutboundConnectNegotiationFailed {
+            circuit_id: info.circuit_id,
+            src_peer_id: info.src_peer_id,
+            src_connection_id: info.src_connection_id,
+            inbound_circuit_req: info.inbound_circuit_req,
+            status,
+        }));
+    }
+
+    fn inject_listen_upgrade_error(&mut self, _: (), error: ProtocolsHandlerUpgrErr<UpgradeError>) {
+        log::debug!("Inbound hop request failed: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        if self.is_idle() {
+            KeepAlive::Until(self.idle_until)
+        } else {
+            KeepAlive::Yes
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event)
+        }
+
+        if let Some(expiry) = self.active_reservation.as_mut() {
+            if expiry.poll_unpin(cx).is_ready() {
+                self.active_reservation = None;
+                return Poll::Ready(ProtocolsHandlerEvent::Custom(Event::ReservationTimedOut))
+            }
+        }
+
+        if let Poll::Ready(Some(event)) = self.reservation_futures.poll_next_unpin(cx) {
+            if let Event::ReservationReqAcceptFailed { .. } = event {
+                self.active_reservation = None;
+            }
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(event))
+        }
+
+        if let Poll::Ready(Some(event)) = self.circuit_deny_futures.poll_next_unpin(cx) {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(event))
+        }
+
+        if let Poll::Ready(Some((circuit_id, dst_peer_id, result))) = self.circuit_accept_futures.poll_next_unpin(cx) {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(match result {
+                Ok((src_stream, dst_stream)) => {
+                    let max_circuit_duration = self.config.max_circuit_duration;
+                    let max_circuit_bytes = self.config.max_circuit_bytes;
+                    self.circuits.push(async move {
+                        let result = copy_with_limits(
+                            src_stream,
+                            dst_stream,
+                            max_circuit_duration,
+                            max_circuit_bytes,
+                        ).await;
+                        (circuit_id, dst_peer_id, result)
+                    }.boxed());
+                    Event::CircuitReqAccepted { circuit_id, dst_peer_id }
+                }
+                Err(error) => Event::CircuitReqAcceptFailed { circuit_id, dst_peer_id, error },
+            }))
+        }
+
+        if let Poll::Ready(Some((circuit_id, dst_peer_id, result))) = self.circuits.poll_next_unpin(cx) {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(Event::CircuitClosed {
+                circuit_id,
+                dst_peer_id,
+                error: result.err(),
+            }))
+        }
+
+        if !self.is_idle() {
+            self.idle_until = Instant::now() + IDLE_TIMEOUT;
+        }
+
+        Poll::Pending
+    }
+}
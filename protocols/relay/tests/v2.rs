@@ -0,0 +1,148 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Integration tests for the circuit relay v2 behaviours.
+
+use futures::executor::block_on;
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{self, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_relay::{Client, ClientEvent, Relay, RelayConfig, RelayEvent};
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+
+#[test]
+fn reservation_and_relayed_connection() {
+    let relay_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let (relay_peer_id, mut relay) = build_relay(RelayConfig::default());
+    Swarm::listen_on(&mut relay, relay_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop { relay.next_event().await; }
+    });
+
+    let relayed_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id.into()))
+        .with(Protocol::P2pCircuit);
+
+    let (dst_peer_id, mut dst) = build_client();
+    Swarm::listen_on(&mut dst, relayed_addr.clone()).unwrap();
+    block_on(async {
+        loop {
+            match dst.next_event().await {
+                SwarmEvent::NewListenAddr(addr) if addr == relayed_addr => break,
+                SwarmEvent::Behaviour(ClientEvent::ReservationReqFailed { .. }) =>
+                    panic!("Reservation refused"),
+                _ => {}
+            }
+        }
+    });
+    async_std::task::spawn(async move {
+        loop { dst.next_event().await; }
+    });
+
+    let (_, mut src) = build_client();
+    Swarm::dial_addr(&mut src, relayed_addr.with(Protocol::P2p(dst_peer_id.into()))).unwrap();
+    block_on(async {
+        loop {
+            match src.next_event().await {
+                SwarmEvent::ConnectionEstablished { peer_id, .. } if peer_id == dst_peer_id => break,
+                SwarmEvent::Behaviour(ClientEvent::OutboundCircuitReqFailed { .. }) =>
+                    panic!("Circuit refused"),
+                _ => {}
+            }
+        }
+    });
+}
+
+#[test]
+fn reservation_limit_per_peer() {
+    let mut config = RelayConfig::default();
+    config.set_max_reservations_per_peer(0);
+
+    let relay_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let (relay_peer_id, mut relay) = build_relay(config);
+    Swarm::listen_on(&mut relay, relay_addr.clone()).unwrap();
+
+    let (_, mut client) = build_client();
+    let relayed_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id.into()))
+        .with(Protocol::P2pCircuit);
+    Swarm::listen_on(&mut client, relayed_addr).unwrap();
+    async_std::task::spawn(async move {
+        loop { client.next_event().await; }
+    });
+
+    block_on(async {
+        loop {
+            match relay.next_event().await {
+                SwarmEvent::Behaviour(RelayEvent::ReservationReqDenied { .. }) => break,
+                SwarmEvent::Behaviour(RelayEvent::ReservationReqAccepted { .. }) =>
+                    panic!("Reservation accepted beyond the limit"),
+                _ => {}
+            }
+        }
+    });
+}
+
+fn build_relay(config: RelayConfig) -> (PeerId, Swarm<Relay>) {
+    let local_public_key = identity::Keypair::generate_ed25519().public();
+    let peer_id = local_public_key.clone().into_peer_id();
+
+    let transport = upgrade_transport(MemoryTransport::default(), local_public_key);
+
+    (peer_id, Swarm::new(transport, Relay::new(config), peer_id))
+}
+
+fn build_client() -> (PeerId, Swarm<Client>) {
+    let local_public_key = identity::Keypair::generate_ed25519().public();
+    let peer_id = local_public_key.clone().into_peer_id();
+
+    let (relay_transport, behaviour) = Client::new_transport_and_behaviour();
+    let transport = upgrade_transport(
+        MemoryTransport::default().or_transport(relay_transport),
+        local_public_key,
+    );
+
+    (peer_id, Swarm::new(transport, behaviour, peer_id))
+}
+
+fn upgrade_transport<T>(transport: T, local_public_key: identity::PublicKey)
+    -> transport::Boxed<(PeerId, StreamMuxerBox)>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: futures::AsyncRead + futures::AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+{
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(yamux::YamuxConfig::default())
+        .boxed()
+}
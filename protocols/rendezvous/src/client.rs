@@ -0,0 +1,396 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! The rendezvous client, registering the local node and discovering others.
+
+use crate::codec::{
+    Cookie,
+    ErrorCode,
+    Message,
+    Namespace,
+    NewRegistration,
+    Registration,
+    RendezvousCodec,
+    RendezvousProtocol,
+    Ttl,
+};
+use crate::record::SignedPeerRecord;
+use futures::{future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::{
+    connection::ConnectionId,
+    identity::{error::SigningError, Keypair},
+    ConnectedPoint,
+    Multiaddr,
+    PeerId,
+};
+use libp2p_request_response::{
+    handler::{RequestProtocol, RequestResponseHandler, RequestResponseHandlerEvent},
+    OutboundFailure,
+    ProtocolSupport,
+    RequestId,
+    RequestResponse,
+    RequestResponseConfig,
+    RequestResponseEvent,
+    RequestResponseMessage,
+};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters};
+use std::{
+    collections::{HashMap, VecDeque},
+    error,
+    fmt,
+    iter,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::{Delay, Instant};
+
+/// Event that can be produced by the [`Client`] behaviour.
+#[derive(Debug)]
+pub enum ClientEvent {
+    /// The local node has been registered at a rendezvous point.
+    Registered {
+        rendezvous_node: PeerId,
+        namespace: Namespace,
+        ttl: Ttl,
+    },
+    /// Registering the local node at a rendezvous point failed.
+    RegisterFailed {
+        rendezvous_node: PeerId,
+        namespace: Namespace,
+        error: ClientError,
+    },
+    /// Registrations have been discovered at a rendezvous point.
+    ///
+    /// The addresses of the registered peers are reported by
+    /// [`NetworkBehaviour::addresses_of_peer`] until their registration
+    /// expires, hence the peers can be dialed by their [`PeerId`].
+    Discovered {
+        rendezvous_node: PeerId,
+        registrations: Vec<Registration>,
+        /// The cookie to pass to the next discovery to only discover new
+        /// registrations.
+        cookie: Cookie,
+    },
+    /// A discovery at a rendezvous point failed.
+    DiscoverFailed {
+        rendezvous_node: PeerId,
+        namespace: Option<Namespace>,
+        error: ClientError,
+    },
+    /// The registrations of a discovered peer have all expired, hence its
+    /// addresses are no longer reported.
+    Expired {
+        peer: PeerId,
+    },
+}
+
+/// The reason a registration or discovery failed.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The local node has no external addresses to register.
+    NoExternalAddresses,
+    /// Signing the peer record of the local node failed.
+    Signing(SigningError),
+    /// The rendezvous point refused the request.
+    Remote(ErrorCode),
+    /// The rendezvous point answered with an unexpected message.
+    UnexpectedResponse,
+    /// The request could not be sent or was not answered.
+    Outbound(OutboundFailure),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::NoExternalAddresses => f.write_str("No external addresses to register"),
+            ClientError::Signing(e) => write!(f, "Failed to sign peer record: {}", e),
+            ClientError::Remote(e) => write!(f, "Refused by rendezvous point: {}", e),
+            ClientError::UnexpectedResponse => f.write_str("Unexpected response of rendezvous point"),
+            ClientError::Outbound(e) => write!(f, "Request failed: {:?}", e),
+        }
+    }
+}
+
+impl error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ClientError::Signing(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A request awaiting its response.
+enum PendingRequest {
+    Register(Namespace),
+    Discover(Option<Namespace>),
+}
+
+/// A [`NetworkBehaviour`] registering the local node at rendezvous points
+/// and discovering other peers registered there.
+///
+/// The local node registers with its external addresses, see
+/// `Swarm::add_external_address`, signed with its identity keypair.
+pub struct Client {
+    inner: RequestResponse<RendezvousCodec>,
+    keypair: Keypair,
+    /// Registrations waiting for the external addresses of the local node,
+    /// which are only known in `poll`.
+    pending_registrations: VecDeque<(PeerId, Namespace, Option<Ttl>)>,
+    pending_requests: HashMap<RequestId, PendingRequest>,
+    /// The addresses of discovered peers by namespace, with the expiry of
+    /// their registration.
+    discovered: HashMap<PeerId, HashMap<Namespace, (Vec<Multiaddr>, Instant)>>,
+    /// Timers of the discovered registrations.
+    expiring: FuturesUnordered<BoxFuture<'static, (PeerId, Namespace)>>,
+    events: VecDeque<ClientEvent>,
+}
+
+impl Client {
+    /// Creates a new rendezvous client, signing the registrations with the
+    /// given identity keypair of the local node.
+    pub fn new(keypair: Keypair) -> Self {
+        Client {
+            inner: RequestResponse::new(
+                RendezvousCodec,
+                iter::once((RendezvousProtocol, ProtocolSupport::Outbound)),
+                RequestResponseConfig::default(),
+            ),
+            keypair,
+            pending_registrations: VecDeque::new(),
+            pending_requests: HashMap::new(),
+            discovered: HashMap::new(),
+            expiring: FuturesUnordered::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Adds a known address of a rendezvous point.
+    pub fn add_address(&mut self, rendezvous_node: &PeerId, address: Multiaddr) {
+        self.inner.add_address(rendezvous_node, address)
+    }
+
+    /// Registers the local node in a namespace at a rendezvous point.
+    ///
+    /// The registration lasts for the given time to live, or the default
+    /// of the rendezvous point, and has to be renewed by registering again.
+    pub fn register(&mut self, namespace: Namespace, rendezvous_node: PeerId, ttl: Option<Ttl>) {
+        self.pending_registrations.push_back((rendezvous_node, namespace, ttl));
+    }
+
+    /// Unregisters the local node from a namespace at a rendezvous point.
+    pub fn unregister(&mut self, namespace: Namespace, rendezvous_node: PeerId) {
+        self.pending_registrations.retain(|(node, ns, _)| *node != rendezvous_node || *ns != namespace);
+        self.inner.send_request(&rendezvous_node, Message::Unregister(namespace));
+    }
+
+    /// Discovers the peers registered at a rendezvous point, in the given
+    /// namespace or in all namespaces.
+    ///
+    /// With the cookie of a previous discovery, only registrations that are
+    /// new since are returned.
+    pub fn discover(
+        &mut self,
+        namespace: Option<Namespace>,
+        cookie: Option<Cookie>,
+        limit: Option<u64>,
+        rendezvous_node: PeerId,
+    ) {
+        let request = Message::Discover { namespace: namespace.clone(), cookie, limit };
+        let request_id = self.inner.send_request(&rendezvous_node, request);
+        self.pending_requests.insert(request_id, PendingRequest::Discover(namespace));
+    }
+
+    fn send_registration(&mut self, rendezvous_node: PeerId, namespace: Namespace, ttl: Option<Ttl>, addresses: Vec<Multiaddr>) {
+        if addresses.is_empty() {
+            let error = ClientError::NoExternalAddresses;
+            self.events.push_back(ClientEvent::RegisterFailed { rendezvous_node, namespace, error });
+            return
+        }
+
+        let record = match SignedPeerRecord::new(&self.keypair, addresses) {
+            Ok(record) => record,
+            Err(e) => {
+                let error = ClientError::Signing(e);
+                self.events.push_back(ClientEvent::RegisterFailed { rendezvous_node, namespace, error });
+                return
+            }
+        };
+
+        let request = Message::Register(NewRegistration { namespace: namespace.clone(), record, ttl });
+        let request_id = self.inner.send_request(&rendezvous_node, request);
+        self.pending_requests.insert(request_id, PendingRequest::Register(namespace));
+    }
+
+    fn on_discovered(&mut self, registrations: &[Registration]) {
+        let now = Instant::now();
+        for registration in registrations {
+            let peer = registration.record.peer_id();
+            let ttl = Duration::from_secs(registration.ttl);
+            let namespace = registration.namespace.clone();
+            self.discovered
+                .entry(peer)
+                .or_default()
+                .insert(namespace.clone(), (registration.record.addresses().to_vec(), now + ttl));
+            self.expiring.push(Delay::new(ttl).map(move |_| (peer, namespace)).boxed());
+        }
+    }
+
+    fn on_response(&mut self, rendezvous_node: PeerId, request_id: RequestId, response: Message) {
+        let event = match (self.pending_requests.remove(&request_id), response) {
+            (Some(PendingRequest::Register(namespace)), Message::RegisterResponse(Ok(ttl))) =>
+                ClientEvent::Registered { rendezvous_node, namespace, ttl },
+            (Some(PendingRequest::Register(namespace)), Message::RegisterResponse(Err(error))) =>
+                ClientEvent::RegisterFailed { rendezvous_node, namespace, error: ClientError::Remote(error) },
+            (Some(PendingRequest::Register(namespace)), _) =>
+                ClientEvent::RegisterFailed { rendezvous_node, namespace, error: ClientError::UnexpectedResponse },
+            (Some(PendingRequest::Discover(_)), Message::DiscoverResponse(Ok((registrations, cookie)))) => {
+                self.on_discovered(&registrations);
+                ClientEvent::Discovered { rendezvous_node, registrations, cookie }
+            }
+            (Some(PendingRequest::Discover(namespace)), Message::DiscoverResponse(Err(error))) =>
+                ClientEvent::DiscoverFailed { rendezvous_node, namespace, error: ClientError::Remote(error) },
+            (Some(PendingRequest::Discover(namespace)), _) =>
+                ClientEvent::DiscoverFailed { rendezvous_node, namespace, error: ClientError::UnexpectedResponse },
+            (None, _) => return,
+        };
+        self.events.push_back(event);
+    }
+
+    fn on_outbound_failure(&mut self, rendezvous_node: PeerId, request_id: RequestId, error: OutboundFailure) {
+        // Failures of unregistrations, which have no response, are not tracked.
+        let event = match self.pending_requests.remove(&request_id) {
+            Some(PendingRequest::Register(namespace)) =>
+                ClientEvent::RegisterFailed { rendezvous_node, namespace, error: ClientError::Outbound(error) },
+            Some(PendingRequest::Discover(namespace)) =>
+                ClientEvent::DiscoverFailed { rendezvous_node, namespace, error: ClientError::Outbound(error) },
+            None => return,
+        };
+        self.events.push_back(event);
+    }
+}
+
+impl NetworkBehaviour for Client {
+    type ProtocolsHandler = RequestResponseHandler<RendezvousCodec>;
+    type OutEvent = ClientEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.inner.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        let mut addresses = self.inner.addresses_of_peer(peer);
+        let now = Instant::now();
+        if let Some(namespaces) = self.discovered.get(peer) {
+            for (addrs, expires) in namespaces.values() {
+                if *expires <= now {
+                    continue
+                }
+                for addr in addrs {
+                    if !addresses.contains(addr) {
+                        addresses.push(addr.clone());
+                    }
+                }
+            }
+        }
+        addresses
+    }
+
+    fn inject_connected(&mut self, peer: &PeerId) {
+        self.inner.inject_connected(peer)
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.inner.inject_disconnected(peer)
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, id: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.inner.inject_connection_established(peer, id, endpoint)
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, id: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.inner.inject_connection_closed(peer, id, endpoint)
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        self.inner.inject_dial_failure(peer)
+    }
+
+    fn inject_event(&mut self, peer: PeerId, id: ConnectionId, event: RequestResponseHandlerEvent<RendezvousCodec>) {
+        self.inner.inject_event(peer, id, event)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<RequestProtocol<RendezvousCodec>, ClientEvent>>
+    {
+        if !self.pending_registrations.is_empty() {
+            let addresses = params.external_addresses().map(|r| r.addr).collect::<Vec<_>>();
+            while let Some((rendezvous_node, namespace, ttl)) = self.pending_registrations.pop_front() {
+                self.send_registration(rendezvous_node, namespace, ttl, addresses.clone());
+            }
+        }
+
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event))
+            }
+
+            while let Poll::Ready(Some((peer, namespace))) = self.expiring.poll_next_unpin(cx) {
+                let now = Instant::now();
+                let expired = match self.discovered.get_mut(&peer) {
+                    Some(namespaces) => {
+                        // The registration may have been discovered again since.
+                        if namespaces.get(&namespace).map_or(false, |(_, expires)| *expires <= now) {
+                            namespaces.remove(&namespace);
+                        }
+                        namespaces.is_empty()
+                    }
+                    None => false,
+                };
+                if expired {
+                    self.discovered.remove(&peer);
+                    self.events.push_back(ClientEvent::Expired { peer });
+                }
+            }
+            if !self.events.is_empty() {
+                continue
+            }
+
+            match self.inner.poll(cx, params) {
+                Poll::Ready(NetworkBehaviourAction::GenerateEvent(event)) => match event {
+                    RequestResponseEvent::Message {
+                        peer,
+                        message: RequestResponseMessage::Response { request_id, response },
+                    } => self.on_response(peer, request_id, response),
+                    RequestResponseEvent::OutboundFailure { peer, request_id, error } =>
+                        self.on_outbound_failure(peer, request_id, error),
+                    RequestResponseEvent::Message { .. }
+                    | RequestResponseEvent::InboundFailure { .. }
+                    | RequestResponseEvent::ResponseSent { .. } => {}
+                },
+                Poll::Ready(action) => return Poll::Ready(action.map_out(|_| {
+                    unreachable!("`GenerateEvent` is handled above.")
+                })),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
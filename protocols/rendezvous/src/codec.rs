@@ -0,0 +1,471 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! The messages of the rendezvous protocol and their encoding.
+
+use crate::{message_proto, record::SignedPeerRecord};
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p_core::{upgrade::{self, ReadOneError}, ProtocolName};
+use libp2p_request_response::RequestResponseCodec;
+use prost::Message as _;
+use std::{convert::TryInto, fmt, io};
+
+use crate::message_proto::message::{MessageType, ResponseStatus};
+
+/// Protocol name of the rendezvous protocol.
+pub const PROTOCOL_NAME: &[u8] = b"/rendezvous/1.0.0";
+
+/// The maximum size of a rendezvous message.
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// The time to live of a registration in seconds.
+pub type Ttl = u64;
+
+/// The time to live of a registration if the registering peer requests none.
+pub const DEFAULT_TTL: Ttl = 60 * 60 * 2;
+
+/// The namespace peers register under and discover each other in.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Namespace(String);
+
+impl Namespace {
+    /// The maximum length of a namespace in bytes.
+    pub const MAX_LEN: usize = 255;
+
+    /// Creates a new namespace.
+    ///
+    /// Returns `None` if the namespace is empty or longer than [`Namespace::MAX_LEN`].
+    pub fn new(ns: impl Into<String>) -> Option<Self> {
+        let ns = ns.into();
+        if ns.is_empty() || ns.len() > Self::MAX_LEN {
+            return None
+        }
+        Some(Namespace(ns))
+    }
+
+    /// Returns the namespace as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The position of a discovery in the registrations of a rendezvous point.
+///
+/// Passing the cookie returned with the registrations of a discovery to the
+/// next discovery only returns registrations that are new since then,
+/// allowing to page through the registrations or to poll for new ones.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Cookie {
+    id: u64,
+    namespace: Option<Namespace>,
+}
+
+impl Cookie {
+    pub(crate) fn new(id: u64, namespace: Option<Namespace>) -> Self {
+        Cookie { id, namespace }
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The namespace of the discovery the cookie was returned for, `None`
+    /// if all namespaces were discovered.
+    pub fn namespace(&self) -> Option<&Namespace> {
+        self.namespace.as_ref()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.id.to_be_bytes().to_vec();
+        if let Some(ns) = &self.namespace {
+            bytes.extend_from_slice(ns.as_str().as_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None
+        }
+        let id = u64::from_be_bytes(bytes[..8].try_into().expect("Slice has 8 bytes."));
+        let namespace = if bytes.len() > 8 {
+            Some(Namespace::new(String::from_utf8(bytes[8..].to_vec()).ok()?)?)
+        } else {
+            None
+        };
+        Some(Cookie { id, namespace })
+    }
+}
+
+/// The reason a rendezvous point refused a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidNamespace,
+    InvalidSignedPeerRecord,
+    InvalidTtl,
+    InvalidCookie,
+    NotAuthorized,
+    InternalError,
+    Unavailable,
+}
+
+impl ErrorCode {
+    fn from_proto(status: ResponseStatus) -> Option<Self> {
+        match status {
+            ResponseStatus::Ok => None,
+            ResponseStatus::EInvalidNamespace => Some(ErrorCode::InvalidNamespace),
+            ResponseStatus::EInvalidSignedPeerRecord => Some(ErrorCode::InvalidSignedPeerRecord),
+            ResponseStatus::EInvalidTtl => Some(ErrorCode::InvalidTtl),
+            ResponseStatus::EInvalidCookie => Some(ErrorCode::InvalidCookie),
+            ResponseStatus::ENotAuthorized => Some(ErrorCode::NotAuthorized),
+            ResponseStatus::EInternalError => Some(ErrorCode::InternalError),
+            ResponseStatus::EUnavailable => Some(ErrorCode::Unavailable),
+        }
+    }
+
+    fn to_proto(self) -> ResponseStatus {
+        match self {
+            ErrorCode::InvalidNamespace => ResponseStatus::EInvalidNamespace,
+            ErrorCode::InvalidSignedPeerRecord => ResponseStatus::EInvalidSignedPeerRecord,
+            ErrorCode::InvalidTtl => ResponseStatus::EInvalidTtl,
+            ErrorCode::InvalidCookie => ResponseStatus::EInvalidCookie,
+            ErrorCode::NotAuthorized => ResponseStatus::ENotAuthorized,
+            ErrorCode::InternalError => ResponseStatus::EInternalError,
+            ErrorCode::Unavailable => ResponseStatus::EUnavailable,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCode::InvalidNamespace => f.write_str("Invalid namespace"),
+            ErrorCode::InvalidSignedPeerRecord => f.write_str("Invalid signed peer record"),
+            ErrorCode::InvalidTtl => f.write_str("Invalid TTL"),
+            ErrorCode::InvalidCookie => f.write_str("Invalid cookie"),
+            ErrorCode::NotAuthorized => f.write_str("Not authorized"),
+            ErrorCode::InternalError => f.write_str("Internal error"),
+            ErrorCode::Unavailable => f.write_str("Unavailable"),
+        }
+    }
+}
+
+/// A registration requested by a peer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NewRegistration {
+    pub namespace: Namespace,
+    pub record: SignedPeerRecord,
+    /// The requested time to live, the default of the rendezvous point if `None`.
+    pub ttl: Option<Ttl>,
+}
+
+/// A registration of a peer at a rendezvous point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registration {
+    pub namespace: Namespace,
+    pub record: SignedPeerRecord,
+    /// The remaining time to live in seconds.
+    pub ttl: Ttl,
+}
+
+/// A message of the rendezvous protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Register(NewRegistration),
+    RegisterResponse(Result<Ttl, ErrorCode>),
+    Unregister(Namespace),
+    Discover {
+        namespace: Option<Namespace>,
+        cookie: Option<Cookie>,
+        limit: Option<u64>,
+    },
+    DiscoverResponse(Result<(Vec<Registration>, Cookie), ErrorCode>),
+}
+
+impl Message {
+    fn into_proto(self) -> message_proto::Message {
+        let mut msg = message_proto::Message::default();
+        match self {
+            Message::Register(registration) => {
+                msg.set_type(MessageType::Register);
+                msg.register = Some(message_proto::message::Register {
+                    ns: Some(registration.namespace.0),
+                    signed_peer_record: Some(registration.record.to_bytes()),
+                    ttl: registration.ttl,
+                });
+            }
+            Message::RegisterResponse(result) => {
+                msg.set_type(MessageType::RegisterResponse);
+                let mut response = message_proto::message::RegisterResponse::default();
+                match result {
+                    Ok(ttl) => {
+                        response.set_status(ResponseStatus::Ok);
+                        response.ttl = Some(ttl);
+                    }
+                    Err(error) => {
+                        response.set_status(error.to_proto());
+                        response.status_text = Some(error.to_string());
+                    }
+                }
+                msg.register_response = Some(response);
+            }
+            Message::Unregister(namespace) => {
+                msg.set_type(MessageType::Unregister);
+                msg.unregister = Some(message_proto::message::Unregister {
+                    ns: Some(namespace.0),
+                    id: None,
+                });
+            }
+            Message::Discover { namespace, cookie, limit } => {
+                msg.set_type(MessageType::Discover);
+                msg.discover = Some(message_proto::message::Discover {
+                    ns: namespace.map(|ns| ns.0),
+                    limit,
+                    cookie: cookie.map(|c| c.to_bytes()),
+                });
+            }
+            Message::DiscoverResponse(result) => {
+                msg.set_type(MessageType::DiscoverResponse);
+                let mut response = message_proto::message::DiscoverResponse::default();
+                match result {
+                    Ok((registrations, cookie)) => {
+                        response.set_status(ResponseStatus::Ok);
+                        response.registrations = registrations
+                            .into_iter()
+                            .map(|r| message_proto::message::Register {
+                                ns: Some(r.namespace.0),
+                                signed_peer_record: Some(r.record.to_bytes()),
+                                ttl: Some(r.ttl),
+                            })
+                            .collect();
+                        response.cookie = Some(cookie.to_bytes());
+                    }
+                    Err(error) => {
+                        response.set_status(error.to_proto());
+                        response.status_text = Some(error.to_string());
+                    }
+                }
+                msg.discover_response = Some(response);
+            }
+        }
+        msg
+    }
+
+    fn from_proto(msg: message_proto::Message) -> Result<Self, String> {
+        fn namespace(ns: Option<String>) -> Result<Namespace, String> {
+            ns.and_then(Namespace::new).ok_or_else(|| "Invalid namespace".to_string())
+        }
+
+        fn record(bytes: Option<Vec<u8>>) -> Result<SignedPeerRecord, String> {
+            let bytes = bytes.ok_or_else(|| "Missing signed peer record".to_string())?;
+            SignedPeerRecord::from_bytes(&bytes).map_err(|e| e.to_string())
+        }
+
+        let r#type = MessageType::from_i32(msg.r#type.unwrap_or_default())
+            .ok_or_else(|| "Unknown message type".to_string())?;
+        let message = match r#type {
+            MessageType::Register => {
+                let register = msg.register.ok_or_else(|| "Missing register".to_string())?;
+                Message::Register(NewRegistration {
+                    namespace: namespace(register.ns)?,
+                    record: record(register.signed_peer_record)?,
+                    ttl: register.ttl,
+                })
+            }
+            MessageType::RegisterResponse => {
+                let response = msg.register_response
+                    .ok_or_else(|| "Missing register response".to_string())?;
+                match ErrorCode::from_proto(response.status()) {
+                    None => Message::RegisterResponse(Ok(
+                        response.ttl.ok_or_else(|| "Missing TTL".to_string())?
+                    )),
+                    Some(error) => Message::RegisterResponse(Err(error)),
+                }
+            }
+            MessageType::Unregister => {
+                let unregister = msg.unregister.ok_or_else(|| "Missing unregister".to_string())?;
+                Message::Unregister(namespace(unregister.ns)?)
+            }
+            MessageType::Discover => {
+                let discover = msg.discover.ok_or_else(|| "Missing discover".to_string())?;
+                Message::Discover {
+                    namespace: discover.ns.map(|ns| namespace(Some(ns))).transpose()?,
+                    cookie: discover.cookie
+                        .map(|c| Cookie::from_bytes(&c).ok_or_else(|| "Invalid cookie".to_string()))
+                        .transpose()?,
+                    limit: discover.limit,
+                }
+            }
+            MessageType::DiscoverResponse => {
+                let response = msg.discover_response
+                    .ok_or_else(|| "Missing discover response".to_string())?;
+                match ErrorCode::from_proto(response.status()) {
+                    None => {
+                        let registrations = response.registrations
+                            .into_iter()
+                            .map(|r| -> Result<_, String> {
+                                Ok(Registration {
+                                    namespace: namespace(r.ns)?,
+                                    record: record(r.signed_peer_record)?,
+                                    ttl: r.ttl.ok_or_else(|| "Missing TTL".to_string())?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, String>>()?;
+                        let cookie = response.cookie
+                            .as_deref()
+                            .and_then(Cookie::from_bytes)
+                            .ok_or_else(|| "Invalid cookie".to_string())?;
+                        Message::DiscoverResponse(Ok((registrations, cookie)))
+                    }
+                    Some(error) => Message::DiscoverResponse(Err(error)),
+                }
+            }
+        };
+        Ok(message)
+    }
+}
+
+/// The name of the rendezvous protocol.
+#[derive(Debug, Clone)]
+pub struct RendezvousProtocol;
+
+impl ProtocolName for RendezvousProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL_NAME
+    }
+}
+
+/// Encoding of rendezvous messages as request and responses.
+///
+/// > **Note**: An `Unregister` request has no response. The remote closes
+/// > the substream instead, failing the outbound request.
+#[derive(Debug, Clone, Default)]
+pub struct RendezvousCodec;
+
+#[async_trait]
+impl RequestResponseCodec for RendezvousCodec {
+    type Protocol = RendezvousProtocol;
+    type Request = Message;
+    type Response = Message;
+
+    async fn read_request<T>(&mut self, _: &RendezvousProtocol, io: &mut T) -> io::Result<Message>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        read_message(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &RendezvousProtocol, io: &mut T) -> io::Result<Message>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        read_message(io).await
+    }
+
+    async fn write_request<T>(&mut self, _: &RendezvousProtocol, io: &mut T, req: Message) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        write_message(io, req).await
+    }
+
+    async fn write_response<T>(&mut self, _: &RendezvousProtocol, io: &mut T, res: Message) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        write_message(io, res).await
+    }
+}
+
+async fn read_message<T>(io: &mut T) -> io::Result<Message>
+where
+    T: AsyncRead + Unpin + Send
+{
+    let bytes = upgrade::read_one(io, MAX_MESSAGE_SIZE).await.map_err(|e| match e {
+        ReadOneError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    })?;
+    let msg = message_proto::Message::decode(&bytes[..])
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Message::from_proto(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_message<T>(io: &mut T, message: Message) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send
+{
+    let msg = message.into_proto();
+    let mut bytes = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+    upgrade::write_with_len_prefix(io, bytes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::identity;
+
+    fn roundtrip(message: Message) {
+        let decoded = Message::from_proto(message.clone().into_proto()).unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let record = SignedPeerRecord::new(&keypair, vec!["/ip4/1.2.3.4/tcp/4001".parse().unwrap()]).unwrap();
+        let namespace = Namespace::new("mwc").unwrap();
+
+        roundtrip(Message::Register(NewRegistration {
+            namespace: namespace.clone(),
+            record: record.clone(),
+            ttl: Some(7200),
+        }));
+        roundtrip(Message::RegisterResponse(Ok(7200)));
+        roundtrip(Message::RegisterResponse(Err(ErrorCode::InvalidTtl)));
+        roundtrip(Message::Unregister(namespace.clone()));
+        roundtrip(Message::Discover {
+            namespace: Some(namespace.clone()),
+            cookie: Some(Cookie::new(42, Some(namespace.clone()))),
+            limit: Some(10),
+        });
+        roundtrip(Message::Discover { namespace: None, cookie: None, limit: None });
+        roundtrip(Message::DiscoverResponse(Ok((
+            vec![Registration { namespace, record, ttl: 3600 }],
+            Cookie::new(1, None),
+        ))));
+        roundtrip(Message::DiscoverResponse(Err(ErrorCode::InvalidCookie)));
+    }
+
+    #[test]
+    fn namespace_length() {
+        assert!(Namespace::new("").is_none());
+        assert!(Namespace::new("a".repeat(Namespace::MAX_LEN)).is_some());
+        assert!(Namespace::new("a".repeat(Namespace::MAX_LEN + 1)).is_none());
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Implementation of the [rendezvous] protocol.
+//!
+//! A rendezvous point is a node that peers register at in order to be
+//! discovered by other peers registered there, without relying on
+//! multicast, like mDNS, or on a public DHT, like Kademlia. This makes it
+//! suitable for networks of nodes only reachable through Tor, where only
+//! the rendezvous points need to be known in advance.
+//!
+//! # Usage
+//!
+//! The [`Server`] behaviour implements the rendezvous point. It stores
+//! registrations within the limits of its [`ServerConfig`] until their time
+//! to live elapses.
+//!
+//! The [`Client`] behaviour registers the local node in a [`Namespace`] at
+//! a rendezvous point with [`Client::register`], using the external
+//! addresses of the local node, and discovers the peers registered in a
+//! namespace with [`Client::discover`]. The addresses of discovered peers
+//! are reported by [`Client`] when dialing them by [`PeerId`], until their
+//! registration expires.
+//!
+//! Registrations carry a [`SignedPeerRecord`], i.e. the addresses of the
+//! registered peer signed with its identity keypair. Neither a rendezvous
+//! point nor another peer can hence forge or alter them.
+//!
+//! [rendezvous]: https://github.com/libp2p/specs/blob/master/rendezvous/README.md
+//! [`PeerId`]: libp2p_core::PeerId
+
+mod client;
+mod codec;
+mod record;
+mod server;
+
+mod message_proto {
+    include!(concat!(env!("OUT_DIR"), "/message_proto.rs"));
+}
+
+pub use client::{Client, ClientError, ClientEvent};
+pub use codec::{Cookie, ErrorCode, Namespace, Registration, Ttl, DEFAULT_TTL, PROTOCOL_NAME};
+pub use record::{RecordError, SignedPeerRecord};
+pub use server::{Server, ServerConfig, ServerEvent};
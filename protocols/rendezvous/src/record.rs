@@ -0,0 +1,204 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Signed peer records, i.e. the addresses of a peer in a signed envelope.
+//!
+//! Registrations carry a [`SignedPeerRecord`] so that a rendezvous point
+//! can neither forge nor alter the addresses of the peers it hands out.
+
+use crate::message_proto;
+use libp2p_core::{identity::{error::SigningError, Keypair}, Multiaddr, PeerId, PublicKey};
+use prost::Message;
+use std::{convert::TryFrom, error, fmt};
+use wasm_timer::{SystemTime, UNIX_EPOCH};
+
+/// Domain separation string of the signed envelope.
+const DOMAIN: &[u8] = b"libp2p-peer-record";
+
+/// Payload type of the signed envelope, i.e. the multicodec of peer records.
+const PAYLOAD_TYPE: &[u8] = &[0x03, 0x01];
+
+/// The addresses of a peer, signed with its identity keypair.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedPeerRecord {
+    peer_id: PeerId,
+    seq: u64,
+    addresses: Vec<Multiaddr>,
+    /// The encoded envelope, kept verbatim to pass it on.
+    envelope: Vec<u8>,
+}
+
+impl SignedPeerRecord {
+    /// Signs the given addresses with the identity keypair of the local node.
+    ///
+    /// The sequence number of the record is the current time in milliseconds
+    /// since the Unix epoch, so that newer records supersede older ones.
+    pub fn new(keypair: &Keypair, addresses: Vec<Multiaddr>) -> Result<Self, SigningError> {
+        let public_key = keypair.public();
+        let peer_id = public_key.clone().into_peer_id();
+        let seq = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let record = message_proto::PeerRecord {
+            peer_id: peer_id.to_bytes(),
+            seq,
+            addresses: addresses
+                .iter()
+                .map(|a| message_proto::peer_record::AddressInfo { multiaddr: a.to_vec() })
+                .collect(),
+        };
+        let payload = encode(&record);
+        let signature = keypair.sign(&signing_payload(&payload))?;
+
+        let envelope = encode(&message_proto::Envelope {
+            public_key: public_key.into_protobuf_encoding(),
+            payload_type: PAYLOAD_TYPE.to_vec(),
+            payload,
+            signature,
+        });
+
+        Ok(SignedPeerRecord { peer_id, seq, addresses, envelope })
+    }
+
+    /// Decodes a signed peer record received from another node, verifying
+    /// its signature.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RecordError> {
+        let envelope = message_proto::Envelope::decode(bytes)
+            .map_err(|e| RecordError::Malformed(e.to_string()))?;
+
+        if envelope.payload_type != PAYLOAD_TYPE {
+            return Err(RecordError::Malformed("unexpected payload type".into()))
+        }
+
+        let public_key = PublicKey::from_protobuf_encoding(&envelope.public_key)
+            .map_err(|e| RecordError::Malformed(e.to_string()))?;
+        if !public_key.verify(&signing_payload(&envelope.payload), &envelope.signature) {
+            return Err(RecordError::InvalidSignature)
+        }
+
+        let record = message_proto::PeerRecord::decode(&envelope.payload[..])
+            .map_err(|e| RecordError::Malformed(e.to_string()))?;
+        let peer_id = PeerId::from_bytes(&record.peer_id)
+            .map_err(|e| RecordError::Malformed(e.to_string()))?;
+        if peer_id.is_public_key(&public_key) != Some(true) {
+            return Err(RecordError::InvalidSignature)
+        }
+        let addresses = record.addresses
+            .into_iter()
+            .map(|a| Multiaddr::try_from(a.multiaddr))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RecordError::Malformed(e.to_string()))?;
+
+        Ok(SignedPeerRecord { peer_id, seq: record.seq, addresses, envelope: bytes.to_vec() })
+    }
+
+    /// Encodes the signed peer record for exchange with other nodes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.envelope.clone()
+    }
+
+    /// The peer whose addresses are recorded, i.e. the signer.
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    /// The sequence number of the record.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// The addresses of the peer.
+    pub fn addresses(&self) -> &[Multiaddr] {
+        &self.addresses
+    }
+}
+
+/// Error decoding a [`SignedPeerRecord`].
+#[derive(Debug)]
+pub enum RecordError {
+    /// The record could not be decoded.
+    Malformed(String),
+    /// The record is not signed by the peer it claims to belong to.
+    InvalidSignature,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::Malformed(e) => write!(f, "Malformed peer record: {}", e),
+            RecordError::InvalidSignature => f.write_str("Invalid peer record signature"),
+        }
+    }
+}
+
+impl error::Error for RecordError {}
+
+fn encode(message: &impl Message) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+    bytes
+}
+
+/// Returns the bytes signed in an envelope, i.e. the length-prefixed domain,
+/// payload type and payload.
+fn signing_payload(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(DOMAIN.len() + PAYLOAD_TYPE.len() + payload.len() + 12);
+    for field in &[DOMAIN, PAYLOAD_TYPE, payload] {
+        prost::encoding::encode_varint(field.len() as u64, &mut bytes);
+        bytes.extend_from_slice(field);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::identity;
+
+    #[test]
+    fn signed_peer_record_roundtrip() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let addresses = vec!["/ip4/1.2.3.4/tcp/4001".parse().unwrap()];
+        let record = SignedPeerRecord::new(&keypair, addresses.clone()).unwrap();
+        let decoded = SignedPeerRecord::from_bytes(&record.to_bytes()).unwrap();
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.peer_id(), keypair.public().into_peer_id());
+        assert_eq!(decoded.addresses(), &addresses[..]);
+    }
+
+    #[test]
+    fn signed_peer_record_rejects_tampering() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let record = SignedPeerRecord::new(&keypair, vec!["/ip4/1.2.3.4/tcp/4001".parse().unwrap()]).unwrap();
+
+        let mut envelope = message_proto::Envelope::decode(&record.to_bytes()[..]).unwrap();
+        let mut payload = message_proto::PeerRecord::decode(&envelope.payload[..]).unwrap();
+        payload.addresses[0].multiaddr = "/ip4/5.6.7.8/tcp/4001".parse::<Multiaddr>().unwrap().to_vec();
+        envelope.payload = encode(&payload);
+
+        match SignedPeerRecord::from_bytes(&encode(&envelope)) {
+            Err(RecordError::InvalidSignature) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
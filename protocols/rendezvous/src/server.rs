@@ -0,0 +1,407 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! The rendezvous point, storing registrations and serving discoveries.
+
+use crate::codec::{
+    Cookie,
+    ErrorCode,
+    Message,
+    Namespace,
+    NewRegistration,
+    Registration,
+    RendezvousCodec,
+    RendezvousProtocol,
+    Ttl,
+    DEFAULT_TTL,
+};
+use crate::record::SignedPeerRecord;
+use futures::{future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::{connection::ConnectionId, ConnectedPoint, Multiaddr, PeerId};
+use libp2p_request_response::{
+    handler::{RequestProtocol, RequestResponseHandler, RequestResponseHandlerEvent},
+    ProtocolSupport,
+    RequestResponse,
+    RequestResponseConfig,
+    RequestResponseEvent,
+    RequestResponseMessage,
+    ResponseChannel,
+};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    iter,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::{Delay, Instant};
+
+/// Configuration of a [`Server`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    min_ttl: Ttl,
+    max_ttl: Ttl,
+    max_registrations: usize,
+    max_registrations_per_peer: usize,
+    max_discover_limit: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            min_ttl: DEFAULT_TTL,
+            max_ttl: 60 * 60 * 72,
+            max_registrations: 10_000,
+            max_registrations_per_peer: 32,
+            max_discover_limit: 1000,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Sets the minimum time to live a peer may register with, in seconds.
+    pub fn set_min_ttl(&mut self, ttl: Ttl) -> &mut Self {
+        self.min_ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum time to live a peer may register with, in seconds.
+    pub fn set_max_ttl(&mut self, ttl: Ttl) -> &mut Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum number of registrations stored.
+    pub fn set_max_registrations(&mut self, n: usize) -> &mut Self {
+        self.max_registrations = n;
+        self
+    }
+
+    /// Sets the maximum number of namespaces a single peer may be registered in.
+    pub fn set_max_registrations_per_peer(&mut self, n: usize) -> &mut Self {
+        self.max_registrations_per_peer = n;
+        self
+    }
+
+    /// Sets the maximum number of registrations returned by a single discovery.
+    pub fn set_max_discover_limit(&mut self, n: u64) -> &mut Self {
+        self.max_discover_limit = n;
+        self
+    }
+}
+
+/// Event that can be produced by the [`Server`] behaviour.
+#[derive(Debug)]
+pub enum ServerEvent {
+    /// A peer registered in a namespace.
+    PeerRegistered {
+        peer: PeerId,
+        registration: Registration,
+    },
+    /// The registration of a peer was refused.
+    PeerNotRegistered {
+        peer: PeerId,
+        namespace: Namespace,
+        error: ErrorCode,
+    },
+    /// A peer unregistered from a namespace.
+    PeerUnregistered {
+        peer: PeerId,
+        namespace: Namespace,
+    },
+    /// A discovery was answered.
+    DiscoverServed {
+        enquirer: PeerId,
+        registrations: Vec<Registration>,
+    },
+    /// A discovery was refused.
+    DiscoverNotServed {
+        enquirer: PeerId,
+        error: ErrorCode,
+    },
+    /// A registration expired without being renewed.
+    RegistrationExpired(Registration),
+}
+
+/// A registration stored by the [`Server`].
+struct StoredRegistration {
+    peer: PeerId,
+    namespace: Namespace,
+    record: SignedPeerRecord,
+    expires: Instant,
+}
+
+impl StoredRegistration {
+    fn to_registration(&self, now: Instant) -> Registration {
+        Registration {
+            namespace: self.namespace.clone(),
+            record: self.record.clone(),
+            ttl: if self.expires > now { (self.expires - now).as_secs() } else { 0 },
+        }
+    }
+}
+
+/// A [`NetworkBehaviour`] acting as rendezvous point.
+///
+/// Peers register their signed addresses in namespaces for a limited time
+/// and discover the registrations of other peers. Discoveries return a
+/// [`Cookie`] with which subsequent discoveries only return registrations
+/// that are new since.
+pub struct Server {
+    inner: RequestResponse<RendezvousCodec>,
+    config: ServerConfig,
+    /// The registrations by identifier, in the order of registration.
+    registrations: BTreeMap<u64, StoredRegistration>,
+    /// The identifiers of the registrations of each peer in each namespace.
+    registration_ids: HashMap<(PeerId, Namespace), u64>,
+    /// The identifier of the next registration. Cookies record the last
+    /// identifier returned, hence identifiers start at `1`.
+    next_registration_id: u64,
+    /// Timers of the registrations, yielding their identifiers.
+    expiring: FuturesUnordered<BoxFuture<'static, u64>>,
+    events: VecDeque<ServerEvent>,
+}
+
+impl Server {
+    /// Creates a new rendezvous point.
+    pub fn new(config: ServerConfig) -> Self {
+        Server {
+            inner: RequestResponse::new(
+                RendezvousCodec,
+                iter::once((RendezvousProtocol, ProtocolSupport::Inbound)),
+                RequestResponseConfig::default(),
+            ),
+            config,
+            registrations: BTreeMap::new(),
+            registration_ids: HashMap::new(),
+            next_registration_id: 1,
+            expiring: FuturesUnordered::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of registrations stored.
+    pub fn num_registrations(&self) -> usize {
+        self.registrations.len()
+    }
+
+    fn register(&mut self, peer: PeerId, registration: NewRegistration) -> Result<Registration, ErrorCode> {
+        if registration.record.peer_id() != peer {
+            return Err(ErrorCode::NotAuthorized)
+        }
+
+        let ttl = registration.ttl.unwrap_or(DEFAULT_TTL);
+        if ttl < self.config.min_ttl || ttl > self.config.max_ttl {
+            return Err(ErrorCode::InvalidTtl)
+        }
+
+        // A registration renewed replaces the previous one. It gets a new
+        // identifier, so that discoveries with a cookie learn about it.
+        let key = (peer, registration.namespace.clone());
+        if let Some(id) = self.registration_ids.remove(&key) {
+            self.registrations.remove(&id);
+        } else {
+            let per_peer = self.registration_ids.keys().filter(|(p, _)| *p == peer).count();
+            if per_peer >= self.config.max_registrations_per_peer
+                || self.registrations.len() >= self.config.max_registrations
+            {
+                return Err(ErrorCode::Unavailable)
+            }
+        }
+
+        let id = self.next_registration_id;
+        self.next_registration_id += 1;
+        self.registration_ids.insert(key, id);
+        self.registrations.insert(id, StoredRegistration {
+            peer,
+            namespace: registration.namespace.clone(),
+            record: registration.record.clone(),
+            expires: Instant::now() + Duration::from_secs(ttl),
+        });
+        self.expiring.push(Delay::new(Duration::from_secs(ttl)).map(move |_| id).boxed());
+
+        Ok(Registration {
+            namespace: registration.namespace,
+            record: registration.record,
+            ttl,
+        })
+    }
+
+    fn unregister(&mut self, peer: PeerId, namespace: Namespace) {
+        if let Some(id) = self.registration_ids.remove(&(peer, namespace)) {
+            self.registrations.remove(&id);
+        }
+    }
+
+    fn discover(&self, namespace: Option<Namespace>, cookie: Option<Cookie>, limit: Option<u64>)
+        -> Result<(Vec<Registration>, Cookie), ErrorCode>
+    {
+        let last_id = match &cookie {
+            Some(cookie) => {
+                if cookie.namespace() != namespace.as_ref() || cookie.id() >= self.next_registration_id {
+                    return Err(ErrorCode::InvalidCookie)
+                }
+                cookie.id()
+            }
+            None => 0,
+        };
+        let limit = limit.map_or(self.config.max_discover_limit, |l| l.min(self.config.max_discover_limit));
+
+        let now = Instant::now();
+        let mut registrations = Vec::new();
+        let mut last_returned_id = last_id;
+        let mut limited = false;
+        for (id, registration) in self.registrations.range(last_id + 1..) {
+            if registration.expires <= now
+                || namespace.as_ref().map_or(false, |ns| *ns != registration.namespace)
+            {
+                continue
+            }
+            if registrations.len() as u64 >= limit {
+                limited = true;
+                break
+            }
+            registrations.push(registration.to_registration(now));
+            last_returned_id = *id;
+        }
+
+        // Unless the limit is reached, all registrations have been seen.
+        let cookie_id = if limited { last_returned_id } else { self.next_registration_id - 1 };
+        Ok((registrations, Cookie::new(cookie_id, namespace)))
+    }
+
+    fn on_request(&mut self, peer: PeerId, request: Message, channel: ResponseChannel<Message>) {
+        match request {
+            Message::Register(registration) => {
+                let namespace = registration.namespace.clone();
+                match self.register(peer, registration) {
+                    Ok(registration) => {
+                        let _ = self.inner.send_response(channel, Message::RegisterResponse(Ok(registration.ttl)));
+                        self.events.push_back(ServerEvent::PeerRegistered { peer, registration });
+                    }
+                    Err(error) => {
+                        let _ = self.inner.send_response(channel, Message::RegisterResponse(Err(error)));
+                        self.events.push_back(ServerEvent::PeerNotRegistered { peer, namespace, error });
+                    }
+                }
+            }
+            Message::Unregister(namespace) => {
+                // Unregistering has no response, dropping the channel closes the substream.
+                self.unregister(peer, namespace.clone());
+                self.events.push_back(ServerEvent::PeerUnregistered { peer, namespace });
+            }
+            Message::Discover { namespace, cookie, limit } => {
+                match self.discover(namespace, cookie, limit) {
+                    Ok((registrations, cookie)) => {
+                        let response = Message::DiscoverResponse(Ok((registrations.clone(), cookie)));
+                        let _ = self.inner.send_response(channel, response);
+                        self.events.push_back(ServerEvent::DiscoverServed { enquirer: peer, registrations });
+                    }
+                    Err(error) => {
+                        let _ = self.inner.send_response(channel, Message::DiscoverResponse(Err(error)));
+                        self.events.push_back(ServerEvent::DiscoverNotServed { enquirer: peer, error });
+                    }
+                }
+            }
+            Message::RegisterResponse(_) | Message::DiscoverResponse(_) => {
+                log::debug!("Unexpected request from {}.", peer);
+            }
+        }
+    }
+}
+
+impl NetworkBehaviour for Server {
+    type ProtocolsHandler = RequestResponseHandler<RendezvousCodec>;
+    type OutEvent = ServerEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.inner.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.inner.addresses_of_peer(peer)
+    }
+
+    fn inject_connected(&mut self, peer: &PeerId) {
+        self.inner.inject_connected(peer)
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.inner.inject_disconnected(peer)
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, id: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.inner.inject_connection_established(peer, id, endpoint)
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, id: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.inner.inject_connection_closed(peer, id, endpoint)
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        self.inner.inject_dial_failure(peer)
+    }
+
+    fn inject_event(&mut self, peer: PeerId, id: ConnectionId, event: RequestResponseHandlerEvent<RendezvousCodec>) {
+        self.inner.inject_event(peer, id, event)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<RequestProtocol<RendezvousCodec>, ServerEvent>>
+    {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event))
+            }
+
+            while let Poll::Ready(Some(id)) = self.expiring.poll_next_unpin(cx) {
+                // Renewed and removed registrations are gone already.
+                if let Some(registration) = self.registrations.remove(&id) {
+                    self.registration_ids.remove(&(registration.peer, registration.namespace.clone()));
+                    let registration = registration.to_registration(Instant::now());
+                    self.events.push_back(ServerEvent::RegistrationExpired(registration));
+                }
+            }
+            if !self.events.is_empty() {
+                continue
+            }
+
+            match self.inner.poll(cx, params) {
+                Poll::Ready(NetworkBehaviourAction::GenerateEvent(event)) => match event {
+                    RequestResponseEvent::Message {
+                        peer,
+                        message: RequestResponseMessage::Request { request, channel, .. },
+                    } => self.on_request(peer, request, channel),
+                    RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                        log::debug!("Inbound request from {} failed: {:?}", peer, error);
+                    }
+                    RequestResponseEvent::Message { .. }
+                    | RequestResponseEvent::OutboundFailure { .. }
+                    | RequestResponseEvent::ResponseSent { .. } => {}
+                },
+                Poll::Ready(action) => return Poll::Ready(action.map_out(|_| {
+                    unreachable!("`GenerateEvent` is handled above.")
+                })),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
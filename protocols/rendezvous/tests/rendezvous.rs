@@ -0,0 +1,157 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Integration tests for the rendezvous behaviours.
+
+use futures::executor::block_on;
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{self, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_rendezvous::{Client, ClientError, ClientEvent, ErrorCode, Namespace, Server, ServerConfig};
+use libp2p_swarm::{AddressScore, Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+
+#[test]
+fn register_discover_and_dial() {
+    let (server_peer_id, server_addr) = spawn_server(ServerConfig::default());
+    let namespace = Namespace::new("mwc").unwrap();
+
+    let alice_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let (alice_peer_id, mut alice) = build_client();
+    Swarm::listen_on(&mut alice, alice_addr.clone()).unwrap();
+    Swarm::add_external_address(&mut alice, alice_addr, AddressScore::Infinite);
+    alice.add_address(&server_peer_id, server_addr.clone());
+    alice.register(namespace.clone(), server_peer_id, None);
+    block_on(async {
+        loop {
+            match alice.next_event().await {
+                SwarmEvent::Behaviour(ClientEvent::Registered { .. }) => break,
+                SwarmEvent::Behaviour(ClientEvent::RegisterFailed { error, .. }) =>
+                    panic!("Registration failed: {}", error),
+                _ => {}
+            }
+        }
+    });
+    async_std::task::spawn(async move {
+        loop { alice.next_event().await; }
+    });
+
+    let (_, mut bob) = build_client();
+    bob.add_address(&server_peer_id, server_addr);
+    bob.discover(Some(namespace), None, None, server_peer_id);
+    block_on(async {
+        loop {
+            match bob.next_event().await {
+                SwarmEvent::Behaviour(ClientEvent::Discovered { registrations, .. }) => {
+                    assert_eq!(registrations.len(), 1);
+                    assert_eq!(registrations[0].record.peer_id(), alice_peer_id);
+                    break
+                }
+                SwarmEvent::Behaviour(ClientEvent::DiscoverFailed { error, .. }) =>
+                    panic!("Discovery failed: {}", error),
+                _ => {}
+            }
+        }
+    });
+
+    // The discovered addresses are used to dial by peer ID.
+    Swarm::dial(&mut bob, &alice_peer_id).unwrap();
+    block_on(async {
+        loop {
+            if let SwarmEvent::ConnectionEstablished { peer_id, .. } = bob.next_event().await {
+                if peer_id == alice_peer_id {
+                    break
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn register_with_invalid_ttl() {
+    let (server_peer_id, server_addr) = spawn_server(ServerConfig::default());
+
+    let (_, mut client) = build_client();
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::add_external_address(&mut client, addr, AddressScore::Infinite);
+    client.add_address(&server_peer_id, server_addr);
+    client.register(Namespace::new("mwc").unwrap(), server_peer_id, Some(60));
+    block_on(async {
+        loop {
+            match client.next_event().await {
+                SwarmEvent::Behaviour(ClientEvent::RegisterFailed { error, .. }) => {
+                    assert!(matches!(error, ClientError::Remote(ErrorCode::InvalidTtl)));
+                    break
+                }
+                SwarmEvent::Behaviour(ClientEvent::Registered { .. }) =>
+                    panic!("Registered with a TTL below the minimum"),
+                _ => {}
+            }
+        }
+    });
+}
+
+fn spawn_server(config: ServerConfig) -> (PeerId, Multiaddr) {
+    let local_public_key = identity::Keypair::generate_ed25519().public();
+    let peer_id = local_public_key.clone().into_peer_id();
+    let transport = upgrade_transport(MemoryTransport::default(), local_public_key);
+    let mut server = Swarm::new(transport, Server::new(config), peer_id);
+
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut server, addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop { server.next_event().await; }
+    });
+
+    (peer_id, addr)
+}
+
+fn build_client() -> (PeerId, Swarm<Client>) {
+    let keypair = identity::Keypair::generate_ed25519();
+    let local_public_key = keypair.public();
+    let peer_id = local_public_key.clone().into_peer_id();
+    let transport = upgrade_transport(MemoryTransport::default(), local_public_key);
+
+    (peer_id, Swarm::new(transport, Client::new(keypair), peer_id))
+}
+
+fn upgrade_transport<T>(transport: T, local_public_key: identity::PublicKey)
+    -> transport::Boxed<(PeerId, StreamMuxerBox)>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: futures::AsyncRead + futures::AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+{
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(yamux::YamuxConfig::default())
+        .boxed()
+}
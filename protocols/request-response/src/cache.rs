@@ -0,0 +1,178 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Caching of responses to inbound requests.
+
+use crate::{RequestId, RequestResponseCodec};
+use lru::LruCache;
+use std::{fmt, sync::Arc, time::Duration};
+use wasm_timer::Instant;
+
+/// The configuration of a cache for responses to inbound requests, see
+/// [`RequestResponse::set_response_cache`](crate::RequestResponse::set_response_cache).
+///
+/// Responses are cached by the fingerprint of the request they answer, as
+/// computed by a function of the negotiated protocol and the request. The
+/// function returns `None` for requests whose responses must not be cached,
+/// i.e. caching is opt-in per protocol and request. Requests with equal
+/// fingerprints must warrant identical responses.
+pub struct ResponseCacheConfig<TCodec>
+where
+    TCodec: RequestResponseCodec
+{
+    capacity: usize,
+    ttl: Duration,
+    fingerprint: Arc<dyn Fn(&TCodec::Protocol, &TCodec::Request) -> Option<u64> + Send + Sync>,
+    clone: fn(&TCodec::Response) -> TCodec::Response,
+}
+
+impl<TCodec> ResponseCacheConfig<TCodec>
+where
+    TCodec: RequestResponseCodec,
+    TCodec::Response: Clone
+{
+    /// Creates a new configuration with the given fingerprint function,
+    /// caching up to 128 responses for 5 seconds each.
+    pub fn new<F>(fingerprint: F) -> Self
+    where
+        F: Fn(&TCodec::Protocol, &TCodec::Request) -> Option<u64> + Send + Sync + 'static
+    {
+        ResponseCacheConfig {
+            capacity: 128,
+            ttl: Duration::from_secs(5),
+            fingerprint: Arc::new(fingerprint),
+            clone: <TCodec::Response as Clone>::clone,
+        }
+    }
+}
+
+impl<TCodec> ResponseCacheConfig<TCodec>
+where
+    TCodec: RequestResponseCodec
+{
+    /// Sets the maximum number of cached responses.
+    ///
+    /// The least recently used responses are evicted first.
+    pub fn set_capacity(&mut self, n: usize) -> &mut Self {
+        self.capacity = n;
+        self
+    }
+
+    /// Sets the duration for which a response is served from the cache.
+    pub fn set_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+impl<TCodec> Clone for ResponseCacheConfig<TCodec>
+where
+    TCodec: RequestResponseCodec
+{
+    fn clone(&self) -> Self {
+        ResponseCacheConfig {
+            capacity: self.capacity,
+            ttl: self.ttl,
+            fingerprint: self.fingerprint.clone(),
+            clone: self.clone,
+        }
+    }
+}
+
+impl<TCodec> fmt::Debug for ResponseCacheConfig<TCodec>
+where
+    TCodec: RequestResponseCodec
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseCacheConfig")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+/// A cache for responses to inbound requests.
+pub(crate) struct ResponseCache<TCodec>
+where
+    TCodec: RequestResponseCodec
+{
+    config: ResponseCacheConfig<TCodec>,
+    /// The cached responses by request fingerprint, together with the
+    /// time they were cached.
+    responses: LruCache<u64, (TCodec::Response, Instant)>,
+    /// The fingerprints of inbound requests waiting for a response
+    /// from the application.
+    pending: LruCache<RequestId, u64>,
+}
+
+impl<TCodec> ResponseCache<TCodec>
+where
+    TCodec: RequestResponseCodec
+{
+    pub(crate) fn new(config: ResponseCacheConfig<TCodec>) -> Self {
+        let capacity = std::cmp::max(1, config.capacity);
+        ResponseCache {
+            config,
+            responses: LruCache::new(capacity),
+            pending: LruCache::new(capacity),
+        }
+    }
+
+    /// Returns the fingerprint of a request, if its response may be cached.
+    pub(crate) fn fingerprint(&self, protocol: &TCodec::Protocol, request: &TCodec::Request)
+        -> Option<u64>
+    {
+        (self.config.fingerprint)(protocol, request)
+    }
+
+    /// Returns a copy of the cached response to requests with the
+    /// given fingerprint, unless it expired.
+    pub(crate) fn get(&mut self, fingerprint: u64) -> Option<TCodec::Response> {
+        if let Some((response, cached)) = self.responses.get(&fingerprint) {
+            if cached.elapsed() < self.config.ttl {
+                return Some((self.config.clone)(response))
+            }
+        } else {
+            return None
+        }
+        self.responses.pop(&fingerprint);
+        None
+    }
+
+    /// Records the fingerprint of an inbound request passed on to
+    /// the application.
+    pub(crate) fn expect(&mut self, request_id: RequestId, fingerprint: u64) {
+        self.pending.put(request_id, fingerprint);
+    }
+
+    /// Caches the response to an inbound request, if its fingerprint
+    /// has been recorded.
+    pub(crate) fn insert(&mut self, request_id: RequestId, response: &TCodec::Response) {
+        if let Some(fingerprint) = self.pending.pop(&request_id) {
+            let response = (self.config.clone)(response);
+            self.responses.put(fingerprint, (response, Instant::now()));
+        }
+    }
+
+    /// Forgets the fingerprint of an inbound request that failed.
+    pub(crate) fn forget(&mut self, request_id: RequestId) {
+        self.pending.pop(&request_id);
+    }
+}
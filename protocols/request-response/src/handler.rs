@@ -80,7 +80,7 @@ where
     /// Inbound upgrades waiting for the incoming request.
     inbound: FuturesUnordered<BoxFuture<'static,
         Result<
-            ((RequestId, TCodec::Request), oneshot::Sender<TCodec::Response>),
+            ((RequestId, TCodec::Protocol, TCodec::Request), oneshot::Sender<TCodec::Response>),
             oneshot::Canceled
         >>>,
     inbound_request_id: Arc<AtomicU64>
@@ -122,6 +122,7 @@ where
     /// A request has been received.
     Request {
         request_id: RequestId,
+        protocol: TCodec::Protocol,
         request: TCodec::Request,
         sender: oneshot::Sender<TCodec::Response>
     },
@@ -301,12 +302,12 @@ where
         // Check for inbound requests.
         while let Poll::Ready(Some(result)) = self.inbound.poll_next_unpin(cx) {
             match result {
-                Ok(((id, rq), rs_sender)) => {
+                Ok(((id, protocol, rq), rs_sender)) => {
                     // We received an inbound request.
                     self.keep_alive = KeepAlive::Yes;
                     return Poll::Ready(ProtocolsHandlerEvent::Custom(
                         RequestResponseHandlerEvent::Request {
-                            request_id: id, request: rq, sender: rs_sender
+                            request_id: id, protocol, request: rq, sender: rs_sender
                         }))
                 }
                 Err(oneshot::Canceled) => {
@@ -344,5 +345,15 @@ where
 
         Poll::Pending
     }
+
+    fn poll_close(&mut self, _: &mut Context<'_>) -> Poll<()> {
+        // Requests and responses in flight are written by their substream
+        // upgrades, which the connection waits for before closing.
+        if self.pending_events.is_empty() && self.outbound.is_empty() && self.inbound.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
 }
 
@@ -56,14 +56,20 @@
 //! advertised during inbound respectively outbound protocol negotiation
 //! on the substreams.
 
+mod cache;
+
 pub mod codec;
 pub mod handler;
 pub mod throttled;
+#[cfg(feature = "tower")]
+pub mod tower;
 
+pub use cache::ResponseCacheConfig;
 pub use codec::{RequestResponseCodec, ProtocolName};
 pub use handler::ProtocolSupport;
 pub use throttled::Throttled;
 
+use cache::ResponseCache;
 use futures::{
     channel::oneshot,
 };
@@ -84,6 +90,7 @@ use libp2p_swarm::{
     NetworkBehaviourAction,
     NotifyHandler,
     PollParameters,
+    ProtocolRegistry,
 };
 use smallvec::SmallVec;
 use std::{
@@ -181,6 +188,29 @@ pub enum OutboundFailure {
     UnsupportedProtocols,
 }
 
+impl OutboundFailure {
+    /// Returns a numeric code identifying the variant of this failure.
+    ///
+    /// The codes are stable across versions and never reused, which makes
+    /// them suitable for identifying failures across language boundaries,
+    /// e.g. in FFI bindings.
+    ///
+    /// | Variant                | Code  |
+    /// |------------------------|-------|
+    /// | `DialFailure`          | `300` |
+    /// | `Timeout`              | `301` |
+    /// | `ConnectionClosed`     | `302` |
+    /// | `UnsupportedProtocols` | `303` |
+    pub fn code(&self) -> u32 {
+        match self {
+            OutboundFailure::DialFailure => 300,
+            OutboundFailure::Timeout => 301,
+            OutboundFailure::ConnectionClosed => 302,
+            OutboundFailure::UnsupportedProtocols => 303,
+        }
+    }
+}
+
 /// Possible failures occurring in the context of receiving an
 /// inbound request and sending a response.
 #[derive(Debug, Clone)]
@@ -201,6 +231,29 @@ pub enum InboundFailure {
     ResponseOmission,
 }
 
+impl InboundFailure {
+    /// Returns a numeric code identifying the variant of this failure.
+    ///
+    /// The codes are stable across versions and never reused, which makes
+    /// them suitable for identifying failures across language boundaries,
+    /// e.g. in FFI bindings.
+    ///
+    /// | Variant                | Code  |
+    /// |------------------------|-------|
+    /// | `Timeout`              | `400` |
+    /// | `ConnectionClosed`     | `401` |
+    /// | `UnsupportedProtocols` | `402` |
+    /// | `ResponseOmission`     | `403` |
+    pub fn code(&self) -> u32 {
+        match self {
+            InboundFailure::Timeout => 400,
+            InboundFailure::ConnectionClosed => 401,
+            InboundFailure::UnsupportedProtocols => 402,
+            InboundFailure::ResponseOmission => 403,
+        }
+    }
+}
+
 /// A channel for sending a response to an inbound request.
 ///
 /// See [`RequestResponse::send_response`].
@@ -305,6 +358,16 @@ where
     /// Requests that have not yet been sent and are waiting for a connection
     /// to be established.
     pending_outbound_requests: HashMap<PeerId, SmallVec<[RequestProtocol<TCodec>; 10]>>,
+    /// Requests to connected peers waiting to be handed to the connection
+    /// handler, together with the chosen connection.
+    outbound_queues: HashMap<PeerId, VecDeque<(ConnectionId, RequestProtocol<TCodec>)>>,
+    /// The peers with a non-empty queue in `outbound_queues`, in the order
+    /// in which they are served. Requests are handed to the connection handlers
+    /// one per peer in turn, so that a peer with many queued requests does
+    /// not delay requests to other peers.
+    outbound_order: VecDeque<PeerId>,
+    /// The cache for responses to inbound requests, if enabled.
+    response_cache: Option<ResponseCache<TCodec>>,
 }
 
 impl<TCodec> RequestResponse<TCodec>
@@ -337,7 +400,18 @@ where
             pending_events: VecDeque::new(),
             connected: HashMap::new(),
             pending_outbound_requests: HashMap::new(),
+            outbound_queues: HashMap::new(),
+            outbound_order: VecDeque::new(),
             addresses: HashMap::new(),
+            response_cache: None,
+        }
+    }
+
+    /// Registers the protocols supported for inbound requests with the
+    /// given registry, on behalf of the behaviour named `owner`.
+    pub fn register_protocols(&self, owner: &str, registry: &ProtocolRegistry) {
+        for p in &self.inbound_protocols {
+            registry.register(owner, p.clone());
         }
     }
 
@@ -400,9 +474,25 @@ where
     pub fn send_response(&mut self, ch: ResponseChannel<TCodec::Response>, rs: TCodec::Response)
         -> Result<(), TCodec::Response>
     {
+        if let Some(cache) = &mut self.response_cache {
+            cache.insert(ch.request_id, &rs);
+        }
         ch.sender.send(rs)
     }
 
+    /// Enables or disables the cache for responses to inbound requests.
+    ///
+    /// If enabled, inbound requests with the same fingerprint as an earlier
+    /// request (see [`ResponseCacheConfig`]) are answered with the response
+    /// previously sent via [`RequestResponse::send_response`], as long as it
+    /// is cached. Such requests are not reported as
+    /// [`RequestResponseEvent::Message`], nor are the corresponding
+    /// [`RequestResponseEvent::ResponseSent`] and
+    /// [`RequestResponseEvent::InboundFailure`] events emitted.
+    pub fn set_response_cache(&mut self, config: Option<ResponseCacheConfig<TCodec>>) {
+        self.response_cache = config.map(ResponseCache::new);
+    }
+
     /// Adds a known address for a peer that can be used for
     /// dialing attempts by the `Swarm`, i.e. is returned
     /// by [`NetworkBehaviour::addresses_of_peer`].
@@ -465,9 +555,9 @@ where
         request_id
     }
 
-    /// Tries to send a request by queueing an appropriate event to be
-    /// emitted to the `Swarm`. If the peer is not currently connected,
-    /// the given request is return unchanged.
+    /// Tries to send a request by queueing it for the connection handler
+    /// of one of the connections to the peer. If the peer is not currently
+    /// connected, the given request is return unchanged.
     fn try_send_request(&mut self, peer: &PeerId, request: RequestProtocol<TCodec>)
         -> Option<RequestProtocol<TCodec>>
     {
@@ -478,11 +568,11 @@ where
             let ix = (request.request_id.0 as usize) % connections.len();
             let conn = &mut connections[ix];
             conn.pending_inbound_responses.insert(request.request_id);
-            self.pending_events.push_back(NetworkBehaviourAction::NotifyHandler {
-                peer_id: *peer,
-                handler: NotifyHandler::One(conn.id),
-                event: request
-            });
+            let queue = self.outbound_queues.entry(*peer).or_default();
+            if queue.is_empty() {
+                self.outbound_order.push_back(*peer);
+            }
+            queue.push_back((conn.id, request));
             None
         } else {
             Some(request)
@@ -523,6 +613,22 @@ where
 
     /// Returns a mutable reference to the connection in `self.connected`
     /// corresponding to the given [`PeerId`] and [`ConnectionId`].
+    /// Remove an inbound request answered from the response cache.
+    ///
+    /// Returns `true` if the provided connection to the given peer is still
+    /// alive and the [`RequestId`] was previously present and is now removed.
+    /// Returns `false` otherwise.
+    fn remove_cached_response(
+        &mut self,
+        peer: &PeerId,
+        connection: ConnectionId,
+        request: RequestId,
+    ) -> bool {
+        self.get_connection_mut(peer, connection)
+            .map(|c| c.cached_responses.remove(&request))
+            .unwrap_or(false)
+    }
+
     fn get_connection_mut(
         &mut self,
         peer: &PeerId,
@@ -594,6 +700,15 @@ where
             self.connected.remove(peer_id);
         }
 
+        // Queued requests for the connection are reported as failed below.
+        if let Some(queue) = self.outbound_queues.get_mut(peer_id) {
+            queue.retain(|(c, _)| c != conn);
+            if queue.is_empty() {
+                self.outbound_queues.remove(peer_id);
+                self.outbound_order.retain(|p| p != peer_id);
+            }
+        }
+
         for request_id in connection.pending_outbound_responses {
             self.pending_events.push_back(NetworkBehaviourAction::GenerateEvent(
                 RequestResponseEvent::InboundFailure {
@@ -659,7 +774,21 @@ where
                     NetworkBehaviourAction::GenerateEvent(
                         RequestResponseEvent::Message { peer, message }));
             }
-            RequestResponseHandlerEvent::Request { request_id, request, sender } => {
+            RequestResponseHandlerEvent::Request { request_id, protocol, request, sender } => {
+                if let Some(cache) = &mut self.response_cache {
+                    if let Some(fingerprint) = cache.fingerprint(&protocol, &request) {
+                        if let Some(response) = cache.get(fingerprint) {
+                            log::trace!("Answering request {} of {} from the cache.", request_id, peer);
+                            if let Some(connection) = self.get_connection_mut(&peer, connection) {
+                                connection.cached_responses.insert(request_id);
+                                let _ = sender.send(response);
+                            }
+                            return
+                        }
+                        cache.expect(request_id, fingerprint);
+                    }
+                }
+
                 let channel = ResponseChannel { request_id, peer, sender };
                 let message = RequestResponseMessage::Request { request_id, request, channel };
                 self.pending_events.push_back(NetworkBehaviourAction::GenerateEvent(
@@ -684,6 +813,10 @@ where
                 }
             }
             RequestResponseHandlerEvent::ResponseSent(request_id) => {
+                if self.remove_cached_response(&peer, connection, request_id) {
+                    return
+                }
+
                 let removed = self.remove_pending_outbound_response(&peer, connection, request_id);
                 debug_assert!(removed, "Expect request_id to be pending before response is sent.");
 
@@ -692,6 +825,10 @@ where
                         RequestResponseEvent::ResponseSent { peer, request_id }));
             }
             RequestResponseHandlerEvent::ResponseOmission(request_id) => {
+                if let Some(cache) = &mut self.response_cache {
+                    cache.forget(request_id);
+                }
+
                 let removed = self.remove_pending_outbound_response(&peer, connection, request_id);
                 debug_assert!(
                     removed,
@@ -723,6 +860,12 @@ where
                 // out to receive the request and for timing out sending the response. In the former
                 // case the request is never added to `pending_outbound_responses` and thus one can
                 // not assert the request_id to be present before removing it.
+                if self.remove_cached_response(&peer, connection, request_id) {
+                    return
+                }
+                if let Some(cache) = &mut self.response_cache {
+                    cache.forget(request_id);
+                }
                 self.remove_pending_outbound_response(&peer, connection, request_id);
 
                 self.pending_events.push_back(
@@ -775,6 +918,31 @@ where
             self.pending_events.shrink_to_fit();
         }
 
+        while let Some(peer) = self.outbound_order.pop_front() {
+            let (conn, request) = match self.outbound_queues.get_mut(&peer) {
+                Some(queue) => match queue.pop_front() {
+                    Some(next) => {
+                        if queue.is_empty() {
+                            self.outbound_queues.remove(&peer);
+                        } else {
+                            self.outbound_order.push_back(peer);
+                        }
+                        next
+                    }
+                    None => {
+                        self.outbound_queues.remove(&peer);
+                        continue
+                    }
+                },
+                None => continue
+            };
+            return Poll::Ready(NetworkBehaviourAction::NotifyHandler {
+                peer_id: peer,
+                handler: NotifyHandler::One(conn),
+                event: request
+            })
+        }
+
         Poll::Pending
     }
 }
@@ -795,7 +963,10 @@ struct Connection {
     pending_outbound_responses: HashSet<RequestId>,
     /// Pending inbound responses for previously sent requests on this
     /// connection.
-    pending_inbound_responses: HashSet<RequestId>
+    pending_inbound_responses: HashSet<RequestId>,
+    /// Inbound requests received on this connection which are answered
+    /// from the response cache.
+    cached_responses: HashSet<RequestId>,
 }
 
 impl Connection {
@@ -805,6 +976,7 @@ impl Connection {
             address,
             pending_outbound_responses: Default::default(),
             pending_inbound_responses: Default::default(),
+            cached_responses: Default::default(),
         }
     }
 }
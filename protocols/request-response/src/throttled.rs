@@ -33,6 +33,19 @@
 //! as well as a special credit message to which an ack message is expected
 //! as a response. It does so by putting a small CBOR encoded header in front
 //! of each message the inner codec produces.
+//!
+//! Optionally, credit grants can be piggybacked on response messages (see
+//! [`Throttled::set_piggyback_credit`]). Instead of sending a separate credit
+//! message, which requires its own substream and ack, the grant is added to
+//! the header of the next response to the peer. Credit owed to a newly
+//! connected peer is likewise delivered with the first response to it.
+//! A successfully delivered response is treated like an
+//! acknowledged credit message. Should the response fail to be sent, the
+//! grant is retransmitted as a standalone credit message.
+//!
+//! > **Note**: Peers must support piggybacked credit grants for this option
+//! > to be enabled, as they would otherwise ignore the credit and eventually
+//! > stop sending requests.
 
 mod codec;
 
@@ -40,7 +53,7 @@ use codec::{Codec, Message, ProtocolWrapper, Type};
 use crate::handler::{RequestProtocol, RequestResponseHandler, RequestResponseHandlerEvent};
 use futures::ready;
 use libp2p_core::{ConnectedPoint, connection::ConnectionId, Multiaddr, PeerId};
-use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolRegistry};
 use lru::LruCache;
 use std::{collections::{HashMap, HashSet, VecDeque}, task::{Context, Poll}};
 use std::{cmp::max, num::NonZeroU16};
@@ -77,7 +90,9 @@ where
     /// Pending events to report in `Throttled::poll`.
     events: VecDeque<Event<C::Request, C::Response, Message<C::Response>>>,
     /// The current credit ID.
-    next_grant_id: u64
+    next_grant_id: u64,
+    /// Whether to piggyback credit grants on responses.
+    piggyback: bool
 }
 
 /// Information about a credit grant that is sent to remote peers.
@@ -85,8 +100,8 @@ where
 struct Grant {
     /// The grant ID. Used to deduplicate retransmitted credit grants.
     id: GrantId,
-    /// The ID of the outbound credit grant message.
-    request: RequestId,
+    /// The message which carries the credit grant.
+    carrier: Carrier,
     /// The credit given in this grant, i.e. the number of additional
     /// requests the remote is allowed to send.
     credit: u16
@@ -132,6 +147,15 @@ impl Limit {
 
 type GrantId = u64;
 
+/// The message a credit grant is sent with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Carrier {
+    /// A standalone credit message, i.e. the outbound request with this ID.
+    Request(RequestId),
+    /// The response to the inbound request with this ID.
+    Response(RequestId)
+}
+
 /// Information related to the current send budget with a peer.
 #[derive(Clone, Debug)]
 struct SendBudget {
@@ -160,6 +184,9 @@ struct RecvBudget {
     limit: Limit,
     /// The remaining credit for requests to receive.
     remaining: u16,
+    /// Credit included in `remaining` which has not yet been granted to
+    /// the remote, waiting to be piggybacked on the next response.
+    owed: u16,
     /// Credit grants sent whose outcome is still undetermined.
     /// Used to avoid emitting events for failed credit grants.
     ///
@@ -190,6 +217,7 @@ impl PeerInfo {
                 grant: None,
                 limit: recv_limit,
                 remaining: 1,
+                owed: 0,
                 sent: HashSet::new(),
             }
         }
@@ -200,6 +228,7 @@ impl PeerInfo {
         self.send_budget.remaining = 1;
         self.recv_budget.sent = HashSet::new();
         self.recv_budget.remaining = max(1, self.recv_budget.remaining);
+        self.recv_budget.owed = 0;
         // Since we potentially reset the remaining receive budget,
         // we forget about the potentially still unacknowledged last grant.
         self.recv_budget.grant = None;
@@ -233,10 +262,20 @@ where
             default_limit: Limit::new(NonZeroU16::new(1).expect("1 > 0")),
             limit_overrides: HashMap::new(),
             events: VecDeque::new(),
-            next_grant_id: 0
+            next_grant_id: 0,
+            piggyback: false
         }
     }
 
+    /// Enable or disable piggybacking of credit grants on responses.
+    ///
+    /// This is disabled by default and must only be enabled if the remote
+    /// peers support piggybacked credit grants.
+    pub fn set_piggyback_credit(&mut self, enabled: bool) {
+        log::trace!("{:08x}: piggyback credit: {}", self.id, enabled);
+        self.piggyback = enabled
+    }
+
     /// Set the global default receive limit per peer.
     pub fn set_receive_limit(&mut self, limit: NonZeroU16) {
         log::trace!("{:08x}: new default limit: {:?}", self.id, limit);
@@ -309,16 +348,44 @@ where
         -> Result<(), C::Response>
     {
         log::trace!("{:08x}: sending response {} to peer {}", self.id, ch.request_id(), &ch.peer);
-        if let Some(info) = self.peer_info.get_mut(&ch.peer) {
+        let peer = ch.peer;
+        let request_id = ch.request_id();
+        let mut message = Message::response(res);
+        if let Some(info) = self.peer_info.get_mut(&peer) {
             if info.recv_budget.remaining == 0 { // need to send more credit to the remote peer
                 let crd = info.recv_budget.limit.switch();
                 info.recv_budget.remaining = info.recv_budget.limit.max_recv.get();
-                self.send_credit(&ch.peer, crd);
+                info.recv_budget.owed = info.recv_budget.owed.saturating_add(crd);
+            }
+            if info.recv_budget.owed > 0 {
+                let crd = std::mem::replace(&mut info.recv_budget.owed, 0);
+                if self.piggyback {
+                    let cid = self.next_grant_id;
+                    self.next_grant_id += 1;
+                    log::trace! { "{:08x}: piggybacking {} credit as grant {} on response {} to {}",
+                        self.id,
+                        crd,
+                        cid,
+                        request_id,
+                        peer
+                    };
+                    message = message.with_credit(crd, cid);
+                    let grant = Grant { id: cid, carrier: Carrier::Response(request_id), credit: crd };
+                    info.recv_budget.grant = Some(grant);
+                } else {
+                    self.send_credit(&peer, crd);
+                }
             }
         }
-        match self.behaviour.send_response(ch, Message::response(res)) {
+        match self.behaviour.send_response(ch, message) {
             Ok(()) => Ok(()),
-            Err(m) => Err(m.into_parts().1.expect("Missing response data.")),
+            Err(m) => {
+                if m.piggybacked_credit().is_some() {
+                    // The response will never be sent, hence neither its credit grant.
+                    self.resend_credit(&peer)
+                }
+                Err(m.into_parts().1.expect("Missing response data."))
+            }
         }
     }
 
@@ -336,6 +403,13 @@ where
         self.behaviour.remove_address(p, a)
     }
 
+    /// Registers the protocols supported for inbound requests.
+    ///
+    /// See [`RequestResponse::register_protocols`] for details.
+    pub fn register_protocols(&self, owner: &str, registry: &ProtocolRegistry) {
+        self.behaviour.register_protocols(owner, registry)
+    }
+
     /// Are we connected to the given peer?
     ///
     /// See [`RequestResponse::is_connected`] for details.
@@ -366,11 +440,40 @@ where
             self.next_grant_id += 1;
             let rid = self.behaviour.send_request(p, Message::credit(credit, cid));
             log::trace!("{:08x}: sending {} credit as grant {} to {}", self.id, credit, cid, p);
-            let grant = Grant { id: cid, request: rid, credit };
+            let grant = Grant { id: cid, carrier: Carrier::Request(rid), credit };
             info.recv_budget.grant = Some(grant);
             info.recv_budget.sent.insert(rid);
         }
     }
+
+    /// Send the current, unacknowledged credit grant of the given peer
+    /// (again) as a standalone credit message.
+    fn resend_credit(&mut self, p: &PeerId) {
+        if let Some(info) = self.peer_info.get_mut(p) {
+            if let Some(grant) = info.recv_budget.grant.as_mut() {
+                let rid = self.behaviour.send_request(p, Message::credit(grant.credit, grant.id));
+                grant.carrier = Carrier::Request(rid);
+                info.recv_budget.sent.insert(rid);
+            }
+        }
+    }
+
+    /// Add the credit of a grant received from the given peer to our send budget.
+    ///
+    /// Grants which have already been received are ignored.
+    fn receive_credit(&mut self, p: &PeerId, id: GrantId, credit: u16) {
+        if let Some(info) = self.peer_info.get_mut(p) {
+            log::trace!("{:08x}: received {} additional credit {} from {}", self.id, credit, id, p);
+            if info.send_budget.grant < Some(id) {
+                if info.send_budget.remaining == 0 && credit > 0 {
+                    log::trace!("{:08x}: sending to peer {} can resume", self.id, p);
+                    self.events.push_back(Event::ResumeSending(*p))
+                }
+                info.send_budget.remaining += credit;
+                info.send_budget.grant = Some(id);
+            }
+        }
+    }
 }
 
 /// A Wrapper around [`RequestResponseEvent`].
@@ -408,16 +511,13 @@ where
 
     fn inject_connection_closed(&mut self, peer: &PeerId, id: &ConnectionId, end: &ConnectedPoint) {
         self.behaviour.inject_connection_closed(peer, id, end);
-        if let Some(info) = self.peer_info.get_mut(peer) {
-            if let Some(grant) = &mut info.recv_budget.grant {
-                log::debug! { "{:08x}: resending credit grant {} to {} after connection closed",
-                    self.id,
-                    grant.id,
-                    peer
-                };
-                let msg = Message::credit(grant.credit, grant.id);
-                grant.request = self.behaviour.send_request(peer, msg)
-            }
+        if let Some(grant) = self.peer_info.get(peer).and_then(|i| i.recv_budget.grant) {
+            log::debug! { "{:08x}: resending credit grant {} to {} after connection closed",
+                self.id,
+                grant.id,
+                peer
+            };
+            self.resend_credit(peer)
         }
     }
 
@@ -426,11 +526,18 @@ where
         self.behaviour.inject_connected(p);
         // The limit may have been added by `Throttled::send_request` already.
         if !self.peer_info.contains_key(p) {
-            if let Some(info) = self.offline_peer_info.pop(p) {
+            if let Some(mut info) = self.offline_peer_info.pop(p) {
                 let recv_budget = info.recv_budget.remaining;
-                self.peer_info.insert(*p, info);
-                if recv_budget > 1 {
-                    self.send_credit(p, recv_budget - 1);
+                if recv_budget > 1 && self.piggyback {
+                    // The remote may always send one request, hence the
+                    // remaining credit can be granted with its response.
+                    info.recv_budget.owed = recv_budget - 1;
+                    self.peer_info.insert(*p, info);
+                } else {
+                    self.peer_info.insert(*p, info);
+                    if recv_budget > 1 {
+                        self.send_credit(p, recv_budget - 1);
+                    }
                 }
             } else {
                 let limit = self.limit_overrides.get(p).copied().unwrap_or(self.default_limit);
@@ -484,6 +591,9 @@ where
                                 }
                                 | Some(Type::Response) => {
                                     log::trace!("{:08x}: received response {} from {}", self.id, request_id, peer);
+                                    if let Some((credit, id)) = response.piggybacked_credit() {
+                                        self.receive_credit(&peer, id, credit)
+                                    }
                                     if let Some(rs) = response.into_parts().1 {
                                         RequestResponseMessage::Response { request_id, response: rs }
                                     } else {
@@ -508,34 +618,24 @@ where
                         | RequestResponseMessage::Request { request_id, request, channel } =>
                             match &request.header().typ {
                                 | Some(Type::Credit) => {
-                                    if let Some(info) = self.peer_info.get_mut(&peer) {
-                                        let id = if let Some(n) = request.header().ident {
-                                            n
-                                        } else {
-                                            log::warn! { "{:08x}: missing credit id in message from {}",
-                                                self.id,
-                                                peer
-                                            }
-                                            continue
-                                        };
-                                        let credit = request.header().credit.unwrap_or(0);
-                                        log::trace! { "{:08x}: received {} additional credit {} from {}",
+                                    if !self.peer_info.contains_key(&peer) {
+                                        continue
+                                    }
+                                    let id = if let Some(n) = request.header().ident {
+                                        n
+                                    } else {
+                                        log::warn! { "{:08x}: missing credit id in message from {}",
                                             self.id,
-                                            credit,
-                                            id,
                                             peer
-                                        };
-                                        if info.send_budget.grant < Some(id) {
-                                            if info.send_budget.remaining == 0 && credit > 0 {
-                                                log::trace!("{:08x}: sending to peer {} can resume", self.id, peer);
-                                                self.events.push_back(Event::ResumeSending(peer))
-                                            }
-                                            info.send_budget.remaining += credit;
-                                            info.send_budget.grant = Some(id);
                                         }
-                                        // Note: Failing to send a response to a credit grant is
-                                        // handled along with other inbound failures further below.
-                                        let _ = self.behaviour.send_response(channel, Message::ack(id));
+                                        continue
+                                    };
+                                    let credit = request.header().credit.unwrap_or(0);
+                                    self.receive_credit(&peer, id, credit);
+                                    // Note: Failing to send a response to a credit grant is
+                                    // handled along with other inbound failures further below.
+                                    let _ = self.behaviour.send_response(channel, Message::ack(id));
+                                    if let Some(info) = self.peer_info.get_mut(&peer) {
                                         info.send_budget.received.insert(request_id);
                                     }
                                     continue
@@ -588,23 +688,22 @@ where
                     request_id,
                     error
                 }) => {
-                    if let Some(info) = self.peer_info.get_mut(&peer) {
-                        if let Some(grant) = info.recv_budget.grant.as_mut() {
-                            if grant.request == request_id {
-                                log::debug! {
-                                    "{:08x}: failed to send {} as credit {} to {}; retrying...",
-                                    self.id,
-                                    grant.credit,
-                                    grant.id,
-                                    peer
-                                };
-                                let msg = Message::credit(grant.credit, grant.id);
-                                grant.request = self.behaviour.send_request(&peer, msg);
-                            }
+                    if let Some(grant) = self.peer_info.get(&peer).and_then(|i| i.recv_budget.grant) {
+                        if grant.carrier == Carrier::Request(request_id) {
+                            log::debug! {
+                                "{:08x}: failed to send {} as credit {} to {}; retrying...",
+                                self.id,
+                                grant.credit,
+                                grant.id,
+                                peer
+                            };
+                            self.resend_credit(&peer)
                         }
+                    }
 
-                        // If the outbound failure was for a credit message, don't report it on
-                        // the public API and retry the sending.
+                    // If the outbound failure was for a credit message, don't report it on
+                    // the public API and retry the sending.
+                    if let Some(info) = self.peer_info.get_mut(&peer) {
                         if info.recv_budget.sent.remove(&request_id) {
                             continue
                         }
@@ -617,6 +716,21 @@ where
                     request_id,
                     error
                 }) => {
+                    // If the failed response carried a piggybacked credit grant,
+                    // retry the grant as a standalone credit message.
+                    if let Some(grant) = self.peer_info.get(&peer).and_then(|i| i.recv_budget.grant) {
+                        if grant.carrier == Carrier::Response(request_id) {
+                            log::debug! {
+                                "{:08x}: failed to piggyback {} as credit {} to {}; retrying...",
+                                self.id,
+                                grant.credit,
+                                grant.id,
+                                peer
+                            };
+                            self.resend_credit(&peer)
+                        }
+                    }
+
                     // If the inbound failure occurred in the context of responding to a
                     // credit grant, don't report it on the public API.
                     if let Some(info) = self.peer_info.get_mut(&peer) {
@@ -635,9 +749,20 @@ where
                     peer,
                     request_id
                 }) => {
-                    // If this event is for an ACK response that was sent for
-                    // the last received credit grant, skip it.
                     if let Some(info) = self.peer_info.get_mut(&peer) {
+                        // A credit grant piggybacked on this response has
+                        // been delivered and need not be resent.
+                        if info.recv_budget.grant.map_or(false, |g| g.carrier == Carrier::Response(request_id)) {
+                            log::trace! { "{:08x}: delivered credit grant {:?} with response {} to {}",
+                                self.id,
+                                info.recv_budget.grant,
+                                request_id,
+                                peer
+                            };
+                            info.recv_budget.grant = None;
+                        }
+                        // If this event is for an ACK response that was sent for
+                        // the last received credit grant, skip it.
                         if info.send_budget.received.remove(&request_id) {
                             log::trace! {
                                 "{:08}: successfully sent ACK for credit grant {:?}.",
@@ -656,10 +781,14 @@ where
                     NetworkBehaviourAction::DialAddress { address },
                 | NetworkBehaviourAction::DialPeer { peer_id, condition } =>
                     NetworkBehaviourAction::DialPeer { peer_id, condition },
+                | NetworkBehaviourAction::Dial { opts } =>
+                    NetworkBehaviourAction::Dial { opts },
                 | NetworkBehaviourAction::NotifyHandler { peer_id, handler, event } =>
                     NetworkBehaviourAction::NotifyHandler { peer_id, handler, event },
                 | NetworkBehaviourAction::ReportObservedAddr { address, score } =>
-                    NetworkBehaviourAction::ReportObservedAddr { address, score }
+                    NetworkBehaviourAction::ReportObservedAddr { address, score },
+                | NetworkBehaviourAction::ReportExternalAddr { address, source } =>
+                    NetworkBehaviourAction::ReportExternalAddr { address, source }
             };
 
             return Poll::Ready(event)
@@ -36,6 +36,9 @@ pub struct Header {
     /// The number of additional requests the remote is willing to receive.
     #[n(1)] pub credit: Option<u16>,
     /// An identifier used for sending credit grants.
+    ///
+    /// Together with `credit` this may also be present in a response header,
+    /// in which case the response carries a piggybacked credit grant.
     #[n(2)] pub ident: Option<u64>
 }
 
@@ -85,6 +88,24 @@ impl<T> Message<T> {
         Message::new(Header { typ: Some(Type::Ack), credit: None, ident: Some(ident) })
     }
 
+    /// Attach a credit grant to this message.
+    ///
+    /// Only meaningful for responses, where it allows piggybacking a credit
+    /// grant instead of sending a separate credit message.
+    pub fn with_credit(mut self, credit: u16, ident: u64) -> Self {
+        self.header.credit = Some(credit);
+        self.header.ident = Some(ident);
+        self
+    }
+
+    /// Get the credit grant piggybacked on a response, if any.
+    pub fn piggybacked_credit(&self) -> Option<(u16, u64)> {
+        match (&self.header.typ, self.header.credit, self.header.ident) {
+            (Some(Type::Response), Some(credit), Some(ident)) => Some((credit, ident)),
+            _ => None
+        }
+    }
+
     /// Access the message header.
     pub fn header(&self) -> &Header {
         &self.header
@@ -0,0 +1,411 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Integration of request-response protocols with [tower](https://docs.rs/tower) services.
+//!
+//! The [`ServiceBridge`] behaviour wraps an existing [`RequestResponse`]
+//! behaviour and connects it to the tower ecosystem in both directions:
+//!
+//!   * Outbound requests can be sent through a [`RequestResponseClient`],
+//!     which implements `tower::Service<(PeerId, Request)>`. The client is
+//!     cheap to clone and can be used from outside of the `Swarm`, e.g.
+//!     wrapped in tower's retry, timeout or load-shed layers.
+//!
+//!   * Inbound requests are served by a `tower::Service<(PeerId, Request)>`
+//!     given to the bridge, whose responses are sent back to the remote.
+//!     Requests are only passed to the service once it reports readiness,
+//!     so backpressure applied by the service delays the processing of
+//!     further inbound requests.
+//!
+//! The bridge is itself a `NetworkBehaviour` and has to be polled by the
+//! `Swarm` for requests and responses to make progress.
+
+use crate::handler::{RequestProtocol, RequestResponseHandler, RequestResponseHandlerEvent};
+use futures::{channel::{mpsc, oneshot}, future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::{ConnectedPoint, connection::ConnectionId, Multiaddr, PeerId};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters};
+use std::{collections::{HashMap, VecDeque}, error, fmt, marker::PhantomData, task::{Context, Poll}};
+use super::{
+    InboundFailure,
+    OutboundFailure,
+    RequestId,
+    RequestResponse,
+    RequestResponseCodec,
+    RequestResponseEvent,
+    RequestResponseMessage,
+    ResponseChannel
+};
+use tower_service::Service;
+
+/// The number of outbound requests that can be buffered per client
+/// before [`Service::poll_ready`] of a [`RequestResponseClient`] reports
+/// `Poll::Pending`.
+const CLIENT_BUFFER_SIZE: usize = 32;
+
+/// An outbound request submitted through a [`RequestResponseClient`].
+struct ClientRequest<TRequest, TResponse> {
+    peer: PeerId,
+    request: TRequest,
+    sender: oneshot::Sender<Result<TResponse, OutboundFailure>>,
+}
+
+/// A `tower::Service` sending requests to peers via a [`ServiceBridge`].
+pub struct RequestResponseClient<TRequest, TResponse> {
+    sender: mpsc::Sender<ClientRequest<TRequest, TResponse>>,
+}
+
+impl<TRequest, TResponse> Clone for RequestResponseClient<TRequest, TResponse> {
+    fn clone(&self) -> Self {
+        RequestResponseClient { sender: self.sender.clone() }
+    }
+}
+
+impl<TRequest, TResponse> fmt::Debug for RequestResponseClient<TRequest, TResponse> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestResponseClient").finish()
+    }
+}
+
+impl<TRequest, TResponse> Service<(PeerId, TRequest)> for RequestResponseClient<TRequest, TResponse>
+where
+    TRequest: Send + 'static,
+    TResponse: Send + 'static
+{
+    type Response = TResponse;
+    type Error = ClientError;
+    type Future = BoxFuture<'static, Result<TResponse, ClientError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ClientError>> {
+        self.sender.poll_ready(cx).map_err(|_| ClientError::Closed)
+    }
+
+    fn call(&mut self, (peer, request): (PeerId, TRequest)) -> Self::Future {
+        let (sender, receiver) = oneshot::channel();
+        let sent = self.sender.start_send(ClientRequest { peer, request, sender });
+        async move {
+            sent.map_err(|_| ClientError::Closed)?;
+            receiver.await
+                .map_err(|_| ClientError::Closed)?
+                .map_err(ClientError::Outbound)
+        }.boxed()
+    }
+}
+
+/// Error of a request sent through a [`RequestResponseClient`].
+#[derive(Debug, Clone)]
+pub enum ClientError {
+    /// Sending the request or receiving the response failed.
+    Outbound(OutboundFailure),
+    /// The [`ServiceBridge`] has been dropped.
+    Closed,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Outbound(e) => write!(f, "Outbound request failed: {:?}", e),
+            ClientError::Closed => f.write_str("Request-response service bridge closed"),
+        }
+    }
+}
+
+impl error::Error for ClientError {}
+
+/// A service that rejects every inbound request, for use with
+/// [`ServiceBridge::client_only`].
+pub struct Reject<TResponse>(PhantomData<fn() -> TResponse>);
+
+impl<TResponse> Default for Reject<TResponse> {
+    fn default() -> Self {
+        Reject(PhantomData)
+    }
+}
+
+impl<TResponse> Clone for Reject<TResponse> {
+    fn clone(&self) -> Self {
+        Reject(PhantomData)
+    }
+}
+
+impl<TResponse> fmt::Debug for Reject<TResponse> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Reject")
+    }
+}
+
+/// The error of the [`Reject`] service.
+#[derive(Debug, Clone, Copy)]
+pub struct Rejected;
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Inbound request rejected")
+    }
+}
+
+impl error::Error for Rejected {}
+
+impl<TRequest, TResponse> Service<(PeerId, TRequest)> for Reject<TResponse> {
+    type Response = TResponse;
+    type Error = Rejected;
+    type Future = future::Ready<Result<TResponse, Rejected>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Rejected>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: (PeerId, TRequest)) -> Self::Future {
+        future::ready(Err(Rejected))
+    }
+}
+
+/// The events emitted by a [`ServiceBridge`].
+#[derive(Debug)]
+pub enum ServiceBridgeEvent<TError> {
+    /// The service failed to process an inbound request. No response is
+    /// sent to the remote, which is informed of the failure by the
+    /// substream being closed.
+    ServiceFailure {
+        /// The peer that sent the request.
+        peer: PeerId,
+        /// The error of the service.
+        error: TError,
+    },
+    /// An inbound request failed, e.g. because the connection was closed
+    /// before the response of the service could be sent.
+    InboundFailure {
+        /// The peer that sent the request.
+        peer: PeerId,
+        /// The ID of the failed inbound request.
+        request_id: RequestId,
+        /// The error that occurred.
+        error: InboundFailure,
+    },
+}
+
+/// A wrapper around [`RequestResponse`] that sends the requests of
+/// [`RequestResponseClient`]s and serves inbound requests with a
+/// `tower::Service`.
+pub struct ServiceBridge<C, S>
+where
+    C: RequestResponseCodec + Send + Clone + 'static,
+    S: Service<(PeerId, C::Request), Response = C::Response>
+{
+    /// The wrapped behaviour.
+    behaviour: RequestResponse<C>,
+    /// The service serving inbound requests.
+    service: S,
+    /// Receives the requests of all clients.
+    requests: mpsc::Receiver<ClientRequest<C::Request, C::Response>>,
+    /// Outbound requests awaiting their response.
+    pending_responses: HashMap<RequestId, oneshot::Sender<Result<C::Response, OutboundFailure>>>,
+    /// Inbound requests waiting for the service to become ready.
+    inbound: VecDeque<(PeerId, C::Request, ResponseChannel<C::Response>)>,
+    /// Inbound requests being processed by the service.
+    serving: FuturesUnordered<BoxFuture<'static, (PeerId, ResponseChannel<C::Response>, Result<C::Response, S::Error>)>>,
+}
+
+impl<C> ServiceBridge<C, Reject<C::Response>>
+where
+    C: RequestResponseCodec + Send + Clone + 'static
+{
+    /// Wraps a behaviour for sending requests only. Inbound requests
+    /// are rejected.
+    pub fn client_only(behaviour: RequestResponse<C>) -> (Self, RequestResponseClient<C::Request, C::Response>) {
+        ServiceBridge::new(behaviour, Reject::default())
+    }
+}
+
+impl<C, S> ServiceBridge<C, S>
+where
+    C: RequestResponseCodec + Send + Clone + 'static,
+    S: Service<(PeerId, C::Request), Response = C::Response>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static
+{
+    /// Wraps a behaviour, serving inbound requests with the given service.
+    ///
+    /// Returns the bridge together with a client for sending requests.
+    pub fn new(behaviour: RequestResponse<C>, service: S) -> (Self, RequestResponseClient<C::Request, C::Response>) {
+        let (sender, requests) = mpsc::channel(CLIENT_BUFFER_SIZE);
+        let bridge = ServiceBridge {
+            behaviour,
+            service,
+            requests,
+            pending_responses: HashMap::new(),
+            inbound: VecDeque::new(),
+            serving: FuturesUnordered::new(),
+        };
+        (bridge, RequestResponseClient { sender })
+    }
+
+    /// Get a reference to the wrapped behaviour.
+    pub fn behaviour(&self) -> &RequestResponse<C> {
+        &self.behaviour
+    }
+
+    /// Get a mutable reference to the wrapped behaviour, e.g. to add
+    /// addresses of peers.
+    pub fn behaviour_mut(&mut self) -> &mut RequestResponse<C> {
+        &mut self.behaviour
+    }
+
+    /// Handles an event of the wrapped behaviour, returning the event to
+    /// emit, if any.
+    fn on_event(&mut self, event: RequestResponseEvent<C::Request, C::Response>)
+        -> Option<ServiceBridgeEvent<S::Error>>
+    {
+        match event {
+            RequestResponseEvent::Message { peer, message } => match message {
+                RequestResponseMessage::Request { request, channel, .. } => {
+                    self.inbound.push_back((peer, request, channel))
+                }
+                RequestResponseMessage::Response { request_id, response } => {
+                    if let Some(sender) = self.pending_responses.remove(&request_id) {
+                        let _ = sender.send(Ok(response));
+                    }
+                }
+            },
+            RequestResponseEvent::OutboundFailure { request_id, error, .. } => {
+                if let Some(sender) = self.pending_responses.remove(&request_id) {
+                    let _ = sender.send(Err(error));
+                }
+            }
+            RequestResponseEvent::InboundFailure { peer, request_id, error } => {
+                return Some(ServiceBridgeEvent::InboundFailure { peer, request_id, error })
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+        None
+    }
+}
+
+impl<C, S> NetworkBehaviour for ServiceBridge<C, S>
+where
+    C: RequestResponseCodec + Send + Clone + 'static,
+    S: Service<(PeerId, C::Request), Response = C::Response> + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static
+{
+    type ProtocolsHandler = RequestResponseHandler<C>;
+    type OutEvent = ServiceBridgeEvent<S::Error>;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.behaviour.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, p: &PeerId) -> Vec<Multiaddr> {
+        self.behaviour.addresses_of_peer(p)
+    }
+
+    fn inject_connection_established(&mut self, p: &PeerId, id: &ConnectionId, end: &ConnectedPoint) {
+        self.behaviour.inject_connection_established(p, id, end)
+    }
+
+    fn inject_connection_closed(&mut self, p: &PeerId, id: &ConnectionId, end: &ConnectedPoint) {
+        self.behaviour.inject_connection_closed(p, id, end)
+    }
+
+    fn inject_connected(&mut self, p: &PeerId) {
+        self.behaviour.inject_connected(p)
+    }
+
+    fn inject_disconnected(&mut self, p: &PeerId) {
+        self.behaviour.inject_disconnected(p)
+    }
+
+    fn inject_dial_failure(&mut self, p: &PeerId) {
+        self.behaviour.inject_dial_failure(p)
+    }
+
+    fn inject_event(&mut self, p: PeerId, i: ConnectionId, e: RequestResponseHandlerEvent<C>) {
+        self.behaviour.inject_event(p, i, e)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<RequestProtocol<C>, Self::OutEvent>>
+    {
+        loop {
+            // Send the requests of the clients.
+            while let Poll::Ready(Some(r)) = self.requests.poll_next_unpin(cx) {
+                if r.sender.is_canceled() {
+                    continue
+                }
+                let request_id = self.behaviour.send_request(&r.peer, r.request);
+                self.pending_responses.insert(request_id, r.sender);
+            }
+
+            // Pass inbound requests to the service while it is ready.
+            while !self.inbound.is_empty() {
+                match self.service.poll_ready(cx) {
+                    Poll::Ready(Ok(())) => {
+                        let (peer, request, channel) = self.inbound.pop_front().expect("inbound is not empty");
+                        let response = self.service.call((peer, request));
+                        self.serving.push(async move { (peer, channel, response.await) }.boxed())
+                    }
+                    Poll::Ready(Err(error)) => {
+                        let (peer, _, _) = self.inbound.pop_front().expect("inbound is not empty");
+                        return Poll::Ready(NetworkBehaviourAction::GenerateEvent(
+                            ServiceBridgeEvent::ServiceFailure { peer, error }))
+                    }
+                    Poll::Pending => break
+                }
+            }
+
+            // Send the responses of the service.
+            while let Poll::Ready(Some((peer, channel, result))) = self.serving.poll_next_unpin(cx) {
+                match result {
+                    Ok(response) => {
+                        if self.behaviour.send_response(channel, response).is_err() {
+                            log::debug!("Response channel to {} closed before the service responded.", peer)
+                        }
+                    }
+                    Err(error) => return Poll::Ready(NetworkBehaviourAction::GenerateEvent(
+                        ServiceBridgeEvent::ServiceFailure { peer, error }))
+                }
+            }
+
+            let event = match self.behaviour.poll(cx, params) {
+                Poll::Ready(NetworkBehaviourAction::GenerateEvent(event)) => match self.on_event(event) {
+                    Some(event) => NetworkBehaviourAction::GenerateEvent(event),
+                    None => continue
+                },
+                Poll::Ready(NetworkBehaviourAction::DisconnectPeer { peer_id }) =>
+                    NetworkBehaviourAction::DisconnectPeer { peer_id },
+                Poll::Ready(NetworkBehaviourAction::DialAddress { address }) =>
+                    NetworkBehaviourAction::DialAddress { address },
+                Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }) =>
+                    NetworkBehaviourAction::DialPeer { peer_id, condition },
+                Poll::Ready(NetworkBehaviourAction::Dial { opts }) =>
+                    NetworkBehaviourAction::Dial { opts },
+                Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event }) =>
+                    NetworkBehaviourAction::NotifyHandler { peer_id, handler, event },
+                Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, score }) =>
+                    NetworkBehaviourAction::ReportObservedAddr { address, score },
+                Poll::Ready(NetworkBehaviourAction::ReportExternalAddr { address, source }) =>
+                    NetworkBehaviourAction::ReportExternalAddr { address, source },
+                Poll::Pending => return Poll::Pending
+            };
+
+            return Poll::Ready(event)
+        }
+    }
+}
@@ -22,21 +22,33 @@
 
 use async_trait::async_trait;
 use libp2p_core::{
+    ConnectedPoint,
     Multiaddr,
     PeerId,
+    connection::ConnectionId,
     identity,
+    multiaddr::Protocol,
     muxing::StreamMuxerBox,
+    simulation::{Simulation, SimulationHandle},
     transport::{self, Transport},
     upgrade::{self, read_one, write_one}
 };
 use libp2p_noise::{NoiseConfig, X25519Spec, Keypair};
 use libp2p_request_response::*;
-use libp2p_swarm::{Swarm, SwarmEvent};
-use libp2p_tcp::TcpConfig;
-use futures::{prelude::*, channel::mpsc, executor::LocalPool, task::SpawnExt};
+use libp2p_swarm::{
+    AddressRecord,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    PollParameters,
+    Swarm,
+    SwarmBuilder,
+    SwarmEvent,
+};
+use futures::{prelude::*, channel::oneshot};
 use rand::{self, Rng};
-use std::{io, iter};
+use std::{io, iter, task::{Context, Poll}, time::Duration};
 use std::{collections::HashSet, num::NonZeroU16};
+use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
 
 #[test]
 fn is_response_outbound() {
@@ -46,13 +58,16 @@ fn is_response_outbound() {
     let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
     let cfg = RequestResponseConfig::default();
 
-    let (peer1_id, trans) = mk_transport();
+    let mut simulation = Simulation::new(0);
+    let (peer1_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
     let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
-    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id.clone());
+    let mut swarm1 = SwarmBuilder::new(trans, ping_proto1, peer1_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
 
     let request_id1 = swarm1.send_request(&offline_peer, ping.clone());
 
-    match futures::executor::block_on(swarm1.next()) {
+    match simulation.block_on(swarm1.next()).unwrap() {
         RequestResponseEvent::OutboundFailure{peer, request_id: req_id, error: _error} => {
             assert_eq!(&offline_peer, &peer);
             assert_eq!(req_id, request_id1);
@@ -75,26 +90,30 @@ fn ping_protocol() {
     let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
     let cfg = RequestResponseConfig::default();
 
-    let (peer1_id, trans) = mk_transport();
+    let mut simulation = Simulation::new(0);
+
+    let (peer1_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
     let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
-    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id.clone());
+    let mut swarm1 = SwarmBuilder::new(trans, ping_proto1, peer1_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
 
-    let (peer2_id, trans) = mk_transport();
+    let (peer2_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
     let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg);
-    let mut swarm2 = Swarm::new(trans, ping_proto2, peer2_id.clone());
-
-    let (mut tx, mut rx) = mpsc::channel::<Multiaddr>(1);
+    let mut swarm2 = SwarmBuilder::new(trans, ping_proto2, peer2_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
 
-    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
-    Swarm::listen_on(&mut swarm1, addr).unwrap();
+    let addr = memory_addr();
+    Swarm::listen_on(&mut swarm1, addr.clone()).unwrap();
+    swarm2.add_address(&peer1_id, addr);
 
     let expected_ping = ping.clone();
     let expected_pong = pong.clone();
 
-    let peer1 = async move {
+    simulation.spawn(async move {
         loop {
             match swarm1.next_event().await {
-                SwarmEvent::NewListenAddr(addr) => tx.send(addr).await.unwrap(),
                 SwarmEvent::Behaviour(RequestResponseEvent::Message {
                     peer,
                     message: RequestResponseMessage::Request { request, channel, .. }
@@ -112,67 +131,130 @@ fn ping_protocol() {
                 _ => {}
             }
         }
-    };
+    });
 
     let num_pings: u8 = rand::thread_rng().gen_range(1, 100);
 
     let peer2 = async move {
-        let mut count = 0;
-        let addr = rx.next().await.unwrap();
-        swarm2.add_address(&peer1_id, addr.clone());
-        let mut req_id = swarm2.send_request(&peer1_id, ping.clone());
-        assert!(swarm2.is_pending_outbound(&peer1_id, &req_id));
-
-        loop {
+        for _ in 0 .. num_pings {
+            let req_id = swarm2.send_request(&peer1_id, ping.clone());
+            assert!(swarm2.is_pending_outbound(&peer1_id, &req_id));
             match swarm2.next().await {
                 RequestResponseEvent::Message {
                     peer,
                     message: RequestResponseMessage::Response { request_id, response }
                 } => {
-                    count += 1;
                     assert_eq!(&response, &expected_pong);
                     assert_eq!(&peer, &peer1_id);
                     assert_eq!(req_id, request_id);
-                    if count >= num_pings {
-                        return
-                    } else {
-                        req_id = swarm2.send_request(&peer1_id, ping.clone());
-                    }
                 },
                 e => panic!("Peer2: Unexpected event: {:?}", e)
             }
         }
     };
 
-    async_std::task::spawn(Box::pin(peer1));
-    let () = async_std::task::block_on(peer2);
+    assert_eq!(simulation.block_on(peer2), Some(()));
+    // Only establishing the connection is subject to the latency.
+    assert_eq!(simulation.now(), LATENCY);
 }
 
+/// Exercises a ping protocol with identical requests answered from the cache.
 #[test]
-fn emits_inbound_connection_closed_failure() {
+fn ping_protocol_response_cache() {
     let ping = Ping("ping".to_string().into_bytes());
+    let pong = Pong("pong".to_string().into_bytes());
 
     let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
     let cfg = RequestResponseConfig::default();
 
-    let (peer1_id, trans) = mk_transport();
-    let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
-    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id.clone());
+    let mut simulation = Simulation::new(0);
+
+    let (peer1_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
+    let mut ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
+    ping_proto1.set_response_cache(Some(ResponseCacheConfig::new(|_: &PingProtocol, ping: &Ping| {
+        Some(ping.0.iter().fold(0u64, |h, b| h.wrapping_mul(31).wrapping_add(u64::from(*b))))
+    })));
+    let mut swarm1 = SwarmBuilder::new(trans, ping_proto1, peer1_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
 
-    let (peer2_id, trans) = mk_transport();
+    let (peer2_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
     let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg);
-    let mut swarm2 = Swarm::new(trans, ping_proto2, peer2_id.clone());
+    let mut swarm2 = SwarmBuilder::new(trans, ping_proto2, peer2_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
 
-    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
-    Swarm::listen_on(&mut swarm1, addr).unwrap();
+    let addr = memory_addr();
+    Swarm::listen_on(&mut swarm1, addr.clone()).unwrap();
+    swarm2.add_address(&peer1_id, addr);
 
-    futures::executor::block_on(async move {
-        while let Some(_) = swarm1.next().now_or_never() {}
-        let addr1 = Swarm::listeners(&swarm1).next().unwrap();
+    let expected_pong = pong.clone();
+    let num_requests = Arc::new(AtomicUsize::new(0));
+    let num_requests1 = num_requests.clone();
 
-        swarm2.add_address(&peer1_id, addr1.clone());
-        swarm2.send_request(&peer1_id, ping.clone());
+    simulation.spawn(async move {
+        loop {
+            match swarm1.next_event().await {
+                SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Request { channel, .. }, ..
+                }) => {
+                    num_requests1.fetch_add(1, Ordering::SeqCst);
+                    swarm1.send_response(channel, pong.clone()).unwrap();
+                },
+                SwarmEvent::Behaviour(RequestResponseEvent::ResponseSent { .. }) => {}
+                SwarmEvent::Behaviour(e) => panic!("Peer1: Unexpected event: {:?}", e),
+                _ => {}
+            }
+        }
+    });
+
+    let num_pings: u8 = rand::thread_rng().gen_range(2, 20);
+
+    let peer2 = async move {
+        for _ in 0 .. num_pings {
+            swarm2.send_request(&peer1_id, ping.clone());
+            match swarm2.next().await {
+                RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Response { response, .. }, ..
+                } => {
+                    assert_eq!(&response, &expected_pong);
+                },
+                e => panic!("Peer2: Unexpected event: {:?}", e)
+            }
+        }
+    };
 
+    assert_eq!(simulation.block_on(peer2), Some(()));
+    assert_eq!(num_requests.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn emits_inbound_connection_closed_failure() {
+    let ping = Ping("ping".to_string().into_bytes());
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let cfg = RequestResponseConfig::default();
+
+    let mut simulation = Simulation::new(0);
+
+    let (peer1_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
+    let mut swarm1 = SwarmBuilder::new(trans, ping_proto1, peer1_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
+
+    let (peer2_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
+    let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg);
+    let mut swarm2 = SwarmBuilder::new(trans, ping_proto2, peer2_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
+
+    let addr = memory_addr();
+    Swarm::listen_on(&mut swarm1, addr.clone()).unwrap();
+    swarm2.add_address(&peer1_id, addr);
+    swarm2.send_request(&peer1_id, ping.clone());
+
+    let closed = simulation.block_on(async move {
         // Wait for swarm 1 to receive request by swarm 2.
         let _channel = loop {
             futures::select!(
@@ -199,28 +281,43 @@ fn emits_inbound_connection_closed_failure() {
             e => panic!("Peer1: Unexpected event: {:?}", e)
         }
     });
+    assert_eq!(closed, Some(()));
 }
 
 #[test]
 fn ping_protocol_throttled() {
+    throttled_ping_pong(false)
+}
+
+#[test]
+fn ping_protocol_throttled_piggyback() {
+    throttled_ping_pong(true)
+}
+
+fn throttled_ping_pong(piggyback: bool) {
     let ping = Ping("ping".to_string().into_bytes());
     let pong = Pong("pong".to_string().into_bytes());
 
     let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
     let cfg = RequestResponseConfig::default();
 
-    let (peer1_id, trans) = mk_transport();
+    let mut simulation = Simulation::new(0);
+
+    let (peer1_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
     let ping_proto1 = RequestResponse::throttled(PingCodec(), protocols.clone(), cfg.clone());
-    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id.clone());
+    let mut swarm1 = SwarmBuilder::new(trans, ping_proto1, peer1_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
 
-    let (peer2_id, trans) = mk_transport();
+    let (peer2_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
     let ping_proto2 = RequestResponse::throttled(PingCodec(), protocols, cfg);
-    let mut swarm2 = Swarm::new(trans, ping_proto2, peer2_id.clone());
-
-    let (mut tx, mut rx) = mpsc::channel::<Multiaddr>(1);
+    let mut swarm2 = SwarmBuilder::new(trans, ping_proto2, peer2_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
 
-    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
-    Swarm::listen_on(&mut swarm1, addr).unwrap();
+    let addr = memory_addr();
+    Swarm::listen_on(&mut swarm1, addr.clone()).unwrap();
+    swarm2.add_address(&peer1_id, addr);
 
     let expected_ping = ping.clone();
     let expected_pong = pong.clone();
@@ -229,11 +326,12 @@ fn ping_protocol_throttled() {
     let limit2: u16 = rand::thread_rng().gen_range(1, 10);
     swarm1.set_receive_limit(NonZeroU16::new(limit1).unwrap());
     swarm2.set_receive_limit(NonZeroU16::new(limit2).unwrap());
+    swarm1.set_piggyback_credit(piggyback);
+    swarm2.set_piggyback_credit(piggyback);
 
-    let peer1 = async move {
+    simulation.spawn(async move {
         for i in 1 .. {
             match swarm1.next_event().await {
-                SwarmEvent::NewListenAddr(addr) => tx.send(addr).await.unwrap(),
                 SwarmEvent::Behaviour(throttled::Event::Event(RequestResponseEvent::Message {
                     peer,
                     message: RequestResponseMessage::Request { request, channel, .. },
@@ -255,15 +353,12 @@ fn ping_protocol_throttled() {
                 swarm1.override_receive_limit(&peer2_id, NonZeroU16::new(lim).unwrap());
             }
         }
-    };
+    });
 
     let num_pings: u16 = rand::thread_rng().gen_range(100, 1000);
 
     let peer2 = async move {
         let mut count = 0;
-        let addr = rx.next().await.unwrap();
-        swarm2.add_address(&peer1_id, addr.clone());
-
         let mut blocked = false;
         let mut req_ids = HashSet::new();
 
@@ -297,17 +392,232 @@ fn ping_protocol_throttled() {
         }
     };
 
-    let mut pool = LocalPool::new();
-    pool.spawner().spawn(peer1.boxed()).unwrap();
-    pool.run_until(peer2);
+    assert_eq!(simulation.block_on(peer2), Some(()));
+}
+
+/// A credit grant piggybacked on a response that has been sent is not
+/// resent as a standalone credit message once the connection closes.
+#[test]
+fn ping_protocol_throttled_piggyback_delivered() {
+    let ping = Ping("ping".to_string().into_bytes());
+    let pong = Pong("pong".to_string().into_bytes());
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let cfg = RequestResponseConfig::default();
+
+    let mut simulation = Simulation::new(0);
+    let latency = Duration::from_millis(100);
+
+    let (peer1_id, trans) = mk_sim_transport(&simulation.handle(), latency);
+    let ping_proto1 = RequestResponse::throttled(PingCodec(), protocols.clone(), cfg.clone());
+    let mut swarm1 = SwarmBuilder::new(trans, ping_proto1, peer1_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
+
+    let (peer2_id, trans) = mk_sim_transport(&simulation.handle(), latency);
+    let ping_proto2 = RequestResponse::throttled(PingCodec(), protocols, cfg);
+    let mut swarm2 = SwarmBuilder::new(trans, ping_proto2, peer2_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
+
+    swarm1.set_piggyback_credit(true);
+    swarm2.set_piggyback_credit(true);
+
+    let addr1: Multiaddr = Protocol::Memory(rand::random::<u64>().saturating_add(1)).into();
+    let addr2: Multiaddr = Protocol::Memory(rand::random::<u64>().saturating_add(1)).into();
+    Swarm::listen_on(&mut swarm1, addr1.clone()).unwrap();
+    Swarm::listen_on(&mut swarm2, addr2.clone()).unwrap();
+    swarm1.add_address(&peer2_id, addr2);
+    swarm2.add_address(&peer1_id, addr1);
+
+    // Any dial of peer 1 would be for resending the credit grant.
+    let dials = Arc::new(AtomicUsize::new(0));
+    let (sent_tx, sent_rx) = oneshot::channel();
+
+    let peer1_dials = dials.clone();
+    simulation.spawn(async move {
+        let mut sent_tx = Some(sent_tx);
+        loop {
+            match swarm1.next_event().await {
+                SwarmEvent::Behaviour(throttled::Event::Event(RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Request { channel, .. }, ..
+                })) => {
+                    // The first request exhausts the receive budget, hence
+                    // the new grant is piggybacked on this response.
+                    swarm1.send_response(channel, pong.clone()).unwrap();
+                },
+                SwarmEvent::Behaviour(throttled::Event::Event(RequestResponseEvent::ResponseSent {
+                    peer, ..
+                })) => {
+                    assert_eq!(&peer, &peer2_id);
+                    if let Some(tx) = sent_tx.take() {
+                        let _ = tx.send(());
+                    }
+                }
+                SwarmEvent::Dialing(_) => {
+                    peer1_dials.fetch_add(1, Ordering::SeqCst);
+                }
+                SwarmEvent::Behaviour(e) => panic!("Peer1: Unexpected event: {:?}", e),
+                _ => {}
+            }
+        }
+    });
+
+    simulation.spawn(async move {
+        swarm2.send_request(&peer1_id, ping).unwrap();
+        let mut sent_rx = sent_rx.fuse();
+        loop {
+            futures::select! {
+                _ = sent_rx => break,
+                _ = swarm2.next_event().fuse() => {}
+            }
+        }
+        // Dropping the swarm closes the connection to peer 1.
+    });
+
+    simulation.run_for(Duration::from_secs(60));
+    assert_eq!(dials.load(Ordering::SeqCst), 0);
+}
+
+/// Exercises the ping protocol through tower services on both ends.
+#[cfg(feature = "tower")]
+#[test]
+fn ping_protocol_tower() {
+    use libp2p_request_response::tower::{ServiceBridge, ServiceBridgeEvent};
+    use std::task::{Context, Poll};
+    use tower_service::Service;
+
+    struct PongService;
+
+    impl Service<(PeerId, Ping)> for PongService {
+        type Response = Pong;
+        type Error = io::Error;
+        type Future = future::Ready<Result<Pong, io::Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, (_, Ping(data)): (PeerId, Ping)) -> Self::Future {
+            future::ok(Pong(data))
+        }
+    }
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let cfg = RequestResponseConfig::default();
+
+    let mut simulation = Simulation::new(0);
+
+    let (peer1_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
+    let (bridge1, _) = ServiceBridge::new(ping_proto1, PongService);
+    let mut swarm1 = SwarmBuilder::new(trans, bridge1, peer1_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
+
+    let (peer2_id, trans) = mk_sim_transport(&simulation.handle(), LATENCY);
+    let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg);
+    let (bridge2, mut client) = ServiceBridge::client_only(ping_proto2);
+    let mut swarm2 = SwarmBuilder::new(trans, bridge2, peer2_id)
+        .executor(Box::new(simulation.handle()))
+        .build();
+
+    let addr = memory_addr();
+    Swarm::listen_on(&mut swarm1, addr.clone()).unwrap();
+    swarm2.behaviour_mut().add_address(&peer1_id, addr);
+
+    simulation.spawn(async move {
+        loop {
+            match swarm1.next().await {
+                ServiceBridgeEvent::ServiceFailure { .. } => panic!("Peer1: Service failure"),
+                ServiceBridgeEvent::InboundFailure { error, .. } => panic!("Peer1: Inbound failure: {:?}", error),
+            }
+        }
+    });
+    simulation.spawn(async move {
+        loop {
+            let event = swarm2.next().await;
+            panic!("Peer2: Unexpected event: {:?}", event)
+        }
+    });
+
+    let pinged = simulation.block_on(async move {
+        for i in 0 .. 10u8 {
+            future::poll_fn(|cx| client.poll_ready(cx)).await.unwrap();
+            let pong = client.call((peer1_id, Ping(vec![i]))).await.unwrap();
+            assert_eq!(pong, Pong(vec![i]));
+        }
+    });
+    assert_eq!(pinged, Some(()));
+}
+
+#[test]
+fn outbound_requests_round_robin() {
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let mut behaviour = RequestResponse::new(PingCodec(), protocols, Default::default());
+
+    let peer_a = PeerId::random();
+    let peer_b = PeerId::random();
+    let endpoint = ConnectedPoint::Dialer { address: "/ip4/127.0.0.1/tcp/1".parse().unwrap() };
+    for (i, peer) in [peer_a, peer_b].iter().enumerate() {
+        behaviour.inject_connection_established(peer, &ConnectionId::new(i), &endpoint);
+        behaviour.inject_connected(peer);
+    }
+
+    for i in 0 .. 3 {
+        behaviour.send_request(&peer_a, Ping(vec![i]));
+    }
+    behaviour.send_request(&peer_b, Ping(vec![3]));
+
+    let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+    let mut order = Vec::new();
+    while let Poll::Ready(action) = behaviour.poll(&mut cx, &mut DummyPollParameters(PeerId::random())) {
+        match action {
+            NetworkBehaviourAction::NotifyHandler { peer_id, .. } => order.push(peer_id),
+            _ => panic!("Unexpected action")
+        }
+    }
+    assert_eq!(order, vec![peer_a, peer_b, peer_a, peer_a]);
+}
+
+struct DummyPollParameters(PeerId);
+
+impl PollParameters for DummyPollParameters {
+    type SupportedProtocolsIter = iter::Empty<Vec<u8>>;
+    type ListenedAddressesIter = iter::Empty<Multiaddr>;
+    type ExternalAddressesIter = iter::Empty<AddressRecord>;
+
+    fn supported_protocols(&self) -> Self::SupportedProtocolsIter {
+        iter::empty()
+    }
+
+    fn listened_addresses(&self) -> Self::ListenedAddressesIter {
+        iter::empty()
+    }
+
+    fn external_addresses(&self) -> Self::ExternalAddressesIter {
+        iter::empty()
+    }
+
+    fn local_peer_id(&self) -> &PeerId {
+        &self.0
+    }
+}
+
+/// The latency of the simulated network.
+const LATENCY: Duration = Duration::from_millis(100);
+
+fn memory_addr() -> Multiaddr {
+    Protocol::Memory(rand::random::<u64>().saturating_add(1)).into()
 }
 
-fn mk_transport() -> (PeerId, transport::Boxed<(PeerId, StreamMuxerBox)>) {
+fn mk_sim_transport(simulation: &SimulationHandle, latency: Duration)
+    -> (PeerId, transport::Boxed<(PeerId, StreamMuxerBox)>)
+{
     let id_keys = identity::Keypair::generate_ed25519();
     let peer_id = id_keys.public().into_peer_id();
     let noise_keys = Keypair::<X25519Spec>::new().into_authentic(&id_keys).unwrap();
-    (peer_id, TcpConfig::new()
-        .nodelay(true)
+    (peer_id, simulation.transport(latency)
         .upgrade(upgrade::Version::V1)
         .authenticate(NoiseConfig::xx(noise_keys).into_authenticated())
         .multiplex(libp2p_yamux::YamuxConfig::default())
@@ -0,0 +1,156 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::control::{Control, NewStream, OpenStreamError, Shared};
+use crate::handler::Handler;
+use crate::protocol::{Stream, StreamProtocol};
+use futures::{channel::mpsc, prelude::*};
+use libp2p_core::{connection::ConnectionId, Multiaddr, PeerId};
+use libp2p_swarm::{
+    DialPeerCondition,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use void::Void;
+
+/// [`NetworkBehaviour`] for opening and accepting streams of arbitrary
+/// protocols, driven through [`Control`] handles.
+///
+/// See the crate root documentation for more information.
+pub struct Behaviour {
+    shared: Arc<Mutex<Shared>>,
+    /// Sender handed to new controls.
+    requests_tx: mpsc::UnboundedSender<(PeerId, NewStream)>,
+    /// Requests of the controls for outbound streams.
+    requests_rx: mpsc::UnboundedReceiver<(PeerId, NewStream)>,
+    /// The peers with at least one established connection.
+    connected: HashSet<PeerId>,
+    /// Requests for outbound streams waiting for the peer to be dialed.
+    pending_dials: HashMap<PeerId, Vec<NewStream>>,
+    /// Queue of actions to return when polled.
+    queued_actions: VecDeque<NetworkBehaviourAction<NewStream, Void>>,
+}
+
+impl Behaviour {
+    /// Creates a new `Behaviour`.
+    pub fn new() -> Self {
+        let (requests_tx, requests_rx) = mpsc::unbounded();
+        Behaviour {
+            shared: Arc::new(Mutex::new(Shared::default())),
+            requests_tx,
+            requests_rx,
+            connected: HashSet::new(),
+            pending_dials: HashMap::new(),
+            queued_actions: VecDeque::new(),
+        }
+    }
+
+    /// Creates a new [`Control`] for opening and accepting streams.
+    pub fn new_control(&self) -> Control {
+        Control::new(self.shared.clone(), self.requests_tx.clone())
+    }
+
+    fn on_request(&mut self, peer_id: PeerId, request: NewStream) {
+        if self.connected.contains(&peer_id) {
+            self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::Any,
+                event: request,
+            });
+            return
+        }
+
+        let pending = self.pending_dials.entry(peer_id).or_default();
+        if pending.is_empty() {
+            self.queued_actions.push_back(NetworkBehaviourAction::DialPeer {
+                peer_id,
+                condition: DialPeerCondition::Disconnected,
+            });
+        }
+        pending.push(request);
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Behaviour::new()
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ProtocolsHandler = Handler;
+    type OutEvent = Void;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        Handler::new(self.shared.clone())
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, peer_id: &PeerId) {
+        self.connected.insert(*peer_id);
+
+        for request in self.pending_dials.remove(peer_id).into_iter().flatten() {
+            self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: *peer_id,
+                handler: NotifyHandler::Any,
+                event: request,
+            });
+        }
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId) {
+        self.connected.remove(peer_id);
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        for request in self.pending_dials.remove(peer_id).into_iter().flatten() {
+            let _ = request.sender.send(Err(OpenStreamError::Dial));
+        }
+    }
+
+    fn inject_event(&mut self, peer_id: PeerId, _: ConnectionId, (stream, protocol): (Stream, StreamProtocol)) {
+        self.shared.lock().expect("not poisoned").on_inbound_stream(peer_id, stream, protocol);
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<NewStream, Void>>
+    {
+        while let Poll::Ready(Some((peer_id, request))) = self.requests_rx.poll_next_unpin(cx) {
+            self.on_request(peer_id, request);
+        }
+
+        if let Some(action) = self.queued_actions.pop_front() {
+            return Poll::Ready(action)
+        }
+
+        Poll::Pending
+    }
+}
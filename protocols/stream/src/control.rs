@@ -0,0 +1,213 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::protocol::{Stream, StreamProtocol};
+use futures::{channel::{mpsc, oneshot}, prelude::*};
+use libp2p_core::PeerId;
+use std::{
+    collections::HashMap,
+    error, fmt, io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+/// Number of inbound streams of a protocol buffered for its
+/// [`IncomingStreams`]. Further streams are dropped.
+const INBOUND_BUFFER: usize = 16;
+
+/// State shared between the behaviour, its handlers and the controls.
+#[derive(Default)]
+pub(crate) struct Shared {
+    /// The protocols accepted for inbound streams.
+    supported_inbound: HashMap<StreamProtocol, mpsc::Sender<(PeerId, Stream)>>,
+}
+
+impl Shared {
+    /// The protocols currently accepted for inbound streams.
+    pub(crate) fn supported_protocols(&mut self) -> Vec<StreamProtocol> {
+        self.supported_inbound.retain(|_, sender| !sender.is_closed());
+        self.supported_inbound.keys().cloned().collect()
+    }
+
+    /// Hands an inbound stream to the [`IncomingStreams`] of its protocol.
+    pub(crate) fn on_inbound_stream(&mut self, peer: PeerId, stream: Stream, protocol: StreamProtocol) {
+        let sender = match self.supported_inbound.get_mut(&protocol) {
+            Some(sender) => sender,
+            None => {
+                log::debug!("Dropping stream of {} for unregistered protocol {}", peer, protocol);
+                return
+            }
+        };
+
+        if let Err(e) = sender.try_send((peer, stream)) {
+            if e.is_full() {
+                log::warn!("Dropping stream of {} for protocol {}: too many pending streams", peer, protocol);
+            } else {
+                self.supported_inbound.remove(&protocol);
+            }
+        }
+    }
+}
+
+/// Request of a [`Control`] for an outbound stream.
+#[derive(Debug)]
+pub struct NewStream {
+    pub(crate) protocol: StreamProtocol,
+    pub(crate) sender: oneshot::Sender<Result<Stream, OpenStreamError>>,
+}
+
+/// A handle for opening and accepting streams through a
+/// [`Behaviour`](crate::Behaviour).
+#[derive(Clone)]
+pub struct Control {
+    shared: Arc<Mutex<Shared>>,
+    requests: mpsc::UnboundedSender<(PeerId, NewStream)>,
+}
+
+impl Control {
+    pub(crate) fn new(
+        shared: Arc<Mutex<Shared>>,
+        requests: mpsc::UnboundedSender<(PeerId, NewStream)>,
+    ) -> Self {
+        Control { shared, requests }
+    }
+
+    /// Opens a stream with the given protocol to the peer, dialing it if it
+    /// is not connected.
+    pub async fn open_stream(&mut self, peer: PeerId, protocol: StreamProtocol)
+        -> Result<Stream, OpenStreamError>
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.requests
+            .unbounded_send((peer, NewStream { protocol, sender }))
+            .map_err(|_| OpenStreamError::Closed)?;
+        receiver.await.map_err(|_| OpenStreamError::Closed)?
+    }
+
+    /// Accepts inbound streams of the given protocol.
+    ///
+    /// The protocol is accepted until the returned [`IncomingStreams`] are
+    /// dropped. Each protocol can only be accepted once at a time.
+    pub fn accept(&mut self, protocol: StreamProtocol) -> Result<IncomingStreams, AlreadyRegistered> {
+        let mut shared = self.shared.lock().expect("not poisoned");
+        if shared.supported_inbound.get(&protocol).map_or(false, |s| !s.is_closed()) {
+            return Err(AlreadyRegistered)
+        }
+
+        let (sender, receiver) = mpsc::channel(INBOUND_BUFFER);
+        shared.supported_inbound.insert(protocol.clone(), sender);
+
+        Ok(IncomingStreams { protocol, shared: self.shared.clone(), receiver })
+    }
+}
+
+impl fmt::Debug for Control {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Control").finish()
+    }
+}
+
+/// The inbound streams of a protocol accepted with [`Control::accept`],
+/// together with the peers that opened them.
+pub struct IncomingStreams {
+    protocol: StreamProtocol,
+    shared: Arc<Mutex<Shared>>,
+    receiver: mpsc::Receiver<(PeerId, Stream)>,
+}
+
+impl IncomingStreams {
+    /// The protocol of the streams.
+    pub fn protocol(&self) -> &StreamProtocol {
+        &self.protocol
+    }
+}
+
+impl futures::Stream for IncomingStreams {
+    type Item = (PeerId, Stream);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl Drop for IncomingStreams {
+    fn drop(&mut self) {
+        self.receiver.close();
+        let mut shared = self.shared.lock().expect("not poisoned");
+        // The protocol may have been accepted anew in the meantime.
+        if shared.supported_inbound.get(&self.protocol).map_or(false, |s| s.is_closed()) {
+            shared.supported_inbound.remove(&self.protocol);
+        }
+    }
+}
+
+impl fmt::Debug for IncomingStreams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncomingStreams").field("protocol", &self.protocol).finish()
+    }
+}
+
+/// Error of [`Control::open_stream`].
+#[derive(Debug)]
+pub enum OpenStreamError {
+    /// The peer could not be dialed.
+    Dial,
+    /// The peer does not support the protocol.
+    UnsupportedProtocol(StreamProtocol),
+    /// The negotiation of the stream failed.
+    Io(io::Error),
+    /// The behaviour or the connection was closed before the stream was
+    /// opened.
+    Closed,
+}
+
+impl fmt::Display for OpenStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenStreamError::Dial => f.write_str("Failed to dial the peer"),
+            OpenStreamError::UnsupportedProtocol(p) => write!(f, "Peer does not support protocol {}", p),
+            OpenStreamError::Io(e) => write!(f, "Failed to negotiate the stream: {}", e),
+            OpenStreamError::Closed => f.write_str("Connection closed"),
+        }
+    }
+}
+
+impl error::Error for OpenStreamError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            OpenStreamError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Error of [`Control::accept`] for a protocol that is already accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyRegistered;
+
+impl fmt::Display for AlreadyRegistered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Protocol is already accepted")
+    }
+}
+
+impl error::Error for AlreadyRegistered {}
@@ -0,0 +1,162 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::control::{NewStream, OpenStreamError, Shared};
+use crate::protocol::{Stream, StreamProtocol, Upgrade};
+use libp2p_core::{upgrade::NegotiationError, UpgradeError};
+use libp2p_swarm::{
+    KeepAlive,
+    NegotiatedSubstream,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use void::Void;
+use wasm_timer::Instant;
+
+/// How long a connection is kept alive without any stream in use.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Protocols handler opening and accepting the streams on a single
+/// connection.
+pub struct Handler {
+    shared: Arc<Mutex<Shared>>,
+    /// Requests for outbound streams not yet passed to the connection.
+    pending_requests: VecDeque<NewStream>,
+    /// Number of outbound streams being negotiated.
+    pending_outbound: usize,
+    /// Negotiated inbound streams to hand to the behaviour.
+    inbound: VecDeque<(Stream, StreamProtocol)>,
+    /// Shared with every stream of the connection, so that the number of
+    /// streams in use is the strong count minus one.
+    active: Arc<()>,
+    /// Until when the connection is kept alive while idle.
+    idle_deadline: Instant,
+}
+
+impl Handler {
+    pub(crate) fn new(shared: Arc<Mutex<Shared>>) -> Self {
+        Handler {
+            shared,
+            pending_requests: VecDeque::new(),
+            pending_outbound: 0,
+            inbound: VecDeque::new(),
+            active: Arc::new(()),
+            idle_deadline: Instant::now() + IDLE_TIMEOUT,
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        !self.pending_requests.is_empty()
+            || self.pending_outbound > 0
+            || !self.inbound.is_empty()
+            || Arc::strong_count(&self.active) > 1
+    }
+}
+
+impl ProtocolsHandler for Handler {
+    type InEvent = NewStream;
+    type OutEvent = (Stream, StreamProtocol);
+    type Error = Void;
+    type InboundProtocol = Upgrade;
+    type OutboundProtocol = Upgrade;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = NewStream;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        let protocols = self.shared.lock().expect("not poisoned").supported_protocols();
+        SubstreamProtocol::new(Upgrade { protocols }, ())
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        (stream, protocol): (NegotiatedSubstream, StreamProtocol),
+        (): (),
+    ) {
+        self.inbound.push_back((Stream::new(stream, self.active.clone()), protocol));
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, stream: NegotiatedSubstream, request: NewStream) {
+        self.pending_outbound -= 1;
+        // The requester may have given up in the meantime.
+        let _ = request.sender.send(Ok(Stream::new(stream, self.active.clone())));
+    }
+
+    fn inject_event(&mut self, request: NewStream) {
+        self.pending_requests.push_back(request);
+    }
+
+    fn inject_dial_upgrade_error(&mut self, request: NewStream, error: ProtocolsHandlerUpgrErr<Void>) {
+        self.pending_outbound -= 1;
+        let error = match error {
+            ProtocolsHandlerUpgrErr::Timeout | ProtocolsHandlerUpgrErr::Timer =>
+                OpenStreamError::Io(io::ErrorKind::TimedOut.into()),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) =>
+                OpenStreamError::UnsupportedProtocol(request.protocol),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::ProtocolError(e))) =>
+                OpenStreamError::Io(e.into()),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(e)) => void::unreachable(e),
+        };
+        let _ = request.sender.send(Err(error));
+    }
+
+    fn inject_listen_upgrade_error(&mut self, (): (), error: ProtocolsHandlerUpgrErr<Void>) {
+        log::debug!("Inbound stream failed: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        if self.is_busy() {
+            KeepAlive::Yes
+        } else {
+            KeepAlive::Until(self.idle_deadline)
+        }
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if self.is_busy() {
+            self.idle_deadline = Instant::now() + IDLE_TIMEOUT;
+        }
+
+        if let Some(request) = self.pending_requests.pop_front() {
+            self.pending_outbound += 1;
+            let upgrade = Upgrade { protocols: vec![request.protocol.clone()] };
+            return Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(upgrade, request),
+            })
+        }
+
+        if let Some(stream) = self.inbound.pop_front() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(stream))
+        }
+
+        Poll::Pending
+    }
+}
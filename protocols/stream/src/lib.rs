@@ -0,0 +1,51 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Generic stream protocols for libp2p.
+//!
+//! The [`Behaviour`] lets application code open substreams of arbitrary
+//! protocols to peers and accept inbound substreams of registered protocols,
+//! without implementing a dedicated [`NetworkBehaviour`] and
+//! [`ProtocolsHandler`] pair. It is driven through a [`Control`] handle,
+//! which can be cloned and moved to other tasks:
+//!
+//! - [`Control::open_stream`] opens a [`Stream`] to a peer, dialing it if
+//!   it is not connected yet.
+//! - [`Control::accept`] registers a protocol and returns the
+//!   [`IncomingStreams`] of remotes opening substreams with it.
+//!
+//! What is exchanged over a [`Stream`] is entirely up to the application.
+//!
+//! > **Note**: A connection is kept alive while any of its streams is in
+//! > use. Once all of them are dropped, the connection may be closed if no
+//! > other protocol keeps it alive.
+//!
+//! [`NetworkBehaviour`]: libp2p_swarm::NetworkBehaviour
+//! [`ProtocolsHandler`]: libp2p_swarm::ProtocolsHandler
+
+mod behaviour;
+mod control;
+mod handler;
+mod protocol;
+
+pub use behaviour::Behaviour;
+pub use control::{AlreadyRegistered, Control, IncomingStreams, OpenStreamError};
+pub use protocol::{InvalidProtocol, Stream, StreamProtocol};
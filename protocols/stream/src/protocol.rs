@@ -0,0 +1,186 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use futures::{future, prelude::*};
+use libp2p_core::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{borrow::Cow, error, fmt, io, pin::Pin, sync::Arc, task::{Context, Poll}, vec};
+use void::Void;
+
+/// The name of a protocol streams are opened with, e.g. `/mwc/sync/1.0.0`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StreamProtocol(Cow<'static, str>);
+
+impl StreamProtocol {
+    /// Creates a protocol name from a static string.
+    ///
+    /// # Panics
+    ///
+    /// If the name does not start with a `/`.
+    pub fn new(name: &'static str) -> Self {
+        match StreamProtocol::try_from_owned(name) {
+            Ok(protocol) => protocol,
+            Err(InvalidProtocol) => panic!("Protocol name {:?} does not start with a '/'", name),
+        }
+    }
+
+    /// Creates a protocol name, failing if it does not start with a `/`.
+    pub fn try_from_owned(name: impl Into<Cow<'static, str>>) -> Result<Self, InvalidProtocol> {
+        let name = name.into();
+        if !name.starts_with('/') {
+            return Err(InvalidProtocol)
+        }
+        Ok(StreamProtocol(name))
+    }
+}
+
+impl AsRef<str> for StreamProtocol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for StreamProtocol {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Display for StreamProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Error of a protocol name that does not start with a `/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidProtocol;
+
+impl fmt::Display for InvalidProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Protocol name does not start with a '/'")
+    }
+}
+
+impl error::Error for InvalidProtocol {}
+
+/// A bidirectional byte stream to a peer, negotiated for a
+/// [`StreamProtocol`].
+pub struct Stream {
+    inner: NegotiatedSubstream,
+    /// Keeps the connection of the stream alive while it is in use.
+    _active: Arc<()>,
+}
+
+impl Stream {
+    pub(crate) fn new(inner: NegotiatedSubstream, active: Arc<()>) -> Self {
+        Stream { inner, _active: active }
+    }
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stream").finish()
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+
+    fn poll_read_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &mut [io::IoSliceMut<'_>])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.inner).poll_read_vectored(cx, bufs)
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Upgrade negotiating one of the given protocols on a substream, handing
+/// out the substream as is.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    pub(crate) protocols: Vec<StreamProtocol>,
+}
+
+impl UpgradeInfo for Upgrade {
+    type Info = StreamProtocol;
+    type InfoIter = vec::IntoIter<StreamProtocol>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.protocols.clone().into_iter()
+    }
+}
+
+impl<C> InboundUpgrade<C> for Upgrade {
+    type Output = (C, StreamProtocol);
+    type Error = Void;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, protocol: StreamProtocol) -> Self::Future {
+        future::ready(Ok((socket, protocol)))
+    }
+}
+
+impl<C> OutboundUpgrade<C> for Upgrade {
+    type Output = C;
+    type Error = Void;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, _: StreamProtocol) -> Self::Future {
+        future::ready(Ok(socket))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_names_start_with_slash() {
+        assert!(StreamProtocol::try_from_owned("/mwc/sync/1.0.0".to_string()).is_ok());
+        assert_eq!(StreamProtocol::try_from_owned("mwc/sync/1.0.0"), Err(InvalidProtocol));
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Integration tests for the stream behaviour.
+
+use futures::{executor::block_on, prelude::*};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{self, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_stream::{Behaviour, Control, OpenStreamError, StreamProtocol};
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+
+const ECHO_PROTOCOL: &str = "/echo/1.0.0";
+
+#[test]
+fn open_and_accept_stream() {
+    let (listener_id, listener_addr, mut listener_control) = spawn_listener();
+    let mut incoming = listener_control.accept(StreamProtocol::new(ECHO_PROTOCOL)).unwrap();
+    async_std::task::spawn(async move {
+        while let Some((_, mut stream)) = incoming.next().await {
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+            stream.close().await.unwrap();
+        }
+    });
+
+    let (dialer_id, mut dialer_control) = spawn_dialer(listener_addr);
+    assert_ne!(dialer_id, listener_id);
+
+    block_on(async {
+        let mut stream = dialer_control
+            .open_stream(listener_id, StreamProtocol::new(ECHO_PROTOCOL))
+            .await
+            .unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        stream.flush().await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello");
+    });
+}
+
+#[test]
+fn open_stream_with_unsupported_protocol() {
+    let (listener_id, listener_addr, mut listener_control) = spawn_listener();
+    let _incoming = listener_control.accept(StreamProtocol::new(ECHO_PROTOCOL)).unwrap();
+    assert!(listener_control.accept(StreamProtocol::new(ECHO_PROTOCOL)).is_err());
+
+    let (_, mut dialer_control) = spawn_dialer(listener_addr);
+
+    block_on(async {
+        match dialer_control.open_stream(listener_id, StreamProtocol::new("/unknown/1.0.0")).await {
+            Err(OpenStreamError::UnsupportedProtocol(p)) => assert_eq!(p.to_string(), "/unknown/1.0.0"),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    });
+}
+
+fn spawn_listener() -> (PeerId, Multiaddr, Control) {
+    let (peer_id, mut swarm) = build_swarm();
+    let control = swarm.new_control();
+
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut swarm, addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop { swarm.next_event().await; }
+    });
+
+    (peer_id, addr, control)
+}
+
+/// Spawns a swarm connected to the given address.
+fn spawn_dialer(addr: Multiaddr) -> (PeerId, Control) {
+    let (peer_id, mut swarm) = build_swarm();
+    let control = swarm.new_control();
+
+    Swarm::dial_addr(&mut swarm, addr).unwrap();
+    block_on(async {
+        loop {
+            if let SwarmEvent::ConnectionEstablished { .. } = swarm.next_event().await {
+                break
+            }
+        }
+    });
+    async_std::task::spawn(async move {
+        loop { swarm.next_event().await; }
+    });
+
+    (peer_id, control)
+}
+
+fn build_swarm() -> (PeerId, Swarm<Behaviour>) {
+    let local_public_key = identity::Keypair::generate_ed25519().public();
+    let peer_id = local_public_key.clone().into_peer_id();
+    let transport = upgrade_transport(MemoryTransport::default(), local_public_key);
+
+    (peer_id, Swarm::new(transport, Behaviour::new(), peer_id))
+}
+
+fn upgrade_transport<T>(transport: T, local_public_key: identity::PublicKey)
+    -> transport::Boxed<(PeerId, StreamMuxerBox)>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: futures::AsyncRead + futures::AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+{
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(yamux::YamuxConfig::default())
+        .boxed()
+}
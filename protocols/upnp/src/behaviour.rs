@@ -0,0 +1,614 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::{igd, natpmp, timeout, Protocol};
+use async_io::Timer;
+use futures::{prelude::*, future::BoxFuture, stream::FuturesUnordered};
+use libp2p_core::{Multiaddr, PeerId, connection::ConnectionId, multiaddr};
+use libp2p_swarm::{
+    AddressScore,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    PollParameters,
+    ProtocolsHandler,
+    protocols_handler::DummyProtocolsHandler
+};
+use std::{
+    cmp,
+    collections::{HashMap, VecDeque},
+    fmt,
+    io,
+    net::{Ipv4Addr, SocketAddrV4},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration
+};
+
+/// The minimum interval between two refreshes of a mapping.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The interval after which a failed mapping is retried.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The initial delay before a failed gateway discovery is retried.
+const MIN_DISCOVERY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The maximum delay between two gateway discovery attempts.
+const MAX_DISCOVERY_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Configuration for the [`Upnp`] behaviour.
+#[derive(Debug, Clone)]
+pub struct UpnpConfig {
+    lease_duration: Duration,
+    timeout: Duration,
+    description: String,
+    gateway: Option<Ipv4Addr>,
+    igd: bool,
+    natpmp: bool,
+}
+
+impl Default for UpnpConfig {
+    fn default() -> Self {
+        UpnpConfig {
+            lease_duration: Duration::from_secs(60 * 60),
+            timeout: Duration::from_secs(5),
+            description: "libp2p".to_owned(),
+            gateway: None,
+            igd: true,
+            natpmp: true,
+        }
+    }
+}
+
+impl UpnpConfig {
+    /// Creates a new configuration with default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the lease duration requested for port mappings.
+    ///
+    /// Mappings are refreshed after half of the granted lease has elapsed.
+    pub fn set_lease_duration(&mut self, d: Duration) -> &mut Self {
+        self.lease_duration = d;
+        self
+    }
+
+    /// Sets the timeout for gateway discovery and for individual requests
+    /// to the gateway.
+    pub fn set_timeout(&mut self, t: Duration) -> &mut Self {
+        self.timeout = t;
+        self
+    }
+
+    /// Sets the description attached to UPnP port mappings.
+    pub fn set_description(&mut self, d: impl Into<String>) -> &mut Self {
+        self.description = d.into();
+        self
+    }
+
+    /// Sets the address of the NAT-PMP gateway.
+    ///
+    /// By default the gateway is assumed to be the first host (`x.y.z.1`)
+    /// of the /24 network of the listen address.
+    pub fn set_gateway(&mut self, gateway: Ipv4Addr) -> &mut Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// Enables or disables UPnP IGD. Enabled by default.
+    pub fn set_igd(&mut self, enabled: bool) -> &mut Self {
+        self.igd = enabled;
+        self
+    }
+
+    /// Enables or disables NAT-PMP, which is tried if no UPnP IGD gateway
+    /// is found. Enabled by default.
+    pub fn set_natpmp(&mut self, enabled: bool) -> &mut Self {
+        self.natpmp = enabled;
+        self
+    }
+}
+
+/// Event that can be produced by the [`Upnp`] behaviour.
+#[derive(Debug)]
+pub enum UpnpEvent {
+    /// A listen port has been mapped on the gateway.
+    ///
+    /// The external address has been reported to the swarm by means of
+    /// [`NetworkBehaviourAction::ReportObservedAddr`].
+    NewExternalAddr {
+        /// The listen address that has been mapped.
+        listen_addr: Multiaddr,
+        /// The external address under which the listen address is reachable.
+        external_addr: Multiaddr,
+    },
+    /// A previously reported external address is no longer valid, either
+    /// because the listen address expired or because the mapping could not be
+    /// renewed.
+    ///
+    /// > **Note**: A `NetworkBehaviour` cannot remove external addresses of
+    /// > the swarm, use `Swarm::remove_external_address` to do so.
+    ExpiredExternalAddr {
+        /// The listen address that had been mapped.
+        listen_addr: Multiaddr,
+        /// The external address that is no longer valid.
+        external_addr: Multiaddr,
+    },
+    /// Mapping a listen port failed. The mapping is retried later.
+    MappingFailed {
+        /// The listen address that could not be mapped.
+        listen_addr: Multiaddr,
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// No gateway supporting port mapping has been found.
+    ///
+    /// The discovery is retried with exponential backoff.
+    GatewayNotFound {
+        /// The delay until the next discovery attempt.
+        retry_in: Duration,
+    },
+}
+
+/// A `NetworkBehaviour` that maps the listen ports of the local node on the
+/// local gateway and reports the resulting external addresses to the swarm.
+///
+/// Only TCP and UDP ports of listen addresses with a private IPv4 address are
+/// mapped, since other addresses are either not reachable from the outside
+/// or do not need a mapping.
+pub struct Upnp {
+    config: UpnpConfig,
+    /// The state of the gateway discovery.
+    gateway: GatewayState,
+    /// The delay before retrying the next failed gateway discovery.
+    discovery_backoff: Duration,
+    /// The mappings by listen address.
+    mappings: HashMap<Multiaddr, Mapping>,
+    /// Pending requests to create or refresh a mapping.
+    requests: FuturesUnordered<BoxFuture<'static, (Multiaddr, io::Result<SocketAddrV4>, Duration)>>,
+    /// Pending requests to remove a mapping.
+    removals: FuturesUnordered<BoxFuture<'static, ()>>,
+    /// Actions to return from `poll`.
+    events: VecDeque<NetworkBehaviourAction<void::Void, UpnpEvent>>,
+}
+
+enum GatewayState {
+    /// No discovery has been attempted yet.
+    Unknown,
+    /// A discovery is in progress.
+    Discovering(BoxFuture<'static, io::Result<Gateway>>),
+    /// A gateway has been found.
+    Available(Gateway),
+    /// No gateway has been found, waiting for the timer to retry.
+    NotFound(Timer),
+}
+
+/// A gateway supporting port mapping.
+#[derive(Debug, Clone)]
+enum Gateway {
+    Igd(igd::Gateway),
+    NatPmp(Ipv4Addr),
+}
+
+impl Gateway {
+    /// Discovers the gateway of the network of the given local address.
+    async fn discover(config: UpnpConfig, local: Ipv4Addr) -> io::Result<Self> {
+        if config.igd {
+            match timeout(config.timeout, igd::search()).await {
+                Some(Ok(gateway)) => return Ok(Gateway::Igd(gateway)),
+                Some(Err(e)) => log::debug!("UPnP IGD discovery failed: {}", e),
+                None => log::debug!("No UPnP IGD gateway found within {:?}", config.timeout)
+            }
+        }
+        if config.natpmp {
+            let gateway = config.gateway.unwrap_or_else(|| {
+                let [a, b, c, _] = local.octets();
+                Ipv4Addr::new(a, b, c, 1)
+            });
+            match natpmp::external_address(gateway).await {
+                Ok(_) => return Ok(Gateway::NatPmp(gateway)),
+                Err(e) => log::debug!("NAT-PMP discovery of {} failed: {}", gateway, e)
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, "no gateway supporting port mapping found"))
+    }
+
+    /// Maps the port of the given internal address, returning the external
+    /// address and the granted lease duration.
+    async fn add_mapping(self, protocol: Protocol, internal: SocketAddrV4, config: UpnpConfig)
+        -> io::Result<(SocketAddrV4, Duration)>
+    {
+        match self {
+            Gateway::Igd(gateway) => {
+                gateway.add_mapping(protocol, internal, config.lease_duration, &config.description).await?;
+                let ip = gateway.external_address().await?;
+                Ok((SocketAddrV4::new(ip, internal.port()), config.lease_duration))
+            }
+            Gateway::NatPmp(gateway) => {
+                let (port, lease) = natpmp::add_mapping(gateway, protocol, internal.port(), config.lease_duration).await?;
+                let ip = natpmp::external_address(gateway).await?;
+                Ok((SocketAddrV4::new(ip, port), lease))
+            }
+        }
+    }
+
+    /// Removes the mapping of the given internal address.
+    async fn remove_mapping(self, protocol: Protocol, internal: SocketAddrV4, external: SocketAddrV4) -> io::Result<()> {
+        match self {
+            Gateway::Igd(gateway) => gateway.remove_mapping(protocol, external.port()).await,
+            Gateway::NatPmp(gateway) => natpmp::remove_mapping(gateway, protocol, internal.port()).await
+        }
+    }
+}
+
+/// A listen address to be mapped.
+struct Mapping {
+    protocol: Protocol,
+    internal: SocketAddrV4,
+    /// The external address, if the mapping is active.
+    external: Option<SocketAddrV4>,
+    state: MappingState,
+}
+
+enum MappingState {
+    /// Waiting for the gateway to be discovered.
+    Pending,
+    /// A request to the gateway is in flight.
+    Requesting,
+    /// Waiting for the timer to refresh or retry the mapping.
+    Idle(Timer),
+}
+
+impl Upnp {
+    /// Creates a new `Upnp` behaviour with the given configuration.
+    pub fn new(config: UpnpConfig) -> Self {
+        Upnp {
+            config,
+            gateway: GatewayState::Unknown,
+            discovery_backoff: MIN_DISCOVERY_BACKOFF,
+            mappings: HashMap::new(),
+            requests: FuturesUnordered::new(),
+            removals: FuturesUnordered::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Returns the active mappings as pairs of listen and external address.
+    pub fn external_addresses(&self) -> impl Iterator<Item = (&Multiaddr, Multiaddr)> {
+        self.mappings.iter().filter_map(|(listen_addr, mapping)| {
+            mapping.external.map(|external| (listen_addr, external_addr(listen_addr, external)))
+        })
+    }
+
+    /// Creates a future requesting the mapping of the given listen address.
+    fn request(&self, gateway: &Gateway, listen_addr: Multiaddr, mapping: &Mapping)
+        -> BoxFuture<'static, (Multiaddr, io::Result<SocketAddrV4>, Duration)>
+    {
+        let gateway = gateway.clone();
+        let (protocol, internal) = (mapping.protocol, mapping.internal);
+        let config = self.config.clone();
+        async move {
+            let t = config.timeout;
+            match timeout(t, gateway.add_mapping(protocol, internal, config)).await {
+                Some(Ok((external, lease))) => (listen_addr, Ok(external), lease),
+                Some(Err(e)) => (listen_addr, Err(e), Duration::from_secs(0)),
+                None => (listen_addr, Err(io::ErrorKind::TimedOut.into()), Duration::from_secs(0))
+            }
+        }.boxed()
+    }
+
+    /// Queues the removal of an active mapping on the gateway.
+    fn remove(&mut self, protocol: Protocol, internal: SocketAddrV4, external: SocketAddrV4) {
+        if let GatewayState::Available(gateway) = &self.gateway {
+            let gateway = gateway.clone();
+            let t = self.config.timeout;
+            self.removals.push(async move {
+                match timeout(t, gateway.remove_mapping(protocol, internal, external)).await {
+                    Some(Ok(())) => log::debug!("Removed port mapping of {}", internal),
+                    Some(Err(e)) => log::debug!("Failed to remove port mapping of {}: {}", internal, e),
+                    None => log::debug!("Timeout removing port mapping of {}", internal)
+                }
+            }.boxed())
+        }
+    }
+
+    /// Handles the result of a mapping request.
+    fn on_mapped(&mut self, listen_addr: Multiaddr, result: io::Result<SocketAddrV4>, lease: Duration) {
+        let mapping = match self.mappings.get_mut(&listen_addr) {
+            Some(mapping) => mapping,
+            None => {
+                // The listen address expired while the request was in flight.
+                if let Ok(external) = result {
+                    let (protocol, internal) = match mappable(&listen_addr) {
+                        Some(m) => m,
+                        None => return
+                    };
+                    self.remove(protocol, internal, external)
+                }
+                return
+            }
+        };
+        match result {
+            Ok(external) => {
+                let refresh = cmp::max(lease / 2, MIN_REFRESH_INTERVAL);
+                mapping.state = MappingState::Idle(Timer::after(refresh));
+                if mapping.external == Some(external) {
+                    log::trace!("Refreshed port mapping of {} to {}", listen_addr, external);
+                    return
+                }
+                let previous = mapping.external.replace(external);
+                if let Some(previous) = previous {
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(UpnpEvent::ExpiredExternalAddr {
+                        listen_addr: listen_addr.clone(),
+                        external_addr: external_addr(&listen_addr, previous),
+                    }))
+                }
+                let address = external_addr(&listen_addr, external);
+                log::debug!("Mapped {} to {}", listen_addr, address);
+                self.events.push_back(NetworkBehaviourAction::ReportObservedAddr {
+                    address: address.clone(),
+                    score: AddressScore::Infinite,
+                });
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(UpnpEvent::NewExternalAddr {
+                    listen_addr,
+                    external_addr: address,
+                }))
+            }
+            Err(error) => {
+                log::debug!("Failed to map {}: {}", listen_addr, error);
+                mapping.state = MappingState::Idle(Timer::after(RETRY_INTERVAL));
+                if let Some(previous) = mapping.external.take() {
+                    self.events.push_back(NetworkBehaviourAction::GenerateEvent(UpnpEvent::ExpiredExternalAddr {
+                        listen_addr: listen_addr.clone(),
+                        external_addr: external_addr(&listen_addr, previous),
+                    }))
+                }
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(UpnpEvent::MappingFailed {
+                    listen_addr,
+                    error,
+                }))
+            }
+        }
+    }
+}
+
+impl Default for Upnp {
+    fn default() -> Self {
+        Upnp::new(UpnpConfig::default())
+    }
+}
+
+impl NetworkBehaviour for Upnp {
+    type ProtocolsHandler = DummyProtocolsHandler;
+    type OutEvent = UpnpEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        ev: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
+    ) {
+        void::unreachable(ev)
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        if let Some((protocol, internal)) = mappable(addr) {
+            self.mappings.entry(addr.clone()).or_insert(Mapping {
+                protocol,
+                internal,
+                external: None,
+                state: MappingState::Pending,
+            });
+        }
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        if let Some(mapping) = self.mappings.remove(addr) {
+            if let Some(external) = mapping.external {
+                self.remove(mapping.protocol, mapping.internal, external);
+                self.events.push_back(NetworkBehaviourAction::GenerateEvent(UpnpEvent::ExpiredExternalAddr {
+                    listen_addr: addr.clone(),
+                    external_addr: external_addr(addr, external),
+                }))
+            }
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<
+        NetworkBehaviourAction<
+            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+            Self::OutEvent,
+        >,
+    > {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Poll::Ready(event)
+            }
+
+            // Retry a failed discovery once the backoff has elapsed.
+            if let GatewayState::NotFound(timer) = &mut self.gateway {
+                if Pin::new(timer).poll(cx).is_ready() {
+                    self.gateway = GatewayState::Unknown
+                }
+            }
+
+            // Discover the gateway once there is something to map.
+            if let GatewayState::Unknown = self.gateway {
+                if let Some(mapping) = self.mappings.values().find(|m| matches!(m.state, MappingState::Pending)) {
+                    let discovery = Gateway::discover(self.config.clone(), *mapping.internal.ip());
+                    self.gateway = GatewayState::Discovering(discovery.boxed());
+                }
+            }
+            if let GatewayState::Discovering(discovery) = &mut self.gateway {
+                match discovery.as_mut().poll(cx) {
+                    Poll::Ready(Ok(gateway)) => {
+                        log::debug!("Discovered gateway {:?}", gateway);
+                        self.gateway = GatewayState::Available(gateway);
+                        self.discovery_backoff = MIN_DISCOVERY_BACKOFF
+                    }
+                    Poll::Ready(Err(e)) => {
+                        let retry_in = self.discovery_backoff;
+                        log::debug!("Gateway discovery failed: {}; retrying in {:?}", e, retry_in);
+                        self.gateway = GatewayState::NotFound(Timer::after(retry_in));
+                        self.discovery_backoff = next_backoff(retry_in);
+                        self.events.push_back(NetworkBehaviourAction::GenerateEvent(UpnpEvent::GatewayNotFound { retry_in }))
+                    }
+                    Poll::Pending => {}
+                }
+            }
+
+            // Create new mappings and refresh existing ones which are due.
+            if let GatewayState::Available(gateway) = &self.gateway {
+                let mut due = Vec::new();
+                for (listen_addr, mapping) in self.mappings.iter_mut() {
+                    let ready = match &mut mapping.state {
+                        MappingState::Pending => true,
+                        MappingState::Requesting => false,
+                        MappingState::Idle(timer) => Pin::new(timer).poll(cx).is_ready()
+                    };
+                    if ready {
+                        mapping.state = MappingState::Requesting;
+                        due.push(listen_addr.clone())
+                    }
+                }
+                for listen_addr in due {
+                    let request = self.request(gateway, listen_addr.clone(), &self.mappings[&listen_addr]);
+                    self.requests.push(request)
+                }
+            }
+
+            while let Poll::Ready(Some((listen_addr, result, lease))) = self.requests.poll_next_unpin(cx) {
+                self.on_mapped(listen_addr, result, lease)
+            }
+
+            while let Poll::Ready(Some(())) = self.removals.poll_next_unpin(cx) {}
+
+            if self.events.is_empty() {
+                return Poll::Pending
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Upnp {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Upnp")
+            .field("config", &self.config)
+            .field("mappings", &self.mappings.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Returns the protocol and internal socket address of a listen address
+/// that should be mapped on the gateway.
+fn mappable(addr: &Multiaddr) -> Option<(Protocol, SocketAddrV4)> {
+    let mut iter = addr.iter();
+    let ip = match iter.next()? {
+        multiaddr::Protocol::Ip4(ip) if ip.is_private() => ip,
+        _ => return None
+    };
+    match iter.next()? {
+        multiaddr::Protocol::Tcp(port) => Some((Protocol::Tcp, SocketAddrV4::new(ip, port))),
+        multiaddr::Protocol::Udp(port) => Some((Protocol::Udp, SocketAddrV4::new(ip, port))),
+        _ => None
+    }
+}
+
+/// Returns the delay before the discovery attempt following the one
+/// delayed by the given backoff.
+fn next_backoff(backoff: Duration) -> Duration {
+    cmp::min(backoff * 2, MAX_DISCOVERY_BACKOFF)
+}
+
+/// Replaces the IP address and port of a listen address with the given
+/// external address, retaining the remaining protocols.
+fn external_addr(listen_addr: &Multiaddr, external: SocketAddrV4) -> Multiaddr {
+    listen_addr.iter().enumerate().map(|(i, p)| match (i, p) {
+        (0, _) => multiaddr::Protocol::Ip4(*external.ip()),
+        (1, multiaddr::Protocol::Tcp(_)) => multiaddr::Protocol::Tcp(external.port()),
+        (1, multiaddr::Protocol::Udp(_)) => multiaddr::Protocol::Udp(external.port()),
+        (_, p) => p
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_private_ipv4_addresses_are_mapped() {
+        let addr = "/ip4/192.168.1.10/tcp/4001".parse().unwrap();
+        assert_eq!(mappable(&addr), Some((Protocol::Tcp, "192.168.1.10:4001".parse().unwrap())));
+        let addr = "/ip4/10.0.0.2/udp/4001/quic".parse().unwrap();
+        assert_eq!(mappable(&addr), Some((Protocol::Udp, "10.0.0.2:4001".parse().unwrap())));
+        for addr in &["/ip4/127.0.0.1/tcp/4001", "/ip4/1.2.3.4/tcp/4001", "/ip6/::1/tcp/4001"] {
+            assert_eq!(mappable(&addr.parse().unwrap()), None);
+        }
+    }
+
+    #[test]
+    fn external_addr_retains_protocol_stack() {
+        let listen_addr = "/ip4/192.168.1.10/tcp/4001/ws".parse().unwrap();
+        let external = external_addr(&listen_addr, "1.2.3.4:5001".parse().unwrap());
+        assert_eq!(external, "/ip4/1.2.3.4/tcp/5001/ws".parse::<Multiaddr>().unwrap());
+    }
+
+    #[test]
+    fn mapped_address_is_reported_with_external_port() {
+        let mut upnp = Upnp::default();
+        let listen_addr: Multiaddr = "/ip4/192.168.1.10/tcp/4001".parse().unwrap();
+        upnp.inject_new_listen_addr(&listen_addr);
+        upnp.on_mapped(listen_addr, Ok("1.2.3.4:5001".parse().unwrap()), Duration::from_secs(60));
+        match upnp.events.pop_front() {
+            Some(NetworkBehaviourAction::ReportObservedAddr { address, .. }) =>
+                assert_eq!(address, "/ip4/1.2.3.4/tcp/5001".parse::<Multiaddr>().unwrap()),
+            _ => panic!("expected the external address to be reported")
+        }
+    }
+
+    #[test]
+    fn discovery_backoff_is_capped() {
+        let mut backoff = MIN_DISCOVERY_BACKOFF;
+        for _ in 0 .. 10 {
+            let next = next_backoff(backoff);
+            assert!(next >= backoff && next <= MAX_DISCOVERY_BACKOFF);
+            backoff = next;
+        }
+        assert_eq!(backoff, MAX_DISCOVERY_BACKOFF);
+    }
+}
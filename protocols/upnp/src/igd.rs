@@ -0,0 +1,355 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! A minimal UPnP Internet Gateway Device client.
+//!
+//! Gateways are discovered via SSDP. Port mappings are managed through the
+//! SOAP interface of the `WANIPConnection` or `WANPPPConnection` service
+//! described in the device description of the gateway.
+
+use crate::Protocol;
+use async_io::Async;
+use futures::prelude::*;
+use std::{error, fmt, io, net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, UdpSocket}, time::Duration};
+
+/// The SSDP multicast address.
+const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+
+/// The SSDP port.
+const SSDP_PORT: u16 = 1900;
+
+/// The SSDP search request for internet gateway devices.
+const SEARCH_REQUEST: &[u8] = b"M-SEARCH * HTTP/1.1\r\n\
+    HOST: 239.255.255.250:1900\r\n\
+    ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\
+    MAN: \"ssdp:discover\"\r\n\
+    MX: 2\r\n\r\n";
+
+/// The UPnP error code of gateways that only support permanent leases.
+const ONLY_PERMANENT_LEASES_SUPPORTED: u16 = 725;
+
+/// The maximum size of an HTTP response accepted from a gateway.
+const MAX_RESPONSE_SIZE: u64 = 64 * 1024;
+
+/// A gateway offering a WAN connection service.
+#[derive(Debug, Clone)]
+pub struct Gateway {
+    /// The address of the HTTP server of the gateway.
+    addr: SocketAddrV4,
+    /// The path of the control URL of the WAN connection service.
+    control_path: String,
+    /// The type of the WAN connection service.
+    service_type: String,
+}
+
+/// Searches the local network for a gateway.
+///
+/// Keeps searching until a gateway with a usable WAN connection service
+/// responds, so the caller is expected to impose a timeout.
+pub async fn search() -> io::Result<Gateway> {
+    let socket = Async::<UdpSocket>::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))?;
+    socket.send_to(SEARCH_REQUEST, SocketAddr::from((SSDP_ADDR, SSDP_PORT))).await?;
+    let mut buf = [0u8; 2048];
+    loop {
+        let (n, from) = socket.recv_from(&mut buf).await?;
+        let location = match location(&buf[.. n]) {
+            Some(location) => location,
+            None => continue
+        };
+        match Gateway::from_location(&location).await {
+            Ok(gateway) => return Ok(gateway),
+            Err(e) => log::debug!("Ignoring UPnP device {} at {}: {}", from, location, e)
+        }
+    }
+}
+
+impl Gateway {
+    /// Fetches the device description at the given location and
+    /// looks up the WAN connection service.
+    async fn from_location(location: &str) -> io::Result<Self> {
+        let (addr, path) = parse_url(location)?;
+        let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, addr);
+        let (status, description) = http_request(addr, request).await?;
+        if status != 200 {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                format!("device description request failed with HTTP status {}", status)))
+        }
+        let (service_type, control_url) = find_service(&description)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no WAN connection service"))?;
+        let (addr, control_path) = if control_url.starts_with("http://") {
+            parse_url(&control_url)?
+        } else if control_url.starts_with('/') {
+            (addr, control_url)
+        } else {
+            (addr, format!("/{}", control_url))
+        };
+        Ok(Gateway { addr, control_path, service_type })
+    }
+
+    /// Queries the external IPv4 address of the gateway.
+    pub async fn external_address(&self) -> io::Result<Ipv4Addr> {
+        let body = self.call("GetExternalIPAddress", String::new()).await?;
+        element(&body, "NewExternalIPAddress")
+            .and_then(|ip| ip.trim().parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing external IP address"))
+    }
+
+    /// Maps the port of the given internal address to the same external port.
+    ///
+    /// Falls back to a permanent mapping if the gateway does not support leases.
+    pub async fn add_mapping(&self, protocol: Protocol, internal: SocketAddrV4, lease: Duration, description: &str)
+        -> io::Result<()>
+    {
+        let args = |lease: u64| format!(
+            "<NewRemoteHost></NewRemoteHost>\
+             <NewExternalPort>{port}</NewExternalPort>\
+             <NewProtocol>{protocol}</NewProtocol>\
+             <NewInternalPort>{port}</NewInternalPort>\
+             <NewInternalClient>{client}</NewInternalClient>\
+             <NewEnabled>1</NewEnabled>\
+             <NewPortMappingDescription>{description}</NewPortMappingDescription>\
+             <NewLeaseDuration>{lease}</NewLeaseDuration>",
+            port = internal.port(),
+            protocol = protocol,
+            client = internal.ip(),
+            description = escape(description),
+            lease = lease);
+        match self.call("AddPortMapping", args(lease.as_secs())).await {
+            Err(e) if lease.as_secs() != 0 && ActionError::code_of(&e) == Some(ONLY_PERMANENT_LEASES_SUPPORTED) => {
+                log::debug!("UPnP gateway only supports permanent leases");
+                self.call("AddPortMapping", args(0)).await.map(|_| ())
+            }
+            result => result.map(|_| ())
+        }
+    }
+
+    /// Removes the mapping of the given external port.
+    pub async fn remove_mapping(&self, protocol: Protocol, external_port: u16) -> io::Result<()> {
+        let args = format!(
+            "<NewRemoteHost></NewRemoteHost>\
+             <NewExternalPort>{}</NewExternalPort>\
+             <NewProtocol>{}</NewProtocol>",
+            external_port, protocol);
+        self.call("DeletePortMapping", args).await.map(|_| ())
+    }
+
+    /// Invokes an action of the WAN connection service and returns the response body.
+    async fn call(&self, action: &str, args: String) -> io::Result<String> {
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+             <s:Body><u:{action} xmlns:u=\"{service}\">{args}</u:{action}></s:Body>\
+             </s:Envelope>",
+            action = action,
+            service = self.service_type,
+            args = args);
+        let request = format!(
+            "POST {path} HTTP/1.0\r\n\
+             Host: {host}\r\n\
+             Content-Type: text/xml; charset=\"utf-8\"\r\n\
+             Content-Length: {len}\r\n\
+             SOAPAction: \"{service}#{action}\"\r\n\r\n{body}",
+            path = self.control_path,
+            host = self.addr,
+            len = body.len(),
+            service = self.service_type,
+            action = action,
+            body = body);
+        let (status, response) = http_request(self.addr, request).await?;
+        if status != 200 {
+            let error = ActionError::new(action, status, &response);
+            return Err(io::Error::new(io::ErrorKind::Other, error))
+        }
+        Ok(response)
+    }
+}
+
+/// The error response of a gateway to a failed action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionError {
+    /// The name of the action that failed.
+    action: String,
+    /// The HTTP status code of the response.
+    status: u16,
+    /// The UPnP error code of the SOAP fault, if any.
+    code: Option<u16>,
+}
+
+impl ActionError {
+    /// Creates an error from the status code and body of an error response.
+    fn new(action: &str, status: u16, body: &str) -> Self {
+        let code = element(body, "errorCode").and_then(|c| c.trim().parse().ok());
+        ActionError { action: action.to_owned(), status, code }
+    }
+
+    /// Returns the UPnP error code if the given error is an [`ActionError`].
+    pub fn code_of(error: &io::Error) -> Option<u16> {
+        error.get_ref()?.downcast_ref::<ActionError>()?.code
+    }
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed with HTTP status {}", self.action, self.status)?;
+        match self.code {
+            Some(code) => write!(f, " and UPnP error code {}", code),
+            None => Ok(())
+        }
+    }
+}
+
+impl error::Error for ActionError {}
+
+/// Sends an HTTP/1.0 request and returns the status code and body of the response.
+///
+/// HTTP/1.0 is used so that the response is neither chunked nor kept alive,
+/// i.e. the body extends to the end of the stream. Responses larger than
+/// [`MAX_RESPONSE_SIZE`] are rejected.
+async fn http_request(addr: SocketAddrV4, request: String) -> io::Result<(u16, String)> {
+    let mut stream = Async::<TcpStream>::connect(SocketAddr::from(addr)).await?;
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.take(MAX_RESPONSE_SIZE + 1).read_to_end(&mut response).await?;
+    if response.len() as u64 > MAX_RESPONSE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "HTTP response too large"))
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response"))?;
+    let body = response.find("\r\n\r\n")
+        .map(|i| response[i + 4 ..].to_owned())
+        .unwrap_or_default();
+    Ok((status, body))
+}
+
+/// Extracts the `LOCATION` header of an SSDP response.
+fn location(response: &[u8]) -> Option<String> {
+    let response = std::str::from_utf8(response).ok()?;
+    response.lines().find_map(|line| {
+        let i = line.find(':')?;
+        if line[.. i].trim().eq_ignore_ascii_case("location") {
+            Some(line[i + 1 ..].trim().to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// Splits an `http://` URL with an IPv4 host into socket address and path.
+fn parse_url(url: &str) -> io::Result<(SocketAddrV4, String)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("unsupported URL: {}", url));
+    if !url.starts_with("http://") {
+        return Err(invalid())
+    }
+    let rest = &url["http://".len() ..];
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[.. i], &rest[i ..]),
+        None => (rest, "/")
+    };
+    let addr = if authority.contains(':') {
+        authority.parse().map_err(|_| invalid())?
+    } else {
+        SocketAddrV4::new(authority.parse().map_err(|_| invalid())?, 80)
+    };
+    Ok((addr, path.to_owned()))
+}
+
+/// Looks up the service type and control URL of the WAN connection
+/// service in a device description.
+fn find_service(description: &str) -> Option<(String, String)> {
+    let mut rest = description;
+    while let Some(start) = rest.find("<service>") {
+        let body = &rest[start + "<service>".len() ..];
+        let end = body.find("</service>")?;
+        let service = &body[.. end];
+        if let (Some(typ), Some(control_url)) = (element(service, "serviceType"), element(service, "controlURL")) {
+            if typ.contains(":WANIPConnection:") || typ.contains(":WANPPPConnection:") {
+                return Some((typ.trim().to_owned(), control_url.trim().to_owned()))
+            }
+        }
+        rest = &body[end ..];
+    }
+    None
+}
+
+/// Returns the content of the first element with the given name.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start ..].find(&close)? + start;
+    Some(&xml[start .. end])
+}
+
+/// Escapes the XML special characters of a text node.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: &str = "<?xml version=\"1.0\"?><root><device><serviceList>\
+        <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+        <controlURL>/ctl/L3F</controlURL></service></serviceList><deviceList><device>\
+        <serviceList><service>\
+        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+        <controlURL>/ctl/IPConn</controlURL></service></serviceList>\
+        </device></deviceList></device></root>";
+
+    #[test]
+    fn finds_wan_connection_service() {
+        assert_eq!(
+            find_service(DESCRIPTION),
+            Some(("urn:schemas-upnp-org:service:WANIPConnection:1".to_owned(), "/ctl/IPConn".to_owned())));
+    }
+
+    #[test]
+    fn parses_ssdp_location() {
+        let response = b"HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\n\
+            Location: http://192.168.1.1:5000/rootDesc.xml\r\n\r\n";
+        let location = location(response).unwrap();
+        assert_eq!(location, "http://192.168.1.1:5000/rootDesc.xml");
+        assert_eq!(
+            parse_url(&location).unwrap(),
+            ("192.168.1.1:5000".parse().unwrap(), "/rootDesc.xml".to_owned()));
+        assert!(parse_url("https://192.168.1.1/").is_err());
+    }
+
+    #[test]
+    fn parses_soap_error_code() {
+        let body = "<s:Envelope><s:Body><s:Fault><faultcode>s:Client</faultcode>\
+            <faultstring>UPnPError</faultstring><detail><UPnPError>\
+            <errorCode>725</errorCode><errorDescription>OnlyPermanentLeasesSupported</errorDescription>\
+            </UPnPError></detail></s:Fault></s:Body></s:Envelope>";
+        let error = io::Error::new(io::ErrorKind::Other, ActionError::new("AddPortMapping", 500, body));
+        assert_eq!(ActionError::code_of(&error), Some(ONLY_PERMANENT_LEASES_SUPPORTED));
+        assert_eq!(error.to_string(), "AddPortMapping failed with HTTP status 500 and UPnP error code 725");
+
+        let error = io::Error::new(io::ErrorKind::Other, ActionError::new("AddPortMapping", 500, ""));
+        assert_eq!(ActionError::code_of(&error), None);
+        assert_eq!(ActionError::code_of(&io::Error::new(io::ErrorKind::Other, "725")), None);
+    }
+}
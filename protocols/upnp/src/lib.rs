@@ -0,0 +1,71 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Automatic port mapping on the local gateway.
+//!
+//! Nodes behind a NAT are in general not reachable from the outside, even if
+//! they listen on a TCP or UDP port. Most consumer routers however allow
+//! hosts of the local network to request a port mapping, either via
+//! [UPnP IGD](http://upnp.org/specs/gw/UPnP-gw-InternetGatewayDevice-v1-Device.pdf)
+//! or via [NAT-PMP](https://tools.ietf.org/html/rfc6886).
+//!
+//! # Usage
+//!
+//! This crate provides the [`Upnp`] struct which implements the `NetworkBehaviour`
+//! trait. Whenever the swarm starts listening on a private IPv4 address, the
+//! behaviour discovers the local gateway, maps the TCP or UDP listen port, keeps
+//! the lease refreshed and reports the resulting external address to the swarm.
+//! Mappings are removed again when the listen address expires.
+
+mod behaviour;
+mod igd;
+mod natpmp;
+
+pub use behaviour::{Upnp, UpnpConfig, UpnpEvent};
+
+use std::fmt;
+
+/// The transport protocol of a port mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Tcp => f.write_str("TCP"),
+            Protocol::Udp => f.write_str("UDP"),
+        }
+    }
+}
+
+/// Awaits the given future for at most the given duration.
+///
+/// Returns `None` if the future did not complete in time.
+async fn timeout<F: std::future::Future>(duration: std::time::Duration, f: F) -> Option<F::Output> {
+    futures::pin_mut!(f);
+    match futures::future::select(f, async_io::Timer::after(duration)).await {
+        futures::future::Either::Left((output, _)) => Some(output),
+        futures::future::Either::Right(_) => None,
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! A minimal NAT-PMP client as per [RFC 6886](https://tools.ietf.org/html/rfc6886).
+
+use crate::{timeout, Protocol};
+use async_io::Async;
+use std::{convert::TryFrom, io, net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket}, time::Duration};
+
+/// The UDP port NAT-PMP gateways listen on.
+const NATPMP_PORT: u16 = 5351;
+
+/// The number of times a request is sent before giving up.
+const MAX_ATTEMPTS: u32 = 4;
+
+/// The time to wait for a response to the first request, doubled
+/// for every retransmission (cf. RFC 6886, section 3.1).
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Opcode of the external address request.
+const OP_EXTERNAL_ADDRESS: u8 = 0;
+
+/// Queries the external IPv4 address of the gateway.
+pub async fn external_address(gateway: Ipv4Addr) -> io::Result<Ipv4Addr> {
+    let response = request(gateway, &[0, OP_EXTERNAL_ADDRESS], 12).await?;
+    Ok(Ipv4Addr::new(response[8], response[9], response[10], response[11]))
+}
+
+/// Requests a mapping of the given internal port for the given lease duration.
+///
+/// The gateway is asked to use the internal port as the external port as well,
+/// but it is free to choose a different one. On success, the external port
+/// and the lease duration granted by the gateway are returned.
+pub async fn add_mapping(gateway: Ipv4Addr, protocol: Protocol, internal_port: u16, lease: Duration)
+    -> io::Result<(u16, Duration)>
+{
+    let lifetime = u32::try_from(lease.as_secs()).unwrap_or(u32::MAX);
+    let response = request(gateway, &map_request(protocol, internal_port, internal_port, lifetime), 16).await?;
+    let external_port = u16::from_be_bytes([response[10], response[11]]);
+    let lifetime = u32::from_be_bytes([response[12], response[13], response[14], response[15]]);
+    Ok((external_port, Duration::from_secs(u64::from(lifetime))))
+}
+
+/// Removes the mapping of the given internal port.
+pub async fn remove_mapping(gateway: Ipv4Addr, protocol: Protocol, internal_port: u16) -> io::Result<()> {
+    request(gateway, &map_request(protocol, internal_port, 0, 0), 16).await.map(|_| ())
+}
+
+/// Returns the opcode of a mapping request for the given protocol.
+fn map_opcode(protocol: Protocol) -> u8 {
+    match protocol {
+        Protocol::Udp => 1,
+        Protocol::Tcp => 2,
+    }
+}
+
+/// Builds a mapping request. A lifetime of zero removes the mapping.
+fn map_request(protocol: Protocol, internal_port: u16, external_port: u16, lifetime: u32) -> [u8; 12] {
+    let mut msg = [0u8; 12];
+    msg[1] = map_opcode(protocol);
+    msg[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    msg[6..8].copy_from_slice(&external_port.to_be_bytes());
+    msg[8..12].copy_from_slice(&lifetime.to_be_bytes());
+    msg
+}
+
+/// Sends a request to the gateway, retransmitting it until a response arrives.
+async fn request(gateway: Ipv4Addr, msg: &[u8], response_len: usize) -> io::Result<Vec<u8>> {
+    let socket = Async::<UdpSocket>::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))?;
+    socket.get_ref().connect(SocketAddrV4::new(gateway, NATPMP_PORT))?;
+    let mut buf = [0u8; 16];
+    let mut wait = INITIAL_TIMEOUT;
+    for _ in 0 .. MAX_ATTEMPTS {
+        socket.send(msg).await?;
+        let n = match timeout(wait, socket.recv(&mut buf)).await {
+            Some(n) => n?,
+            None => {
+                wait *= 2;
+                continue
+            }
+        };
+        return parse_response(msg[1], &buf[.. n], response_len)
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "no response from NAT-PMP gateway"))
+}
+
+/// Validates the response to a request with the given opcode.
+fn parse_response(opcode: u8, response: &[u8], response_len: usize) -> io::Result<Vec<u8>> {
+    if response.len() < response_len || response[0] != 0 || response[1] != 128 + opcode {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed NAT-PMP response"))
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(response[.. response_len].to_vec()),
+        code => Err(io::Error::new(io::ErrorKind::Other, format!("NAT-PMP request failed with result code {}", code)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_request_layout() {
+        let msg = map_request(Protocol::Tcp, 4001, 4002, 3600);
+        assert_eq!(msg, [0, 2, 0, 0, 0x0f, 0xa1, 0x0f, 0xa2, 0, 0, 0x0e, 0x10]);
+    }
+
+    #[test]
+    fn parse_error_response() {
+        let response = [0, 130, 0, 2, 0, 0, 0, 1, 0x0f, 0xa1, 0, 0, 0, 0, 0, 0];
+        assert!(parse_response(2, &response, 16).is_err());
+        let response = [0, 130, 0, 0, 0, 0, 0, 1, 0x0f, 0xa1, 0x0f, 0xa1, 0, 0, 0x0e, 0x10];
+        assert_eq!(parse_response(2, &response, 16).unwrap(), response.to_vec());
+        assert!(parse_response(1, &response, 16).is_err());
+    }
+}
@@ -54,9 +54,11 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
     let protocols_handler = quote!{::libp2p::swarm::ProtocolsHandler};
     let into_proto_select_ident = quote!{::libp2p::swarm::IntoProtocolsHandlerSelect};
     let peer_id = quote!{::libp2p::core::PeerId};
+    let behaviour_summary = quote!{::libp2p::swarm::diagnostics::BehaviourSummary};
     let connection_id = quote!{::libp2p::core::connection::ConnectionId};
     let connected_point = quote!{::libp2p::core::ConnectedPoint};
     let listener_id = quote!{::libp2p::core::connection::ListenerId};
+    let switchable = quote!{::libp2p::swarm::switch::Switchable};
 
     let poll_parameters = quote!{::libp2p::swarm::PollParameters};
 
@@ -68,9 +70,20 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
         quote!{<#(#lf,)* #(#tp,)* #(#cst,)*>}
     };
 
+    // Whether we generate the out event, with a variant per field, if we find a
+    // `#[behaviour(generate_out_event)]` attribute on the struct.
+    let generate_out_event = ast.attrs.iter()
+        .filter_map(get_meta_items)
+        .flatten()
+        .any(|meta_item| match meta_item {
+            syn::NestedMeta::Meta(syn::Meta::Path(ref m)) => m.is_ident("generate_out_event"),
+            _ => false
+        });
+
     // Whether or not we require the `NetworkBehaviourEventProcess` trait to be implemented.
+    // A generated out event implies that events are bubbled up instead.
     let event_process = {
-        let mut event_process = true; // Default to true for backwards compatibility
+        let mut event_process = !generate_out_event; // Default to true for backwards compatibility
 
         for meta_items in ast.attrs.iter().filter_map(get_meta_items) {
             for meta_item in meta_items {
@@ -90,9 +103,14 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
 
     // The final out event.
     // If we find a `#[behaviour(out_event = "Foo")]` attribute on the struct, we set `Foo` as
-    // the out event. Otherwise we use `()`.
+    // the out event. Otherwise we use `()`, or `<Name>Event` if the out event is generated.
     let out_event = {
-        let mut out = quote!{()};
+        let mut out = if generate_out_event {
+            let ident = Ident::new(&format!("{}Event", name), name.span());
+            quote!{#ident}
+        } else {
+            quote!{()}
+        };
         for meta_items in ast.attrs.iter().filter_map(get_meta_items) {
             for meta_item in meta_items {
                 match meta_item {
@@ -151,6 +169,20 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
         })
     };
 
+    // Build the list of statements to put in the body of `diagnostics()`.
+    let diagnostics_stmts = {
+        data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            if is_ignored(&field) {
+                return None;
+            }
+
+            Some(match field.ident {
+                Some(ref i) => quote!{ out.extend(self.#i.diagnostics()); },
+                None => quote!{ out.extend(self.#field_n.diagnostics()); },
+            })
+        })
+    };
+
     // Build the list of statements to put in the body of `inject_connected()`.
     let inject_connected_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
@@ -286,6 +318,20 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
         })
     };
 
+    // Build the list of statements to put in the body of `inject_expired_external_addr()`.
+    let inject_expired_external_addr_stmts = {
+        data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
+            if is_ignored(&field) {
+                return None;
+            }
+
+            Some(match field.ident {
+                Some(ref i) => quote!{ self.#i.inject_expired_external_addr(addr); },
+                None => quote!{ self.#field_n.inject_expired_external_addr(addr); },
+            })
+        })
+    };
+
     // Build the list of statements to put in the body of `inject_listener_error()`.
     let inject_listener_error_stmts = {
         data_struct.fields.iter().enumerate().filter_map(move |(field_n, field)| {
@@ -441,6 +487,9 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                     std::task::Poll::Ready(#network_behaviour_action::DialPeer { peer_id, condition }) => {
                         return std::task::Poll::Ready(#network_behaviour_action::DialPeer { peer_id, condition });
                     }
+                    std::task::Poll::Ready(#network_behaviour_action::Dial { opts }) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::Dial { opts });
+                    }
                     std::task::Poll::Ready(#network_behaviour_action::DisconnectPeer { peer_id }) => {
                         return std::task::Poll::Ready(#network_behaviour_action::DisconnectPeer { peer_id });
                     }
@@ -454,12 +503,97 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                     std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address, score }) => {
                         return std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address, score });
                     }
+                    std::task::Poll::Ready(#network_behaviour_action::ReportExternalAddr { address, source }) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::ReportExternalAddr { address, source });
+                    }
                     std::task::Poll::Pending => break,
                 }
             }
         })
     });
 
+    // If some fields are marked with `#[behaviour(toggle)]`, we generate methods
+    // for enabling and disabling them by name.
+    let switch_impl = {
+        let toggle_fields = data_struct.fields.iter()
+            .enumerate()
+            .filter(|(_, field)| is_toggle(field))
+            .map(|(field_n, field)| match field.ident {
+                Some(ref i) => (i.to_string(), quote!{ self.#i }),
+                None => {
+                    let field_n = syn::Index::from(field_n);
+                    (field_n.index.to_string(), quote!{ self.#field_n })
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if toggle_fields.is_empty() {
+            quote!{}
+        } else {
+            let names = toggle_fields.iter().map(|(n, _)| n).collect::<Vec<_>>();
+            let fields = toggle_fields.iter().map(|(_, f)| f).collect::<Vec<_>>();
+            let orig_where_clause = &ast.generics.where_clause;
+            quote!{
+                impl #impl_generics #name #ty_generics #orig_where_clause {
+                    /// Enables or disables the behaviour of the field with the given name,
+                    /// which is marked with `#[behaviour(toggle)]`.
+                    ///
+                    /// Returns `false` if there is no such field.
+                    pub fn set_behaviour_enabled(&mut self, field: &str, enabled: bool) -> bool {
+                        match field {
+                            #(#names => { #switchable::set_enabled(&mut #fields, enabled); true })*
+                            _ => false
+                        }
+                    }
+
+                    /// Returns whether the behaviour of the field with the given name,
+                    /// which is marked with `#[behaviour(toggle)]`, is enabled.
+                    ///
+                    /// Returns `None` if there is no such field.
+                    pub fn is_behaviour_enabled(&self, field: &str) -> Option<bool> {
+                        match field {
+                            #(#names => Some(#switchable::is_enabled(&#fields)),)*
+                            _ => None
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    // Generate the out event with a variant per field and the conversions
+    // from the events of the fields, if requested.
+    let out_event_impl = if generate_out_event {
+        if ast.generics.type_params().next().is_some() {
+            return syn::Error::new_spanned(&ast.ident, "`generate_out_event` is not supported for generic structs")
+                .to_compile_error()
+                .into()
+        }
+        let vis = &ast.vis;
+        let doc = format!("The events emitted by [`{}`], one variant per behaviour.", name);
+        let (variants, types): (Vec<_>, Vec<_>) = data_struct.fields.iter()
+            .filter(|f| !is_ignored(f))
+            .enumerate()
+            .map(|(n, field)| (event_variant(n, field), &field.ty))
+            .unzip();
+        quote!{
+            #[doc = #doc]
+            #vis enum #out_event {
+                #(#variants(<#types as #trait_to_impl>::OutEvent),)*
+            }
+
+            #(
+                impl From<<#types as #trait_to_impl>::OutEvent> for #out_event {
+                    fn from(event: <#types as #trait_to_impl>::OutEvent) -> Self {
+                        #out_event::#variants(event)
+                    }
+                }
+            )*
+        }
+    } else {
+        quote!{}
+    };
+
     // Now the magic happens.
     let final_quote = quote!{
         impl #impl_generics #trait_to_impl for #name #ty_generics
@@ -479,6 +613,12 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                 out
             }
 
+            fn diagnostics(&self) -> Vec<#behaviour_summary> {
+                let mut out = Vec::new();
+                #(#diagnostics_stmts);*
+                out
+            }
+
             fn inject_connected(&mut self, peer_id: &#peer_id) {
                 #(#inject_connected_stmts);*
             }
@@ -519,6 +659,10 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                 #(#inject_new_external_addr_stmts);*
             }
 
+            fn inject_expired_external_addr(&mut self, addr: &#multiaddr) {
+                #(#inject_expired_external_addr_stmts);*
+            }
+
             fn inject_listener_error(&mut self, id: #listener_id, err: &(dyn std::error::Error + 'static)) {
                 #(#inject_listener_error_stmts);*
             }
@@ -545,6 +689,10 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                 f
             }
         }
+
+        #switch_impl
+
+        #out_event_impl
     };
 
     final_quote.into()
@@ -565,6 +713,41 @@ fn get_meta_items(attr: &syn::Attribute) -> Option<Vec<syn::NestedMeta>> {
     }
 }
 
+/// Returns the variant of the generated out event for a field, i.e. the name
+/// of the field in upper camel case, or `Field<n>` for tuple structs.
+fn event_variant(n: usize, field: &syn::Field) -> Ident {
+    match &field.ident {
+        Some(ident) => {
+            let variant = ident.to_string()
+                .split('_')
+                .filter(|part| !part.is_empty())
+                .map(|part| {
+                    let mut chars = part.chars();
+                    chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+                })
+                .collect::<String>();
+            Ident::new(&variant, ident.span())
+        }
+        None => Ident::new(&format!("Field{}", n), syn::spanned::Spanned::span(&field.ty)),
+    }
+}
+
+/// Returns true if a field is marked with `#[behaviour(toggle)]` by the user.
+fn is_toggle(field: &syn::Field) -> bool {
+    for meta_items in field.attrs.iter().filter_map(get_meta_items) {
+        for meta_item in meta_items {
+            match meta_item {
+                syn::NestedMeta::Meta(syn::Meta::Path(ref m)) if m.is_ident("toggle") => {
+                    return true;
+                }
+                _ => ()
+            }
+        }
+    }
+
+    false
+}
+
 /// Returns true if a field is marked as ignored by the user.
 fn is_ignored(field: &syn::Field) -> bool {
     for meta_items in field.attrs.iter().filter_map(get_meta_items) {
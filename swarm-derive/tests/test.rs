@@ -306,3 +306,82 @@ fn event_process_false() {
         };
     }
 }
+
+#[test]
+fn toggle_fields() {
+    #[allow(dead_code)]
+    #[derive(NetworkBehaviour)]
+    struct Foo {
+        ping: libp2p::ping::Ping,
+        #[behaviour(toggle)]
+        kad: libp2p::swarm::switch::Switch<libp2p::kad::Kademlia<libp2p::kad::record::store::MemoryStore>>,
+    }
+
+    impl libp2p::swarm::NetworkBehaviourEventProcess<libp2p::ping::PingEvent> for Foo {
+        fn inject_event(&mut self, _: libp2p::ping::PingEvent) {
+        }
+    }
+
+    impl libp2p::swarm::NetworkBehaviourEventProcess<libp2p::kad::KademliaEvent> for Foo {
+        fn inject_event(&mut self, _: libp2p::kad::KademliaEvent) {
+        }
+    }
+
+    let peer_id = libp2p::PeerId::random();
+    let store = libp2p::kad::record::store::MemoryStore::new(peer_id);
+    let mut foo = Foo {
+        ping: libp2p::ping::Ping::default(),
+        kad: libp2p::swarm::switch::Switch::new(libp2p::kad::Kademlia::new(peer_id, store), true),
+    };
+    require_net_behaviour::<Foo>();
+
+    assert_eq!(foo.is_behaviour_enabled("kad"), Some(true));
+    assert!(foo.set_behaviour_enabled("kad", false));
+    assert_eq!(foo.is_behaviour_enabled("kad"), Some(false));
+    assert!(!foo.kad.is_enabled());
+    assert!(!foo.set_behaviour_enabled("ping", false));
+    assert_eq!(foo.is_behaviour_enabled("ping"), None);
+}
+
+#[test]
+fn generated_out_event() {
+    #[allow(dead_code)]
+    #[derive(NetworkBehaviour)]
+    #[behaviour(generate_out_event)]
+    struct Foo {
+        ping: libp2p::ping::Ping,
+        identify: libp2p::identify::Identify,
+        #[behaviour(ignore)]
+        count: u32,
+    }
+
+    #[allow(dead_code)]
+    #[derive(NetworkBehaviour)]
+    #[behaviour(out_event = "MyEvent", generate_out_event)]
+    struct Bar {
+        ping: libp2p::ping::Ping,
+        floodsub_behaviour: libp2p::floodsub::Floodsub,
+    }
+
+    #[allow(dead_code, unreachable_code)]
+    fn bar() {
+        require_net_behaviour::<Foo>();
+        require_net_behaviour::<Bar>();
+
+        let mut _swarm: libp2p::Swarm<Foo> = unimplemented!();
+        let _ = async {
+            match _swarm.next().await {
+                FooEvent::Ping(_) => {},
+                FooEvent::Identify(_) => {},
+            }
+        };
+
+        let mut _swarm: libp2p::Swarm<Bar> = unimplemented!();
+        let _ = async {
+            match _swarm.next().await {
+                MyEvent::Ping(_) => {},
+                MyEvent::FloodsubBehaviour(_) => {},
+            }
+        };
+    }
+}
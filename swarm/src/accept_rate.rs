@@ -0,0 +1,220 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Rate limiting of inbound connections per source IP address and prefix.
+
+use libp2p_core::{Multiaddr, multiaddr::Protocol};
+use std::{collections::HashMap, net::{IpAddr, Ipv4Addr, Ipv6Addr}, time::Duration};
+use wasm_timer::Instant;
+
+/// The duration of a rate limiting window.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// The limits on the rate at which inbound connections are accepted.
+///
+/// Inbound connections exceeding a limit are dropped before the security
+/// upgrade, i.e. without any cryptographic handshake. Connections over
+/// transports without an IP address (e.g. memory or Unix domain sockets)
+/// are never limited.
+///
+/// By default, no limits are enforced.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AcceptRateLimits {
+    max_per_ip: Option<u32>,
+    max_per_prefix: Option<u32>,
+}
+
+impl AcceptRateLimits {
+    /// Configures the maximum number of inbound connections accepted per
+    /// second from a single IP address.
+    pub fn with_max_per_ip(mut self, limit: Option<u32>) -> Self {
+        self.max_per_ip = limit;
+        self
+    }
+
+    /// Configures the maximum number of inbound connections accepted per
+    /// second from a single IPv4 `/24` or IPv6 `/64` prefix.
+    pub fn with_max_per_prefix(mut self, limit: Option<u32>) -> Self {
+        self.max_per_prefix = limit;
+        self
+    }
+}
+
+/// The counters of inbound connections accepted and dropped
+/// according to the [`AcceptRateLimits`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AcceptRateStats {
+    accepted: u64,
+    dropped_per_ip: u64,
+    dropped_per_prefix: u64,
+}
+
+impl AcceptRateStats {
+    /// The number of inbound connections accepted.
+    pub fn accepted(&self) -> u64 {
+        self.accepted
+    }
+
+    /// The number of inbound connections dropped due to the limit per IP address.
+    pub fn dropped_per_ip(&self) -> u64 {
+        self.dropped_per_ip
+    }
+
+    /// The number of inbound connections dropped due to the limit per prefix.
+    pub fn dropped_per_prefix(&self) -> u64 {
+        self.dropped_per_prefix
+    }
+
+    /// The total number of inbound connections dropped.
+    pub fn dropped(&self) -> u64 {
+        self.dropped_per_ip + self.dropped_per_prefix
+    }
+}
+
+/// The source of inbound connections a limit applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Source {
+    Ip(IpAddr),
+    Prefix(IpAddr),
+}
+
+/// Enforces [`AcceptRateLimits`] over fixed windows of one second.
+#[derive(Debug)]
+pub(crate) struct AcceptRateLimiter {
+    limits: AcceptRateLimits,
+    /// The connections accepted per source in the current window.
+    counts: HashMap<Source, u32>,
+    /// The start of the current window.
+    window_start: Instant,
+    stats: AcceptRateStats,
+}
+
+impl AcceptRateLimiter {
+    pub(crate) fn new(limits: AcceptRateLimits) -> Self {
+        AcceptRateLimiter {
+            limits,
+            counts: HashMap::new(),
+            window_start: Instant::now(),
+            stats: AcceptRateStats::default(),
+        }
+    }
+
+    /// Returns the counters of accepted and dropped connections.
+    pub(crate) fn stats(&self) -> AcceptRateStats {
+        self.stats
+    }
+
+    /// Checks whether an inbound connection from the given address is to be
+    /// accepted, counting it towards the limits if so.
+    pub(crate) fn try_accept(&mut self, send_back_addr: &Multiaddr) -> bool {
+        self.try_accept_at(send_back_addr, Instant::now())
+    }
+
+    fn try_accept_at(&mut self, send_back_addr: &Multiaddr, now: Instant) -> bool {
+        let ip = match send_back_addr.iter().next() {
+            Some(Protocol::Ip4(ip)) => IpAddr::V4(ip),
+            Some(Protocol::Ip6(ip)) => IpAddr::V6(ip),
+            _ => {
+                self.stats.accepted += 1;
+                return true
+            }
+        };
+
+        if now.duration_since(self.window_start) >= WINDOW {
+            self.counts.clear();
+            self.window_start = now;
+        }
+
+        let limits = [
+            (Source::Ip(ip), self.limits.max_per_ip),
+            (Source::Prefix(prefix(ip)), self.limits.max_per_prefix),
+        ];
+
+        for (source, limit) in limits.iter() {
+            if let Some(max) = limit {
+                if self.counts.get(source).map_or(false, |n| n >= max) {
+                    match source {
+                        Source::Ip(_) => self.stats.dropped_per_ip += 1,
+                        Source::Prefix(_) => self.stats.dropped_per_prefix += 1,
+                    }
+                    return false
+                }
+            }
+        }
+
+        for (source, limit) in limits.iter() {
+            if limit.is_some() {
+                *self.counts.entry(*source).or_insert(0) += 1;
+            }
+        }
+        self.stats.accepted += 1;
+        true
+    }
+}
+
+/// Returns the IPv4 `/24` or IPv6 `/64` prefix of an IP address.
+fn prefix(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(s[0], s[1], s[2], s[3], 0, 0, 0, 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_per_ip_and_prefix() {
+        let limits = AcceptRateLimits::default()
+            .with_max_per_ip(Some(2))
+            .with_max_per_prefix(Some(3));
+        let mut limiter = AcceptRateLimiter::new(limits);
+        let now = Instant::now();
+
+        let a: Multiaddr = "/ip4/10.0.0.1/tcp/1000".parse().unwrap();
+        let b: Multiaddr = "/ip4/10.0.0.2/tcp/1000".parse().unwrap();
+        let c: Multiaddr = "/ip4/10.0.1.1/tcp/1000".parse().unwrap();
+        let mem: Multiaddr = "/memory/1234".parse().unwrap();
+
+        assert!(limiter.try_accept_at(&a, now));
+        assert!(limiter.try_accept_at(&a, now));
+        assert!(!limiter.try_accept_at(&a, now));
+        assert!(limiter.try_accept_at(&b, now));
+        assert!(!limiter.try_accept_at(&b, now));
+        assert!(limiter.try_accept_at(&c, now));
+        assert!(limiter.try_accept_at(&mem, now));
+
+        let stats = limiter.stats();
+        assert_eq!(stats.accepted(), 5);
+        assert_eq!(stats.dropped_per_ip(), 1);
+        assert_eq!(stats.dropped_per_prefix(), 1);
+
+        // The limits are reset in the next window.
+        assert!(limiter.try_accept_at(&a, now + WINDOW));
+        assert!(limiter.try_accept_at(&b, now + WINDOW));
+    }
+}
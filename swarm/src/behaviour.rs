@@ -18,7 +18,8 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::{AddressScore, AddressRecord};
+use crate::{AddressScore, AddressRecord, DialOpts, ExternalAddrSource};
+use crate::diagnostics::BehaviourSummary;
 use crate::protocols_handler::{IntoProtocolsHandler, ProtocolsHandler};
 use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, connection::{ConnectionId, ListenerId}};
 use std::{error, task::Context, task::Poll};
@@ -46,6 +47,13 @@ use std::{error, task::Context, task::Poll};
 /// Alternatively, users can specify `#[behaviour(event_process = false)]`. In this case, users
 /// should provide a custom `out_event` and implement [`From`] for each of the event types generated
 /// by the struct members.
+///
+/// With `#[behaviour(generate_out_event)]`, which implies `event_process = false`, the derive
+/// generates the out event instead: an `enum` named after the `out_event`, or `<Struct>Event`
+/// by default, with a variant per struct member named after the member in upper camel case,
+/// together with the [`From`] implementations. Members whose behaviours emit the same event
+/// type cannot be combined this way.
+///
 /// Not processing events within the derived [`NetworkBehaviour`] will cause them to be emitted as
 /// part of polling the swarm in [`SwarmEvent::Behaviour`](crate::SwarmEvent::Behaviour).
 ///
@@ -160,6 +168,11 @@ pub trait NetworkBehaviour: Send + 'static {
     fn inject_new_external_addr(&mut self, _addr: &Multiaddr) {
     }
 
+    /// Indicates to the behaviour that an external address confirmed earlier
+    /// has expired, see [`NetworkBehaviourAction::ReportExternalAddr`].
+    fn inject_expired_external_addr(&mut self, _addr: &Multiaddr) {
+    }
+
     /// A listener experienced an error.
     fn inject_listener_error(&mut self, _id: ListenerId, _err: &(dyn std::error::Error + 'static)) {
     }
@@ -168,6 +181,15 @@ pub trait NetworkBehaviour: Send + 'static {
     fn inject_listener_closed(&mut self, _id: ListenerId, _reason: Result<(), &std::io::Error>) {
     }
 
+    /// Returns summaries of the state of the behaviour, included in the
+    /// [`Diagnostics`](crate::diagnostics::Diagnostics) of the swarm.
+    ///
+    /// Behaviours composed of other behaviours return the summaries of
+    /// their members. The default implementation returns no summaries.
+    fn diagnostics(&self) -> Vec<BehaviourSummary> {
+        Vec::new()
+    }
+
     /// Polls for things that swarm should do.
     ///
     /// This API mimics the API of the `Stream` trait. The method may register the current task in
@@ -246,6 +268,18 @@ pub enum NetworkBehaviourAction<TInEvent, TOutEvent> {
         condition: DialPeerCondition,
     },
 
+    /// Instructs the swarm to dial according to the given [`DialOpts`], e.g.
+    /// via explicit addresses or with an overridden role of the local node
+    /// in the connection upgrades.
+    ///
+    /// See [`ExpandedSwarm::dial_with_opts`](crate::ExpandedSwarm::dial_with_opts).
+    /// On failure to dial a known peer, [`NetworkBehaviour::inject_dial_failure`]
+    /// is invoked.
+    Dial {
+        /// The options of the dialing attempt.
+        opts: DialOpts,
+    },
+
     /// Instructs the swarm to close connection the peer with `PeerId`.
     /// The connection status flag will be update with disconnect timeout
     DisconnectPeer {
@@ -292,6 +326,22 @@ pub enum NetworkBehaviourAction<TInEvent, TOutEvent> {
         /// relative to other observed addresses.
         score: AddressScore,
     },
+
+    /// Reports an external address of the local node from the given source,
+    /// e.g. the address observed by a remote or the result of a reachability
+    /// probe.
+    ///
+    /// Contrary to [`NetworkBehaviourAction::ReportObservedAddr`], the address
+    /// only becomes an external address once the `Swarm` is confident about
+    /// it, i.e. after enough distinct remotes reported it, and expires once
+    /// the reports are outdated. See
+    /// [`SwarmBuilder::external_addrs`](crate::SwarmBuilder::external_addrs).
+    ReportExternalAddr {
+        /// The external address of the local node.
+        address: Multiaddr,
+        /// The source of the report.
+        source: ExternalAddrSource,
+    },
 }
 
 impl<TInEvent, TOutEvent> NetworkBehaviourAction<TInEvent, TOutEvent> {
@@ -304,6 +354,8 @@ impl<TInEvent, TOutEvent> NetworkBehaviourAction<TInEvent, TOutEvent> {
                 NetworkBehaviourAction::DialAddress { address },
             NetworkBehaviourAction::DialPeer { peer_id, condition } =>
                 NetworkBehaviourAction::DialPeer { peer_id, condition },
+            NetworkBehaviourAction::Dial { opts } =>
+                NetworkBehaviourAction::Dial { opts },
             NetworkBehaviourAction::DisconnectPeer { peer_id } =>
                 NetworkBehaviourAction::DisconnectPeer { peer_id },
             NetworkBehaviourAction::NotifyHandler { peer_id, handler, event } =>
@@ -313,7 +365,9 @@ impl<TInEvent, TOutEvent> NetworkBehaviourAction<TInEvent, TOutEvent> {
                     event: f(event)
                 },
             NetworkBehaviourAction::ReportObservedAddr { address, score } =>
-                NetworkBehaviourAction::ReportObservedAddr { address, score }
+                NetworkBehaviourAction::ReportObservedAddr { address, score },
+            NetworkBehaviourAction::ReportExternalAddr { address, source } =>
+                NetworkBehaviourAction::ReportExternalAddr { address, source }
         }
     }
 
@@ -326,12 +380,16 @@ impl<TInEvent, TOutEvent> NetworkBehaviourAction<TInEvent, TOutEvent> {
                 NetworkBehaviourAction::DialAddress { address },
             NetworkBehaviourAction::DialPeer { peer_id, condition } =>
                 NetworkBehaviourAction::DialPeer { peer_id, condition },
+            NetworkBehaviourAction::Dial { opts } =>
+                NetworkBehaviourAction::Dial { opts },
             NetworkBehaviourAction::DisconnectPeer { peer_id } =>
                 NetworkBehaviourAction::DisconnectPeer { peer_id },
             NetworkBehaviourAction::NotifyHandler { peer_id, handler, event } =>
                 NetworkBehaviourAction::NotifyHandler { peer_id, handler, event },
             NetworkBehaviourAction::ReportObservedAddr { address, score } =>
-                NetworkBehaviourAction::ReportObservedAddr { address, score }
+                NetworkBehaviourAction::ReportObservedAddr { address, score },
+            NetworkBehaviourAction::ReportExternalAddr { address, source } =>
+                NetworkBehaviourAction::ReportExternalAddr { address, source }
         }
     }
 }
@@ -0,0 +1,255 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! A snapshot of the state of a [`Swarm`](crate::Swarm) for debugging.
+//!
+//! [`Swarm::diagnostics`](crate::ExpandedSwarm::diagnostics) returns the
+//! [`Diagnostics`] of a swarm: its listeners and external addresses, the
+//! established connections, the pending dials and the summaries provided
+//! by the [`NetworkBehaviour`](crate::NetworkBehaviour) through
+//! [`NetworkBehaviour::diagnostics`](crate::NetworkBehaviour::diagnostics).
+//!
+//! With the `serde` feature enabled, the [`Diagnostics`] implement
+//! `serde::Serialize`, e.g. for exposing them through an RPC endpoint.
+//! Peer IDs, connection IDs and connection stages are serialized as strings.
+
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, connection::{ConnectionId, ConnectionStage}};
+use std::{collections::HashMap, time::Duration};
+use wasm_timer::Instant;
+
+/// A snapshot of the state of a [`Swarm`](crate::Swarm).
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostics {
+    /// The ID of the local peer.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::display"))]
+    pub local_peer_id: PeerId,
+    /// The addresses the swarm listens on.
+    pub listeners: Vec<Multiaddr>,
+    /// The external addresses of the swarm, highest scored first.
+    pub external_addresses: Vec<Multiaddr>,
+    /// The established connections.
+    pub connections: Vec<ConnectionDiagnostics>,
+    /// The outgoing connection attempts in progress.
+    pub pending_dials: Vec<PendingDial>,
+    /// The summaries provided by the behaviour.
+    pub behaviour: Vec<BehaviourSummary>,
+}
+
+/// The state of an established connection.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConnectionDiagnostics {
+    /// The ID of the connection.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::debug"))]
+    pub id: ConnectionId,
+    /// The peer of the connection.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::display"))]
+    pub peer_id: PeerId,
+    /// The endpoint of the connection.
+    pub endpoint: Endpoint,
+    /// The protocols negotiated on the substreams of the connection, sorted
+    /// by name.
+    ///
+    /// Only available for connections whose stream multiplexer collects
+    /// statistics (see [`MuxerStats`](libp2p_core::muxing::MuxerStats)),
+    /// empty otherwise.
+    pub protocols: Vec<String>,
+    /// The time elapsed since the connection was established.
+    pub age: Duration,
+}
+
+/// The endpoint of a connection, i.e. a serializable [`ConnectedPoint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Endpoint {
+    /// We dialed the remote.
+    Dialer {
+        /// The address we dialed.
+        address: Multiaddr,
+    },
+    /// The remote dialed us.
+    Listener {
+        /// The local address on which the connection was accepted.
+        local_addr: Multiaddr,
+        /// The address to reach the remote, as reported by the transport.
+        send_back_addr: Multiaddr,
+    },
+}
+
+impl From<&ConnectedPoint> for Endpoint {
+    fn from(endpoint: &ConnectedPoint) -> Self {
+        match endpoint {
+            ConnectedPoint::Dialer { address } =>
+                Endpoint::Dialer { address: address.clone() },
+            ConnectedPoint::Listener { local_addr, send_back_addr } =>
+                Endpoint::Listener {
+                    local_addr: local_addr.clone(),
+                    send_back_addr: send_back_addr.clone(),
+                },
+        }
+    }
+}
+
+/// An outgoing connection attempt in progress.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PendingDial {
+    /// The ID of the pending connection.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::debug"))]
+    pub id: ConnectionId,
+    /// The peer being dialed, if known.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::display_opt"))]
+    pub peer_id: Option<PeerId>,
+    /// The address being dialed.
+    pub address: Multiaddr,
+    /// The current stage of the connection setup.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "ser::display"))]
+    pub stage: ConnectionStage,
+    /// The time elapsed since the dial started.
+    pub elapsed: Duration,
+}
+
+/// A summary of the state of a behaviour, provided through
+/// [`NetworkBehaviour::diagnostics`](crate::NetworkBehaviour::diagnostics).
+///
+/// ```
+/// use libp2p_swarm::diagnostics::BehaviourSummary;
+///
+/// let summary = BehaviourSummary::new("kad")
+///     .with_entry("routing table size", 42)
+///     .with_entry("mode", "server");
+/// assert_eq!(summary.entries.len(), 2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BehaviourSummary {
+    /// The name of the behaviour, e.g. the name of its protocol.
+    pub name: String,
+    /// Named values describing the state of the behaviour.
+    pub entries: Vec<(String, String)>,
+}
+
+impl BehaviourSummary {
+    /// Creates a summary of the behaviour with the given name without entries.
+    pub fn new(name: impl Into<String>) -> Self {
+        BehaviourSummary { name: name.into(), entries: Vec::new() }
+    }
+
+    /// Adds an entry to the summary.
+    pub fn with_entry(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.entries.push((key.into(), value.to_string()));
+        self
+    }
+}
+
+/// Records the established connections of a swarm together with the
+/// instants at which they were established.
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionRecords {
+    connections: HashMap<ConnectionId, (PeerId, ConnectedPoint, Instant)>,
+}
+
+impl ConnectionRecords {
+    /// Records a newly established connection.
+    pub(crate) fn on_established(&mut self, peer_id: PeerId, id: ConnectionId, endpoint: ConnectedPoint) {
+        self.connections.insert(id, (peer_id, endpoint, Instant::now()));
+    }
+
+    /// Records the new endpoint of a connection.
+    pub(crate) fn on_address_change(&mut self, id: &ConnectionId, new_endpoint: ConnectedPoint) {
+        if let Some((_, endpoint, _)) = self.connections.get_mut(id) {
+            *endpoint = new_endpoint;
+        }
+    }
+
+    /// Forgets a closed connection.
+    pub(crate) fn on_closed(&mut self, id: &ConnectionId) {
+        self.connections.remove(id);
+    }
+
+    /// Returns the diagnostics of the established connections, oldest
+    /// first, looking up the negotiated protocols of each connection
+    /// with the given function.
+    pub(crate) fn diagnostics<F>(&self, mut protocols: F) -> Vec<ConnectionDiagnostics>
+    where
+        F: FnMut(&ConnectionId) -> Vec<String>,
+    {
+        let mut connections = self.connections.iter()
+            .map(|(id, (peer_id, endpoint, established))| ConnectionDiagnostics {
+                id: *id,
+                peer_id: *peer_id,
+                endpoint: Endpoint::from(endpoint),
+                protocols: protocols(id),
+                age: established.elapsed(),
+            })
+            .collect::<Vec<_>>();
+        connections.sort_by(|a, b| b.age.cmp(&a.age));
+        connections
+    }
+}
+
+#[cfg(feature = "serde")]
+mod ser {
+    use serde::Serializer;
+    use std::fmt;
+
+    pub(super) fn display<T: fmt::Display, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(value)
+    }
+
+    pub(super) fn display_opt<T: fmt::Display, S: Serializer>(value: &Option<T>, s: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => s.collect_str(value),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub(super) fn debug<T: fmt::Debug, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(&format_args!("{:?}", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_established_connections() {
+        let mut records = ConnectionRecords::default();
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/1234".parse().unwrap();
+        let new_addr: Multiaddr = "/ip4/127.0.0.1/tcp/5678".parse().unwrap();
+
+        records.on_established(peer, ConnectionId::new(1), ConnectedPoint::Dialer { address: addr.clone() });
+        records.on_established(peer, ConnectionId::new(2), ConnectedPoint::Dialer { address: addr });
+        records.on_address_change(&ConnectionId::new(2), ConnectedPoint::Dialer { address: new_addr.clone() });
+        records.on_closed(&ConnectionId::new(1));
+
+        let connections = records.diagnostics(|_| vec!["/ping/1.0.0".to_string()]);
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].id, ConnectionId::new(2));
+        assert_eq!(connections[0].peer_id, peer);
+        assert_eq!(connections[0].endpoint, Endpoint::Dialer { address: new_addr });
+        assert_eq!(connections[0].protocols, vec!["/ping/1.0.0".to_string()]);
+    }
+}
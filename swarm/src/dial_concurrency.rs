@@ -0,0 +1,123 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Dialing the addresses of a peer concurrently.
+
+use libp2p_core::{Multiaddr, PeerId};
+use std::{collections::HashMap, num::NonZeroU8};
+
+/// Splits the ranked addresses of a peer into at most `factor` lanes,
+/// each of which is dialed serially by a separate dialing attempt.
+///
+/// The addresses are distributed round-robin, such that the addresses
+/// ranked first are dialed first across all lanes.
+pub(crate) fn lanes(addrs: Vec<Multiaddr>, factor: NonZeroU8) -> Vec<Vec<Multiaddr>> {
+    let num_lanes = std::cmp::min(usize::from(factor.get()), addrs.len());
+    let mut lanes = vec![Vec::new(); num_lanes];
+    for (i, addr) in addrs.into_iter().enumerate() {
+        lanes[i % num_lanes].push(addr);
+    }
+    lanes
+}
+
+/// The ongoing concurrent dialing attempts, i.e. those with more than one lane.
+#[derive(Debug, Default)]
+pub(crate) struct ConcurrentDials {
+    dials: HashMap<PeerId, ConcurrentDial>,
+}
+
+#[derive(Debug)]
+struct ConcurrentDial {
+    /// The addresses of all lanes.
+    addresses: Vec<Multiaddr>,
+    /// The number of lanes which have neither failed nor been aborted.
+    lanes: u32,
+}
+
+impl ConcurrentDials {
+    /// Records the start of a concurrent dialing attempt to a peer.
+    pub(crate) fn insert(&mut self, peer: PeerId, lanes: &[Vec<Multiaddr>]) {
+        let dial = self.dials.entry(peer)
+            .or_insert_with(|| ConcurrentDial { addresses: Vec::new(), lanes: 0 });
+        dial.addresses.extend(lanes.iter().flatten().cloned());
+        dial.lanes += lanes.len() as u32;
+    }
+
+    /// Records that a lane of a dialing attempt to a peer failed, i.e. that
+    /// all of its addresses failed, returning the number of lanes still
+    /// being dialed.
+    ///
+    /// Returns 0 for a dialing attempt that is not concurrent.
+    pub(crate) fn on_lane_failed(&mut self, peer: &PeerId) -> u32 {
+        match self.dials.get_mut(peer) {
+            Some(dial) if dial.lanes > 1 => {
+                dial.lanes -= 1;
+                dial.lanes
+            }
+            Some(_) => {
+                self.dials.remove(peer);
+                0
+            }
+            None => 0,
+        }
+    }
+
+    /// Records a new outgoing connection to a peer, returning the addresses
+    /// of the concurrent dialing attempt, if any, whose remaining lanes are
+    /// to be aborted.
+    pub(crate) fn on_established(&mut self, peer: &PeerId) -> Option<Vec<Multiaddr>> {
+        self.dials.remove(peer).map(|dial| dial.addresses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::multiaddr::Protocol;
+
+    fn addr(port: u16) -> Multiaddr {
+        Multiaddr::empty().with(Protocol::Memory(port.into()))
+    }
+
+    #[test]
+    fn addresses_distributed_round_robin() {
+        let addrs = (1 ..= 5).map(addr).collect::<Vec<_>>();
+        let lanes = lanes(addrs, NonZeroU8::new(2).unwrap());
+        assert_eq!(lanes, vec![vec![addr(1), addr(3), addr(5)], vec![addr(2), addr(4)]]);
+
+        let lanes = super::lanes(vec![addr(1)], NonZeroU8::new(3).unwrap());
+        assert_eq!(lanes, vec![vec![addr(1)]]);
+    }
+
+    #[test]
+    fn dial_fails_with_last_lane() {
+        let peer = PeerId::random();
+        let mut dials = ConcurrentDials::default();
+        dials.insert(peer, &[vec![addr(1)], vec![addr(2)], vec![addr(3)]]);
+        assert_eq!(dials.on_lane_failed(&peer), 2);
+        assert_eq!(dials.on_lane_failed(&peer), 1);
+        assert_eq!(dials.on_lane_failed(&peer), 0);
+        assert_eq!(dials.on_established(&peer), None);
+
+        dials.insert(peer, &[vec![addr(1)], vec![addr(2)]]);
+        assert_eq!(dials.on_established(&peer), Some(vec![addr(1), addr(2)]));
+    }
+}
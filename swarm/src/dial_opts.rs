@@ -0,0 +1,219 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Options for dialing a peer or an address, see [`DialOpts`].
+
+use crate::DialPeerCondition;
+use libp2p_core::{ConnectedPoint, Endpoint, Multiaddr, PeerId, network::DialParams, transport::DialCancelToken};
+use std::collections::HashMap;
+
+/// Options for a dialing attempt initiated with
+/// [`ExpandedSwarm::dial_with_opts`](crate::ExpandedSwarm::dial_with_opts).
+///
+/// ```
+/// # use libp2p_core::{Endpoint, Multiaddr, PeerId};
+/// # use libp2p_swarm::{DialOpts, DialPeerCondition};
+/// let opts = DialOpts::peer_id(PeerId::random())
+///     .addresses(vec!["/ip4/127.0.0.1/tcp/4001".parse::<Multiaddr>().unwrap()])
+///     .condition(DialPeerCondition::Disconnected)
+///     .override_role(Endpoint::Listener)
+///     .tag(7);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DialOpts {
+    pub(crate) peer_id: Option<PeerId>,
+    pub(crate) addresses: Vec<Multiaddr>,
+    pub(crate) extend_addresses_through_behaviour: bool,
+    pub(crate) condition: DialPeerCondition,
+    pub(crate) params: DialParams,
+    pub(crate) tag: Option<u64>,
+}
+
+impl DialOpts {
+    /// Dials the given peer.
+    ///
+    /// Unless explicit addresses are given with [`DialOpts::addresses`],
+    /// the addresses reported by [`NetworkBehaviour::addresses_of_peer`](crate::NetworkBehaviour::addresses_of_peer)
+    /// are dialed.
+    pub fn peer_id(peer_id: PeerId) -> Self {
+        DialOpts {
+            peer_id: Some(peer_id),
+            addresses: Vec::new(),
+            extend_addresses_through_behaviour: false,
+            condition: DialPeerCondition::Always,
+            params: DialParams::default(),
+            tag: None,
+        }
+    }
+
+    /// Dials the given address without expecting a particular remote peer ID.
+    pub fn unknown_peer_id(address: Multiaddr) -> Self {
+        DialOpts {
+            peer_id: None,
+            addresses: vec![address],
+            extend_addresses_through_behaviour: false,
+            condition: DialPeerCondition::Always,
+            params: DialParams::default(),
+            tag: None,
+        }
+    }
+
+    /// Dials the peer via the given addresses, tried in the order of the
+    /// [`DialRanking`](crate::DialRanking), instead of the addresses reported
+    /// by the behaviour.
+    ///
+    /// Ignored when dialing an address of an unknown peer.
+    pub fn addresses(mut self, addresses: Vec<Multiaddr>) -> Self {
+        if self.peer_id.is_some() {
+            self.addresses = addresses;
+        }
+        self
+    }
+
+    /// Additionally dials the peer via the addresses reported by
+    /// [`NetworkBehaviour::addresses_of_peer`](crate::NetworkBehaviour::addresses_of_peer)
+    /// when explicit addresses are given with [`DialOpts::addresses`].
+    pub fn extend_addresses_through_behaviour(mut self) -> Self {
+        self.extend_addresses_through_behaviour = true;
+        self
+    }
+
+    /// Initiates the dialing attempt only if the given condition is met,
+    /// [`DialPeerCondition::Always`] by default.
+    ///
+    /// Ignored when dialing an address of an unknown peer.
+    pub fn condition(mut self, condition: DialPeerCondition) -> Self {
+        self.condition = condition;
+        self
+    }
+
+    /// Overrides the role of the local node in the upgrades of the outgoing
+    /// connections, e.g. [`Endpoint::Listener`] for hole punching.
+    ///
+    /// See [`DialParams::with_role_override`].
+    pub fn override_role(mut self, role: Endpoint) -> Self {
+        self.params = self.params.with_role_override(role);
+        self
+    }
+
+    /// Aborts the dialing attempt when the given token is cancelled.
+    pub fn cancel(mut self, cancel: DialCancelToken) -> Self {
+        self.params = self.params.with_cancel(cancel);
+        self
+    }
+
+    /// Attaches an opaque tag to the dialing attempt, which is returned in
+    /// the [`SwarmEvent`](crate::SwarmEvent)s reporting its outcome.
+    ///
+    /// Tags are tracked per peer and, for dialing attempts to unknown peers,
+    /// per address, i.e. a new tagged dialing attempt to the same peer or
+    /// address replaces the tag of an ongoing one.
+    pub fn tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    /// Returns the peer to dial, if known.
+    pub fn get_peer_id(&self) -> Option<PeerId> {
+        self.peer_id
+    }
+}
+
+/// The tags of the ongoing dialing attempts, see [`DialOpts::tag`].
+#[derive(Debug, Default)]
+pub(crate) struct DialTags {
+    peers: HashMap<PeerId, u64>,
+    addresses: HashMap<Multiaddr, u64>,
+}
+
+impl DialTags {
+    /// Tags the dialing attempt to the given peer.
+    pub(crate) fn insert_peer(&mut self, peer: PeerId, tag: u64) {
+        self.peers.insert(peer, tag);
+    }
+
+    /// Tags the dialing attempt to the given address of an unknown peer.
+    pub(crate) fn insert_address(&mut self, address: Multiaddr, tag: u64) {
+        self.addresses.insert(address, tag);
+    }
+
+    /// Returns the tag of the dialing attempt which resulted in a new
+    /// connection, if any, completing the dialing attempt.
+    pub(crate) fn on_established(&mut self, peer: &PeerId, endpoint: &ConnectedPoint) -> Option<u64> {
+        match endpoint {
+            ConnectedPoint::Dialer { address } => self.addresses.remove(address)
+                .or_else(|| self.peers.remove(peer)),
+            ConnectedPoint::Listener { .. } => None,
+        }
+    }
+
+    /// Returns the tag of the dialing attempt to the given peer whose
+    /// connection attempt failed, if any, completing the dialing attempt
+    /// if no attempts remain.
+    pub(crate) fn on_failure(&mut self, peer: &PeerId, attempts_remaining: u32) -> Option<u64> {
+        if attempts_remaining == 0 {
+            self.peers.remove(peer)
+        } else {
+            self.peers.get(peer).copied()
+        }
+    }
+
+    /// Returns the tag of the failed dialing attempt to the given address
+    /// of an unknown peer, if any.
+    pub(crate) fn on_unknown_peer_failure(&mut self, address: &Multiaddr) -> Option<u64> {
+        self.addresses.remove(address)
+    }
+
+    /// Forgets the tag of the dialing attempt to the given peer, e.g.
+    /// because the connection was closed immediately.
+    pub(crate) fn remove_peer(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_returned_until_the_dialing_attempt_completes() {
+        let peer = PeerId::random();
+        let address: Multiaddr = "/memory/1".parse().unwrap();
+        let mut tags = DialTags::default();
+        tags.insert_peer(peer, 1);
+
+        assert_eq!(tags.on_failure(&peer, 1), Some(1));
+        let listener = ConnectedPoint::Listener {
+            local_addr: Multiaddr::empty(),
+            send_back_addr: address.clone(),
+        };
+        assert_eq!(tags.on_established(&peer, &listener), None);
+        let dialer = ConnectedPoint::Dialer { address: address.clone() };
+        assert_eq!(tags.on_established(&peer, &dialer), Some(1));
+        assert_eq!(tags.on_failure(&peer, 0), None);
+
+        tags.insert_address(address.clone(), 2);
+        tags.insert_peer(peer, 3);
+        assert_eq!(tags.on_unknown_peer_failure(&address), Some(2));
+        assert_eq!(tags.on_failure(&peer, 0), Some(3));
+        assert_eq!(tags.on_failure(&peer, 0), None);
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Ranking of the addresses of a peer by their dialing history.
+
+use libp2p_core::{Multiaddr, PeerId};
+use std::{cmp, collections::HashMap, time::Duration};
+use wasm_timer::Instant;
+
+/// The configuration of the ranking of the addresses of a peer when dialing.
+///
+/// The addresses returned by
+/// [`NetworkBehaviour::addresses_of_peer`](crate::NetworkBehaviour::addresses_of_peer)
+/// are dialed in the following order:
+///
+/// 1. by the number of consecutive failed dialing attempts, so that stale
+///    addresses are only tried after all others,
+/// 2. addresses that were dialed successfully before unknown addresses,
+/// 3. by the configured transport preference,
+/// 4. by the latency of the last successful dialing attempts.
+///
+/// Addresses ranking equally are dialed in the order returned by the
+/// behaviour. Failures older than the configured expiry are forgotten,
+/// giving addresses a new chance, e.g. after a change of the network.
+#[derive(Debug, Clone)]
+pub struct DialRanking {
+    failure_expiry: Duration,
+    max_addresses: usize,
+    transport_preference: Option<fn(&Multiaddr) -> u8>,
+}
+
+impl Default for DialRanking {
+    fn default() -> Self {
+        DialRanking {
+            failure_expiry: Duration::from_secs(30 * 60),
+            max_addresses: 4096,
+            transport_preference: None,
+        }
+    }
+}
+
+impl DialRanking {
+    /// Configures after how long failed dialing attempts of an address
+    /// are forgotten.
+    pub fn with_failure_expiry(mut self, expiry: Duration) -> Self {
+        self.failure_expiry = expiry;
+        self
+    }
+
+    /// Configures the maximum number of addresses for which the dialing
+    /// history is kept. The least recently dialed addresses are forgotten
+    /// first.
+    pub fn with_max_addresses(mut self, max: usize) -> Self {
+        self.max_addresses = max;
+        self
+    }
+
+    /// Configures the preference of transports, as a function returning
+    /// the rank of an address. Addresses of a lower rank are dialed first.
+    pub fn with_transport_preference(mut self, preference: fn(&Multiaddr) -> u8) -> Self {
+        self.transport_preference = Some(preference);
+        self
+    }
+}
+
+/// The dialing history of an address.
+#[derive(Debug, Clone)]
+pub struct AddressStats {
+    successes: u32,
+    failures: u32,
+    consecutive_failures: u32,
+    latency: Option<Duration>,
+    last_dialed: Instant,
+    /// The sequence number of the last dialing attempt, for evicting the
+    /// least recently dialed address.
+    seq: u64,
+}
+
+impl AddressStats {
+    fn new(now: Instant) -> Self {
+        AddressStats {
+            successes: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            latency: None,
+            last_dialed: now,
+            seq: 0,
+        }
+    }
+
+    /// The number of successful dialing attempts.
+    pub fn successes(&self) -> u32 {
+        self.successes
+    }
+
+    /// The number of failed dialing attempts.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// The number of failed dialing attempts since the last successful one.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// The smoothed time it took to establish a connection, if known.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+}
+
+/// The dialing history of addresses and the dialing attempts in progress.
+#[derive(Debug)]
+pub(crate) struct AddressScores {
+    config: DialRanking,
+    addresses: HashMap<Multiaddr, AddressStats>,
+    /// The start of the current attempt of each outgoing connection in progress.
+    attempts: HashMap<PeerId, Instant>,
+    /// The sequence number of the next dialing attempt.
+    next_seq: u64,
+}
+
+impl AddressScores {
+    pub(crate) fn new(config: DialRanking) -> Self {
+        AddressScores { config, addresses: HashMap::new(), attempts: HashMap::new(), next_seq: 0 }
+    }
+
+    /// Returns the dialing history of an address, if any.
+    pub(crate) fn stats(&self, addr: &Multiaddr) -> Option<&AddressStats> {
+        self.addresses.get(addr)
+    }
+
+    /// Sorts the addresses of a peer in the order in which they are dialed.
+    pub(crate) fn rank(&self, addrs: &mut Vec<Multiaddr>) {
+        let now = Instant::now();
+        let preference = self.config.transport_preference;
+        addrs.sort_by_cached_key(|addr| {
+            let preference = preference.map_or(0, |p| p(addr));
+            match self.addresses.get(addr) {
+                Some(stats) => {
+                    let failures = if now.duration_since(stats.last_dialed) < self.config.failure_expiry {
+                        stats.consecutive_failures
+                    } else {
+                        0
+                    };
+                    (failures, stats.successes == 0, preference, stats.latency.is_none(), stats.latency)
+                }
+                None => (0, true, preference, true, None),
+            }
+        });
+    }
+
+    /// Records the start of a dialing attempt to a peer.
+    pub(crate) fn on_attempt(&mut self, peer_id: &PeerId) {
+        self.attempts.insert(*peer_id, Instant::now());
+    }
+
+    /// Records a successful dialing attempt.
+    pub(crate) fn on_success(&mut self, peer_id: &PeerId, addr: &Multiaddr) {
+        let now = Instant::now();
+        let latency = self.attempts.remove(peer_id).map(|start| now.duration_since(start));
+        let stats = self.entry(addr, now);
+        stats.successes = stats.successes.saturating_add(1);
+        stats.consecutive_failures = 0;
+        if let Some(latency) = latency {
+            // Exponentially weighted moving average, favouring the history.
+            stats.latency = Some(match stats.latency {
+                Some(avg) => (avg * 3 + latency) / 4,
+                None => latency,
+            });
+        }
+    }
+
+    /// Records a failed dialing attempt.
+    ///
+    /// If the dialing attempt is continued with another address,
+    /// `attempts_remaining` is non-zero and the next attempt starts.
+    pub(crate) fn on_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, attempts_remaining: u32) {
+        let now = Instant::now();
+        let stats = self.entry(addr, now);
+        stats.failures = stats.failures.saturating_add(1);
+        stats.consecutive_failures = stats.consecutive_failures.saturating_add(1);
+        if let Some(peer_id) = peer_id {
+            if attempts_remaining > 0 {
+                self.attempts.insert(*peer_id, now);
+            } else {
+                self.attempts.remove(peer_id);
+            }
+        }
+    }
+
+    /// Returns the history of an address for recording a dialing attempt,
+    /// evicting the least recently dialed address if necessary.
+    fn entry(&mut self, addr: &Multiaddr, now: Instant) -> &mut AddressStats {
+        if !self.addresses.contains_key(addr) && self.addresses.len() >= cmp::max(self.config.max_addresses, 1) {
+            let oldest = self.addresses.iter()
+                .min_by_key(|(_, stats)| stats.seq)
+                .map(|(addr, _)| addr.clone());
+            if let Some(oldest) = oldest {
+                self.addresses.remove(&oldest);
+            }
+        }
+        let stats = self.addresses.entry(addr.clone()).or_insert_with(|| AddressStats::new(now));
+        stats.last_dialed = now;
+        stats.seq = self.next_seq;
+        self.next_seq += 1;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::multiaddr::Protocol;
+
+    fn addr(port: u16) -> Multiaddr {
+        Multiaddr::empty().with(Protocol::Memory(port.into()))
+    }
+
+    #[test]
+    fn failing_addresses_ranked_last() {
+        let peer = PeerId::random();
+        let mut scores = AddressScores::new(DialRanking::default());
+        scores.on_failure(Some(&peer), &addr(1), 2);
+        scores.on_failure(Some(&peer), &addr(2), 1);
+        scores.on_failure(Some(&peer), &addr(2), 0);
+        scores.on_attempt(&peer);
+        scores.on_success(&peer, &addr(3));
+
+        let mut addrs = vec![addr(1), addr(2), addr(4), addr(3)];
+        scores.rank(&mut addrs);
+        assert_eq!(addrs, vec![addr(3), addr(4), addr(1), addr(2)]);
+        assert_eq!(scores.stats(&addr(2)).unwrap().failures(), 2);
+        assert!(scores.stats(&addr(3)).unwrap().latency().is_some());
+
+        // A success resets the consecutive failures.
+        scores.on_success(&peer, &addr(2));
+        let stats = scores.stats(&addr(2)).unwrap();
+        assert_eq!((stats.successes(), stats.consecutive_failures()), (1, 0));
+    }
+
+    #[test]
+    fn failures_expire() {
+        let peer = PeerId::random();
+        let mut scores = AddressScores::new(DialRanking::default().with_failure_expiry(Duration::from_secs(0)));
+        scores.on_failure(Some(&peer), &addr(1), 0);
+        let mut addrs = vec![addr(1), addr(2)];
+        scores.rank(&mut addrs);
+        assert_eq!(addrs, vec![addr(1), addr(2)]);
+    }
+
+    #[test]
+    fn transport_preference() {
+        fn prefer_even(addr: &Multiaddr) -> u8 {
+            match addr.iter().next() {
+                Some(Protocol::Memory(port)) => (port % 2) as u8,
+                _ => u8::MAX,
+            }
+        }
+        let scores = AddressScores::new(DialRanking::default().with_transport_preference(prefer_even));
+        let mut addrs = vec![addr(1), addr(2), addr(3), addr(4)];
+        scores.rank(&mut addrs);
+        assert_eq!(addrs, vec![addr(2), addr(4), addr(1), addr(3)]);
+    }
+
+    #[test]
+    fn least_recently_dialed_evicted() {
+        let mut scores = AddressScores::new(DialRanking::default().with_max_addresses(2));
+        scores.on_failure(None, &addr(1), 0);
+        scores.on_failure(None, &addr(2), 0);
+        scores.on_failure(None, &addr(1), 0);
+        scores.on_failure(None, &addr(3), 0);
+        assert!(scores.stats(&addr(1)).is_some());
+        assert!(scores.stats(&addr(2)).is_none());
+        assert!(scores.stats(&addr(3)).is_some());
+    }
+}
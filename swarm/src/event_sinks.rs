@@ -0,0 +1,234 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Additional consumers of the events of the swarm.
+
+use futures::{channel::mpsc, prelude::*, stream::FusedStream};
+use std::{pin::Pin, sync::{Arc, atomic::{AtomicU64, Ordering}}, task::{Context, Poll}};
+
+/// How an event sink handles events while its channel is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkPolicy {
+    /// The swarm stops making progress until the channel has room again,
+    /// i.e. the consumer of the sink exerts backpressure on the swarm.
+    Block,
+    /// Events are dropped while the channel is full, counted by
+    /// [`EventReceiver::dropped`].
+    DropNewest,
+}
+
+/// The receiving end of an event sink, see
+/// [`ExpandedSwarm::add_event_sink`](crate::ExpandedSwarm::add_event_sink).
+///
+/// Dropping the receiver removes the sink from the swarm.
+#[derive(Debug)]
+pub struct EventReceiver<T> {
+    receiver: mpsc::Receiver<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> EventReceiver<T> {
+    /// The number of events dropped because the channel was full,
+    /// if the sink has the policy [`SinkPolicy::DropNewest`].
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Stream for EventReceiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl<T> FusedStream for EventReceiver<T> {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
+
+/// An event sink, as seen by the swarm.
+trait DispatchSink<E>: Send {
+    /// Prepares the delivery of an event.
+    fn start(&mut self, event: &E);
+
+    /// Delivers the prepared event, if any.
+    ///
+    /// Returns `false` if the receiver has been dropped.
+    fn poll_deliver(&mut self, cx: &mut Context<'_>) -> Poll<bool>;
+}
+
+struct ChannelSink<T, F> {
+    map: F,
+    policy: SinkPolicy,
+    sender: mpsc::Sender<T>,
+    dropped: Arc<AtomicU64>,
+    /// The mapped event awaiting delivery.
+    pending: Option<T>,
+}
+
+impl<E, T, F> DispatchSink<E> for ChannelSink<T, F>
+where
+    F: FnMut(&E) -> Option<T> + Send,
+    T: Send,
+{
+    fn start(&mut self, event: &E) {
+        self.pending = (self.map)(event);
+    }
+
+    fn poll_deliver(&mut self, cx: &mut Context<'_>) -> Poll<bool> {
+        let item = match self.pending.take() {
+            Some(item) => item,
+            None => return Poll::Ready(true),
+        };
+        match self.policy {
+            SinkPolicy::Block => match self.sender.poll_ready(cx) {
+                Poll::Ready(Ok(())) => Poll::Ready(self.sender.start_send(item).is_ok()),
+                Poll::Ready(Err(_)) => Poll::Ready(false),
+                Poll::Pending => {
+                    self.pending = Some(item);
+                    Poll::Pending
+                }
+            },
+            SinkPolicy::DropNewest => match self.sender.try_send(item) {
+                Ok(()) => Poll::Ready(true),
+                Err(e) if e.is_full() => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Poll::Ready(true)
+                }
+                Err(_) => Poll::Ready(false),
+            },
+        }
+    }
+}
+
+/// The event sinks registered with the swarm.
+pub(crate) struct EventSinks<E> {
+    sinks: Vec<Box<dyn DispatchSink<E>>>,
+}
+
+impl<E> Default for EventSinks<E> {
+    fn default() -> Self {
+        EventSinks { sinks: Vec::new() }
+    }
+}
+
+impl<E> EventSinks<E> {
+    /// Adds a sink, returning its receiver.
+    ///
+    /// The channel holds at least `capacity` events.
+    pub(crate) fn add<T, F>(&mut self, capacity: usize, policy: SinkPolicy, map: F) -> EventReceiver<T>
+    where
+        F: FnMut(&E) -> Option<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        self.sinks.push(Box::new(ChannelSink {
+            map,
+            policy,
+            sender,
+            dropped: dropped.clone(),
+            pending: None,
+        }));
+        EventReceiver { receiver, dropped }
+    }
+
+    /// The number of sinks whose receivers have not been noticed as dropped yet.
+    pub(crate) fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Prepares the delivery of a new event to all sinks.
+    pub(crate) fn start(&mut self, event: &E) {
+        for sink in &mut self.sinks {
+            sink.start(event)
+        }
+    }
+
+    /// Delivers the prepared event to all sinks, removing sinks whose
+    /// receivers have been dropped.
+    ///
+    /// Returns `Poll::Pending` as long as a sink with the policy
+    /// [`SinkPolicy::Block`] has no room for the event.
+    pub(crate) fn poll_deliver(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut blocked = false;
+        let mut i = 0;
+        while i < self.sinks.len() {
+            match self.sinks[i].poll_deliver(cx) {
+                Poll::Ready(true) => i += 1,
+                Poll::Ready(false) => {
+                    self.sinks.swap_remove(i);
+                }
+                Poll::Pending => {
+                    blocked = true;
+                    i += 1
+                }
+            }
+        }
+        if blocked {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker_ref;
+
+    fn deliver(sinks: &mut EventSinks<u32>, event: u32) -> Poll<()> {
+        sinks.start(&event);
+        sinks.poll_deliver(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    #[test]
+    fn drop_newest_counts_dropped_events() {
+        let mut sinks = EventSinks::default();
+        let mut even = sinks.add(0, SinkPolicy::DropNewest, |n: &u32| Some(*n).filter(|n| n % 2 == 0));
+        for n in 0 .. 6 {
+            assert_eq!(deliver(&mut sinks, n), Poll::Ready(()));
+        }
+        // The channel has room for a single event per sender.
+        assert_eq!(even.next().now_or_never(), Some(Some(0)));
+        assert_eq!(even.dropped(), 2);
+
+        drop(even);
+        assert_eq!(deliver(&mut sinks, 6), Poll::Ready(()));
+        assert_eq!(sinks.len(), 0);
+    }
+
+    #[test]
+    fn block_exerts_backpressure() {
+        let mut sinks = EventSinks::default();
+        let mut all = sinks.add(0, SinkPolicy::Block, |n: &u32| Some(*n));
+        assert_eq!(deliver(&mut sinks, 1), Poll::Ready(()));
+        assert_eq!(deliver(&mut sinks, 2), Poll::Pending);
+        assert_eq!(all.next().now_or_never(), Some(Some(1)));
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert_eq!(sinks.poll_deliver(&mut cx), Poll::Ready(()));
+        assert_eq!(all.next().now_or_never(), Some(Some(2)));
+        assert_eq!(all.dropped(), 0);
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Management of the external addresses of the local node by confidence.
+//!
+//! Reports about external addresses, i.e. addresses observed by remotes,
+//! results of reachability probes and explicitly configured addresses, are
+//! aggregated per address into a confidence score. An address is confirmed,
+//! i.e. added to the external addresses of the `Swarm`, once its confidence
+//! reaches the configured threshold and expires once it falls below it again
+//! because the reports supporting it are older than the configured TTL.
+
+use futures::prelude::*;
+use libp2p_core::{Multiaddr, PeerId};
+use std::{collections::{HashMap, VecDeque}, pin::Pin, task::{Context, Poll}, time::Duration};
+use wasm_timer::{Delay, Instant};
+
+/// The weight of a successful reachability probe relative to an observation.
+const PROBE_WEIGHT: u32 = 2;
+
+/// The source of a report about an external address of the local node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalAddrSource {
+    /// The address has been observed by a remote, e.g. via identify.
+    ///
+    /// Repeated observations of a remote only refresh its previous one,
+    /// i.e. confidence requires observations of multiple remotes.
+    Observed(PeerId),
+    /// The result of a reachability probe of the address by a remote, e.g.
+    /// a dial-back of AutoNAT.
+    ///
+    /// A successful probe counts as much as two observations. A failed probe
+    /// discards all reports about the address.
+    Probe {
+        /// The remote that probed the address.
+        peer_id: PeerId,
+        /// Whether the remote managed to reach the local node on the address.
+        reachable: bool,
+    },
+    /// The address has been configured explicitly. It is confirmed immediately
+    /// and never expires.
+    Config,
+}
+
+/// The configuration of the management of external addresses.
+#[derive(Debug, Clone)]
+pub struct ExternalAddrConfig {
+    confirmations: u32,
+    ttl: Duration,
+    max_candidates: usize,
+}
+
+impl Default for ExternalAddrConfig {
+    fn default() -> Self {
+        ExternalAddrConfig {
+            confirmations: 2,
+            ttl: Duration::from_secs(60 * 60),
+            max_candidates: 64,
+        }
+    }
+}
+
+impl ExternalAddrConfig {
+    /// Configures the confidence an address needs to be confirmed, i.e. the
+    /// number of distinct remotes that observed it.
+    ///
+    /// A value of `0` is treated as `1`. Defaults to `2`.
+    pub fn with_confirmations(mut self, n: u32) -> Self {
+        self.confirmations = n.max(1);
+        self
+    }
+
+    /// Configures after how long a report no longer contributes to the
+    /// confidence of an address. Defaults to one hour.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Configures the maximum number of addresses tracked that are not
+    /// confirmed. If the limit is reached, the one with the lowest confidence
+    /// is forgotten in favour of a newly reported address. Defaults to `64`.
+    pub fn with_max_candidates(mut self, n: usize) -> Self {
+        self.max_candidates = n;
+        self
+    }
+}
+
+/// An address tracked as potential external address of the local node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalAddrCandidate {
+    /// The address.
+    pub address: Multiaddr,
+    /// The confidence in the address, i.e. the sum of the weights of the
+    /// current reports about it. `u32::MAX` for configured addresses.
+    pub confidence: u32,
+    /// Whether the address is confirmed.
+    pub confirmed: bool,
+}
+
+/// A change of the confirmed external addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ExternalAddrChange {
+    Confirmed(Multiaddr),
+    Expired(Multiaddr),
+}
+
+/// The reports about a single address.
+#[derive(Debug, Default)]
+struct Candidate {
+    /// The weight and time of the latest report of every remote.
+    reports: HashMap<PeerId, (u32, Instant)>,
+    /// Whether the address has been configured explicitly.
+    configured: bool,
+    confirmed: bool,
+}
+
+impl Candidate {
+    fn confidence(&self) -> u32 {
+        if self.configured {
+            return u32::MAX
+        }
+        self.reports.values().fold(0, |sum, (w, _)| sum.saturating_add(*w))
+    }
+}
+
+/// The external addresses of the local node with their confidence.
+pub(crate) struct ExternalAddrs {
+    config: ExternalAddrConfig,
+    candidates: HashMap<Multiaddr, Candidate>,
+    changes: VecDeque<ExternalAddrChange>,
+    /// Fires when the next report expires.
+    timer: Option<Delay>,
+}
+
+impl ExternalAddrs {
+    pub(crate) fn new(config: ExternalAddrConfig) -> Self {
+        ExternalAddrs {
+            config,
+            candidates: HashMap::new(),
+            changes: VecDeque::new(),
+            timer: None,
+        }
+    }
+
+    /// Records a report about an address.
+    pub(crate) fn report(&mut self, address: Multiaddr, source: ExternalAddrSource) {
+        self.report_at(address, source, Instant::now());
+        self.reset_timer();
+    }
+
+    fn report_at(&mut self, address: Multiaddr, source: ExternalAddrSource, now: Instant) {
+        self.expire(now);
+
+        if !self.candidates.contains_key(&address) && !self.make_room() {
+            tracing::debug!("Ignoring report of external address {}: too many candidates.", address);
+            return
+        }
+        let candidate = self.candidates.entry(address.clone()).or_default();
+        match source {
+            ExternalAddrSource::Observed(peer_id) => {
+                candidate.reports.insert(peer_id, (1, now));
+            }
+            ExternalAddrSource::Probe { peer_id, reachable: true } => {
+                candidate.reports.insert(peer_id, (PROBE_WEIGHT, now));
+            }
+            ExternalAddrSource::Probe { reachable: false, .. } => {
+                candidate.reports.clear();
+            }
+            ExternalAddrSource::Config => {
+                candidate.configured = true;
+            }
+        }
+        self.update(address);
+    }
+
+    /// Forgets an address, e.g. after it has been removed explicitly.
+    pub(crate) fn remove(&mut self, address: &Multiaddr) {
+        self.candidates.remove(address);
+    }
+
+    /// Returns the tracked addresses.
+    pub(crate) fn candidates(&self) -> impl Iterator<Item = ExternalAddrCandidate> + '_ {
+        self.candidates.iter().map(|(address, c)| ExternalAddrCandidate {
+            address: address.clone(),
+            confidence: c.confidence(),
+            confirmed: c.confirmed,
+        })
+    }
+
+    /// Polls for the next change of the confirmed addresses.
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<ExternalAddrChange> {
+        while let Some(timer) = self.timer.as_mut() {
+            if Pin::new(timer).poll(cx).is_pending() {
+                break
+            }
+            self.expire(Instant::now());
+            self.reset_timer();
+        }
+        match self.changes.pop_front() {
+            Some(change) => Poll::Ready(change),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Discards the reports older than the TTL.
+    fn expire(&mut self, now: Instant) {
+        let ttl = self.config.ttl;
+        let expired = self.candidates.iter_mut()
+            .filter_map(|(address, c)| {
+                let before = c.reports.len();
+                c.reports.retain(|_, (_, at)| *at + ttl > now);
+                if c.reports.len() < before { Some(address.clone()) } else { None }
+            })
+            .collect::<Vec<_>>();
+        for address in expired {
+            self.update(address);
+        }
+    }
+
+    /// Confirms or expires an address after its reports changed.
+    fn update(&mut self, address: Multiaddr) {
+        let candidate = match self.candidates.get_mut(&address) {
+            Some(c) => c,
+            None => return,
+        };
+        let confident = candidate.confidence() >= self.config.confirmations;
+        if confident && !candidate.confirmed {
+            candidate.confirmed = true;
+            self.changes.push_back(ExternalAddrChange::Confirmed(address));
+        } else if !confident && candidate.confirmed {
+            candidate.confirmed = false;
+            self.changes.push_back(ExternalAddrChange::Expired(address.clone()));
+        }
+        if let Some(c) = self.candidates.get(&address) {
+            if c.reports.is_empty() && !c.configured {
+                self.candidates.remove(&address);
+            }
+        }
+    }
+
+    /// Makes room for a new unconfirmed address by forgetting the one with
+    /// the lowest confidence, if necessary.
+    ///
+    /// Returns `false` if there is no room.
+    fn make_room(&mut self) -> bool {
+        let unconfirmed = self.candidates.values().filter(|c| !c.confirmed).count();
+        if unconfirmed < self.config.max_candidates {
+            return true
+        }
+        let weakest = self.candidates.iter()
+            .filter(|(_, c)| !c.confirmed)
+            .min_by_key(|(_, c)| c.confidence())
+            .map(|(a, _)| a.clone());
+        match weakest {
+            Some(address) => {
+                self.candidates.remove(&address);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resets the timer to the expiry of the oldest report.
+    fn reset_timer(&mut self) {
+        let ttl = self.config.ttl;
+        let next = self.candidates.values()
+            .flat_map(|c| c.reports.values().map(|(_, at)| *at + ttl))
+            .min();
+        self.timer = next.map(Delay::new_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> Multiaddr {
+        format!("/ip4/192.0.2.1/tcp/{}", port).parse().unwrap()
+    }
+
+    fn confirmed(addrs: &ExternalAddrs) -> Vec<Multiaddr> {
+        addrs.candidates().filter(|c| c.confirmed).map(|c| c.address).collect()
+    }
+
+    #[test]
+    fn observations_of_distinct_peers_confirm() {
+        let mut addrs = ExternalAddrs::new(ExternalAddrConfig::default().with_confirmations(2));
+        let now = Instant::now();
+        let peer = PeerId::random();
+
+        addrs.report_at(addr(1), ExternalAddrSource::Observed(peer), now);
+        addrs.report_at(addr(1), ExternalAddrSource::Observed(peer), now);
+        assert!(confirmed(&addrs).is_empty());
+
+        addrs.report_at(addr(1), ExternalAddrSource::Observed(PeerId::random()), now);
+        assert_eq!(confirmed(&addrs), vec![addr(1)]);
+        assert_eq!(addrs.changes.pop_front(), Some(ExternalAddrChange::Confirmed(addr(1))));
+    }
+
+    #[test]
+    fn reports_expire() {
+        let ttl = Duration::from_secs(10);
+        let mut addrs = ExternalAddrs::new(ExternalAddrConfig::default().with_ttl(ttl));
+        let now = Instant::now();
+
+        addrs.report_at(addr(1), ExternalAddrSource::Probe { peer_id: PeerId::random(), reachable: true }, now);
+        addrs.report_at(addr(2), ExternalAddrSource::Config, now);
+        assert_eq!(addrs.changes.len(), 2);
+
+        addrs.expire(now + ttl);
+        assert_eq!(confirmed(&addrs), vec![addr(2)]);
+        assert_eq!(addrs.changes.pop_back(), Some(ExternalAddrChange::Expired(addr(1))));
+        assert_eq!(addrs.candidates().count(), 1);
+    }
+
+    #[test]
+    fn failed_probe_discards_reports() {
+        let mut addrs = ExternalAddrs::new(ExternalAddrConfig::default());
+        let now = Instant::now();
+
+        addrs.report_at(addr(1), ExternalAddrSource::Probe { peer_id: PeerId::random(), reachable: true }, now);
+        assert_eq!(confirmed(&addrs), vec![addr(1)]);
+
+        addrs.report_at(addr(1), ExternalAddrSource::Probe { peer_id: PeerId::random(), reachable: false }, now);
+        assert!(confirmed(&addrs).is_empty());
+        assert_eq!(addrs.changes.pop_back(), Some(ExternalAddrChange::Expired(addr(1))));
+    }
+
+    #[test]
+    fn weakest_candidate_is_evicted() {
+        let mut addrs = ExternalAddrs::new(ExternalAddrConfig::default()
+            .with_confirmations(3)
+            .with_max_candidates(2));
+        let now = Instant::now();
+
+        addrs.report_at(addr(1), ExternalAddrSource::Observed(PeerId::random()), now);
+        addrs.report_at(addr(1), ExternalAddrSource::Observed(PeerId::random()), now);
+        addrs.report_at(addr(2), ExternalAddrSource::Observed(PeerId::random()), now);
+        addrs.report_at(addr(3), ExternalAddrSource::Observed(PeerId::random()), now);
+
+        let mut tracked = addrs.candidates().map(|c| c.address).collect::<Vec<_>>();
+        tracked.sort();
+        assert_eq!(tracked, vec![addr(1), addr(3)]);
+    }
+}
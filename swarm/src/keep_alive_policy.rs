@@ -0,0 +1,163 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A keep-alive policy for all connections of the swarm.
+
+use libp2p_core::{PeerId, connection::ConnectionId};
+use std::{collections::{HashMap, HashSet}, time::Duration};
+
+/// A keep-alive policy for all connections, complementing and overriding
+/// the answers of [`ProtocolsHandler::connection_keep_alive`](crate::ProtocolsHandler::connection_keep_alive).
+///
+/// By default, the keep-alive of connections is left to the handlers.
+#[derive(Debug, Clone, Default)]
+pub struct KeepAlivePolicy {
+    max_connections: Option<usize>,
+    idle_timeout: Option<Duration>,
+    protected: HashSet<PeerId>,
+}
+
+impl KeepAlivePolicy {
+    /// Configures the maximum number of established connections to keep.
+    ///
+    /// Whenever a connection is established beyond the maximum, the least
+    /// recently useful connections are closed, i.e. the connections with
+    /// the oldest event emitted by or addressed to their handler.
+    pub fn with_max_connections(mut self, max: Option<usize>) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Configures the duration after which an idle connection is closed,
+    /// even if its handler wants to keep it alive.
+    ///
+    /// A connection is idle as long as no substream is opened and no event
+    /// is emitted by or addressed to its handler.
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Configures peers, e.g. bootstrap peers, whose connections are always
+    /// kept alive, even if their handlers do not want to keep them alive.
+    ///
+    /// The connections of protected peers are neither closed when idle nor
+    /// in favour of other connections.
+    pub fn with_protected_peers(mut self, peers: impl IntoIterator<Item = PeerId>) -> Self {
+        self.protected = peers.into_iter().collect();
+        self
+    }
+
+    /// Returns the maximum number of established connections to keep, if any.
+    pub fn max_connections(&self) -> Option<usize> {
+        self.max_connections
+    }
+
+    /// Returns the duration after which an idle connection is closed, if any.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    /// Checks whether the connections of a peer are always kept alive.
+    pub fn is_protected(&self, peer_id: &PeerId) -> bool {
+        self.protected.contains(peer_id)
+    }
+
+    /// Returns the peers whose connections are always kept alive.
+    pub(crate) fn protected_peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.protected.iter()
+    }
+}
+
+/// Tracks when the established connections were last useful, for closing
+/// the least recently useful connections beyond
+/// [`KeepAlivePolicy::max_connections`].
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionUsage {
+    /// The peer and the sequence number of the last use per connection.
+    connections: HashMap<ConnectionId, (PeerId, u64)>,
+    /// The sequence number of the next use.
+    next_seq: u64,
+}
+
+impl ConnectionUsage {
+    /// Records a newly established connection, counting as a use.
+    pub(crate) fn on_established(&mut self, peer_id: PeerId, id: ConnectionId) {
+        self.connections.insert(id, (peer_id, self.next_seq));
+        self.next_seq += 1;
+    }
+
+    /// Records the use of a connection.
+    pub(crate) fn on_used(&mut self, id: &ConnectionId) {
+        if let Some((_, seq)) = self.connections.get_mut(id) {
+            *seq = self.next_seq;
+            self.next_seq += 1;
+        }
+    }
+
+    /// Forgets a closed connection.
+    pub(crate) fn on_closed(&mut self, id: &ConnectionId) {
+        self.connections.remove(id);
+    }
+
+    /// Returns the least recently useful connections of unprotected peers
+    /// exceeding the maximum number of connections of the policy.
+    pub(crate) fn excess(&self, policy: &KeepAlivePolicy) -> Vec<(PeerId, ConnectionId)> {
+        let max = match policy.max_connections {
+            Some(max) if self.connections.len() > max => max,
+            _ => return Vec::new(),
+        };
+        let mut candidates = self.connections.iter()
+            .filter(|(_, (peer_id, _))| !policy.is_protected(peer_id))
+            .map(|(id, (peer_id, seq))| (*seq, *peer_id, *id))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(seq, _, _)| *seq);
+        candidates.into_iter()
+            .take(self.connections.len() - max)
+            .map(|(_, peer_id, id)| (peer_id, id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_in_excess() {
+        let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let policy = KeepAlivePolicy::default()
+            .with_max_connections(Some(1))
+            .with_protected_peers(vec![c]);
+        let mut usage = ConnectionUsage::default();
+        usage.on_established(a, ConnectionId::new(1));
+        assert!(usage.excess(&policy).is_empty());
+        usage.on_established(b, ConnectionId::new(2));
+        usage.on_used(&ConnectionId::new(1));
+        assert_eq!(usage.excess(&policy), vec![(b, ConnectionId::new(2))]);
+
+        // Connections of protected peers are never in excess.
+        usage.on_closed(&ConnectionId::new(2));
+        usage.on_established(c, ConnectionId::new(3));
+        assert_eq!(usage.excess(&policy), vec![(a, ConnectionId::new(1))]);
+        usage.on_closed(&ConnectionId::new(1));
+        assert!(usage.excess(&policy).is_empty());
+    }
+}
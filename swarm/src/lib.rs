@@ -53,15 +53,29 @@
 //! are supported, when to open a new outbound substream, etc.
 //!
 
+mod accept_rate;
 mod behaviour;
+mod dial_concurrency;
+mod dial_opts;
+mod dial_ranking;
+mod event_sinks;
+mod external_addrs;
+mod keep_alive_policy;
+mod peer_bans;
+mod peer_store;
+mod protocol_registry;
 mod registry;
 #[cfg(test)]
 mod test;
 mod upgrade;
 
+pub mod diagnostics;
 pub mod protocols_handler;
+pub mod snapshot;
+pub mod switch;
 pub mod toggle;
 
+pub use accept_rate::{AcceptRateLimits, AcceptRateStats};
 pub use behaviour::{
     NetworkBehaviour,
     NetworkBehaviourAction,
@@ -82,8 +96,24 @@ pub use protocols_handler::{
     OneShotHandlerConfig,
     SubstreamProtocol
 };
+pub use dial_opts::DialOpts;
+pub use dial_ranking::{AddressStats, DialRanking};
+pub use event_sinks::{EventReceiver, SinkPolicy};
+pub use external_addrs::{ExternalAddrCandidate, ExternalAddrConfig, ExternalAddrSource};
+pub use keep_alive_policy::KeepAlivePolicy;
+pub use peer_bans::DialBackoff;
+pub use peer_store::{FilePersistence, PeerRecord, PeerStore, PeerStorePersistence, StoredPeer};
+pub use protocol_registry::{ProtocolConflict, ProtocolRegistry};
 pub use registry::{AddressScore, AddressRecord, AddAddressResult};
 
+use accept_rate::AcceptRateLimiter;
+use diagnostics::{ConnectionRecords, Diagnostics, PendingDial};
+use dial_concurrency::ConcurrentDials;
+use dial_opts::DialTags;
+use dial_ranking::AddressScores;
+use event_sinks::EventSinks;
+use external_addrs::{ExternalAddrChange, ExternalAddrs};
+use keep_alive_policy::ConnectionUsage;
 use protocols_handler::{
     NodeHandlerWrapperBuilder,
     NodeHandlerWrapperError,
@@ -99,6 +129,8 @@ use libp2p_core::{
     Multiaddr,
     Negotiated,
     PeerId,
+    onion,
+    buffer_pool::{BufferPool, BufferPoolConfig},
     connection::{
         ConnectionError,
         ConnectionId,
@@ -108,26 +140,31 @@ use libp2p_core::{
         IntoConnectionHandler,
         ListenerId,
         PendingConnectionError,
+        PendingConnectionInfo,
+        StageTimeouts,
         Substream
     },
-    transport::{self, TransportError},
-    muxing::StreamMuxerBox,
+    transport::{self, TransportError, DialCancelToken},
+    muxing::{MuxerStats, StreamMuxerBox},
     network::{
         ConnectionLimits,
         Network,
         NetworkInfo,
         NetworkEvent,
         NetworkConfig,
+        DialParams,
         peer::ConnectedPeer,
     },
     upgrade::{ProtocolName},
 };
+use peer_bans::{DialBackoffs, PeerBans};
 use registry::{Addresses, AddressIntoIter};
 use smallvec::SmallVec;
-use std::{error, fmt, io, ops::{Deref, DerefMut}, pin::Pin, task::{Context, Poll}};
-use std::collections::HashSet;
-use std::num::{NonZeroU32, NonZeroUsize};
+use std::{collections::HashSet, error, fmt, io, ops::{Deref, DerefMut}, pin::Pin, task::{Context, Poll}};
+use std::num::{NonZeroU8, NonZeroU32, NonZeroUsize};
+use std::{sync::Arc, time::Duration};
 use upgrade::UpgradeInfoSend as _;
+use wasm_timer::{Delay, Instant};
 
 /// Contains the state of the network, plus the way it should behave.
 pub type Swarm<TBehaviour> = ExpandedSwarm<
@@ -157,6 +194,9 @@ pub enum SwarmEvent<TBvEv, THandleErr> {
         /// Number of established connections to this peer, including the one that has just been
         /// opened.
         num_established: NonZeroU32,
+        /// The tag of the dialing attempt which resulted in the connection, if
+        /// any, see [`DialOpts::tag`].
+        tag: Option<u64>,
     },
     /// A connection with the given peer has been closed,
     /// possibly as a result of an error.
@@ -215,7 +255,12 @@ pub enum SwarmEvent<TBvEv, THandleErr> {
         /// Error that has been encountered.
         error: PendingConnectionError<io::Error>,
         /// Number of remaining connection attempts that are being tried for this peer.
+        ///
+        /// With a [`SwarmBuilder::dial_concurrency_factor`] greater than 1, this
+        /// includes the connection attempts in progress concurrently.
         attempts_remaining: u32,
+        /// The tag of the dialing attempt, if any, see [`DialOpts::tag`].
+        tag: Option<u64>,
     },
     /// Tried to dial an address but it ended up being unreachaable.
     /// Contrary to `UnreachableAddr`, we don't know the identity of the peer that we were trying
@@ -225,6 +270,8 @@ pub enum SwarmEvent<TBvEv, THandleErr> {
         address: Multiaddr,
         /// Error that has been encountered.
         error: PendingConnectionError<io::Error>,
+        /// The tag of the dialing attempt, if any, see [`DialOpts::tag`].
+        tag: Option<u64>,
     },
     /// One of our listeners has reported a new local listening address.
     NewListenAddr(Multiaddr),
@@ -252,11 +299,20 @@ pub enum SwarmEvent<TBvEv, THandleErr> {
     /// [`UnreachableAddr`](SwarmEvent::UnreachableAddr) event is reported
     /// with `attempts_remaining` equal to 0.
     Dialing(PeerId),
+    /// An address reported via [`ExpandedSwarm::report_external_address`]
+    /// or [`NetworkBehaviourAction::ReportExternalAddr`] has been confirmed
+    /// and added to the external addresses.
+    ExternalAddrConfirmed(Multiaddr),
+    /// A confirmed external address has expired and has been removed from
+    /// the external addresses, unless it has also been added by other means,
+    /// e.g. via [`ExpandedSwarm::add_external_address`].
+    ExternalAddrExpired(Multiaddr),
 }
 
 /// Contains the state of the network, plus the way it should behave.
 pub struct ExpandedSwarm<TBehaviour, TInEvent, TOutEvent, THandler>
 where
+    TBehaviour: NetworkBehaviour,
     THandler: IntoProtocolsHandler,
 {
     network: Network<
@@ -280,8 +336,47 @@ where
     /// similar mechanisms.
     external_addrs: Addresses,
 
+    /// The reports about external addresses, confirming and expiring
+    /// addresses in `external_addrs` by their confidence.
+    external_addr_reports: ExternalAddrs,
+
+    /// The addresses in `external_addrs` that have been added by
+    /// `external_addr_reports` and are thus removed again on expiry.
+    /// Addresses added by other means are never expired.
+    reported_external_addrs: HashSet<Multiaddr>,
+
     /// List of nodes for which we deny any incoming connection.
-    banned_peers: HashSet<PeerId>,
+    banned_peers: PeerBans,
+
+    /// The dial backoffs of peers whose recent dialing attempts failed.
+    dial_backoffs: DialBackoffs,
+
+    /// The dialing history of addresses, ranking the addresses of a peer
+    /// when dialing.
+    address_scores: AddressScores,
+
+    /// The tags of the ongoing dialing attempts.
+    dial_tags: DialTags,
+
+    /// The maximum number of addresses of a peer dialed concurrently.
+    dial_concurrency_factor: NonZeroU8,
+
+    /// The ongoing dialing attempts dialing addresses concurrently.
+    concurrent_dials: ConcurrentDials,
+
+    /// The store of known peers, if any.
+    peer_store: Option<PeerStore>,
+
+    /// The keep-alive policy for all connections, if any.
+    keep_alive_policy: Option<Arc<KeepAlivePolicy>>,
+
+    /// When the established connections were last useful, according to
+    /// which connections are closed beyond the maximum of the `keep_alive_policy`.
+    connection_usage: ConnectionUsage,
+
+    /// The endpoints of the established connections and when they were
+    /// established, reported in the [`Diagnostics`].
+    connection_records: ConnectionRecords,
 
     /// Pending event to be delivered to connection handlers
     /// (or dropped if the peer disconnected) before the `behaviour`
@@ -290,11 +385,33 @@ where
 
     /// The configured override for substream protocol upgrades, if any.
     substream_upgrade_protocol_override: Option<libp2p_core::upgrade::Version>,
+
+    /// The registry of the protocols supported by the behaviours, if any.
+    protocol_registry: Option<ProtocolRegistry>,
+
+    /// Limits the rate of inbound connections per source IP address and prefix.
+    accept_rate_limiter: AcceptRateLimiter,
+
+    /// Whether the swarm is shutting down, see [`ExpandedSwarm::close`].
+    closing: bool,
+
+    /// The additional consumers of the events of the swarm.
+    event_sinks: EventSinks<SwarmEvent<
+        TBehaviour::OutEvent,
+        <THandler::Handler as ProtocolsHandler>::Error
+    >>,
+
+    /// An event not yet delivered to all `event_sinks`.
+    held_event: Option<SwarmEvent<
+        TBehaviour::OutEvent,
+        <THandler::Handler as ProtocolsHandler>::Error
+    >>,
 }
 
 impl<TBehaviour, TInEvent, TOutEvent, THandler> Deref for
     ExpandedSwarm<TBehaviour, TInEvent, TOutEvent, THandler>
 where
+    TBehaviour: NetworkBehaviour,
     THandler: IntoProtocolsHandler,
 {
     type Target = TBehaviour;
@@ -307,6 +424,7 @@ where
 impl<TBehaviour, TInEvent, TOutEvent, THandler> DerefMut for
     ExpandedSwarm<TBehaviour, TInEvent, TOutEvent, THandler>
 where
+    TBehaviour: NetworkBehaviour,
     THandler: IntoProtocolsHandler,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
@@ -317,6 +435,7 @@ where
 impl<TBehaviour, TInEvent, TOutEvent, THandler> Unpin for
     ExpandedSwarm<TBehaviour, TInEvent, TOutEvent, THandler>
 where
+    TBehaviour: NetworkBehaviour,
     THandler: IntoProtocolsHandler,
 {
 }
@@ -339,7 +458,8 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         SwarmBuilder::new(transport, behaviour, local_peer_id).build()
     }
 
-    /// Returns information about the [`Network`] underlying the `Swarm`.
+    /// Returns information about the [`Network`] underlying the `Swarm`,
+    /// including the traffic per connection and negotiated protocol.
     pub fn network_info(me: &Self) -> NetworkInfo {
         me.network.info()
     }
@@ -360,47 +480,247 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
 
     /// Initiates a new dialing attempt to the given address.
     pub fn dial_addr(me: &mut Self, addr: Multiaddr) -> Result<(), ConnectionLimit> {
+        Self::dial_addr_impl(me, addr, DialParams::default())
+    }
+
+    /// Like [`ExpandedSwarm::dial_addr`], but the connection attempt is
+    /// aborted when the given token is cancelled, e.g. because the user
+    /// no longer waits for the connection.
+    pub fn dial_addr_with_cancel(me: &mut Self, addr: Multiaddr, cancel: DialCancelToken)
+        -> Result<(), ConnectionLimit>
+    {
+        Self::dial_addr_impl(me, addr, DialParams::default().with_cancel(cancel))
+    }
+
+    fn dial_addr_impl(me: &mut Self, addr: Multiaddr, params: DialParams) -> Result<(), ConnectionLimit> {
         let handler = me.behaviour.new_handler()
             .into_node_handler_builder()
-            .with_substream_upgrade_protocol_override(me.substream_upgrade_protocol_override);
-        me.network.dial(&addr, handler).map(|_id| ())
+            .with_substream_upgrade_protocol_override(me.substream_upgrade_protocol_override)
+            .with_keep_alive_policy(me.keep_alive_policy.clone());
+        me.network.dial_with_params(&addr, handler, params).map(|_id| ())
     }
 
     /// Initiates a new dialing attempt to the given peer.
     pub fn dial(me: &mut Self, peer_id: &PeerId) -> Result<(), DialError> {
-        if me.banned_peers.contains(peer_id) {
+        Self::dial_with_opts(me, DialOpts::peer_id(*peer_id))
+    }
+
+    /// Like [`ExpandedSwarm::dial`], but the dialing attempt is aborted when
+    /// the given token is cancelled, without trying any remaining addresses.
+    ///
+    /// The aborted connection attempt is reported to the behaviour like any
+    /// other failed dialing attempt.
+    pub fn dial_with_cancel(me: &mut Self, peer_id: &PeerId, cancel: DialCancelToken)
+        -> Result<(), DialError>
+    {
+        Self::dial_with_opts(me, DialOpts::peer_id(*peer_id).cancel(cancel))
+    }
+
+    /// Initiates a new dialing attempt according to the given [`DialOpts`].
+    ///
+    /// If the [`DialPeerCondition`] of the options is not met, no dialing
+    /// attempt is initiated and [`DialError::DialPeerConditionFalse`] is
+    /// returned without notifying the behaviour.
+    pub fn dial_with_opts(me: &mut Self, opts: DialOpts) -> Result<(), DialError> {
+        let DialOpts {
+            peer_id,
+            addresses,
+            extend_addresses_through_behaviour,
+            condition,
+            params,
+            tag,
+        } = opts;
+
+        let peer_id = match peer_id {
+            Some(peer_id) => peer_id,
+            None => {
+                let address = addresses.into_iter().next()
+                    .expect("`DialOpts::unknown_peer_id` always sets an address; QED");
+                Self::dial_addr_impl(me, address.clone(), params)
+                    .map_err(DialError::ConnectionLimit)?;
+                if let Some(tag) = tag {
+                    me.dial_tags.insert_address(address, tag);
+                }
+                return Ok(())
+            }
+        };
+
+        let condition_matched = match condition {
+            DialPeerCondition::Disconnected => me.network.is_disconnected(&peer_id),
+            DialPeerCondition::NotDialing => !me.network.is_dialing(&peer_id),
+            DialPeerCondition::Always => true,
+        };
+        if !condition_matched {
+            return Err(DialError::DialPeerConditionFalse(condition))
+        }
+
+        Self::dial_peer_impl(me, &peer_id, addresses, extend_addresses_through_behaviour, params)?;
+        if let Some(tag) = tag {
+            me.dial_tags.insert_peer(peer_id, tag);
+        }
+        Ok(())
+    }
+
+    fn dial_peer_impl(
+        me: &mut Self,
+        peer_id: &PeerId,
+        mut addrs: Vec<Multiaddr>,
+        extend_addresses_through_behaviour: bool,
+        params: DialParams,
+    ) -> Result<(), DialError> {
+        if me.banned_peers.is_banned(peer_id) {
             me.behaviour.inject_dial_failure(peer_id);
             return Err(DialError::Banned)
         }
 
+        if addrs.is_empty() || extend_addresses_through_behaviour {
+            for addr in me.known_addresses_of_peer(peer_id) {
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+            }
+        }
+        // Never dial our own listen addresses nor addresses which can't
+        // reach the peer, e.g. the onion service of another key.
         let self_listening = &me.listened_addrs;
-        let mut addrs = me.behaviour.addresses_of_peer(peer_id)
-            .into_iter()
-            .filter(|a| !self_listening.contains(a));
-
-        let result =
-            if let Some(first) = addrs.next() {
-                let handler = me.behaviour.new_handler()
-                    .into_node_handler_builder()
-                    .with_substream_upgrade_protocol_override(me.substream_upgrade_protocol_override);
-                me.network.peer(*peer_id)
-                    .dial(first, addrs, handler)
-                    .map(|_| ())
-                    .map_err(DialError::ConnectionLimit)
-            } else {
-                Err(DialError::NoAddresses)
-            };
+        addrs.retain(|a| !self_listening.contains(a) && onion::matches_peer(a, peer_id));
+        me.address_scores.rank(&mut addrs);
+
+        let mut lanes = dial_concurrency::lanes(addrs, me.dial_concurrency_factor);
+        let mut started = 0;
+        let mut result = Err(DialError::NoAddresses);
+        for lane in &lanes {
+            let mut addrs = lane.iter().cloned();
+            let first = addrs.next().expect("lanes are not empty; QED");
+            let handler = me.behaviour.new_handler()
+                .into_node_handler_builder()
+                .with_substream_upgrade_protocol_override(me.substream_upgrade_protocol_override)
+                .with_keep_alive_policy(me.keep_alive_policy.clone());
+            let peer = me.network.peer(*peer_id);
+            match peer.dial_with_params(first, addrs, handler, params.clone()) {
+                Ok(_) => {
+                    started += 1;
+                    result = Ok(());
+                }
+                Err(limit) => {
+                    // The lanes dialed so far continue.
+                    if started == 0 {
+                        result = Err(DialError::ConnectionLimit(limit));
+                    }
+                    break
+                }
+            }
+        }
+        if result.is_ok() {
+            me.address_scores.on_attempt(peer_id);
+            if started > 1 {
+                lanes.truncate(started);
+                me.concurrent_dials.insert(*peer_id, &lanes);
+            }
+        }
 
         if let Err(error) = &result {
-            log::debug!(
+            tracing::debug!(
                 "New dialing attempt to peer {:?} failed: {:?}.",
                 peer_id, error);
+            if let DialError::NoAddresses = error {
+                me.dial_backoffs.on_dial_failure(peer_id);
+            }
             me.behaviour.inject_dial_failure(&peer_id);
         }
 
         result
     }
 
+    /// Returns the [`ProtocolRegistry`] configured with
+    /// [`SwarmBuilder::protocol_registry`], if any.
+    pub fn protocol_registry(me: &Self) -> Option<&ProtocolRegistry> {
+        me.protocol_registry.as_ref()
+    }
+
+    /// Returns the counters of inbound connections accepted and dropped
+    /// according to the [`AcceptRateLimits`] configured with
+    /// [`SwarmBuilder::accept_rate_limits`].
+    pub fn accept_rate_stats(me: &Self) -> AcceptRateStats {
+        me.accept_rate_limiter.stats()
+    }
+
+    /// Returns the [`KeepAlivePolicy`] configured with
+    /// [`SwarmBuilder::keep_alive_policy`], if any.
+    pub fn keep_alive_policy(me: &Self) -> Option<&KeepAlivePolicy> {
+        me.keep_alive_policy.as_deref()
+    }
+
+    /// Returns the [`PeerStore`] configured with [`SwarmBuilder::peer_store`], if any.
+    pub fn peer_store(me: &Self) -> Option<&PeerStore> {
+        me.peer_store.as_ref()
+    }
+
+    /// Returns the [`PeerStore`] configured with [`SwarmBuilder::peer_store`],
+    /// if any, e.g. for adding addresses and protocols of peers or for
+    /// saving it with [`PeerStore::save`].
+    pub fn peer_store_mut(me: &mut Self) -> Option<&mut PeerStore> {
+        me.peer_store.as_mut()
+    }
+
+    /// Returns the dialing history of an address, according to which the
+    /// addresses of a peer are ranked when dialing, see [`DialRanking`].
+    pub fn address_stats<'a>(me: &'a Self, addr: &Multiaddr) -> Option<&'a AddressStats> {
+        me.address_scores.stats(addr)
+    }
+
+    /// Returns the statistics of the stream multiplexer of an established
+    /// connection, i.e. the substreams opened and closed and the traffic
+    /// per negotiated protocol.
+    ///
+    /// Returns `None` if the connection is unknown or its stream multiplexer
+    /// does not collect statistics.
+    pub fn connection_stats(me: &mut Self, id: ConnectionId) -> Option<MuxerStats> {
+        me.network.connection_stats(id)
+    }
+
+    /// Returns an iterator over all pending connections, incoming and outgoing,
+    /// together with the time elapsed in each stage of the connection setup,
+    /// i.e. establishing the transport connection and negotiating the security
+    /// protocol and the stream multiplexer.
+    pub fn pending_connections(me: &Self) -> impl Iterator<Item = PendingConnectionInfo<'_>> {
+        me.network.pending_connections()
+    }
+
+    /// Returns a snapshot of the state of the swarm for debugging, i.e. its
+    /// listeners and external addresses, the established connections, the
+    /// pending dials and the summaries provided by the behaviour through
+    /// [`NetworkBehaviour::diagnostics`].
+    pub fn diagnostics(me: &Self) -> Diagnostics {
+        let info = me.network.info();
+        let connections = me.connection_records.diagnostics(|id| {
+            info.connection_bandwidth().iter()
+                .find(|c| c.id() == *id)
+                .map(|c| c.protocols().iter().map(|(p, _)| p.clone()).collect())
+                .unwrap_or_default()
+        });
+        let pending_dials = me.network.pending_connections()
+            .filter_map(|pending| match pending.endpoint {
+                ConnectedPoint::Dialer { address } => Some(PendingDial {
+                    id: pending.id,
+                    peer_id: pending.peer_id.copied(),
+                    address: address.clone(),
+                    stage: pending.progress.stage(),
+                    elapsed: pending.progress.elapsed(),
+                }),
+                ConnectedPoint::Listener { .. } => None,
+            })
+            .collect();
+        Diagnostics {
+            local_peer_id: *me.network.local_peer_id(),
+            listeners: me.network.listen_addrs().cloned().collect(),
+            external_addresses: me.external_addrs.iter().map(|r| r.addr.clone()).collect(),
+            connections,
+            pending_dials,
+            behaviour: me.behaviour.diagnostics(),
+        }
+    }
+
     /// Returns an iterator that produces the list of addresses we're listening on.
     pub fn listeners(me: &Self) -> impl Iterator<Item = &Multiaddr> {
         me.network.listen_addrs()
@@ -433,6 +753,7 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
     /// [`NetworkBehaviourAction::ReportObservedAddr`] or explicitly
     /// through this method.
     pub fn add_external_address(me: &mut Self, a: Multiaddr, s: AddressScore) -> AddAddressResult {
+        me.reported_external_addrs.remove(&a);
         me.external_addrs.add(a, s)
     }
 
@@ -443,15 +764,50 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
     /// Returns `true` if the address existed and was removed, `false`
     /// otherwise.
     pub fn remove_external_address(me: &mut Self, addr: &Multiaddr) -> bool {
+        me.external_addr_reports.remove(addr);
+        me.reported_external_addrs.remove(addr);
         me.external_addrs.remove(addr)
     }
 
+    /// Reports an external address of the local node, e.g. an address
+    /// observed by a remote, the result of a reachability probe or an
+    /// address from the configuration of the application.
+    ///
+    /// Contrary to [`ExpandedSwarm::add_external_address`], the address is
+    /// only added to the external addresses once enough reports corroborate
+    /// it and removed again once they are outdated, which is reported via
+    /// [`SwarmEvent::ExternalAddrConfirmed`] and
+    /// [`SwarmEvent::ExternalAddrExpired`]. See [`ExternalAddrConfig`].
+    pub fn report_external_address(me: &mut Self, addr: Multiaddr, source: ExternalAddrSource) {
+        me.external_addr_reports.report(addr, source)
+    }
+
+    /// Returns the addresses reported via
+    /// [`ExpandedSwarm::report_external_address`] or
+    /// [`NetworkBehaviourAction::ReportExternalAddr`] with their confidence.
+    pub fn external_address_candidates(me: &Self) -> impl Iterator<Item = ExternalAddrCandidate> + '_ {
+        me.external_addr_reports.candidates()
+    }
+
     /// Bans a peer by its peer ID.
     ///
     /// Any incoming connection and any dialing attempt will immediately be rejected.
-    /// This function has no effect if the peer is already banned.
+    /// This function has no effect if the peer is already banned permanently.
     pub fn ban_peer_id(me: &mut Self, peer_id: PeerId) {
-        if me.banned_peers.insert(peer_id) {
+        Self::ban_impl(me, peer_id, None)
+    }
+
+    /// Bans a peer by its peer ID for the given duration.
+    ///
+    /// Like [`ExpandedSwarm::ban_peer_id`], except that the ban is lifted
+    /// automatically once the duration has elapsed. An existing ban of the
+    /// peer is only ever extended, never shortened.
+    pub fn ban_peer(me: &mut Self, peer_id: PeerId, duration: Duration) {
+        Self::ban_impl(me, peer_id, Some(Instant::now() + duration))
+    }
+
+    fn ban_impl(me: &mut Self, peer_id: PeerId, until: Option<Instant>) {
+        if me.banned_peers.ban(peer_id, until) {
             if let Some(peer) = me.network.peer(peer_id).into_connected() {
                 peer.disconnect();
             }
@@ -460,7 +816,33 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
 
     /// Unbans a peer.
     pub fn unban_peer_id(me: &mut Self, peer_id: PeerId) {
-        me.banned_peers.remove(&peer_id);
+        me.banned_peers.unban(&peer_id);
+    }
+
+    /// Protects the connections of a peer, e.g. of a bootstrap node, returning
+    /// `false` if the peer was already protected.
+    ///
+    /// When the limit of established incoming (outgoing) connections of the
+    /// [`ConnectionLimits`] is reached, a new connection of a protected peer
+    /// is not rejected but evicts the least valuable established incoming
+    /// (outgoing) connection of an unprotected peer instead, i.e. a connection
+    /// of the peer with the most connections, preferring the most recent one.
+    ///
+    /// The peers protected by the [`KeepAlivePolicy`] are protected as well.
+    pub fn add_protected_peer(me: &mut Self, peer_id: PeerId) -> bool {
+        me.network.add_protected_peer(peer_id)
+    }
+
+    /// Removes the protection of the connections of a peer added with
+    /// [`ExpandedSwarm::add_protected_peer`], returning `false` if the peer
+    /// was not protected.
+    pub fn remove_protected_peer(me: &mut Self, peer_id: &PeerId) -> bool {
+        me.network.remove_protected_peer(peer_id)
+    }
+
+    /// Checks whether the connections of a peer are protected from eviction.
+    pub fn is_protected(me: &Self, peer_id: &PeerId) -> bool {
+        me.network.is_protected(peer_id)
     }
 
     /// Checks whether the [`Network`] has an established connection to a peer.
@@ -473,6 +855,50 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         me.network.is_dialing(peer_id)
     }
 
+    /// Gracefully shuts down the `Swarm`, resolving once all connections
+    /// are closed or the given deadline has passed.
+    ///
+    /// Inbound connections are no longer accepted and dialing attempts of
+    /// the `NetworkBehaviour` are refused. All connections are drained, i.e.
+    /// their handlers may complete pending work (e.g. sending queued
+    /// responses or messages) before the connections are closed, see
+    /// [`ProtocolsHandler::poll_close`]. In the meantime, the `Swarm` is polled
+    /// by the returned future and the events it produces are discarded.
+    /// Connections still open at the deadline are closed immediately.
+    ///
+    /// The `Swarm` is not meant to be used any further once closed.
+    pub async fn close(me: &mut Self, deadline: Instant) {
+        me.closing = true;
+        let peers = me.network.connected_peers().cloned().collect::<Vec<_>>();
+        for peer_id in &peers {
+            me.drain_peer(peer_id);
+        }
+
+        let mut timer = Delay::new_at(deadline);
+        future::poll_fn(|cx| {
+            loop {
+                if me.network.connected_peers().next().is_none()
+                    && me.network.pending_connections().next().is_none()
+                {
+                    return Poll::Ready(())
+                }
+                if timer.poll_unpin(cx).is_ready() {
+                    let peers = me.network.connected_peers().cloned().collect::<Vec<_>>();
+                    tracing::debug!("Closing deadline passed, disconnecting {} peer(s).", peers.len());
+                    for peer_id in peers {
+                        if let Some(peer) = me.network.peer(peer_id).into_connected() {
+                            peer.disconnect();
+                        }
+                    }
+                    return Poll::Ready(())
+                }
+                if ExpandedSwarm::poll_next_event(Pin::new(&mut *me), cx).is_pending() {
+                    return Poll::Pending
+                }
+            }
+        }).await
+    }
+
     /// Returns the next event that happens in the `Swarm`.
     ///
     /// Includes events from the `NetworkBehaviour` but also events about the connections status.
@@ -492,11 +918,48 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         }).await
     }
 
+    /// Adds a consumer of the events of the `Swarm`, in addition to the
+    /// one driving the `Swarm`, e.g. a metrics or UI task.
+    ///
+    /// Every event of the `Swarm` is passed to `map`, and the events
+    /// returned are sent to the returned [`EventReceiver`] through a
+    /// channel holding at least `capacity` events. While the channel is
+    /// full, the [`SinkPolicy`] applies. Dropping the receiver removes
+    /// the sink.
+    pub fn add_event_sink<T, F>(me: &mut Self, capacity: usize, policy: SinkPolicy, map: F)
+        -> EventReceiver<T>
+    where
+        F: FnMut(&SwarmEvent<TBehaviour::OutEvent, THandleErr>) -> Option<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        me.event_sinks.add(capacity, policy, map)
+    }
+
     /// Internal function used by everything event-related.
     ///
-    /// Polls the `Swarm` for the next event.
+    /// Polls the `Swarm` for the next event, which is delivered to the
+    /// event sinks before being returned.
     fn poll_next_event(mut self: Pin<&mut Self>, cx: &mut Context<'_>)
         -> Poll<SwarmEvent<TBehaviour::OutEvent, THandleErr>>
+    {
+        let event = match self.held_event.take() {
+            Some(event) => event,
+            None => {
+                let event = futures::ready!(ExpandedSwarm::poll_swarm_event(self.as_mut(), cx));
+                self.event_sinks.start(&event);
+                event
+            }
+        };
+        if self.event_sinks.poll_deliver(cx).is_pending() {
+            self.held_event = Some(event);
+            return Poll::Pending
+        }
+        Poll::Ready(event)
+    }
+
+    /// Polls the network and the behaviour for the next event of the `Swarm`.
+    fn poll_swarm_event(mut self: Pin<&mut Self>, cx: &mut Context<'_>)
+        -> Poll<SwarmEvent<TBehaviour::OutEvent, THandleErr>>
     {
         // We use a `this` variable because the compiler can't mutably borrow multiple times
         // across a `Deref`.
@@ -505,23 +968,50 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         loop {
             let mut network_not_ready = false;
 
+            // Apply the changes of the external addresses confirmed by reports.
+            if let Poll::Ready(change) = this.external_addr_reports.poll(cx) {
+                match change {
+                    ExternalAddrChange::Confirmed(addr) => {
+                        // Addresses that are already known, e.g. added via
+                        // `add_external_address`, are left untouched.
+                        if this.external_addrs.iter().all(|a| a.addr != addr) {
+                            this.behaviour.inject_new_external_addr(&addr);
+                            this.external_addrs.add(addr.clone(), AddressScore::Infinite);
+                            this.reported_external_addrs.insert(addr.clone());
+                        }
+                        return Poll::Ready(SwarmEvent::ExternalAddrConfirmed(addr))
+                    }
+                    ExternalAddrChange::Expired(addr) => {
+                        if this.reported_external_addrs.remove(&addr) {
+                            this.external_addrs.remove(&addr);
+                            this.behaviour.inject_expired_external_addr(&addr);
+                        }
+                        return Poll::Ready(SwarmEvent::ExternalAddrExpired(addr))
+                    }
+                }
+            }
+
             // First let the network make progress.
             match this.network.poll(cx) {
                 Poll::Pending => network_not_ready = true,
                 Poll::Ready(NetworkEvent::ConnectionEvent { connection, event }) => {
                     let peer = connection.peer_id();
                     let connection = connection.id();
-                    this.behaviour.inject_event(peer, connection, event);
+                    this.connection_usage.on_used(&connection);
+                    tracing::debug_span!("inject_event", peer_id = %peer, connection_id = ?connection)
+                        .in_scope(|| this.behaviour.inject_event(peer, connection, event));
                 },
                 Poll::Ready(NetworkEvent::AddressChange { connection, new_endpoint, old_endpoint }) => {
                     let peer = connection.peer_id();
                     let connection = connection.id();
+                    this.connection_records.on_address_change(&connection, new_endpoint.clone());
                     this.behaviour.inject_address_change(&peer, &connection, &old_endpoint, &new_endpoint);
                 },
                 Poll::Ready(NetworkEvent::ConnectionEstablished { connection, num_established }) => {
                     let peer_id = connection.peer_id();
                     let endpoint = connection.endpoint().clone();
-                    if this.banned_peers.contains(&peer_id) {
+                    if this.banned_peers.is_banned(&peer_id) {
+                        this.dial_tags.remove_peer(&peer_id);
                         this.network.peer(peer_id)
                             .into_connected()
                             .expect("the Network just notified us that we were connected; QED")
@@ -531,26 +1021,49 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                             endpoint,
                         });
                     } else {
-                        log::debug!("Connection established: {:?}; Total (peer): {}.",
+                        tracing::debug!("Connection established: {:?}; Total (peer): {}.",
                             connection.connected(), num_established);
+                        this.dial_backoffs.on_connected(&peer_id);
+                        if let ConnectedPoint::Dialer { address } = &endpoint {
+                            this.address_scores.on_success(&peer_id, address);
+                            if let Some(addrs) = this.concurrent_dials.on_established(&peer_id) {
+                                this.abort_dials(&peer_id, &addrs);
+                            }
+                        }
+                        this.connection_usage.on_established(peer_id, connection.id());
+                        this.connection_records.on_established(peer_id, connection.id(), endpoint.clone());
+                        if let Some(store) = this.peer_store.as_mut() {
+                            store.on_connection_established(&peer_id, &endpoint);
+                        }
+                        let tag = this.dial_tags.on_established(&peer_id, &endpoint);
                         let endpoint = connection.endpoint().clone();
                         this.behaviour.inject_connection_established(&peer_id, &connection.id(), &endpoint);
                         if num_established.get() == 1 {
                             this.behaviour.inject_connected(&peer_id);
                         }
+                        if this.closing {
+                            // The connection was pending when closing the swarm.
+                            this.drain_peer(&peer_id);
+                        }
+                        this.close_excess_connections();
                         return Poll::Ready(SwarmEvent::ConnectionEstablished {
-                            peer_id, num_established, endpoint
+                            peer_id, num_established, endpoint, tag
                         });
                     }
                 },
                 Poll::Ready(NetworkEvent::ConnectionClosed { id, connected, error, num_established }) => {
                     if let Some(error) = error.as_ref() {
-                        log::debug!("Connection {:?} closed: {:?}", connected, error);
+                        tracing::debug!("Connection {:?} closed: {:?}", connected, error);
                     } else {
-                        log::debug!("Connection {:?} closed (active close).", connected);
+                        tracing::debug!("Connection {:?} closed (active close).", connected);
                     }
                     let peer_id = connected.peer_id;
                     let endpoint = connected.endpoint;
+                    this.connection_usage.on_closed(&id);
+                    this.connection_records.on_closed(&id);
+                    if let Some(store) = this.peer_store.as_mut() {
+                        store.on_connection_closed(&peer_id);
+                    }
                     this.behaviour.inject_connection_closed(&peer_id, &id, &endpoint);
                     if num_established == 0 {
                         this.behaviour.inject_disconnected(&peer_id);
@@ -563,13 +1076,25 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     });
                 },
                 Poll::Ready(NetworkEvent::IncomingConnection { connection, .. }) => {
+                    if this.closing {
+                        tracing::debug!("Incoming connection from {} dropped: swarm is closing.",
+                            connection.send_back_addr);
+                        continue
+                    }
+                    if !this.accept_rate_limiter.try_accept(&connection.send_back_addr) {
+                        // Dropping the connection aborts it before the security upgrade.
+                        tracing::debug!("Incoming connection from {} dropped: accept rate limit exceeded.",
+                            connection.send_back_addr);
+                        continue
+                    }
                     let handler = this.behaviour.new_handler()
                         .into_node_handler_builder()
-                        .with_substream_upgrade_protocol_override(this.substream_upgrade_protocol_override);
+                        .with_substream_upgrade_protocol_override(this.substream_upgrade_protocol_override)
+                        .with_keep_alive_policy(this.keep_alive_policy.clone());
                     let local_addr = connection.local_addr.clone();
                     let send_back_addr = connection.send_back_addr.clone();
                     if let Err(e) = this.network.accept(connection, handler) {
-                        log::warn!("Incoming connection rejected: {:?}", e);
+                        tracing::warn!("Incoming connection rejected: {:?}", e);
                     }
                     return Poll::Ready(SwarmEvent::IncomingConnection {
                         local_addr,
@@ -577,7 +1102,7 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     });
                 },
                 Poll::Ready(NetworkEvent::NewListenerAddress { listener_id, listen_addr }) => {
-                    log::debug!("Listener {:?}; New address: {:?}", listener_id, listen_addr);
+                    tracing::debug!("Listener {:?}; New address: {:?}", listener_id, listen_addr);
                     if !this.listened_addrs.contains(&listen_addr) {
                         this.listened_addrs.push(listen_addr.clone())
                     }
@@ -585,13 +1110,13 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     return Poll::Ready(SwarmEvent::NewListenAddr(listen_addr));
                 }
                 Poll::Ready(NetworkEvent::ExpiredListenerAddress { listener_id, listen_addr }) => {
-                    log::debug!("Listener {:?}; Expired address {:?}.", listener_id, listen_addr);
+                    tracing::debug!("Listener {:?}; Expired address {:?}.", listener_id, listen_addr);
                     this.listened_addrs.retain(|a| a != &listen_addr);
                     this.behaviour.inject_expired_listen_addr(&listen_addr);
                     return Poll::Ready(SwarmEvent::ExpiredListenAddr(listen_addr));
                 }
                 Poll::Ready(NetworkEvent::ListenerClosed { listener_id, addresses, reason }) => {
-                    log::debug!("Listener {:?}; Closed by {:?}.", listener_id, reason);
+                    tracing::debug!("Listener {:?}; Closed by {:?}.", listener_id, reason);
                     for addr in addresses.iter() {
                         this.behaviour.inject_expired_listen_addr(addr);
                     }
@@ -611,7 +1136,7 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     });
                 },
                 Poll::Ready(NetworkEvent::IncomingConnectionError { local_addr, send_back_addr, error }) => {
-                    log::debug!("Incoming connection failed: {:?}", error);
+                    tracing::debug!("Incoming connection failed: {:?}", error);
                     return Poll::Ready(SwarmEvent::IncomingConnectionError {
                         local_addr,
                         send_back_addr,
@@ -619,27 +1144,46 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     });
                 },
                 Poll::Ready(NetworkEvent::DialError { peer_id, multiaddr, error, attempts_remaining }) => {
-                    log::debug!(
+                    tracing::debug!(
                         "Connection attempt to {:?} via {:?} failed with {:?}. Attempts remaining: {}.",
                         peer_id, multiaddr, error, attempts_remaining);
+                    this.address_scores.on_failure(Some(&peer_id), &multiaddr, attempts_remaining);
                     this.behaviour.inject_addr_reach_failure(Some(&peer_id), &multiaddr, &error);
+                    // The connection attempts of other lanes of a concurrent
+                    // dialing attempt are still in progress.
+                    let attempts_remaining = if attempts_remaining == 0 {
+                        this.concurrent_dials.on_lane_failed(&peer_id)
+                    } else {
+                        attempts_remaining
+                    };
                     if attempts_remaining == 0 {
+                        // A cancelled dial says nothing about the reachability of the peer.
+                        let cancelled = matches!(&error,
+                            PendingConnectionError::IO(e) if e.kind() == io::ErrorKind::Interrupted);
+                        if !cancelled {
+                            this.dial_backoffs.on_dial_failure(&peer_id);
+                        }
                         this.behaviour.inject_dial_failure(&peer_id);
                     }
+                    let tag = this.dial_tags.on_failure(&peer_id, attempts_remaining);
                     return Poll::Ready(SwarmEvent::UnreachableAddr {
                         peer_id,
                         address: multiaddr,
                         error,
                         attempts_remaining,
+                        tag,
                     });
                 },
                 Poll::Ready(NetworkEvent::UnknownPeerDialError { multiaddr, error, .. }) => {
-                    log::debug!("Connection attempt to address {:?} of unknown peer failed with {:?}",
+                    tracing::debug!("Connection attempt to address {:?} of unknown peer failed with {:?}",
                         multiaddr, error);
+                    this.address_scores.on_failure(None, &multiaddr, 0);
                     this.behaviour.inject_addr_reach_failure(None, &multiaddr, &error);
+                    let tag = this.dial_tags.on_unknown_peer_failure(&multiaddr);
                     return Poll::Ready(SwarmEvent::UnknownPeerUnreachableAddr {
                         address: multiaddr,
                         error,
+                        tag,
                     });
                 },
             }
@@ -689,10 +1233,16 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     return Poll::Ready(SwarmEvent::Behaviour(event))
                 },
                 Poll::Ready(NetworkBehaviourAction::DialAddress { address }) => {
-                    let _ = ExpandedSwarm::dial_addr(&mut *this, address);
+                    if !this.closing {
+                        let _ = ExpandedSwarm::dial_addr(&mut *this, address);
+                    }
                 },
                 Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }) => {
-                    if this.banned_peers.contains(&peer_id) {
+                    if this.closing || this.banned_peers.is_banned(&peer_id) {
+                        this.behaviour.inject_dial_failure(&peer_id);
+                    } else if let Some(remaining) = this.dial_backoffs.remaining(&peer_id) {
+                        tracing::debug!("Not dialing {:?}: backing off for another {:?}.",
+                            peer_id, remaining);
                         this.behaviour.inject_dial_failure(&peer_id);
                     } else {
                         let condition_matched = match condition {
@@ -708,25 +1258,40 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                             // Even if the condition for a _new_ dialing attempt is not met,
                             // we always add any potentially new addresses of the peer to an
                             // ongoing dialing attempt, if there is one.
-                            log::trace!("Condition for new dialing attempt to {:?} not met: {:?}",
+                            tracing::trace!("Condition for new dialing attempt to {:?} not met: {:?}",
                                 peer_id, condition);
-                            let self_listening = &this.listened_addrs;
-                            if let Some(mut peer) = this.network.peer(peer_id).into_dialing() {
-                                let addrs = this.behaviour.addresses_of_peer(peer.id());
-                                let mut attempt = peer.some_attempt();
-                                for a in addrs {
-                                    if !self_listening.contains(&a) {
-                                        attempt.add_address(a);
+                            if this.network.is_dialing(&peer_id) {
+                                let addrs = this.known_addresses_of_peer(&peer_id);
+                                let self_listening = &this.listened_addrs;
+                                if let Some(mut peer) = this.network.peer(peer_id).into_dialing() {
+                                    let mut attempt = peer.some_attempt();
+                                    for a in addrs {
+                                        if !self_listening.contains(&a) {
+                                            attempt.add_address(a);
+                                        }
                                     }
                                 }
                             }
                         }
                     }
                 },
+                Poll::Ready(NetworkBehaviourAction::Dial { opts }) => {
+                    let peer_id = opts.peer_id;
+                    if this.closing {
+                        if let Some(peer_id) = peer_id {
+                            this.behaviour.inject_dial_failure(&peer_id);
+                        }
+                    } else if ExpandedSwarm::dial_with_opts(this, opts).is_ok() {
+                        if let Some(peer_id) = peer_id {
+                            return Poll::Ready(SwarmEvent::Dialing(peer_id))
+                        }
+                    }
+                },
                 Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event }) => {
                     if let Some(mut peer) = this.network.peer(peer_id).into_connected() {
                         match handler {
                             NotifyHandler::One(connection) => {
+                                this.connection_usage.on_used(&connection);
                                 if let Some(mut conn) = peer.connection(connection) {
                                     if let Some(event) = notify_one(&mut conn, event, cx) {
                                         let handler = PendingNotifyHandler::One(connection);
@@ -754,9 +1319,23 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                         if this.external_addrs.iter().all(|a| a.addr != addr) {
                             this.behaviour.inject_new_external_addr(&addr);
                         }
+                        this.reported_external_addrs.remove(&addr);
                         this.external_addrs.add(addr, score);
                     }
                 },
+                Poll::Ready(NetworkBehaviourAction::ReportExternalAddr { address, source }) => {
+                    // Observed addresses are subject to the same translation
+                    // as for `ReportObservedAddr`, while the other sources
+                    // report addresses of the local node as they are.
+                    let addrs: Vec<_> = match source {
+                        ExternalAddrSource::Observed(_) =>
+                            this.network.address_translation(&address).collect(),
+                        _ => vec![address],
+                    };
+                    for addr in addrs {
+                        this.external_addr_reports.report(addr, source.clone());
+                    }
+                },
             }
         }
     }
@@ -775,6 +1354,61 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         }
     }
 
+    /// Drains all connections to the given peer, closing each once its
+    /// handler has completed its pending work.
+    fn drain_peer(&mut self, peer_id: &PeerId) {
+        if let Some(mut peer) = self.network.peer(*peer_id).into_connected() {
+            let mut conns = peer.connections();
+            while let Some(conn) = conns.next() {
+                conn.start_drain();
+            }
+        }
+    }
+
+    /// Returns the addresses of a peer reported by the behaviour, followed
+    /// by those of the [`PeerStore`], if any, without duplicates.
+    fn known_addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        let mut addrs = self.behaviour.addresses_of_peer(peer_id);
+        if let Some(store) = &self.peer_store {
+            for addr in store.addresses(peer_id) {
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+            }
+        }
+        addrs
+    }
+
+    /// Aborts the connection attempts to a peer dialing any of the given
+    /// addresses, e.g. the remaining lanes of a concurrent dialing attempt.
+    fn abort_dials(&mut self, peer_id: &PeerId, addrs: &[Multiaddr]) {
+        if let Some(mut peer) = self.network.peer(*peer_id).into_dialing() {
+            let mut attempts = peer.attempts();
+            while let Some(attempt) = attempts.next() {
+                if addrs.contains(attempt.address()) {
+                    attempt.abort();
+                }
+            }
+        }
+    }
+
+    /// Closes the least recently useful connections exceeding the maximum
+    /// number of connections of the [`KeepAlivePolicy`], if any.
+    fn close_excess_connections(&mut self) {
+        let excess = match &self.keep_alive_policy {
+            Some(policy) => self.connection_usage.excess(policy),
+            None => return,
+        };
+        for (peer_id, id) in excess {
+            tracing::debug!("Closing connection {:?} to {:?}: exceeds keep-alive policy.", id, peer_id);
+            if let Some(mut peer) = self.network.peer(peer_id).into_connected() {
+                if let Some(conn) = peer.connection(id) {
+                    conn.start_close();
+                }
+            }
+        }
+    }
+
     pub fn get_behaviour(&mut self) -> &mut TBehaviour {
         &mut self.behaviour
     }
@@ -937,6 +1571,15 @@ pub struct SwarmBuilder<TBehaviour> {
     behaviour: TBehaviour,
     network_config: NetworkConfig,
     substream_upgrade_protocol_override: Option<libp2p_core::upgrade::Version>,
+    protocol_registry: Option<ProtocolRegistry>,
+    accept_rate_limits: AcceptRateLimits,
+    dial_backoff: Option<DialBackoff>,
+    dial_ranking: DialRanking,
+    keep_alive_policy: Option<KeepAlivePolicy>,
+    peer_store: Option<PeerStore>,
+    dial_concurrency_factor: NonZeroU8,
+    external_addrs: ExternalAddrConfig,
+    buffer_pool: Option<BufferPoolConfig>,
 }
 
 impl<TBehaviour> SwarmBuilder<TBehaviour>
@@ -956,6 +1599,15 @@ where TBehaviour: NetworkBehaviour,
             behaviour,
             network_config: Default::default(),
             substream_upgrade_protocol_override: None,
+            protocol_registry: None,
+            accept_rate_limits: AcceptRateLimits::default(),
+            dial_backoff: Some(DialBackoff::default()),
+            dial_ranking: DialRanking::default(),
+            keep_alive_policy: None,
+            peer_store: None,
+            dial_concurrency_factor: NonZeroU8::new(1).expect("1 > 0"),
+            external_addrs: ExternalAddrConfig::default(),
+            buffer_pool: None,
         }
     }
 
@@ -1016,6 +1668,102 @@ where TBehaviour: NetworkBehaviour,
         self
     }
 
+    /// Configures the limits on the rate at which inbound connections are
+    /// accepted per source IP address and prefix.
+    ///
+    /// The counters of accepted and dropped connections are available
+    /// through [`ExpandedSwarm::accept_rate_stats`].
+    pub fn accept_rate_limits(mut self, limits: AcceptRateLimits) -> Self {
+        self.accept_rate_limits = limits;
+        self
+    }
+
+    /// Configures the exponential backoff of dialing requests of the
+    /// [`NetworkBehaviour`] for peers whose recent dialing attempts failed,
+    /// or disables it with `None`.
+    ///
+    /// Defaults to [`DialBackoff::default`]. Explicit dialing attempts via
+    /// [`ExpandedSwarm::dial`] are never subject to the backoff.
+    pub fn dial_backoff(mut self, backoff: Option<DialBackoff>) -> Self {
+        self.dial_backoff = backoff;
+        self
+    }
+
+    /// Configures the ranking of the addresses of a peer by their dialing
+    /// history when dialing the peer.
+    ///
+    /// Defaults to [`DialRanking::default`]. The history of an address is
+    /// available through [`ExpandedSwarm::address_stats`].
+    pub fn dial_ranking(mut self, ranking: DialRanking) -> Self {
+        self.dial_ranking = ranking;
+        self
+    }
+
+    /// Configures the maximum number of addresses of a peer that are
+    /// dialed concurrently, 1 by default, i.e. addresses are dialed
+    /// one after the other.
+    ///
+    /// With a factor greater than 1, the ranked addresses of a peer are
+    /// distributed among up to that many connection attempts in progress
+    /// at the same time. The first successful connection is kept while
+    /// the remaining connection attempts are aborted. The dialing attempt
+    /// fails once all addresses failed.
+    pub fn dial_concurrency_factor(mut self, factor: NonZeroU8) -> Self {
+        self.dial_concurrency_factor = factor;
+        self
+    }
+
+    /// Configures when external addresses reported via
+    /// [`NetworkBehaviourAction::ReportExternalAddr`] or
+    /// [`ExpandedSwarm::report_external_address`] are confirmed and when
+    /// they expire.
+    ///
+    /// Defaults to [`ExternalAddrConfig::default`].
+    pub fn external_addrs(mut self, config: ExternalAddrConfig) -> Self {
+        self.external_addrs = config;
+        self
+    }
+
+    /// Configures the [`BufferPool::global`] pool of I/O buffers from which
+    /// transports and stream multiplexers take their read and write buffers,
+    /// e.g. to retain more idle buffers on nodes with a high connection churn.
+    ///
+    /// > **Note**: The pool is shared by all swarms of the process, thus the
+    /// > configuration of the swarm built last applies.
+    pub fn buffer_pool(mut self, config: BufferPoolConfig) -> Self {
+        self.buffer_pool = Some(config);
+        self
+    }
+
+    /// Configures a [`KeepAlivePolicy`] for all connections, complementing
+    /// and overriding the keep-alive of the individual connection handlers.
+    pub fn keep_alive_policy(mut self, policy: KeepAlivePolicy) -> Self {
+        self.keep_alive_policy = Some(policy);
+        self
+    }
+
+    /// Configures a [`PeerStore`], which is consulted in addition to
+    /// [`NetworkBehaviour::addresses_of_peer`] when dialing a peer and
+    /// records the connection history of peers.
+    ///
+    /// The store is used as given, i.e. previously saved peers must be
+    /// loaded with [`PeerStore::load`] beforehand.
+    pub fn peer_store(mut self, store: PeerStore) -> Self {
+        self.peer_store = Some(store);
+        self
+    }
+
+    /// Configures individual timeouts for the stages of pending connections,
+    /// i.e. establishing the transport connection and negotiating the security
+    /// protocol and the stream multiplexer.
+    ///
+    /// The stages are reported by transports upgraded through
+    /// [`libp2p_core::transport::upgrade::Builder`].
+    pub fn stage_timeouts(mut self, timeouts: StageTimeouts) -> Self {
+        self.network_config = self.network_config.with_stage_timeouts(timeouts);
+        self
+    }
+
     /// Configures an override for the substream upgrade protocol to use.
     ///
     /// The subtream upgrade protocol is the multistream-select protocol
@@ -1031,6 +1779,16 @@ where TBehaviour: NetworkBehaviour,
         self
     }
 
+    /// Configures the [`ProtocolRegistry`] with which the behaviours have
+    /// registered their protocols.
+    ///
+    /// [`SwarmBuilder::build`] logs a warning for each protocol registered
+    /// by multiple behaviours or in conflicting versions.
+    pub fn protocol_registry(mut self, registry: ProtocolRegistry) -> Self {
+        self.protocol_registry = Some(registry);
+        self
+    }
+
     /// Builds a `Swarm` with the current configuration.
     pub fn build(mut self) -> Swarm<TBehaviour> {
         let supported_protocols = self.behaviour
@@ -1041,6 +1799,14 @@ where TBehaviour: NetworkBehaviour,
             .map(|info| info.protocol_name().to_vec())
             .collect();
 
+        if let Some(registry) = &self.protocol_registry {
+            registry.warn_conflicts();
+        }
+
+        if let Some(config) = self.buffer_pool {
+            BufferPool::global().configure(config);
+        }
+
         // If no executor has been explicitly configured, try to set up a thread pool.
         let network_cfg = self.network_config.or_else_with_executor(|| {
             match ThreadPoolBuilder::new()
@@ -1051,13 +1817,18 @@ where TBehaviour: NetworkBehaviour,
                     Some(Box::new(move |f| tp.spawn_ok(f)))
                 },
                 Err(err) => {
-                    log::warn!("Failed to create executor thread pool: {:?}", err);
+                    tracing::warn!("Failed to create executor thread pool: {:?}", err);
                     None
                 }
             }
         });
 
-        let network = Network::new(self.transport, self.local_peer_id, network_cfg);
+        let mut network = Network::new(self.transport, self.local_peer_id, network_cfg);
+        if let Some(policy) = &self.keep_alive_policy {
+            for peer in policy.protected_peers() {
+                network.add_protected_peer(*peer);
+            }
+        }
 
         ExpandedSwarm {
             network,
@@ -1065,9 +1836,25 @@ where TBehaviour: NetworkBehaviour,
             supported_protocols,
             listened_addrs: SmallVec::new(),
             external_addrs: Addresses::default(),
-            banned_peers: HashSet::new(),
+            external_addr_reports: ExternalAddrs::new(self.external_addrs),
+            reported_external_addrs: HashSet::new(),
+            banned_peers: PeerBans::default(),
+            dial_backoffs: DialBackoffs::new(self.dial_backoff),
+            address_scores: AddressScores::new(self.dial_ranking),
+            dial_tags: DialTags::default(),
+            dial_concurrency_factor: self.dial_concurrency_factor,
+            concurrent_dials: ConcurrentDials::default(),
+            peer_store: self.peer_store,
+            keep_alive_policy: self.keep_alive_policy.map(Arc::new),
+            connection_usage: ConnectionUsage::default(),
+            connection_records: ConnectionRecords::default(),
             pending_event: None,
             substream_upgrade_protocol_override: self.substream_upgrade_protocol_override,
+            protocol_registry: self.protocol_registry,
+            accept_rate_limiter: AcceptRateLimiter::new(self.accept_rate_limits),
+            closing: false,
+            event_sinks: EventSinks::default(),
+            held_event: None,
         }
     }
 }
@@ -1081,8 +1868,33 @@ pub enum DialError {
     /// has been reached.
     ConnectionLimit(ConnectionLimit),
     /// [`NetworkBehaviour::addresses_of_peer`] returned no addresses
-    /// for the peer to dial.
-    NoAddresses
+    /// for the peer to dial and none were given in the [`DialOpts`].
+    NoAddresses,
+    /// The [`DialPeerCondition`] of the [`DialOpts`] was not met.
+    DialPeerConditionFalse(DialPeerCondition),
+}
+
+impl DialError {
+    /// Returns a numeric code identifying the variant of this error.
+    ///
+    /// Contrary to the error messages, the codes are stable across versions
+    /// and never reused, which makes them suitable for identifying errors
+    /// across language boundaries, e.g. in FFI bindings.
+    ///
+    /// | Variant                  | Code  |
+    /// |--------------------------|-------|
+    /// | `Banned`                 | `200` |
+    /// | `ConnectionLimit`        | `201` |
+    /// | `NoAddresses`            | `202` |
+    /// | `DialPeerConditionFalse` | `203` |
+    pub fn code(&self) -> u32 {
+        match self {
+            DialError::Banned => 200,
+            DialError::ConnectionLimit(_) => 201,
+            DialError::NoAddresses => 202,
+            DialError::DialPeerConditionFalse(_) => 203,
+        }
+    }
 }
 
 impl fmt::Display for DialError {
@@ -1090,7 +1902,9 @@ impl fmt::Display for DialError {
         match self {
             DialError::ConnectionLimit(err) => write!(f, "Dial error: {}", err),
             DialError::NoAddresses => write!(f, "Dial error: no addresses for peer."),
-            DialError::Banned => write!(f, "Dial error: peer is banned.")
+            DialError::Banned => write!(f, "Dial error: peer is banned."),
+            DialError::DialPeerConditionFalse(c) =>
+                write!(f, "Dial error: condition {:?} for dialing peer was false.", c),
         }
     }
 }
@@ -1100,7 +1914,8 @@ impl error::Error for DialError {
         match self {
             DialError::ConnectionLimit(err) => Some(err),
             DialError::NoAddresses => None,
-            DialError::Banned => None
+            DialError::Banned => None,
+            DialError::DialPeerConditionFalse(_) => None,
         }
     }
 }
@@ -1156,6 +1971,15 @@ mod tests {
     use super::*;
 
     fn new_test_swarm<T, O>(handler_proto: T) -> Swarm<CallTraceBehaviour<MockBehaviour<T, O>>>
+    where
+        T: ProtocolsHandler + Clone,
+        T::OutEvent: Clone,
+        O: Send + 'static
+    {
+        new_test_swarm_builder(handler_proto).build()
+    }
+
+    fn new_test_swarm_builder<T, O>(handler_proto: T) -> SwarmBuilder<CallTraceBehaviour<MockBehaviour<T, O>>>
     where
         T: ProtocolsHandler + Clone,
         T::OutEvent: Clone,
@@ -1170,7 +1994,7 @@ mod tests {
             .multiplex(libp2p_mplex::MplexConfig::new())
             .boxed();
         let behaviour = CallTraceBehaviour::new(MockBehaviour::new(handler_proto));
-        SwarmBuilder::new(transport, behaviour, PeerId::from_public_key(pubkey)).build()
+        SwarmBuilder::new(transport, behaviour, PeerId::from_public_key(pubkey))
     }
 
     /// Establishes a number of connections between two peers,
@@ -1276,4 +2100,454 @@ mod tests {
             }
         }))
     }
+
+    /// Establishes a number of connections between two peers, after which
+    /// one peer closes its swarm.
+    ///
+    /// The test expects all connections to be closed gracefully before
+    /// the deadline passes.
+    #[test]
+    fn close_drains_connections() {
+        let mut handler_proto = DummyProtocolsHandler::default();
+        handler_proto.keep_alive = KeepAlive::Yes;
+
+        let mut swarm1 = new_test_swarm::<_, ()>(handler_proto.clone());
+        let mut swarm2 = new_test_swarm::<_, ()>(handler_proto);
+
+        let addr2: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(&mut swarm2, addr2.clone()).unwrap();
+
+        let num_connections = 3;
+        for _ in 0 .. num_connections {
+            Swarm::dial_addr(&mut swarm1, addr2.clone()).unwrap();
+        }
+
+        executor::block_on(future::poll_fn(|cx| {
+            loop {
+                let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+                let poll2 = Swarm::poll_next_event(Pin::new(&mut swarm2), cx);
+                if [&swarm1, &swarm2].iter().all(|s| {
+                    s.behaviour.inject_connection_established.len() == num_connections
+                }) {
+                    return Poll::Ready(())
+                }
+                if poll1.is_pending() && poll2.is_pending() {
+                    return Poll::Pending
+                }
+            }
+        }));
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        executor::block_on(async {
+            let close = Swarm::close(&mut swarm1, deadline);
+            let remote = future::poll_fn(|cx| {
+                while Swarm::poll_next_event(Pin::new(&mut swarm2), cx).is_ready() {}
+                Poll::<()>::Pending
+            });
+            futures::pin_mut!(close, remote);
+            future::select(close, remote).await;
+        });
+
+        assert_eq!(swarm1.behaviour.inject_connection_closed.len(), num_connections);
+        assert_eq!(swarm1.behaviour.inject_disconnected.len(), 1);
+        assert!(Instant::now() < deadline);
+    }
+
+    #[test]
+    fn keep_alive_policy_closes_excess_connections() {
+        let mut handler_proto = DummyProtocolsHandler::default();
+        handler_proto.keep_alive = KeepAlive::Yes;
+
+        let policy = KeepAlivePolicy::default().with_max_connections(Some(1));
+        let mut swarm1 = new_test_swarm_builder::<_, ()>(handler_proto.clone())
+            .keep_alive_policy(policy)
+            .build();
+        let mut swarm2 = new_test_swarm::<_, ()>(handler_proto);
+
+        let addr2: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(&mut swarm2, addr2.clone()).unwrap();
+
+        let num_connections = 3;
+        for _ in 0 .. num_connections {
+            Swarm::dial_addr(&mut swarm1, addr2.clone()).unwrap();
+        }
+
+        executor::block_on(future::poll_fn(|cx| {
+            loop {
+                let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+                let poll2 = Swarm::poll_next_event(Pin::new(&mut swarm2), cx);
+                if swarm1.behaviour.inject_connection_established.len() == num_connections
+                    && swarm1.behaviour.inject_connection_closed.len() == num_connections - 1
+                {
+                    return Poll::Ready(())
+                }
+                if poll1.is_pending() && poll2.is_pending() {
+                    return Poll::Pending
+                }
+            }
+        }));
+
+        assert!(swarm1.behaviour.inject_disconnected.is_empty());
+        assert_eq!(Swarm::network_info(&swarm1).connection_counters().num_established(), 1);
+    }
+
+    #[test]
+    fn dial_error_codes_are_stable() {
+        assert_eq!(DialError::Banned.code(), 200);
+        assert_eq!(DialError::ConnectionLimit(ConnectionLimit { limit: 1, current: 1 }).code(), 201);
+        assert_eq!(DialError::NoAddresses.code(), 202);
+        assert_eq!(DialError::DialPeerConditionFalse(DialPeerCondition::Always).code(), 203);
+    }
+
+    #[test]
+    fn dial_with_opts_returns_tags() {
+        let mut handler_proto = DummyProtocolsHandler::default();
+        handler_proto.keep_alive = KeepAlive::Yes;
+
+        let mut swarm1 = new_test_swarm::<_, ()>(handler_proto.clone());
+        let mut swarm2 = new_test_swarm::<_, ()>(handler_proto);
+
+        let addr2: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        let unreachable: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(&mut swarm2, addr2.clone()).unwrap();
+        let swarm2_id = *Swarm::local_peer_id(&swarm2);
+
+        Swarm::dial_with_opts(&mut swarm1, DialOpts::peer_id(swarm2_id)
+            .addresses(vec![addr2])
+            .tag(7)).unwrap();
+        Swarm::dial_with_opts(&mut swarm1, DialOpts::unknown_peer_id(unreachable.clone())
+            .tag(8)).unwrap();
+
+        let mut established = None;
+        let mut unreachable_tag = None;
+        executor::block_on(future::poll_fn(|cx| {
+            loop {
+                let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+                let poll2 = Swarm::poll_next_event(Pin::new(&mut swarm2), cx);
+                match &poll1 {
+                    Poll::Ready(SwarmEvent::ConnectionEstablished { peer_id, tag, .. }) =>
+                        established = Some((*peer_id, *tag)),
+                    Poll::Ready(SwarmEvent::UnknownPeerUnreachableAddr { address, tag, .. }) => {
+                        assert_eq!(address, &unreachable);
+                        unreachable_tag = *tag;
+                    }
+                    _ => {}
+                }
+                if established.is_some() && unreachable_tag.is_some() {
+                    return Poll::Ready(())
+                }
+                if poll1.is_pending() && poll2.is_pending() {
+                    return Poll::Pending
+                }
+            }
+        }));
+
+        assert_eq!(established, Some((swarm2_id, Some(7))));
+        assert_eq!(unreachable_tag, Some(8));
+
+        match Swarm::dial_with_opts(&mut swarm1, DialOpts::peer_id(swarm2_id)
+            .condition(DialPeerCondition::Disconnected))
+        {
+            Err(DialError::DialPeerConditionFalse(DialPeerCondition::Disconnected)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dial_consults_peer_store() {
+        let mut handler_proto = DummyProtocolsHandler::default();
+        handler_proto.keep_alive = KeepAlive::Yes;
+
+        let mut swarm2 = new_test_swarm::<_, ()>(handler_proto.clone());
+        let addr2: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(&mut swarm2, addr2.clone()).unwrap();
+        let swarm2_id = *Swarm::local_peer_id(&swarm2);
+
+        let mut store = PeerStore::new();
+        store.add_address(swarm2_id, addr2);
+        let mut swarm1 = new_test_swarm_builder::<_, ()>(handler_proto)
+            .peer_store(store)
+            .build();
+
+        // The behaviour knows no addresses of the peer.
+        Swarm::dial(&mut swarm1, &swarm2_id).unwrap();
+
+        executor::block_on(future::poll_fn(|cx| {
+            loop {
+                let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+                let poll2 = Swarm::poll_next_event(Pin::new(&mut swarm2), cx);
+                if swarm1.behaviour.inject_connection_established.len() == 1 {
+                    return Poll::Ready(())
+                }
+                if poll1.is_pending() && poll2.is_pending() {
+                    return Poll::Pending
+                }
+            }
+        }));
+
+        let record = Swarm::peer_store(&swarm1).unwrap().peer(&swarm2_id).unwrap();
+        assert_eq!((record.connections(), record.total_connections()), (1, 1));
+    }
+
+    #[test]
+    fn concurrent_dial_keeps_first_connection() {
+        let mut handler_proto = DummyProtocolsHandler::default();
+        handler_proto.keep_alive = KeepAlive::Yes;
+
+        let mut swarm1 = new_test_swarm_builder::<_, ()>(handler_proto.clone())
+            .dial_concurrency_factor(NonZeroU8::new(3).unwrap())
+            .build();
+        let mut swarm2 = new_test_swarm::<_, ()>(handler_proto);
+        let swarm2_id = *Swarm::local_peer_id(&swarm2);
+
+        let unreachable: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        let mut addrs = vec![unreachable];
+        for _ in 0 .. 2 {
+            let addr: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+            Swarm::listen_on(&mut swarm2, addr.clone()).unwrap();
+            addrs.push(addr);
+        }
+
+        Swarm::dial_with_opts(&mut swarm1, DialOpts::peer_id(swarm2_id).addresses(addrs)).unwrap();
+        assert_eq!(Swarm::network_info(&swarm1).connection_counters().num_pending_outgoing(), 3);
+
+        executor::block_on(future::poll_fn(|cx| {
+            loop {
+                let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+                let poll2 = Swarm::poll_next_event(Pin::new(&mut swarm2), cx);
+                if poll1.is_pending() && poll2.is_pending() {
+                    if swarm1.behaviour.inject_connection_established.is_empty() {
+                        return Poll::Pending
+                    }
+                    return Poll::Ready(())
+                }
+            }
+        }));
+
+        // The remaining connection attempt has been aborted and the failure of
+        // the unreachable address did not fail the dialing attempt.
+        assert_eq!(swarm1.behaviour.inject_connection_established.len(), 1);
+        assert!(swarm1.behaviour.inject_dial_failure.is_empty());
+        assert!(!Swarm::is_dialing(&swarm1, &swarm2_id));
+    }
+
+    #[test]
+    fn protected_peer_evicts_connection_at_limit() {
+        let mut handler_proto = DummyProtocolsHandler::default();
+        handler_proto.keep_alive = KeepAlive::Yes;
+
+        let limits = ConnectionLimits::default().with_max_established_incoming(Some(1));
+        let mut swarm1 = new_test_swarm_builder::<_, ()>(handler_proto.clone())
+            .connection_limits(limits)
+            .build();
+        let mut swarm2 = new_test_swarm::<_, ()>(handler_proto.clone());
+        let mut swarm3 = new_test_swarm::<_, ()>(handler_proto);
+        let swarm2_id = *Swarm::local_peer_id(&swarm2);
+        let swarm3_id = *Swarm::local_peer_id(&swarm3);
+
+        let addr1: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(&mut swarm1, addr1.clone()).unwrap();
+        assert!(Swarm::add_protected_peer(&mut swarm1, swarm3_id));
+
+        Swarm::dial_addr(&mut swarm2, addr1.clone()).unwrap();
+        let mut dialed_protected = false;
+        executor::block_on(future::poll_fn(|cx| {
+            loop {
+                let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+                let poll2 = Swarm::poll_next_event(Pin::new(&mut swarm2), cx);
+                let poll3 = Swarm::poll_next_event(Pin::new(&mut swarm3), cx);
+                if !dialed_protected && swarm1.behaviour.inject_connection_established.len() == 1 {
+                    // Dial from the protected peer only once the limit is reached.
+                    Swarm::dial_addr(&mut swarm3, addr1.clone()).unwrap();
+                    dialed_protected = true;
+                    continue
+                }
+                if swarm1.behaviour.inject_connection_closed.len() == 1 {
+                    return Poll::Ready(())
+                }
+                if poll1.is_pending() && poll2.is_pending() && poll3.is_pending() {
+                    return Poll::Pending
+                }
+            }
+        }));
+
+        let established = &swarm1.behaviour.inject_connection_established;
+        assert_eq!(established.iter().map(|(p, _, _)| *p).collect::<Vec<_>>(), vec![swarm2_id, swarm3_id]);
+        assert_eq!(swarm1.behaviour.inject_connection_closed[0].0, swarm2_id);
+        assert!(Swarm::is_connected(&swarm1, &swarm3_id));
+    }
+
+    #[test]
+    fn diagnostics_report_connections_and_summaries() {
+        let mut handler_proto = DummyProtocolsHandler::default();
+        handler_proto.keep_alive = KeepAlive::Yes;
+
+        let mut swarm1 = new_test_swarm::<_, ()>(handler_proto.clone());
+        let mut swarm2 = new_test_swarm::<_, ()>(handler_proto);
+        let swarm2_id = *Swarm::local_peer_id(&swarm2);
+        let summary = diagnostics::BehaviourSummary::new("mock").with_entry("peers", 1);
+        swarm1.behaviour.inner().summaries.push(summary.clone());
+
+        let addr2: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(&mut swarm2, addr2.clone()).unwrap();
+        Swarm::dial_addr(&mut swarm1, addr2.clone()).unwrap();
+
+        let diagnostics = Swarm::diagnostics(&swarm1);
+        assert_eq!(diagnostics.pending_dials.len(), 1);
+        assert_eq!(diagnostics.pending_dials[0].address, addr2);
+
+        executor::block_on(future::poll_fn(|cx| {
+            loop {
+                let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+                let poll2 = Swarm::poll_next_event(Pin::new(&mut swarm2), cx);
+                if swarm1.behaviour.inject_connection_established.len() == 1 {
+                    return Poll::Ready(())
+                }
+                if poll1.is_pending() && poll2.is_pending() {
+                    return Poll::Pending
+                }
+            }
+        }));
+
+        let diagnostics = Swarm::diagnostics(&swarm1);
+        assert_eq!(diagnostics.local_peer_id, *Swarm::local_peer_id(&swarm1));
+        assert!(diagnostics.pending_dials.is_empty());
+        assert_eq!(diagnostics.connections.len(), 1);
+        assert_eq!(diagnostics.connections[0].peer_id, swarm2_id);
+        assert_eq!(diagnostics.connections[0].endpoint, diagnostics::Endpoint::Dialer { address: addr2 });
+        assert_eq!(diagnostics.behaviour, vec![summary]);
+        assert_eq!(Swarm::diagnostics(&swarm2).listeners.len(), 1);
+    }
+
+    #[test]
+    fn reported_external_addresses_are_confirmed() {
+        let mut swarm = new_test_swarm_builder::<_, ()>(DummyProtocolsHandler::default())
+            .external_addrs(ExternalAddrConfig::default().with_confirmations(2))
+            .build();
+        let addr: Multiaddr = "/ip4/192.0.2.1/tcp/1".parse().unwrap();
+
+        // A single remote is not enough to confirm an address, no matter
+        // how often it reports it.
+        let peer = PeerId::random();
+        Swarm::report_external_address(&mut swarm, addr.clone(), ExternalAddrSource::Probe { peer_id: peer, reachable: false });
+        Swarm::report_external_address(&mut swarm, addr.clone(), ExternalAddrSource::Observed(peer));
+        Swarm::report_external_address(&mut swarm, addr.clone(), ExternalAddrSource::Observed(peer));
+        assert!(Swarm::poll_next_event(Pin::new(&mut swarm), &mut Context::from_waker(futures::task::noop_waker_ref())).is_pending());
+        assert_eq!(Swarm::external_addresses(&swarm).count(), 0);
+
+        Swarm::report_external_address(&mut swarm, addr.clone(), ExternalAddrSource::Observed(PeerId::random()));
+        match executor::block_on(swarm.next_event()) {
+            SwarmEvent::ExternalAddrConfirmed(a) => assert_eq!(a, addr),
+            e => panic!("Unexpected event: {:?}", e),
+        }
+        assert_eq!(Swarm::external_addresses(&swarm).map(|r| &r.addr).collect::<Vec<_>>(), vec![&addr]);
+        assert_eq!(swarm.behaviour.inject_new_external_addr, vec![addr.clone()]);
+        let candidate = Swarm::external_address_candidates(&swarm).next().unwrap();
+        assert_eq!((candidate.confidence, candidate.confirmed), (2, true));
+    }
+
+    #[test]
+    fn expiry_keeps_manually_added_addresses() {
+        let mut swarm = new_test_swarm_builder::<_, ()>(DummyProtocolsHandler::default())
+            .external_addrs(ExternalAddrConfig::default()
+                .with_confirmations(1)
+                .with_ttl(Duration::from_millis(100)))
+            .build();
+        let manual: Multiaddr = "/ip4/192.0.2.1/tcp/1".parse().unwrap();
+        let reported: Multiaddr = "/ip4/192.0.2.2/tcp/1".parse().unwrap();
+
+        Swarm::add_external_address(&mut swarm, manual.clone(), AddressScore::Infinite);
+        for addr in vec![manual.clone(), reported.clone()] {
+            Swarm::report_external_address(&mut swarm, addr.clone(), ExternalAddrSource::Observed(PeerId::random()));
+            match executor::block_on(swarm.next_event()) {
+                SwarmEvent::ExternalAddrConfirmed(a) => assert_eq!(a, addr),
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+        assert_eq!(swarm.behaviour.inject_new_external_addr, vec![reported.clone()]);
+
+        // Both reports expire, but only the reported address is removed.
+        let mut expired = Vec::new();
+        while expired.len() < 2 {
+            match executor::block_on(swarm.next_event()) {
+                SwarmEvent::ExternalAddrExpired(a) => expired.push(a),
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+        assert!(expired.contains(&manual) && expired.contains(&reported));
+        assert_eq!(Swarm::external_addresses(&swarm).map(|r| &r.addr).collect::<Vec<_>>(), vec![&manual]);
+        assert_eq!(swarm.behaviour.inject_expired_external_addr, vec![reported]);
+    }
+
+    #[test]
+    fn behaviour_cancels_dial() {
+        let mut swarm1 = new_test_swarm::<_, ()>(DummyProtocolsHandler::default());
+        let mut swarm2 = new_test_swarm::<_, ()>(DummyProtocolsHandler::default());
+        let swarm2_id = *Swarm::local_peer_id(&swarm2);
+
+        // `swarm2` is never polled, hence the dial does not complete on its own.
+        let addr2: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(&mut swarm2, addr2.clone()).unwrap();
+
+        let cancel = DialCancelToken::new();
+        let opts = DialOpts::peer_id(swarm2_id).addresses(vec![addr2]).cancel(cancel.clone());
+        swarm1.behaviour.inner().next_action = Some(NetworkBehaviourAction::Dial { opts });
+        match executor::block_on(swarm1.next_event()) {
+            SwarmEvent::Dialing(peer_id) => assert_eq!(peer_id, swarm2_id),
+            e => panic!("Unexpected event: {:?}", e),
+        }
+
+        cancel.cancel();
+        executor::block_on(future::poll_fn(|cx| loop {
+            let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+            if !swarm1.behaviour.inject_dial_failure.is_empty() {
+                return Poll::Ready(())
+            }
+            if poll1.is_pending() {
+                return Poll::Pending
+            }
+        }));
+        assert_eq!(swarm1.behaviour.inject_dial_failure, vec![swarm2_id]);
+    }
+
+    #[test]
+    fn cancelled_dial_is_not_backed_off() {
+        let mut swarm1 = new_test_swarm::<_, ()>(DummyProtocolsHandler::default());
+        let mut swarm2 = new_test_swarm::<_, ()>(DummyProtocolsHandler::default());
+        let swarm2_id = *Swarm::local_peer_id(&swarm2);
+
+        let addr2: Multiaddr = multiaddr::Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(&mut swarm2, addr2.clone()).unwrap();
+        swarm1.behaviour.inner().addresses.insert(swarm2_id, vec![addr2.clone()]);
+
+        let cancel = DialCancelToken::new();
+        let opts = DialOpts::peer_id(swarm2_id).addresses(vec![addr2]).cancel(cancel.clone());
+        swarm1.behaviour.inner().next_action = Some(NetworkBehaviourAction::Dial { opts });
+        match executor::block_on(swarm1.next_event()) {
+            SwarmEvent::Dialing(peer_id) => assert_eq!(peer_id, swarm2_id),
+            e => panic!("Unexpected event: {:?}", e),
+        }
+
+        cancel.cancel();
+        executor::block_on(future::poll_fn(|cx| loop {
+            let poll1 = Swarm::poll_next_event(Pin::new(&mut swarm1), cx);
+            if !swarm1.behaviour.inject_dial_failure.is_empty() {
+                return Poll::Ready(())
+            }
+            if poll1.is_pending() {
+                return Poll::Pending
+            }
+        }));
+
+        // Dialing the peer again right away is not subject to the dial backoff.
+        swarm1.behaviour.inner().next_action = Some(NetworkBehaviourAction::DialPeer {
+            peer_id: swarm2_id,
+            condition: DialPeerCondition::Always,
+        });
+        match executor::block_on(swarm1.next_event()) {
+            SwarmEvent::Dialing(peer_id) => assert_eq!(peer_id, swarm2_id),
+            e => panic!("Unexpected event: {:?}", e),
+        }
+        assert_eq!(swarm1.behaviour.inject_dial_failure, vec![swarm2_id]);
+    }
 }
@@ -0,0 +1,198 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Peer bans with an optional expiry and exponential dial backoff.
+
+use libp2p_core::PeerId;
+use std::{cmp, collections::HashMap, time::Duration};
+use wasm_timer::Instant;
+
+/// The peers banned, either permanently or until some point in time.
+#[derive(Debug, Default)]
+pub(crate) struct PeerBans {
+    banned: HashMap<PeerId, Option<Instant>>,
+}
+
+impl PeerBans {
+    /// Bans a peer until the given point in time, or permanently if `None`.
+    ///
+    /// Returns `true` if the peer was not banned before.
+    pub(crate) fn ban(&mut self, peer_id: PeerId, until: Option<Instant>) -> bool {
+        let was_banned = self.is_banned(&peer_id);
+        // A ban is only ever extended, never shortened.
+        let until = match (self.banned.get(&peer_id), until) {
+            (Some(None), _) | (_, None) => None,
+            (Some(Some(current)), Some(until)) => Some(cmp::max(*current, until)),
+            (None, until) => until,
+        };
+        self.banned.insert(peer_id, until);
+        !was_banned
+    }
+
+    /// Lifts the ban of a peer, if any.
+    pub(crate) fn unban(&mut self, peer_id: &PeerId) {
+        self.banned.remove(peer_id);
+    }
+
+    /// Checks whether a peer is currently banned, forgetting expired bans.
+    pub(crate) fn is_banned(&mut self, peer_id: &PeerId) -> bool {
+        match self.banned.get(peer_id) {
+            None => false,
+            Some(None) => true,
+            Some(Some(until)) if *until > Instant::now() => true,
+            Some(Some(_)) => {
+                self.banned.remove(peer_id);
+                false
+            }
+        }
+    }
+}
+
+/// The configuration of the exponential backoff of dialing attempts to
+/// peers whose recent dialing attempts failed.
+///
+/// After `n` consecutive failed dialing attempts, dialing requests of the
+/// [`NetworkBehaviour`](crate::NetworkBehaviour) for the peer are refused
+/// for `initial * 2^(n-1)`, capped at `max`. An established connection
+/// to the peer resets the backoff.
+#[derive(Debug, Clone)]
+pub struct DialBackoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Default for DialBackoff {
+    fn default() -> Self {
+        DialBackoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl DialBackoff {
+    /// Configures the backoff after the first failed dialing attempt.
+    pub fn with_initial(mut self, initial: Duration) -> Self {
+        self.initial = initial;
+        self
+    }
+
+    /// Configures the maximum backoff.
+    pub fn with_max(mut self, max: Duration) -> Self {
+        self.max = max;
+        self
+    }
+
+    /// Returns the backoff after the given number of consecutive failures.
+    fn backoff(&self, failures: u32) -> Duration {
+        let factor = 1u32.checked_shl(failures.saturating_sub(1)).unwrap_or(u32::MAX);
+        cmp::min(self.initial.checked_mul(factor).unwrap_or(self.max), self.max)
+    }
+}
+
+/// The dial backoffs of peers whose recent dialing attempts failed.
+#[derive(Debug)]
+pub(crate) struct DialBackoffs {
+    config: Option<DialBackoff>,
+    /// The number of consecutive failures and the end of the backoff per peer.
+    peers: HashMap<PeerId, (u32, Instant)>,
+}
+
+impl DialBackoffs {
+    pub(crate) fn new(config: Option<DialBackoff>) -> Self {
+        DialBackoffs { config, peers: HashMap::new() }
+    }
+
+    /// Records a failed dialing attempt to a peer.
+    pub(crate) fn on_dial_failure(&mut self, peer_id: &PeerId) {
+        if let Some(config) = &self.config {
+            let now = Instant::now();
+            // Forget peers whose backoff expired long ago.
+            let max = config.max;
+            self.peers.retain(|_, (_, until)| *until + max > now);
+            let (failures, until) = self.peers.entry(*peer_id).or_insert((0, now));
+            *failures = failures.saturating_add(1);
+            *until = now + config.backoff(*failures);
+            tracing::debug!("Backing off from dialing {:?} for {:?} after {} failures.",
+                peer_id, *until - now, failures);
+        }
+    }
+
+    /// Resets the backoff of a peer, e.g. after a connection has been established.
+    pub(crate) fn on_connected(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+
+    /// Returns the remaining backoff of a peer, if any.
+    pub(crate) fn remaining(&self, peer_id: &PeerId) -> Option<Duration> {
+        let (_, until) = self.peers.get(peer_id)?;
+        let now = Instant::now();
+        if *until > now {
+            Some(*until - now)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff() {
+        let config = DialBackoff::default()
+            .with_initial(Duration::from_secs(1))
+            .with_max(Duration::from_secs(10));
+        assert_eq!(config.backoff(1), Duration::from_secs(1));
+        assert_eq!(config.backoff(2), Duration::from_secs(2));
+        assert_eq!(config.backoff(4), Duration::from_secs(8));
+        assert_eq!(config.backoff(5), Duration::from_secs(10));
+        assert_eq!(config.backoff(100), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn backoff_reset_on_connection() {
+        let peer = PeerId::random();
+        let mut backoffs = DialBackoffs::new(Some(DialBackoff::default()));
+        assert!(backoffs.remaining(&peer).is_none());
+        backoffs.on_dial_failure(&peer);
+        assert!(backoffs.remaining(&peer).is_some());
+        backoffs.on_connected(&peer);
+        assert!(backoffs.remaining(&peer).is_none());
+
+        let mut disabled = DialBackoffs::new(None);
+        disabled.on_dial_failure(&peer);
+        assert!(disabled.remaining(&peer).is_none());
+    }
+
+    #[test]
+    fn temporary_ban_expires() {
+        let peer = PeerId::random();
+        let mut bans = PeerBans::default();
+        assert!(bans.ban(peer, Some(Instant::now() + Duration::from_secs(60))));
+        assert!(!bans.ban(peer, Some(Instant::now())));
+        assert!(bans.is_banned(&peer));
+        bans.unban(&peer);
+        assert!(!bans.is_banned(&peer));
+        assert!(bans.ban(peer, Some(Instant::now())));
+        assert!(!bans.is_banned(&peer));
+    }
+}
@@ -0,0 +1,482 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! A store of the known peers, shared by all behaviours of the swarm.
+
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use std::{
+    cmp,
+    collections::HashMap,
+    fmt,
+    fs,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+use wasm_timer::Instant;
+
+/// A store of the addresses, supported protocols, connection history and
+/// latency of peers.
+///
+/// A `PeerStore` configured with [`SwarmBuilder::peer_store`](crate::SwarmBuilder::peer_store)
+/// is consulted in addition to
+/// [`NetworkBehaviour::addresses_of_peer`](crate::NetworkBehaviour::addresses_of_peer)
+/// when dialing a peer. The swarm records the addresses of successful
+/// outgoing connections and the connection history of peers, while other
+/// information is fed by the application through
+/// [`ExpandedSwarm::peer_store_mut`](crate::ExpandedSwarm::peer_store_mut),
+/// e.g. the listen addresses and protocols of an identify `Received` event or
+/// the round-trip times of a ping event.
+///
+/// Addresses expire after a time-to-live and the store can be saved
+/// across restarts through a [`PeerStorePersistence`].
+pub struct PeerStore {
+    address_ttl: Duration,
+    max_addresses_per_peer: usize,
+    peers: HashMap<PeerId, PeerRecord>,
+    persistence: Option<Box<dyn PeerStorePersistence>>,
+}
+
+impl fmt::Debug for PeerStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerStore")
+            .field("address_ttl", &self.address_ttl)
+            .field("max_addresses_per_peer", &self.max_addresses_per_peer)
+            .field("peers", &self.peers)
+            .field("persistence", &self.persistence.is_some())
+            .finish()
+    }
+}
+
+impl Default for PeerStore {
+    fn default() -> Self {
+        PeerStore {
+            address_ttl: Duration::from_secs(60 * 60),
+            max_addresses_per_peer: 32,
+            peers: HashMap::new(),
+            persistence: None,
+        }
+    }
+}
+
+impl PeerStore {
+    /// Creates a new, empty `PeerStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures the time-to-live of addresses added without an explicit
+    /// one, which is one hour by default.
+    pub fn with_address_ttl(mut self, ttl: Duration) -> Self {
+        self.address_ttl = ttl;
+        self
+    }
+
+    /// Configures the maximum number of addresses kept per peer, 32 by
+    /// default. The addresses expiring first are forgotten first.
+    pub fn with_max_addresses_per_peer(mut self, max: usize) -> Self {
+        self.max_addresses_per_peer = max;
+        self
+    }
+
+    /// Configures the persistence used by [`PeerStore::load`] and
+    /// [`PeerStore::save`].
+    pub fn with_persistence(mut self, persistence: impl PeerStorePersistence + 'static) -> Self {
+        self.persistence = Some(Box::new(persistence));
+        self
+    }
+
+    /// Adds an address of a peer with the configured time-to-live,
+    /// refreshing the expiry of a known address.
+    pub fn add_address(&mut self, peer: PeerId, address: Multiaddr) {
+        self.add_address_with_ttl(peer, address, self.address_ttl)
+    }
+
+    /// Adds an address of a peer with the given time-to-live, extending
+    /// the expiry of a known address if it expires earlier.
+    pub fn add_address_with_ttl(&mut self, peer: PeerId, address: Multiaddr, ttl: Duration) {
+        let expires = Instant::now() + ttl;
+        let max = cmp::max(self.max_addresses_per_peer, 1);
+        let record = self.peers.entry(peer).or_default();
+        if let Some(entry) = record.addresses.iter_mut().find(|(a, _)| *a == address) {
+            entry.1 = cmp::max(entry.1, expires);
+            return
+        }
+        if record.addresses.len() >= max {
+            let first = record.addresses.iter()
+                .enumerate()
+                .min_by_key(|(_, (_, expires))| *expires)
+                .map(|(i, _)| i);
+            if let Some(i) = first {
+                record.addresses.remove(i);
+            }
+        }
+        record.addresses.push((address, expires));
+    }
+
+    /// Removes an address of a peer, returning whether it was known.
+    pub fn remove_address(&mut self, peer: &PeerId, address: &Multiaddr) -> bool {
+        if let Some(record) = self.peers.get_mut(peer) {
+            let len = record.addresses.len();
+            record.addresses.retain(|(a, _)| a != address);
+            return record.addresses.len() != len
+        }
+        false
+    }
+
+    /// Returns the addresses of a peer which have not yet expired.
+    pub fn addresses(&self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.peers.get(peer)
+            .map(|record| record.addresses().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Records the protocols supported by a peer, e.g. as reported by
+    /// identify, replacing the previously recorded ones.
+    pub fn set_protocols(&mut self, peer: PeerId, protocols: impl IntoIterator<Item = String>) {
+        self.peers.entry(peer).or_default().protocols = protocols.into_iter().collect();
+    }
+
+    /// Records a measured round-trip time to a peer, e.g. as reported by ping.
+    pub fn record_latency(&mut self, peer: PeerId, rtt: Duration) {
+        let record = self.peers.entry(peer).or_default();
+        // Exponentially weighted moving average, favouring the history.
+        record.latency = Some(match record.latency {
+            Some(avg) => (avg * 3 + rtt) / 4,
+            None => rtt,
+        });
+    }
+
+    /// Returns the record of a peer, if known.
+    pub fn peer(&self, peer: &PeerId) -> Option<&PeerRecord> {
+        self.peers.get(peer)
+    }
+
+    /// Returns an iterator over the known peers and their records.
+    pub fn peers(&self) -> impl Iterator<Item = (&PeerId, &PeerRecord)> {
+        self.peers.iter()
+    }
+
+    /// Forgets everything known about a peer.
+    pub fn remove_peer(&mut self, peer: &PeerId) -> Option<PeerRecord> {
+        self.peers.remove(peer)
+    }
+
+    /// Removes the expired addresses and the peers of which nothing
+    /// but expired addresses is known.
+    pub fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.peers.retain(|_, record| {
+            record.addresses.retain(|(_, expires)| *expires > now);
+            !record.addresses.is_empty()
+                || !record.protocols.is_empty()
+                || record.connections > 0
+                || record.latency.is_some()
+        });
+    }
+
+    /// Replaces the content of the store with the peers loaded from the
+    /// configured [`PeerStorePersistence`], if any.
+    pub fn load(&mut self) -> io::Result<()> {
+        let peers = match self.persistence.as_mut() {
+            Some(persistence) => persistence.load()?,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        self.peers = peers.into_iter().map(|stored| {
+            let record = PeerRecord {
+                addresses: stored.addresses.into_iter()
+                    .map(|(address, ttl)| (address, now + ttl))
+                    .collect(),
+                protocols: stored.protocols,
+                latency: stored.latency,
+                ..PeerRecord::default()
+            };
+            (stored.peer_id, record)
+        }).collect();
+        Ok(())
+    }
+
+    /// Saves the addresses which have not yet expired, the protocols and
+    /// the latency of all known peers to the configured [`PeerStorePersistence`],
+    /// if any.
+    pub fn save(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let peers = self.peers.iter().map(|(peer_id, record)| StoredPeer {
+            peer_id: *peer_id,
+            addresses: record.addresses.iter()
+                .filter(|(_, expires)| *expires > now)
+                .map(|(address, expires)| (address.clone(), *expires - now))
+                .collect(),
+            protocols: record.protocols.clone(),
+            latency: record.latency,
+        }).collect::<Vec<_>>();
+        match self.persistence.as_mut() {
+            Some(persistence) => persistence.save(&peers),
+            None => Ok(()),
+        }
+    }
+
+    /// Records a new connection to a peer, including the address of
+    /// an outgoing connection.
+    pub(crate) fn on_connection_established(&mut self, peer: &PeerId, endpoint: &ConnectedPoint) {
+        if let ConnectedPoint::Dialer { address } = endpoint {
+            self.add_address(*peer, address.clone());
+        }
+        let record = self.peers.entry(*peer).or_default();
+        record.connections += 1;
+        record.total_connections = record.total_connections.saturating_add(1);
+        record.last_connected = Some(Instant::now());
+    }
+
+    /// Records a closed connection to a peer.
+    pub(crate) fn on_connection_closed(&mut self, peer: &PeerId) {
+        if let Some(record) = self.peers.get_mut(peer) {
+            record.connections = record.connections.saturating_sub(1);
+            if record.connections == 0 {
+                record.last_disconnected = Some(Instant::now());
+            }
+        }
+    }
+}
+
+/// Everything a [`PeerStore`] knows about a peer.
+#[derive(Debug, Clone, Default)]
+pub struct PeerRecord {
+    addresses: Vec<(Multiaddr, Instant)>,
+    protocols: Vec<String>,
+    latency: Option<Duration>,
+    connections: u32,
+    total_connections: u64,
+    last_connected: Option<Instant>,
+    last_disconnected: Option<Instant>,
+}
+
+impl PeerRecord {
+    /// Returns the addresses of the peer which have not yet expired.
+    pub fn addresses(&self) -> impl Iterator<Item = &Multiaddr> {
+        let now = Instant::now();
+        self.addresses.iter()
+            .filter(move |(_, expires)| *expires > now)
+            .map(|(address, _)| address)
+    }
+
+    /// Returns the protocols supported by the peer.
+    pub fn protocols(&self) -> &[String] {
+        &self.protocols
+    }
+
+    /// Returns the smoothed round-trip time to the peer, if measured.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// Returns the number of currently established connections to the peer.
+    pub fn connections(&self) -> u32 {
+        self.connections
+    }
+
+    /// Returns the number of connections established to the peer so far.
+    pub fn total_connections(&self) -> u64 {
+        self.total_connections
+    }
+
+    /// Returns when a connection to the peer was last established.
+    pub fn last_connected(&self) -> Option<Instant> {
+        self.last_connected
+    }
+
+    /// Returns when the last connection to the peer was closed.
+    pub fn last_disconnected(&self) -> Option<Instant> {
+        self.last_disconnected
+    }
+}
+
+/// A peer as saved by a [`PeerStorePersistence`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredPeer {
+    /// The ID of the peer.
+    pub peer_id: PeerId,
+    /// The addresses of the peer, with their remaining time-to-live
+    /// at the time of saving.
+    pub addresses: Vec<(Multiaddr, Duration)>,
+    /// The protocols supported by the peer.
+    pub protocols: Vec<String>,
+    /// The smoothed round-trip time to the peer, if measured.
+    pub latency: Option<Duration>,
+}
+
+/// The persistence of a [`PeerStore`] across restarts.
+pub trait PeerStorePersistence: Send {
+    /// Loads the saved peers.
+    fn load(&mut self) -> io::Result<Vec<StoredPeer>>;
+
+    /// Saves the given peers, replacing the previously saved ones.
+    fn save(&mut self, peers: &[StoredPeer]) -> io::Result<()>;
+}
+
+/// A [`PeerStorePersistence`] saving the peers to a text file.
+///
+/// Each line of the file describes one property of a peer:
+///
+/// ```text
+/// <peer-id> address <multiaddr> <ttl-secs>
+/// <peer-id> protocol <name>
+/// <peer-id> latency <micros>
+/// ```
+///
+/// Loading a file which does not exist yields no peers.
+#[derive(Debug, Clone)]
+pub struct FilePersistence {
+    path: PathBuf,
+}
+
+impl FilePersistence {
+    /// Creates a new `FilePersistence` for the file at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FilePersistence { path: path.into() }
+    }
+}
+
+impl PeerStorePersistence for FilePersistence {
+    fn load(&mut self) -> io::Result<Vec<StoredPeer>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid line: {}", line));
+        let mut peers: Vec<StoredPeer> = Vec::new();
+        for line in io::BufReader::new(file).lines() {
+            let line = line?;
+            let mut parts = line.split_whitespace();
+            let peer_id = match parts.next() {
+                Some(peer_id) => PeerId::from_str(peer_id).map_err(|_| invalid(&line))?,
+                None => continue,
+            };
+            let index = match peers.iter().position(|p| p.peer_id == peer_id) {
+                Some(index) => index,
+                None => {
+                    peers.push(StoredPeer { peer_id, addresses: Vec::new(), protocols: Vec::new(), latency: None });
+                    peers.len() - 1
+                }
+            };
+            let peer = &mut peers[index];
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("address"), Some(address), Some(ttl)) => {
+                    let address = address.parse().map_err(|_| invalid(&line))?;
+                    let ttl = ttl.parse().map_err(|_| invalid(&line))?;
+                    peer.addresses.push((address, Duration::from_secs(ttl)));
+                }
+                (Some("protocol"), Some(protocol), None) => peer.protocols.push(protocol.to_owned()),
+                (Some("latency"), Some(micros), None) => {
+                    let micros = micros.parse().map_err(|_| invalid(&line))?;
+                    peer.latency = Some(Duration::from_micros(micros));
+                }
+                _ => return Err(invalid(&line)),
+            }
+        }
+        Ok(peers)
+    }
+
+    fn save(&mut self, peers: &[StoredPeer]) -> io::Result<()> {
+        let mut content = Vec::new();
+        for peer in peers {
+            for (address, ttl) in &peer.addresses {
+                writeln!(content, "{} address {} {}", peer.peer_id, address, ttl.as_secs())?;
+            }
+            for protocol in &peer.protocols {
+                writeln!(content, "{} protocol {}", peer.peer_id, protocol)?;
+            }
+            if let Some(latency) = peer.latency {
+                writeln!(content, "{} latency {}", peer.peer_id, latency.as_micros())?;
+            }
+        }
+        fs::write(&self.path, content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::multiaddr::Protocol;
+
+    fn addr(port: u16) -> Multiaddr {
+        Multiaddr::empty().with(Protocol::Memory(port.into()))
+    }
+
+    #[test]
+    fn addresses_expire_and_are_bounded() {
+        let peer = PeerId::random();
+        let mut store = PeerStore::new().with_max_addresses_per_peer(2);
+        store.add_address_with_ttl(peer, addr(1), Duration::from_secs(0));
+        store.add_address_with_ttl(peer, addr(2), Duration::from_secs(60));
+        assert_eq!(store.addresses(&peer), vec![addr(2)]);
+
+        // The address expiring first is evicted.
+        store.add_address(peer, addr(3));
+        assert_eq!(store.addresses(&peer), vec![addr(2), addr(3)]);
+        assert!(store.remove_address(&peer, &addr(2)));
+        assert_eq!(store.addresses(&peer), vec![addr(3)]);
+
+        store.add_address_with_ttl(peer, addr(4), Duration::from_secs(0));
+        store.remove_address(&peer, &addr(3));
+        store.remove_expired();
+        assert!(store.peer(&peer).is_none());
+    }
+
+    #[test]
+    fn records_connection_history() {
+        let peer = PeerId::random();
+        let mut store = PeerStore::new();
+        let endpoint = ConnectedPoint::Dialer { address: addr(1) };
+        store.on_connection_established(&peer, &endpoint);
+        store.on_connection_established(&peer, &endpoint);
+        store.on_connection_closed(&peer);
+
+        let record = store.peer(&peer).unwrap();
+        assert_eq!(record.addresses().collect::<Vec<_>>(), vec![&addr(1)]);
+        assert_eq!((record.connections(), record.total_connections()), (1, 2));
+        assert!(record.last_connected().is_some());
+        assert!(record.last_disconnected().is_none());
+    }
+
+    #[test]
+    fn saved_to_file_and_loaded() {
+        let path = std::env::temp_dir().join(format!("peer-store-{}", rand::random::<u64>()));
+        let peer = PeerId::random();
+        let mut store = PeerStore::new().with_persistence(FilePersistence::new(&path));
+        store.add_address(peer, addr(1));
+        store.set_protocols(peer, vec!["/ipfs/id/1.0.0".to_owned()]);
+        store.record_latency(peer, Duration::from_millis(20));
+        store.save().unwrap();
+
+        let mut loaded = PeerStore::new().with_persistence(FilePersistence::new(&path));
+        loaded.load().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let record = loaded.peer(&peer).unwrap();
+        assert_eq!(loaded.addresses(&peer), vec![addr(1)]);
+        assert_eq!(record.protocols(), &["/ipfs/id/1.0.0".to_owned()]);
+        assert_eq!(record.latency(), Some(Duration::from_millis(20)));
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A registry of the protocols supported by the local node.
+//!
+//! Behaviours register the names of the protocols they speak with a shared
+//! [`ProtocolRegistry`] when they are constructed. The registry can then be
+//! queried for the supported protocols and their versions, e.g. by the
+//! identify protocol when advertising the capabilities of the local node,
+//! and detects protocols registered by more than one behaviour.
+
+use libp2p_core::upgrade::ProtocolName;
+use std::{collections::BTreeMap, fmt, sync::{Arc, Mutex}};
+
+/// A shared registry of the protocols supported by the local node.
+///
+/// Cloning a `ProtocolRegistry` yields a handle to the same registry.
+#[derive(Clone, Default)]
+pub struct ProtocolRegistry {
+    /// The registered protocols, mapped to the names of the behaviours
+    /// which registered them.
+    protocols: Arc<Mutex<BTreeMap<String, Vec<String>>>>,
+}
+
+/// A problem with the registered protocols, as reported by
+/// [`ProtocolRegistry::conflicts`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolConflict {
+    /// The same protocol has been registered by multiple behaviours.
+    ///
+    /// Inbound substreams for the protocol are only ever delivered to one
+    /// of the behaviours.
+    Duplicate {
+        /// The protocol name.
+        protocol: String,
+        /// The behaviours which registered the protocol.
+        owners: Vec<String>,
+    },
+    /// Different versions of the same protocol have been registered by
+    /// different behaviours.
+    Versions {
+        /// The protocol name without version.
+        name: String,
+        /// The registered versions together with the behaviour which
+        /// registered them.
+        versions: Vec<(String, String)>,
+    },
+}
+
+impl fmt::Display for ProtocolConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolConflict::Duplicate { protocol, owners } =>
+                write!(f, "protocol {} registered by {}", protocol, owners.join(", ")),
+            ProtocolConflict::Versions { name, versions } => {
+                write!(f, "conflicting versions of protocol {}:", name)?;
+                for (version, owner) in versions {
+                    write!(f, " {} ({})", version, owner)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl ProtocolRegistry {
+    /// Creates an empty `ProtocolRegistry`.
+    pub fn new() -> Self {
+        ProtocolRegistry::default()
+    }
+
+    /// Registers a protocol supported by the behaviour named `owner`.
+    ///
+    /// Protocol names which are not valid UTF-8 are registered lossily.
+    /// Returns `false` if the behaviour has already registered the protocol.
+    pub fn register(&self, owner: &str, protocol: impl ProtocolName) -> bool {
+        let protocol = String::from_utf8_lossy(protocol.protocol_name()).into_owned();
+        let mut protocols = self.protocols.lock().expect("not poisoned");
+        let owners = protocols.entry(protocol).or_default();
+        if owners.iter().any(|o| o == owner) {
+            return false
+        }
+        owners.push(owner.to_owned());
+        true
+    }
+
+    /// Removes all protocols registered by the behaviour named `owner`.
+    pub fn unregister(&self, owner: &str) {
+        let mut protocols = self.protocols.lock().expect("not poisoned");
+        for owners in protocols.values_mut() {
+            owners.retain(|o| o != owner);
+        }
+        protocols.retain(|_, owners| !owners.is_empty());
+    }
+
+    /// Returns the names of all registered protocols, in lexicographic order.
+    pub fn protocols(&self) -> Vec<String> {
+        self.protocols.lock().expect("not poisoned").keys().cloned().collect()
+    }
+
+    /// Returns the names of the behaviours which registered the given protocol.
+    pub fn owners(&self, protocol: &str) -> Vec<String> {
+        self.protocols.lock().expect("not poisoned")
+            .get(protocol)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Checks whether the given protocol is registered.
+    pub fn supports(&self, protocol: &str) -> bool {
+        self.protocols.lock().expect("not poisoned").contains_key(protocol)
+    }
+
+    /// Returns the registered versions of the protocol with the given name,
+    /// e.g. `["1.0.0", "1.1.0"]` for `/meshsub`.
+    pub fn versions(&self, name: &str) -> Vec<String> {
+        self.protocols.lock().expect("not poisoned")
+            .keys()
+            .filter_map(|p| split_version(p))
+            .filter(|(n, _)| *n == name)
+            .map(|(_, v)| v.to_owned())
+            .collect()
+    }
+
+    /// Returns the registered protocols which are also supported by a remote,
+    /// given the protocols it advertises.
+    pub fn common<'a>(&self, remote: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let protocols = self.protocols.lock().expect("not poisoned");
+        remote.into_iter()
+            .filter(|p| protocols.contains_key(*p))
+            .map(|p| p.to_owned())
+            .collect()
+    }
+
+    /// Returns the protocols registered by more than one behaviour and the
+    /// protocols of which different versions have been registered by
+    /// different behaviours.
+    pub fn conflicts(&self) -> Vec<ProtocolConflict> {
+        let protocols = self.protocols.lock().expect("not poisoned");
+        let mut conflicts = Vec::new();
+        let mut versioned = BTreeMap::<&str, Vec<(&str, &str)>>::new();
+        for (protocol, owners) in protocols.iter() {
+            if owners.len() > 1 {
+                conflicts.push(ProtocolConflict::Duplicate {
+                    protocol: protocol.clone(),
+                    owners: owners.clone(),
+                });
+            }
+            if let Some((name, version)) = split_version(protocol) {
+                let entry = versioned.entry(name).or_default();
+                for owner in owners {
+                    entry.push((version, owner));
+                }
+            }
+        }
+        for (name, versions) in versioned {
+            let owner = versions[0].1;
+            if versions.iter().all(|(_, o)| *o == owner) {
+                // A behaviour supporting multiple versions of a protocol is fine.
+                continue
+            }
+            let mut distinct = versions.iter().map(|(v, _)| *v).collect::<Vec<_>>();
+            distinct.dedup();
+            if distinct.len() > 1 {
+                conflicts.push(ProtocolConflict::Versions {
+                    name: name.to_owned(),
+                    versions: versions.iter()
+                        .map(|(v, o)| (v.to_string(), o.to_string()))
+                        .collect(),
+                });
+            }
+        }
+        conflicts
+    }
+
+    /// Logs a warning for each of the [conflicts](ProtocolRegistry::conflicts).
+    pub fn warn_conflicts(&self) {
+        for conflict in self.conflicts() {
+            tracing::warn!("Protocol registry: {}", conflict);
+        }
+    }
+}
+
+impl fmt::Debug for ProtocolRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.protocols.lock().expect("not poisoned").iter())
+            .finish()
+    }
+}
+
+/// Splits a protocol name like `/ipfs/kad/1.0.0` into the name and the
+/// version, i.e. the last path segment if it starts with a digit.
+fn split_version(protocol: &str) -> Option<(&str, &str)> {
+    let pos = protocol.rfind('/')?;
+    let (name, version) = (&protocol[.. pos], &protocol[pos + 1 ..]);
+    if name.is_empty() || !version.starts_with(|c: char| c.is_ascii_digit()) {
+        return None
+    }
+    Some((name, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_and_capabilities() {
+        let registry = ProtocolRegistry::new();
+        assert!(registry.register("gossipsub", b"/meshsub/1.1.0"));
+        assert!(registry.register("gossipsub", b"/meshsub/1.0.0"));
+        assert!(!registry.register("gossipsub", b"/meshsub/1.0.0"));
+        assert!(registry.register("ping", b"/ipfs/ping/1.0.0"));
+
+        assert_eq!(registry.versions("/meshsub"), vec!["1.0.0", "1.1.0"]);
+        assert!(registry.supports("/ipfs/ping/1.0.0"));
+        assert_eq!(registry.common(vec!["/ipfs/ping/1.0.0", "/ipfs/id/1.0.0"]), vec!["/ipfs/ping/1.0.0"]);
+        assert!(registry.conflicts().is_empty());
+
+        registry.unregister("ping");
+        assert!(!registry.supports("/ipfs/ping/1.0.0"));
+    }
+
+    #[test]
+    fn detects_conflicts() {
+        let registry = ProtocolRegistry::new();
+        registry.register("ping", b"/ipfs/ping/1.0.0");
+        registry.register("custom-ping", b"/ipfs/ping/1.0.0");
+        registry.register("kad", b"/ipfs/kad/1.0.0");
+        registry.register("kad-next", b"/ipfs/kad/2.0.0");
+
+        assert_eq!(registry.conflicts(), vec![
+            ProtocolConflict::Duplicate {
+                protocol: "/ipfs/ping/1.0.0".into(),
+                owners: vec!["ping".into(), "custom-ping".into()],
+            },
+            ProtocolConflict::Versions {
+                name: "/ipfs/kad".into(),
+                versions: vec![("1.0.0".into(), "kad".into()), ("2.0.0".into(), "kad-next".into())],
+            },
+        ]);
+    }
+}
@@ -190,6 +190,20 @@ pub trait ProtocolsHandler: Send + 'static {
         ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
     >;
 
+    /// Polls the handler for the completion of its pending work, e.g. queued
+    /// messages that have yet to be sent, before the connection is gracefully
+    /// closed.
+    ///
+    /// This method is called when the connection is drained, e.g. as part of
+    /// [`Swarm::close`](crate::Swarm::close). The handler keeps being polled
+    /// through [`ProtocolsHandler::poll`] in the meantime, but should not
+    /// start any new work. Once `Ready` is returned, the connection is closed.
+    ///
+    /// The default implementation has no pending work to wait for.
+    fn poll_close(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+
     /// Adds a closure that turns the input event into something else.
     fn map_in_event<TNewIn, TMap>(self, map: TMap) -> MapInEvent<Self, TNewIn, TMap>
     where
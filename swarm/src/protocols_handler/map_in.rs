@@ -112,4 +112,8 @@ where
     > {
         self.inner.poll(cx)
     }
+
+    fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_close(cx)
+    }
 }
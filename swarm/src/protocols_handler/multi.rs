@@ -122,7 +122,7 @@ where
                             // Different upgrade (i.e. protocol negotiation) protocol
                             // versions are usually incompatible and not negotiated
                             // themselves, so a protocol upgrade may fail.
-                            log::warn!("Differing upgrade versions. Defaulting to V1.");
+                            tracing::warn!("Differing upgrade versions. Defaulting to V1.");
                             Some(upgrade::Version::V1)
                         } else {
                             Some(v)
@@ -143,7 +143,7 @@ where
         if let Some(h) = self.handlers.get_mut(&key) {
             h.inject_fully_negotiated_outbound(protocol, arg)
         } else {
-            log::error!("inject_fully_negotiated_outbound: no handler for key")
+            tracing::error!("inject_fully_negotiated_outbound: no handler for key")
         }
     }
 
@@ -157,7 +157,7 @@ where
                 h.inject_fully_negotiated_inbound(arg, i)
             }
         } else {
-            log::error!("inject_fully_negotiated_inbound: no handler for key")
+            tracing::error!("inject_fully_negotiated_inbound: no handler for key")
         }
     }
 
@@ -165,7 +165,7 @@ where
         if let Some(h) = self.handlers.get_mut(&key) {
             h.inject_event(event)
         } else {
-            log::error!("inject_event: no handler for key")
+            tracing::error!("inject_event: no handler for key")
         }
     }
 
@@ -183,7 +183,7 @@ where
         if let Some(h) = self.handlers.get_mut(&key) {
             h.inject_dial_upgrade_error(arg, error)
         } else {
-            log::error!("inject_dial_upgrade_error: no handler for protocol")
+            tracing::error!("inject_dial_upgrade_error: no handler for protocol")
         }
     }
 
@@ -286,6 +286,19 @@ where
 
         Poll::Pending
     }
+
+    fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        // Poll all handlers, so that each is woken up once done.
+        let mut done = true;
+        for h in self.handlers.values_mut() {
+            done &= h.poll_close(cx).is_ready();
+        }
+        if done {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
 }
 
 /// A [`IntoProtocolsHandler`] for multiple other `IntoProtocolsHandler`s.
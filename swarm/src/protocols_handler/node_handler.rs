@@ -18,6 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::KeepAlivePolicy;
 use crate::upgrade::SendWrapper;
 use crate::protocols_handler::{
     KeepAlive,
@@ -39,10 +40,13 @@ use libp2p_core::{
         Substream,
         SubstreamEndpoint,
     },
-    muxing::StreamMuxerBox,
-    upgrade::{self, InboundUpgradeApply, OutboundUpgradeApply, UpgradeError}
+    muxing::{OutboundSubstreamError, StreamMuxerBox, SubstreamStats},
+    upgrade::{
+        self, InboundUpgradeApply, NegotiationError, OutboundUpgradeApply, ProtocolError,
+        ProtocolName, UpgradeError
+    }
 };
-use std::{error, fmt, pin::Pin, task::Context, task::Poll, time::Duration};
+use std::{error, fmt, io, pin::Pin, sync::Arc, task::Context, task::Poll, time::Duration};
 use wasm_timer::{Delay, Instant};
 
 /// Prototype for a `NodeHandlerWrapper`.
@@ -51,6 +55,8 @@ pub struct NodeHandlerWrapperBuilder<TIntoProtoHandler> {
     handler: TIntoProtoHandler,
     /// The substream upgrade protocol override, if any.
     substream_upgrade_protocol_override: Option<upgrade::Version>,
+    /// The keep-alive policy of the swarm, if any.
+    keep_alive_policy: Option<Arc<KeepAlivePolicy>>,
 }
 
 impl<TIntoProtoHandler> NodeHandlerWrapperBuilder<TIntoProtoHandler>
@@ -62,6 +68,7 @@ where
         NodeHandlerWrapperBuilder {
             handler,
             substream_upgrade_protocol_override: None,
+            keep_alive_policy: None,
         }
     }
 
@@ -72,6 +79,14 @@ where
         self.substream_upgrade_protocol_override = version;
         self
     }
+
+    pub(crate) fn with_keep_alive_policy(
+        mut self,
+        policy: Option<Arc<KeepAlivePolicy>>
+    ) -> Self {
+        self.keep_alive_policy = policy;
+        self
+    }
 }
 
 impl<TIntoProtoHandler, TProtoHandler> IntoConnectionHandler
@@ -83,6 +98,12 @@ where
     type Handler = NodeHandlerWrapper<TIntoProtoHandler::Handler>;
 
     fn into_handler(self, connected: &Connected) -> Self::Handler {
+        let protected = self.keep_alive_policy.as_ref()
+            .map_or(false, |p| p.is_protected(&connected.peer_id));
+        let idle_timeout = self.keep_alive_policy.as_ref()
+            .and_then(|p| p.idle_timeout())
+            .filter(|_| !protected)
+            .map(|timeout| (timeout, Delay::new(timeout)));
         NodeHandlerWrapper {
             handler: self.handler.into_handler(&connected.peer_id, &connected.endpoint),
             negotiating_in: Default::default(),
@@ -91,6 +112,8 @@ where
             unique_dial_upgrade_id: 0,
             shutdown: Shutdown::None,
             substream_upgrade_protocol_override: self.substream_upgrade_protocol_override,
+            protected,
+            idle_timeout,
         }
     }
 }
@@ -107,12 +130,12 @@ where
     /// Futures that upgrade incoming substreams.
     negotiating_in: FuturesUnordered<SubstreamUpgrade<
         TProtoHandler::InboundOpenInfo,
-        InboundUpgradeApply<Substream<StreamMuxerBox>, SendWrapper<TProtoHandler::InboundProtocol>>,
+        InboundUpgradeApply<Substream<StreamMuxerBox>, TagProtocol<SendWrapper<TProtoHandler::InboundProtocol>>>,
     >>,
     /// Futures that upgrade outgoing substreams.
     negotiating_out: FuturesUnordered<SubstreamUpgrade<
         TProtoHandler::OutboundOpenInfo,
-        OutboundUpgradeApply<Substream<StreamMuxerBox>, SendWrapper<TProtoHandler::OutboundProtocol>>,
+        OutboundUpgradeApply<Substream<StreamMuxerBox>, TagProtocol<SendWrapper<TProtoHandler::OutboundProtocol>>>,
     >>,
     /// For each outbound substream request, how to upgrade it. The first element of the tuple
     /// is the unique identifier (see `unique_dial_upgrade_id`).
@@ -123,6 +146,23 @@ where
     shutdown: Shutdown,
     /// The substream upgrade protocol override, if any.
     substream_upgrade_protocol_override: Option<upgrade::Version>,
+    /// Whether the connection is always kept alive, see [`KeepAlivePolicy`].
+    protected: bool,
+    /// The idle timeout of the [`KeepAlivePolicy`], if any, with the timer
+    /// that is reset on every activity of the connection.
+    idle_timeout: Option<(Duration, Delay)>,
+}
+
+impl<TProtoHandler> NodeHandlerWrapper<TProtoHandler>
+where
+    TProtoHandler: ProtocolsHandler,
+{
+    /// Resets the idle timeout, if any, on activity of the connection.
+    fn on_activity(&mut self) {
+        if let Some((timeout, timer)) = &mut self.idle_timeout {
+            timer.reset(*timeout)
+        }
+    }
 }
 
 struct SubstreamUpgrade<UserData, Upgrade> {
@@ -166,6 +206,47 @@ where
     }
 }
 
+/// Wraps the upgrade of a substream, attributing the traffic of the
+/// substream to the negotiated protocol in the statistics of the connection.
+struct TagProtocol<U> {
+    upgrade: U,
+    stats: Option<SubstreamStats>,
+}
+
+impl<U: upgrade::UpgradeInfo> upgrade::UpgradeInfo for TagProtocol<U> {
+    type Info = U::Info;
+    type InfoIter = U::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.upgrade.protocol_info()
+    }
+}
+
+impl<C, U: upgrade::InboundUpgrade<C>> upgrade::InboundUpgrade<C> for TagProtocol<U> {
+    type Output = U::Output;
+    type Error = U::Error;
+    type Future = U::Future;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        if let Some(stats) = &self.stats {
+            stats.set_protocol(info.protocol_name());
+        }
+        self.upgrade.upgrade_inbound(socket, info)
+    }
+}
+
+impl<C, U: upgrade::OutboundUpgrade<C>> upgrade::OutboundUpgrade<C> for TagProtocol<U> {
+    type Output = U::Output;
+    type Error = U::Error;
+    type Future = U::Future;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        if let Some(stats) = &self.stats {
+            stats.set_protocol(info.protocol_name());
+        }
+        self.upgrade.upgrade_outbound(socket, info)
+    }
+}
 
 /// The options for a planned connection & handler shutdown.
 ///
@@ -242,12 +323,14 @@ where
         substream: Self::Substream,
         endpoint: SubstreamEndpoint<Self::OutboundOpenInfo>,
     ) {
+        self.on_activity();
         match endpoint {
             SubstreamEndpoint::Listener => {
                 let protocol = self.handler.listen_protocol();
                 let timeout = *protocol.timeout();
                 let (_, upgrade, user_data) = protocol.into_upgrade();
-                let upgrade = upgrade::apply_inbound(substream, SendWrapper(upgrade));
+                let stats = substream.stats();
+                let upgrade = upgrade::apply_inbound(substream, TagProtocol { upgrade: SendWrapper(upgrade), stats });
                 let timeout = Delay::new(timeout);
                 self.negotiating_in.push(SubstreamUpgrade {
                     user_data: Some(user_data),
@@ -271,11 +354,12 @@ where
                 let (_, (mut version, upgrade)) = self.queued_dial_upgrades.remove(pos);
                 if let Some(v) = self.substream_upgrade_protocol_override {
                     if v != version {
-                        log::debug!("Substream upgrade protocol override: {:?} -> {:?}", version, v);
+                        tracing::debug!("Substream upgrade protocol override: {:?} -> {:?}", version, v);
                         version = v;
                     }
                 }
-                let upgrade = upgrade::apply_outbound(substream, upgrade, version);
+                let stats = substream.stats();
+                let upgrade = upgrade::apply_outbound(substream, TagProtocol { upgrade, stats }, version);
                 let timeout = Delay::new(timeout);
                 self.negotiating_out.push(SubstreamUpgrade {
                     user_data: Some(user_data),
@@ -287,6 +371,7 @@ where
     }
 
     fn inject_event(&mut self, event: Self::InEvent) {
+        self.on_activity();
         self.handler.inject_event(event);
     }
 
@@ -294,6 +379,17 @@ where
         self.handler.inject_address_change(new_address);
     }
 
+    fn inject_outbound_failure(&mut self, (upgrade_id, user_data, _): Self::OutboundOpenInfo, error: io::Error) {
+        self.queued_dial_upgrades.retain(|(id, _)| id != &upgrade_id);
+        let error = match OutboundSubstreamError::from_io_error(&error) {
+            Some(OutboundSubstreamError::Timeout) => ProtocolsHandlerUpgrErr::Timeout,
+            _ => ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(
+                NegotiationError::ProtocolError(ProtocolError::IoError(error))
+            )),
+        };
+        self.handler.inject_dial_upgrade_error(user_data, error);
+    }
+
     fn poll(&mut self, cx: &mut Context<'_>) -> Poll<
         Result<ConnectionHandlerEvent<Self::OutboundOpenInfo, Self::OutEvent>, Self::Error>
     > {
@@ -313,7 +409,7 @@ where
 
         // Poll the handler at the end so that we see the consequences of the method
         // calls on `self.handler`.
-        let poll_result = self.handler.poll(cx);
+        let poll_result = tracing::trace_span!("handler").in_scope(|| self.handler.poll(cx));
 
         // Ask the handler whether it wants the connection (and the handler itself)
         // to be kept alive, which determines the planned shutdown, if any.
@@ -327,9 +423,13 @@ where
             (_, KeepAlive::No) => self.shutdown = Shutdown::Asap,
             (_, KeepAlive::Yes) => self.shutdown = Shutdown::None
         };
+        if self.protected {
+            self.shutdown = Shutdown::None;
+        }
 
         match poll_result {
             Poll::Ready(ProtocolsHandlerEvent::Custom(event)) => {
+                self.on_activity();
                 return Poll::Ready(Ok(ConnectionHandlerEvent::Custom(event)));
             }
             Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest { protocol }) => {
@@ -337,6 +437,7 @@ where
                 let timeout = *protocol.timeout();
                 self.unique_dial_upgrade_id += 1;
                 let (version, upgrade, info) = protocol.into_upgrade();
+                self.on_activity();
                 self.queued_dial_upgrades.push((id, (version, SendWrapper(upgrade))));
                 return Poll::Ready(Ok(
                     ConnectionHandlerEvent::OutboundSubstreamRequest((id, info, timeout)),
@@ -357,8 +458,26 @@ where
                     Poll::Pending => {}
                 }
             }
+            // The idle timeout of the keep-alive policy overrides the handler.
+            if let Some((_, timer)) = &mut self.idle_timeout {
+                if let Poll::Ready(_) = Future::poll(Pin::new(timer), cx) {
+                    return Poll::Ready(Err(NodeHandlerWrapperError::KeepAliveTimeout))
+                }
+            }
         }
 
         Poll::Pending
     }
+
+    fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        // Substreams still being opened or negotiated may carry pending work
+        // of the handler, so the handler is only done once they are settled.
+        if !self.queued_dial_upgrades.is_empty()
+            || !self.negotiating_in.is_empty()
+            || !self.negotiating_out.is_empty()
+        {
+            return Poll::Pending
+        }
+        self.handler.poll_close(cx)
+    }
 }
@@ -221,6 +221,16 @@ where
 
         Poll::Pending
     }
+
+    fn poll_close(&mut self, _: &mut Context<'_>) -> Poll<()> {
+        // Queued and outstanding requests are flushed by `poll`, which is
+        // called again whenever their substreams make progress.
+        if self.pending_requests() == 0 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
 }
 
 /// Configuration parameters for the `OneShotHandler`
@@ -273,4 +273,15 @@ where
 
         Poll::Pending
     }
+
+    fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        // Poll both handlers, so that both are woken up once done.
+        let proto1 = self.proto1.poll_close(cx);
+        let proto2 = self.proto2.poll_close(cx);
+        if proto1.is_ready() && proto2.is_ready() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
 }
@@ -0,0 +1,365 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! A rolling snapshot of the network state for post-mortem diagnostics.
+//!
+//! The [`CrashSnapshot`] behaviour observes the events of the [`Swarm`](crate::Swarm)
+//! it is part of and maintains a compact summary of the connected peers,
+//! their connections, the listen addresses and the most recent errors.
+//! The summary can be rendered or written to a file at any time through a
+//! [`SnapshotHandle`], in particular from a panic hook installed with
+//! [`SnapshotHandle::install_panic_hook`] or from a thread handling
+//! signals of the process.
+//!
+//! ```
+//! use libp2p_swarm::snapshot::CrashSnapshot;
+//!
+//! let snapshot = CrashSnapshot::new();
+//! snapshot.handle().install_panic_hook("network-state.txt");
+//! // Add `snapshot` as a field to the behaviour of the swarm.
+//! ```
+
+use crate::{NetworkBehaviour, NetworkBehaviourAction, PollParameters};
+use crate::protocols_handler::DummyProtocolsHandler;
+use libp2p_core::{
+    ConnectedPoint,
+    Multiaddr,
+    PeerId,
+    connection::{ConnectionId, ListenerId},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    error,
+    fmt,
+    fs,
+    io,
+    panic,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use void::Void;
+use wasm_timer::Instant;
+
+/// The default number of recent errors retained by a [`CrashSnapshot`].
+const DEFAULT_MAX_ERRORS: usize = 32;
+
+/// A [`NetworkBehaviour`] maintaining a [`Snapshot`] of the network state.
+///
+/// The behaviour neither opens substreams nor emits events.
+pub struct CrashSnapshot {
+    state: Arc<Mutex<Snapshot>>,
+}
+
+impl CrashSnapshot {
+    /// Creates a new `CrashSnapshot`, retaining the 32 most recent errors.
+    pub fn new() -> Self {
+        CrashSnapshot::with_max_errors(DEFAULT_MAX_ERRORS)
+    }
+
+    /// Creates a new `CrashSnapshot`, retaining the given number of recent errors.
+    pub fn with_max_errors(max_errors: usize) -> Self {
+        CrashSnapshot {
+            state: Arc::new(Mutex::new(Snapshot {
+                started: Instant::now(),
+                peers: HashMap::new(),
+                listen_addrs: Vec::new(),
+                external_addrs: Vec::new(),
+                errors: VecDeque::with_capacity(max_errors),
+                max_errors,
+                connections_established: 0,
+                connections_closed: 0,
+            }))
+        }
+    }
+
+    /// Returns a handle to the snapshot, which remains usable
+    /// after the behaviour has been moved into a [`Swarm`](crate::Swarm).
+    pub fn handle(&self) -> SnapshotHandle {
+        SnapshotHandle { state: self.state.clone() }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Snapshot)) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut state)
+    }
+}
+
+impl Default for CrashSnapshot {
+    fn default() -> Self {
+        CrashSnapshot::new()
+    }
+}
+
+/// A handle to the [`Snapshot`] of a [`CrashSnapshot`] behaviour.
+#[derive(Clone)]
+pub struct SnapshotHandle {
+    state: Arc<Mutex<Snapshot>>,
+}
+
+impl SnapshotHandle {
+    /// Renders the current snapshot as text.
+    pub fn render(&self) -> String {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).to_string()
+    }
+
+    /// Writes the current snapshot to the file at the given path,
+    /// replacing any previous content.
+    pub fn dump(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.render())
+    }
+
+    /// Installs a panic hook writing the panic message followed by the
+    /// current snapshot to the file at the given path, before invoking
+    /// the previously installed panic hook.
+    ///
+    /// > **Note**: If the panic occurs while the snapshot is being updated,
+    /// > only the panic message is written.
+    pub fn install_panic_hook(&self, path: impl Into<PathBuf>) {
+        let state = self.state.clone();
+        let path = path.into();
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // The panicking thread may hold the lock, so don't block on it.
+            let snapshot = match state.try_lock() {
+                Ok(state) => state.to_string(),
+                Err(_) => String::from("snapshot unavailable\n"),
+            };
+            let _ = fs::write(&path, format!("{}\n\n{}", info, snapshot));
+            previous(info);
+        }));
+    }
+}
+
+/// A compact summary of the network state.
+struct Snapshot {
+    /// When the snapshot was created.
+    started: Instant,
+    /// The connected peers.
+    peers: HashMap<PeerId, PeerState>,
+    /// The addresses the swarm listens on.
+    listen_addrs: Vec<Multiaddr>,
+    /// The external addresses of the swarm that have been discovered.
+    external_addrs: Vec<Multiaddr>,
+    /// The most recent errors, oldest first.
+    errors: VecDeque<ErrorRecord>,
+    /// The maximum length of `errors`.
+    max_errors: usize,
+    /// The total number of established connections.
+    connections_established: u64,
+    /// The total number of closed connections.
+    connections_closed: u64,
+}
+
+/// The state of a connected peer.
+struct PeerState {
+    /// When the first of the current connections was established.
+    since: Instant,
+    /// The established connections.
+    connections: Vec<(ConnectionId, ConnectedPoint)>,
+}
+
+/// An error observed by a [`CrashSnapshot`].
+struct ErrorRecord {
+    at: Instant,
+    message: String,
+}
+
+impl Snapshot {
+    fn record_error(&mut self, message: String) {
+        if self.max_errors == 0 {
+            return
+        }
+        if self.errors.len() == self.max_errors {
+            self.errors.pop_front();
+        }
+        self.errors.push_back(ErrorRecord { at: Instant::now(), message });
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let now = Instant::now();
+        let num_connections = self.peers.values().map(|p| p.connections.len()).sum::<usize>();
+        writeln!(f, "uptime: {}s", now.duration_since(self.started).as_secs())?;
+        writeln!(f, "connections: {} open, {} established, {} closed",
+            num_connections, self.connections_established, self.connections_closed)?;
+        writeln!(f, "listen addresses: {}", self.listen_addrs.len())?;
+        for addr in &self.listen_addrs {
+            writeln!(f, "  {}", addr)?;
+        }
+        writeln!(f, "external addresses: {}", self.external_addrs.len())?;
+        for addr in &self.external_addrs {
+            writeln!(f, "  {}", addr)?;
+        }
+        writeln!(f, "peers: {}", self.peers.len())?;
+        for (peer, state) in &self.peers {
+            writeln!(f, "  {} for {}s", peer, now.duration_since(state.since).as_secs())?;
+            for (_, endpoint) in &state.connections {
+                match endpoint {
+                    ConnectedPoint::Dialer { address } =>
+                        writeln!(f, "    dialer {}", address)?,
+                    ConnectedPoint::Listener { send_back_addr, .. } =>
+                        writeln!(f, "    listener {}", send_back_addr)?,
+                }
+            }
+        }
+        writeln!(f, "recent errors: {}", self.errors.len())?;
+        for error in &self.errors {
+            writeln!(f, "  {}s ago: {}", now.duration_since(error.at).as_secs(), error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl NetworkBehaviour for CrashSnapshot {
+    type ProtocolsHandler = DummyProtocolsHandler;
+    type OutEvent = Void;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {
+    }
+
+    fn inject_disconnected(&mut self, _: &PeerId) {
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, id: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.update(|s| {
+            s.connections_established += 1;
+            s.peers.entry(*peer)
+                .or_insert_with(|| PeerState { since: Instant::now(), connections: Vec::new() })
+                .connections
+                .push((*id, endpoint.clone()));
+        })
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, id: &ConnectionId, _: &ConnectedPoint) {
+        self.update(|s| {
+            s.connections_closed += 1;
+            if let Some(state) = s.peers.get_mut(peer) {
+                state.connections.retain(|(c, _)| c != id);
+                if state.connections.is_empty() {
+                    s.peers.remove(peer);
+                }
+            }
+        })
+    }
+
+    fn inject_address_change(&mut self, peer: &PeerId, id: &ConnectionId, _: &ConnectedPoint, new: &ConnectedPoint) {
+        self.update(|s| {
+            if let Some(state) = s.peers.get_mut(peer) {
+                for (c, endpoint) in state.connections.iter_mut() {
+                    if c == id {
+                        *endpoint = new.clone();
+                    }
+                }
+            }
+        })
+    }
+
+    fn inject_event(&mut self, _: PeerId, _: ConnectionId, event: Void) {
+        void::unreachable(event)
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error) {
+        self.update(|s| match peer {
+            Some(peer) => s.record_error(format!("failed to reach {} at {}: {}", peer, addr, error)),
+            None => s.record_error(format!("failed to reach {}: {}", addr, error)),
+        })
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        self.update(|s| s.record_error(format!("failed to dial {}", peer)))
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.update(|s| if !s.listen_addrs.contains(addr) {
+            s.listen_addrs.push(addr.clone())
+        })
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.update(|s| s.listen_addrs.retain(|a| a != addr))
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.update(|s| if !s.external_addrs.contains(addr) {
+            s.external_addrs.push(addr.clone())
+        })
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.update(|s| s.external_addrs.retain(|a| a != addr))
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn std::error::Error + 'static)) {
+        self.update(|s| s.record_error(format!("listener {:?} error: {}", id, err)))
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId, reason: Result<(), &std::io::Error>) {
+        if let Err(err) = reason {
+            self.update(|s| s.record_error(format!("listener {:?} closed: {}", id, err)))
+        }
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<Void, Void>>
+    {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_connections_and_errors() {
+        let mut snapshot = CrashSnapshot::with_max_errors(2);
+        let handle = snapshot.handle();
+        let peer = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/1234".parse().unwrap();
+        let endpoint = ConnectedPoint::Dialer { address: addr.clone() };
+
+        snapshot.inject_new_listen_addr(&addr);
+        snapshot.inject_connection_established(&peer, &ConnectionId::new(1), &endpoint);
+        for _ in 0 .. 3 {
+            snapshot.inject_dial_failure(&peer);
+        }
+
+        let rendered = handle.render();
+        assert!(rendered.contains("connections: 1 open, 1 established, 0 closed"));
+        assert!(rendered.contains(&format!("dialer {}", addr)));
+        assert!(rendered.contains("recent errors: 2"));
+
+        snapshot.inject_connection_closed(&peer, &ConnectionId::new(1), &endpoint);
+        let rendered = handle.render();
+        assert!(rendered.contains("peers: 0"));
+        assert!(rendered.contains("connections: 0 open, 1 established, 1 closed"));
+    }
+}
@@ -0,0 +1,574 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Switching a `NetworkBehaviour` on and off at runtime.
+//!
+//! Unlike a [`Toggle`](crate::toggle::Toggle), whose state is chosen at
+//! initialization, a [`Switch`] can be enabled and disabled at any time
+//! without rebuilding the `Swarm`. While disabled, the inner behaviour is
+//! not polled, inbound substreams for its protocols are denied and the
+//! protocols handlers of existing connections are dropped once they have
+//! completed their pending work (see [`ProtocolsHandler::poll_close`]),
+//! closing their substreams.
+
+use crate::{NetworkBehaviour, NetworkBehaviourAction, NetworkBehaviourEventProcess, NotifyHandler, PollParameters};
+use crate::diagnostics::BehaviourSummary;
+use crate::upgrade::{SendWrapper, InboundUpgradeSend, OutboundUpgradeSend};
+use crate::protocols_handler::{
+    KeepAlive,
+    SubstreamProtocol,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    IntoProtocolsHandler
+};
+use either::Either;
+use libp2p_core::{
+    ConnectedPoint,
+    PeerId,
+    Multiaddr,
+    connection::{ConnectionId, ListenerId},
+    either::{EitherError, EitherOutput},
+    upgrade::{DeniedUpgrade, EitherUpgrade}
+};
+use smallvec::SmallVec;
+use std::{collections::{HashMap, VecDeque}, error, io, task::Context, task::Poll};
+
+/// A `NetworkBehaviour` that can be enabled and disabled at runtime.
+///
+/// Fields of a struct deriving `NetworkBehaviour` that are marked with
+/// `#[behaviour(toggle)]` must implement this trait.
+pub trait Switchable {
+    /// Returns `true` if the behaviour is enabled.
+    fn is_enabled(&self) -> bool;
+
+    /// Enables or disables the behaviour.
+    fn set_enabled(&mut self, enabled: bool);
+}
+
+/// Implementation of `NetworkBehaviour` that can be enabled and disabled at runtime.
+///
+/// The inner behaviour is informed about all connections and listeners even
+/// while disabled, so that its state is consistent once enabled again.
+pub struct Switch<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour
+{
+    inner: TBehaviour,
+    enabled: bool,
+    /// Incremented whenever the switch is enabled or disabled.
+    generation: u64,
+    /// The established connections, whose handlers are notified when switching.
+    connections: HashMap<PeerId, SmallVec<[ConnectionId; 2]>>,
+    /// Switch events yet to be delivered to the handlers.
+    pending_events: VecDeque<(PeerId, ConnectionId, SwitchInEvent<TBehaviour::ProtocolsHandler>)>,
+}
+
+impl<TBehaviour> Switch<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour
+{
+    /// Creates a new `Switch` around the given behaviour, in the given state.
+    pub fn new(inner: TBehaviour, enabled: bool) -> Self {
+        Switch {
+            inner,
+            enabled,
+            generation: 0,
+            connections: HashMap::new(),
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Returns `true` if the `Switch` is enabled and `false` if it's disabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables or disables the inner behaviour.
+    ///
+    /// Has no effect if the `Switch` is already in the given state.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled == enabled {
+            return
+        }
+        self.enabled = enabled;
+        self.generation += 1;
+        for (peer_id, connections) in &self.connections {
+            for connection in connections {
+                let event = switch_event(&mut self.inner, enabled);
+                self.pending_events.push_back((*peer_id, *connection, event));
+            }
+        }
+    }
+
+    /// Returns a reference to the inner `NetworkBehaviour`.
+    pub fn get_ref(&self) -> &TBehaviour {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner `NetworkBehaviour`.
+    pub fn get_mut(&mut self) -> &mut TBehaviour {
+        &mut self.inner
+    }
+}
+
+/// Builds the event switching a handler to the given state.
+fn switch_event<TBehaviour>(inner: &mut TBehaviour, enabled: bool) -> SwitchInEvent<TBehaviour::ProtocolsHandler>
+where
+    TBehaviour: NetworkBehaviour
+{
+    if enabled {
+        SwitchInEvent::Enable(inner.new_handler())
+    } else {
+        SwitchInEvent::Disable
+    }
+}
+
+impl<TBehaviour> Switchable for Switch<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour
+{
+    fn is_enabled(&self) -> bool {
+        Switch::is_enabled(self)
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        Switch::set_enabled(self, enabled)
+    }
+}
+
+impl<TBehaviour> NetworkBehaviour for Switch<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour
+{
+    type ProtocolsHandler = IntoSwitchHandler<TBehaviour::ProtocolsHandler>;
+    type OutEvent = TBehaviour::OutEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        IntoSwitchHandler {
+            inner: if self.enabled { Some(self.inner.new_handler()) } else { None },
+            generation: self.generation,
+        }
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        if self.enabled {
+            self.inner.addresses_of_peer(peer_id)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn inject_connected(&mut self, peer_id: &PeerId) {
+        self.inner.inject_connected(peer_id)
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId) {
+        self.inner.inject_disconnected(peer_id)
+    }
+
+    fn inject_connection_established(&mut self, peer_id: &PeerId, connection: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.connections.entry(*peer_id).or_default().push(*connection);
+        self.inner.inject_connection_established(peer_id, connection, endpoint)
+    }
+
+    fn inject_address_change(&mut self, peer_id: &PeerId, connection: &ConnectionId, old: &ConnectedPoint, new: &ConnectedPoint) {
+        self.inner.inject_address_change(peer_id, connection, old, new)
+    }
+
+    fn inject_connection_closed(&mut self, peer_id: &PeerId, connection: &ConnectionId, endpoint: &ConnectedPoint) {
+        if let Some(connections) = self.connections.get_mut(peer_id) {
+            connections.retain(|c| c != connection);
+            if connections.is_empty() {
+                self.connections.remove(peer_id);
+            }
+        }
+        self.inner.inject_connection_closed(peer_id, connection, endpoint)
+    }
+
+    fn inject_event(
+        &mut self,
+        peer_id: PeerId,
+        connection: ConnectionId,
+        event: <<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::OutEvent
+    ) {
+        match event {
+            SwitchOutEvent::Inner(event) => self.inner.inject_event(peer_id, connection, event),
+            SwitchOutEvent::Created { generation } => {
+                // The switch changed its state while the connection was pending.
+                if generation != self.generation {
+                    let event = switch_event(&mut self.inner, self.enabled);
+                    self.pending_events.push_back((peer_id, connection, event));
+                }
+            }
+        }
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error) {
+        self.inner.inject_addr_reach_failure(peer_id, addr, error)
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        self.inner.inject_dial_failure(peer_id)
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_new_listen_addr(addr)
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_expired_listen_addr(addr)
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_new_external_addr(addr)
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_expired_external_addr(addr)
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn error::Error + 'static)) {
+        self.inner.inject_listener_error(id, err)
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId, reason: Result<(), &io::Error>) {
+        self.inner.inject_listener_closed(id, reason)
+    }
+
+    fn diagnostics(&self) -> Vec<BehaviourSummary> {
+        if self.enabled {
+            self.inner.diagnostics()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<<<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent, Self::OutEvent>>
+    {
+        if let Some((peer_id, connection, event)) = self.pending_events.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::One(connection),
+                event,
+            })
+        }
+
+        if self.enabled {
+            self.inner.poll(cx, params).map(|action| action.map_in(SwitchInEvent::Inner))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<TEvent, TBehaviour> NetworkBehaviourEventProcess<TEvent> for Switch<TBehaviour>
+where
+    TBehaviour: NetworkBehaviour + NetworkBehaviourEventProcess<TEvent>
+{
+    fn inject_event(&mut self, event: TEvent) {
+        NetworkBehaviourEventProcess::inject_event(&mut self.inner, event)
+    }
+}
+
+/// Event sent to a [`SwitchHandler`].
+pub enum SwitchInEvent<TInner>
+where
+    TInner: IntoProtocolsHandler
+{
+    /// An event for the inner handler.
+    Inner(<TInner::Handler as ProtocolsHandler>::InEvent),
+    /// Enables the handler, building the inner handler from the given
+    /// prototype if there is none.
+    Enable(TInner),
+    /// Disables the handler, dropping the inner handler once it has
+    /// completed its pending work.
+    Disable,
+}
+
+/// Event produced by a [`SwitchHandler`].
+pub enum SwitchOutEvent<TEvent> {
+    /// An event of the inner handler.
+    Inner(TEvent),
+    /// Reports the generation of the [`Switch`] at the time the handler
+    /// was created, right after the connection is established.
+    Created { generation: u64 },
+}
+
+/// Implementation of `IntoProtocolsHandler` that can be switched on and off.
+pub struct IntoSwitchHandler<TInner> {
+    inner: Option<TInner>,
+    generation: u64,
+}
+
+impl<TInner> IntoProtocolsHandler for IntoSwitchHandler<TInner>
+where
+    TInner: IntoProtocolsHandler
+{
+    type Handler = SwitchHandler<TInner>;
+
+    fn into_handler(self, remote_peer_id: &PeerId, connected_point: &ConnectedPoint) -> Self::Handler {
+        SwitchHandler {
+            remote_peer_id: *remote_peer_id,
+            connected_point: connected_point.clone(),
+            inner: self.inner.map(|h| h.into_handler(remote_peer_id, connected_point)),
+            inner_id: 0,
+            disabling: false,
+            created: Some(self.generation),
+        }
+    }
+
+    fn inbound_protocol(&self) -> <Self::Handler as ProtocolsHandler>::InboundProtocol {
+        if let Some(inner) = self.inner.as_ref() {
+            EitherUpgrade::A(SendWrapper(inner.inbound_protocol()))
+        } else {
+            EitherUpgrade::B(SendWrapper(DeniedUpgrade))
+        }
+    }
+}
+
+/// Implementation of `ProtocolsHandler` that can be switched on and off.
+///
+/// Substreams are tagged with an identifier of the inner handler they
+/// belong to, so that substreams negotiated for an inner handler that has
+/// been dropped in the meantime are discarded.
+pub struct SwitchHandler<TInner>
+where
+    TInner: IntoProtocolsHandler
+{
+    remote_peer_id: PeerId,
+    connected_point: ConnectedPoint,
+    inner: Option<TInner::Handler>,
+    /// Identifies the current inner handler.
+    inner_id: u64,
+    /// Whether the inner handler is to be dropped once it is done.
+    disabling: bool,
+    /// The generation of the switch to report, if not yet reported.
+    created: Option<u64>,
+}
+
+impl<TInner> SwitchHandler<TInner>
+where
+    TInner: IntoProtocolsHandler
+{
+    /// Returns the inner handler, if it belongs to the given identifier.
+    fn inner_mut(&mut self, id: u64) -> Option<&mut TInner::Handler> {
+        if id == self.inner_id {
+            self.inner.as_mut()
+        } else {
+            None
+        }
+    }
+}
+
+impl<TInner> ProtocolsHandler for SwitchHandler<TInner>
+where
+    TInner: IntoProtocolsHandler
+{
+    type InEvent = SwitchInEvent<TInner>;
+    type OutEvent = SwitchOutEvent<<TInner::Handler as ProtocolsHandler>::OutEvent>;
+    type Error = <TInner::Handler as ProtocolsHandler>::Error;
+    type InboundProtocol = EitherUpgrade<
+        SendWrapper<<TInner::Handler as ProtocolsHandler>::InboundProtocol>,
+        SendWrapper<DeniedUpgrade>
+    >;
+    type OutboundProtocol = <TInner::Handler as ProtocolsHandler>::OutboundProtocol;
+    type OutboundOpenInfo = (u64, <TInner::Handler as ProtocolsHandler>::OutboundOpenInfo);
+    type InboundOpenInfo = Either<(u64, <TInner::Handler as ProtocolsHandler>::InboundOpenInfo), ()>;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        match self.inner.as_ref() {
+            Some(inner) if !self.disabling => {
+                let id = self.inner_id;
+                inner.listen_protocol()
+                    .map_upgrade(|u| EitherUpgrade::A(SendWrapper(u)))
+                    .map_info(|i| Either::Left((id, i)))
+            }
+            _ => SubstreamProtocol::new(EitherUpgrade::B(SendWrapper(DeniedUpgrade)), Either::Right(()))
+        }
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        out: <Self::InboundProtocol as InboundUpgradeSend>::Output,
+        info: Self::InboundOpenInfo
+    ) {
+        let out = match out {
+            EitherOutput::First(out) => out,
+            EitherOutput::Second(v) => void::unreachable(v),
+        };
+        match info {
+            Either::Left((id, info)) => match self.inner_mut(id) {
+                Some(inner) => inner.inject_fully_negotiated_inbound(out, info),
+                None => tracing::debug!("Dropping inbound substream of a disabled handler."),
+            },
+            Either::Right(()) => panic!("Unexpected `Either::Right` in `inject_fully_negotiated_inbound`."),
+        }
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        out: <Self::OutboundProtocol as OutboundUpgradeSend>::Output,
+        (id, info): Self::OutboundOpenInfo
+    ) {
+        match self.inner_mut(id) {
+            Some(inner) => inner.inject_fully_negotiated_outbound(out, info),
+            None => tracing::debug!("Dropping outbound substream of a disabled handler."),
+        }
+    }
+
+    fn inject_event(&mut self, event: Self::InEvent) {
+        match event {
+            SwitchInEvent::Inner(event) => match self.inner.as_mut() {
+                Some(inner) => inner.inject_event(event),
+                None => tracing::debug!("Dropping event for a disabled handler."),
+            },
+            SwitchInEvent::Enable(proto) => {
+                if self.inner.is_none() {
+                    self.inner = Some(proto.into_handler(&self.remote_peer_id, &self.connected_point));
+                    self.inner_id += 1;
+                }
+                self.disabling = false;
+            }
+            SwitchInEvent::Disable => self.disabling = self.inner.is_some(),
+        }
+    }
+
+    fn inject_address_change(&mut self, addr: &Multiaddr) {
+        self.connected_point.set_remote_address(addr.clone());
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_address_change(addr)
+        }
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        (id, info): Self::OutboundOpenInfo,
+        err: ProtocolsHandlerUpgrErr<<Self::OutboundProtocol as OutboundUpgradeSend>::Error>
+    ) {
+        if let Some(inner) = self.inner_mut(id) {
+            inner.inject_dial_upgrade_error(info, err)
+        }
+    }
+
+    fn inject_listen_upgrade_error(
+        &mut self,
+        info: Self::InboundOpenInfo,
+        err: ProtocolsHandlerUpgrErr<<Self::InboundProtocol as InboundUpgradeSend>::Error>
+    ) {
+        let (inner, info) = match info {
+            Either::Left((id, info)) => match self.inner_mut(id) {
+                Some(inner) => (inner, info),
+                None => return,
+            },
+            // Ignore listen upgrade errors of denied substreams.
+            Either::Right(()) => return,
+        };
+
+        let err = match err {
+            ProtocolsHandlerUpgrErr::Timeout => ProtocolsHandlerUpgrErr::Timeout,
+            ProtocolsHandlerUpgrErr::Timer => ProtocolsHandlerUpgrErr::Timer,
+            ProtocolsHandlerUpgrErr::Upgrade(err) =>
+                ProtocolsHandlerUpgrErr::Upgrade(err.map_err(|err| match err {
+                    EitherError::A(e) => e,
+                    EitherError::B(v) => void::unreachable(v)
+                }))
+        };
+
+        inner.inject_listen_upgrade_error(info, err)
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        match self.inner.as_ref() {
+            Some(_) if self.disabling => KeepAlive::Yes,
+            Some(inner) => inner.connection_keep_alive(),
+            None => KeepAlive::No,
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if let Some(generation) = self.created.take() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(SwitchOutEvent::Created { generation }))
+        }
+
+        let id = self.inner_id;
+        if let Some(inner) = self.inner.as_mut() {
+            if let Poll::Ready(event) = inner.poll(cx) {
+                return Poll::Ready(event
+                    .map_outbound_open_info(|i| (id, i))
+                    .map_custom(SwitchOutEvent::Inner))
+            }
+            if self.disabling && inner.poll_close(cx).is_ready() {
+                // Dropping the inner handler closes its substreams.
+                self.inner = None;
+                self.disabling = false;
+            }
+        }
+
+        Poll::Pending
+    }
+
+    fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.poll_close(cx)
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols_handler::DummyProtocolsHandler;
+    use futures::task::noop_waker;
+
+    #[test]
+    fn disable_and_enable_handler() {
+        let proto = IntoSwitchHandler { inner: Some(DummyProtocolsHandler::default()), generation: 3 };
+        let endpoint = ConnectedPoint::Dialer { address: Multiaddr::empty() };
+        let mut handler = proto.into_handler(&PeerId::random(), &endpoint);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        match handler.poll(&mut cx) {
+            Poll::Ready(ProtocolsHandlerEvent::Custom(SwitchOutEvent::Created { generation })) =>
+                assert_eq!(generation, 3),
+            _ => panic!("Expected the generation to be reported first.")
+        }
+        assert!(matches!(handler.listen_protocol().info(), Either::Left((0, ()))));
+
+        // The dummy handler has no pending work and is dropped right away.
+        handler.inject_event(SwitchInEvent::Disable);
+        assert!(matches!(handler.listen_protocol().info(), Either::Right(())));
+        assert!(handler.poll(&mut cx).is_pending());
+        assert!(handler.inner.is_none());
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::No);
+
+        handler.inject_event(SwitchInEvent::Enable(DummyProtocolsHandler::default()));
+        assert!(matches!(handler.listen_protocol().info(), Either::Left((1, ()))));
+    }
+}
@@ -25,6 +25,7 @@ use crate::{
     IntoProtocolsHandler,
     PollParameters
 };
+use crate::diagnostics::BehaviourSummary;
 use libp2p_core::{
     ConnectedPoint,
     PeerId,
@@ -50,6 +51,8 @@ where
     ///
     /// An action is only returned once.
     pub next_action: Option<NetworkBehaviourAction<THandler::InEvent, TOutEvent>>,
+    /// The summaries to return from `diagnostics`.
+    pub summaries: Vec<BehaviourSummary>,
 }
 
 impl<THandler, TOutEvent> MockBehaviour<THandler, TOutEvent>
@@ -61,6 +64,7 @@ where
             handler_proto,
             addresses: HashMap::new(),
             next_action: None,
+            summaries: Vec::new(),
         }
     }
 }
@@ -91,6 +95,10 @@ where
     fn inject_event(&mut self, _: PeerId, _: ConnectionId, _: THandler::OutEvent) {
     }
 
+    fn diagnostics(&self) -> Vec<BehaviourSummary> {
+        self.summaries.clone()
+    }
+
     fn poll(&mut self, _: &mut Context, _: &mut impl PollParameters) ->
         Poll<NetworkBehaviourAction<THandler::InEvent, Self::OutEvent>>
     {
@@ -117,6 +125,7 @@ where
     pub inject_dial_failure: Vec<PeerId>,
     pub inject_new_listen_addr: Vec<Multiaddr>,
     pub inject_new_external_addr: Vec<Multiaddr>,
+    pub inject_expired_external_addr: Vec<Multiaddr>,
     pub inject_expired_listen_addr: Vec<Multiaddr>,
     pub inject_listener_error: Vec<ListenerId>,
     pub inject_listener_closed: Vec<(ListenerId, bool)>,
@@ -140,6 +149,7 @@ where
             inject_dial_failure: Vec::new(),
             inject_new_listen_addr: Vec::new(),
             inject_new_external_addr: Vec::new(),
+            inject_expired_external_addr: Vec::new(),
             inject_expired_listen_addr: Vec::new(),
             inject_listener_error: Vec::new(),
             inject_listener_closed: Vec::new(),
@@ -158,11 +168,16 @@ where
         self.inject_dial_failure = Vec::new();
         self.inject_new_listen_addr = Vec::new();
         self.inject_new_external_addr = Vec::new();
+        self.inject_expired_external_addr = Vec::new();
         self.inject_expired_listen_addr = Vec::new();
         self.inject_listener_error = Vec::new();
         self.inject_listener_closed = Vec::new();
         self.poll = 0;
     }
+
+    pub fn inner(&mut self) -> &mut TInner {
+        &mut self.inner
+    }
 }
 
 impl<TInner> NetworkBehaviour for CallTraceBehaviour<TInner>
@@ -232,6 +247,11 @@ where
         self.inner.inject_new_external_addr(a);
     }
 
+    fn inject_expired_external_addr(&mut self, a: &Multiaddr) {
+        self.inject_expired_external_addr.push(a.clone());
+        self.inner.inject_expired_external_addr(a);
+    }
+
     fn inject_listener_error(&mut self, l: ListenerId, e: &(dyn std::error::Error + 'static)) {
         self.inject_listener_error.push(l.clone());
         self.inner.inject_listener_error(l, e);
@@ -242,6 +262,10 @@ where
         self.inner.inject_listener_closed(l, r);
     }
 
+    fn diagnostics(&self) -> Vec<BehaviourSummary> {
+        self.inner.diagnostics()
+    }
+
     fn poll(&mut self, cx: &mut Context, args: &mut impl PollParameters) ->
         Poll<NetworkBehaviourAction<
             <<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent,
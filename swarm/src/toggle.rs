@@ -19,6 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::{NetworkBehaviour, NetworkBehaviourAction, NetworkBehaviourEventProcess, PollParameters};
+use crate::diagnostics::BehaviourSummary;
 use crate::upgrade::{SendWrapper, InboundUpgradeSend, OutboundUpgradeSend};
 use crate::protocols_handler::{
     KeepAlive,
@@ -151,6 +152,16 @@ where
         }
     }
 
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.inject_expired_external_addr(addr)
+        }
+    }
+
+    fn diagnostics(&self) -> Vec<BehaviourSummary> {
+        self.inner.as_ref().map(|b| b.diagnostics()).unwrap_or_else(Vec::new)
+    }
+
     fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
         -> Poll<NetworkBehaviourAction<<<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent, Self::OutEvent>>
     {
@@ -316,6 +327,14 @@ where
             Poll::Pending
         }
     }
+
+    fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.poll_close(cx)
+        } else {
+            Poll::Ready(())
+        }
+    }
 }
 
 #[cfg(test)]
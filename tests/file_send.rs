@@ -0,0 +1,68 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Runs the transfer protocol of the `file-send` example over the
+//! development transport.
+
+#[path = "../examples/file-send/transfer.rs"]
+mod transfer;
+
+use async_std::task;
+use futures::prelude::*;
+use libp2p::{PeerId, Swarm, identity, request_response::RequestResponse, swarm::SwarmEvent};
+use transfer::{CHUNK_SIZE, Chunk, FileCodec, Outgoing, file_protocol, receive, send};
+
+fn mk_swarm() -> Swarm<RequestResponse<FileCodec>> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = PeerId::from_public_key(local_key.public());
+    let transport = libp2p::build_development_transport(local_key).unwrap();
+    Swarm::new(transport, file_protocol(), local_peer_id)
+}
+
+#[test]
+fn chunk_roundtrip() {
+    let chunk = Chunk { name: "a.txt".into(), offset: 42, last: true, data: vec![1, 2, 3] };
+    assert_eq!(Chunk::decode(&chunk.encode()).unwrap(), chunk);
+
+    let chunk = Chunk { name: "../a.txt".into(), .. chunk };
+    assert!(Chunk::decode(&chunk.encode()).is_err());
+}
+
+#[test]
+fn file_is_transferred() {
+    let dir = std::env::temp_dir().join(format!("file-send-{}", std::process::id()));
+    std::fs::create_dir(&dir).unwrap();
+    let data = (0 .. 3 * CHUNK_SIZE + 17).map(|i| i as u8).collect::<Vec<_>>();
+
+    let mut receiver = mk_swarm();
+    Swarm::listen_on(&mut receiver, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+    let addr = task::block_on(async {
+        loop {
+            if let SwarmEvent::NewListenAddr(addr) = receiver.next_event().await {
+                break addr
+            }
+        }
+    });
+    task::spawn(receive(receiver, dir.clone()).map(|_| ()));
+
+    task::block_on(send(mk_swarm(), addr, Outgoing::new("file".into(), data.clone()))).unwrap();
+    assert_eq!(std::fs::read(dir.join("file")).unwrap(), data);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,86 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Runs the chat protocol of the `lan-chat` example over the development
+//! transport. Discovery through mDNS is left out, the peers are given each
+//! other's addresses instead.
+
+#[path = "../examples/lan-chat/chat.rs"]
+mod chat;
+
+use async_std::task;
+use chat::{ChatCodec, chat_protocol};
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    Swarm,
+    identity,
+    request_response::{RequestResponse, RequestResponseEvent, RequestResponseMessage},
+    swarm::SwarmEvent,
+};
+
+fn mk_swarm() -> Swarm<RequestResponse<ChatCodec>> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = PeerId::from_public_key(local_key.public());
+    let transport = libp2p::build_development_transport(local_key).unwrap();
+    Swarm::new(transport, chat_protocol(), local_peer_id)
+}
+
+#[test]
+fn chat_message_is_delivered_and_acknowledged() {
+    let mut sender = mk_swarm();
+    let mut receiver = mk_swarm();
+    let receiver_id = *Swarm::local_peer_id(&receiver);
+
+    Swarm::listen_on(&mut receiver, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+    let addr: Multiaddr = task::block_on(async {
+        loop {
+            if let SwarmEvent::NewListenAddr(addr) = receiver.next_event().await {
+                break addr
+            }
+        }
+    });
+
+    task::spawn(async move {
+        loop {
+            if let RequestResponseEvent::Message {
+                message: RequestResponseMessage::Request { request, channel, .. }, ..
+            } = receiver.next().await {
+                assert_eq!(request, "hello");
+                receiver.send_response(channel, ()).unwrap();
+            }
+        }
+    });
+
+    sender.add_address(&receiver_id, addr);
+    sender.send_request(&receiver_id, "hello".to_string());
+    task::block_on(async {
+        loop {
+            match sender.next().await {
+                RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Response { .. }, ..
+                } => break,
+                RequestResponseEvent::OutboundFailure { error, .. } =>
+                    panic!("Sending failed: {:?}", error),
+                _ => {}
+            }
+        }
+    });
+}
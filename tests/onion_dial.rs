@@ -0,0 +1,114 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Dials a node by its onion address with the transport of the
+//! `onion-dial` example, through a stand-in for the SOCKS5 proxy of a Tor
+//! daemon.
+
+#[path = "../examples/onion-dial/socks.rs"]
+mod socks;
+
+use async_std::{net::{TcpListener, TcpStream}, task};
+use futures::{future, io, prelude::*};
+use libp2p::{
+    PeerId,
+    Swarm,
+    identity,
+    multiaddr::Protocol,
+    ping::{Ping, PingConfig, PingEvent},
+    swarm::SwarmEvent,
+};
+use std::net::SocketAddr;
+
+fn mk_swarm(keypair: &identity::Keypair, proxy: SocketAddr) -> Swarm<Ping> {
+    let peer_id = PeerId::from_public_key(keypair.public());
+    let transport = socks::build_transport(keypair, proxy);
+    Swarm::new(transport, Ping::new(PingConfig::new().with_keep_alive(true)), peer_id)
+}
+
+/// Accepts a single SOCKS5 request to connect to `host` and forwards the
+/// connection to `target`, like Tor does for a published onion service.
+async fn proxy(listener: TcpListener, host: String, target: SocketAddr) {
+    let (mut client, _) = listener.accept().await.unwrap();
+    let mut greeting = [0; 3];
+    client.read_exact(&mut greeting).await.unwrap();
+    assert_eq!(greeting, [5, 1, 0]);
+    client.write_all(&[5, 0]).await.unwrap();
+
+    let mut request = [0; 5];
+    client.read_exact(&mut request).await.unwrap();
+    assert_eq!(request[.. 4], [5, 1, 0, 3]);
+    let mut requested = vec![0; request[4] as usize + 2];
+    client.read_exact(&mut requested).await.unwrap();
+    assert_eq!(&requested[.. request[4] as usize], host.as_bytes());
+
+    let server = TcpStream::connect(target).await.unwrap();
+    client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).await.unwrap();
+
+    let (mut client_w, mut server_w) = (client.clone(), server.clone());
+    let _ = future::join(io::copy(client, &mut server_w), io::copy(server, &mut client_w)).await;
+}
+
+#[test]
+fn ping_through_onion_address() {
+    let proxy_listener = task::block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let proxy_addr = proxy_listener.local_addr().unwrap();
+
+    let ed25519_key = identity::ed25519::Keypair::generate();
+    let onion_key = ed25519_key.public();
+    let listener_key = identity::Keypair::Ed25519(ed25519_key);
+    let listener_id = PeerId::from_public_key(listener_key.public());
+    let mut listener = mk_swarm(&listener_key, proxy_addr);
+    Swarm::listen_on(&mut listener, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+    let port = task::block_on(async {
+        loop {
+            if let SwarmEvent::NewListenAddr(addr) = listener.next_event().await {
+                if let Some(Protocol::Tcp(port)) = addr.iter().last() {
+                    break port
+                }
+            }
+        }
+    });
+    task::spawn(async move {
+        loop { listener.next_event().await; }
+    });
+
+    let host = format!("{}.onion", listener_id.as_onion_address().unwrap());
+    task::spawn(proxy(proxy_listener, host, SocketAddr::from(([127, 0, 0, 1], port))));
+
+    let mut dialer = mk_swarm(&identity::Keypair::generate_ed25519(), proxy_addr);
+    let onion_addr = socks::onion_address(&onion_key, 80).with(Protocol::P2p(listener_id.into()));
+    Swarm::dial_addr(&mut dialer, onion_addr).unwrap();
+    task::block_on(async {
+        loop {
+            match dialer.next_event().await {
+                SwarmEvent::Behaviour(PingEvent { peer, result: Ok(_) }) => {
+                    assert_eq!(peer, listener_id);
+                    break
+                }
+                SwarmEvent::UnreachableAddr { address, error, .. } =>
+                    panic!("Failed to reach {}: {}", address, error),
+                SwarmEvent::UnknownPeerUnreachableAddr { address, error, .. } =>
+                    panic!("Failed to reach {}: {}", address, error),
+                _ => {}
+            }
+        }
+    });
+}
@@ -0,0 +1,86 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Pings a node through a relay, with the nodes of the `relay` example.
+
+#[path = "../examples/relay/node.rs"]
+mod node;
+
+use async_std::task;
+use libp2p::{
+    Swarm,
+    identity,
+    multiaddr::Protocol,
+    ping::PingEvent,
+    relay::ClientEvent,
+    swarm::SwarmEvent,
+};
+use node::Event;
+
+#[test]
+fn ping_through_relay() {
+    let mut relay = node::relay_server(identity::Keypair::generate_ed25519());
+    let relay_id = *Swarm::local_peer_id(&relay);
+    Swarm::listen_on(&mut relay, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+    let relay_addr = task::block_on(async {
+        loop {
+            if let SwarmEvent::NewListenAddr(addr) = relay.next_event().await {
+                break addr
+            }
+        }
+    });
+    task::spawn(async move {
+        loop { relay.next_event().await; }
+    });
+
+    let relayed_addr = relay_addr
+        .with(Protocol::P2p(relay_id.into()))
+        .with(Protocol::P2pCircuit);
+
+    let mut dst = node::relay_client(identity::Keypair::generate_ed25519());
+    let dst_id = *Swarm::local_peer_id(&dst);
+    Swarm::listen_on(&mut dst, relayed_addr.clone()).unwrap();
+    task::block_on(async {
+        loop {
+            match dst.next_event().await {
+                SwarmEvent::NewListenAddr(addr) if addr == relayed_addr => break,
+                SwarmEvent::Behaviour(Event::Client(ClientEvent::ReservationReqFailed { .. })) =>
+                    panic!("Reservation refused"),
+                _ => {}
+            }
+        }
+    });
+    task::spawn(async move {
+        loop { dst.next_event().await; }
+    });
+
+    let mut src = node::relay_client(identity::Keypair::generate_ed25519());
+    Swarm::dial_addr(&mut src, relayed_addr.with(Protocol::P2p(dst_id.into()))).unwrap();
+    task::block_on(async {
+        loop {
+            match src.next_event().await {
+                SwarmEvent::Behaviour(Event::Ping(PingEvent { peer, result: Ok(_) })) if peer == dst_id => break,
+                SwarmEvent::Behaviour(Event::Client(ClientEvent::OutboundCircuitReqFailed { .. })) =>
+                    panic!("Circuit refused"),
+                _ => {}
+            }
+        }
+    });
+}
@@ -0,0 +1,158 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Reaches a peer by its ID through a relay and checks that the relayed
+//! addresses it announces survive identify.
+
+use async_std::task;
+use libp2p::{
+    Multiaddr,
+    NetworkBehaviour,
+    PeerId,
+    Swarm,
+    Transport,
+    core::{muxing::StreamMuxerBox, transport, upgrade},
+    identify::{Identify, IdentifyEvent},
+    identity,
+    multiaddr::Protocol,
+    noise,
+    relay::{Client, ClientEvent, Relay, RelayConfig},
+    swarm::{DialOpts, SwarmEvent},
+    tcp::TcpConfig,
+    yamux,
+};
+
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "Event", event_process = false)]
+struct Node {
+    relay_client: Client,
+    identify: Identify,
+}
+
+#[derive(Debug)]
+enum Event {
+    Client(ClientEvent),
+    Identify(IdentifyEvent),
+}
+
+impl From<ClientEvent> for Event {
+    fn from(event: ClientEvent) -> Self {
+        Event::Client(event)
+    }
+}
+
+impl From<IdentifyEvent> for Event {
+    fn from(event: IdentifyEvent) -> Self {
+        Event::Identify(event)
+    }
+}
+
+fn upgrade_transport<T>(transport: T, keypair: &identity::Keypair)
+    -> transport::Boxed<(PeerId, StreamMuxerBox)>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: futures::AsyncRead + futures::AsyncWrite + Send + Unpin + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+{
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(keypair).unwrap();
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(yamux::YamuxConfig::default())
+        .boxed()
+}
+
+fn build_relay() -> Swarm<Relay> {
+    let keypair = identity::Keypair::generate_ed25519();
+    let peer_id = PeerId::from_public_key(keypair.public());
+    let transport = upgrade_transport(TcpConfig::new(), &keypair);
+    Swarm::new(transport, Relay::new(RelayConfig::default()), peer_id)
+}
+
+fn build_node() -> Swarm<Node> {
+    let keypair = identity::Keypair::generate_ed25519();
+    let peer_id = PeerId::from_public_key(keypair.public());
+    let (relay_transport, relay_client) = Client::new_transport_and_behaviour();
+    let transport = upgrade_transport(relay_transport.or_transport(TcpConfig::new()), &keypair);
+    let identify = Identify::new("/test/1.0.0".into(), "test".into(), keypair.public());
+    Swarm::new(transport, Node { relay_client, identify }, peer_id)
+}
+
+#[test]
+fn dial_by_peer_id_through_relay_and_identify() {
+    let mut relay = build_relay();
+    let relay_id = *Swarm::local_peer_id(&relay);
+    Swarm::listen_on(&mut relay, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+    let relay_addr = task::block_on(async {
+        loop {
+            if let SwarmEvent::NewListenAddr(addr) = relay.next_event().await {
+                break addr
+            }
+        }
+    });
+    task::spawn(async move {
+        loop { relay.next_event().await; }
+    });
+
+    let relayed_addr: Multiaddr = relay_addr
+        .with(Protocol::P2p(relay_id.into()))
+        .with(Protocol::P2pCircuit);
+
+    let mut dst = build_node();
+    let dst_id = *Swarm::local_peer_id(&dst);
+    Swarm::listen_on(&mut dst, relayed_addr.clone()).unwrap();
+    task::block_on(async {
+        loop {
+            match dst.next_event().await {
+                SwarmEvent::NewListenAddr(addr) if addr == relayed_addr => break,
+                SwarmEvent::Behaviour(Event::Client(ClientEvent::ReservationReqFailed { .. })) =>
+                    panic!("Reservation refused"),
+                _ => {}
+            }
+        }
+    });
+    task::spawn(async move {
+        loop { dst.next_event().await; }
+    });
+
+    // The `/p2p` component of the relay must not get the address discarded.
+    let mut src = build_node();
+    let opts = DialOpts::peer_id(dst_id)
+        .addresses(vec![relayed_addr.clone().with(Protocol::P2p(dst_id.into()))]);
+    Swarm::dial_with_opts(&mut src, opts).unwrap();
+    task::block_on(async {
+        loop {
+            match src.next_event().await {
+                SwarmEvent::Behaviour(Event::Identify(IdentifyEvent::Received { peer_id, info, .. }))
+                    if peer_id == dst_id =>
+                {
+                    assert!(info.listen_addrs.contains(&relayed_addr));
+                    break
+                }
+                SwarmEvent::Behaviour(Event::Client(ClientEvent::OutboundCircuitReqFailed { .. })) =>
+                    panic!("Circuit refused"),
+                _ => {}
+            }
+        }
+    });
+}
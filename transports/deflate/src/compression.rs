@@ -0,0 +1,675 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! An upgrade negotiating one of several compression algorithms.
+
+use futures::{prelude::*, ready};
+use libp2p_core::{InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo};
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    mem,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::Context,
+    task::Poll,
+};
+
+/// The size of the buffer for reading compressed bytes from the inner stream.
+const READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// A compression algorithm supported by the [`CompressionConfig`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Algorithm {
+    /// DEFLATE, negotiated as `/deflate/1.0.0` and compatible with the
+    /// [`DeflateConfig`](crate::DeflateConfig).
+    Deflate,
+    /// Zstandard, negotiated as `/zstd/1.0.0`, or as `/zstd/1.0.0/dict/<name>`
+    /// with a dictionary configured through
+    /// [`CompressionConfig::with_zstd_dictionary`].
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// Brotli, negotiated as `/brotli/1.0.0`.
+    #[cfg(feature = "brotli")]
+    Brotli,
+}
+
+/// The compression level, mapped to the range of levels of each algorithm.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    /// The fastest compression of the algorithm.
+    Fastest,
+    /// The default compression of the algorithm.
+    Default,
+    /// The best compression of the algorithm, apart from levels requiring
+    /// large amounts of memory.
+    Best,
+    /// The given level of the algorithm, capped at its maximum.
+    Precise(u32),
+}
+
+impl Level {
+    fn deflate(self) -> flate2::Compression {
+        match self {
+            Level::Fastest => flate2::Compression::fast(),
+            Level::Default => flate2::Compression::default(),
+            Level::Best => flate2::Compression::best(),
+            Level::Precise(level) => flate2::Compression::new(level.min(9)),
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    fn zstd(self) -> i32 {
+        match self {
+            Level::Fastest => 1,
+            Level::Default => zstd::DEFAULT_COMPRESSION_LEVEL,
+            Level::Best => 19,
+            Level::Precise(level) => level.max(1).min(22) as i32,
+        }
+    }
+
+    #[cfg(feature = "brotli")]
+    fn brotli(self) -> u32 {
+        match self {
+            Level::Fastest => 0,
+            Level::Default => 6,
+            Level::Best => 11,
+            Level::Precise(level) => level.min(11),
+        }
+    }
+}
+
+/// A Zstandard dictionary, identified by a name.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone)]
+struct Dictionary {
+    name: String,
+    bytes: Arc<Vec<u8>>,
+}
+
+/// Upgrade negotiating a compression algorithm, in the order of preference
+/// of the dialer.
+///
+/// Data is compressed with the [`Level`] configured for the direction of
+/// the upgrade, i.e. [`CompressionConfig::with_inbound_level`] on upgrades
+/// accepted from the remote and [`CompressionConfig::with_outbound_level`]
+/// on upgrades initiated locally.
+///
+/// ```
+/// use libp2p_deflate::{Algorithm, CompressionConfig, Level};
+///
+/// let config = CompressionConfig::new(vec![Algorithm::Deflate])
+///     .with_inbound_level(Level::Best)
+///     .with_outbound_level(Level::Fastest);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    algorithms: Vec<Algorithm>,
+    inbound_level: Level,
+    outbound_level: Level,
+    #[cfg(feature = "zstd")]
+    zstd_dictionary: Option<Dictionary>,
+}
+
+impl CompressionConfig {
+    /// Creates a new `CompressionConfig` supporting the given algorithms,
+    /// in order of preference, with the default level in both directions.
+    pub fn new(algorithms: impl IntoIterator<Item = Algorithm>) -> Self {
+        CompressionConfig {
+            algorithms: algorithms.into_iter().collect(),
+            inbound_level: Level::Default,
+            outbound_level: Level::Default,
+            #[cfg(feature = "zstd")]
+            zstd_dictionary: None,
+        }
+    }
+
+    /// Sets the level of compression on upgrades accepted from the remote.
+    pub fn with_inbound_level(mut self, level: Level) -> Self {
+        self.inbound_level = level;
+        self
+    }
+
+    /// Sets the level of compression on upgrades initiated locally.
+    pub fn with_outbound_level(mut self, level: Level) -> Self {
+        self.outbound_level = level;
+        self
+    }
+
+    /// Sets the dictionary for Zstandard, e.g. trained on typical payloads
+    /// with `zstd --train`.
+    ///
+    /// Both peers must use the same dictionary under the same name, which is
+    /// part of the protocol name. Zstandard without the dictionary remains
+    /// supported as a fallback with lower preference.
+    #[cfg(feature = "zstd")]
+    pub fn with_zstd_dictionary(mut self, name: impl Into<String>, dictionary: Vec<u8>) -> Self {
+        self.zstd_dictionary = Some(Dictionary { name: name.into(), bytes: Arc::new(dictionary) });
+        self
+    }
+
+    fn output<C>(self, socket: C, info: CompressionProtocol, level: Level) -> io::Result<CompressionOutput<C>> {
+        let write_out = SharedBuffer::default();
+        let (encoder, decoder): (Box<dyn Encode>, Box<dyn Decode>) = match info.algorithm {
+            Algorithm::Deflate => (
+                Box::new(flate2::write::DeflateEncoder::new(write_out.clone(), level.deflate())),
+                Box::new(DeflateDecoder { decompress: flate2::Decompress::new(false), finished: false }),
+            ),
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => match self.zstd_dictionary.as_ref().filter(|_| info.dictionary) {
+                Some(dictionary) => (
+                    Box::new(zstd::stream::write::Encoder::with_dictionary(write_out.clone(), level.zstd(), &dictionary.bytes)?),
+                    Box::new(ZstdDecoder { decoder: zstd::stream::raw::Decoder::with_dictionary(&dictionary.bytes)?, finished: false }),
+                ),
+                None => (
+                    Box::new(zstd::stream::write::Encoder::new(write_out.clone(), level.zstd())?),
+                    Box::new(ZstdDecoder { decoder: zstd::stream::raw::Decoder::new()?, finished: false }),
+                ),
+            },
+            #[cfg(feature = "brotli")]
+            Algorithm::Brotli => (
+                Box::new(brotli::CompressorWriter::new(write_out.clone(), 4096, level.brotli(), 22)),
+                Box::new(BrotliDecoder {
+                    state: brotli::BrotliState::new(Default::default(), Default::default(), Default::default()),
+                    finished: false,
+                }),
+            ),
+        };
+        Ok(CompressionOutput {
+            inner: socket,
+            encoder: Some(encoder),
+            decoder,
+            write_out,
+            read_buffer: Vec::new(),
+            read_pos: 0,
+            inner_read_eof: false,
+            needs_flush: false,
+        })
+    }
+}
+
+/// The protocol name of an [`Algorithm`] of a [`CompressionConfig`].
+#[derive(Debug, Clone)]
+pub struct CompressionProtocol {
+    algorithm: Algorithm,
+    /// Whether the Zstandard dictionary is used.
+    #[cfg(feature = "zstd")]
+    dictionary: bool,
+    name: Cow<'static, [u8]>,
+}
+
+impl CompressionProtocol {
+    /// The negotiated algorithm.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+}
+
+impl ProtocolName for CompressionProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        &self.name
+    }
+}
+
+impl UpgradeInfo for CompressionConfig {
+    type Info = CompressionProtocol;
+    type InfoIter = std::vec::IntoIter<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        let mut protocols = Vec::new();
+        for algorithm in &self.algorithms {
+            let name: &'static [u8] = match algorithm {
+                Algorithm::Deflate => b"/deflate/1.0.0",
+                #[cfg(feature = "zstd")]
+                Algorithm::Zstd => {
+                    if let Some(dictionary) = &self.zstd_dictionary {
+                        protocols.push(CompressionProtocol {
+                            algorithm: *algorithm,
+                            dictionary: true,
+                            name: Cow::Owned(format!("/zstd/1.0.0/dict/{}", dictionary.name).into_bytes()),
+                        });
+                    }
+                    b"/zstd/1.0.0"
+                }
+                #[cfg(feature = "brotli")]
+                Algorithm::Brotli => b"/brotli/1.0.0",
+            };
+            protocols.push(CompressionProtocol {
+                algorithm: *algorithm,
+                #[cfg(feature = "zstd")]
+                dictionary: false,
+                name: Cow::Borrowed(name),
+            });
+        }
+        protocols.into_iter()
+    }
+}
+
+impl<C> InboundUpgrade<C> for CompressionConfig
+where
+    C: AsyncRead + AsyncWrite,
+{
+    type Output = CompressionOutput<C>;
+    type Error = io::Error;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        let level = self.inbound_level;
+        future::ready(self.output(socket, info, level))
+    }
+}
+
+impl<C> OutboundUpgrade<C> for CompressionConfig
+where
+    C: AsyncRead + AsyncWrite,
+{
+    type Output = CompressionOutput<C>;
+    type Error = io::Error;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        let level = self.outbound_level;
+        future::ready(self.output(socket, info, level))
+    }
+}
+
+/// A streaming compressor, finishing the compressed stream when closed.
+trait Encode: Write + Send {
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl Encode for flate2::write::DeflateEncoder<SharedBuffer> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        (*self).finish().map(drop)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Encode for zstd::stream::write::Encoder<'static, SharedBuffer> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        (*self).finish().map(drop)
+    }
+}
+
+#[cfg(feature = "brotli")]
+impl Encode for brotli::CompressorWriter<SharedBuffer> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        // Consuming the writer finishes the compressed stream.
+        drop(self.into_inner());
+        Ok(())
+    }
+}
+
+/// A streaming decompressor, writing at most as many bytes as fit into the
+/// output buffer it is given, so that the memory used for reading does not
+/// depend on the compression ratio chosen by the remote.
+trait Decode: Send {
+    /// Decompresses from `input` into `output`, returning the number of bytes
+    /// consumed from `input` and the number of bytes written to `output`.
+    fn decode(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<(usize, usize)>;
+
+    /// Whether the end of the compressed stream has been decompressed.
+    fn is_finished(&self) -> bool;
+}
+
+struct DeflateDecoder {
+    decompress: flate2::Decompress,
+    finished: bool,
+}
+
+impl Decode for DeflateDecoder {
+    fn decode(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<(usize, usize)> {
+        let before_in = self.decompress.total_in();
+        let before_out = self.decompress.total_out();
+        let status = self.decompress.decompress(input, output, flate2::FlushDecompress::None)?;
+        self.finished |= status == flate2::Status::StreamEnd;
+        let consumed = (self.decompress.total_in() - before_in) as usize;
+        let written = (self.decompress.total_out() - before_out) as usize;
+        Ok((consumed, written))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(feature = "zstd")]
+struct ZstdDecoder {
+    decoder: zstd::stream::raw::Decoder<'static>,
+    finished: bool,
+}
+
+#[cfg(feature = "zstd")]
+impl Decode for ZstdDecoder {
+    fn decode(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<(usize, usize)> {
+        use zstd::stream::raw::{InBuffer, Operation, OutBuffer};
+
+        let mut input = InBuffer::around(input);
+        let mut output = OutBuffer::around(output);
+        // A hint of `0` means that a frame has been decoded and flushed entirely.
+        let hint = self.decoder.run(&mut input, &mut output)?;
+        self.finished = hint == 0;
+        Ok((input.pos, output.pos()))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(feature = "brotli")]
+struct BrotliDecoder {
+    state: brotli::BrotliState<brotli::enc::StandardAlloc, brotli::enc::StandardAlloc, brotli::enc::StandardAlloc>,
+    finished: bool,
+}
+
+#[cfg(feature = "brotli")]
+impl Decode for BrotliDecoder {
+    fn decode(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<(usize, usize)> {
+        let (mut available_in, mut input_offset) = (input.len(), 0);
+        let (mut available_out, mut output_offset, mut total_out) = (output.len(), 0, 0);
+        let result = brotli::BrotliDecompressStream(
+            &mut available_in, &mut input_offset, input,
+            &mut available_out, &mut output_offset, output,
+            &mut total_out, &mut self.state,
+        );
+        match result {
+            brotli::BrotliResult::ResultSuccess => self.finished = true,
+            brotli::BrotliResult::ResultFailure =>
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid brotli stream")),
+            brotli::BrotliResult::NeedsMoreInput | brotli::BrotliResult::NeedsMoreOutput => {}
+        }
+        Ok((input_offset, output_offset))
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+/// A buffer shared between a [`CompressionOutput`] and its compressor, which
+/// writes to it.
+#[derive(Debug, Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<u8>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decodes and encodes traffic using the negotiated [`Algorithm`].
+pub struct CompressionOutput<S> {
+    /// Inner stream where we read compressed data from and write compressed data to.
+    inner: S,
+    /// Compresses the written data into `write_out`. `None` once closed.
+    encoder: Option<Box<dyn Encode>>,
+    /// Decompresses the data read from `inner` into the buffers given to `poll_read`.
+    decoder: Box<dyn Decode>,
+    /// Compressed bytes that need to be sent out once `inner` is ready to
+    /// accept more.
+    write_out: SharedBuffer,
+    /// Compressed bytes read from `inner`, of which those before `read_pos` have been
+    /// consumed by `decoder`.
+    read_buffer: Vec<u8>,
+    read_pos: usize,
+    /// When we read from `inner` and `Ok(0)` is returned, we set this to `true` so that we don't
+    /// read from it again.
+    inner_read_eof: bool,
+    /// Whether data has been written to `encoder` since it was last flushed.
+    needs_flush: bool,
+}
+
+impl<S> CompressionOutput<S> {
+    /// Tries to write the content of `self.write_out` to `self.inner`.
+    /// Returns `Ready(Ok(()))` if `self.write_out` is empty.
+    fn flush_write_out(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>>
+        where S: AsyncWrite + Unpin
+    {
+        let mut write_out = self.write_out.lock();
+        loop {
+            if write_out.is_empty() {
+                return Poll::Ready(Ok(()))
+            }
+
+            match ready!(AsyncWrite::poll_write(Pin::new(&mut self.inner), cx, &write_out))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                n => { write_out.drain(.. n); }
+            }
+        }
+    }
+}
+
+impl<S> AsyncRead for CompressionOutput<S>
+    where S: AsyncRead + Unpin
+{
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize, io::Error>> {
+        // We use a `this` variable because the compiler doesn't allow multiple mutable borrows
+        // across a `Deref`.
+        let this = &mut *self;
+
+        // We special-case this, otherwise an empty buffer would make the loop below infinite.
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        loop {
+            let (consumed, read) = this.decoder.decode(&this.read_buffer[this.read_pos ..], buf)?;
+            this.read_pos += consumed;
+            if read != 0 || this.decoder.is_finished() {
+                return Poll::Ready(Ok(read))
+            }
+            if consumed != 0 {
+                continue
+            }
+            if this.inner_read_eof {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated compressed stream")))
+            }
+
+            // The decompressor needs more input. We keep the bytes it has not consumed yet and
+            // read more from `self.inner` behind them.
+            this.read_buffer.drain(.. this.read_pos);
+            this.read_pos = 0;
+            let len = this.read_buffer.len();
+            this.read_buffer.resize(len + READ_BUFFER_SIZE, 0);
+            match AsyncRead::poll_read(Pin::new(&mut this.inner), cx, &mut this.read_buffer[len ..]) {
+                Poll::Ready(Ok(n)) => {
+                    this.read_buffer.truncate(len + n);
+                    this.inner_read_eof = n == 0;
+                }
+                Poll::Ready(Err(err)) => {
+                    this.read_buffer.truncate(len);
+                    return Poll::Ready(Err(err))
+                }
+                Poll::Pending => {
+                    this.read_buffer.truncate(len);
+                    return Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for CompressionOutput<S>
+    where S: AsyncWrite + Unpin
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8])
+        -> Poll<Result<usize, io::Error>>
+    {
+        // We use a `this` variable because the compiler doesn't allow multiple mutable borrows
+        // across a `Deref`.
+        let this = &mut *self;
+
+        // We don't want to accumulate too much data in `self.write_out`, so we only proceed if it
+        // is empty.
+        ready!(this.flush_write_out(cx))?;
+
+        let encoder = this.encoder.as_mut().ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        let written = encoder.write(buf)?;
+        this.needs_flush |= written != 0;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        // We use a `this` variable because the compiler doesn't allow multiple mutable borrows
+        // across a `Deref`.
+        let this = &mut *self;
+
+        if mem::take(&mut this.needs_flush) {
+            if let Some(encoder) = this.encoder.as_mut() {
+                encoder.flush()?;
+            }
+        }
+        ready!(this.flush_write_out(cx))?;
+        AsyncWrite::poll_flush(Pin::new(&mut this.inner), cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        // We use a `this` variable because the compiler doesn't allow multiple mutable borrows
+        // across a `Deref`.
+        let this = &mut *self;
+
+        if let Some(encoder) = this.encoder.take() {
+            encoder.finish()?;
+        }
+        ready!(this.flush_write_out(cx))?;
+        AsyncWrite::poll_close(Pin::new(&mut this.inner), cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, io::Cursor};
+
+    fn compress(config: CompressionConfig, protocol: CompressionProtocol, message: &[u8]) -> Vec<u8> {
+        let mut compressed = config
+            .output(Cursor::new(Vec::new()), protocol, Level::Default)
+            .unwrap();
+        block_on(async {
+            for chunk in message.chunks(1000) {
+                compressed.write_all(chunk).await.unwrap();
+                compressed.flush().await.unwrap();
+            }
+            compressed.close().await.unwrap();
+        });
+        compressed.inner.into_inner()
+    }
+
+    fn roundtrip(config: CompressionConfig, protocol: CompressionProtocol, message: &[u8]) {
+        let compressed = compress(config.clone(), protocol.clone(), message);
+        assert!(compressed.len() < message.len());
+
+        let mut decompressed = config.output(Cursor::new(compressed), protocol, Level::Default).unwrap();
+        let mut buf = Vec::new();
+        block_on(decompressed.read_to_end(&mut buf)).unwrap();
+        assert_eq!(buf, message);
+    }
+
+    fn message() -> Vec<u8> {
+        (0 .. 1000u32).flat_map(|i| format!("block {} header {}\n", i, i % 7).into_bytes()).collect()
+    }
+
+    #[test]
+    fn protocols_in_order_of_preference() {
+        let config = CompressionConfig::new(vec![Algorithm::Deflate]);
+        let names = config.protocol_info().map(|p| p.protocol_name().to_vec()).collect::<Vec<_>>();
+        assert_eq!(names, vec![b"/deflate/1.0.0".to_vec()]);
+    }
+
+    #[test]
+    fn deflate_roundtrip() {
+        let config = CompressionConfig::new(vec![Algorithm::Deflate]);
+        let protocol = config.protocol_info().next().unwrap();
+        roundtrip(config, protocol, &message());
+    }
+
+    #[test]
+    fn reads_are_bounded_by_the_buffer() {
+        let config = CompressionConfig::new(vec![Algorithm::Deflate]);
+        let protocol = config.protocol_info().next().unwrap();
+        let message = vec![0; 1024 * 1024];
+        let compressed = compress(config.clone(), protocol.clone(), &message);
+
+        let mut decompressed = config.output(Cursor::new(compressed), protocol, Level::Default).unwrap();
+        let mut buf = [0; 64];
+        let mut total = 0;
+        loop {
+            let n = block_on(decompressed.read(&mut buf)).unwrap();
+            if n == 0 {
+                break
+            }
+            assert!(decompressed.read_buffer.len() <= 2 * READ_BUFFER_SIZE);
+            total += n;
+        }
+        assert_eq!(total, message.len());
+    }
+
+    #[test]
+    fn truncated_stream_is_an_error() {
+        let config = CompressionConfig::new(vec![Algorithm::Deflate]);
+        let protocol = config.protocol_info().next().unwrap();
+        let mut compressed = compress(config.clone(), protocol.clone(), &message());
+        compressed.truncate(compressed.len() / 2);
+
+        let mut decompressed = config.output(Cursor::new(compressed), protocol, Level::Default).unwrap();
+        let mut buf = Vec::new();
+        let err = block_on(decompressed.read_to_end(&mut buf)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_roundtrip_with_dictionary() {
+        let config = CompressionConfig::new(vec![Algorithm::Zstd, Algorithm::Deflate])
+            .with_zstd_dictionary("headers", b"block header ".repeat(16));
+        let protocols = config.protocol_info().collect::<Vec<_>>();
+        let names = protocols.iter().map(|p| p.protocol_name().to_vec()).collect::<Vec<_>>();
+        assert_eq!(names, vec![
+            b"/zstd/1.0.0/dict/headers".to_vec(),
+            b"/zstd/1.0.0".to_vec(),
+            b"/deflate/1.0.0".to_vec(),
+        ]);
+        for protocol in protocols {
+            roundtrip(config.clone(), protocol, &message());
+        }
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn brotli_roundtrip() {
+        let config = CompressionConfig::new(vec![Algorithm::Brotli]);
+        let protocol = config.protocol_info().next().unwrap();
+        assert_eq!(protocol.algorithm(), Algorithm::Brotli);
+        roundtrip(config, protocol, &message());
+    }
+}
@@ -22,6 +22,10 @@ use futures::{prelude::*, ready};
 use libp2p_core::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
 use std::{io, iter, pin::Pin, task::Context, task::Poll};
 
+mod compression;
+
+pub use compression::{Algorithm, CompressionConfig, CompressionOutput, CompressionProtocol, Level};
+
 #[derive(Debug, Copy, Clone)]
 pub struct DeflateConfig {
     compression: flate2::Compression,
@@ -0,0 +1,471 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Resolution of `/dnsaddr/` addresses.
+//!
+//! A [dnsaddr](https://github.com/multiformats/multiaddr/blob/master/protocols/DNSADDR.md)
+//! domain publishes the addresses of one or more peers as TXT records of the
+//! form `dnsaddr=<multiaddr>` under the name `_dnsaddr.<domain>`. A record may
+//! in turn refer to another `/dnsaddr/` domain, forming a chain which is
+//! followed until addresses ending in `/p2p/<peer-id>` are found.
+//!
+//! The TXT records are queried directly from the nameservers of the system
+//! (or the ones configured) and cached for the TTL of the records.
+
+use futures::{channel::oneshot, executor::ThreadPool};
+use dns_parser::{Builder, Packet, QueryClass, QueryType, RData, ResponseCode};
+use lazy_static::lazy_static;
+use libp2p_core::{PeerId, multiaddr::{Multiaddr, Protocol}};
+use log::{debug, trace};
+use std::{
+    cmp,
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+
+/// The maximum number of TXT lookups performed for resolving a single domain.
+const DEFAULT_MAX_LOOKUPS: usize = 32;
+
+/// The upper bound for caching TXT records, regardless of their TTL.
+const MAX_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The duration for which the absence of TXT records is cached.
+const NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// The resolver used by [`resolve_dnsaddr`].
+    static ref GLOBAL_RESOLVER: io::Result<DnsaddrResolver> = DnsaddrResolver::new();
+}
+
+/// Resolves a `/dnsaddr/` domain into the addresses of the peers it refers to,
+/// using the nameservers of the system.
+///
+/// Results are cached process-wide. See [`DnsaddrResolver`] for a resolver
+/// with its own configuration and cache.
+pub async fn resolve_dnsaddr(domain: &str) -> io::Result<Vec<(PeerId, Multiaddr)>> {
+    match &*GLOBAL_RESOLVER {
+        Ok(resolver) => resolver.resolve(domain).await,
+        Err(e) => Err(io::Error::new(e.kind(), e.to_string()))
+    }
+}
+
+/// Resolves `/dnsaddr/` domains, caching the queried TXT records.
+///
+/// Cloning a resolver is cheap and clones share the cache.
+#[derive(Clone)]
+pub struct DnsaddrResolver {
+    /// The nameservers to query, in order of preference.
+    nameservers: Vec<SocketAddr>,
+    /// The timeout of a single query.
+    timeout: Duration,
+    /// The maximum number of lookups for resolving a single domain.
+    max_lookups: usize,
+    /// Pool of threads to run the blocking queries on.
+    thread_pool: ThreadPool,
+    /// The cached TXT records by queried name.
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+/// The cached TXT records of a name.
+struct CacheEntry {
+    records: Vec<String>,
+    expires: Instant,
+}
+
+/// A parsed `dnsaddr` TXT record.
+#[derive(Debug, PartialEq)]
+enum Entry {
+    /// The address of a peer.
+    Peer(PeerId, Multiaddr),
+    /// A reference to another `/dnsaddr/` domain.
+    Dnsaddr(String),
+}
+
+impl DnsaddrResolver {
+    /// Creates a resolver using the nameservers of the system.
+    pub fn new() -> io::Result<Self> {
+        let nameservers = system_nameservers();
+        if nameservers.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no nameservers configured"))
+        }
+        DnsaddrResolver::with_nameservers(nameservers)
+    }
+
+    /// Creates a resolver using the given nameservers.
+    pub fn with_nameservers(nameservers: Vec<SocketAddr>) -> io::Result<Self> {
+        let thread_pool = ThreadPool::builder()
+            .pool_size(1)
+            .name_prefix("libp2p-dnsaddr-")
+            .create()?;
+        Ok(DnsaddrResolver {
+            nameservers,
+            timeout: Duration::from_secs(5),
+            max_lookups: DEFAULT_MAX_LOOKUPS,
+            thread_pool,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Sets the timeout of a single TXT query.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of TXT lookups performed for resolving a
+    /// single domain, bounding the length and fan-out of `/dnsaddr/` chains.
+    pub fn set_max_lookups(&mut self, max_lookups: usize) -> &mut Self {
+        self.max_lookups = max_lookups;
+        self
+    }
+
+    /// Resolves a `/dnsaddr/` domain into the addresses of the peers it refers to.
+    ///
+    /// Nested `/dnsaddr/` domains are followed, each domain at most once.
+    /// Records without a trailing `/p2p/<peer-id>` are ignored.
+    pub async fn resolve(&self, domain: &str) -> io::Result<Vec<(PeerId, Multiaddr)>> {
+        let mut peers = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![domain.to_owned()];
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name.clone()) {
+                debug!("Skipping already resolved dnsaddr domain {}", name);
+                continue
+            }
+            if visited.len() > self.max_lookups {
+                debug!("Maximum number of lookups reached while resolving {}", domain);
+                break
+            }
+            let records = match self.lookup(&name).await {
+                Ok(records) => records,
+                Err(e) if name == domain => return Err(e),
+                Err(e) => {
+                    debug!("Failed to resolve nested dnsaddr domain {}: {}", name, e);
+                    continue
+                }
+            };
+            for record in records {
+                match parse_record(&record) {
+                    Some(Entry::Peer(peer, addr)) => peers.push((peer, addr)),
+                    Some(Entry::Dnsaddr(next)) => pending.push(next),
+                    None => trace!("Ignoring TXT record of {}: {}", name, record)
+                }
+            }
+        }
+        Ok(peers)
+    }
+
+    /// Returns the TXT records of `_dnsaddr.<name>`, querying the nameservers
+    /// if they are not cached.
+    async fn lookup(&self, name: &str) -> io::Result<Vec<String>> {
+        let qname = format!("_dnsaddr.{}", name);
+        if let Some(entry) = self.cache.lock().unwrap().get(&qname) {
+            if entry.expires > Instant::now() {
+                return Ok(entry.records.clone())
+            }
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let nameservers = self.nameservers.clone();
+        let timeout = self.timeout;
+        let query = qname.clone();
+        self.thread_pool.spawn_ok(async move {
+            let _ = tx.send(query_txt(&nameservers, &query, timeout));
+        });
+        let (records, ttl) = rx.await
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "DNS resolver crashed"))??;
+
+        trace!("Caching {} TXT records of {} for {:?}", records.len(), qname, ttl);
+        let expires = Instant::now() + cmp::min(ttl, MAX_TTL);
+        self.cache.lock().unwrap().insert(qname, CacheEntry { records: records.clone(), expires });
+        Ok(records)
+    }
+}
+
+/// Parses a `dnsaddr=<multiaddr>` TXT record.
+fn parse_record(record: &str) -> Option<Entry> {
+    if !record.starts_with("dnsaddr=") {
+        return None
+    }
+    let mut addr = record["dnsaddr=".len() ..].parse::<Multiaddr>().ok()?;
+    if let Some(Protocol::Dnsaddr(name)) = addr.iter().next() {
+        return Some(Entry::Dnsaddr(name.into_owned()))
+    }
+    match addr.pop() {
+        Some(Protocol::P2p(hash)) => {
+            let peer = PeerId::from_multihash(hash).ok()?;
+            Some(Entry::Peer(peer, addr))
+        }
+        _ => None
+    }
+}
+
+/// Queries the TXT records of the given name, trying the nameservers in order.
+///
+/// Returns the records together with their TTL.
+fn query_txt(nameservers: &[SocketAddr], name: &str, timeout: Duration) -> io::Result<(Vec<String>, Duration)> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no nameservers configured");
+    for nameserver in nameservers {
+        match query_nameserver(*nameserver, name, timeout) {
+            Ok(result) => return Ok(result),
+            Err(e) => {
+                debug!("TXT query of {} at {} failed: {}", name, nameserver, e);
+                last_error = e
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Queries the TXT records of the given name from a single nameserver.
+fn query_nameserver(nameserver: SocketAddr, name: &str, timeout: Duration) -> io::Result<(Vec<String>, Duration)> {
+    let local: SocketAddr = if nameserver.is_ipv4() {
+        (IpAddr::from([0u8; 4]), 0).into()
+    } else {
+        (IpAddr::from([0u16; 8]), 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect(nameserver)?;
+
+    let id = rand::random();
+    let mut builder = Builder::new_query(id, true);
+    builder.add_question(name, false, QueryType::TXT, QueryClass::IN);
+    let query = builder.build()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS query too large"))?;
+    socket.send(&query)?;
+
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = socket.recv(&mut buf)?;
+        let packet = match Packet::parse(&buf[.. n]) {
+            Ok(packet) => packet,
+            Err(e) => {
+                trace!("Ignoring invalid DNS response from {}: {}", nameserver, e);
+                continue
+            }
+        };
+        if packet.header.id != id {
+            continue
+        }
+        if packet.header.truncated {
+            debug!("TXT response of {} from {} truncated, retrying over TCP", name, nameserver);
+            return query_nameserver_tcp(nameserver, name, &query, id, timeout)
+        }
+        return parse_response(&packet, name)
+    }
+}
+
+/// Queries the TXT records of the given name from a single nameserver over
+/// TCP, for responses that do not fit into a UDP datagram.
+fn query_nameserver_tcp(nameserver: SocketAddr, name: &str, query: &[u8], id: u16, timeout: Duration)
+    -> io::Result<(Vec<String>, Duration)>
+{
+    let mut stream = TcpStream::connect_timeout(&nameserver, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    // Messages over TCP are prefixed with their length (RFC 1035, section 4.2.2).
+    let len = u16::try_from(query.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS query too large"))?;
+    let mut message = Vec::with_capacity(2 + query.len());
+    message.extend_from_slice(&len.to_be_bytes());
+    message.extend_from_slice(query);
+    stream.write_all(&message)?;
+
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut buf = vec![0u8; usize::from(u16::from_be_bytes(len))];
+    stream.read_exact(&mut buf)?;
+    let packet = Packet::parse(&buf)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if packet.header.id != id {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "DNS response ID mismatch"))
+    }
+    if packet.header.truncated {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "DNS response truncated"))
+    }
+    parse_response(&packet, name)
+}
+
+/// Extracts the TXT records of a response together with their TTL.
+fn parse_response(packet: &Packet<'_>, name: &str) -> io::Result<(Vec<String>, Duration)> {
+    match packet.header.response_code {
+        ResponseCode::NoError => {}
+        ResponseCode::NameError => return Ok((Vec::new(), NEGATIVE_TTL)),
+        code => return Err(io::Error::new(io::ErrorKind::Other, format!("DNS error: {:?}", code)))
+    }
+    let mut ttl = MAX_TTL.as_secs();
+    let mut records = Vec::new();
+    for answer in &packet.answers {
+        if let RData::TXT(ref txt) = answer.data {
+            ttl = cmp::min(ttl, u64::from(answer.ttl));
+            // A TXT record may be split into several character strings.
+            let record = txt.iter().flat_map(|s| s.iter().copied()).collect::<Vec<u8>>();
+            match String::from_utf8(record) {
+                Ok(record) => records.push(record),
+                Err(_) => trace!("Ignoring non-UTF-8 TXT record of {}", name)
+            }
+        }
+    }
+    let ttl = if records.is_empty() { NEGATIVE_TTL } else { Duration::from_secs(ttl) };
+    Ok((records, ttl))
+}
+
+/// Returns the nameservers listed in `/etc/resolv.conf`.
+fn system_nameservers() -> Vec<SocketAddr> {
+    std::fs::read_to_string("/etc/resolv.conf")
+        .map(|conf| parse_resolv_conf(&conf))
+        .unwrap_or_default()
+}
+
+/// Parses the `nameserver` lines of a `resolv.conf` file.
+fn parse_resolv_conf(conf: &str) -> Vec<SocketAddr> {
+    conf.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("nameserver") {
+                return None
+            }
+            // Strip the zone of link-local IPv6 addresses, e.g. `fe80::1%eth0`.
+            let ip = words.next()?.split('%').next()?;
+            ip.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, 53))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_records() {
+        let peer = PeerId::random();
+        let record = format!("dnsaddr=/ip4/1.2.3.4/tcp/4001/p2p/{}", peer);
+        assert_eq!(
+            parse_record(&record),
+            Some(Entry::Peer(peer, "/ip4/1.2.3.4/tcp/4001".parse().unwrap())));
+        assert_eq!(
+            parse_record("dnsaddr=/dnsaddr/sjc-1.bootstrap.libp2p.io"),
+            Some(Entry::Dnsaddr("sjc-1.bootstrap.libp2p.io".to_owned())));
+        assert_eq!(parse_record("dnsaddr=/ip4/1.2.3.4/tcp/4001"), None);
+        assert_eq!(parse_record("v=spf1 -all"), None);
+    }
+
+    #[test]
+    fn parses_resolv_conf() {
+        let conf = "# comment\nsearch example.com\nnameserver 10.0.0.1\nnameserver fe80::1%eth0\n";
+        assert_eq!(
+            parse_resolv_conf(conf),
+            vec!["10.0.0.1:53".parse().unwrap(), "[fe80::1]:53".parse().unwrap()]);
+    }
+
+    /// Builds a TXT response to the given query.
+    fn txt_response(id: u16, truncated: bool, name: &str, records: &[String]) -> Vec<u8> {
+        let mut qname = Vec::new();
+        for label in name.split('.') {
+            qname.push(label.len() as u8);
+            qname.extend_from_slice(label.as_bytes());
+        }
+        qname.push(0);
+
+        let answers = if truncated { &[][..] } else { records };
+        let mut out = Vec::new();
+        out.extend_from_slice(&id.to_be_bytes());
+        // A recursive response, with the TC bit set if truncated.
+        out.extend_from_slice(&if truncated { 0x8380u16 } else { 0x8180u16 }.to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes());
+        out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        out.extend_from_slice(&qname);
+        out.extend_from_slice(&[0, 16, 0, 1]);
+        for record in answers {
+            out.extend_from_slice(&qname);
+            out.extend_from_slice(&[0, 16, 0, 1]);
+            out.extend_from_slice(&300u32.to_be_bytes());
+            out.extend_from_slice(&(record.len() as u16 + 1).to_be_bytes());
+            out.push(record.len() as u8);
+            out.extend_from_slice(record.as_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn truncated_responses_are_retried_over_tcp() {
+        let name = "_dnsaddr.example.com";
+        let records = vec![format!("dnsaddr=/ip4/1.2.3.4/tcp/4001/p2p/{}", PeerId::random())];
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let nameserver = udp.local_addr().unwrap();
+        let tcp = std::net::TcpListener::bind(nameserver).unwrap();
+
+        let expected = records.clone();
+        let server = std::thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            let (n, from) = udp.recv_from(&mut buf).unwrap();
+            let id = Packet::parse(&buf[.. n]).unwrap().header.id;
+            // Invalid datagrams are skipped like responses to other queries.
+            udp.send_to(b"\x00", from).unwrap();
+            udp.send_to(&txt_response(id, true, name, &records), from).unwrap();
+
+            let (mut stream, _) = tcp.accept().unwrap();
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).unwrap();
+            let mut query = vec![0u8; usize::from(u16::from_be_bytes(len))];
+            stream.read_exact(&mut query).unwrap();
+            let response = txt_response(id, false, name, &records);
+            stream.write_all(&(response.len() as u16).to_be_bytes()).unwrap();
+            stream.write_all(&response).unwrap();
+        });
+
+        let (received, ttl) = query_nameserver(nameserver, name, Duration::from_secs(5)).unwrap();
+        assert_eq!(received, expected);
+        assert_eq!(ttl, Duration::from_secs(300));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn cached_chains_terminate() {
+        let peer = PeerId::random();
+        let resolver = DnsaddrResolver::with_nameservers(Vec::new()).unwrap();
+        {
+            let expires = Instant::now() + Duration::from_secs(60);
+            let mut cache = resolver.cache.lock().unwrap();
+            cache.insert("_dnsaddr.a.example".to_owned(), CacheEntry {
+                records: vec!["dnsaddr=/dnsaddr/b.example".to_owned()],
+                expires,
+            });
+            cache.insert("_dnsaddr.b.example".to_owned(), CacheEntry {
+                records: vec![
+                    "dnsaddr=/dnsaddr/a.example".to_owned(),
+                    format!("dnsaddr=/ip4/1.2.3.4/tcp/4001/p2p/{}", peer),
+                ],
+                expires,
+            });
+        }
+        let peers = futures::executor::block_on(resolver.resolve("a.example")).unwrap();
+        assert_eq!(peers, vec![(peer, "/ip4/1.2.3.4/tcp/4001".parse().unwrap())]);
+    }
+}
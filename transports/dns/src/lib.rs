@@ -32,6 +32,13 @@
 //! `/dns/`, `/dns4/`, or `/dns6/` component, a DNS resolve will be performed and the component
 //! will be replaced with `/ip4/` and/or `/ip6/` components.
 //!
+//! In addition, [`resolve_dnsaddr`] and [`DnsaddrResolver`] resolve `/dnsaddr/` domains
+//! into the addresses of the peers they refer to, e.g. for bootstrapping.
+//!
+
+mod dnsaddr;
+
+pub use dnsaddr::{DnsaddrResolver, resolve_dnsaddr};
 
 use futures::{prelude::*, channel::oneshot, future::BoxFuture};
 use libp2p_core::{
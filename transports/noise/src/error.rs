@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use libp2p_core::identity;
+use libp2p_core::{identity, PeerId};
 use snow::error::Error as SnowError;
 use std::{error::Error, fmt, io};
 
@@ -39,6 +39,11 @@ pub enum NoiseError {
     InvalidPayload(prost::DecodeError),
     /// A signature was required and could not be created.
     SigningError(identity::error::SigningError),
+    /// The application payload of the remote has been rejected.
+    PayloadRejected,
+    /// The remote has been rejected by the peer filter of the
+    /// [`HandshakePayload`](crate::HandshakePayload).
+    PeerRejected(PeerId),
 }
 
 impl fmt::Display for NoiseError {
@@ -50,6 +55,8 @@ impl fmt::Display for NoiseError {
             NoiseError::InvalidPayload(e) => write!(f, "{}", e),
             NoiseError::AuthenticationFailed => f.write_str("Authentication failed"),
             NoiseError::SigningError(e) => write!(f, "{}", e),
+            NoiseError::PayloadRejected => f.write_str("handshake payload rejected"),
+            NoiseError::PeerRejected(p) => write!(f, "remote {} rejected", p),
         }
     }
 }
@@ -63,6 +70,8 @@ impl Error for NoiseError {
             NoiseError::AuthenticationFailed => None,
             NoiseError::InvalidPayload(e) => Some(e),
             NoiseError::SigningError(e) => Some(e),
+            NoiseError::PayloadRejected => None,
+            NoiseError::PeerRejected(_) => None,
         }
     }
 }
@@ -27,18 +27,23 @@ use bytes::Bytes;
 use framed::{MAX_FRAME_LEN, NoiseFramed};
 use futures::ready;
 use futures::prelude::*;
+use libp2p_core::buffer_pool::{BufferPool, PooledBuffer};
 use log::trace;
-use std::{cmp::min, fmt, io, pin::Pin, task::{Context, Poll}};
+use std::{cmp::min, fmt, io::{self, IoSlice}, pin::Pin, task::{Context, Poll}};
 
 /// A noise session to a remote.
 ///
 /// `T` is the type of the underlying I/O resource.
+///
+/// Data written is buffered in a buffer from the [`BufferPool::global`]
+/// pool until a full frame can be sent or the `NoiseOutput` is flushed.
+/// Vectored writes gather all given slices into the same frame.
 pub struct NoiseOutput<T> {
     io: NoiseFramed<T, snow::TransportState>,
     recv_buffer: Bytes,
     recv_offset: usize,
-    send_buffer: Vec<u8>,
-    send_offset: usize,
+    send_buffer: PooledBuffer,
+    remote_payload: Vec<u8>,
 }
 
 impl<T> fmt::Debug for NoiseOutput<T> {
@@ -55,10 +60,78 @@ impl<T> NoiseOutput<T> {
             io,
             recv_buffer: Bytes::new(),
             recv_offset: 0,
-            send_buffer: Vec::new(),
-            send_offset: 0,
+            send_buffer: BufferPool::global().get(),
+            remote_payload: Vec::new(),
         }
     }
+
+    /// Returns the application payload sent by the remote during the
+    /// handshake, which is empty if the remote sent none.
+    ///
+    /// See [`HandshakePayload`](handshake::HandshakePayload).
+    pub fn remote_payload(&self) -> &[u8] {
+        &self.remote_payload
+    }
+}
+
+impl<T: AsyncRead + Unpin> NoiseOutput<T> {
+    /// Reads up to `max` bytes of decrypted data, handing out a slice of the
+    /// received frame instead of copying it. An empty buffer signals the end
+    /// of the stream.
+    ///
+    /// > **Note**: As long as a slice of a frame is held, the frame buffer
+    /// > can not be reused for the next frame.
+    pub fn poll_read_bytes(mut self: Pin<&mut Self>, cx: &mut Context<'_>, max: usize) -> Poll<io::Result<Bytes>> {
+        loop {
+            let len = self.recv_buffer.len();
+            let off = self.recv_offset;
+            if len > 0 || max == 0 {
+                let n = min(len - off, max);
+                let data = self.recv_buffer.slice(off .. off + n);
+                trace!("read: sliced {}/{} bytes", off + n, len);
+                self.recv_offset += n;
+                if len == self.recv_offset {
+                    trace!("read: frame consumed");
+                    self.recv_buffer = Bytes::new();
+                    self.recv_offset = 0;
+                }
+                return Poll::Ready(Ok(data))
+            }
+
+            match Pin::new(&mut self.io).poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(Ok(Bytes::new())),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(Some(Ok(frame))) => {
+                    self.recv_buffer = frame;
+                    self.recv_offset = 0;
+                }
+            }
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> NoiseOutput<T> {
+    /// Writes data from a reference-counted buffer, returning the number of
+    /// bytes written.
+    ///
+    /// If no data is buffered from previous writes, up to a full frame is
+    /// encrypted directly from `buf`, skipping the copy into the send buffer.
+    /// Every such write produces a frame of its own, so data should be
+    /// written in large chunks. Otherwise, the data is buffered like with
+    /// [`AsyncWrite::poll_write`].
+    pub fn poll_write_bytes(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &Bytes) -> Poll<io::Result<usize>> {
+        if !self.send_buffer.is_empty() || buf.is_empty() {
+            return self.poll_write(cx, buf)
+        }
+        let this = Pin::into_inner(self);
+        let mut io = Pin::new(&mut this.io);
+        ready!(io.as_mut().poll_ready(cx))?;
+        let n = min(MAX_FRAME_LEN, buf.len());
+        trace!("write: sending {} bytes", n);
+        io.start_send_slice(&buf[.. n])?;
+        Poll::Ready(Ok(n))
+    }
 }
 
 impl<T: AsyncRead + Unpin> AsyncRead for NoiseOutput<T> {
@@ -95,25 +168,31 @@ impl<T: AsyncRead + Unpin> AsyncRead for NoiseOutput<T> {
 
 impl<T: AsyncWrite + Unpin> AsyncWrite for NoiseOutput<T> {
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<io::Result<usize>> {
         let this = Pin::into_inner(self);
         let mut io = Pin::new(&mut this.io);
-        let frame_buf = &mut this.send_buffer;
 
         // The MAX_FRAME_LEN is the maximum buffer size before a frame must be sent.
-        if this.send_offset == MAX_FRAME_LEN {
+        if this.send_buffer.len() == MAX_FRAME_LEN {
             trace!("write: sending {} bytes", MAX_FRAME_LEN);
             ready!(io.as_mut().poll_ready(cx))?;
-            io.as_mut().start_send(&frame_buf)?;
-            this.send_offset = 0;
+            io.as_mut().start_send_slice(&this.send_buffer)?;
+            this.send_buffer.clear();
         }
 
-        let off = this.send_offset;
-        let n = min(MAX_FRAME_LEN, off.saturating_add(buf.len()));
-        this.send_buffer.resize(n, 0u8);
-        let n = min(MAX_FRAME_LEN - off, buf.len());
-        this.send_buffer[off .. off + n].copy_from_slice(&buf[.. n]);
-        this.send_offset += n;
-        trace!("write: buffered {} bytes", this.send_offset);
+        let mut n = 0;
+        for buf in bufs {
+            let k = min(MAX_FRAME_LEN - this.send_buffer.len(), buf.len());
+            this.send_buffer.extend_from_slice(&buf[.. k]);
+            n += k;
+            if this.send_buffer.len() == MAX_FRAME_LEN {
+                break
+            }
+        }
+        trace!("write: buffered {} bytes", this.send_buffer.len());
 
         Poll::Ready(Ok(n))
     }
@@ -121,14 +200,13 @@ impl<T: AsyncWrite + Unpin> AsyncWrite for NoiseOutput<T> {
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let this = Pin::into_inner(self);
         let mut io = Pin::new(&mut this.io);
-        let frame_buf = &mut this.send_buffer;
 
         // Check if there is still one more frame to send.
-        if this.send_offset > 0 {
+        if !this.send_buffer.is_empty() {
             ready!(io.as_mut().poll_ready(cx))?;
-            trace!("flush: sending {} bytes", this.send_offset);
-            io.as_mut().start_send(&frame_buf)?;
-            this.send_offset = 0;
+            trace!("flush: sending {} bytes", this.send_buffer.len());
+            io.as_mut().start_send_slice(&this.send_buffer)?;
+            this.send_buffer.clear();
         }
 
         io.as_mut().poll_flush(cx)
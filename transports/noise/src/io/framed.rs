@@ -21,11 +21,12 @@
 //! This module provides a `Sink` and `Stream` for length-delimited
 //! Noise protocol messages in form of [`NoiseFramed`].
 
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
 use crate::{NoiseError, Protocol, PublicKey};
 use crate::io::NoiseOutput;
 use futures::ready;
 use futures::prelude::*;
+use libp2p_core::buffer_pool::{BufferPool, PooledBuffer};
 use log::{debug, trace};
 use std::{fmt, io, pin::Pin, task::{Context, Poll}};
 
@@ -33,6 +34,8 @@ use std::{fmt, io, pin::Pin, task::{Context, Poll}};
 const MAX_NOISE_MSG_LEN: usize = 65535;
 /// Space given to the encryption buffer to hold key material.
 const EXTRA_ENCRYPT_SPACE: usize = 1024;
+/// Size of the length prefix of a noise message.
+const FRAME_LEN_SIZE: usize = 2;
 /// Max. length for Noise protocol message payloads.
 pub const MAX_FRAME_LEN: usize = MAX_NOISE_MSG_LEN - EXTRA_ENCRYPT_SPACE;
 
@@ -45,14 +48,20 @@ static_assertions::const_assert! {
 ///
 /// `T` is the type of the underlying I/O resource and `S` the
 /// type of the Noise session state.
+///
+/// The read, write and decryption buffers are taken from the
+/// [`BufferPool::global`] pool and returned to it when the
+/// `NoiseFramed` is dropped.
 pub struct NoiseFramed<T, S> {
     io: T,
     session: S,
     read_state: ReadState,
     write_state: WriteState,
-    read_buffer: Vec<u8>,
-    write_buffer: Vec<u8>,
-    decrypt_buffer: BytesMut,
+    read_buffer: PooledBuffer,
+    /// The length prefix followed by the cipher text of the frame
+    /// being written, such that both are sent with a single write.
+    write_buffer: PooledBuffer,
+    decrypt_buffer: PooledBuffer,
 }
 
 impl<T, S> fmt::Debug for NoiseFramed<T, S> {
@@ -64,20 +73,40 @@ impl<T, S> fmt::Debug for NoiseFramed<T, S> {
     }
 }
 
+impl<T, S> NoiseFramed<T, S> {
+    /// Consumes the `NoiseFramed` after a received frame failed to decrypt,
+    /// returning the underlying I/O resource together with that raw frame.
+    ///
+    /// Returns `None` if no decryption error occurred.
+    pub(crate) fn into_undecryptable_frame(self) -> Option<(T, Vec<u8>)> {
+        match self.read_state {
+            ReadState::DecErr => Some((self.io, self.read_buffer.to_vec())),
+            _ => None
+        }
+    }
+}
+
 impl<T> NoiseFramed<T, snow::HandshakeState> {
     /// Creates a nwe `NoiseFramed` for beginning a Noise protocol handshake.
     pub fn new(io: T, state: snow::HandshakeState) -> Self {
+        let pool = BufferPool::global();
         NoiseFramed {
             io,
             session: state,
             read_state: ReadState::Ready,
             write_state: WriteState::Ready,
-            read_buffer: Vec::new(),
-            write_buffer: Vec::new(),
-            decrypt_buffer: BytesMut::new(),
+            read_buffer: pool.get(),
+            write_buffer: pool.get(),
+            decrypt_buffer: pool.get(),
         }
     }
 
+    /// Returns the static DH public key of the remote, if already received
+    /// or known in advance.
+    pub(crate) fn remote_static(&self) -> Option<&[u8]> {
+        self.session.get_remote_static()
+    }
+
     /// Converts the `NoiseFramed` into a `NoiseOutput` encrypted data stream
     /// once the handshake is complete, including the static DH [`PublicKey`]
     /// of the remote, if received.
@@ -137,9 +166,7 @@ enum ReadState {
 enum WriteState {
     /// Ready to write another frame.
     Ready,
-    /// Writing the frame length.
-    WriteLen { len: usize, buf: [u8; 2], off: usize },
-    /// Writing the frame data.
+    /// Writing the frame length and data.
     WriteData { len: usize, off: usize },
     /// EOF has been reached unexpectedly (terminal state).
     Eof,
@@ -260,25 +287,6 @@ where
                 WriteState::Ready => {
                     return Poll::Ready(Ok(()));
                 }
-                WriteState::WriteLen { len, buf, mut off } => {
-                    trace!("write: frame len ({}, {:?}, {}/2)", len, buf, off);
-                    match write_frame_len(&mut this.io, cx, &buf, &mut off) {
-                        Poll::Ready(Ok(true)) => (),
-                        Poll::Ready(Ok(false)) => {
-                            trace!("write: eof");
-                            this.write_state = WriteState::Eof;
-                            return Poll::Ready(Err(io::ErrorKind::WriteZero.into()))
-                        }
-                        Poll::Ready(Err(e)) => {
-                            return Poll::Ready(Err(e))
-                        }
-                        Poll::Pending => {
-                            this.write_state = WriteState::WriteLen { len, buf, off };
-                            return Poll::Pending
-                        }
-                    }
-                    this.write_state = WriteState::WriteData { len, off: 0 }
-                }
                 WriteState::WriteData { len, ref mut off } => {
                     let n = {
                         let f = Pin::new(&mut this.io).poll_write(cx, &this.write_buffer[*off .. len]);
@@ -309,18 +317,39 @@ where
     }
 
     fn start_send(self: Pin<&mut Self>, frame: &Vec<u8>) -> Result<(), Self::Error> {
+        self.start_send_slice(frame)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_ready(cx))?;
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.io).poll_close(cx)
+    }
+}
+
+impl<T, S> NoiseFramed<T, S>
+where
+    T: AsyncWrite + Unpin,
+    S: SessionState + Unpin
+{
+    /// Encrypts a frame from a plain byte slice, like `Sink::start_send`.
+    pub(crate) fn start_send_slice(self: Pin<&mut Self>, frame: &[u8]) -> io::Result<()> {
         assert!(frame.len() <= MAX_FRAME_LEN);
         let mut this = Pin::into_inner(self);
         assert!(this.write_state.is_ready());
 
-        this.write_buffer.resize(frame.len() + EXTRA_ENCRYPT_SPACE, 0u8);
-        match this.session.write_message(frame, &mut this.write_buffer[..]) {
+        this.write_buffer.resize(FRAME_LEN_SIZE + frame.len() + EXTRA_ENCRYPT_SPACE, 0u8);
+        match this.session.write_message(frame, &mut this.write_buffer[FRAME_LEN_SIZE ..]) {
             Ok(n) => {
                 trace!("write: cipher text len = {} bytes", n);
-                this.write_buffer.truncate(n);
-                this.write_state = WriteState::WriteLen {
-                    len: n,
-                    buf: u16::to_be_bytes(n as u16),
+                this.write_buffer[.. FRAME_LEN_SIZE].copy_from_slice(&u16::to_be_bytes(n as u16));
+                this.write_buffer.truncate(FRAME_LEN_SIZE + n);
+                this.write_state = WriteState::WriteData {
+                    len: FRAME_LEN_SIZE + n,
                     off: 0
                 };
                 Ok(())
@@ -332,16 +361,6 @@ where
             }
         }
     }
-
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        ready!(self.as_mut().poll_ready(cx))?;
-        Pin::new(&mut self.io).poll_flush(cx)
-    }
-
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        ready!(self.as_mut().poll_flush(cx))?;
-        Pin::new(&mut self.io).poll_close(cx)
-    }
 }
 
 /// A stateful context in which Noise protocol messages can be read and written.
@@ -402,37 +421,3 @@ fn read_frame_len<R: AsyncRead + Unpin>(
         }
     }
 }
-
-/// Write 2 bytes as frame length from the given buffer into the given sink.
-///
-/// Panics if `off >= 2`.
-///
-/// When [`Poll::Pending`] is returned, the given offset
-/// may have been updated (i.e. a byte may have been written) and must
-/// be preserved for the next invocation.
-///
-/// Returns `false` if EOF has been encountered.
-fn write_frame_len<W: AsyncWrite + Unpin>(
-    mut io: &mut W,
-    cx: &mut Context<'_>,
-    buf: &[u8; 2],
-    off: &mut usize,
-) -> Poll<io::Result<bool>> {
-    loop {
-        match ready!(Pin::new(&mut io).poll_write(cx, &buf[*off ..])) {
-            Ok(n) => {
-                if n == 0 {
-                    return Poll::Ready(Ok(false))
-                }
-                *off += n;
-                if *off == 2 {
-                    return Poll::Ready(Ok(true))
-                }
-            }
-            Err(e) => {
-                return Poll::Ready(Err(e));
-            }
-        }
-    }
-}
-
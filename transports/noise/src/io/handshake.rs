@@ -29,11 +29,11 @@ use crate::LegacyConfig;
 use crate::error::NoiseError;
 use crate::protocol::{Protocol, PublicKey, KeypairIdentity};
 use crate::io::{NoiseOutput, framed::NoiseFramed};
-use libp2p_core::identity;
+use libp2p_core::{identity, PeerId};
 use futures::prelude::*;
 use futures::task;
 use prost::Message;
-use std::{io, pin::Pin, task::Context};
+use std::{fmt, io, pin::Pin, sync::Arc, task::Context};
 
 /// The identity of the remote established during a handshake.
 pub enum RemoteIdentity<C> {
@@ -90,6 +90,85 @@ pub enum IdentityExchange {
     None { remote: identity::PublicKey }
 }
 
+/// An application payload exchanged during a handshake in addition to the
+/// identities, e.g. the genesis hash of the chain a node follows.
+///
+/// The local payload is sent along with the local identity. The payload
+/// received from the remote can be checked with a predicate, failing the
+/// handshake with [`NoiseError::PayloadRejected`] if it does not hold, and is
+/// afterwards available through [`NoiseOutput::remote_payload`].
+///
+/// > **Note**: The payload is encrypted, but the responder of an `XX`
+/// > handshake sends it before the initiator is authenticated. It should
+/// > therefore not contain secrets.
+///
+/// In addition, a predicate over the [`PeerId`] of the remote can be given,
+/// which is evaluated as soon as the identity of the remote is received and
+/// authenticated, i.e. before the local identity and payload are sent if the
+/// remote identifies first. This is the case for the dialer of an `XX`
+/// handshake and the listener of an `IX` or `IK` handshake. Otherwise the
+/// handshake still fails with [`NoiseError::PeerRejected`] before it completes.
+#[derive(Clone, Default)]
+pub struct HandshakePayload {
+    local: Vec<u8>,
+    check: Option<Arc<dyn Fn(&[u8]) -> bool + Send + Sync>>,
+    peer_filter: Option<Arc<dyn Fn(&PeerId) -> bool + Send + Sync>>,
+}
+
+impl HandshakePayload {
+    /// Creates a new `HandshakePayload` sending the given payload to the remote
+    /// and accepting any payload of the remote.
+    pub fn new(local: Vec<u8>) -> Self {
+        HandshakePayload { local, check: None }
+    }
+
+    /// Sets the predicate the payload of the remote must satisfy.
+    pub fn with_check<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static
+    {
+        self.check = Some(Arc::new(check));
+        self
+    }
+
+    /// Sets the predicate the [`PeerId`] of the remote must satisfy,
+    /// e.g. membership of an allowlist.
+    pub fn with_peer_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&PeerId) -> bool + Send + Sync + 'static
+    {
+        self.peer_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Returns the payload sent to the remote.
+    pub fn local(&self) -> &[u8] {
+        &self.local
+    }
+
+    /// Checks whether the given payload of the remote is acceptable.
+    fn accepts(&self, remote: &[u8]) -> bool {
+        self.check.as_ref().map_or(true, |check| check(remote))
+    }
+
+    /// Takes the peer filter of `other` if none is set.
+    pub(crate) fn inherit_peer_filter(&mut self, other: &HandshakePayload) {
+        if self.peer_filter.is_none() {
+            self.peer_filter = other.peer_filter.clone();
+        }
+    }
+}
+
+impl fmt::Debug for HandshakePayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandshakePayload")
+            .field("local", &self.local)
+            .field("check", &self.check.is_some())
+            .field("peer_filter", &self.peer_filter.is_some())
+            .finish()
+    }
+}
+
 /// A future performing a Noise handshake pattern.
 pub struct Handshake<T, C>(
     Pin<Box<dyn Future<
@@ -129,14 +208,30 @@ pub fn rt1_initiator<T, C>(
     identity_x: IdentityExchange,
     legacy: LegacyConfig,
 ) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    rt1_initiator_with_payload(io, session, identity, identity_x, legacy, HandshakePayload::default())
+}
+
+/// Like [`rt1_initiator`], additionally exchanging an application payload with the remote.
+pub fn rt1_initiator_with_payload<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: HandshakePayload,
+) -> Handshake<T, C>
 where
     T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
     C: Protocol<C> + AsRef<[u8]>
 {
     Handshake(Box::pin(async move {
-        let mut state = State::new(io, session, identity, identity_x, legacy)?;
+        let mut state = State::new(io, session, identity, identity_x, legacy, payload)?;
         send_identity(&mut state).await?;
-        recv_identity(&mut state).await?;
+        recv_identity::<_, C>(&mut state).await?;
         state.finish()
     }))
 }
@@ -164,13 +259,29 @@ pub fn rt1_responder<T, C>(
     identity_x: IdentityExchange,
     legacy: LegacyConfig,
 ) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    rt1_responder_with_payload(io, session, identity, identity_x, legacy, HandshakePayload::default())
+}
+
+/// Like [`rt1_responder`], additionally exchanging an application payload with the remote.
+pub fn rt1_responder_with_payload<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: HandshakePayload,
+) -> Handshake<T, C>
 where
     T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
     C: Protocol<C> + AsRef<[u8]>
 {
     Handshake(Box::pin(async move {
-        let mut state = State::new(io, session, identity, identity_x, legacy)?;
-        recv_identity(&mut state).await?;
+        let mut state = State::new(io, session, identity, identity_x, legacy, payload)?;
+        recv_identity::<_, C>(&mut state).await?;
         send_identity(&mut state).await?;
         state.finish()
     }))
@@ -201,14 +312,30 @@ pub fn rt15_initiator<T, C>(
     identity_x: IdentityExchange,
     legacy: LegacyConfig,
 ) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    rt15_initiator_with_payload(io, session, identity, identity_x, legacy, HandshakePayload::default())
+}
+
+/// Like [`rt15_initiator`], additionally exchanging an application payload with the remote.
+pub fn rt15_initiator_with_payload<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: HandshakePayload,
+) -> Handshake<T, C>
 where
     T: AsyncWrite + AsyncRead + Unpin + Send + 'static,
     C: Protocol<C> + AsRef<[u8]>
 {
     Handshake(Box::pin(async move {
-        let mut state = State::new(io, session, identity, identity_x, legacy)?;
+        let mut state = State::new(io, session, identity, identity_x, legacy, payload)?;
         send_empty(&mut state).await?;
-        recv_identity(&mut state).await?;
+        recv_identity::<_, C>(&mut state).await?;
         send_identity(&mut state).await?;
         state.finish()
     }))
@@ -239,22 +366,147 @@ pub fn rt15_responder<T, C>(
     identity_x: IdentityExchange,
     legacy: LegacyConfig,
 ) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    rt15_responder_with_payload(io, session, identity, identity_x, legacy, HandshakePayload::default())
+}
+
+/// Like [`rt15_responder`], additionally exchanging an application payload with the remote.
+pub fn rt15_responder_with_payload<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: HandshakePayload,
+) -> Handshake<T, C>
 where
     T: AsyncWrite + AsyncRead + Unpin + Send + 'static,
     C: Protocol<C> + AsRef<[u8]>
 {
     Handshake(Box::pin(async move {
-        let mut state = State::new(io, session, identity, identity_x, legacy)?;
+        let mut state = State::new(io, session, identity, identity_x, legacy, payload)?;
         recv_empty(&mut state).await?;
         send_identity(&mut state).await?;
-        recv_identity(&mut state).await?;
+        recv_identity::<_, C>(&mut state).await?;
         state.finish()
     }))
 }
 
+/// Creates an authenticated Noise handshake for the initiator of a single
+/// roundtrip (2 message) handshake pattern with a known responder static DH
+/// public key (i.e. `IK`), which falls back to a 1.5-roundtrip handshake
+/// pattern (i.e. `XX`) if the responder no longer uses that key.
+///
+/// The fallback is signalled by the responder with a [`FALLBACK_FRAME`]
+/// in place of the second message, after which both sides perform the
+/// message sequence of [`rt15_initiator`] and [`rt15_responder`] over the
+/// same I/O resource, using the `fallback` session. In either case the
+/// remote is expected to identify with the given public identity key.
+///
+/// ```raw
+/// initiator -{id}-> responder
+/// initiator <-{id}- responder
+///
+/// or
+///
+/// initiator -{id}-> responder
+/// initiator <-FALLBACK- responder
+/// initiator --{}--> responder
+/// initiator <-{id}- responder
+/// initiator -{id}-> responder
+/// ```
+pub fn rt1_initiator_with_fallback<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    fallback: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    remote: identity::PublicKey,
+    legacy: LegacyConfig,
+    payload: HandshakePayload,
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let identity_x = IdentityExchange::Send { remote: remote.clone() };
+        let mut state = State::new(io, session, identity.clone(), identity_x, legacy.clone(), payload.clone())?;
+        send_identity(&mut state).await?;
+        let io = match recv_identity::<_, C>(&mut state).await {
+            Ok(()) => return state.finish(),
+            Err(e) => match state.io.into_undecryptable_frame() {
+                Some((io, frame)) if frame == FALLBACK_FRAME => io,
+                _ => return Err(e)
+            }
+        };
+        log::debug!("Remote static DH key changed, falling back to XX.");
+        rt15_initiator_with_payload(io, fallback, identity, IdentityExchange::Send { remote }, legacy, payload).await
+    }))
+}
+
+/// Creates an authenticated Noise handshake for the responder of a single
+/// roundtrip (2 message) handshake pattern with a known responder static DH
+/// public key (i.e. `IK`), which falls back to a 1.5-roundtrip handshake
+/// pattern (i.e. `XX`) if the first message cannot be decrypted, e.g.
+/// because the initiator used an outdated static DH public key.
+///
+/// See [`rt1_initiator_with_fallback`] for the message sequence. In the
+/// fallback handshake, the identities are exchanged mutually.
+pub fn rt1_responder_with_fallback<T, C>(
+    io: T,
+    session: Result<snow::HandshakeState, NoiseError>,
+    fallback: Result<snow::HandshakeState, NoiseError>,
+    identity: KeypairIdentity,
+    identity_x: IdentityExchange,
+    legacy: LegacyConfig,
+    payload: HandshakePayload,
+) -> Handshake<T, C>
+where
+    T: AsyncWrite + AsyncRead + Send + Unpin + 'static,
+    C: Protocol<C> + AsRef<[u8]>
+{
+    Handshake(Box::pin(async move {
+        let mut state = State::new(io, session, identity.clone(), identity_x, legacy.clone(), payload.clone())?;
+        let mut io = match recv_identity::<_, C>(&mut state).await {
+            Ok(()) => {
+                send_identity(&mut state).await?;
+                return state.finish()
+            }
+            Err(e) => match state.io.into_undecryptable_frame() {
+                Some((io, _)) => io,
+                None => return Err(e)
+            }
+        };
+        log::debug!("Failed to decrypt first handshake message, falling back to XX.");
+        send_fallback(&mut io).await?;
+        rt15_responder_with_payload(io, fallback, identity, IdentityExchange::Mutual, legacy, payload).await
+    }))
+}
+
 //////////////////////////////////////////////////////////////////////////////
 // Internal
 
+/// The frame sent (unencrypted) by the responder of a handshake created with
+/// [`rt1_responder_with_fallback`] to signal a fallback to `XX`.
+///
+/// Being shorter than an ephemeral DH public key, it can never be mistaken
+/// for a regular handshake message.
+const FALLBACK_FRAME: &[u8] = b"xx-fallback";
+
+/// Sends the [`FALLBACK_FRAME`] to the remote.
+async fn send_fallback<T>(io: &mut T) -> Result<(), NoiseError>
+where
+    T: AsyncWrite + Unpin
+{
+    io.write_all(&(FALLBACK_FRAME.len() as u16).to_be_bytes()).await?;
+    io.write_all(FALLBACK_FRAME).await?;
+    io.flush().await?;
+    Ok(())
+}
+
 /// Handshake state.
 struct State<T> {
     /// The underlying I/O resource.
@@ -270,6 +522,10 @@ struct State<T> {
     send_identity: bool,
     /// Legacy configuration parameters.
     legacy: LegacyConfig,
+    /// The application payload to send and to check the remote's payload against.
+    payload: HandshakePayload,
+    /// The application payload received from the remote.
+    remote_payload: Vec<u8>,
 }
 
 impl<T> State<T> {
@@ -284,6 +540,7 @@ impl<T> State<T> {
         identity: KeypairIdentity,
         identity_x: IdentityExchange,
         legacy: LegacyConfig,
+        payload: HandshakePayload,
     ) -> Result<Self, NoiseError> {
         let (id_remote_pubkey, send_identity) = match identity_x {
             IdentityExchange::Mutual => (None, true),
@@ -299,6 +556,8 @@ impl<T> State<T> {
                 id_remote_pubkey,
                 send_identity,
                 legacy,
+                payload,
+                remote_payload: Vec::new(),
             }
         )
     }
@@ -312,7 +571,8 @@ impl<T> State<T>
     where
         C: Protocol<C> + AsRef<[u8]>
     {
-        let (pubkey, io) = self.io.into_transport()?;
+        let (pubkey, mut io) = self.io.into_transport()?;
+        io.remote_payload = self.remote_payload;
         let remote = match (self.id_remote_pubkey, pubkey) {
             (_, None) => RemoteIdentity::Unknown,
             (None, Some(dh_pk)) => RemoteIdentity::StaticDhKey(dh_pk),
@@ -368,9 +628,10 @@ where
 
 /// A future for receiving a Noise handshake message with a payload
 /// identifying the remote.
-async fn recv_identity<T>(state: &mut State<T>) -> Result<(), NoiseError>
+async fn recv_identity<T, C>(state: &mut State<T>) -> Result<(), NoiseError>
 where
     T: AsyncRead + Unpin,
+    C: Protocol<C> + AsRef<[u8]>
 {
     let msg = recv(state).await?;
 
@@ -421,6 +682,29 @@ where
         state.dh_remote_pubkey_sig = Some(pb.identity_sig);
     }
 
+    if let Some(filter) = &state.payload.peer_filter {
+        let id_pk = state.id_remote_pubkey.as_ref().ok_or(NoiseError::AuthenticationFailed)?;
+        // Authenticate the identity of the remote now rather than when the
+        // handshake is finished, so that it cannot be claimed by a remote
+        // merely knowing an allowed public key.
+        let dh_pk = state.io.remote_static()
+            .ok_or(NoiseError::AuthenticationFailed)
+            .and_then(C::public_from_bytes)?;
+        if !C::verify(id_pk, &dh_pk, &state.dh_remote_pubkey_sig) {
+            return Err(NoiseError::InvalidKey)
+        }
+        let peer_id = id_pk.clone().into_peer_id();
+        if !filter(&peer_id) {
+            log::debug!("Rejecting remote {} during handshake.", peer_id);
+            return Err(NoiseError::PeerRejected(peer_id))
+        }
+    }
+
+    if !state.payload.accepts(&pb.data) {
+        return Err(NoiseError::PayloadRejected)
+    }
+    state.remote_payload = pb.data;
+
     Ok(())
 }
 
@@ -439,6 +723,8 @@ where
         pb.identity_sig = sig.clone()
     }
 
+    pb.data = state.payload.local.clone();
+
     let mut msg =
         if state.legacy.send_legacy_handshake {
             let mut msg = Vec::with_capacity(2 + pb.encoded_len());
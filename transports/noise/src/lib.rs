@@ -30,6 +30,11 @@
 //! > **Note**: Only the `XX` handshake pattern is currently guaranteed to provide
 //! >           interoperability with other libp2p implementations.
 //!
+//! With the `pq-hybrid` feature, the `XXhfs` handshake pattern additionally
+//! performs a Kyber1024 key encapsulation, so that recorded traffic remains
+//! confidential even if X25519 is broken in the future. It is negotiated
+//! under a distinct protocol name and can be offered alongside `XX`.
+//!
 //! All upgrades produce as output a pair, consisting of the remote's static public key
 //! and a `NoiseOutput` which represents the established cryptographic session with the
 //! remote, implementing `futures::io::AsyncRead` and `futures::io::AsyncWrite`.
@@ -61,9 +66,11 @@ mod protocol;
 pub use error::NoiseError;
 pub use io::NoiseOutput;
 pub use io::handshake;
-pub use io::handshake::{Handshake, RemoteIdentity, IdentityExchange};
+pub use io::handshake::{Handshake, HandshakePayload, RemoteIdentity, IdentityExchange};
 pub use protocol::{Keypair, AuthenticKeypair, KeypairIdentity, PublicKey, SecretKey};
 pub use protocol::{Protocol, ProtocolParams, IX, IK, XX};
+#[cfg(feature = "pq-hybrid")]
+pub use protocol::XXhfs;
 pub use protocol::{x25519::X25519, x25519_spec::X25519Spec};
 
 use futures::prelude::*;
@@ -77,6 +84,8 @@ pub struct NoiseConfig<P, C: Zeroize, R = ()> {
     dh_keys: AuthenticKeypair<C>,
     params: ProtocolParams,
     legacy: LegacyConfig,
+    payload: HandshakePayload,
+    prologue: Vec<u8>,
     remote: R,
     _marker: std::marker::PhantomData<P>
 }
@@ -93,6 +102,42 @@ impl<H, C: Zeroize, R> NoiseConfig<H, C, R> {
         self.legacy = cfg;
         self
     }
+
+    /// Sets the application payload to exchange with the remote during
+    /// the handshake, e.g. to reject peers of a different network before
+    /// the connection is established.
+    ///
+    /// A peer filter set with [`NoiseConfig::set_peer_filter`] is retained
+    /// unless the given payload has its own.
+    pub fn set_payload(&mut self, mut payload: HandshakePayload) -> &mut Self {
+        payload.inherit_peer_filter(&self.payload);
+        self.payload = payload;
+        self
+    }
+
+    /// Sets a predicate over the [`PeerId`] of the remote, e.g. membership
+    /// of an allowlist, which is evaluated during the handshake.
+    ///
+    /// Where the remote identifies first, i.e. when dialing with `XX` or
+    /// listening with `IX` or `IK`, a rejected remote never learns the
+    /// local identity. See [`HandshakePayload`] for details.
+    pub fn set_peer_filter<F>(&mut self, filter: F) -> &mut Self
+    where
+        F: Fn(&PeerId) -> bool + Send + Sync + 'static
+    {
+        self.payload = std::mem::take(&mut self.payload).with_peer_filter(filter);
+        self
+    }
+
+    /// Sets the prologue that both parties mix into the handshake hash.
+    ///
+    /// The handshake fails unless the remote uses the same prologue, which
+    /// binds the Noise session to data known to both sides, e.g. the
+    /// certificate fingerprints of an underlying DTLS connection.
+    pub fn set_prologue(&mut self, prologue: impl Into<Vec<u8>>) -> &mut Self {
+        self.prologue = prologue.into();
+        self
+    }
 }
 
 impl<C> NoiseConfig<IX, C>
@@ -105,6 +150,8 @@ where
             dh_keys,
             params: C::params_ix(),
             legacy: LegacyConfig::default(),
+            payload: HandshakePayload::default(),
+            prologue: Vec::new(),
             remote: (),
             _marker: std::marker::PhantomData
         }
@@ -121,6 +168,8 @@ where
             dh_keys,
             params: C::params_xx(),
             legacy: LegacyConfig::default(),
+            payload: HandshakePayload::default(),
+            prologue: Vec::new(),
             remote: (),
             _marker: std::marker::PhantomData
         }
@@ -135,11 +184,17 @@ where
     ///
     /// Since the identity of the local node is known to the remote, this configuration
     /// does not transmit a static DH public key or public identity key to the remote.
+    ///
+    /// If the first handshake message of the remote cannot be decrypted, e.g. because
+    /// the remote used an outdated static DH public key of the local node, the handshake
+    /// falls back to the `XX` pattern, in which identities are exchanged mutually.
     pub fn ik_listener(dh_keys: AuthenticKeypair<C>) -> Self {
         NoiseConfig {
             dh_keys,
             params: C::params_ik(),
             legacy: LegacyConfig::default(),
+            payload: HandshakePayload::default(),
+            prologue: Vec::new(),
             remote: (),
             _marker: std::marker::PhantomData
         }
@@ -154,6 +209,12 @@ where
     ///
     /// In this configuration, the remote identity is known to the local node,
     /// but the local node still needs to transmit its own public identity.
+    ///
+    /// Should the remote no longer use the given static DH public key, e.g. after
+    /// a restart with a fresh DH keypair, the remote signals a fallback to the `XX`
+    /// pattern and the handshake is completed as such, still expecting the remote
+    /// to identify with `remote_id`. The fallback costs an additional roundtrip
+    /// and is only supported by remotes configured with [`NoiseConfig::ik_listener`].
     pub fn ik_dialer(
         dh_keys: AuthenticKeypair<C>,
         remote_id: identity::PublicKey,
@@ -163,6 +224,8 @@ where
             dh_keys,
             params: C::params_ik(),
             legacy: LegacyConfig::default(),
+            payload: HandshakePayload::default(),
+            prologue: Vec::new(),
             remote: (remote_dh, remote_id),
             _marker: std::marker::PhantomData
         }
@@ -183,13 +246,15 @@ where
 
     fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
         let session = self.params.into_builder()
+            .prologue(&self.prologue)
             .local_private_key(self.dh_keys.secret().as_ref())
             .build_responder()
             .map_err(NoiseError::from);
-        handshake::rt1_responder(socket, session,
+        handshake::rt1_responder_with_payload(socket, session,
             self.dh_keys.into_identity(),
             IdentityExchange::Mutual,
-            self.legacy)
+            self.legacy,
+            self.payload)
     }
 }
 
@@ -205,13 +270,15 @@ where
 
     fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
         let session = self.params.into_builder()
+            .prologue(&self.prologue)
             .local_private_key(self.dh_keys.secret().as_ref())
             .build_initiator()
             .map_err(NoiseError::from);
-        handshake::rt1_initiator(socket, session,
+        handshake::rt1_initiator_with_payload(socket, session,
                                  self.dh_keys.into_identity(),
                                  IdentityExchange::Mutual,
-                                 self.legacy)
+                                 self.legacy,
+                                 self.payload)
     }
 }
 
@@ -229,13 +296,15 @@ where
 
     fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
         let session = self.params.into_builder()
+            .prologue(&self.prologue)
             .local_private_key(self.dh_keys.secret().as_ref())
             .build_responder()
             .map_err(NoiseError::from);
-        handshake::rt15_responder(socket, session,
+        handshake::rt15_responder_with_payload(socket, session,
             self.dh_keys.into_identity(),
             IdentityExchange::Mutual,
-            self.legacy)
+            self.legacy,
+            self.payload)
     }
 }
 
@@ -251,13 +320,67 @@ where
 
     fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
         let session = self.params.into_builder()
+            .prologue(&self.prologue)
+            .local_private_key(self.dh_keys.secret().as_ref())
+            .build_initiator()
+            .map_err(NoiseError::from);
+        handshake::rt15_initiator_with_payload(socket, session,
+            self.dh_keys.into_identity(),
+            IdentityExchange::Mutual,
+            self.legacy,
+            self.payload)
+    }
+}
+
+// Handshake pattern XXhfs //////////////////////////////////////////////////
+
+#[cfg(feature = "pq-hybrid")]
+impl<T, C> InboundUpgrade<T> for NoiseConfig<XXhfs, C>
+where
+    NoiseConfig<XXhfs, C>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let session = self.params.into_builder()
+            .prologue(&self.prologue)
+            .local_private_key(self.dh_keys.secret().as_ref())
+            .build_responder()
+            .map_err(NoiseError::from);
+        handshake::rt15_responder_with_payload(socket, session,
+            self.dh_keys.into_identity(),
+            IdentityExchange::Mutual,
+            self.legacy,
+            self.payload)
+    }
+}
+
+#[cfg(feature = "pq-hybrid")]
+impl<T, C> OutboundUpgrade<T> for NoiseConfig<XXhfs, C>
+where
+    NoiseConfig<XXhfs, C>: UpgradeInfo,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    C: Protocol<C> + AsRef<[u8]> + Zeroize + Send + 'static,
+{
+    type Output = (RemoteIdentity<C>, NoiseOutput<T>);
+    type Error = NoiseError;
+    type Future = Handshake<T, C>;
+
+    fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
+        let session = self.params.into_builder()
+            .prologue(&self.prologue)
             .local_private_key(self.dh_keys.secret().as_ref())
             .build_initiator()
             .map_err(NoiseError::from);
-        handshake::rt15_initiator(socket, session,
+        handshake::rt15_initiator_with_payload(socket, session,
             self.dh_keys.into_identity(),
             IdentityExchange::Mutual,
-            self.legacy)
+            self.legacy,
+            self.payload)
     }
 }
 
@@ -275,13 +398,20 @@ where
 
     fn upgrade_inbound(self, socket: T, _: Self::Info) -> Self::Future {
         let session = self.params.into_builder()
+            .prologue(&self.prologue)
+            .local_private_key(self.dh_keys.secret().as_ref())
+            .build_responder()
+            .map_err(NoiseError::from);
+        let fallback = C::params_xx().into_builder()
+            .prologue(&self.prologue)
             .local_private_key(self.dh_keys.secret().as_ref())
             .build_responder()
             .map_err(NoiseError::from);
-        handshake::rt1_responder(socket, session,
+        handshake::rt1_responder_with_fallback(socket, session, fallback,
             self.dh_keys.into_identity(),
             IdentityExchange::Receive,
-            self.legacy)
+            self.legacy,
+            self.payload)
     }
 }
 
@@ -297,14 +427,21 @@ where
 
     fn upgrade_outbound(self, socket: T, _: Self::Info) -> Self::Future {
         let session = self.params.into_builder()
+            .prologue(&self.prologue)
             .local_private_key(self.dh_keys.secret().as_ref())
             .remote_public_key(self.remote.0.as_ref())
             .build_initiator()
             .map_err(NoiseError::from);
-        handshake::rt1_initiator(socket, session,
+        let fallback = C::params_xx().into_builder()
+            .prologue(&self.prologue)
+            .local_private_key(self.dh_keys.secret().as_ref())
+            .build_initiator()
+            .map_err(NoiseError::from);
+        handshake::rt1_initiator_with_fallback(socket, session, fallback,
             self.dh_keys.into_identity(),
-            IdentityExchange::Send { remote: self.remote.1 },
-            self.legacy)
+            self.remote.1,
+            self.legacy,
+            self.payload)
     }
 }
 
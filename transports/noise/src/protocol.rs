@@ -52,6 +52,13 @@ pub enum IX {}
 #[derive(Debug, Clone)]
 pub enum XX {}
 
+/// Type tag for the hybrid XXhfs handshake pattern, i.e. the XX handshake
+/// pattern with an additional key encapsulation for forward secrecy
+/// against quantum adversaries.
+#[cfg(feature = "pq-hybrid")]
+#[derive(Debug, Clone)]
+pub enum XXhfs {}
+
 /// A Noise protocol over DH keys of type `C`. The choice of `C` determines the
 /// protocol parameters for each handshake pattern.
 pub trait Protocol<C> {
@@ -212,7 +219,8 @@ impl<T: AsRef<[u8]>> AsRef<[u8]> for PublicKey<T> {
 /// Custom `snow::CryptoResolver` which delegates to either the
 /// `RingResolver` on native or the `DefaultResolver` on wasm
 /// for hash functions and symmetric ciphers, while using x25519-dalek
-/// for Curve25519 DH.
+/// for Curve25519 DH. Key encapsulation mechanisms of hybrid handshakes
+/// are always resolved by the `DefaultResolver`.
 struct Resolver;
 
 impl snow::resolvers::CryptoResolver for Resolver {
@@ -249,6 +257,11 @@ impl snow::resolvers::CryptoResolver for Resolver {
             snow::resolvers::RingResolver.resolve_cipher(choice)
         }
     }
+
+    #[cfg(feature = "pq-hybrid")]
+    fn resolve_kem(&self, choice: &snow::params::KemChoice) -> Option<Box<dyn snow::types::Kem>> {
+        snow::resolvers::DefaultResolver.resolve_kem(choice)
+    }
 }
 
 /// Wrapper around a CSPRNG to implement `snow::Random` trait for.
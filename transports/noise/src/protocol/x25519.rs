@@ -26,7 +26,7 @@
 use crate::{NoiseConfig, NoiseError, Protocol, ProtocolParams};
 use curve25519_dalek::edwards::CompressedEdwardsY;
 use lazy_static::lazy_static;
-use libp2p_core::UpgradeInfo;
+use libp2p_core::{PeerId, UpgradeInfo};
 use libp2p_core::{identity, identity::ed25519};
 use rand::Rng;
 use sha2::{Sha512, Digest};
@@ -95,6 +95,25 @@ impl<R> UpgradeInfo for NoiseConfig<IK, X25519, R> {
     }
 }
 
+impl NoiseConfig<IK, X25519, (PublicKey<X25519>, identity::PublicKey)> {
+    /// Create a new `NoiseConfig` for the `IK` handshake pattern (initiator side),
+    /// deriving the public identity key and static DH public key of the remote
+    /// from its `PeerId`.
+    ///
+    /// This is possible if the `PeerId` embeds an Ed25519 public key and the
+    /// remote uses the static DH keypair [derived](Keypair::from_identity) from
+    /// its identity keypair. Otherwise the handshake falls back to `XX`, as
+    /// described for [`NoiseConfig::ik_dialer`].
+    ///
+    /// Returns `None` if the `PeerId` does not embed an Ed25519 public key.
+    pub fn ik_dialer_for_peer(dh_keys: AuthenticKeypair<X25519>, peer_id: &PeerId) -> Option<Self> {
+        let pk = peer_id.as_dalek_pubkey().ok()?;
+        let pk = ed25519::PublicKey::decode(pk.as_bytes()).ok()?;
+        let remote_dh = PublicKey::from_ed25519(&pk);
+        Some(NoiseConfig::ik_dialer(dh_keys, identity::PublicKey::Ed25519(pk), remote_dh))
+    }
+}
+
 /// Legacy Noise protocol for X25519.
 ///
 /// **Note**: This `Protocol` provides no configuration that
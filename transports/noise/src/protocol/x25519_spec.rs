@@ -31,6 +31,14 @@ use zeroize::Zeroize;
 
 use super::{*, x25519::X25519};
 
+#[cfg(feature = "pq-hybrid")]
+lazy_static::lazy_static! {
+    static ref PARAMS_XX_HFS: ProtocolParams = "Noise_XXhfs_25519+Kyber1024_ChaChaPoly_SHA256"
+        .parse()
+        .map(ProtocolParams)
+        .expect("Invalid protocol name");
+}
+
 /// Prefix of static key signatures for domain separation.
 const STATIC_KEY_DOMAIN: &str = "noise-libp2p-static-key:";
 
@@ -98,6 +106,41 @@ impl<R> UpgradeInfo for NoiseConfig<IK, X25519Spec, R> {
     }
 }
 
+/// **Note**: This is not a standardised upgrade. Peers not supporting it
+/// can be reached by offering it alongside the `XX` upgrade, e.g. with
+/// [`SelectUpgrade`](libp2p_core::upgrade::SelectUpgrade).
+#[cfg(feature = "pq-hybrid")]
+impl UpgradeInfo for NoiseConfig<XXhfs, X25519Spec> {
+    type Info = &'static [u8];
+    type InfoIter = std::iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once(b"/noise/xxhfs/25519+kyber1024/chachapoly/sha256/0.1.0")
+    }
+}
+
+#[cfg(feature = "pq-hybrid")]
+impl NoiseConfig<XXhfs, X25519Spec> {
+    /// Create a new `NoiseConfig` for the hybrid `XXhfs` handshake pattern,
+    /// combining X25519 with a Kyber1024 key encapsulation.
+    ///
+    /// The session keys are only compromised if both X25519 and Kyber1024
+    /// are broken, protecting recorded traffic against future quantum
+    /// adversaries. Authentication still relies on the X25519 static keys
+    /// and the signatures of the identity keys.
+    pub fn xx_hfs(dh_keys: AuthenticKeypair<X25519Spec>) -> Self {
+        NoiseConfig {
+            dh_keys,
+            params: PARAMS_XX_HFS.clone(),
+            legacy: crate::LegacyConfig::default(),
+            payload: crate::HandshakePayload::default(),
+            prologue: Vec::new(),
+            remote: (),
+            _marker: std::marker::PhantomData
+        }
+    }
+}
+
 /// Noise protocols for X25519 with libp2p-spec compliant signatures.
 ///
 /// **Note**: Only the XX handshake pattern is currently guaranteed to be
@@ -23,7 +23,8 @@ use futures::{future::{self, Either}, prelude::*};
 use libp2p_core::identity;
 use libp2p_core::upgrade::{self, Negotiated, apply_inbound, apply_outbound};
 use libp2p_core::transport::{Transport, ListenerEvent};
-use libp2p_noise::{Keypair, X25519, X25519Spec, NoiseConfig, RemoteIdentity, NoiseError, NoiseOutput};
+use libp2p_noise::{Keypair, X25519, X25519Spec, XX, NoiseConfig, RemoteIdentity, NoiseError, NoiseOutput};
+use libp2p_noise::HandshakePayload;
 use libp2p_tcp::TcpConfig;
 use log::info;
 use quickcheck::QuickCheck;
@@ -70,6 +71,38 @@ fn xx_spec() {
     QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<Message>) -> bool)
 }
 
+#[cfg(feature = "pq-hybrid")]
+#[test]
+fn xx_hfs() {
+    let _ = env_logger::try_init();
+    fn prop(mut messages: Vec<Message>) -> bool {
+        messages.truncate(5);
+        let server_id = identity::Keypair::generate_ed25519();
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = server_id.public();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519Spec>::new().into_authentic(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xx_hfs(server_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519Spec>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xx_hfs(client_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, messages);
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(Vec<Message>) -> bool)
+}
+
 #[test]
 fn xx() {
     let _ = env_logger::try_init();
@@ -176,6 +209,194 @@ fn ik_xx() {
     QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<Message>) -> bool)
 }
 
+#[test]
+fn ik_fallback_xx() {
+    let _ = env_logger::try_init();
+    fn prop(mut messages: Vec<Message>) -> bool {
+        messages.truncate(5);
+        let server_id = identity::Keypair::generate_ed25519();
+        let server_id_public = server_id.public();
+
+        let client_id = identity::Keypair::generate_ed25519();
+        let client_id_public = client_id.public();
+
+        // The client only knows an outdated static DH key of the server.
+        let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+        let outdated_dh_public = Keypair::<X25519>::new().public().clone();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                if endpoint.is_listener() {
+                    Either::Left(apply_inbound(output, NoiseConfig::ik_listener(server_dh)))
+                } else {
+                    Either::Right(apply_outbound(output, NoiseConfig::xx(server_dh),
+                        upgrade::Version::V1))
+                }
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let server_id_public2 = server_id_public.clone();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                if endpoint.is_dialer() {
+                    Either::Left(apply_outbound(output,
+                        NoiseConfig::ik_dialer(client_dh, server_id_public, outdated_dh_public),
+                        upgrade::Version::V1))
+                } else {
+                    Either::Right(apply_inbound(output, NoiseConfig::xx(client_dh)))
+                }
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public2));
+
+        run(server_transport, client_transport, messages);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<Message>) -> bool)
+}
+
+#[test]
+fn ik_for_peer() {
+    let _ = env_logger::try_init();
+    fn prop(mut messages: Vec<Message>) -> bool {
+        messages.truncate(5);
+        let server_id = identity::Keypair::generate_ed25519();
+        let server_id_public = server_id.public();
+        let server_peer_id = server_id_public.clone().into_peer_id();
+
+        let client_id = identity::Keypair::generate_ed25519();
+        let client_id_public = client_id.public();
+
+        let server_dh = Keypair::<X25519>::from_identity(&server_id).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                if endpoint.is_listener() {
+                    Either::Left(apply_inbound(output, NoiseConfig::ik_listener(server_dh)))
+                } else {
+                    Either::Right(apply_outbound(output, NoiseConfig::xx(server_dh),
+                        upgrade::Version::V1))
+                }
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                if endpoint.is_dialer() {
+                    let config = NoiseConfig::ik_dialer_for_peer(client_dh, &server_peer_id)
+                        .expect("Ed25519 peer ID");
+                    Either::Left(apply_outbound(output, config, upgrade::Version::V1))
+                } else {
+                    Either::Right(apply_inbound(output, NoiseConfig::xx(client_dh)))
+                }
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, messages);
+        true
+    }
+    QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<Message>) -> bool)
+}
+
+#[test]
+fn xx_payload() {
+    let _ = env_logger::try_init();
+    let server = payload_config(b"server", b"client");
+    let client = payload_config(b"client", b"server");
+    let (server_result, client_result) = handshake_with_payloads(server, client);
+    let (_, server_output) = server_result.expect("server handshake");
+    let (_, client_output) = client_result.expect("client handshake");
+    assert_eq!(server_output.remote_payload(), b"client");
+    assert_eq!(client_output.remote_payload(), b"server");
+}
+
+#[test]
+fn xx_payload_mismatch() {
+    let _ = env_logger::try_init();
+    let server = payload_config(b"mainnet", b"mainnet");
+    let client = payload_config(b"floonet", b"floonet");
+    let (server_result, client_result) = handshake_with_payloads(server, client);
+    match client_result {
+        Err(upgrade::UpgradeError::Apply(NoiseError::PayloadRejected)) => {}
+        _ => panic!("Expected the client to reject the payload of the server")
+    }
+    assert!(server_result.is_err());
+}
+
+#[test]
+fn xx_peer_filter() {
+    let _ = env_logger::try_init();
+    let server_keys = identity::Keypair::generate_ed25519();
+    let server_id = server_keys.public().into_peer_id();
+    let server_config = || {
+        let dh_keys = Keypair::<X25519Spec>::new().into_authentic(&server_keys).unwrap();
+        NoiseConfig::xx(dh_keys)
+    };
+
+    let mut client = payload_config(b"", b"");
+    client.set_peer_filter(move |p| p == &server_id);
+    let (server_result, client_result) = handshake_with_payloads(server_config(), client);
+    assert!(server_result.is_ok());
+    assert!(client_result.is_ok());
+
+    let mut client = payload_config(b"", b"");
+    client.set_peer_filter(|_| false);
+    let (server_result, client_result) = handshake_with_payloads(server_config(), client);
+    match client_result {
+        Err(upgrade::UpgradeError::Apply(NoiseError::PeerRejected(p))) =>
+            assert_eq!(p, server_keys.public().into_peer_id()),
+        _ => panic!("Expected the client to reject the server")
+    }
+    // The client never sent its identity.
+    assert!(server_result.is_err());
+}
+
+/// Creates an `XX` configuration sending `local` and expecting `remote`
+/// as the payload of the remote.
+fn payload_config(local: &[u8], remote: &'static [u8]) -> NoiseConfig<XX, X25519Spec> {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let dh_keys = Keypair::<X25519Spec>::new().into_authentic(&id_keys).unwrap();
+    let mut config = NoiseConfig::xx(dh_keys);
+    config.set_payload(HandshakePayload::new(local.to_vec()).with_check(move |p| p == remote));
+    config
+}
+
+/// Performs a handshake between a server and a client over TCP, returning the
+/// results of both sides.
+fn handshake_with_payloads(
+    server: NoiseConfig<XX, X25519Spec>,
+    client: NoiseConfig<XX, X25519Spec>,
+) -> (
+    Result<Output<X25519Spec>, upgrade::UpgradeError<NoiseError>>,
+    Result<Output<X25519Spec>, upgrade::UpgradeError<NoiseError>>,
+) {
+    futures::executor::block_on(async {
+        let mut listener = TcpConfig::new()
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let address = listener.try_next()
+            .await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        let server_fut = async {
+            let (upgrade, _) = listener.try_next()
+                .await
+                .expect("some event")
+                .expect("no error")
+                .into_upgrade()
+                .expect("inbound connection");
+            apply_inbound(upgrade.await.expect("no error"), server).await
+        };
+        let client_fut = async {
+            let socket = TcpConfig::new().dial(address).unwrap().await.expect("no error");
+            apply_outbound(socket, client, upgrade::Version::V1).await
+        };
+        future::join(server_fut, client_fut).await
+    })
+}
+
 type Output<C> = (RemoteIdentity<C>, NoiseOutput<Negotiated<Async<TcpStream>>>);
 
 fn run<T, U, I, C>(server_transport: T, client_transport: U, messages: I)
@@ -23,6 +23,11 @@
 //!
 //! Libp2p nodes configured with a pre-shared key can only communicate with other nodes with
 //! the same key.
+//!
+//! To rotate the key of a network without restarting all nodes at once, a node can be
+//! configured with a set of [`PreSharedKeys`]. Outbound connections use the active key,
+//! which can be changed at runtime, while inbound connections are accepted with any key
+//! of the set.
 mod crypt_writer;
 use crypt_writer::CryptWriter;
 use futures::prelude::*;
@@ -42,6 +47,7 @@ use std::{
     num::ParseIntError,
     pin::Pin,
     str::FromStr,
+    sync::{Arc, RwLock},
     task::{Context, Poll},
 };
 
@@ -50,6 +56,11 @@ const NONCE_SIZE: usize = 24;
 const WRITE_BUFFER_SIZE: usize = 1024;
 const FINGERPRINT_SIZE: usize = 16;
 
+/// The first bytes sent by a dialer using multistream-select, i.e. the
+/// length-prefixed multistream-select protocol header, used by default to
+/// identify the key of an inbound connection.
+const MULTISTREAM_HEADER: &[u8] = b"\x13/multistream/1.0.0\n";
+
 /// A pre-shared key, consisting of 32 bytes of random data.
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct PreSharedKey([u8; KEY_SIZE]);
@@ -181,21 +192,129 @@ impl error::Error for KeyParseError {
     }
 }
 
+/// A set of pre-shared keys identified by ids, one of which is active.
+///
+/// The set is shared by all clones, so that keys can be added, removed or
+/// activated at runtime, affecting all subsequent handshakes.
+#[derive(Debug, Clone)]
+pub struct PreSharedKeys {
+    inner: Arc<RwLock<KeySet>>,
+}
+
+#[derive(Debug, Clone)]
+struct KeySet {
+    /// The keys in the order in which they are tried on inbound connections.
+    keys: Vec<(String, PreSharedKey)>,
+    /// The index of the active key in `keys`.
+    active: usize,
+}
+
+impl PreSharedKeys {
+    /// Creates a set with a single, active key.
+    pub fn new(id: impl Into<String>, key: PreSharedKey) -> Self {
+        PreSharedKeys {
+            inner: Arc::new(RwLock::new(KeySet { keys: vec![(id.into(), key)], active: 0 }))
+        }
+    }
+
+    /// Adds a key to the end of the set, replacing the key with the same id, if any.
+    pub fn add(&self, id: impl Into<String>, key: PreSharedKey) {
+        let id = id.into();
+        let mut set = self.inner.write().expect("not poisoned");
+        if let Some(entry) = set.keys.iter_mut().find(|(i, _)| *i == id) {
+            entry.1 = key;
+        } else {
+            set.keys.push((id, key));
+        }
+    }
+
+    /// Removes the key with the given id.
+    ///
+    /// Returns `false` if there is no such key or if it is the active key,
+    /// which cannot be removed.
+    pub fn remove(&self, id: &str) -> bool {
+        let mut set = self.inner.write().expect("not poisoned");
+        match set.keys.iter().position(|(i, _)| i == id) {
+            Some(ix) if ix != set.active => {
+                set.keys.remove(ix);
+                if ix < set.active {
+                    set.active -= 1;
+                }
+                true
+            }
+            _ => false
+        }
+    }
+
+    /// Makes the key with the given id the key used for outbound connections.
+    ///
+    /// Returns `false` if there is no such key.
+    pub fn set_active(&self, id: &str) -> bool {
+        let mut set = self.inner.write().expect("not poisoned");
+        match set.keys.iter().position(|(i, _)| i == id) {
+            Some(ix) => {
+                set.active = ix;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Returns the id of the key used for outbound connections.
+    pub fn active_id(&self) -> String {
+        let set = self.inner.read().expect("not poisoned");
+        set.keys[set.active].0.clone()
+    }
+
+    /// Returns the ids of all keys in the order in which they are tried.
+    pub fn ids(&self) -> Vec<String> {
+        let set = self.inner.read().expect("not poisoned");
+        set.keys.iter().map(|(i, _)| i.clone()).collect()
+    }
+
+    fn snapshot(&self) -> KeySet {
+        self.inner.read().expect("not poisoned").clone()
+    }
+}
+
 /// Private network configuration
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct PnetConfig {
-    /// the PreSharedKey to use for encryption
-    key: PreSharedKey,
+    /// the PreSharedKeys to use for encryption
+    keys: PreSharedKeys,
+    /// the plaintext expected at the start of an inbound connection,
+    /// used to identify the key of the remote
+    probe: Vec<u8>,
 }
 impl PnetConfig {
     pub fn new(key: PreSharedKey) -> Self {
-        Self { key }
+        Self::with_keys(PreSharedKeys::new("default", key))
+    }
+
+    /// Creates a configuration using a set of keys.
+    ///
+    /// If the set contains more than one key, the key of an inbound connection is
+    /// identified by decrypting the first bytes sent by the remote with each key,
+    /// in order, until they match the expected plaintext. By default this is the
+    /// multistream-select header, which is always sent first by the dialer when
+    /// the pnet handshake is followed by a regular upgrade. See [`PnetConfig::with_probe`].
+    pub fn with_keys(keys: PreSharedKeys) -> Self {
+        Self { keys, probe: MULTISTREAM_HEADER.to_vec() }
+    }
+
+    /// Sets the plaintext expected at the start of an inbound connection.
+    pub fn with_probe(mut self, probe: Vec<u8>) -> Self {
+        self.probe = probe;
+        self
     }
 
     /// upgrade a connection to use pre shared key encryption.
     ///
     /// the upgrade works by both sides exchanging 24 byte nonces and then encrypting
-    /// subsequent traffic with XSalsa20
+    /// subsequent traffic with XSalsa20.
+    ///
+    /// Only the active key is used. See [`PnetConfig::handshake_inbound`] for
+    /// accepting connections with any key of a set.
     pub async fn handshake<TSocket>(
         self,
         mut socket: TSocket,
@@ -203,23 +322,96 @@ impl PnetConfig {
     where
         TSocket: AsyncRead + AsyncWrite + Send + Unpin + 'static,
     {
-        trace!("exchanging nonces");
-        let mut local_nonce = [0u8; NONCE_SIZE];
-        let mut remote_nonce = [0u8; NONCE_SIZE];
-        rand::thread_rng().fill_bytes(&mut local_nonce);
-        socket
-            .write_all(&local_nonce)
-            .await
-            .map_err(PnetError::HandshakeError)?;
+        let (local_nonce, remote_nonce) = exchange_nonces(&mut socket).await?;
+        let set = self.keys.snapshot();
+        let (id, key) = set.keys[set.active].clone();
+        trace!("setting up ciphers");
+        let write_cipher = XSalsa20::new(&key.0.into(), &local_nonce.into());
+        let read_cipher = XSalsa20::new(&key.0.into(), &remote_nonce.into());
+        Ok(PnetOutput::new(socket, write_cipher, read_cipher, id, Vec::new()))
+    }
+
+    /// upgrade an outbound connection, using the active key.
+    pub async fn handshake_outbound<TSocket>(
+        self,
+        socket: TSocket,
+    ) -> Result<PnetOutput<TSocket>, PnetError>
+    where
+        TSocket: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        self.handshake(socket).await
+    }
+
+    /// upgrade an inbound connection, accepting any key of the set.
+    ///
+    /// With more than one key, the first bytes sent by the remote are read
+    /// to identify its key, as described in [`PnetConfig::with_keys`].
+    pub async fn handshake_inbound<TSocket>(
+        self,
+        mut socket: TSocket,
+    ) -> Result<PnetOutput<TSocket>, PnetError>
+    where
+        TSocket: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let set = self.keys.snapshot();
+        if set.keys.len() == 1 || self.probe.is_empty() {
+            return self.handshake(socket).await
+        }
+        let (local_nonce, remote_nonce) = exchange_nonces(&mut socket).await?;
+        let mut ciphertext = vec![0u8; self.probe.len()];
         socket
-            .read_exact(&mut remote_nonce)
+            .read_exact(&mut ciphertext)
             .await
             .map_err(PnetError::HandshakeError)?;
-        trace!("setting up ciphers");
-        let write_cipher = XSalsa20::new(&self.key.0.into(), &local_nonce.into());
-        let read_cipher = XSalsa20::new(&self.key.0.into(), &remote_nonce.into());
-        Ok(PnetOutput::new(socket, write_cipher, read_cipher))
+        let (id, key, read_cipher, prefix) = match_key(&set.keys, &remote_nonce, &ciphertext, &self.probe)
+            .ok_or(PnetError::NoMatchingKey)?;
+        trace!("identified key {}", id);
+        let write_cipher = XSalsa20::new(&key.0.into(), &local_nonce.into());
+        Ok(PnetOutput::new(socket, write_cipher, read_cipher, id, prefix))
+    }
+}
+
+/// Exchanges nonces with the remote, returning the local and remote nonce.
+async fn exchange_nonces<TSocket>(
+    socket: &mut TSocket,
+) -> Result<([u8; NONCE_SIZE], [u8; NONCE_SIZE]), PnetError>
+where
+    TSocket: AsyncRead + AsyncWrite + Unpin,
+{
+    trace!("exchanging nonces");
+    let mut local_nonce = [0u8; NONCE_SIZE];
+    let mut remote_nonce = [0u8; NONCE_SIZE];
+    rand::thread_rng().fill_bytes(&mut local_nonce);
+    socket
+        .write_all(&local_nonce)
+        .await
+        .map_err(PnetError::HandshakeError)?;
+    socket
+        .read_exact(&mut remote_nonce)
+        .await
+        .map_err(PnetError::HandshakeError)?;
+    Ok((local_nonce, remote_nonce))
+}
+
+/// Finds the first key decrypting the given ciphertext to the expected plaintext.
+///
+/// Returns the id and key together with the read cipher, positioned after the
+/// ciphertext, and the decrypted plaintext.
+fn match_key(
+    keys: &[(String, PreSharedKey)],
+    remote_nonce: &[u8; NONCE_SIZE],
+    ciphertext: &[u8],
+    probe: &[u8],
+) -> Option<(String, PreSharedKey, XSalsa20, Vec<u8>)> {
+    for (id, key) in keys {
+        let mut cipher = XSalsa20::new(&key.0.into(), &(*remote_nonce).into());
+        let mut plaintext = ciphertext.to_vec();
+        cipher.apply_keystream(&mut plaintext);
+        if plaintext == probe {
+            return Some((id.clone(), *key, cipher, plaintext))
+        }
     }
+    None
 }
 
 /// The result of a handshake. This implements AsyncRead and AsyncWrite and can therefore
@@ -229,17 +421,36 @@ pub struct PnetOutput<S> {
     #[pin]
     inner: CryptWriter<S>,
     read_cipher: XSalsa20,
+    /// the id of the key used for the connection
+    key_id: String,
+    /// decrypted data read during the handshake, returned before any further data
+    read_prefix: Vec<u8>,
 }
 
 impl<S: AsyncRead + AsyncWrite> PnetOutput<S> {
-    fn new(inner: S, write_cipher: XSalsa20, read_cipher: XSalsa20) -> Self {
+    fn new(
+        inner: S,
+        write_cipher: XSalsa20,
+        read_cipher: XSalsa20,
+        key_id: String,
+        read_prefix: Vec<u8>,
+    ) -> Self {
         Self {
             inner: CryptWriter::with_capacity(WRITE_BUFFER_SIZE, inner, write_cipher),
             read_cipher,
+            key_id,
+            read_prefix,
         }
     }
 }
 
+impl<S> PnetOutput<S> {
+    /// Returns the id of the pre-shared key used for the connection.
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+}
+
 impl<S: AsyncRead + AsyncWrite> AsyncRead for PnetOutput<S> {
     fn poll_read(
         self: Pin<&mut Self>,
@@ -247,6 +458,12 @@ impl<S: AsyncRead + AsyncWrite> AsyncRead for PnetOutput<S> {
         buf: &mut [u8],
     ) -> Poll<Result<usize, io::Error>> {
         let this = self.project();
+        if !this.read_prefix.is_empty() {
+            let n = std::cmp::min(this.read_prefix.len(), buf.len());
+            buf[..n].copy_from_slice(&this.read_prefix[..n]);
+            this.read_prefix.drain(..n);
+            return Poll::Ready(Ok(n));
+        }
         let result = this.inner.get_pin_mut().poll_read(cx, buf);
         if let Poll::Ready(Ok(size)) = &result {
             trace!("read {} bytes", size);
@@ -282,6 +499,8 @@ pub enum PnetError {
     HandshakeError(IoError),
     /// I/O error.
     IoError(IoError),
+    /// None of the pre-shared keys matches the key of the remote.
+    NoMatchingKey,
 }
 
 impl From<IoError> for PnetError {
@@ -296,6 +515,7 @@ impl error::Error for PnetError {
         match *self {
             PnetError::HandshakeError(ref err) => Some(err),
             PnetError::IoError(ref err) => Some(err),
+            PnetError::NoMatchingKey => None,
         }
     }
 }
@@ -306,6 +526,7 @@ impl fmt::Display for PnetError {
         match self {
             PnetError::HandshakeError(e) => write!(f, "Handshake error: {}", e),
             PnetError::IoError(e) => write!(f, "I/O error: {}", e),
+            PnetError::NoMatchingKey => write!(f, "No matching pre-shared key"),
         }
     }
 }
@@ -358,6 +579,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn key_set() {
+        let keys = PreSharedKeys::new("a", PreSharedKey([1; KEY_SIZE]));
+        keys.add("b", PreSharedKey([2; KEY_SIZE]));
+        assert_eq!(keys.ids(), vec!["a", "b"]);
+        assert!(!keys.remove("a"));
+        assert!(keys.set_active("b"));
+        assert!(keys.remove("a"));
+        assert_eq!(keys.active_id(), "b");
+        assert!(!keys.set_active("a"));
+    }
+
+    #[test]
+    fn match_key_identifies_remote_key() {
+        let keys = vec![
+            ("old".to_string(), PreSharedKey([1; KEY_SIZE])),
+            ("new".to_string(), PreSharedKey([2; KEY_SIZE])),
+        ];
+        let nonce = [7; NONCE_SIZE];
+        let mut ciphertext = MULTISTREAM_HEADER.to_vec();
+        XSalsa20::new(&keys[1].1 .0.into(), &nonce.into()).apply_keystream(&mut ciphertext);
+
+        let (id, _, _, plaintext) = match_key(&keys, &nonce, &ciphertext, MULTISTREAM_HEADER).unwrap();
+        assert_eq!(id, "new");
+        assert_eq!(plaintext, MULTISTREAM_HEADER);
+        assert!(match_key(&keys[..1], &nonce, &ciphertext, MULTISTREAM_HEADER).is_none());
+    }
+
     #[test]
     fn fingerprint() {
         // checked against go-ipfs output
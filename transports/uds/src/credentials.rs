@@ -0,0 +1,115 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Credentials of the process on the other end of a Unix domain socket.
+
+use std::{io, os::unix::io::{AsRawFd, RawFd}};
+
+/// The credentials of the process on the other end of a Unix domain socket,
+/// as captured by the kernel when the connection was established.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    /// The effective user ID of the process.
+    pub uid: u32,
+    /// The effective group ID of the process.
+    pub gid: u32,
+    /// The process ID, if the platform reports it.
+    pub pid: Option<i32>,
+}
+
+/// Returns the credentials of the process on the other end of the given socket.
+///
+/// Uses `SO_PEERCRED` on Linux and Android and `getpeereid` (i.e. `LOCAL_PEERCRED`)
+/// on the BSDs and macOS, where the process ID is not available.
+pub fn peer_credentials(socket: &impl AsRawFd) -> io::Result<PeerCredentials> {
+    from_fd(socket.as_raw_fd())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn from_fd(fd: RawFd) -> io::Result<PeerCredentials> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // Safety: `cred` and `len` are valid for writes and `len` is the size of `cred`.
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(PeerCredentials { uid: cred.uid, gid: cred.gid, pid: Some(cred.pid) })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn from_fd(fd: RawFd) -> io::Result<PeerCredentials> {
+    let mut uid = 0;
+    let mut gid = 0;
+    // Safety: `uid` and `gid` are valid for writes.
+    let ret = unsafe { libc::getpeereid(fd, &mut uid, &mut gid) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(PeerCredentials { uid, gid, pid: None })
+}
+
+/// Restricts the processes permitted to connect to a listener.
+///
+/// An empty allow-list permits every process.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AllowList {
+    uids: Vec<u32>,
+    gids: Vec<u32>,
+}
+
+impl AllowList {
+    pub(crate) fn allow_uid(&mut self, uid: u32) {
+        if !self.uids.contains(&uid) {
+            self.uids.push(uid)
+        }
+    }
+
+    pub(crate) fn allow_gid(&mut self, gid: u32) {
+        if !self.gids.contains(&gid) {
+            self.gids.push(gid)
+        }
+    }
+
+    /// Whether the allow-list permits every process.
+    pub(crate) fn is_open(&self) -> bool {
+        self.uids.is_empty() && self.gids.is_empty()
+    }
+
+    /// Whether a process with the given credentials is permitted to connect.
+    pub(crate) fn permits(&self, creds: &PeerCredentials) -> bool {
+        self.is_open() || self.uids.contains(&creds.uid) || self.gids.contains(&creds.gid)
+    }
+}
+
+/// Returns the effective user ID of the current process.
+pub(crate) fn current_uid() -> u32 {
+    // Safety: `geteuid` is always successful.
+    unsafe { libc::geteuid() }
+}
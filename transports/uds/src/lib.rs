@@ -30,6 +30,13 @@
 //!
 //! The `UdsConfig` structs implements the `Transport` trait of the `core` library. See the
 //! documentation of `core` and of libp2p in general to learn how to use the `Transport` trait.
+//!
+//! # Peer credentials
+//!
+//! The credentials (user, group and process ID) of the process on the other end of a
+//! connection can be read with [`peer_credentials`]. Listeners can be restricted to
+//! processes of certain users or groups, e.g. with `UdsConfig::allow_current_user`,
+//! in which case connections from other processes are closed right after being accepted.
 
 #![cfg(all(unix, not(target_os = "emscripten")))]
 #![cfg_attr(docsrs, doc(cfg(all(unix, not(target_os = "emscripten")))))]
@@ -44,6 +51,11 @@ use libp2p_core::{
 use log::debug;
 use std::{io, path::PathBuf};
 
+mod credentials;
+
+use credentials::AllowList;
+pub use credentials::{PeerCredentials, peer_credentials};
+
 macro_rules! codegen {
     ($feature_name:expr, $uds_config:ident, $build_listener:expr, $unix_stream:ty, $($mut_or_not:tt)*) => {
 
@@ -51,12 +63,39 @@ macro_rules! codegen {
 #[cfg_attr(docsrs, doc(cfg(feature = $feature_name)))]
 #[derive(Debug, Clone)]
 pub struct $uds_config {
+    allow_list: AllowList,
 }
 
 impl $uds_config {
     /// Creates a new configuration object for Unix domain sockets.
     pub fn new() -> $uds_config {
-        $uds_config {}
+        $uds_config {
+            allow_list: AllowList::default(),
+        }
+    }
+
+    /// Permits processes running as the given user to connect to listeners.
+    ///
+    /// Once any user or group is permitted, connections from processes which
+    /// are not permitted are rejected.
+    pub fn allow_uid(mut self, uid: u32) -> Self {
+        self.allow_list.allow_uid(uid);
+        self
+    }
+
+    /// Permits processes running with the given group to connect to listeners.
+    ///
+    /// Once any user or group is permitted, connections from processes which
+    /// are not permitted are rejected.
+    pub fn allow_gid(mut self, gid: u32) -> Self {
+        self.allow_list.allow_gid(gid);
+        self
+    }
+
+    /// Permits processes running as the effective user of the current process
+    /// to connect to listeners.
+    pub fn allow_current_user(self) -> Self {
+        self.allow_uid(credentials::current_uid())
     }
 }
 
@@ -69,6 +108,7 @@ impl Transport for $uds_config {
 
     fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
         if let Ok(path) = multiaddr_to_path(&addr) {
+            let allow_list = self.allow_list;
             Ok(async move { $build_listener(&path).await }
                 .map_ok(move |listener| {
                     stream::once({
@@ -79,18 +119,34 @@ impl Transport for $uds_config {
                         }
                     }).chain(stream::unfold(listener, move |$($mut_or_not)* listener| {
                         let addr = addr.clone();
+                        let allow_list = allow_list.clone();
                         async move {
-                            let (stream, _) = match listener.accept().await {
-                                Ok(v) => v,
-                                Err(err) => return Some((Err(err), listener))
-                            };
-                            debug!("incoming connection on {}", addr);
-                            let event = ListenerEvent::Upgrade {
-                                upgrade: future::ok(stream),
-                                local_addr: addr.clone(),
-                                remote_addr: addr.clone()
-                            };
-                            Some((Ok(event), listener))
+                            loop {
+                                let (stream, _) = match listener.accept().await {
+                                    Ok(v) => v,
+                                    Err(err) => return Some((Err(err), listener))
+                                };
+                                if !allow_list.is_open() {
+                                    match peer_credentials(&stream) {
+                                        Ok(creds) if allow_list.permits(&creds) => {}
+                                        Ok(creds) => {
+                                            debug!("rejecting connection on {} from {:?}", addr, creds);
+                                            continue
+                                        }
+                                        Err(err) => {
+                                            debug!("rejecting connection on {} without credentials: {}", addr, err);
+                                            continue
+                                        }
+                                    }
+                                }
+                                debug!("incoming connection on {}", addr);
+                                let event = ListenerEvent::Upgrade {
+                                    upgrade: future::ok(stream),
+                                    local_addr: addr.clone(),
+                                    remote_addr: addr.clone()
+                                };
+                                return Some((Ok(event), listener))
+                            }
                         }
                     }))
                 })
@@ -161,7 +217,7 @@ fn multiaddr_to_path(addr: &Multiaddr) -> Result<PathBuf, ()> {
 
 #[cfg(all(test, feature = "async-std"))]
 mod tests {
-    use super::{multiaddr_to_path, UdsConfig};
+    use super::{multiaddr_to_path, peer_credentials, UdsConfig};
     use futures::{channel::oneshot, prelude::*};
     use std::{self, borrow::Cow, path::Path};
     use libp2p_core::{Transport, multiaddr::{Protocol, Multiaddr}};
@@ -222,6 +278,40 @@ mod tests {
         });
     }
 
+    #[test]
+    fn peer_credentials_allow_list() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let uid = unsafe { libc::geteuid() };
+
+        for (config, permitted) in vec![
+            (UdsConfig::new().allow_current_user(), true),
+            (UdsConfig::new().allow_uid(uid.wrapping_add(1)), false),
+        ] {
+            let socket = temp_dir.path().join(format!("socket-{}", permitted));
+            let addr = Multiaddr::from(Protocol::Unix(Cow::Owned(socket.to_string_lossy().into_owned())));
+            let mut listener = config.listen_on(addr.clone()).unwrap();
+
+            async_std::task::block_on(async move {
+                listener.try_next().await.unwrap().expect("some event").into_new_address().expect("listen address");
+                async_std::task::spawn(async move {
+                    while let Some(event) = listener.try_next().await.unwrap() {
+                        if let Some((sock, _)) = event.into_upgrade() {
+                            let mut sock = sock.await.unwrap();
+                            let creds = peer_credentials(&sock).unwrap();
+                            assert_eq!(creds.uid, uid);
+                            sock.write_all(&[1]).await.unwrap();
+                        }
+                    }
+                });
+
+                let mut socket = UdsConfig::new().dial(addr).unwrap().await.unwrap();
+                let mut buf = Vec::new();
+                socket.read_to_end(&mut buf).await.unwrap_or(0);
+                assert_eq!(buf.is_empty(), !permitted);
+            });
+        }
+    }
+
     #[test]
     #[ignore]       // TODO: for the moment unix addresses fail to parse
     fn larger_addr_denied() {
@@ -31,11 +31,16 @@
 //! Call `new()` with a JavaScript object that implements the interface described in the `ffi`
 //! module.
 //!
+//! With the `websocket` feature enabled, `ExtTransport::websocket()` provides a transport built on
+//! the browser's `WebSocket` API that doesn't require any JavaScript code on the user's side.
+//!
 
 use futures::{prelude::*, future::Ready};
 use libp2p_core::{transport::ListenerEvent, transport::TransportError, Multiaddr, Transport};
 use parity_send_wrapper::SendWrapper;
 use std::{collections::VecDeque, error, fmt, io, mem, pin::Pin, task::Context, task::Poll};
+#[cfg(feature = "websocket")]
+use std::{cmp, time::Duration};
 use wasm_bindgen::{JsCast, prelude::*};
 use wasm_bindgen_futures::JsFuture;
 
@@ -133,6 +138,11 @@ pub mod ffi {
     extern "C" {
         /// Returns a `Transport` implemented using websockets.
         pub fn websocket_transport() -> Transport;
+
+        /// Returns a `Transport` implemented using websockets, configured with the given object.
+        ///
+        /// See [`WebSocketConfig`](crate::WebSocketConfig) for the supported fields.
+        pub fn websocket_transport_with_config(config: &JsValue) -> Transport;
     }
 }
 
@@ -150,6 +160,90 @@ impl ExtTransport {
     }
 }
 
+#[cfg(feature = "websocket")]
+impl ExtTransport {
+    /// Creates a new `ExtTransport` that dials `/ws` and `/wss` addresses with the browser's
+    /// `WebSocket` API.
+    ///
+    /// Listening is not supported.
+    pub fn websocket(config: WebSocketConfig) -> Self {
+        ExtTransport::new(ffi::websocket_transport_with_config(&config.to_js()))
+    }
+}
+
+/// Configuration of the WebSocket transport created by [`ExtTransport::websocket`].
+#[cfg(feature = "websocket")]
+#[derive(Debug, Clone)]
+pub struct WebSocketConfig {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_frame_size: usize,
+}
+
+#[cfg(feature = "websocket")]
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            max_frame_size: 64 * 1024,
+        }
+    }
+}
+
+#[cfg(feature = "websocket")]
+impl WebSocketConfig {
+    /// Sets the maximum number of attempts to open the WebSocket when dialing, including the
+    /// first one.
+    ///
+    /// An attempt is only repeated if the WebSocket was closed with a close code indicating that
+    /// the server may accept it later, e.g. `1006` (abnormal closure) or `1013` (try again later).
+    /// Only opening the WebSocket is retried, a WebSocket closed after it has been opened is never
+    /// reopened.
+    ///
+    /// Defaults to `3`. A value of `0` is treated as `1`, i.e. no retries.
+    pub fn with_max_attempts(mut self, n: u32) -> Self {
+        self.max_attempts = cmp::max(n, 1);
+        self
+    }
+
+    /// Sets the delay before the first retry. The delay doubles after every retry.
+    pub fn with_initial_backoff(mut self, d: Duration) -> Self {
+        self.initial_backoff = d;
+        self
+    }
+
+    /// Sets the upper bound of the delay between two attempts.
+    pub fn with_max_backoff(mut self, d: Duration) -> Self {
+        self.max_backoff = d;
+        self
+    }
+
+    /// Sets the maximum size of a binary frame. Larger writes are split into multiple frames.
+    ///
+    /// Defaults to 64 KiB. A value of `0` is treated as `1`.
+    pub fn with_max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = cmp::max(size, 1);
+        self
+    }
+
+    /// Builds the object passed to `ffi::websocket_transport_with_config`.
+    fn to_js(&self) -> JsValue {
+        let obj = js_sys::Object::new();
+        let set = |key: &str, value: f64| {
+            js_sys::Reflect::set(&obj, &JsValue::from_str(key), &JsValue::from_f64(value))
+                .expect("setting a property of a plain object never fails");
+        };
+        set("max_attempts", f64::from(self.max_attempts));
+        set("initial_backoff_ms", self.initial_backoff.as_millis() as f64);
+        set("max_backoff_ms", self.max_backoff.as_millis() as f64);
+        set("max_frame_size", self.max_frame_size as f64);
+        obj.into()
+    }
+}
+
 impl fmt::Debug for ExtTransport {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("ExtTransport").finish()
@@ -502,6 +596,35 @@ fn js_value_to_addr(addr: &JsValue) -> Result<Multiaddr, JsErr> {
 /// Error that can be generated by the `ExtTransport`.
 pub struct JsErr(SendWrapper<JsValue>);
 
+impl JsErr {
+    /// If the error reports a closed WebSocket, returns the close code.
+    ///
+    /// The error must be an instance of `Error` whose `name` property has been set to
+    /// `"WebSocketCloseError"` and with a numeric `code` property, as produced by the transport
+    /// of the `websocket` feature.
+    pub fn close_code(&self) -> Option<u16> {
+        let err = self.0.dyn_ref::<js_sys::Error>()?;
+        if err.name() != "WebSocketCloseError" {
+            return None
+        }
+        js_sys::Reflect::get(err, &JsValue::from_str("code")).ok()?
+            .as_f64()
+            .map(|code| code as u16)
+    }
+
+    /// Returns the `io::ErrorKind` matching the error.
+    fn kind(&self) -> io::ErrorKind {
+        match self.close_code() {
+            Some(1001) => io::ErrorKind::ConnectionAborted,
+            Some(1006) => io::ErrorKind::ConnectionReset,
+            Some(1002) | Some(1003) | Some(1007) | Some(1009) => io::ErrorKind::InvalidData,
+            Some(1008) => io::ErrorKind::PermissionDenied,
+            Some(1012) | Some(1013) => io::ErrorKind::ConnectionRefused,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
 impl From<JsValue> for JsErr {
     fn from(val: JsValue) -> JsErr {
         JsErr(SendWrapper::new(val))
@@ -516,7 +639,7 @@ impl From<libp2p_core::multiaddr::Error> for JsErr {
 
 impl From<JsErr> for io::Error {
     fn from(err: JsErr) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, err.to_string())
+        io::Error::new(err.kind(), err.to_string())
     }
 }
 
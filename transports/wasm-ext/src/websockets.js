@@ -18,9 +18,24 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-export const websocket_transport = () => {
+export const websocket_transport = () => websocket_transport_with_config({});
+
+// Same as `websocket_transport`, but with the given configuration. All fields are optional:
+//
+// - `max_attempts`: number of attempts to open the WebSocket, including the first one.
+// - `initial_backoff_ms`: delay before the first retry, doubled after every retry.
+// - `max_backoff_ms`: upper bound of the delay between two attempts.
+// - `max_frame_size`: maximum size of a single binary frame we send.
+export const websocket_transport_with_config = (config) => {
+	config = {
+		max_attempts: Math.max(config.max_attempts || 1, 1),
+		initial_backoff_ms: config.initial_backoff_ms || 500,
+		max_backoff_ms: config.max_backoff_ms || 10000,
+		max_frame_size: config.max_frame_size || 64 * 1024,
+	};
+
 	return {
-		dial: dial,
+		dial: (addr) => dial(addr, config),
 		listen_on: (addr) => {
 			let err = new Error("Listening on WebSockets is not possible from within a browser");
 			err.name = "NotSupportedError";
@@ -50,26 +65,60 @@ const multiaddr_to_ws = (addr) => {
 	throw err;
 }
 
-// Attempt to dial a multiaddress.
-const dial = (addr) => {
-	let ws = new WebSocket(multiaddr_to_ws(addr));
+// Close codes for which opening the WebSocket again later might succeed: going away (1001),
+// abnormal closure (1006), internal error (1011), service restart (1012) and try again later
+// (1013).
+const TRANSIENT_CLOSE_CODES = [1001, 1006, 1011, 1012, 1013];
+
+// Turns a `CloseEvent` into an `Error` that carries the close code and reason. The Rust side
+// recognizes these errors by their `name`.
+const close_error = (ev) => {
+	let err = new Error("WebSocket closed with code " + ev.code + (ev.reason ? ": " + ev.reason : ""));
+	err.name = "WebSocketCloseError";
+	err.code = ev.code;
+	err.reason = ev.reason;
+	return err;
+}
+
+// Attempt to dial a multiaddress, opening the WebSocket again with exponential backoff as long as
+// it fails with a transient close code and `config.max_attempts` isn't reached.
+const dial = (addr, config) => {
+	let url = multiaddr_to_ws(addr);
+
+	const attempt = (n, backoff) => open(url, config).catch((err) => {
+		if (n >= config.max_attempts || err.name != "WebSocketCloseError" || !TRANSIENT_CLOSE_CODES.includes(err.code)) {
+			throw err;
+		}
+		return new Promise((resolve) => setTimeout(resolve, backoff))
+			.then(() => attempt(n + 1, Math.min(backoff * 2, config.max_backoff_ms)));
+	});
+
+	return attempt(1, config.initial_backoff_ms);
+}
+
+// Opens a WebSocket to the given URL.
+const open = (url, config) => {
+	let ws = new WebSocket(url);
 	ws.binaryType = "arraybuffer";
 	let reader = read_queue();
 
 	return new Promise((open_resolve, open_reject) => {
-		ws.onerror = (ev) => {
+		// The `error` event doesn't carry any information and is always followed by a `close`
+		// event, which is where we report the failure.
+		ws.onerror = () => {};
+		ws.onclose = (ev) => {
 			// If `open_resolve` has been called earlier, calling `open_reject` seems to be
 			// silently ignored. It is easier to unconditionally call `open_reject` rather than
 			// check in which state the connection is, which would be error-prone.
-			open_reject(ev);
+			open_reject(close_error(ev));
 			// Injecting an EOF is how we report to the reading side that the connection has been
-			// closed. Injecting multiple EOFs is harmless.
-			reader.inject_eof();
-		};
-		ws.onclose = (ev) => {
-			// Same remarks as above.
-			open_reject(ev);
-			reader.inject_eof();
+			// closed normally, i.e. with code 1000 or without a code (1005). Any other close code
+			// is reported as an error.
+			if (ev.code == 1000 || ev.code == 1005) {
+				reader.inject_eof();
+			} else {
+				reader.inject_error(close_error(ev));
+			}
 		};
 
 		// We inject all incoming messages into the queue unconditionally. The caller isn't
@@ -77,16 +126,22 @@ const dial = (addr) => {
 		ws.onmessage = (ev) => reader.inject_array_buffer(ev.data);
 
 		ws.onopen = () => open_resolve({
-			read: (function*() { while(ws.readyState == 1) { yield reader.next(); } })(),
+			// The queue always ends with an EOF or an error once the WebSocket is closed, hence
+			// iterating forever doesn't lose the data received right before closing.
+			read: (function*() { while(true) { yield reader.next(); } })(),
 			write: (data) => {
 				if (ws.readyState == 1) {
-					ws.send(data);
+					// Split large writes into multiple binary frames. `send` copies the data,
+					// hence passing views of the buffer is fine.
+					for (let offset = 0; offset < data.length; offset += config.max_frame_size) {
+						ws.send(data.subarray(offset, offset + config.max_frame_size));
+					}
 					return promise_when_send_finished(ws);
 				} else {
 					return Promise.reject("WebSocket is closed");
 				}
 			},
-			shutdown: () => ws.close(),
+			shutdown: () => ws.close(1000),
 			close: () => {}
 		});
 	});
@@ -124,6 +179,8 @@ const read_queue = () => {
 		// If `resolve` isn't null, it is a "resolve" function of a promise that has already been
 		// returned by `next`. It should be called with some data.
 		resolve: null,
+		// The "reject" function of the same promise as `resolve`.
+		reject: null,
 	};
 
 	return {
@@ -132,6 +189,7 @@ const read_queue = () => {
 			if (state.resolve != null) {
 				state.resolve(buffer);
 				state.resolve = null;
+				state.reject = null;
 			} else {
 				state.queue.push(Promise.resolve(buffer));
 			}
@@ -142,11 +200,26 @@ const read_queue = () => {
 			if (state.resolve != null) {
 				state.resolve(null);
 				state.resolve = null;
+				state.reject = null;
 			} else {
 				state.queue.push(Promise.resolve(null));
 			}
 		},
 
+		// Inserts an error in the queue.
+		inject_error: (err) => {
+			if (state.reject != null) {
+				state.reject(err);
+				state.resolve = null;
+				state.reject = null;
+			} else {
+				let promise = Promise.reject(err);
+				// Avoid reports of unhandled rejections if the queue is never read again.
+				promise.catch(() => {});
+				state.queue.push(promise);
+			}
+		},
+
 		// Returns a Promise that yields the next entry as an ArrayBuffer.
 		next: () => {
 			if (state.queue.length != 0) {
@@ -156,6 +229,7 @@ const read_queue = () => {
 					throw "Internal error: already have a pending promise";
 				return new Promise((resolve, reject) => {
 					state.resolve = resolve;
+					state.reject = reject;
 				});
 			}
 		}
@@ -0,0 +1,66 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::{Error, Fingerprint};
+use webrtc::peer_connection::certificate::RTCCertificate;
+
+/// The DTLS certificate of a node.
+///
+/// The fingerprint of the certificate of a listener is part of its
+/// `webrtc-direct` addresses. A node should therefore keep using the same
+/// certificate across restarts, lest dialers reject it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Certificate {
+    inner: RTCCertificate,
+}
+
+impl Certificate {
+    /// Generates a new certificate with a random ECDSA P-256 key.
+    pub fn generate() -> Result<Self, Error> {
+        let keypair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)
+            .map_err(|e| Error::WebRtc(webrtc::Error::new(e.to_string())))?;
+        Ok(Certificate { inner: RTCCertificate::from_key_pair(keypair)? })
+    }
+
+    /// Parses a certificate and its key from the PEM encoding produced by
+    /// [`Certificate::serialize_pem`].
+    pub fn from_pem(pem: &str) -> Result<Self, Error> {
+        Ok(Certificate { inner: RTCCertificate::from_pem(pem)? })
+    }
+
+    /// Encodes the certificate and its key as PEM, for persisting it.
+    pub fn serialize_pem(&self) -> String {
+        self.inner.serialize_pem()
+    }
+
+    /// Returns the SHA-256 fingerprint of the certificate.
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.inner.get_fingerprints()
+            .iter()
+            .find(|f| f.algorithm == "sha-256")
+            .and_then(|f| Fingerprint::from_sdp_format(&f.value))
+            .expect("a certificate has a SHA-256 fingerprint")
+    }
+
+    pub(crate) fn to_rtc_certificate(&self) -> RTCCertificate {
+        self.inner.clone()
+    }
+}
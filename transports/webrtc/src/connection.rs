@@ -0,0 +1,190 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::{Error, Substream};
+use futures::{channel::{mpsc, oneshot}, future::BoxFuture, prelude::*, ready};
+use libp2p_core::muxing::{StreamMuxer, StreamMuxerEvent};
+use parking_lot::Mutex;
+use std::{fmt, pin::Pin, sync::Arc, task::{Context, Poll}};
+use webrtc::{
+    data::data_channel::DataChannel as DetachedDataChannel,
+    data_channel::RTCDataChannel,
+    peer_connection::RTCPeerConnection,
+};
+
+/// The maximum number of inbound data channels not yet accepted as substreams.
+const MAX_PENDING_INBOUND: usize = 32;
+
+/// A WebRTC connection, with a substream per data channel.
+pub struct Connection {
+    peer_conn: Arc<RTCPeerConnection>,
+    /// The opened data channels of the remote.
+    incoming: Mutex<mpsc::Receiver<Arc<DetachedDataChannel>>>,
+    /// Closing the peer connection, once started.
+    closing: Mutex<Option<BoxFuture<'static, Result<(), Error>>>>,
+}
+
+impl Connection {
+    /// Creates a connection from an established peer connection, with the
+    /// data channels of the remote registered by [`accept_data_channels`].
+    pub(crate) fn new(peer_conn: Arc<RTCPeerConnection>, incoming: mpsc::Receiver<Arc<DetachedDataChannel>>) -> Self {
+        Connection {
+            peer_conn,
+            incoming: Mutex::new(incoming),
+            closing: Mutex::new(None),
+        }
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("state", &self.peer_conn.connection_state())
+            .finish()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let peer_conn = self.peer_conn.clone();
+            runtime.spawn(async move {
+                if let Err(e) = peer_conn.close().await {
+                    log::debug!("Failed to close peer connection: {}", e)
+                }
+            });
+        }
+    }
+}
+
+impl StreamMuxer for Connection {
+    type Substream = Substream;
+    type OutboundSubstream = BoxFuture<'static, Result<Arc<DetachedDataChannel>, Error>>;
+    type Error = Error;
+
+    fn poll_event(&self, cx: &mut Context<'_>) -> Poll<Result<StreamMuxerEvent<Self::Substream>, Self::Error>> {
+        match ready!(self.incoming.lock().poll_next_unpin(cx)) {
+            Some(channel) => {
+                log::trace!("Inbound substream on data channel {}", channel.stream_identifier());
+                Poll::Ready(Ok(StreamMuxerEvent::InboundSubstream(Substream::new(channel))))
+            }
+            None => Poll::Ready(Err(Error::ConnectionClosed))
+        }
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        let peer_conn = self.peer_conn.clone();
+        async move {
+            let channel = peer_conn.create_data_channel("", None).await?;
+            detach_on_open(channel).await.map_err(|_| Error::DataChannelClosed)?
+        }.boxed()
+    }
+
+    fn poll_outbound(&self, cx: &mut Context<'_>, s: &mut Self::OutboundSubstream)
+        -> Poll<Result<Self::Substream, Self::Error>>
+    {
+        let channel = ready!(s.poll_unpin(cx))?;
+        log::trace!("Outbound substream on data channel {}", channel.stream_identifier());
+        Poll::Ready(Ok(Substream::new(channel)))
+    }
+
+    fn destroy_outbound(&self, _: Self::OutboundSubstream) {}
+
+    fn read_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &mut [u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        AsyncRead::poll_read(Pin::new(s), cx, buf).map_err(Error::Io)
+    }
+
+    fn write_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &[u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        AsyncWrite::poll_write(Pin::new(s), cx, buf).map_err(Error::Io)
+    }
+
+    fn flush_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        AsyncWrite::poll_flush(Pin::new(s), cx).map_err(Error::Io)
+    }
+
+    fn shutdown_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        AsyncWrite::poll_close(Pin::new(s), cx).map_err(Error::Io)
+    }
+
+    fn destroy_substream(&self, _: Self::Substream) {}
+
+    fn close(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut closing = self.closing.lock();
+        let peer_conn = self.peer_conn.clone();
+        let future = closing.get_or_insert_with(|| async move {
+            peer_conn.close().await.map_err(Error::from)
+        }.boxed());
+        future.poll_unpin(cx)
+    }
+
+    fn flush_all(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Substreams are flushed individually.
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Registers a handler accepting the data channels opened by the remote,
+/// which are yielded by the returned stream once open.
+///
+/// Must be registered before the connection is established, lest the
+/// remote opens data channels before a handler exists.
+pub(crate) fn accept_data_channels(peer_conn: &RTCPeerConnection) -> mpsc::Receiver<Arc<DetachedDataChannel>> {
+    let (tx, rx) = mpsc::channel(MAX_PENDING_INBOUND);
+    peer_conn.on_data_channel(Box::new(move |channel: Arc<RTCDataChannel>| {
+        log::trace!("Remote opens data channel {}", channel.id());
+        let mut tx = tx.clone();
+        Box::pin(async move {
+            match detach_on_open(channel).await {
+                Ok(Ok(channel)) => {
+                    if let Err(e) = tx.try_send(channel) {
+                        log::debug!("Dropping inbound data channel: {}", e)
+                    }
+                }
+                Ok(Err(e)) => log::debug!("Failed to detach inbound data channel: {}", e),
+                Err(oneshot::Canceled) => log::debug!("Inbound data channel closed before opening")
+            }
+        })
+    }));
+    rx
+}
+
+/// Detaches a data channel from the peer connection once it is open, for
+/// reading and writing it directly.
+pub(crate) fn detach_on_open(channel: Arc<RTCDataChannel>)
+    -> oneshot::Receiver<Result<Arc<DetachedDataChannel>, Error>>
+{
+    let (tx, rx) = oneshot::channel();
+    let c = channel.clone();
+    channel.on_open(Box::new(move || {
+        Box::pin(async move {
+            let _ = tx.send(c.detach().await.map_err(Error::from));
+        })
+    }));
+    rx
+}
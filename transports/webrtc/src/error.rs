@@ -0,0 +1,74 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::Fingerprint;
+use libp2p_core::PeerId;
+use std::io;
+use thiserror::Error;
+
+/// Error of the WebRTC transport.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An error of the underlying WebRTC stack.
+    #[error("WebRTC error: {0}")]
+    WebRtc(#[from] webrtc::Error),
+
+    /// An I/O error, e.g. when binding a UDP socket.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The Noise handshake authenticating the remote failed.
+    #[error("failed to authenticate the remote: {0}")]
+    Authentication(#[from] libp2p_noise::NoiseError),
+
+    /// The DTLS certificate of the remote does not match the expected
+    /// fingerprint.
+    #[error("invalid certificate fingerprint (expected {expected:?}, got {got:?})")]
+    InvalidFingerprint {
+        expected: Fingerprint,
+        got: Fingerprint,
+    },
+
+    /// The remote authenticated with a different peer ID than expected.
+    #[error("invalid peer ID (expected {expected}, got {got})")]
+    InvalidPeerId {
+        expected: PeerId,
+        got: PeerId,
+    },
+
+    /// A data channel was closed before it could be used.
+    #[error("data channel closed before it was opened")]
+    DataChannelClosed,
+
+    /// The connection was closed.
+    #[error("connection closed")]
+    ConnectionClosed,
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            Error::ConnectionClosed => io::ErrorKind::ConnectionReset.into(),
+            e => io::Error::new(io::ErrorKind::Other, e),
+        }
+    }
+}
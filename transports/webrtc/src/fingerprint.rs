@@ -0,0 +1,127 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use libp2p_core::multihash::Multihash;
+use sha2::{Digest, Sha256};
+use std::{convert::TryInto, fmt};
+
+/// The multihash code of SHA-256.
+const SHA256: u64 = 0x12;
+
+/// The SHA-256 fingerprint of a DTLS certificate.
+///
+/// The fingerprint of the certificate of the listener is part of its
+/// `webrtc-direct` address (as `certhash`), which allows a dialer to
+/// authenticate the DTLS handshake without any certificate authority.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    /// Creates a fingerprint from the raw SHA-256 digest of a certificate.
+    pub const fn raw(digest: [u8; 32]) -> Self {
+        Fingerprint(digest)
+    }
+
+    /// Computes the fingerprint of a DER encoded certificate.
+    pub fn from_certificate(der: &[u8]) -> Self {
+        let mut digest = [0; 32];
+        digest.copy_from_slice(&Sha256::digest(der));
+        Fingerprint(digest)
+    }
+
+    /// Extracts the fingerprint from the multihash of a `certhash`.
+    ///
+    /// Returns `None` if the multihash is not a SHA-256 digest.
+    pub fn try_from_multihash(hash: &Multihash) -> Option<Self> {
+        if hash.code() != SHA256 {
+            return None
+        }
+        hash.digest().try_into().ok().map(Fingerprint)
+    }
+
+    /// Returns the multihash of this fingerprint, as used in a `certhash`.
+    pub fn to_multihash(&self) -> Multihash {
+        Multihash::wrap(SHA256, &self.0).expect("a SHA-256 digest fits into a multihash")
+    }
+
+    /// Returns the raw digest.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Returns the name of the hash algorithm as used in the `a=fingerprint`
+    /// attribute of a session description.
+    pub fn algorithm(&self) -> &'static str {
+        "sha-256"
+    }
+
+    /// Formats the digest as used in the `a=fingerprint` attribute of a
+    /// session description, i.e. as colon separated upper case hex bytes.
+    pub fn to_sdp_format(&self) -> String {
+        self.0.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":")
+    }
+
+    /// Parses a digest formatted as by [`Fingerprint::to_sdp_format`].
+    pub fn from_sdp_format(s: &str) -> Option<Self> {
+        let mut digest = [0; 32];
+        let mut bytes = s.split(':');
+        for b in digest.iter_mut() {
+            *b = u8::from_str_radix(bytes.next()?, 16).ok()?;
+        }
+        if bytes.next().is_some() {
+            return None
+        }
+        Some(Fingerprint(digest))
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Fingerprint").field(&self.to_sdp_format()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multihash_roundtrip() {
+        let fingerprint = Fingerprint::from_certificate(b"certificate");
+        let hash = fingerprint.to_multihash();
+        assert_eq!(hash.code(), SHA256);
+        assert_eq!(Fingerprint::try_from_multihash(&hash), Some(fingerprint));
+
+        let identity = Multihash::wrap(0, fingerprint.as_bytes()).unwrap();
+        assert_eq!(Fingerprint::try_from_multihash(&identity), None);
+    }
+
+    #[test]
+    fn sdp_format() {
+        let fingerprint = Fingerprint::raw([0xAB; 32]);
+        let formatted = fingerprint.to_sdp_format();
+        assert_eq!(formatted.len(), 32 * 3 - 1);
+        assert!(formatted.starts_with("AB:AB:"));
+        assert_eq!(Fingerprint::from_sdp_format(&formatted), Some(fingerprint));
+        assert_eq!(Fingerprint::from_sdp_format(&formatted[3 ..]), None);
+        assert_eq!(Fingerprint::from_sdp_format(&format!("{}:AB", formatted)), None);
+    }
+}
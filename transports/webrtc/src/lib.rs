@@ -0,0 +1,227 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Implementation of the libp2p `Transport` trait for WebRTC.
+//!
+//! A node listening on a `webrtc-direct` address can be connected to from a
+//! browser without any signalling server or WebSocket gateway. The address of
+//! such a listener carries the fingerprint of its DTLS certificate:
+//!
+//! ```text
+//! /ip4/1.2.3.4/udp/4242/webrtc-direct/certhash/<multibase encoded multihash>
+//! ```
+//!
+//! Knowing the address and the fingerprint, a dialer can construct the
+//! session description of the listener itself (see [`sdp`]) and start the
+//! ICE and DTLS handshakes right away. Data channels are then authenticated
+//! by a Noise handshake whose prologue binds the DTLS certificates of both
+//! sides to the libp2p identities (see [`noise_prologue`]).
+//!
+//! # Usage
+//!
+//! This crate provides the [`WebRtcConfig`] struct, which implements the
+//! `Transport` trait for the native side of such connections. Its output is
+//! already authenticated and multiplexed: every substream of a [`Connection`]
+//! is a data channel. The transport thus does not need to be upgraded.
+//!
+//! ```no_run
+//! use libp2p_core::{identity, Transport};
+//! use mwc_libp2p_webrtc::{Certificate, WebRtcConfig};
+//!
+//! # async fn listen() -> Result<(), Box<dyn std::error::Error>> {
+//! let id_keys = identity::Keypair::generate_ed25519();
+//! let transport = WebRtcConfig::new(id_keys, Certificate::generate()?);
+//! let _listener = transport.listen_on("/ip4/0.0.0.0/udp/0/webrtc-direct".parse()?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The transport relies on the Tokio runtime it is used within.
+
+mod certificate;
+mod connection;
+mod error;
+mod fingerprint;
+mod substream;
+mod transport;
+mod udp_mux;
+mod upgrade;
+pub mod sdp;
+
+mod message_proto {
+    include!(concat!(env!("OUT_DIR"), "/webrtc.pb.rs"));
+}
+
+pub use certificate::Certificate;
+pub use connection::Connection;
+pub use error::Error;
+pub use fingerprint::Fingerprint;
+pub use substream::Substream;
+pub use transport::WebRtcConfig;
+
+use libp2p_core::PeerId;
+use multiaddr::{Multiaddr, Protocol};
+use std::net::{IpAddr, SocketAddr};
+
+/// Prefix of the Noise prologue of a `webrtc-direct` connection.
+const NOISE_PROLOGUE_PREFIX: &[u8] = b"libp2p-webrtc-noise:";
+
+/// Parses a `webrtc-direct` address to listen on into a socket address.
+///
+/// Returns `None` if the address is not of the form
+/// `/ip4|ip6/<ip>/udp/<port>/webrtc-direct`.
+pub fn parse_webrtc_listen_addr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut iter = addr.iter();
+
+    let ip = match iter.next()? {
+        Protocol::Ip4(ip) => IpAddr::from(ip),
+        Protocol::Ip6(ip) => IpAddr::from(ip),
+        _ => return None
+    };
+    let port = match iter.next()? {
+        Protocol::Udp(port) => port,
+        _ => return None
+    };
+    match iter.next()? {
+        Protocol::WebRtcDirect => {}
+        _ => return None
+    }
+    if iter.next().is_some() {
+        return None
+    }
+
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Parses a `webrtc-direct` address into the socket address and certificate
+/// fingerprint of the listener, as well as its peer ID, if present.
+///
+/// Returns `None` if the address is not of the form
+/// `/ip4|ip6/<ip>/udp/<port>/webrtc-direct/certhash/<hash>[/p2p/<peer>]`.
+pub fn parse_webrtc_dial_addr(addr: &Multiaddr) -> Option<(SocketAddr, Fingerprint, Option<PeerId>)> {
+    let mut iter = addr.iter();
+
+    let ip = match iter.next()? {
+        Protocol::Ip4(ip) => IpAddr::from(ip),
+        Protocol::Ip6(ip) => IpAddr::from(ip),
+        _ => return None
+    };
+    let port = match iter.next()? {
+        Protocol::Udp(port) => port,
+        _ => return None
+    };
+    match iter.next()? {
+        Protocol::WebRtcDirect => {}
+        _ => return None
+    }
+    let fingerprint = match iter.next()? {
+        Protocol::Certhash(hash) => Fingerprint::try_from_multihash(&hash)?,
+        _ => return None
+    };
+    let peer_id = match iter.next() {
+        Some(Protocol::P2p(hash)) => Some(PeerId::from_multihash(hash).ok()?),
+        None => None,
+        Some(_) => return None
+    };
+    if iter.next().is_some() {
+        return None
+    }
+
+    Some((SocketAddr::new(ip, port), fingerprint, peer_id))
+}
+
+/// Returns the `webrtc-direct` address of a listener bound to the given
+/// socket address with a certificate of the given fingerprint.
+pub fn webrtc_listen_addr(addr: SocketAddr, fingerprint: &Fingerprint) -> Multiaddr {
+    Multiaddr::empty()
+        .with(addr.ip().into())
+        .with(Protocol::Udp(addr.port()))
+        .with(Protocol::WebRtcDirect)
+        .with(Protocol::Certhash(fingerprint.to_multihash()))
+}
+
+/// Returns the prologue of the Noise handshake performed on the first data
+/// channel of a `webrtc-direct` connection.
+///
+/// Both sides must use the same prologue, otherwise the handshake fails.
+/// Including the certificate fingerprints of both the dialer and the listener
+/// thus guards against a man in the middle of the DTLS connection.
+pub fn noise_prologue(dialer: &Fingerprint, listener: &Fingerprint) -> Vec<u8> {
+    let dialer = dialer.to_multihash().to_bytes();
+    let listener = listener.to_multihash().to_bytes();
+    let mut prologue = Vec::with_capacity(NOISE_PROLOGUE_PREFIX.len() + dialer.len() + listener.len());
+    prologue.extend_from_slice(NOISE_PROLOGUE_PREFIX);
+    prologue.extend_from_slice(&dialer);
+    prologue.extend_from_slice(&listener);
+    prologue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listen_addr_roundtrip() {
+        let socket = "127.0.0.1:4242".parse().unwrap();
+        let fingerprint = Fingerprint::from_certificate(b"certificate");
+        let addr = webrtc_listen_addr(socket, &fingerprint);
+        assert_eq!(parse_webrtc_dial_addr(&addr), Some((socket, fingerprint, None)));
+
+        let peer_id = PeerId::random();
+        let addr = addr.with(Protocol::P2p(peer_id.into()));
+        assert_eq!(parse_webrtc_dial_addr(&addr), Some((socket, fingerprint, Some(peer_id))));
+    }
+
+    #[test]
+    fn parses_listen_addr() {
+        let addr = "/ip4/0.0.0.0/udp/0/webrtc-direct".parse().unwrap();
+        assert_eq!(parse_webrtc_listen_addr(&addr), Some("0.0.0.0:0".parse().unwrap()));
+        let addr = "/ip6/::1/udp/4242/webrtc-direct".parse().unwrap();
+        assert_eq!(parse_webrtc_listen_addr(&addr), Some("[::1]:4242".parse().unwrap()));
+
+        let fingerprint = Fingerprint::from_certificate(b"certificate");
+        let addr = webrtc_listen_addr("127.0.0.1:4242".parse().unwrap(), &fingerprint);
+        assert_eq!(parse_webrtc_listen_addr(&addr), None);
+        assert_eq!(parse_webrtc_listen_addr(&"/ip4/127.0.0.1/udp/4242".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn rejects_other_addrs() {
+        let addrs = [
+            "/ip4/127.0.0.1/tcp/4242",
+            "/ip4/127.0.0.1/udp/4242/webrtc-direct",
+            "/ip4/127.0.0.1/udp/4242/p2p-webrtc-direct",
+            "/dns4/example.com/udp/4242/webrtc-direct/certhash/uEiCDdrDFbM9JTSRxXg4eNjqdPT1SYHpGQrnrXjQmrBHrPQ",
+            "/ip4/127.0.0.1/udp/4242/webrtc-direct/certhash/uEiCDdrDFbM9JTSRxXg4eNjqdPT1SYHpGQrnrXjQmrBHrPQ/ws",
+        ];
+        for addr in addrs.iter() {
+            assert_eq!(parse_webrtc_dial_addr(&addr.parse().unwrap()), None, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn noise_prologue_is_directional() {
+        let a = Fingerprint::from_certificate(b"a");
+        let b = Fingerprint::from_certificate(b"b");
+        assert!(noise_prologue(&a, &b).starts_with(NOISE_PROLOGUE_PREFIX));
+        assert_ne!(noise_prologue(&a, &b), noise_prologue(&b, &a));
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Session descriptions for `webrtc-direct` connections.
+//!
+//! No signalling channel exists between the two sides of a `webrtc-direct`
+//! connection. Instead, each side constructs the session description of the
+//! remote from what it already knows: the address of the remote, the
+//! certificate fingerprint of the listener (taken from the `certhash`) and
+//! the ICE username fragment chosen by the dialer, which doubles as the ICE
+//! password.
+
+use crate::fingerprint::Fingerprint;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::net::SocketAddr;
+
+/// The prefix of every username fragment, identifying the protocol version.
+const UFRAG_PREFIX: &str = "libp2p+webrtc+v1/";
+
+/// Placeholder fingerprint used in the description of a dialer.
+///
+/// The listener does not know the certificate of a dialer in advance. The
+/// dialer is instead authenticated by the Noise handshake, whose prologue
+/// binds both certificates to the connection (see [`crate::noise_prologue`]).
+const UNKNOWN_FINGERPRINT: Fingerprint = Fingerprint::raw([0xFF; 32]);
+
+/// Generates a random ICE username fragment.
+pub fn random_ufrag() -> String {
+    let random = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .collect::<String>();
+    format!("{}{}", UFRAG_PREFIX, random)
+}
+
+/// Renders the session description of a listener at `addr`.
+///
+/// Used by a dialer as the answer to its own offer.
+pub fn answer(addr: SocketAddr, fingerprint: &Fingerprint, ufrag: &str) -> String {
+    render(addr, fingerprint, ufrag, &[
+        "a=ice-lite",
+    ], &[
+        "a=setup:passive",
+    ], &[
+        "a=candidate:1 1 UDP 1 {ip} {port} typ host",
+        "a=end-of-candidates",
+    ])
+}
+
+/// Renders the session description of a dialer at `addr`.
+///
+/// Used by a listener as the offer of a dialer from which it received a
+/// STUN binding request carrying the username fragment `ufrag`.
+pub fn offer(addr: SocketAddr, ufrag: &str) -> String {
+    render(addr, &UNKNOWN_FINGERPRINT, ufrag, &[], &[
+        "a=ice-options:ice2",
+        "a=setup:actpass",
+    ], &[])
+}
+
+fn render(
+    addr: SocketAddr,
+    fingerprint: &Fingerprint,
+    ufrag: &str,
+    session: &[&str],
+    media: &[&str],
+    trailer: &[&str],
+) -> String {
+    let (version, ip) = match addr {
+        SocketAddr::V4(a) => ("IP4", a.ip().to_string()),
+        SocketAddr::V6(a) => ("IP6", a.ip().to_string()),
+    };
+    let port = addr.port().to_string();
+
+    let mut lines = vec![
+        "v=0".to_string(),
+        format!("o=- 0 0 IN {} {}", version, ip),
+        "s=-".to_string(),
+        format!("c=IN {} {}", version, ip),
+        "t=0 0".to_string(),
+    ];
+    lines.extend(session.iter().map(|l| l.to_string()));
+    lines.push(format!("m=application {} UDP/DTLS/SCTP webrtc-datachannel", port));
+    lines.push("a=mid:0".to_string());
+    lines.extend(media.iter().map(|l| l.to_string()));
+    lines.push(format!("a=ice-ufrag:{}", ufrag));
+    lines.push(format!("a=ice-pwd:{}", ufrag));
+    lines.push(format!("a=fingerprint:{} {}", fingerprint.algorithm(), fingerprint.to_sdp_format()));
+    lines.push("a=sctp-port:5000".to_string());
+    lines.push("a=max-message-size:16384".to_string());
+    lines.extend(trailer.iter().map(|l| l.replace("{ip}", &ip).replace("{port}", &port)));
+
+    let mut sdp = lines.join("\r\n");
+    sdp.push_str("\r\n");
+    sdp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answer_contains_listener_details() {
+        let addr = "127.0.0.1:4242".parse().unwrap();
+        let fingerprint = Fingerprint::raw([0x01; 32]);
+        let ufrag = random_ufrag();
+        let sdp = answer(addr, &fingerprint, &ufrag);
+
+        assert!(ufrag.starts_with(UFRAG_PREFIX));
+        assert!(sdp.contains("c=IN IP4 127.0.0.1\r\n"));
+        assert!(sdp.contains("m=application 4242 UDP/DTLS/SCTP webrtc-datachannel\r\n"));
+        assert!(sdp.contains(&format!("a=ice-pwd:{}\r\n", ufrag)));
+        assert!(sdp.contains(&format!("a=fingerprint:sha-256 {}\r\n", fingerprint.to_sdp_format())));
+        assert!(sdp.contains("a=candidate:1 1 UDP 1 127.0.0.1 4242 typ host\r\n"));
+    }
+
+    #[test]
+    fn offer_uses_placeholder_fingerprint() {
+        let addr = "[::1]:4242".parse().unwrap();
+        let sdp = offer(addr, "ufrag");
+        assert!(sdp.contains("c=IN IP6 ::1\r\n"));
+        assert!(sdp.contains("a=setup:actpass\r\n"));
+        assert!(sdp.contains(&UNKNOWN_FINGERPRINT.to_sdp_format()));
+    }
+}
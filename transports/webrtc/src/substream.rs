@@ -0,0 +1,188 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Substreams on top of data channels.
+//!
+//! Every message sent on a data channel is a length-prefixed protobuf
+//! [`Message`](crate::message_proto::Message), which carries a chunk of the
+//! data of the substream and/or a flag signalling the closing of one or both
+//! of its directions. This gives data channels the half-close semantics
+//! expected of substreams.
+
+use crate::message_proto::{message::Flag, Message};
+use asynchronous_codec::Framed;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{prelude::*, ready};
+use prost::Message as _;
+use std::{
+    cmp,
+    fmt,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+use unsigned_varint::codec::UviBytes;
+use webrtc::data::data_channel::{DataChannel as DetachedDataChannel, PollDataChannel};
+
+/// The maximum size of a message on a data channel, including its length
+/// prefix, as supported by all WebRTC implementations.
+const MAX_MSG_LEN: usize = 16 * 1024;
+
+/// The maximum length of the varint length prefix of a message.
+const VARINT_LEN: usize = 2;
+
+/// The overhead of the protobuf encoding of a message with a data chunk
+/// and a flag.
+const PROTO_OVERHEAD: usize = 5;
+
+/// The maximum size of a data chunk in a message.
+const MAX_DATA_LEN: usize = MAX_MSG_LEN - VARINT_LEN - PROTO_OVERHEAD;
+
+/// A substream of a [`Connection`](crate::Connection), backed by a data channel.
+pub struct Substream {
+    channel: Arc<DetachedDataChannel>,
+    io: Framed<Compat<PollDataChannel>, UviBytes>,
+    /// Received data not yet read.
+    read_buffer: Bytes,
+    read_closed: bool,
+    write_closed: bool,
+}
+
+impl Substream {
+    pub(crate) fn new(channel: Arc<DetachedDataChannel>) -> Self {
+        let mut codec = UviBytes::default();
+        codec.set_max_len(MAX_MSG_LEN - VARINT_LEN);
+        let io = Framed::new(PollDataChannel::new(channel.clone()).compat(), codec);
+        Substream {
+            channel,
+            io,
+            read_buffer: Bytes::new(),
+            read_closed: false,
+            write_closed: false,
+        }
+    }
+
+    /// Returns the ID of the underlying data channel.
+    pub fn id(&self) -> u16 {
+        self.channel.stream_identifier()
+    }
+
+    /// Sends a message, waiting for the previous one to be accepted first.
+    fn poll_send(&mut self, cx: &mut Context<'_>, message: Message) -> Poll<io::Result<()>> {
+        ready!(self.io.poll_ready_unpin(cx))?;
+        let mut buf = BytesMut::with_capacity(message.encoded_len());
+        message.encode(&mut buf).expect("the buffer has sufficient capacity");
+        self.io.start_send_unpin(buf.freeze())?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl fmt::Debug for Substream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Substream")
+            .field("id", &self.id())
+            .field("read_closed", &self.read_closed)
+            .field("write_closed", &self.write_closed)
+            .finish()
+    }
+}
+
+impl AsyncRead for Substream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        loop {
+            if !this.read_buffer.is_empty() {
+                let n = cmp::min(buf.len(), this.read_buffer.len());
+                buf[.. n].copy_from_slice(&this.read_buffer[.. n]);
+                this.read_buffer.advance(n);
+                return Poll::Ready(Ok(n))
+            }
+            if this.read_closed {
+                return Poll::Ready(Ok(0))
+            }
+            let frame = match ready!(this.io.poll_next_unpin(cx)) {
+                Some(frame) => frame?,
+                None => {
+                    this.read_closed = true;
+                    continue
+                }
+            };
+            let message = Message::decode(frame)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if let Some(data) = message.message {
+                this.read_buffer = Bytes::from(data);
+            }
+            match message.flag.and_then(Flag::from_i32) {
+                Some(Flag::Fin) => this.read_closed = true,
+                Some(Flag::StopSending) => this.write_closed = true,
+                Some(Flag::Reset) => {
+                    this.read_closed = true;
+                    this.write_closed = true;
+                    this.read_buffer = Bytes::new();
+                    return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Substream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if self.write_closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+        }
+        let n = cmp::min(buf.len(), MAX_DATA_LEN);
+        let message = Message { flag: None, message: Some(buf[.. n].to_vec()) };
+        ready!(self.poll_send(cx, message))?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.io.poll_flush_unpin(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.write_closed {
+            let message = Message { flag: Some(Flag::Fin as i32), message: None };
+            ready!(self.poll_send(cx, message))?;
+            self.write_closed = true;
+        }
+        self.io.poll_flush_unpin(cx)
+    }
+}
+
+impl Drop for Substream {
+    fn drop(&mut self) {
+        // Closing the data channel requires a round trip, which is left to
+        // the runtime the channel was created in.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let channel = self.channel.clone();
+            runtime.spawn(async move {
+                if let Err(e) = channel.close().await {
+                    log::debug!("Failed to close data channel {}: {}", channel.stream_identifier(), e)
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::{
+    parse_webrtc_dial_addr,
+    parse_webrtc_listen_addr,
+    udp_mux::{NewRemote, UdpMux},
+    upgrade,
+    webrtc_listen_addr,
+    Certificate,
+    Connection,
+    Error,
+};
+use futures::{channel::mpsc, future::BoxFuture, prelude::*, stream::BoxStream};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    transport::{ListenerEvent, Transport, TransportError},
+    PeerId,
+};
+use std::{fmt, io, net::SocketAddr, sync::Arc};
+
+/// A transport for `webrtc-direct` addresses.
+///
+/// Dials and listens on UDP sockets, establishing connections authenticated
+/// by a Noise handshake and multiplexed over data channels. Listening on an
+/// address reports it with the `certhash` of the local certificate appended.
+///
+/// The transport, its listeners and its connections must be used within a
+/// Tokio runtime.
+#[derive(Clone)]
+pub struct WebRtcConfig {
+    id_keys: identity::Keypair,
+    certificate: Certificate,
+}
+
+impl WebRtcConfig {
+    /// Creates a new configuration with the identity of the local node and
+    /// the certificate for DTLS.
+    pub fn new(id_keys: identity::Keypair, certificate: Certificate) -> Self {
+        WebRtcConfig { id_keys, certificate }
+    }
+
+    /// Returns the DTLS certificate of the local node.
+    pub fn certificate(&self) -> &Certificate {
+        &self.certificate
+    }
+}
+
+impl fmt::Debug for WebRtcConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebRtcConfig")
+            .field("fingerprint", &self.certificate.fingerprint())
+            .finish()
+    }
+}
+
+impl Transport for WebRtcConfig {
+    type Output = (PeerId, Connection);
+    type Error = Error;
+    type Listener = BoxStream<'static, Result<ListenerEvent<Self::ListenerUpgrade, Self::Error>, Self::Error>>;
+    type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let socket_addr = parse_webrtc_listen_addr(&addr)
+            .ok_or_else(|| TransportError::MultiaddrNotSupported(addr.clone()))?;
+        let (mux, remotes) = bind(socket_addr).map_err(|e| TransportError::Other(Error::Io(e)))?;
+        let local_addr = mux.local_addr();
+        let fingerprint = self.certificate.fingerprint();
+        let listen_addrs = host_addrs(local_addr)
+            .map_err(|e| TransportError::Other(Error::Io(e)))?
+            .into_iter()
+            .map(|a| Ok(ListenerEvent::NewAddress(webrtc_listen_addr(a, &fingerprint))))
+            .collect::<Vec<_>>();
+        log::debug!("Listening on {} with certificate {:?}", local_addr, fingerprint);
+
+        let upgrades = remotes.map(move |remote| {
+            log::trace!("Incoming connection from {} with ufrag {}", remote.addr, remote.ufrag);
+            let upgrade = upgrade::inbound(
+                mux.clone(),
+                remote.addr,
+                remote.ufrag,
+                self.id_keys.clone(),
+                self.certificate.clone(),
+            ).boxed();
+            Ok(ListenerEvent::Upgrade {
+                upgrade,
+                local_addr: webrtc_listen_addr(local_addr, &fingerprint),
+                remote_addr: Multiaddr::empty()
+                    .with(remote.addr.ip().into())
+                    .with(Protocol::Udp(remote.addr.port()))
+                    .with(Protocol::WebRtcDirect),
+            })
+        });
+
+        Ok(stream::iter(listen_addrs).chain(upgrades).boxed())
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let (socket_addr, fingerprint, expected) = match parse_webrtc_dial_addr(&addr) {
+            Some((a, f, p)) if a.port() != 0 && !a.ip().is_unspecified() => (a, f, p),
+            _ => return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+        log::debug!("Dialing {}", addr);
+        Ok(async move {
+            let (peer_id, connection) = upgrade::outbound(
+                socket_addr,
+                self.id_keys,
+                self.certificate,
+                fingerprint,
+            ).await?;
+            match expected {
+                Some(expected) if expected != peer_id => Err(Error::InvalidPeerId { expected, got: peer_id }),
+                _ => Ok((peer_id, connection))
+            }
+        }.boxed())
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        libp2p_core::address_translation(listen, observed)
+    }
+}
+
+/// Binds a UDP socket to the given address and starts demultiplexing it.
+fn bind(addr: SocketAddr) -> io::Result<(Arc<UdpMux>, mpsc::Receiver<NewRemote>)> {
+    let socket = std::net::UdpSocket::bind(addr)?;
+    socket.set_nonblocking(true)?;
+    let socket = tokio::net::UdpSocket::from_std(socket)?;
+    UdpMux::new(socket)
+}
+
+/// Returns the addresses of the local host a socket bound to the given
+/// address is reachable at.
+fn host_addrs(addr: SocketAddr) -> io::Result<Vec<SocketAddr>> {
+    if !addr.ip().is_unspecified() {
+        return Ok(vec![addr])
+    }
+    Ok(if_addrs::get_if_addrs()?
+        .into_iter()
+        .map(|iface| iface.ip())
+        .filter(|ip| ip.is_ipv4() == addr.is_ipv4())
+        .map(|ip| SocketAddr::new(ip, addr.port()))
+        .collect())
+}
@@ -0,0 +1,356 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Sharing the UDP socket of a listener among the ICE agents of its
+//! connections.
+//!
+//! A listener learns about a new dialer from the first ICE binding request
+//! the dialer sends. The username fragment in the `USERNAME` attribute of
+//! that request identifies the connection, since the dialer chose it and the
+//! listener answers with the same one (see [`crate::sdp`]). Subsequent
+//! packets are demultiplexed by the address of the remote.
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use parking_lot::Mutex;
+use std::{
+    cmp,
+    collections::HashMap,
+    convert::TryInto,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Weak},
+};
+use tokio::{net::UdpSocket, task::JoinHandle};
+use webrtc::{ice::udp_mux::UDPMux, util::{self, Conn}};
+
+/// The type of a STUN binding request.
+const STUN_BINDING_REQUEST: u16 = 0x0001;
+
+/// The magic cookie of every STUN message.
+const STUN_MAGIC_COOKIE: u32 = 0x2112_A442;
+
+/// The type of the STUN `USERNAME` attribute.
+const STUN_USERNAME: u16 = 0x0006;
+
+/// The length of the header of a STUN message.
+const STUN_HEADER_LEN: usize = 20;
+
+/// The size of the buffer datagrams are received into.
+const RECEIVE_MTU: usize = 8192;
+
+/// The maximum number of received packets buffered per connection.
+const MAX_PENDING_PACKETS: usize = 64;
+
+/// The maximum number of new remotes not yet accepted by the listener.
+const MAX_PENDING_REMOTES: usize = 16;
+
+/// A remote that sent a binding request for an unknown username fragment.
+#[derive(Debug)]
+pub(crate) struct NewRemote {
+    /// The address of the remote.
+    pub(crate) addr: SocketAddr,
+    /// The username fragment chosen by the remote.
+    pub(crate) ufrag: String,
+}
+
+/// A [`UDPMux`] handing out a [`Conn`] per username fragment.
+pub(crate) struct UdpMux {
+    socket: Arc<UdpSocket>,
+    local_addr: SocketAddr,
+    /// The connections by username fragment.
+    conns: Mutex<HashMap<String, Arc<MuxedConn>>>,
+    /// The connections by address of the remote.
+    remotes: Mutex<HashMap<SocketAddr, Arc<MuxedConn>>>,
+    /// The task receiving from the socket.
+    reader: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl UdpMux {
+    /// Starts receiving on the given socket, which must have been created
+    /// within a Tokio runtime.
+    ///
+    /// Returns the mux together with the stream of new remotes, each of
+    /// which is expected to be accepted with an ICE agent for its username
+    /// fragment.
+    pub(crate) fn new(socket: UdpSocket) -> io::Result<(Arc<Self>, mpsc::Receiver<NewRemote>)> {
+        let local_addr = socket.local_addr()?;
+        let socket = Arc::new(socket);
+        let (tx, rx) = mpsc::channel(MAX_PENDING_REMOTES);
+        let mux = Arc::new(UdpMux {
+            socket: socket.clone(),
+            local_addr,
+            conns: Mutex::new(HashMap::new()),
+            remotes: Mutex::new(HashMap::new()),
+            reader: Mutex::new(None),
+        });
+        let reader = tokio::spawn(receive(Arc::downgrade(&mux), socket, tx));
+        *mux.reader.lock() = Some(reader);
+        Ok((mux, rx))
+    }
+
+    /// Returns the local address of the socket.
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Dispatches a received datagram to the connection of its sender.
+    fn dispatch(&self, data: &[u8], from: SocketAddr, new_remotes: &mut mpsc::Sender<NewRemote>) {
+        let known = self.remotes.lock().get(&from).cloned();
+        let conn = match known {
+            Some(conn) => conn,
+            None => {
+                let ufrag = match binding_request_ufrag(data) {
+                    Some(ufrag) => ufrag,
+                    None => {
+                        log::trace!("Dropping datagram of unknown remote {}", from);
+                        return
+                    }
+                };
+                let existing = self.conns.lock().get(&ufrag).cloned();
+                let conn = match existing {
+                    Some(conn) => conn,
+                    None => {
+                        let remote = NewRemote { addr: from, ufrag: ufrag.clone() };
+                        if let Err(e) = new_remotes.try_send(remote) {
+                            log::debug!("Dropping binding request of {}: {}", from, e);
+                            return
+                        }
+                        let conn = Arc::new(MuxedConn::new(ufrag.clone(), self.socket.clone(), self.local_addr));
+                        self.conns.lock().insert(ufrag, conn.clone());
+                        conn
+                    }
+                };
+                self.remotes.lock().insert(from, conn.clone());
+                conn
+            }
+        };
+        conn.push(data, from)
+    }
+
+    /// Closes all connections and stops receiving.
+    fn shutdown(&self) {
+        if let Some(reader) = self.reader.lock().take() {
+            reader.abort()
+        }
+        for (_, conn) in self.conns.lock().drain() {
+            conn.shutdown()
+        }
+        self.remotes.lock().clear();
+    }
+}
+
+impl Drop for UdpMux {
+    fn drop(&mut self) {
+        self.shutdown()
+    }
+}
+
+#[async_trait]
+impl UDPMux for UdpMux {
+    async fn close(&self) -> Result<(), util::Error> {
+        self.shutdown();
+        Ok(())
+    }
+
+    async fn get_conn(self: Arc<Self>, ufrag: &str) -> Result<Arc<dyn Conn + Send + Sync>, util::Error> {
+        let conn = self.conns.lock()
+            .entry(ufrag.to_owned())
+            .or_insert_with(|| Arc::new(MuxedConn::new(ufrag.to_owned(), self.socket.clone(), self.local_addr)))
+            .clone();
+        Ok(conn)
+    }
+
+    async fn remove_conn_by_ufrag(&self, ufrag: &str) {
+        if let Some(conn) = self.conns.lock().remove(ufrag) {
+            conn.shutdown()
+        }
+        self.remotes.lock().retain(|_, conn| conn.ufrag != ufrag);
+    }
+}
+
+/// Receives datagrams from the socket for as long as the mux exists.
+async fn receive(mux: Weak<UdpMux>, socket: Arc<UdpSocket>, mut new_remotes: mpsc::Sender<NewRemote>) {
+    let mut buf = vec![0; RECEIVE_MTU];
+    loop {
+        let (n, from) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            // Reported on some platforms for ICMP port unreachable messages.
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => {
+                log::error!("Failed to receive on {:?}: {}", socket.local_addr(), e);
+                return
+            }
+        };
+        match mux.upgrade() {
+            Some(mux) => mux.dispatch(&buf[.. n], from, &mut new_remotes),
+            None => return
+        }
+    }
+}
+
+/// The [`Conn`] of a single ICE agent.
+struct MuxedConn {
+    ufrag: String,
+    socket: Arc<UdpSocket>,
+    local_addr: SocketAddr,
+    sender: Mutex<Option<tokio::sync::mpsc::Sender<(Vec<u8>, SocketAddr)>>>,
+    receiver: tokio::sync::Mutex<tokio::sync::mpsc::Receiver<(Vec<u8>, SocketAddr)>>,
+}
+
+impl MuxedConn {
+    fn new(ufrag: String, socket: Arc<UdpSocket>, local_addr: SocketAddr) -> Self {
+        let (tx, rx) = tokio::sync::mpsc::channel(MAX_PENDING_PACKETS);
+        MuxedConn {
+            ufrag,
+            socket,
+            local_addr,
+            sender: Mutex::new(Some(tx)),
+            receiver: tokio::sync::Mutex::new(rx),
+        }
+    }
+
+    /// Queues a received datagram, dropping it if the queue is full.
+    fn push(&self, data: &[u8], from: SocketAddr) {
+        if let Some(sender) = self.sender.lock().as_ref() {
+            if sender.try_send((data.to_vec(), from)).is_err() {
+                log::trace!("Dropping datagram of {} for {}", from, self.ufrag)
+            }
+        }
+    }
+
+    /// Ends the stream of received datagrams.
+    fn shutdown(&self) {
+        self.sender.lock().take();
+    }
+}
+
+#[async_trait]
+impl Conn for MuxedConn {
+    async fn connect(&self, _: SocketAddr) -> util::Result<()> {
+        Err(util::Error::Other("a muxed connection cannot be connected".into()))
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> util::Result<usize> {
+        self.recv_from(buf).await.map(|(n, _)| n)
+    }
+
+    async fn recv_from(&self, buf: &mut [u8]) -> util::Result<(usize, SocketAddr)> {
+        let (data, from) = self.receiver.lock().await
+            .recv().await
+            .ok_or_else(|| util::Error::Other("muxed connection closed".into()))?;
+        let n = cmp::min(buf.len(), data.len());
+        buf[.. n].copy_from_slice(&data[.. n]);
+        Ok((n, from))
+    }
+
+    async fn send(&self, _: &[u8]) -> util::Result<usize> {
+        Err(util::Error::Other("a muxed connection has no remote address".into()))
+    }
+
+    async fn send_to(&self, buf: &[u8], target: SocketAddr) -> util::Result<usize> {
+        Ok(self.socket.send_to(buf, target).await?)
+    }
+
+    fn local_addr(&self) -> util::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    async fn close(&self) -> util::Result<()> {
+        self.shutdown();
+        Ok(())
+    }
+}
+
+/// Returns the local username fragment of a STUN binding request, i.e. the
+/// part of the `USERNAME` attribute before the colon.
+fn binding_request_ufrag(data: &[u8]) -> Option<String> {
+    if data.len() < STUN_HEADER_LEN {
+        return None
+    }
+    let typ = u16::from_be_bytes(data[0 .. 2].try_into().ok()?);
+    let len = usize::from(u16::from_be_bytes(data[2 .. 4].try_into().ok()?));
+    let cookie = u32::from_be_bytes(data[4 .. 8].try_into().ok()?);
+    if typ != STUN_BINDING_REQUEST || cookie != STUN_MAGIC_COOKIE || data.len() < STUN_HEADER_LEN + len {
+        return None
+    }
+    let mut attrs = &data[STUN_HEADER_LEN .. STUN_HEADER_LEN + len];
+    while attrs.len() >= 4 {
+        let typ = u16::from_be_bytes(attrs[0 .. 2].try_into().ok()?);
+        let len = usize::from(u16::from_be_bytes(attrs[2 .. 4].try_into().ok()?));
+        let value = attrs.get(4 .. 4 + len)?;
+        if typ == STUN_USERNAME {
+            let username = std::str::from_utf8(value).ok()?;
+            let ufrag = username.split(':').next()?;
+            return Some(ufrag.to_owned())
+        }
+        // Attribute values are padded to a multiple of 4 bytes.
+        let padded = (len + 3) & !3;
+        attrs = attrs.get(4 + padded ..)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding_request(attrs: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (typ, value) in attrs {
+            body.extend_from_slice(&typ.to_be_bytes());
+            body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            body.extend_from_slice(value);
+            body.resize((body.len() + 3) & !3, 0);
+        }
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&STUN_BINDING_REQUEST.to_be_bytes());
+        msg.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+        msg.extend_from_slice(&[7; 12]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    #[test]
+    fn extracts_ufrag_of_binding_request() {
+        let msg = binding_request(&[(0x0024, b"prio"), (STUN_USERNAME, b"libp2p+webrtc+v1/abc:libp2p+webrtc+v1/abc")]);
+        assert_eq!(binding_request_ufrag(&msg), Some("libp2p+webrtc+v1/abc".to_owned()));
+    }
+
+    #[test]
+    fn ignores_other_datagrams() {
+        let msg = binding_request(&[(0x0024, b"prio")]);
+        assert_eq!(binding_request_ufrag(&msg), None);
+
+        let mut msg = binding_request(&[(STUN_USERNAME, b"a:b")]);
+        msg[0 .. 2].copy_from_slice(&0x0101u16.to_be_bytes());
+        assert_eq!(binding_request_ufrag(&msg), None);
+
+        let msg = binding_request(&[(STUN_USERNAME, b"a:b")]);
+        assert_eq!(binding_request_ufrag(&msg[.. msg.len() - 4]), None);
+        assert_eq!(binding_request_ufrag(&[0x16, 0xfe, 0xfd]), None);
+    }
+}
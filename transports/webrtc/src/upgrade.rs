@@ -0,0 +1,209 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Establishing a connection, from the ICE and DTLS handshakes of the peer
+//! connection to the Noise handshake on the first data channel.
+
+use crate::{
+    connection::{accept_data_channels, detach_on_open},
+    noise_prologue,
+    sdp,
+    udp_mux::UdpMux,
+    Certificate,
+    Connection,
+    Error,
+    Fingerprint,
+    Substream,
+};
+use futures::{channel::oneshot, future::{self, Either}, prelude::*};
+use futures_timer::Delay;
+use libp2p_core::{identity, InboundUpgrade, OutboundUpgrade, PeerId, UpgradeInfo};
+use libp2p_noise::{Keypair, NoiseAuthenticated, NoiseConfig, X25519Spec, XX};
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+use webrtc::{
+    api::{setting_engine::SettingEngine, APIBuilder},
+    data::data_channel::DataChannel as DetachedDataChannel,
+    data_channel::data_channel_init::RTCDataChannelInit,
+    dtls_transport::dtls_role::DTLSRole,
+    ice::{network_type::NetworkType, udp_mux::UDPMux, udp_network::UDPNetwork},
+    peer_connection::{
+        configuration::RTCConfiguration,
+        sdp::session_description::RTCSessionDescription,
+        RTCPeerConnection,
+    },
+};
+
+/// The maximum duration of establishing a connection.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// The ID of the pre-negotiated data channel of the Noise handshake.
+const HANDSHAKE_CHANNEL_ID: u16 = 0;
+
+/// Establishes a connection to the listener at the given address, whose
+/// certificate must have the given fingerprint.
+pub(crate) async fn outbound(
+    addr: SocketAddr,
+    id_keys: identity::Keypair,
+    certificate: Certificate,
+    server_fingerprint: Fingerprint,
+) -> Result<(PeerId, Connection), Error> {
+    let ufrag = sdp::random_ufrag();
+    let peer_conn = new_peer_connection(&certificate, setting_engine(&ufrag, addr)).await?;
+    let incoming = accept_data_channels(&peer_conn);
+
+    let handshake = async {
+        let channel = open_handshake_channel(&peer_conn).await?;
+
+        let offer = peer_conn.create_offer(None).await?;
+        peer_conn.set_local_description(offer).await?;
+        let answer = RTCSessionDescription::answer(sdp::answer(addr, &server_fingerprint, &ufrag))?;
+        peer_conn.set_remote_description(answer).await?;
+
+        let channel = channel.await.map_err(|_| Error::DataChannelClosed)??;
+        let fingerprint = remote_fingerprint(&peer_conn).await;
+        if fingerprint != server_fingerprint {
+            return Err(Error::InvalidFingerprint { expected: server_fingerprint, got: fingerprint })
+        }
+
+        // The dialer is the responder of the Noise handshake.
+        let noise = noise_config(&id_keys, &certificate.fingerprint(), &server_fingerprint)?;
+        let info = noise.protocol_info().into_iter().next().expect("Noise supports a protocol");
+        let (peer_id, _) = noise.upgrade_inbound(Substream::new(channel), info).await?;
+        Ok::<_, Error>(peer_id)
+    };
+
+    let peer_id = establish(&peer_conn, handshake).await?;
+    log::debug!("Connected to {} at {}", peer_id, addr);
+    Ok((peer_id, Connection::new(peer_conn, incoming)))
+}
+
+/// Accepts a connection from the dialer at the given address, which sent a
+/// binding request with the given username fragment.
+pub(crate) async fn inbound(
+    mux: Arc<UdpMux>,
+    addr: SocketAddr,
+    ufrag: String,
+    id_keys: identity::Keypair,
+    certificate: Certificate,
+) -> Result<(PeerId, Connection), Error> {
+    let mut settings = setting_engine(&ufrag, addr);
+    settings.set_lite(true);
+    settings.set_udp_network(UDPNetwork::Muxed(mux as Arc<dyn UDPMux + Send + Sync>));
+    // The certificate of the dialer is unknown in advance. It is bound to
+    // its identity by the prologue of the Noise handshake instead.
+    settings.disable_certificate_fingerprint_verification(true);
+    settings.set_answering_dtls_role(DTLSRole::Server)?;
+    let peer_conn = new_peer_connection(&certificate, settings).await?;
+    let incoming = accept_data_channels(&peer_conn);
+
+    let handshake = async {
+        let channel = open_handshake_channel(&peer_conn).await?;
+
+        let offer = RTCSessionDescription::offer(sdp::offer(addr, &ufrag))?;
+        peer_conn.set_remote_description(offer).await?;
+        let answer = peer_conn.create_answer(None).await?;
+        peer_conn.set_local_description(answer).await?;
+
+        let channel = channel.await.map_err(|_| Error::DataChannelClosed)??;
+        let client_fingerprint = remote_fingerprint(&peer_conn).await;
+
+        // The listener is the initiator of the Noise handshake.
+        let noise = noise_config(&id_keys, &client_fingerprint, &certificate.fingerprint())?;
+        let info = noise.protocol_info().into_iter().next().expect("Noise supports a protocol");
+        let (peer_id, _) = noise.upgrade_outbound(Substream::new(channel), info).await?;
+        Ok::<_, Error>(peer_id)
+    };
+
+    let peer_id = establish(&peer_conn, handshake).await?;
+    log::debug!("Accepted connection of {} from {}", peer_id, addr);
+    Ok((peer_id, Connection::new(peer_conn, incoming)))
+}
+
+/// Runs the handshake of a peer connection, closing the peer connection if
+/// the handshake fails or times out.
+async fn establish(
+    peer_conn: &RTCPeerConnection,
+    handshake: impl Future<Output = Result<PeerId, Error>>,
+) -> Result<PeerId, Error> {
+    futures::pin_mut!(handshake);
+    let result = match future::select(handshake, Delay::new(HANDSHAKE_TIMEOUT)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(Error::Io(io::ErrorKind::TimedOut.into()))
+    };
+    if let Err(e) = &result {
+        log::debug!("WebRTC handshake failed: {}", e);
+        if let Err(e) = peer_conn.close().await {
+            log::debug!("Failed to close peer connection: {}", e)
+        }
+    }
+    result
+}
+
+/// Creates the settings of a peer connection with the given remote.
+fn setting_engine(ufrag: &str, remote: SocketAddr) -> SettingEngine {
+    let mut settings = SettingEngine::default();
+    // The username fragment doubles as the ICE password.
+    settings.set_ice_credentials(ufrag.to_owned(), ufrag.to_owned());
+    settings.detach_data_channels();
+    let network_type = if remote.is_ipv4() { NetworkType::Udp4 } else { NetworkType::Udp6 };
+    settings.set_network_types(vec![network_type]);
+    settings
+}
+
+async fn new_peer_connection(certificate: &Certificate, settings: SettingEngine)
+    -> Result<Arc<RTCPeerConnection>, Error>
+{
+    let api = APIBuilder::new().with_setting_engine(settings).build();
+    let config = RTCConfiguration {
+        certificates: vec![certificate.to_rtc_certificate()],
+        ..RTCConfiguration::default()
+    };
+    Ok(Arc::new(api.new_peer_connection(config).await?))
+}
+
+/// Creates the pre-negotiated data channel of the Noise handshake.
+async fn open_handshake_channel(peer_conn: &RTCPeerConnection)
+    -> Result<oneshot::Receiver<Result<Arc<DetachedDataChannel>, Error>>, Error>
+{
+    let init = RTCDataChannelInit {
+        negotiated: Some(HANDSHAKE_CHANNEL_ID),
+        ..RTCDataChannelInit::default()
+    };
+    let channel = peer_conn.create_data_channel("", Some(init)).await?;
+    Ok(detach_on_open(channel))
+}
+
+/// Returns the fingerprint of the DTLS certificate of the remote.
+async fn remote_fingerprint(peer_conn: &RTCPeerConnection) -> Fingerprint {
+    let certificate = peer_conn.sctp().transport().get_remote_certificate().await;
+    Fingerprint::from_certificate(&certificate)
+}
+
+/// Creates the Noise configuration binding the handshake to the
+/// certificates of the dialer and the listener.
+fn noise_config(id_keys: &identity::Keypair, dialer: &Fingerprint, listener: &Fingerprint)
+    -> Result<NoiseAuthenticated<XX, X25519Spec, ()>, Error>
+{
+    let dh_keys = Keypair::<X25519Spec>::new().into_authentic(id_keys)?;
+    let mut config = NoiseConfig::xx(dh_keys);
+    config.set_prologue(noise_prologue(dialer, listener));
+    Ok(config.into_authenticated())
+}
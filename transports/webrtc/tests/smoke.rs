@@ -0,0 +1,114 @@
+// Copyright 2021 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use futures::{future::poll_fn, prelude::*};
+use libp2p_core::{
+    identity,
+    multiaddr::Protocol,
+    muxing::{StreamMuxer, StreamMuxerEvent},
+    transport::{ListenerEvent, Transport},
+    Multiaddr,
+    PeerId,
+};
+use mwc_libp2p_webrtc::{Certificate, Connection, Error, WebRtcConfig};
+
+fn mk_transport() -> (PeerId, WebRtcConfig) {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = id_keys.public().into_peer_id();
+    let certificate = Certificate::generate().unwrap();
+    (peer_id, WebRtcConfig::new(id_keys, certificate))
+}
+
+/// Starts listening on a local address, returning the listener and the
+/// reported address.
+async fn listen(transport: WebRtcConfig) -> (<WebRtcConfig as Transport>::Listener, Multiaddr) {
+    let mut listener = transport.listen_on("/ip4/127.0.0.1/udp/0/webrtc-direct".parse().unwrap()).unwrap();
+    match listener.next().await {
+        Some(Ok(ListenerEvent::NewAddress(addr))) => (listener, addr),
+        e => panic!("Unexpected listener event: {:?}", e.map(|e| e.map(|e| e.map(|_| ()))))
+    }
+}
+
+/// Accepts the next connection of the listener.
+async fn accept(listener: &mut <WebRtcConfig as Transport>::Listener) -> (PeerId, Connection) {
+    let (upgrade, _) = listener.next().await.unwrap().unwrap().into_upgrade().unwrap();
+    upgrade.await.unwrap()
+}
+
+#[tokio::test]
+async fn echo_on_data_channel() {
+    let _ = env_logger::try_init();
+
+    let (listener_id, listener_transport) = mk_transport();
+    let (dialer_id, dialer_transport) = mk_transport();
+    let (mut listener, addr) = listen(listener_transport).await;
+    let addr = addr.with(Protocol::P2p(listener_id.into()));
+
+    let ((remote_dialer, listener_conn), dialed) = future::join(
+        accept(&mut listener),
+        dialer_transport.dial(addr).unwrap()
+    ).await;
+    let (remote_listener, dialer_conn) = dialed.unwrap();
+    assert_eq!(remote_dialer, dialer_id);
+    assert_eq!(remote_listener, listener_id);
+
+    let echo = async {
+        let mut substream = match poll_fn(|cx| listener_conn.poll_event(cx)).await.unwrap() {
+            StreamMuxerEvent::InboundSubstream(s) => s,
+            StreamMuxerEvent::AddressChange(a) => panic!("Unexpected address change to {}", a)
+        };
+        let mut buf = Vec::new();
+        substream.read_to_end(&mut buf).await.unwrap();
+        substream.write_all(&buf).await.unwrap();
+        substream.close().await.unwrap();
+    };
+
+    let message = vec![0x42; 100 * 1024];
+    let send = async {
+        let mut outbound = dialer_conn.open_outbound();
+        let mut substream = poll_fn(|cx| dialer_conn.poll_outbound(cx, &mut outbound)).await.unwrap();
+        substream.write_all(&message).await.unwrap();
+        substream.close().await.unwrap();
+        let mut buf = Vec::new();
+        substream.read_to_end(&mut buf).await.unwrap();
+        buf
+    };
+
+    let ((), echoed) = future::join(echo, send).await;
+    assert_eq!(echoed, message);
+}
+
+#[tokio::test]
+async fn dial_rejects_other_peer() {
+    let _ = env_logger::try_init();
+
+    let (_, listener_transport) = mk_transport();
+    let (_, dialer_transport) = mk_transport();
+    let (mut listener, addr) = listen(listener_transport).await;
+    let expected = PeerId::random();
+    let addr = addr.with(Protocol::P2p(expected.into()));
+
+    let (_, dialed) = future::join(accept(&mut listener), dialer_transport.dial(addr).unwrap()).await;
+    match dialed {
+        Err(Error::InvalidPeerId { expected: e, .. }) => assert_eq!(e, expected),
+        r => panic!("Unexpected dial result: {:?}", r.map(|(peer_id, _)| peer_id))
+    }
+}